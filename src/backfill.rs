@@ -0,0 +1,326 @@
+use crate::binning::{self, PositionBinning};
+use crate::columns::{self, Column, MetaRow};
+use crate::data_processor::Frame;
+use crate::direction;
+use crate::dose_equivalent::QualityFactor;
+use crate::gps_processor::GpsData;
+use crate::info_processor::MeasInfoData;
+use crate::orientation::Orientation;
+use crate::processor::Processor;
+use crate::utils;
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Columns added to or updated in the existing metadata files
+pub const BACKFILL_COLUMNS: [&str; 5] = ["lat", "lon", "alt", "l_shell", "region"];
+
+/// Headers of the time column the GPS record is matched to, in order of preference
+pub const TIME_HEADERS: [&str; 2] = ["Frame Timestamp", "Timestamp"];
+
+/// Path of the upgraded file next to the original (data_2024-03-01.info -> data_2024-03-01.geo.info)
+pub fn output_path(info_path: &Path, out_dir: Option<&Path>) -> PathBuf {
+    let stem = info_path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = format!("{}.geo.info", stem);
+    match out_dir {
+        Some(dir) => dir.join(name),
+        None => info_path.with_file_name(name),
+    }
+}
+
+/// Measurement info columns filled in when they are empty, the ones of runs without the
+/// measurement info file that do not depend on the frame
+pub const MEAS_COLUMNS: [&str; 8] = [
+    "timestamp",
+    "temp",
+    "acq_time",
+    "pixels_short",
+    "pixels_long",
+    "pixels_saved",
+    "pixels_not_saved",
+    "error_id",
+];
+
+/// Prefix of the .info header line listing the inputs a run was started without
+pub const MISSING_PREFIX: &str = "# missing inputs: ";
+
+/// Records the metadata files are completed from
+#[derive(Debug, Default)]
+pub struct BackfillInputs {
+    /// GPS records sorted by time with the name of their file
+    pub gps: Option<(Vec<GpsData>, String)>,
+    /// Measurement info records sorted by time with the name of their file
+    pub meas: Option<(Vec<MeasInfoData>, String)>,
+    /// Hit pixel count ending a frame acquisition, for the acq_time column
+    pub max_pix_count: usize,
+}
+
+/// Position of the column in the headers, appended when missing
+fn column_index(headers: &mut Vec<String>, column: &Column) -> usize {
+    match headers.iter().position(|h| h == column.header) {
+        Some(i) => i,
+        None => {
+            headers.push(column.header.to_string());
+            headers.len() - 1
+        }
+    }
+}
+
+/// Completes the metadata text: with GPS records the geolocation columns are added or
+/// updated and the empty cells of the other GPS columns filled, with measurement info
+/// records the empty cells of the measurement info columns. Returns the upgraded text and
+/// the number of data rows.
+pub fn backfill(content: &str, inputs: &BackfillInputs) -> Result<(String, usize)> {
+    let lend = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut lines = content.lines().filter(|line| !line.is_empty());
+    let mut out = Vec::new();
+    // the added columns are rounded like the ones written by the run
+    let mut binning = PositionBinning::default();
+
+    let header = loop {
+        match lines.next() {
+            Some(line) if line.starts_with(MISSING_PREFIX) => {
+                let missing: Vec<&str> = line[MISSING_PREFIX.len()..]
+                    .split(" (")
+                    .next()
+                    .unwrap_or_default()
+                    .split(", ")
+                    .filter(|input| match *input {
+                        "gps" => inputs.gps.is_none(),
+                        "meas" => inputs.meas.is_none(),
+                        _ => true,
+                    })
+                    .collect();
+                if !missing.is_empty() {
+                    out.push(format!(
+                        "{}{} (see the backfill command)",
+                        MISSING_PREFIX,
+                        missing.join(", ")
+                    ));
+                }
+            }
+            Some(line) if line.starts_with(binning::BINNING_PREFIX) => {
+                binning = line[binning::BINNING_PREFIX.len()..]
+                    .parse()
+                    .context("invalid position binning header")?;
+                out.push(line.to_string());
+            }
+            Some(line) if line.starts_with('#') => out.push(line.to_string()),
+            Some(line) => break line,
+            None => bail!("missing column header line"),
+        }
+    };
+    if let Some((_, source)) = &inputs.gps {
+        out.push(format!(
+            "# backfill: {} from {}",
+            BACKFILL_COLUMNS.join(","),
+            source
+        ));
+    }
+    if let Some((_, source)) = &inputs.meas {
+        out.push(format!(
+            "# backfill: empty measurement info columns from {}",
+            source
+        ));
+    }
+
+    let mut headers: Vec<String> = header.split('\t').map(str::to_string).collect();
+    let Some(time_col) = TIME_HEADERS
+        .iter()
+        .find_map(|name| headers.iter().position(|h| h == name))
+    else {
+        bail!("no {} column", TIME_HEADERS.join(" or "));
+    };
+    // columns replaced and columns filled when empty
+    let mut updated: Vec<(&Column, usize)> = Vec::new();
+    let mut filled: Vec<(&Column, usize)> = Vec::new();
+    if inputs.gps.is_some() {
+        for column in columns::resolve(&BACKFILL_COLUMNS)? {
+            updated.push((column, column_index(&mut headers, column)));
+        }
+        for column in columns::COLUMNS.iter().filter(|c| {
+            (c.gps || columns::ATTITUDE_COLUMNS.contains(&c.name))
+                && !BACKFILL_COLUMNS.contains(&c.name)
+        }) {
+            if let Some(i) = headers.iter().position(|h| h == column.header) {
+                filled.push((column, i));
+            }
+        }
+    }
+    if inputs.meas.is_some() {
+        for column in columns::resolve(&MEAS_COLUMNS)? {
+            if let Some(i) = headers.iter().position(|h| h == column.header) {
+                filled.push((column, i));
+            }
+        }
+    }
+    out.push(headers.join("\t"));
+
+    let mut rows = 0;
+    for (i, line) in lines.enumerate() {
+        let mut values: Vec<String> = line.split('\t').map(str::to_string).collect();
+        values.resize(values.len().max(headers.len()), String::new());
+        let timestamp: f64 = values
+            .get(time_col)
+            .filter(|v| !v.is_empty())
+            .map(|v| utils::parse_field(v, &headers[time_col]))
+            .transpose()?
+            .with_context(|| format!("row {}: missing {} value", i + 1, headers[time_col]))?;
+        let gps_data = match &inputs.gps {
+            Some((gps, _)) => match utils::nearest(gps, timestamp, |g| g.timestamp) {
+                Some(gps_data) => gps_data.clone(),
+                None => bail!("no GPS records"),
+            },
+            None => GpsData::default(),
+        };
+        let (info, acq_time) = match &inputs.meas {
+            Some((meas, _)) => match utils::nearest(meas, timestamp, |m| m.timestamp) {
+                Some(info) => (
+                    info.clone(),
+                    Processor::calculate_acq_time(info, inputs.max_pix_count),
+                ),
+                None => bail!("no measurement info records"),
+            },
+            None => (MeasInfoData::default(), 0.0),
+        };
+        let frame = Frame::from_planes(Vec::new(), Vec::new(), timestamp);
+        let row = MetaRow {
+            frame_index: i + 1,
+            frame_number: i + 1,
+            frame: &frame,
+            info: &info,
+            gps: &gps_data,
+            gps_missing: false,
+            info_missing: false,
+            acq_time,
+            kev_per_count: 1.0,
+            quality_factor: QualityFactor::default(),
+            mounting: direction::IDENTITY,
+            maneuver: false,
+            attitude_excluded: false,
+            tle_residual: None,
+            mode: None,
+            orientation: Orientation::Detector,
+            binning,
+            interpolated: None,
+            gps_columns: columns::GpsColumns::Nearest,
+            velocity: None,
+            pointing_frame: direction::PointingFrame::J2000,
+        };
+        for &(column, target) in &updated {
+            values[target] = column.format(&row);
+        }
+        for &(column, target) in &filled {
+            if values[target].is_empty() {
+                values[target] = column.format(&row);
+            }
+        }
+        out.push(values.join("\t"));
+        rows += 1;
+    }
+    Ok((out.join(lend) + lend, rows))
+}
+
+/// Writes the upgraded copy of the metadata file, returns its path and the number of rows
+pub fn backfill_file(
+    info_path: &Path,
+    inputs: &BackfillInputs,
+    out_dir: Option<&Path>,
+) -> Result<(PathBuf, usize)> {
+    let content = fs::read_to_string(info_path)
+        .with_context(|| format!("cannot read {}", info_path.display()))?;
+    let (upgraded, rows) =
+        backfill(&content, inputs).with_context(|| format!("{}", info_path.display()))?;
+    let out_path = output_path(info_path, out_dir);
+    fs::write(&out_path, upgraded)
+        .with_context(|| format!("cannot write {}", out_path.display()))?;
+    Ok((out_path, rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backfill() {
+        let gps: Vec<GpsData> = [(100.0, 7e6), (200.0, -7e6)]
+            .iter()
+            .map(|&(timestamp, j2000_x)| GpsData {
+                timestamp,
+                j2000_x,
+                ..Default::default()
+            })
+            .collect();
+        let content = "# repro_hash: abc\nFrame Index\tTimestamp\tFrame Timestamp\tLatitude\n\
+                       1\t105\t104\told\n2\t196\t190\told\n";
+        let inputs = BackfillInputs {
+            gps: Some((gps, String::from("gps.csv"))),
+            ..Default::default()
+        };
+        let (upgraded, rows) = backfill(content, &inputs).unwrap();
+        assert_eq!(rows, 2);
+        let lines: Vec<&str> = upgraded.lines().collect();
+        assert_eq!(lines[0], "# repro_hash: abc");
+        assert_eq!(
+            lines[1],
+            "# backfill: lat,lon,alt,l_shell,region from gps.csv"
+        );
+        assert_eq!(
+            lines[2],
+            "Frame Index\tTimestamp\tFrame Timestamp\tLatitude\tLongitude\tAltitude\tL-shell\tRegion"
+        );
+        let row: Vec<&str> = lines[3].split('\t').collect();
+        assert_eq!(row[..3], ["1", "105", "104"]);
+        assert_eq!(row.len(), 8);
+        assert_eq!(row[3], "-0.1676");
+        assert_eq!(row[5], "621.863");
+        let row: Vec<&str> = lines[4].split('\t').collect();
+        assert_eq!(row[5], "621.863");
+        assert_ne!(lines[3], lines[4]);
+
+        assert!(backfill("Frame Index\n1\n", &inputs).is_err());
+        let binned = format!("{}alt=10\n{}", binning::BINNING_PREFIX, content);
+        let (upgraded, _) = backfill(&binned, &inputs).unwrap();
+        let row: Vec<&str> = upgraded.lines().nth(4).unwrap().split('\t').collect();
+        assert_eq!(row[5], "620");
+
+        // run without both files: the empty cells are filled, the given input is no longer
+        // listed as missing
+        let meas = vec![MeasInfoData {
+            timestamp: 103.0,
+            temp: -4.5,
+            pixel_short: 100.0,
+            pixel_long: 1000.0,
+            ..Default::default()
+        }];
+        let inputs = BackfillInputs {
+            meas: Some((meas, String::from("meas.csv"))),
+            max_pix_count: 1638,
+            ..inputs
+        };
+        let content = "# missing inputs: gps, meas (see the backfill command)\n\
+                       Frame Index\tTimestamp\tFrame Timestamp\tTemp\tGPS J2000 X\tacq_time\n\
+                       1\t\t104\t\t\t\n";
+        let (upgraded, _) = backfill(content, &inputs).unwrap();
+        let lines: Vec<&str> = upgraded.lines().collect();
+        assert_eq!(
+            lines[0],
+            "# backfill: lat,lon,alt,l_shell,region from gps.csv"
+        );
+        assert_eq!(
+            lines[1],
+            "# backfill: empty measurement info columns from meas.csv"
+        );
+        let row: Vec<&str> = lines[3].split('\t').collect();
+        assert_eq!(row[..6], ["1", "103", "104", "-4.5", "7000000", "1.638"]);
+        assert_eq!(row[8], "621.863");
+        assert_eq!(
+            output_path(Path::new("/a/data_2024-03-01.info"), None),
+            Path::new("/a/data_2024-03-01.geo.info")
+        );
+    }
+}
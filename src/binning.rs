@@ -0,0 +1,143 @@
+use anyhow::{Context, Result, bail};
+use std::fmt;
+use std::str::FromStr;
+
+/// Start of the .info header line recording the binning of the position columns
+pub const BINNING_PREFIX: &str = "# position_binning: ";
+
+/// Bin width of a quantity, values are rounded to the nearest multiple of it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    pub width: f64,
+    /// Decimals needed to write the multiples of the width
+    decimals: usize,
+}
+
+impl Step {
+    pub fn new(width: f64) -> Result<Self> {
+        if !(width > 0.0 && width.is_finite()) {
+            bail!("bin width {} must be positive", width);
+        }
+        let decimals = (0..9)
+            .find(|&d| {
+                let scaled = width * 10f64.powi(d as i32);
+                (scaled - scaled.round()).abs() < 1e-9 * scaled.max(1.0)
+            })
+            .unwrap_or(9);
+        Ok(Step { width, decimals })
+    }
+
+    pub fn apply(&self, value: f64) -> f64 {
+        let scale = 10f64.powi(self.decimals as i32);
+        // cuts the float error of the multiplication, + 0.0 turns -0 into 0
+        ((value / self.width).round() * self.width * scale).round() / scale + 0.0
+    }
+
+    pub fn format(&self, value: f64) -> String {
+        format!("{:.*}", self.decimals, self.apply(value))
+    }
+}
+
+/// Coarsening of the positions derived from the GPS records for public data releases, e.g.
+/// `lat=0.1,lon=0.1,alt=1,l_shell=0.1`; the quantities without a step keep their precision
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PositionBinning {
+    /// Latitude and longitude in degrees
+    pub lat: Option<Step>,
+    pub lon: Option<Step>,
+    /// Altitude in km
+    pub alt: Option<Step>,
+    pub l_shell: Option<Step>,
+}
+
+impl PositionBinning {
+    pub fn is_none(&self) -> bool {
+        *self == PositionBinning::default()
+    }
+
+    /// Value written for the quantity, with the given decimals when it is not binned
+    pub fn format(step: Option<Step>, value: f64, decimals: usize) -> String {
+        match step {
+            Some(step) => step.format(value),
+            None => format!("{:.*}", decimals, value),
+        }
+    }
+
+    pub fn apply(step: Option<Step>, value: f64) -> f64 {
+        step.map_or(value, |step| step.apply(value))
+    }
+}
+
+impl FromStr for PositionBinning {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut binning = PositionBinning::default();
+        if s == "none" {
+            return Ok(binning);
+        }
+        for part in s.split(',') {
+            let Some((name, width)) = part.split_once('=') else {
+                bail!("expected quantity=width, e.g. lat=0.1,lon=0.1");
+            };
+            let width: f64 = width
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid bin width '{}'", width))?;
+            let step = Some(Step::new(width).with_context(|| format!("{} binning", name))?);
+            match name.trim() {
+                "lat" => binning.lat = step,
+                "lon" => binning.lon = step,
+                "alt" => binning.alt = step,
+                "l_shell" => binning.l_shell = step,
+                name => bail!(
+                    "unknown quantity '{}', expected lat, lon, alt or l_shell",
+                    name
+                ),
+            }
+        }
+        Ok(binning)
+    }
+}
+
+impl fmt::Display for PositionBinning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts: Vec<String> = [
+            ("lat", self.lat),
+            ("lon", self.lon),
+            ("alt", self.alt),
+            ("l_shell", self.l_shell),
+        ]
+        .iter()
+        .filter_map(|(name, step)| step.map(|step| format!("{}={}", name, step.width)))
+        .collect();
+        if parts.is_empty() {
+            f.write_str("none")
+        } else {
+            f.write_str(&parts.join(","))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_binning() {
+        let binning: PositionBinning = "lat=0.1,lon=0.25,alt=5".parse().unwrap();
+        assert_eq!(binning.to_string(), "lat=0.1,lon=0.25,alt=5");
+        assert_eq!(PositionBinning::format(binning.lat, 32.99637, 4), "33.0");
+        assert_eq!(PositionBinning::format(binning.lon, -157.632, 4), "-157.75");
+        assert_eq!(PositionBinning::format(binning.alt, 628.164, 3), "630");
+        assert_eq!(
+            PositionBinning::format(binning.l_shell, 1.56791, 3),
+            "1.568"
+        );
+        assert_eq!(PositionBinning::format(binning.lat, -0.04, 4), "0.0");
+        assert_eq!(PositionBinning::apply(binning.lat, 32.99637), 33.0);
+        assert!("none".parse::<PositionBinning>().unwrap().is_none());
+        assert!("lat=0".parse::<PositionBinning>().is_err());
+        assert!("speed=1".parse::<PositionBinning>().is_err());
+    }
+}
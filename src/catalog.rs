@@ -0,0 +1,450 @@
+use crate::manifest::Manifest;
+use crate::processor::ExposureLedger;
+use crate::provenance::BuildInfo;
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+use anyhow::Context;
+use anyhow::{Result, bail};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Tables of the catalog, `{id}` is the auto-incremented key of the database
+const CREATE_TABLES: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id {id},
+    satellite TEXT NOT NULL,
+    registered TEXT NOT NULL,
+    repro_hash TEXT NOT NULL,
+    decoder_version TEXT NOT NULL,
+    decoder_version_code BIGINT NOT NULL,
+    decoder_commit TEXT NOT NULL,
+    output_directory TEXT NOT NULL,
+    data_file TEXT NOT NULL,
+    written_frames BIGINT NOT NULL,
+    written_time DOUBLE PRECISION NOT NULL,
+    skipped_frames BIGINT NOT NULL,
+    bad_lines BIGINT NOT NULL,
+    truncated_frames BIGINT NOT NULL,
+    invalid_gps_frames BIGINT NOT NULL,
+    stale_gps_frames BIGINT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS days (
+    id {id},
+    run_id BIGINT NOT NULL REFERENCES runs (id),
+    satellite TEXT NOT NULL,
+    date TEXT NOT NULL,
+    frames BIGINT NOT NULL,
+    first_frame TEXT NOT NULL,
+    last_frame TEXT NOT NULL,
+    warnings TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS files (
+    day_id BIGINT NOT NULL REFERENCES days (id),
+    name TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    sha256 TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS days_satellite_date ON days (satellite, date);
+";
+
+const INSERT_RUN: &str = "INSERT INTO runs (satellite, registered, repro_hash, decoder_version, \
+     decoder_version_code, decoder_commit, output_directory, data_file, written_frames, \
+     written_time, skipped_frames, bad_lines, truncated_frames, invalid_gps_frames, \
+     stale_gps_frames) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15) \
+     RETURNING id";
+
+const INSERT_DAY: &str = "INSERT INTO days (run_id, satellite, date, frames, first_frame, \
+     last_frame, warnings) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id";
+
+const INSERT_FILE: &str = "INSERT INTO files (day_id, name, size, sha256) VALUES ($1, $2, $3, $4)";
+
+/// Database of the processed runs, a SQLite file or a PostgreSQL connection string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CatalogTarget {
+    Sqlite(String),
+    Postgres(String),
+}
+
+impl FromStr for CatalogTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.starts_with("postgres://") || s.starts_with("postgresql://") {
+            Ok(CatalogTarget::Postgres(s.to_string()))
+        } else if s.is_empty() {
+            bail!("expected a SQLite file or a postgres:// connection string");
+        } else {
+            Ok(CatalogTarget::Sqlite(
+                s.strip_prefix("sqlite:").unwrap_or(s).to_string(),
+            ))
+        }
+    }
+}
+
+impl fmt::Display for CatalogTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CatalogTarget::Sqlite(path) => write!(f, "sqlite:{}", path),
+            CatalogTarget::Postgres(url) => f.write_str(url),
+        }
+    }
+}
+
+/// Sortable form of a crate version, major * 1000000 + minor * 1000 + patch
+pub fn version_code(version: &str) -> i64 {
+    let release = version.split(['-', '+']).next().unwrap_or_default();
+    let mut parts = release
+        .split('.')
+        .map(|part| part.parse::<i64>().unwrap_or(0));
+    let mut code = 0;
+    for _ in 0..3 {
+        code = code * 1000 + parts.next().unwrap_or(0).min(999);
+    }
+    code
+}
+
+/// Run registered in the catalog with the days it finalized
+#[derive(Debug, Clone)]
+pub struct RunEntry {
+    pub satellite: String,
+    /// UTC time of the registration
+    pub registered: String,
+    pub repro_hash: String,
+    pub build: BuildInfo,
+    pub output_directory: String,
+    pub data_file: String,
+    pub written_frames: usize,
+    pub written_time: f64,
+    pub skipped_frames: usize,
+    pub bad_lines: usize,
+    pub truncated_frames: usize,
+    pub invalid_gps_frames: usize,
+    pub stale_gps_frames: usize,
+    /// Manifests of the finalized days
+    pub days: Vec<Manifest>,
+}
+
+impl RunEntry {
+    /// Entry of a finished run, with the manifests of its days read from the output directory
+    pub fn new(
+        satellite: &str,
+        repro_hash: &str,
+        ledger: &ExposureLedger,
+        data_file: &str,
+        out_dir: &str,
+    ) -> Result<Self> {
+        let dir = Path::new(out_dir);
+        let days = ledger
+            .finalized_days
+            .iter()
+            .map(|date| Manifest::load(&dir.join(Manifest::file_name(date))))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(RunEntry {
+            satellite: satellite.to_string(),
+            registered: chrono::Utc::now()
+                .format("%Y-%m-%d %H:%M:%S UTC")
+                .to_string(),
+            repro_hash: repro_hash.to_string(),
+            build: BuildInfo::current(),
+            output_directory: dir
+                .canonicalize()
+                .unwrap_or_else(|_| dir.to_path_buf())
+                .display()
+                .to_string(),
+            data_file: data_file.to_string(),
+            written_frames: ledger.written_frames,
+            written_time: ledger.written_time,
+            skipped_frames: ledger.skipped_frames,
+            bad_lines: ledger.bad_lines,
+            truncated_frames: ledger.truncated_frames,
+            invalid_gps_frames: ledger.invalid_gps_frames,
+            stale_gps_frames: ledger.stale_gps_frames,
+            days,
+        })
+    }
+}
+
+/// Parameter of a catalog statement
+#[derive(Debug, Clone)]
+#[cfg_attr(not(any(feature = "sqlite", feature = "postgres")), allow(dead_code))]
+enum Value {
+    Text(String),
+    Int(i64),
+    Real(f64),
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Text(value.to_string())
+    }
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Value::Int(value as i64)
+    }
+}
+
+/// Connection to a catalog database, the statements use `$n` parameters
+trait Connection {
+    fn batch(&mut self, sql: &str) -> Result<()>;
+    fn execute(&mut self, sql: &str, params: &[Value]) -> Result<()>;
+    /// Runs an INSERT ... RETURNING id
+    fn insert(&mut self, sql: &str, params: &[Value]) -> Result<i64>;
+}
+
+#[cfg(feature = "sqlite")]
+impl Connection for rusqlite::Connection {
+    fn batch(&mut self, sql: &str) -> Result<()> {
+        Ok(self.execute_batch(sql)?)
+    }
+
+    fn execute(&mut self, sql: &str, params: &[Value]) -> Result<()> {
+        rusqlite::Connection::execute(self, sql, sqlite_params(params))?;
+        Ok(())
+    }
+
+    fn insert(&mut self, sql: &str, params: &[Value]) -> Result<i64> {
+        Ok(self.query_row(sql, sqlite_params(params), |row| row.get(0))?)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_params(params: &[Value]) -> rusqlite::ParamsFromIter<Vec<rusqlite::types::Value>> {
+    rusqlite::params_from_iter(
+        params
+            .iter()
+            .map(|value| match value {
+                Value::Text(text) => rusqlite::types::Value::Text(text.clone()),
+                Value::Int(int) => rusqlite::types::Value::Integer(*int),
+                Value::Real(real) => rusqlite::types::Value::Real(*real),
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+#[cfg(feature = "postgres")]
+impl Connection for postgres::Client {
+    fn batch(&mut self, sql: &str) -> Result<()> {
+        Ok(self.batch_execute(sql)?)
+    }
+
+    fn execute(&mut self, sql: &str, params: &[Value]) -> Result<()> {
+        let params = postgres_params(params);
+        let refs: Vec<&(dyn postgres::types::ToSql + Sync)> =
+            params.iter().map(|param| param.as_ref()).collect();
+        postgres::Client::execute(self, sql, &refs)?;
+        Ok(())
+    }
+
+    fn insert(&mut self, sql: &str, params: &[Value]) -> Result<i64> {
+        let params = postgres_params(params);
+        let refs: Vec<&(dyn postgres::types::ToSql + Sync)> =
+            params.iter().map(|param| param.as_ref()).collect();
+        Ok(self.query_one(sql, &refs)?.get(0))
+    }
+}
+
+#[cfg(feature = "postgres")]
+fn postgres_params(params: &[Value]) -> Vec<Box<dyn postgres::types::ToSql + Sync>> {
+    params
+        .iter()
+        .map(|value| -> Box<dyn postgres::types::ToSql + Sync> {
+            match value {
+                Value::Text(text) => Box::new(text.clone()),
+                Value::Int(int) => Box::new(*int),
+                Value::Real(real) => Box::new(*real),
+            }
+        })
+        .collect()
+}
+
+/// Connects to the catalog, with the type of the auto-incremented keys of the database
+fn connect(target: &CatalogTarget) -> Result<(Box<dyn Connection>, &'static str)> {
+    match target {
+        #[cfg(feature = "sqlite")]
+        CatalogTarget::Sqlite(path) => Ok((
+            Box::new(
+                rusqlite::Connection::open(path)
+                    .with_context(|| format!("cannot open catalog {}", path))?,
+            ),
+            "INTEGER PRIMARY KEY",
+        )),
+        #[cfg(not(feature = "sqlite"))]
+        CatalogTarget::Sqlite(_) => {
+            bail!("built without the sqlite feature (cargo build --release --features sqlite)")
+        }
+        #[cfg(feature = "postgres")]
+        CatalogTarget::Postgres(url) => Ok((
+            Box::new(
+                postgres::Client::connect(url, postgres::NoTls)
+                    .context("cannot connect to the catalog database")?,
+            ),
+            "BIGSERIAL PRIMARY KEY",
+        )),
+        #[cfg(not(feature = "postgres"))]
+        CatalogTarget::Postgres(_) => {
+            bail!("built without the postgres feature (cargo build --release --features postgres)")
+        }
+    }
+}
+
+/// Opens the catalog, creating its tables when missing
+fn open(target: &CatalogTarget) -> Result<Box<dyn Connection>> {
+    let (mut connection, id) = connect(target)?;
+    connection.batch(&CREATE_TABLES.replace("{id}", id))?;
+    Ok(connection)
+}
+
+/// Adds the run, its days and their files to the catalog in one transaction, returns the
+/// id of the run
+pub fn register(target: &CatalogTarget, entry: &RunEntry) -> Result<i64> {
+    let mut connection = open(target)?;
+    connection.batch("BEGIN")?;
+    match insert_run(connection.as_mut(), entry) {
+        Ok(id) => {
+            connection.batch("COMMIT")?;
+            Ok(id)
+        }
+        Err(e) => {
+            let _ = connection.batch("ROLLBACK");
+            Err(e)
+        }
+    }
+}
+
+fn insert_run(connection: &mut dyn Connection, entry: &RunEntry) -> Result<i64> {
+    let run_id = connection.insert(
+        INSERT_RUN,
+        &[
+            entry.satellite.as_str().into(),
+            entry.registered.as_str().into(),
+            entry.repro_hash.as_str().into(),
+            entry.build.version.as_str().into(),
+            Value::Int(version_code(&entry.build.version)),
+            entry.build.commit.as_str().into(),
+            entry.output_directory.as_str().into(),
+            entry.data_file.as_str().into(),
+            entry.written_frames.into(),
+            Value::Real(entry.written_time),
+            entry.skipped_frames.into(),
+            entry.bad_lines.into(),
+            entry.truncated_frames.into(),
+            entry.invalid_gps_frames.into(),
+            entry.stale_gps_frames.into(),
+        ],
+    )?;
+    for day in &entry.days {
+        let day_id = connection.insert(
+            INSERT_DAY,
+            &[
+                Value::Int(run_id),
+                entry.satellite.as_str().into(),
+                day.date.as_str().into(),
+                day.frames.into(),
+                day.first_frame.as_str().into(),
+                day.last_frame.as_str().into(),
+                day.warnings.join("; ").as_str().into(),
+            ],
+        )?;
+        for file in &day.files {
+            connection.execute(
+                INSERT_FILE,
+                &[
+                    Value::Int(day_id),
+                    file.name.as_str().into(),
+                    Value::Int(file.size as i64),
+                    file.sha256.as_str().into(),
+                ],
+            )?;
+        }
+    }
+    Ok(run_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{FileEntry, MANIFEST_VERSION};
+
+    #[test]
+    fn test_catalog() {
+        assert_eq!(version_code("1.3.0"), 1_003_000);
+        assert_eq!(version_code("0.12.4-rc1"), 12_004);
+        assert!(version_code("1.10.0") > version_code("1.9.7"));
+        assert_eq!(
+            "postgres://catalog@db/oneweb"
+                .parse::<CatalogTarget>()
+                .unwrap(),
+            CatalogTarget::Postgres("postgres://catalog@db/oneweb".to_string())
+        );
+        assert_eq!(
+            "sqlite:runs.db".parse::<CatalogTarget>().unwrap(),
+            CatalogTarget::Sqlite("runs.db".to_string())
+        );
+
+        let day = |date: &str| Manifest {
+            manifest_version: MANIFEST_VERSION,
+            date: date.to_string(),
+            repro_hash: "abc".to_string(),
+            build: None,
+            frames: 20,
+            first_frame: format!("{} 00:00:01", date),
+            last_frame: format!("{} 23:59:01", date),
+            files: vec![FileEntry {
+                name: format!("data_{}.clog", date),
+                size: 100,
+                sha256: "00".to_string(),
+            }],
+            warnings: Vec::new(),
+        };
+        let mut entry = RunEntry {
+            satellite: "OW-0042".to_string(),
+            registered: "2024-03-02 10:00:00 UTC".to_string(),
+            repro_hash: "abc".to_string(),
+            build: BuildInfo::current(),
+            output_directory: "/data/out".to_string(),
+            data_file: "dosimeter_image_packets.csv".to_string(),
+            written_frames: 40,
+            written_time: 821.4,
+            skipped_frames: 0,
+            bad_lines: 1,
+            truncated_frames: 0,
+            invalid_gps_frames: 0,
+            stale_gps_frames: 0,
+            days: vec![day("2024-02-29"), day("2024-03-01")],
+        };
+        entry.build.version = "1.2.0".to_string();
+
+        let path = std::env::temp_dir().join(format!("oneweb-catalog-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let target = CatalogTarget::Sqlite(path.display().to_string());
+        #[cfg(feature = "sqlite")]
+        {
+            assert_eq!(register(&target, &entry).unwrap(), 1);
+            entry.build.version = "1.3.1".to_string();
+            entry.days.remove(0);
+            assert_eq!(register(&target, &entry).unwrap(), 2);
+
+            let connection = rusqlite::Connection::open(&path).unwrap();
+            let dates: Vec<String> = connection
+                .prepare(
+                    "SELECT DISTINCT date FROM days JOIN runs ON runs.id = days.run_id \
+                     WHERE days.satellite = 'OW-0042' AND decoder_version_code >= 1003000",
+                )
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap();
+            assert_eq!(dates, ["2024-03-01"]);
+            let files: i64 = connection
+                .query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(files, 3);
+            let _ = std::fs::remove_file(&path);
+        }
+        #[cfg(not(feature = "sqlite"))]
+        assert!(register(&target, &entry).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use crate::deglitch::GpsDeglitcher;
+use crate::event_catalog::EventCatalog;
+use crate::gps_processor::GpsData;
+use crate::info_processor::MeasInfoData;
+use crate::line_reader::Position;
+use crate::manifest::DayFiles;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// State of a conversion after a written frame, an interrupted run continues from it with
+/// `--resume`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Repro hash of the run, the resumed run must have the same inputs and settings
+    pub repro_hash: String,
+    /// Frames of the data file decoded so far
+    pub frames: usize,
+    /// Positions of the data, GPS and measurement info files after the last read line
+    pub data: Position,
+    pub gps: Position,
+    pub meas: Position,
+    pub last_gps_data: GpsData,
+    pub pending_gps_data: Option<GpsData>,
+    pub last_info_data: MeasInfoData,
+    /// Frames written to the files of the current day
+    pub frame_index: usize,
+    /// Day being written and the lengths of its files at the checkpoint
+    pub day: Option<DayFiles>,
+    pub file_lengths: Vec<u64>,
+    /// Clusters cataloged so far on the day
+    #[serde(default)]
+    pub catalog: Option<EventCatalog>,
+    /// GPS records held back by the outlier repair
+    #[serde(default)]
+    pub deglitch: Option<GpsDeglitcher>,
+    /// Dose map and hot pixel statistics accumulated up to the checkpoint, saved next to it
+    #[serde(default)]
+    pub dose_map: Option<PathBuf>,
+    #[serde(default)]
+    pub hot_pixel_stats: Option<PathBuf>,
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("cannot read checkpoint {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("invalid checkpoint {}", path.display()))
+    }
+
+    /// Replaces the checkpoint file, a crash while writing leaves the previous one
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("cannot write checkpoint {}", tmp.display()))?;
+        fs::rename(&tmp, path)
+            .with_context(|| format!("cannot write checkpoint {}", path.display()))
+    }
+
+    /// Files of the cumulative products saved with the checkpoint
+    pub fn products(&self) -> impl Iterator<Item = &PathBuf> {
+        self.dose_map.iter().chain(&self.hot_pixel_stats)
+    }
+}
+
+/// File of a cumulative product saved with the checkpoint after the frame; it is named by
+/// the frame so the files of the previous checkpoint stay valid until it is replaced
+pub fn product_path(path: &Path, frames: usize, product: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.{}", frames, product));
+    path.with_file_name(name)
+}
+
+/// Opens an output file of the checkpoint day for appending, the content written after the
+/// checkpoint is cut off
+pub fn reopen(path: &Path, len: u64) -> Result<BufWriter<File>> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("cannot reopen {}", path.display()))?;
+    file.set_len(len)?;
+    file.seek(SeekFrom::End(0))?;
+    Ok(BufWriter::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_checkpoint() {
+        let dir = std::env::temp_dir().join(format!("oneweb-checkpoint-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut day = DayFiles::new("2024-03-01", vec!["data_2024-03-01.clog".to_string()]);
+        day.add_frame(1709251200.5);
+        let checkpoint = Checkpoint {
+            repro_hash: "abc".to_string(),
+            frames: 12,
+            data: Position {
+                offset: 4096,
+                line_no: 40,
+            },
+            gps: Position::default(),
+            meas: Position::default(),
+            last_gps_data: GpsData {
+                timestamp: 1709251200.0,
+                problems: vec!["zero position".to_string()],
+                ..Default::default()
+            },
+            pending_gps_data: None,
+            last_info_data: MeasInfoData::default(),
+            frame_index: 3,
+            day: Some(day),
+            file_lengths: vec![6],
+            catalog: None,
+            deglitch: None,
+            dose_map: Some(product_path(&dir.join("run.checkpoint"), 12, "dose_map")),
+            hot_pixel_stats: None,
+        };
+        let path = dir.join("run.checkpoint");
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        assert_eq!(loaded.data, checkpoint.data);
+        assert_eq!(loaded.last_gps_data.problems, ["zero position"]);
+        assert_eq!(
+            loaded.products().collect::<Vec<_>>(),
+            [&dir.join("run.checkpoint.12.dose_map")]
+        );
+        assert_eq!(loaded.day.unwrap().first_frame, 1709251200.5);
+        assert!(!path.with_extension("tmp").exists());
+
+        let clog = dir.join("data_2024-03-01.clog");
+        fs::write(&clog, "Frame 1\nFrame 2\n").unwrap();
+        let mut writer = reopen(&clog, 8).unwrap();
+        writer.write_all(b"Frame 3\n").unwrap();
+        drop(writer);
+        assert_eq!(fs::read_to_string(&clog).unwrap(), "Frame 1\nFrame 3\n");
+        assert!(Checkpoint::load(&dir.join("missing")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
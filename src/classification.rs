@@ -0,0 +1,136 @@
+use crate::clustering::ClusterFeatures;
+use serde::Deserialize;
+use std::fmt;
+
+/// Standard Timepix cluster taxonomy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClusterClass {
+    Dot,
+    SmallBlob,
+    HeavyBlob,
+    StraightTrack,
+    CurlyTrack,
+}
+
+impl fmt::Display for ClusterClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ClusterClass::Dot => "dot",
+            ClusterClass::SmallBlob => "small blob",
+            ClusterClass::HeavyBlob => "heavy blob",
+            ClusterClass::StraightTrack => "straight track",
+            ClusterClass::CurlyTrack => "curly track",
+        })
+    }
+}
+
+/// Thresholds of the classification, the `[classification]` table of the configuration file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClassThresholds {
+    /// Largest dot in pixels
+    pub dot_max_pixels: usize,
+    /// Clusters up to this size that are not tracks are blobs
+    pub blob_max_pixels: usize,
+    /// Largest roundness (minor over major axis) of a straight track
+    pub straight_max_roundness: f64,
+    /// Smallest fraction of the pixels within one pixel of the axis of a straight track
+    pub straight_min_linearity: f64,
+    /// Smallest fraction of the bounding box covered by a blob
+    pub blob_min_fill: f64,
+    /// Mean energy per pixel in keV above which a blob is heavy
+    pub heavy_blob_kev: f64,
+}
+
+impl Default for ClassThresholds {
+    fn default() -> Self {
+        ClassThresholds {
+            dot_max_pixels: 2,
+            blob_max_pixels: 4,
+            straight_max_roundness: 0.5,
+            straight_min_linearity: 0.9,
+            blob_min_fill: 0.5,
+            heavy_blob_kev: 150.0,
+        }
+    }
+}
+
+impl ClassThresholds {
+    /// Class of the cluster from its morphology features
+    pub fn classify(&self, features: &ClusterFeatures, kev_per_count: f64) -> ClusterClass {
+        if features.pixels <= self.dot_max_pixels {
+            return ClusterClass::Dot;
+        }
+        if features.roundness <= self.straight_max_roundness
+            && features.linearity >= self.straight_min_linearity
+        {
+            return ClusterClass::StraightTrack;
+        }
+        let (x_min, y_min, x_max, y_max) = features.bounding_box;
+        let area = ((x_max - x_min) as f64 + 1.0) * ((y_max - y_min) as f64 + 1.0);
+        if features.pixels <= self.blob_max_pixels
+            || features.pixels as f64 / area >= self.blob_min_fill
+        {
+            let mean_energy = features.total as f64 * kev_per_count / features.pixels as f64;
+            return if mean_energy > self.heavy_blob_kev {
+                ClusterClass::HeavyBlob
+            } else {
+                ClusterClass::SmallBlob
+            };
+        }
+        ClusterClass::CurlyTrack
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clustering::{Cluster, Pixel};
+
+    #[test]
+    fn test_classify() {
+        let cluster = |pixels: &[(u8, u8, u16)]| Cluster {
+            pixels: pixels
+                .iter()
+                .map(|&(x, y, value)| Pixel::new(x, y, value, 0))
+                .collect(),
+            merged: 0,
+        };
+        let thresholds = ClassThresholds::default();
+        let class = |pixels: &[(u8, u8, u16)]| thresholds.classify(&cluster(pixels).analyze(), 1.0);
+
+        assert_eq!(class(&[(10, 10, 30)]), ClusterClass::Dot);
+        let blob = [
+            (10, 10, 30),
+            (11, 10, 30),
+            (10, 11, 30),
+            (11, 11, 30),
+            (12, 11, 30),
+        ];
+        assert_eq!(class(&blob), ClusterClass::SmallBlob);
+        let heavy = blob.map(|(x, y, _)| (x, y, 400));
+        assert_eq!(class(&heavy), ClusterClass::HeavyBlob);
+        let line: Vec<_> = (0..12).map(|i| (20 + i, 30 + i / 3, 20)).collect();
+        assert_eq!(class(&line), ClusterClass::StraightTrack);
+        let curl = [
+            (40, 40, 20),
+            (41, 41, 20),
+            (42, 42, 20),
+            (43, 43, 20),
+            (44, 42, 20),
+            (45, 41, 20),
+            (46, 40, 20),
+            (46, 39, 20),
+            (45, 38, 20),
+        ];
+        assert_eq!(class(&curl), ClusterClass::CurlyTrack);
+        assert_eq!(ClusterClass::HeavyBlob.to_string(), "heavy blob");
+
+        let custom: ClassThresholds = toml::from_str("dot_max_pixels = 5").unwrap();
+        assert_eq!(
+            custom.classify(&cluster(&blob).analyze(), 1.0),
+            ClusterClass::Dot
+        );
+        assert!(toml::from_str::<ClassThresholds>("dots = 5").is_err());
+    }
+}
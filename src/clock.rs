@@ -0,0 +1,69 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Source of the time measured by the stage timers, replaceable for reproducible runs
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Reading of the clock as the time since an arbitrary origin
+    fn now(&self) -> Duration;
+
+    /// Time elapsed since an earlier reading
+    fn elapsed(&self, since: Duration) -> Duration {
+        self.now().saturating_sub(since)
+    }
+}
+
+/// Monotonic system clock
+#[derive(Debug)]
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+}
+
+/// Clock for tests where every measured interval takes the same fixed time, so the timing
+/// report does not depend on the machine or on the thread scheduling
+#[derive(Debug)]
+pub struct SimulatedClock {
+    pub step: Duration,
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn elapsed(&self, _since: Duration) -> Duration {
+        self.step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clocks() {
+        let clock = SimulatedClock {
+            step: Duration::from_millis(1),
+        };
+        let start = clock.now();
+        assert_eq!(clock.elapsed(start), Duration::from_millis(1));
+        assert_eq!(clock.elapsed(start), Duration::from_millis(1));
+
+        let clock = SystemClock::default();
+        let start = clock.now();
+        assert!(clock.now() >= start);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::clustering::Cluster;
+use crate::gps_processor::GpsData;
+#[cfg(feature = "parquet")]
+use anyhow::Context;
+use anyhow::Result;
+#[cfg(not(feature = "parquet"))]
+use anyhow::bail;
+use std::path::Path;
+#[cfg(feature = "parquet")]
+use std::sync::Arc;
+
+/// Name of the cluster table of a day written by `--format parquet`
+pub fn file_name(date: &str) -> String {
+    format!("data_{}.clusters.parquet", date)
+}
+
+/// Columns of the buffered row group
+#[derive(Default)]
+struct Columns {
+    frame: Vec<u64>,
+    timestamp: Vec<f64>,
+    centroid_x: Vec<f64>,
+    centroid_y: Vec<f64>,
+    size: Vec<u32>,
+    total_itot: Vec<u64>,
+    total_tot: Vec<u64>,
+    gps_x: Vec<Option<f64>>,
+    gps_y: Vec<Option<f64>>,
+    gps_z: Vec<Option<f64>>,
+}
+
+/// Parquet table of the clusters of a day, one row per cluster with its frame number and
+/// time, value weighted centroid, size, iToT and ToT sums and the J2000 position of the
+/// matched GPS record (null when the GPS is stale or not read)
+#[cfg_attr(not(feature = "parquet"), allow(dead_code))]
+pub struct ClusterTable {
+    #[cfg(feature = "parquet")]
+    writer: parquet::arrow::ArrowWriter<std::fs::File>,
+    #[cfg(not(feature = "parquet"))]
+    writer: std::convert::Infallible,
+    columns: Columns,
+    /// Frames of a row group; the rows are buffered until a group is full, so the memory of
+    /// the writer stays bounded however many frames the day has
+    chunk_frames: usize,
+    /// Frames of the buffered row group
+    frames: usize,
+}
+
+impl ClusterTable {
+    /// Creates the table of the day in the output directory, written in row groups of
+    /// `chunk_frames` frames
+    pub fn create(dir: &Path, date: &str, chunk_frames: usize) -> Result<Self> {
+        Self::with_row_groups(&dir.join(file_name(date)), chunk_frames)
+    }
+
+    /// Adds the clusters of a frame
+    pub fn write_frame(
+        &mut self,
+        frame: usize,
+        timestamp: f64,
+        clusters: &[Cluster],
+        gps: Option<&GpsData>,
+    ) -> Result<()> {
+        for cluster in clusters {
+            let features = cluster.analyze();
+            let columns = &mut self.columns;
+            columns.frame.push(frame as u64);
+            columns.timestamp.push(timestamp);
+            columns.centroid_x.push(features.centroid.0);
+            columns.centroid_y.push(features.centroid.1);
+            columns.size.push(features.pixels as u32);
+            columns.total_itot.push(features.total);
+            columns
+                .total_tot
+                .push(cluster.pixels.iter().map(|p| p.value2 as u64).sum());
+            columns.gps_x.push(gps.map(|g| g.j2000_x));
+            columns.gps_y.push(gps.map(|g| g.j2000_y));
+            columns.gps_z.push(gps.map(|g| g.j2000_z));
+        }
+        self.frames += 1;
+        if self.frames >= self.chunk_frames {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl ClusterTable {
+    fn with_row_groups(path: &Path, chunk_frames: usize) -> Result<Self> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("cannot create {}", path.display()))?;
+        let properties = parquet::file::properties::WriterProperties::builder()
+            .set_compression(parquet::basic::Compression::SNAPPY)
+            .build();
+        let writer = parquet::arrow::ArrowWriter::try_new(file, schema(), Some(properties))?;
+        Ok(ClusterTable {
+            writer,
+            columns: Columns::default(),
+            chunk_frames,
+            frames: 0,
+        })
+    }
+
+    /// Writes the buffered rows as a row group
+    fn write_row_group(&mut self) -> Result<()> {
+        use arrow::array::{ArrayRef, Float64Array, UInt32Array, UInt64Array};
+
+        self.frames = 0;
+        if self.columns.frame.is_empty() {
+            return Ok(());
+        }
+        let c = std::mem::take(&mut self.columns);
+        let arrays: Vec<ArrayRef> = vec![
+            Arc::new(UInt64Array::from(c.frame)),
+            Arc::new(Float64Array::from(c.timestamp)),
+            Arc::new(Float64Array::from(c.centroid_x)),
+            Arc::new(Float64Array::from(c.centroid_y)),
+            Arc::new(UInt32Array::from(c.size)),
+            Arc::new(UInt64Array::from(c.total_itot)),
+            Arc::new(UInt64Array::from(c.total_tot)),
+            Arc::new(Float64Array::from(c.gps_x)),
+            Arc::new(Float64Array::from(c.gps_y)),
+            Arc::new(Float64Array::from(c.gps_z)),
+        ];
+        let batch = arrow::record_batch::RecordBatch::try_new(schema(), arrays)?;
+        self.writer.write(&batch)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the last row group and the footer of the file
+    pub fn finish(mut self) -> Result<()> {
+        self.write_row_group()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet")]
+fn schema() -> arrow::datatypes::SchemaRef {
+    use arrow::datatypes::{DataType, Field, Schema};
+
+    Arc::new(Schema::new(vec![
+        Field::new("frame", DataType::UInt64, false),
+        Field::new("timestamp", DataType::Float64, false),
+        Field::new("centroid_x", DataType::Float64, false),
+        Field::new("centroid_y", DataType::Float64, false),
+        Field::new("size", DataType::UInt32, false),
+        Field::new("total_itot", DataType::UInt64, false),
+        Field::new("total_tot", DataType::UInt64, false),
+        Field::new("gps_x", DataType::Float64, true),
+        Field::new("gps_y", DataType::Float64, true),
+        Field::new("gps_z", DataType::Float64, true),
+    ]))
+}
+
+#[cfg(not(feature = "parquet"))]
+impl ClusterTable {
+    fn with_row_groups(_path: &Path, _chunk_frames: usize) -> Result<Self> {
+        bail!("built without the parquet feature (cargo build --release --features parquet)")
+    }
+
+    fn write_row_group(&mut self) -> Result<()> {
+        match self.writer {}
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self.writer {}
+    }
+}
+
+#[cfg(all(test, feature = "parquet"))]
+mod tests {
+    use super::*;
+    use crate::clustering::Pixel;
+    use arrow::array::{Array, AsArray};
+    use arrow::datatypes::{Float64Type, UInt32Type, UInt64Type};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn test_cluster_table() {
+        let path =
+            std::env::temp_dir().join(format!("oneweb-clusters-{}.parquet", std::process::id()));
+        let pixel = |x, y, value, value2| Pixel {
+            x,
+            y,
+            value,
+            value2,
+            saturated: false,
+            neighbor_mask: 0,
+            neighbors: [0; 8],
+        };
+        let cluster = Cluster {
+            pixels: vec![pixel(10, 20, 30, 3), pixel(12, 20, 10, 1)],
+            merged: 0,
+        };
+        let gps = GpsData {
+            j2000_x: 7.0e6,
+            j2000_y: -1.0e6,
+            j2000_z: 2.5e5,
+            ..Default::default()
+        };
+
+        // row groups of 2 frames with 2 clusters each
+        let mut table = ClusterTable::with_row_groups(&path, 2).unwrap();
+        for frame in 1..=5 {
+            let gps = (frame != 3).then_some(&gps);
+            let clusters = vec![cluster.clone(); 2];
+            table
+                .write_frame(frame, 1709251200.0 + frame as f64, &clusters, gps)
+                .unwrap();
+        }
+        table.finish().unwrap();
+
+        let builder =
+            ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap()).unwrap();
+        let row_groups: Vec<i64> = builder
+            .metadata()
+            .row_groups()
+            .iter()
+            .map(|group| group.num_rows())
+            .collect();
+        assert_eq!(row_groups, vec![4, 4, 2]);
+        let batches: Vec<_> = builder.build().unwrap().map(|b| b.unwrap()).collect();
+        let batch = arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(batch.num_rows(), 10);
+        let frames = batch.column(0).as_primitive::<UInt64Type>();
+        assert_eq!(frames.values().to_vec(), vec![1, 1, 2, 2, 3, 3, 4, 4, 5, 5]);
+        let timestamps = batch.column(1).as_primitive::<Float64Type>();
+        assert_eq!(timestamps.value(9), 1709251205.0);
+        assert_eq!(batch.column(2).as_primitive::<Float64Type>().value(0), 10.5);
+        assert_eq!(batch.column(3).as_primitive::<Float64Type>().value(0), 20.0);
+        assert_eq!(batch.column(4).as_primitive::<UInt32Type>().value(0), 2);
+        assert_eq!(batch.column(5).as_primitive::<UInt64Type>().value(0), 40);
+        assert_eq!(batch.column(6).as_primitive::<UInt64Type>().value(0), 4);
+        let gps_x = batch.column(7).as_primitive::<Float64Type>();
+        assert_eq!(gps_x.value(0), 7.0e6);
+        assert_eq!(gps_x.null_count(), 2);
+        assert!(gps_x.is_null(4) && gps_x.is_null(5));
+        assert_eq!(
+            batch.column(9).as_primitive::<Float64Type>().value(9),
+            2.5e5
+        );
+    }
+}
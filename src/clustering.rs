@@ -1,10 +1,15 @@
+use anyhow::{Result, bail};
 use std::fmt;
+use std::str::FromStr;
 
+#[derive(Clone)]
 pub struct Pixel {
     pub x: u8,
     pub y: u8,
     pub value: u16,
     pub value2: u16,
+    /// The iToT counter is saturated, the value is a lower bound
+    pub saturated: bool,
     pub neighbor_mask: u8,
     pub neighbors: [i8; 8],
 }
@@ -17,6 +22,7 @@ impl Pixel {
             y,
             value,
             value2,
+            saturated: false,
             neighbor_mask: 0,
             neighbors: [-1; 8],
         }
@@ -38,20 +44,283 @@ impl fmt::Debug for Pixel {
     }
 }
 
-#[derive(Debug, Default)]
+/// Morphology features of a cluster, see `Cluster::analyze`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterFeatures {
+    pub pixels: usize,
+    /// Sum of the pixel values
+    pub total: u64,
+    /// Largest pixel value
+    pub max: u16,
+    /// Inclusive bounding box (x_min, y_min, x_max, y_max)
+    pub bounding_box: (u8, u8, u8, u8),
+    /// Ratio of the minor to the major axis of the pixel distribution, 1 for round
+    /// clusters and 0 for a line
+    pub roundness: f64,
+    /// Fraction of the pixels within one pixel of the major axis
+    pub linearity: f64,
+    /// A pixel is on the edge of the sensor, the cluster may be cut off
+    pub border: bool,
+    /// Centroid weighted by the pixel values (column, row)
+    pub centroid: (f64, f64),
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct Cluster {
     pub pixels: Vec<Pixel>,
+    /// Number of clusters merged into this one by `merge_clusters`
+    pub merged: usize,
 }
 
 #[allow(dead_code)]
 impl Cluster {
     pub fn new() -> Cluster {
-        Cluster { pixels: Vec::new() }
+        Cluster {
+            pixels: Vec::new(),
+            merged: 0,
+        }
     }
 
     pub fn add_pixel(&mut self, pixel: Pixel) {
         self.pixels.push(pixel);
     }
+
+    /// Fraction of the pixels with a saturated iToT counter
+    pub fn saturation(&self) -> f64 {
+        if self.pixels.is_empty() {
+            return 0.0;
+        }
+        self.pixels.iter().filter(|p| p.saturated).count() as f64 / self.pixels.len() as f64
+    }
+
+    /// Inclusive bounding box (x_min, y_min, x_max, y_max)
+    pub fn bounding_box(&self) -> (u8, u8, u8, u8) {
+        self.pixels.iter().fold((u8::MAX, u8::MAX, 0, 0), |b, p| {
+            (b.0.min(p.x), b.1.min(p.y), b.2.max(p.x), b.3.max(p.y))
+        })
+    }
+
+    /// Computes the morphology features of the cluster
+    pub fn analyze(&self) -> ClusterFeatures {
+        let n = self.pixels.len().max(1) as f64;
+        let total: u64 = self.pixels.iter().map(|p| p.value as u64).sum();
+        let centroid = if total > 0 {
+            let weighted = |f: fn(&Pixel) -> u8| {
+                self.pixels
+                    .iter()
+                    .map(|p| f(p) as f64 * p.value as f64)
+                    .sum::<f64>()
+                    / total as f64
+            };
+            (weighted(|p| p.x), weighted(|p| p.y))
+        } else {
+            (
+                self.pixels.iter().map(|p| p.x as f64).sum::<f64>() / n,
+                self.pixels.iter().map(|p| p.y as f64).sum::<f64>() / n,
+            )
+        };
+
+        // principal axes of the pixel positions
+        let cx = self.pixels.iter().map(|p| p.x as f64).sum::<f64>() / n;
+        let cy = self.pixels.iter().map(|p| p.y as f64).sum::<f64>() / n;
+        let (mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0);
+        for p in &self.pixels {
+            let (dx, dy) = (p.x as f64 - cx, p.y as f64 - cy);
+            sxx += dx * dx / n;
+            syy += dy * dy / n;
+            sxy += dx * dy / n;
+        }
+        let mean = 0.5 * (sxx + syy);
+        let root = (0.25 * (sxx - syy).powi(2) + sxy * sxy).sqrt();
+        let (major, minor) = ((mean + root).sqrt(), (mean - root).max(0.0).sqrt());
+        let roundness = if major > 0.0 { minor / major } else { 1.0 };
+        let angle = 0.5 * (2.0 * sxy).atan2(sxx - syy);
+        let (dir_x, dir_y) = (angle.cos(), angle.sin());
+        let near_axis = self
+            .pixels
+            .iter()
+            .filter(|p| ((p.x as f64 - cx) * -dir_y + (p.y as f64 - cy) * dir_x).abs() <= 1.0)
+            .count();
+
+        ClusterFeatures {
+            pixels: self.pixels.len(),
+            total,
+            max: self.pixels.iter().map(|p| p.value).max().unwrap_or(0),
+            bounding_box: self.bounding_box(),
+            roundness,
+            linearity: near_axis as f64 / n,
+            border: self
+                .pixels
+                .iter()
+                .any(|p| p.x == 0 || p.y == 0 || p.x == u8::MAX || p.y == u8::MAX),
+            centroid,
+        }
+    }
+
+    /// Empty pixels between the bounding boxes along the axis with the larger gap,
+    /// 0 for touching or overlapping boxes
+    pub fn separation(&self, other: &Cluster) -> u8 {
+        let (a, b) = (self.bounding_box(), other.bounding_box());
+        let gap = |min_a: u8, max_a: u8, min_b: u8, max_b: u8| {
+            (min_b.saturating_sub(max_a))
+                .max(min_a.saturating_sub(max_b))
+                .saturating_sub(1)
+        };
+        gap(a.0, a.2, b.0, b.2).max(gap(a.1, a.3, b.1, b.3))
+    }
+
+    /// Appends the pixels of the other cluster, the neighbour indices are shifted accordingly
+    pub fn absorb(&mut self, other: Cluster) {
+        let offset = self.pixels.len();
+        for mut pixel in other.pixels {
+            for neighbor in pixel.neighbors.iter_mut().filter(|n| **n >= 0) {
+                *neighbor = i8::try_from(*neighbor as usize + offset).unwrap_or(-1);
+            }
+            self.pixels.push(pixel);
+        }
+        self.merged += other.merged + 1;
+    }
+}
+
+/// Merges clusters with bounding boxes separated by at most `distance` empty pixels
+/// (tracks split by dead pixels or charge sharing). Merged clusters take the place of
+/// the first of their parts.
+pub fn merge_clusters(clusters: Vec<Cluster>, distance: u8) -> Vec<Cluster> {
+    // union-find over the cluster indices
+    let mut parent: Vec<usize> = (0..clusters.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for i in 0..clusters.len() {
+        for j in i + 1..clusters.len() {
+            if clusters[i].separation(&clusters[j]) <= distance {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut merged: Vec<Option<Cluster>> = Vec::with_capacity(clusters.len());
+    for (i, cluster) in clusters.into_iter().enumerate() {
+        let r = root(&mut parent, i);
+        if r == i {
+            merged.push(Some(cluster));
+        } else {
+            merged.push(None);
+            if let Some(target) = merged[r].as_mut() {
+                target.absorb(cluster);
+            }
+        }
+    }
+    merged.into_iter().flatten().collect()
+}
+
+/// Label of the pixels without a hit in a label matrix
+pub const NO_LABEL: u32 = u32::MAX;
+
+/// Device computing the connected components of the hit pixels of a 256x256 frame
+pub trait Labeler: fmt::Debug + Send + Sync {
+    /// Label of every pixel: the smallest pixel index of its 8-connected component,
+    /// `NO_LABEL` for pixels without a hit
+    fn label(&self, frame: &[u16]) -> Result<Vec<u32>>;
+}
+
+/// Hardware running the clustering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClusterBackend {
+    #[default]
+    Cpu,
+    /// Component labeling on the GPU, pixels of a cluster in raster order
+    Gpu,
+}
+
+impl FromStr for ClusterBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "cpu" => Ok(ClusterBackend::Cpu),
+            "gpu" => Ok(ClusterBackend::Gpu),
+            _ => bail!("expected cpu or gpu"),
+        }
+    }
+}
+
+impl fmt::Display for ClusterBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClusterBackend::Cpu => write!(f, "cpu"),
+            ClusterBackend::Gpu => write!(f, "gpu"),
+        }
+    }
+}
+
+/// Label matrix of the clusters found by `Clusterer::search_frame`
+pub fn labels_from_clusters(clusters: &[Cluster], width: usize, len: usize) -> Vec<u32> {
+    let mut labels = vec![NO_LABEL; len];
+    for cluster in clusters {
+        let index = |p: &Pixel| p.y as usize * width + p.x as usize;
+        let label = cluster.pixels.iter().map(index).min().unwrap_or(0) as u32;
+        for pixel in &cluster.pixels {
+            labels[index(pixel)] = label;
+        }
+    }
+    labels
+}
+
+/// Clusters of a label matrix, ordered by their first pixel like `Clusterer::search_frame`,
+/// with the pixels in raster order
+pub fn clusters_from_labels(
+    frame: &[u16],
+    frame2: &[u16],
+    labels: &[u32],
+    width: usize,
+) -> Vec<Cluster> {
+    const DIRX: [i64; 8] = [-1, -1, 0, 1, 1, 1, 0, -1];
+    const DIRY: [i64; 8] = [0, 1, 1, 1, 0, -1, -1, -1];
+    let height = (frame.len() / width) as i64;
+    let mut clusters: Vec<Cluster> = Vec::new();
+    // cluster of each label and the position of each pixel in its cluster
+    let mut cluster_of = vec![usize::MAX; frame.len()];
+    let mut position = vec![0usize; frame.len()];
+    for (idx, &label) in labels.iter().enumerate() {
+        if label == NO_LABEL {
+            continue;
+        }
+        let root = label as usize;
+        if cluster_of[root] == usize::MAX {
+            cluster_of[root] = clusters.len();
+            clusters.push(Cluster::new());
+        }
+        let cluster = &mut clusters[cluster_of[root]];
+        position[idx] = cluster.pixels.len();
+        cluster.add_pixel(Pixel::new(
+            (idx % width) as u8,
+            (idx / width) as u8,
+            frame[idx],
+            frame2[idx],
+        ));
+    }
+    for cluster in &mut clusters {
+        for pixel in &mut cluster.pixels {
+            let (x, y) = (pixel.x as i64, pixel.y as i64);
+            for dir in 0..8 {
+                let (dx, dy) = (x + DIRX[dir], y + DIRY[dir]);
+                if dx < 0 || dy < 0 || dx >= width as i64 || dy >= height {
+                    continue;
+                }
+                let didx = (dy * width as i64 + dx) as usize;
+                if labels[didx] != NO_LABEL {
+                    pixel.add_neighbor(dir, i8::try_from(position[didx]).unwrap_or(-1));
+                }
+            }
+        }
+    }
+    clusters
 }
 
 #[allow(dead_code)]
@@ -59,6 +328,12 @@ pub struct Clusterer {
     pub vec: Vec<Cluster>,
 }
 
+impl Default for Clusterer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[allow(dead_code)]
 impl Clusterer {
     pub fn new() -> Clusterer {
@@ -134,3 +409,102 @@ impl Clusterer {
         clusters
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cluster(pixels: &[(u8, u8)]) -> Cluster {
+        Cluster {
+            pixels: pixels
+                .iter()
+                .map(|&(x, y)| Pixel::new(x, y, 10, 1))
+                .collect(),
+            merged: 0,
+        }
+    }
+
+    #[test]
+    fn test_merge_clusters() {
+        // track split by a dead pixel at (12, 10), a far away dot
+        let left = cluster(&[(10, 10), (11, 10)]);
+        let right = cluster(&[(13, 10), (14, 11)]);
+        let dot = cluster(&[(100, 100)]);
+        assert_eq!(left.separation(&right), 1);
+        assert_eq!(left.separation(&left), 0);
+
+        let clusters = vec![left.clone(), dot.clone(), right.clone()];
+        assert_eq!(merge_clusters(clusters.clone(), 0).len(), 3);
+        let merged = merge_clusters(clusters, 1);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].pixels.len(), 4);
+        assert_eq!(merged[0].merged, 1);
+        assert_eq!(merged[0].bounding_box(), (10, 10, 14, 11));
+        assert_eq!(merged[1].merged, 0);
+
+        // chains merge transitively
+        let far_right = cluster(&[(16, 11)]);
+        let merged = merge_clusters(vec![far_right, left, right], 1);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].merged, 2);
+    }
+
+    #[test]
+    fn test_analyze() {
+        let mut track = cluster(&[(10, 10), (11, 11), (12, 12), (13, 13), (14, 14)]);
+        track.pixels[4].value = 50;
+        let features = track.analyze();
+        assert_eq!(features.pixels, 5);
+        assert_eq!((features.total, features.max), (90, 50));
+        assert_eq!(features.bounding_box, (10, 10, 14, 14));
+        assert!(features.roundness < 1e-6);
+        assert_eq!(features.linearity, 1.0);
+        assert!(!features.border);
+        // (10 * (10 + 11 + 12 + 13) + 50 * 14) / 90
+        assert!((features.centroid.0 - 1160.0 / 90.0).abs() < 1e-9);
+        assert_eq!(features.centroid.0, features.centroid.1);
+
+        let blob = cluster(&[(0, 0), (1, 0), (0, 1), (1, 1)]).analyze();
+        assert!((blob.roundness - 1.0).abs() < 1e-9);
+        assert!(blob.border);
+        assert_eq!(blob.centroid, (0.5, 0.5));
+    }
+
+    #[test]
+    fn test_clusters_from_labels() {
+        let mut frame = vec![0u16; 256 * 256];
+        // L shape found from its top pixel, a diagonal pair and a dot on the edge
+        for (x, y, v) in [
+            (5, 1, 1),
+            (5, 2, 2),
+            (6, 2, 3),
+            (20, 3, 4),
+            (21, 4, 5),
+            (255, 255, 6),
+        ] {
+            frame[y * 256 + x] = v;
+        }
+        let event = vec![7u16; 256 * 256];
+        let clusters = Clusterer::new().search_frame(&frame, &event, 256, 256);
+        let labels = labels_from_clusters(&clusters, 256, frame.len());
+        assert_eq!(labels[2 * 256 + 6], 256 + 5);
+        let rebuilt = clusters_from_labels(&frame, &event, &labels, 256);
+        assert_eq!(rebuilt.len(), clusters.len());
+        for (a, b) in clusters.iter().zip(&rebuilt) {
+            let key = |c: &Cluster| {
+                let mut pixels: Vec<(u8, u8, u16)> =
+                    c.pixels.iter().map(|p| (p.y, p.x, p.value)).collect();
+                pixels.sort();
+                pixels
+            };
+            assert_eq!(key(a), key(b));
+        }
+        let values: Vec<u16> = rebuilt[0].pixels.iter().map(|p| p.value).collect();
+        assert_eq!(values, [1, 2, 3]);
+        assert_eq!(rebuilt[0].pixels[1].neighbors[4], 2);
+        assert_eq!(
+            "gpu".parse::<ClusterBackend>().unwrap(),
+            ClusterBackend::Gpu
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use crate::classification::ClassThresholds;
+use crate::clustering::Cluster;
+use crate::data_processor::{DataProcessor, Frame};
+use crate::event_display;
+use crate::processor;
+use crate::tpx3lut::MATRIX_SIZE;
+use crate::utils;
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Loads a 256x256 pixel matrix from an ASCII (whitespace separated rows) or NumPy .npy file.
+/// Fractional values are rounded to the nearest count.
+pub fn load_matrix(path: &Path) -> Result<Vec<u16>> {
+    let values = match path.extension().and_then(|e| e.to_str()) {
+        Some("npy") => utils::load_npy_matrix(path)?,
+        _ => utils::load_ascii_matrix::<f64>(path)?,
+    };
+    if values.len() != MATRIX_SIZE {
+        bail!(
+            "{}: expected {} values (256x256), found {}",
+            path.display(),
+            MATRIX_SIZE,
+            values.len()
+        );
+    }
+    values
+        .iter()
+        .enumerate()
+        .map(|(idx, &value)| {
+            let count = value.round();
+            if !(0.0..=u16::MAX as f64).contains(&count) {
+                bail!(
+                    "{}: value {} of pixel ({}, {}) out of range 0-{}",
+                    path.display(),
+                    value,
+                    idx % 256,
+                    idx / 256,
+                    u16::MAX
+                );
+            }
+            Ok(count as u16)
+        })
+        .collect()
+}
+
+/// Clusters the matrices with the clustering of the flight data processing
+pub fn clusterize(itot: Vec<u16>, event: Vec<u16>, merge_distance: Option<u8>) -> Frame {
+    let mut frame = Frame::from_planes(itot, event, 0.0);
+    let mut data_processor = DataProcessor::new();
+    data_processor.merge_distance = merge_distance;
+    data_processor.clusterize_frame(&mut frame);
+    frame
+}
+
+/// Writes the per-cluster features: size, energy, centroid, peak value, morphology label,
+/// merged cluster count and saturated pixel fraction
+pub fn save_features(path: &Path, clusters: &[Cluster], kev_per_count: f64) -> Result<()> {
+    let mut writer = BufWriter::new(
+        fs::File::create(path)
+            .with_context(|| format!("cannot write cluster features {}", path.display()))?,
+    );
+    write_features(&mut writer, clusters, kev_per_count)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Header of the morphology feature table of `write_morphology`
+pub const MORPHOLOGY_HEADER: &str = "frame,cluster,pixels,total,max,x_min,y_min,x_max,y_max,centroid_x,centroid_y,roundness,linearity,border,class";
+
+/// CSV rows of the morphology features (`Cluster::analyze`) of the clusters of a frame
+/// with their class
+pub fn write_morphology<W: Write>(
+    writer: &mut W,
+    frame: usize,
+    clusters: &[Cluster],
+    thresholds: &ClassThresholds,
+    kev_per_count: f64,
+    lend: &str,
+) -> Result<()> {
+    for (i, cluster) in clusters.iter().enumerate() {
+        let f = cluster.analyze();
+        let (x_min, y_min, x_max, y_max) = f.bounding_box;
+        write!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{:.3},{:.3},{:.3},{:.3},{},{}{}",
+            frame,
+            i + 1,
+            f.pixels,
+            f.total,
+            f.max,
+            x_min,
+            y_min,
+            x_max,
+            y_max,
+            f.centroid.0,
+            f.centroid.1,
+            f.roundness,
+            f.linearity,
+            u8::from(f.border),
+            thresholds.classify(&f, kev_per_count),
+            lend
+        )?;
+    }
+    Ok(())
+}
+
+/// Tab separated feature table of the clusters, see `save_features`
+pub fn write_features<W: Write>(
+    writer: &mut W,
+    clusters: &[Cluster],
+    kev_per_count: f64,
+) -> Result<()> {
+    writeln!(
+        writer,
+        "Cluster\tPixels\tEnergy[keV]\tX\tY\tMax Value\tLabel\tMerged\tSaturation"
+    )?;
+    for (i, cluster) in clusters.iter().enumerate() {
+        let n = cluster.pixels.len() as f64;
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{:.2}\t{:.2}\t{}\t{}\t{}\t{:.3}",
+            i + 1,
+            cluster.pixels.len(),
+            event_display::cluster_energy(cluster, kev_per_count),
+            cluster.pixels.iter().map(|p| p.x as f64).sum::<f64>() / n,
+            cluster.pixels.iter().map(|p| p.y as f64).sum::<f64>() / n,
+            cluster.pixels.iter().map(|p| p.value).max().unwrap_or(0),
+            event_display::classify(cluster, kev_per_count),
+            cluster.merged,
+            cluster.saturation()
+        )?;
+    }
+    Ok(())
+}
+
+/// Clusters the matrix file (with the optional second value matrix) and writes
+/// `<stem>.clog` and `<stem>.clusters.tsv` to out_dir. Returns the clog path and cluster count.
+pub fn clusterize_file(
+    matrix_file: &Path,
+    event_file: Option<&Path>,
+    out_dir: &Path,
+    kev_per_count: f64,
+    acq_time: f64,
+    merge_distance: Option<u8>,
+) -> Result<(PathBuf, usize)> {
+    let itot = load_matrix(matrix_file)?;
+    let event = match event_file {
+        Some(path) => load_matrix(path)?,
+        None => vec![0; MATRIX_SIZE],
+    };
+    let frame = clusterize(itot, event, merge_distance);
+
+    let stem = matrix_file
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let clog_path = out_dir.join(format!("{}.clog", stem));
+    let mut writer = BufWriter::new(
+        fs::File::create(&clog_path)
+            .with_context(|| format!("cannot write {}", clog_path.display()))?,
+    );
+    processor::write_clog_frame(
+        &mut writer,
+        1,
+        frame.timestamp,
+        acq_time,
+        &frame.clusters,
+        None,
+        "\n",
+    )?;
+    writer.flush()?;
+    save_features(
+        &out_dir.join(format!("{}.clusters.tsv", stem)),
+        &frame.clusters,
+        kev_per_count,
+    )?;
+    Ok((clog_path, frame.clusters.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clusterize_file() {
+        let dir = std::env::temp_dir().join(format!("oneweb-clusterize-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut itot = vec![0u16; MATRIX_SIZE];
+        itot[256 + 1] = 10;
+        itot[256 + 2] = 20;
+        itot[100 * 256 + 200] = 7;
+        let matrix = dir.join("calib.txt");
+        utils::save_ascii_matrix(&matrix, &itot, 256).unwrap();
+
+        let (clog, clusters) = clusterize_file(&matrix, None, &dir, 2.0, 1.5, None).unwrap();
+        assert_eq!(clusters, 2);
+        let content = fs::read_to_string(clog).unwrap();
+        assert_eq!(
+            content,
+            "Frame 1 (0, 1.5 s)\n[1, 1, 10, 0] [2, 1, 20, 0] \n[200, 100, 7, 0] \n\n"
+        );
+        let features = fs::read_to_string(dir.join("calib.clusters.tsv")).unwrap();
+        let lines: Vec<&str> = features.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("1\t2\t60\t1.50\t1.00\t20\t"));
+
+        fs::write(&matrix, "1 2 3\n").unwrap();
+        assert!(load_matrix(&matrix).is_err());
+        fs::write(&matrix, "-1 ".repeat(MATRIX_SIZE)).unwrap();
+        assert!(load_matrix(&matrix).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
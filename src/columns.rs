@@ -0,0 +1,1104 @@
+use crate::binning::PositionBinning;
+use crate::data_processor::Frame;
+use crate::direction::{self, Matrix3, PointingFrame};
+use crate::dose_equivalent::{self, QualityFactor};
+use crate::dosimetry;
+use crate::gps_processor::GpsData;
+use crate::info_processor::MeasInfoData;
+use crate::mode::PayloadMode;
+use crate::orbit::{self, Geodetic, ReferenceFrame};
+use crate::orientation::Orientation;
+use crate::tpx3lut::MATRIX_SIZE;
+use anyhow::{Result, bail};
+use std::fmt;
+use std::str::FromStr;
+
+/// Everything a metadata column can be derived from
+pub struct MetaRow<'a> {
+    /// Index of the frame in the output file (1-based)
+    pub frame_index: usize,
+    /// Number of the frame in the data file (1-based), unique over the whole run
+    pub frame_number: usize,
+    pub frame: &'a Frame,
+    pub info: &'a MeasInfoData,
+    pub gps: &'a GpsData,
+    /// No GPS record within the max GPS staleness of the frame time
+    pub gps_missing: bool,
+    /// Run without the measurement info file, the info is a placeholder
+    pub info_missing: bool,
+    pub acq_time: f64,
+    pub kev_per_count: f64,
+    /// Quality factor curve of the dose equivalent columns
+    pub quality_factor: QualityFactor,
+    /// Rotation of detector to spacecraft body vectors
+    pub mounting: Matrix3,
+    /// Matched GPS record taken during an attitude maneuver
+    pub maneuver: bool,
+    /// The attitude of the matched GPS record is not used (maneuver excluded or position
+    /// propagated from a TLE)
+    pub attitude_excluded: bool,
+    /// Distance in m between the GPS position and the TLE propagation at the record time
+    pub tle_residual: Option<f64>,
+    /// Payload mode inferred from the measurement cadence, None when not detected
+    pub mode: Option<PayloadMode>,
+    /// Orientation of the pixel coordinates of the outputs
+    pub orientation: Orientation,
+    /// Rounding of the geodetic position and L-shell columns
+    pub binning: PositionBinning,
+    /// J2000 position interpolated to the frame time between the GPS records around it,
+    /// None outside the track and in its gaps
+    pub interpolated: Option<[f64; 3]>,
+    /// Position of the GPS columns
+    pub gps_columns: GpsColumns,
+    /// J2000 velocity at the time of the matched GPS record from the GPS track, None without
+    /// the track and in its gaps
+    pub velocity: Option<[f64; 3]>,
+    /// Frame of the boresight and Euler angle columns
+    pub pointing_frame: PointingFrame,
+}
+
+impl MetaRow<'_> {
+    fn ecef(&self) -> [f64; 3] {
+        self.position(ReferenceFrame::Itrf)
+    }
+
+    fn position(&self, frame: ReferenceFrame) -> [f64; 3] {
+        match (self.gps_columns, self.interpolated) {
+            (GpsColumns::Interpolated, Some(pos)) => frame.transform(pos, self.frame.timestamp),
+            _ => frame.transform(self.nearest(), self.gps.timestamp),
+        }
+    }
+
+    /// J2000 position of the matched GPS record
+    fn nearest(&self) -> [f64; 3] {
+        [self.gps.j2000_x, self.gps.j2000_y, self.gps.j2000_z]
+    }
+
+    /// Body frame vector in the pointing frame at the time of the matched GPS record, None
+    /// without a usable attitude quaternion or, in LVLH, the velocity
+    fn pointing(&self, body: [f64; 3]) -> Option<[f64; 3]> {
+        let q = self.gps.quaternion_normalized()?;
+        self.pointing_frame.rotate(
+            direction::body_to_j2000(q, body),
+            self.gps.timestamp,
+            self.nearest(),
+            self.velocity,
+        )
+    }
+
+    fn boresight(&self) -> Option<[f64; 3]> {
+        self.pointing(direction::boresight(&self.mounting))
+    }
+
+    /// Yaw, pitch and roll of the spacecraft body in the pointing frame
+    fn euler_angles(&self) -> Option<[f64; 3]> {
+        let [x, y, z] =
+            [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]].map(|axis| self.pointing(axis));
+        let axes = [x?, y?, z?];
+        Some(direction::euler_angles(
+            &[0, 1, 2].map(|i| axes.map(|axis| axis[i])),
+        ))
+    }
+
+    /// Angle in degrees between the sensor normal and the J2000 velocity
+    fn ram_angle(&self) -> Option<f64> {
+        let q = self.gps.quaternion_normalized()?;
+        let normal = direction::body_to_j2000(q, direction::boresight(&self.mounting));
+        direction::angle_between(normal, self.velocity?)
+    }
+
+    fn geodetic(&self) -> Geodetic {
+        orbit::ecef_to_geodetic(self.ecef())
+    }
+
+    /// Mean absorbed dose rate of the sensor in Gy/s
+    fn dose_rate(&self) -> f64 {
+        dosimetry::frame_dose_rate(self.frame, self.kev_per_count, self.acq_time)
+    }
+
+    /// Dose equivalent rate of the sensor in Sv/s
+    fn dose_equivalent_rate(&self) -> f64 {
+        if self.acq_time <= 0.0 {
+            return 0.0;
+        }
+        let (_, dose_equivalent) = dose_equivalent::frame_dose(
+            &self.frame.clusters,
+            self.kev_per_count,
+            self.quality_factor,
+        );
+        dose_equivalent / self.acq_time
+    }
+}
+
+/// Column of the metadata (.info) output
+#[derive(Debug)]
+pub struct Column {
+    /// Name used in the configuration
+    pub name: &'static str,
+    /// Header written to the output
+    pub header: &'static str,
+    pub description: &'static str,
+    /// Derived from the matched GPS record, empty when the record is missing
+    pub gps: bool,
+    pub value: fn(&MetaRow) -> String,
+}
+
+impl Column {
+    /// Value of the column for the row
+    pub fn format(&self, row: &MetaRow) -> String {
+        if self.gps && row.gps_missing
+            || row.attitude_excluded && ATTITUDE_COLUMNS.contains(&self.name)
+            || row.info_missing && INFO_COLUMNS.contains(&self.name)
+        {
+            String::new()
+        } else {
+            (self.value)(row)
+        }
+    }
+}
+
+/// Registry of all available columns
+pub static COLUMNS: &[Column] = &[
+    Column {
+        name: "frame_index",
+        header: "Frame Index",
+        description: "index of the frame in the file (1-based)",
+        gps: false,
+        value: |r| r.frame_index.to_string(),
+    },
+    Column {
+        name: "global_frame_index",
+        header: "Global Frame Index",
+        description: "number of the frame in the data file (1-based), continues across files",
+        gps: false,
+        value: |r| r.frame_number.to_string(),
+    },
+    Column {
+        name: "timestamp",
+        header: "Timestamp",
+        description: "measurement info time (unix s)",
+        gps: false,
+        value: |r| r.info.timestamp.to_string(),
+    },
+    Column {
+        name: "frame_timestamp",
+        header: "Frame Timestamp",
+        description: "frame time (unix s), see --frame-time-source",
+        gps: false,
+        value: |r| r.frame.timestamp.to_string(),
+    },
+    Column {
+        name: "temp",
+        header: "Temp",
+        description: "detector temperature",
+        gps: false,
+        value: |r| r.info.temp.to_string(),
+    },
+    Column {
+        name: "gps_x",
+        header: "GPS J2000 X",
+        description: "J2000 position X (m)",
+        gps: true,
+        value: |r| r.position(ReferenceFrame::J2000)[0].to_string(),
+    },
+    Column {
+        name: "gps_y",
+        header: "GPS J2000 Y",
+        description: "J2000 position Y (m)",
+        gps: true,
+        value: |r| r.position(ReferenceFrame::J2000)[1].to_string(),
+    },
+    Column {
+        name: "gps_z",
+        header: "GPS J2000 Z",
+        description: "J2000 position Z (m)",
+        gps: true,
+        value: |r| r.position(ReferenceFrame::J2000)[2].to_string(),
+    },
+    Column {
+        name: "interp_x",
+        header: "Interpolated J2000 X",
+        description: "J2000 position X interpolated to the frame time between the GPS records around it (m)",
+        gps: true,
+        value: |r| interpolated(r, |pos| format!("{:.3}", pos[0])),
+    },
+    Column {
+        name: "interp_y",
+        header: "Interpolated J2000 Y",
+        description: "J2000 position Y interpolated to the frame time between the GPS records around it (m)",
+        gps: true,
+        value: |r| interpolated(r, |pos| format!("{:.3}", pos[1])),
+    },
+    Column {
+        name: "interp_z",
+        header: "Interpolated J2000 Z",
+        description: "J2000 position Z interpolated to the frame time between the GPS records around it (m)",
+        gps: true,
+        value: |r| interpolated(r, |pos| format!("{:.3}", pos[2])),
+    },
+    Column {
+        name: "interp_offset",
+        header: "Interpolation Offset",
+        description: "distance between the matched GPS record position and the interpolated position (m)",
+        gps: true,
+        value: |r| {
+            let nearest = r.nearest();
+            interpolated(r, |pos| {
+                let d2: f64 = (0..3).map(|i| (pos[i] - nearest[i]).powi(2)).sum();
+                format!("{:.3}", d2.sqrt())
+            })
+        },
+    },
+    Column {
+        name: "position_source",
+        header: "Position Source",
+        description: "gps (GPS record), tle (propagated from --tle-file for a missing record) or interpolated (outlier repaired by --deglitch-gps)",
+        gps: true,
+        value: |r| {
+            String::from(if r.gps.propagated {
+                "tle"
+            } else if r.gps.repaired {
+                "interpolated"
+            } else {
+                "gps"
+            })
+        },
+    },
+    Column {
+        name: "tle_residual",
+        header: "TLE Residual",
+        description: "distance between the GPS position and the --tle-file propagation (km)",
+        gps: true,
+        value: |r| {
+            r.tle_residual
+                .map(|residual| format!("{:.3}", residual / 1e3))
+                .unwrap_or_default()
+        },
+    },
+    Column {
+        name: "teme_x",
+        header: "GPS TEME X",
+        description: "TEME position X (m)",
+        gps: true,
+        value: |r| format!("{:.3}", r.position(ReferenceFrame::Teme)[0]),
+    },
+    Column {
+        name: "teme_y",
+        header: "GPS TEME Y",
+        description: "TEME position Y (m)",
+        gps: true,
+        value: |r| format!("{:.3}", r.position(ReferenceFrame::Teme)[1]),
+    },
+    Column {
+        name: "teme_z",
+        header: "GPS TEME Z",
+        description: "TEME position Z (m)",
+        gps: true,
+        value: |r| format!("{:.3}", r.position(ReferenceFrame::Teme)[2]),
+    },
+    Column {
+        name: "itrf_x",
+        header: "GPS ITRF X",
+        description: "ITRF (Earth fixed) position X (m)",
+        gps: true,
+        value: |r| format!("{:.3}", r.position(ReferenceFrame::Itrf)[0]),
+    },
+    Column {
+        name: "itrf_y",
+        header: "GPS ITRF Y",
+        description: "ITRF (Earth fixed) position Y (m)",
+        gps: true,
+        value: |r| format!("{:.3}", r.position(ReferenceFrame::Itrf)[1]),
+    },
+    Column {
+        name: "itrf_z",
+        header: "GPS ITRF Z",
+        description: "ITRF (Earth fixed) position Z (m)",
+        gps: true,
+        value: |r| format!("{:.3}", r.position(ReferenceFrame::Itrf)[2]),
+    },
+    Column {
+        name: "q_scalar",
+        header: "GPS Q Scalar",
+        description: "attitude quaternion scalar part",
+        gps: true,
+        value: |r| r.gps.q_est_prop_bj_scalar.to_string(),
+    },
+    Column {
+        name: "q_vector_1",
+        header: "GPS Q Vector 1",
+        description: "attitude quaternion vector part 1",
+        gps: true,
+        value: |r| r.gps.q_est_prop_bj_vector_1.to_string(),
+    },
+    Column {
+        name: "q_vector_2",
+        header: "GPS Q Vector 2",
+        description: "attitude quaternion vector part 2",
+        gps: true,
+        value: |r| r.gps.q_est_prop_bj_vector_2.to_string(),
+    },
+    Column {
+        name: "q_vector_3",
+        header: "GPS Q Vector 3",
+        description: "attitude quaternion vector part 3",
+        gps: true,
+        value: |r| r.gps.q_est_prop_bj_vector_3.to_string(),
+    },
+    Column {
+        name: "boresight_x",
+        header: "Boresight X",
+        description: "X of the sensor normal (boresight) unit vector in the --pointing-frame",
+        gps: true,
+        value: |r| {
+            r.boresight()
+                .map(|v| format!("{:.6}", v[0]))
+                .unwrap_or_default()
+        },
+    },
+    Column {
+        name: "boresight_y",
+        header: "Boresight Y",
+        description: "Y of the sensor normal (boresight) unit vector in the --pointing-frame",
+        gps: true,
+        value: |r| {
+            r.boresight()
+                .map(|v| format!("{:.6}", v[1]))
+                .unwrap_or_default()
+        },
+    },
+    Column {
+        name: "boresight_z",
+        header: "Boresight Z",
+        description: "Z of the sensor normal (boresight) unit vector in the --pointing-frame",
+        gps: true,
+        value: |r| {
+            r.boresight()
+                .map(|v| format!("{:.6}", v[2]))
+                .unwrap_or_default()
+        },
+    },
+    Column {
+        name: "boresight_lon",
+        header: "Boresight Lon",
+        description: "longitude-like angle of the boresight in the --pointing-frame, right ascension in J2000 (deg)",
+        gps: true,
+        value: |r| {
+            r.boresight()
+                .map(|v| format!("{:.3}", direction::angles(v)[0]))
+                .unwrap_or_default()
+        },
+    },
+    Column {
+        name: "boresight_lat",
+        header: "Boresight Lat",
+        description: "latitude-like angle of the boresight in the --pointing-frame, declination in J2000 (deg)",
+        gps: true,
+        value: |r| {
+            r.boresight()
+                .map(|v| format!("{:.3}", direction::angles(v)[1]))
+                .unwrap_or_default()
+        },
+    },
+    Column {
+        name: "yaw",
+        header: "Yaw",
+        description: "yaw of the spacecraft body (z-y-x Euler angles) relative to the --pointing-frame (deg)",
+        gps: true,
+        value: |r| {
+            r.euler_angles()
+                .map(|a| format!("{:.3}", a[0]))
+                .unwrap_or_default()
+        },
+    },
+    Column {
+        name: "pitch",
+        header: "Pitch",
+        description: "pitch of the spacecraft body (z-y-x Euler angles) relative to the --pointing-frame (deg)",
+        gps: true,
+        value: |r| {
+            r.euler_angles()
+                .map(|a| format!("{:.3}", a[1]))
+                .unwrap_or_default()
+        },
+    },
+    Column {
+        name: "roll",
+        header: "Roll",
+        description: "roll of the spacecraft body (z-y-x Euler angles) relative to the --pointing-frame (deg)",
+        gps: true,
+        value: |r| {
+            r.euler_angles()
+                .map(|a| format!("{:.3}", a[2]))
+                .unwrap_or_default()
+        },
+    },
+    Column {
+        name: "ram_angle",
+        header: "Ram Angle",
+        description: "angle between the sensor normal and the J2000 velocity direction (deg)",
+        gps: true,
+        value: |r| {
+            r.ram_angle()
+                .map(|a| format!("{:.3}", a))
+                .unwrap_or_default()
+        },
+    },
+    Column {
+        name: "maneuver",
+        header: "Maneuver",
+        description: "1 when the matched GPS record is in an attitude maneuver (--max-attitude-jump)",
+        gps: true,
+        value: |r| u8::from(r.maneuver).to_string(),
+    },
+    Column {
+        name: "acq_time",
+        header: "acq_time",
+        description: "estimated acquisition time (s)",
+        gps: false,
+        value: |r| r.acq_time.to_string(),
+    },
+    Column {
+        name: "pixels_short",
+        header: "pixels short",
+        description: "pixel count of the short acquisition",
+        gps: false,
+        value: |r| r.info.pixel_short.to_string(),
+    },
+    Column {
+        name: "pixels_long",
+        header: "pixels long",
+        description: "pixel count of the long acquisition",
+        gps: false,
+        value: |r| r.info.pixel_long.to_string(),
+    },
+    Column {
+        name: "gps_timestamp",
+        header: "GPS Timestamp",
+        description: "time of the matched GPS record (unix s)",
+        gps: true,
+        value: |r| r.gps.timestamp.to_string(),
+    },
+    Column {
+        name: "lat",
+        header: "Latitude",
+        description: "geodetic latitude of the subsatellite point (deg)",
+        gps: true,
+        value: |r| PositionBinning::format(r.binning.lat, r.geodetic().latitude, 4),
+    },
+    Column {
+        name: "lon",
+        header: "Longitude",
+        description: "longitude of the subsatellite point (deg)",
+        gps: true,
+        value: |r| PositionBinning::format(r.binning.lon, r.geodetic().longitude, 4),
+    },
+    Column {
+        name: "alt",
+        header: "Altitude",
+        description: "altitude above the WGS84 ellipsoid (km)",
+        gps: true,
+        value: |r| PositionBinning::format(r.binning.alt, r.geodetic().altitude / 1000.0, 3),
+    },
+    Column {
+        name: "l_shell",
+        header: "L-shell",
+        description: "McIlwain L of the centered dipole field (Earth radii)",
+        gps: true,
+        value: |r| PositionBinning::format(r.binning.l_shell, orbit::dipole_l_shell(r.ecef()), 3),
+    },
+    Column {
+        name: "region",
+        header: "Region",
+        description: "radiation environment: saa, polar (outer belt horns) or low_latitude",
+        gps: true,
+        value: |r| {
+            orbit::radiation_region(&r.geodetic(), orbit::dipole_l_shell(r.ecef())).to_string()
+        },
+    },
+    Column {
+        name: "dose_rate",
+        header: "Dose Rate",
+        description: "mean absorbed dose rate of the sensor (Gy/s)",
+        gps: false,
+        value: |r| format!("{:e}", r.dose_rate()),
+    },
+    Column {
+        name: "dose_equivalent_rate",
+        header: "Dose Equivalent Rate",
+        description: "dose equivalent rate of the sensor from the cluster LET and the quality factor curve (Sv/s)",
+        gps: false,
+        value: |r| format!("{:e}", r.dose_equivalent_rate()),
+    },
+    Column {
+        name: "quality_factor",
+        header: "Quality Factor",
+        description: "mean quality factor of the frame clusters (dose equivalent over absorbed dose)",
+        gps: false,
+        value: |r| {
+            let (dose, dose_equivalent) =
+                dose_equivalent::frame_dose(&r.frame.clusters, r.kev_per_count, r.quality_factor);
+            if dose > 0.0 {
+                format!("{:.3}", dose_equivalent / dose)
+            } else {
+                String::new()
+            }
+        },
+    },
+    Column {
+        name: "hit_pixels",
+        header: "Hit Pixels",
+        description: "number of hit pixels in the frame",
+        gps: false,
+        value: |r| {
+            r.frame
+                .itot()
+                .iter()
+                .filter(|&&v| v != 0)
+                .count()
+                .to_string()
+        },
+    },
+    Column {
+        name: "clusters",
+        header: "Clusters",
+        description: "number of clusters in the frame",
+        gps: false,
+        value: |r| r.frame.clusters.len().to_string(),
+    },
+    Column {
+        name: "merged_clusters",
+        header: "Merged Clusters",
+        description: "clusters merged into another by the merge distance",
+        gps: false,
+        value: |r| {
+            let merged: usize = r.frame.clusters.iter().map(|c| c.merged).sum();
+            merged.to_string()
+        },
+    },
+    Column {
+        name: "total_itot",
+        header: "Total iToT",
+        description: "sum of the iToT values of the hit pixels",
+        gps: false,
+        value: |r| {
+            let total: u64 = r.frame.itot().iter().map(|&v| v as u64).sum();
+            total.to_string()
+        },
+    },
+    Column {
+        name: "total_tot",
+        header: "Total ToT",
+        description: "sum of the second (ToT) values of the hit pixels",
+        gps: false,
+        value: |r| {
+            let total: u64 = r.frame.event().iter().map(|&v| v as u64).sum();
+            total.to_string()
+        },
+    },
+    Column {
+        name: "max_cluster_size",
+        header: "Max Cluster Size",
+        description: "pixels of the largest cluster in the frame, 0 without clusters",
+        gps: false,
+        value: |r| {
+            let max = r.frame.clusters.iter().map(|c| c.pixels.len()).max();
+            max.unwrap_or(0).to_string()
+        },
+    },
+    Column {
+        name: "occupancy",
+        header: "Occupancy",
+        description: "fraction of the matrix pixels hit",
+        gps: false,
+        value: |r| {
+            let hit = r.frame.itot().iter().filter(|&&v| v != 0).count();
+            format!("{:.6}", hit as f64 / MATRIX_SIZE as f64)
+        },
+    },
+    Column {
+        name: "noise_threshold",
+        header: "Noise Threshold",
+        description: "mean iToT threshold of the noise model at the frame temperature, empty without a model",
+        gps: false,
+        value: |r| {
+            r.frame
+                .noise_threshold
+                .map(|threshold| format!("{:.3}", threshold))
+                .unwrap_or_default()
+        },
+    },
+    Column {
+        name: "noise_pixels",
+        header: "Noise Pixels",
+        description: "pixels at or below the noise threshold removed before the clustering",
+        gps: false,
+        value: |r| r.frame.noise_pixels.to_string(),
+    },
+    Column {
+        name: "adaptive_threshold",
+        header: "Adaptive Threshold",
+        description: "iToT threshold derived from the frame statistics, empty when not applied",
+        gps: false,
+        value: |r| {
+            r.frame
+                .adaptive_threshold
+                .map(|threshold| format!("{:.1}", threshold))
+                .unwrap_or_default()
+        },
+    },
+    Column {
+        name: "adaptive_pixels",
+        header: "Adaptive Pixels",
+        description: "pixels below the adaptive threshold removed before the clustering",
+        gps: false,
+        value: |r| r.frame.adaptive_pixels.to_string(),
+    },
+    Column {
+        name: "masked_pixels",
+        header: "Masked Pixels",
+        description: "hit pixels of the --mask-file mask removed by the decoder",
+        gps: false,
+        value: |r| r.frame.masked.count().to_string(),
+    },
+    Column {
+        name: "truncated",
+        header: "Truncated",
+        description: "1 when the end of readout was lost and the next start of readout closed the frame",
+        gps: false,
+        value: |r| u8::from(r.frame.truncated).to_string(),
+    },
+    Column {
+        name: "payload_bytes",
+        header: "Payload Bytes",
+        description: "bytes of the assembled frame payload (after the decompression)",
+        gps: false,
+        value: |r| r.frame.bytes.payload.to_string(),
+    },
+    Column {
+        name: "packet_bytes",
+        header: "Packet Bytes",
+        description: "payload bytes decoded as pixel packets",
+        gps: false,
+        value: |r| r.frame.bytes.packets.to_string(),
+    },
+    Column {
+        name: "header_bytes",
+        header: "Header Bytes",
+        description: "payload bytes of the frame headers and the end of readout",
+        gps: false,
+        value: |r| r.frame.bytes.headers.to_string(),
+    },
+    Column {
+        name: "discarded_bytes",
+        header: "Discarded Bytes",
+        description: "payload bytes skipped as unexpected data or incomplete packets",
+        gps: false,
+        value: |r| r.frame.bytes.discarded.to_string(),
+    },
+    Column {
+        name: "decode_efficiency",
+        header: "Decode Efficiency",
+        description: "percentage of the payload bytes decoded as pixel packets",
+        gps: false,
+        value: |r| format!("{:.2}", r.frame.bytes.efficiency()),
+    },
+    Column {
+        name: "invalid_pixels",
+        header: "Invalid Pixels",
+        description: "number of pixels with a code outside the ToT/iToT lookup tables",
+        gps: false,
+        value: |r| r.frame.invalid().count().to_string(),
+    },
+    Column {
+        name: "invalid_pixel_list",
+        header: "Invalid Pixel List",
+        description: "x:y of the pixels with a code outside the lookup tables, separated by ;",
+        gps: false,
+        value: |r| {
+            let pixels: Vec<String> = r
+                .frame
+                .invalid()
+                .indices()
+                .map(|idx| {
+                    let (x, y) = r.orientation.apply((idx % 256) as u8, (idx / 256) as u8);
+                    format!("{}:{}", x, y)
+                })
+                .collect();
+            pixels.join(";")
+        },
+    },
+    Column {
+        name: "saturated_pixels",
+        header: "Saturated Pixels",
+        description: "number of pixels with the iToT code at the lookup table maximum (saturated counter)",
+        gps: false,
+        value: |r| r.frame.saturated().count().to_string(),
+    },
+    Column {
+        name: "pixels_saved",
+        header: "pixels saved",
+        description: "pixel count saved on board",
+        gps: false,
+        value: |r| r.info.pixel_saved.to_string(),
+    },
+    Column {
+        name: "pixels_not_saved",
+        header: "pixels not saved",
+        description: "pixel count not saved on board",
+        gps: false,
+        value: |r| r.info.pixel_not_saved.to_string(),
+    },
+    Column {
+        name: "mode",
+        header: "Mode",
+        description: "payload mode from the measurement cadence: nominal, high_res or low_rate",
+        gps: false,
+        value: |r| r.mode.map(|mode| mode.to_string()).unwrap_or_default(),
+    },
+    Column {
+        name: "error_id",
+        header: "Error ID",
+        description: "error id of the measurement",
+        gps: false,
+        value: |r| r.info.error_id.clone(),
+    },
+];
+
+/// Columns written when none are configured (the original .info layout)
+pub const DEFAULT_COLUMNS: [&str; 14] = [
+    "frame_index",
+    "timestamp",
+    "frame_timestamp",
+    "temp",
+    "gps_x",
+    "gps_y",
+    "gps_z",
+    "q_scalar",
+    "q_vector_1",
+    "q_vector_2",
+    "q_vector_3",
+    "acq_time",
+    "pixels_short",
+    "pixels_long",
+];
+
+/// Columns added by `GpsColumns::Both`
+pub const INTERPOLATED_COLUMNS: [&str; 4] = ["interp_x", "interp_y", "interp_z", "interp_offset"];
+
+/// Value of an interpolated position column, empty without an interpolated position
+fn interpolated(row: &MetaRow, value: impl Fn([f64; 3]) -> String) -> String {
+    row.interpolated.map(value).unwrap_or_default()
+}
+
+/// Position written in the GPS columns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GpsColumns {
+    /// Position of the GPS record closest to the frame
+    #[default]
+    Nearest,
+    /// Position interpolated to the frame time, the closest record where it cannot be
+    /// interpolated
+    Interpolated,
+    /// Nearest positions with the interpolated ones in additional columns
+    Both,
+}
+
+impl FromStr for GpsColumns {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "nearest" => Ok(GpsColumns::Nearest),
+            "interpolated" => Ok(GpsColumns::Interpolated),
+            "both" => Ok(GpsColumns::Both),
+            _ => bail!("expected nearest, interpolated or both"),
+        }
+    }
+}
+
+impl fmt::Display for GpsColumns {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GpsColumns::Nearest => write!(f, "nearest"),
+            GpsColumns::Interpolated => write!(f, "interpolated"),
+            GpsColumns::Both => write!(f, "both"),
+        }
+    }
+}
+
+pub fn find(name: &str) -> Option<&'static Column> {
+    COLUMNS.iter().find(|c| c.name == name)
+}
+
+/// Columns with the exact position, not covered by the position binning
+pub const POSITION_COLUMNS: [&str; 14] = [
+    "gps_x",
+    "gps_y",
+    "gps_z",
+    "teme_x",
+    "teme_y",
+    "teme_z",
+    "itrf_x",
+    "itrf_y",
+    "itrf_z",
+    "tle_residual",
+    "interp_x",
+    "interp_y",
+    "interp_z",
+    "interp_offset",
+];
+
+/// Columns added by `--pointing-frame`
+pub const POINTING_COLUMNS: [&str; 9] = [
+    "boresight_x",
+    "boresight_y",
+    "boresight_z",
+    "boresight_lon",
+    "boresight_lat",
+    "yaw",
+    "pitch",
+    "roll",
+    "ram_angle",
+];
+
+/// Columns of the attitude quaternion and the pointing derived from it, empty for frames of
+/// excluded maneuvers
+pub const ATTITUDE_COLUMNS: [&str; 13] = [
+    "q_scalar",
+    "q_vector_1",
+    "q_vector_2",
+    "q_vector_3",
+    "boresight_x",
+    "boresight_y",
+    "boresight_z",
+    "boresight_lon",
+    "boresight_lat",
+    "yaw",
+    "pitch",
+    "roll",
+    "ram_angle",
+];
+
+/// Columns derived from the measurement info record or the acquisition time, empty when
+/// running without the measurement info file
+pub const INFO_COLUMNS: [&str; 11] = [
+    "timestamp",
+    "temp",
+    "acq_time",
+    "pixels_short",
+    "pixels_long",
+    "pixels_saved",
+    "pixels_not_saved",
+    "error_id",
+    "dose_rate",
+    "dose_equivalent_rate",
+    "mode",
+];
+
+/// Columns reporting the pixels marked by `SentinelPolicy::Invalid`
+pub const INVALID_PIXEL_COLUMNS: [&str; 2] = ["invalid_pixels", "invalid_pixel_list"];
+
+/// Looks up the named columns in the registry
+pub fn resolve<S: AsRef<str>>(names: &[S]) -> Result<Vec<&'static Column>> {
+    if names.is_empty() {
+        bail!("no metadata columns selected");
+    }
+    names
+        .iter()
+        .map(|name| {
+            let name = name.as_ref();
+            find(name).ok_or_else(|| {
+                let available: Vec<&str> = COLUMNS.iter().map(|c| c.name).collect();
+                anyhow::anyhow!(
+                    "unknown metadata column '{}', available: {}",
+                    name,
+                    available.join(", ")
+                )
+            })
+        })
+        .collect()
+}
+
+pub fn default_columns() -> Vec<&'static Column> {
+    resolve(&DEFAULT_COLUMNS).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_processor::{DataProcessor, PixelCodes};
+    use crate::direction;
+
+    #[test]
+    fn test_resolve() {
+        let columns = default_columns();
+        assert_eq!(columns.len(), 14);
+        assert_eq!(columns[13].header, "pixels long");
+        let err = resolve(&["frame_index", "speed"]).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("unknown metadata column 'speed'")
+        );
+
+        let frame = Frame::from_planes(vec![0; MATRIX_SIZE], vec![0; MATRIX_SIZE], 1709251501.3);
+        let info = MeasInfoData {
+            temp: -4.0,
+            ..Default::default()
+        };
+        let gps = GpsData {
+            j2000_x: 7e6,
+            ..Default::default()
+        };
+        let row = MetaRow {
+            frame_index: 3,
+            frame_number: 1042,
+            frame: &frame,
+            info: &info,
+            gps: &gps,
+            gps_missing: false,
+            info_missing: false,
+            acq_time: 2.5,
+            kev_per_count: 1.0,
+            quality_factor: QualityFactor::default(),
+            mounting: direction::IDENTITY,
+            maneuver: false,
+            attitude_excluded: false,
+            tle_residual: None,
+            mode: None,
+            orientation: Orientation::Detector,
+            binning: PositionBinning::default(),
+            interpolated: None,
+            gps_columns: GpsColumns::Nearest,
+            velocity: None,
+            pointing_frame: PointingFrame::J2000,
+        };
+        let values: Vec<String> = resolve(&[
+            "frame_index",
+            "global_frame_index",
+            "temp",
+            "alt",
+            "dose_rate",
+        ])
+        .unwrap()
+        .iter()
+        .map(|c| (c.value)(&row))
+        .collect();
+        assert_eq!(values, ["3", "1042", "-4", "621.863", "0e0"]);
+        let binned = MetaRow {
+            binning: "alt=10".parse().unwrap(),
+            ..row
+        };
+        assert_eq!(find("alt").unwrap().format(&binned), "620");
+        let interpolated = MetaRow {
+            interpolated: Some([6e6, 0.0, 0.0]),
+            gps_columns: GpsColumns::Both,
+            ..row
+        };
+        let names = ["gps_x", "interp_x", "interp_offset"];
+        let values: Vec<String> = resolve(&names)
+            .unwrap()
+            .iter()
+            .map(|c| c.format(&interpolated))
+            .collect();
+        assert_eq!(values, ["7000000", "6000000.000", "1000000.000"]);
+        let interpolated = MetaRow {
+            gps_columns: GpsColumns::Interpolated,
+            ..interpolated
+        };
+        assert_eq!(find("gps_x").unwrap().format(&interpolated), "6000000");
+        assert_eq!(find("interp_x").unwrap().format(&row), "");
+        assert_eq!("both".parse::<GpsColumns>().unwrap(), GpsColumns::Both);
+        assert!("closest".parse::<GpsColumns>().is_err());
+
+        // identity attitude in an equatorial orbit at +x moving towards +y
+        let pointed_gps = GpsData {
+            q_est_prop_bj_scalar: 1.0,
+            ..gps.clone()
+        };
+        let pointed = MetaRow {
+            gps: &pointed_gps,
+            velocity: Some([0.0, 7.5e3, 0.0]),
+            ..row
+        };
+        let values = |row: &MetaRow| -> Vec<String> {
+            resolve(&POINTING_COLUMNS)
+                .unwrap()
+                .iter()
+                .map(|c| c.format(row))
+                .collect()
+        };
+        // the signs of zero components are arbitrary
+        let numbers = |row: &MetaRow| -> Vec<f64> {
+            values(row).iter().map(|v| v.parse().unwrap()).collect()
+        };
+        assert_eq!(
+            numbers(&pointed),
+            [0.0, 0.0, 1.0, 0.0, 90.0, 0.0, 0.0, 0.0, 90.0]
+        );
+        let lvlh = MetaRow {
+            pointing_frame: PointingFrame::Lvlh,
+            ..pointed
+        };
+        assert_eq!(numbers(&lvlh)[..5], [0.0, -1.0, 0.0, 270.0, 0.0]);
+        let excluded = MetaRow {
+            attitude_excluded: true,
+            ..lvlh
+        };
+        assert!(values(&excluded).iter().all(String::is_empty));
+        assert_eq!(find("ram_angle").unwrap().format(&row), "");
+        let stale = MetaRow {
+            gps_missing: true,
+            info_missing: true,
+            ..row
+        };
+        let values: Vec<String> =
+            resolve(&["frame_index", "gps_x", "alt", "region", "temp", "acq_time"])
+                .unwrap()
+                .iter()
+                .map(|c| c.format(&stale))
+                .collect();
+        assert_eq!(values, ["3", "", "", "", "", ""]);
+
+        // hit pixels with code 0, outside the lookup table
+        let mut codes = PixelCodes::default();
+        codes.hits.set(2 * 256 + 5);
+        codes.hits.set(7);
+        let invalid_frame = Frame::new(Vec::new(), codes, Default::default(), 0.0);
+        let row = MetaRow {
+            frame: &invalid_frame,
+            ..stale
+        };
+        let values: Vec<String> = resolve(&INVALID_PIXEL_COLUMNS)
+            .unwrap()
+            .iter()
+            .map(|c| (c.value)(&row))
+            .collect();
+        assert_eq!(values, ["2", "7:0;5:2"]);
+
+        let mut itot = vec![0; MATRIX_SIZE];
+        let mut event = vec![0; MATRIX_SIZE];
+        for (idx, value) in [(100, 10), (101, 20), (5000, 40)] {
+            itot[idx] = value;
+            event[idx] = value / 10;
+        }
+        let mut busy_frame = Frame::from_planes(itot, event, 0.0);
+        DataProcessor::new().clusterize_frame(&mut busy_frame);
+        let row = MetaRow {
+            frame: &busy_frame,
+            ..row
+        };
+        let values: Vec<String> = resolve(&[
+            "clusters",
+            "total_itot",
+            "total_tot",
+            "max_cluster_size",
+            "occupancy",
+        ])
+        .unwrap()
+        .iter()
+        .map(|c| c.format(&row))
+        .collect();
+        assert_eq!(values, ["2", "70", "7", "2", "0.000046"]);
+    }
+}
@@ -0,0 +1,626 @@
+use crate::backfill::TIME_HEADERS;
+use crate::index::format_date;
+use crate::orbit::{self, Geodetic};
+use crate::utils::{self, format_time};
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Frames in the SAA less than this many s apart belong to the same passage
+const SAA_PASS_GAP: f64 = 300.0;
+
+/// Values of one .info row used in the comparison, None when the column is missing or empty
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProductRow {
+    pub timestamp: f64,
+    pub dose_rate: Option<f64>,
+    /// The subsatellite point is in the South Atlantic Anomaly
+    pub saa: Option<bool>,
+    pub temp: Option<f64>,
+    /// The measurement has an error id
+    pub error: Option<bool>,
+    pub acq_time: Option<f64>,
+    /// Payload bytes of the frame and the bytes decoded as pixel packets
+    pub bytes: Option<(f64, f64)>,
+}
+
+/// Converted products of one satellite
+#[derive(Debug, Clone)]
+pub struct SatProducts {
+    pub name: String,
+    /// Rows of all daily .info files in time order
+    pub rows: Vec<ProductRow>,
+}
+
+impl SatProducts {
+    /// Reads the daily `data_<date>.info` files below dir
+    pub fn load(name: &str, dir: &Path) -> Result<Self> {
+        let mut rows = Vec::new();
+        let mut dirs = vec![dir.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let entries: Vec<PathBuf> = fs::read_dir(&dir)
+                .with_context(|| format!("cannot read {}", dir.display()))?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .collect();
+            for path in entries {
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                if path.is_dir() {
+                    if !file_name.starts_with('.') {
+                        dirs.push(path);
+                    }
+                } else if file_name.starts_with("data_")
+                    && file_name.ends_with(".info")
+                    && file_name.matches('.').count() == 1
+                {
+                    let content = fs::read_to_string(&path)
+                        .with_context(|| format!("cannot read {}", path.display()))?;
+                    let mut day =
+                        parse_info(&content).with_context(|| format!("{}", path.display()))?;
+                    let clog = path.with_extension("clog");
+                    if day.iter().all(|row| row.acq_time.is_none()) && clog.exists() {
+                        fill_acq_times(&mut day, &clog)?;
+                    }
+                    rows.extend(day);
+                }
+            }
+        }
+        if rows.is_empty() {
+            bail!("no data_<date>.info files in {}", dir.display());
+        }
+        rows.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+        Ok(SatProducts {
+            name: name.to_string(),
+            rows,
+        })
+    }
+
+    pub fn span(&self) -> (f64, f64) {
+        let first = self.rows.first().map(|r| r.timestamp).unwrap_or(0.0);
+        let last = self.rows.last().map(|r| r.timestamp).unwrap_or(0.0);
+        (first, last)
+    }
+
+    fn rows_in(&self, (start, end): (f64, f64)) -> Vec<&ProductRow> {
+        self.rows
+            .iter()
+            .filter(|r| r.timestamp >= start && r.timestamp <= end)
+            .collect()
+    }
+}
+
+/// Reads the comparison values of a .info file. The SAA flag comes from the Region column,
+/// or from Latitude and Longitude when the file has no Region column. CRLF line ends and
+/// spaces around the headers and values of older converters are ignored.
+pub fn parse_info(content: &str) -> Result<Vec<ProductRow>> {
+    let mut lines = content
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    let Some(header) = lines.next() else {
+        bail!("missing column header line");
+    };
+    let headers: Vec<&str> = header.split('\t').map(str::trim).collect();
+    let column = |name: &str| headers.iter().position(|h| *h == name);
+    let Some(time_col) = TIME_HEADERS.iter().find_map(|name| column(name)) else {
+        bail!("no {} column", TIME_HEADERS.join(" or "));
+    };
+    let (dose_col, region_col, temp_col, error_col, acq_col) = (
+        column("Dose Rate"),
+        column("Region"),
+        column("Temp"),
+        column("Error ID"),
+        column("acq_time"),
+    );
+    let (lat_col, lon_col) = (column("Latitude"), column("Longitude"));
+    let (payload_col, packet_col) = (column("Payload Bytes"), column("Packet Bytes"));
+
+    let mut rows = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let values: Vec<&str> = line.split('\t').collect();
+        let value = |col: Option<usize>| {
+            col.and_then(|c| values.get(c))
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+        };
+        let number = |col: Option<usize>| value(col).and_then(|v| v.parse::<f64>().ok());
+        let timestamp: f64 = value(Some(time_col))
+            .map(|v| utils::parse_field(v, headers[time_col]))
+            .transpose()?
+            .with_context(|| format!("row {}: missing {} value", i + 1, headers[time_col]))?;
+        let saa = match region_col {
+            Some(_) => value(region_col).map(|region| region == "saa"),
+            None => number(lat_col).zip(number(lon_col)).map(|(lat, lon)| {
+                orbit::SAA.contains(&Geodetic {
+                    latitude: lat,
+                    longitude: lon,
+                    altitude: 0.0,
+                })
+            }),
+        };
+        rows.push(ProductRow {
+            timestamp,
+            dose_rate: number(dose_col),
+            saa,
+            temp: number(temp_col),
+            // the error id is empty when the measurement has no error
+            error: error_col.map(|_| {
+                value(error_col)
+                    .map(|id| id.trim_matches('"').trim())
+                    .is_some_and(|id| !id.is_empty() && id != "0")
+            }),
+            acq_time: number(acq_col),
+            bytes: number(payload_col).zip(number(packet_col)),
+        });
+    }
+    Ok(rows)
+}
+
+/// Frame of a .clog file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClogFrame {
+    pub number: usize,
+    pub timestamp: f64,
+    pub acq_time: f64,
+    /// Pixels `[x, y, value, value2]` of each cluster, the values are energies in keV in the
+    /// clogs written with an energy calibration
+    pub clusters: Vec<Vec<[f64; 4]>>,
+}
+
+/// Number written by any converter version, with a decimal point or comma
+fn parse_decimal(value: &str) -> Option<f64> {
+    value.trim().replace(',', ".").parse().ok()
+}
+
+/// Splits at the first comma followed by a space, a decimal comma has none
+fn split_list(text: &str) -> Option<(&str, &str)> {
+    let pos = text
+        .char_indices()
+        .find(|&(i, c)| c == ',' && text[i + 1..].starts_with(char::is_whitespace))?
+        .0;
+    Some((&text[..pos], &text[pos + 1..]))
+}
+
+/// Reads the `Frame <n> (<timestamp>, <acq_time> s)` line of a frame
+fn parse_frame_line(line: &str) -> Option<(usize, f64, f64)> {
+    let (number, rest) = line.strip_prefix("Frame")?.split_once('(')?;
+    let (timestamp, acq_time) = split_list(rest.trim_end().strip_suffix(')')?)?;
+    let acq_time = acq_time.trim();
+    let acq_time = acq_time
+        .strip_suffix("sec")
+        .or_else(|| acq_time.strip_suffix('s'))
+        .unwrap_or(acq_time);
+    Some((
+        number.trim().parse().ok()?,
+        parse_decimal(timestamp)?,
+        parse_decimal(acq_time)?,
+    ))
+}
+
+/// Reads the `[x, y, value, value2]` pixels of a cluster line
+fn parse_cluster_line(line: &str) -> Option<Vec<[f64; 4]>> {
+    let mut pixels = Vec::new();
+    for pixel in line.split(']').map(str::trim).filter(|p| !p.is_empty()) {
+        let pixel = pixel.strip_prefix('[')?;
+        // the Python prototype writes no spaces, then a comma is never a decimal comma
+        let values: Vec<&str> = if pixel.contains(", ") {
+            let mut values = Vec::new();
+            let mut rest = pixel;
+            while let Some((value, next)) = split_list(rest) {
+                values.push(value);
+                rest = next;
+            }
+            values.push(rest);
+            values
+        } else {
+            pixel.split(',').collect()
+        };
+        let values: Vec<f64> = values
+            .into_iter()
+            .map(parse_decimal)
+            .collect::<Option<_>>()?;
+        pixels.push(values.try_into().ok()?);
+    }
+    Some(pixels)
+}
+
+/// Reads the frames of a .clog file of this converter, its older versions or the Python
+/// prototype: CRLF or LF line ends, spaces at the line ends, `#` header lines, and
+/// acquisition times with or without the ` s` unit, in exponent notation or with a decimal
+/// comma are accepted
+pub fn parse_clog(content: &str) -> Result<Vec<ClogFrame>> {
+    let mut frames: Vec<ClogFrame> = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with("Frame") {
+            let Some((number, timestamp, acq_time)) = parse_frame_line(line) else {
+                bail!("line {}: invalid frame line {:?}", i + 1, line);
+            };
+            frames.push(ClogFrame {
+                number,
+                timestamp,
+                acq_time,
+                clusters: Vec::new(),
+            });
+            continue;
+        }
+        let Some(frame) = frames.last_mut() else {
+            bail!("line {}: cluster before the first frame", i + 1);
+        };
+        let Some(pixels) = parse_cluster_line(line) else {
+            bail!("line {}: invalid cluster line {:?}", i + 1, line);
+        };
+        frame.clusters.push(pixels);
+    }
+    Ok(frames)
+}
+
+/// Takes the acquisition times of .info rows without the acq_time column, e.g. of the
+/// Python prototype, from the frames of the .clog file of the day
+fn fill_acq_times(rows: &mut [ProductRow], clog: &Path) -> Result<()> {
+    let content =
+        fs::read_to_string(clog).with_context(|| format!("cannot read {}", clog.display()))?;
+    let frames = parse_clog(&content).with_context(|| format!("{}", clog.display()))?;
+    if frames.len() != rows.len() {
+        tracing::warn!(
+            "{} has {} frames for {} .info rows, its acquisition times are not used",
+            clog.display(),
+            frames.len(),
+            rows.len()
+        );
+        return Ok(());
+    }
+    for (row, frame) in rows.iter_mut().zip(frames) {
+        row.acq_time = Some(frame.acq_time);
+    }
+    Ok(())
+}
+
+/// Entry and exit times of the SAA passages, frames without the SAA flag are ignored
+pub fn saa_passes(rows: &[&ProductRow]) -> Vec<(f64, f64)> {
+    let mut passes: Vec<(f64, f64)> = Vec::new();
+    let mut last_outside: Option<f64> = None;
+    for row in rows {
+        match row.saa {
+            Some(true) => match passes.last_mut() {
+                Some(pass)
+                    if row.timestamp - pass.1 < SAA_PASS_GAP
+                        && last_outside.is_none_or(|t| t < pass.1) =>
+                {
+                    pass.1 = row.timestamp
+                }
+                _ => passes.push((row.timestamp, row.timestamp)),
+            },
+            Some(false) => last_outside = Some(row.timestamp),
+            None => {}
+        }
+    }
+    passes
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, n) = values.fold((0.0, 0usize), |(sum, n), v| (sum + v, n + 1));
+    (n > 0).then(|| sum / n as f64)
+}
+
+fn fmt_value(value: Option<f64>, format: fn(f64) -> String) -> String {
+    value.map(format).unwrap_or_default()
+}
+
+/// Passage of the other satellite with the entry closest to the reference entry
+fn closest_pass(passes: &[(f64, f64)], entry: f64, window: f64) -> Option<(f64, f64)> {
+    passes
+        .iter()
+        .copied()
+        .filter(|p| (p.0 - entry).abs() <= window)
+        .min_by(|a, b| (a.0 - entry).abs().total_cmp(&(b.0 - entry).abs()))
+}
+
+/// Writes the comparison of the satellites to the first one over their common period:
+/// a summary with dose rate ratio and health deltas, the daily dose rate ratios and the
+/// timing differences of the SAA passages matched within saa_window s
+pub fn write_report<W: Write>(sats: &[SatProducts], saa_window: f64, writer: &mut W) -> Result<()> {
+    if sats.len() < 2 {
+        bail!("at least two satellites are needed for a comparison");
+    }
+    let period = sats
+        .iter()
+        .map(|s| s.span())
+        .fold((f64::NEG_INFINITY, f64::INFINITY), |(start, end), span| {
+            (start.max(span.0), end.min(span.1))
+        });
+    if period.0 > period.1 {
+        bail!("the products of the satellites do not overlap in time");
+    }
+    let rows: Vec<Vec<&ProductRow>> = sats.iter().map(|s| s.rows_in(period)).collect();
+    let passes: Vec<Vec<(f64, f64)>> = rows.iter().map(|r| saa_passes(r)).collect();
+    let reference = &sats[0].name;
+    writeln!(
+        writer,
+        "# Comparison to {} from {} to {}, SAA passages matched within {} s",
+        reference,
+        format_time(period.0),
+        format_time(period.1),
+        saa_window
+    )?;
+
+    let dose_rate = |rows: &[&ProductRow]| mean(rows.iter().filter_map(|r| r.dose_rate));
+    let temp = |rows: &[&ProductRow]| mean(rows.iter().filter_map(|r| r.temp));
+    let errors =
+        |rows: &[&ProductRow]| mean(rows.iter().filter_map(|r| r.error.map(|e| e as u8 as f64)));
+    let acq_time = |rows: &[&ProductRow]| mean(rows.iter().filter_map(|r| r.acq_time));
+    let ratio =
+        |a: Option<f64>, b: Option<f64>| a.zip(b).filter(|(_, b)| *b != 0.0).map(|(a, b)| a / b);
+    let delta = |a: Option<f64>, b: Option<f64>| a.zip(b).map(|(a, b)| a - b);
+    writeln!(
+        writer,
+        "satellite\tframes\tdose_rate[Gy/s]\tdose_rate_ratio\tsaa_passes\tsaa_matched\tsaa_entry_delta[s]\ttemp\ttemp_delta\terror_fraction\terror_fraction_delta\tacq_time[s]\tacq_time_delta[s]"
+    )?;
+    for (i, sat) in sats.iter().enumerate() {
+        let matched: Vec<f64> = passes[0]
+            .iter()
+            .filter_map(|p| closest_pass(&passes[i], p.0, saa_window).map(|o| o.0 - p.0))
+            .collect();
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            sat.name,
+            rows[i].len(),
+            fmt_value(dose_rate(&rows[i]), |v| format!("{:.4e}", v)),
+            fmt_value(ratio(dose_rate(&rows[i]), dose_rate(&rows[0])), |v| {
+                format!("{:.4}", v)
+            }),
+            passes[i].len(),
+            matched.len(),
+            fmt_value(mean(matched.into_iter()), |v| format!("{:.1}", v)),
+            fmt_value(temp(&rows[i]), |v| format!("{:.2}", v)),
+            fmt_value(delta(temp(&rows[i]), temp(&rows[0])), |v| format!(
+                "{:.2}",
+                v
+            )),
+            fmt_value(errors(&rows[i]), |v| format!("{:.4}", v)),
+            fmt_value(delta(errors(&rows[i]), errors(&rows[0])), |v| {
+                format!("{:.4}", v)
+            }),
+            fmt_value(acq_time(&rows[i]), |v| format!("{:.3}", v)),
+            fmt_value(delta(acq_time(&rows[i]), acq_time(&rows[0])), |v| {
+                format!("{:.3}", v)
+            }),
+        )?;
+    }
+
+    writeln!(writer, "# Daily dose rate ratios to {}", reference)?;
+    writeln!(
+        writer,
+        "date\tsatellite\tframes\tdose_rate[Gy/s]\tdose_rate_ratio"
+    )?;
+    let daily: Vec<BTreeMap<String, Vec<&ProductRow>>> = rows
+        .iter()
+        .map(|rows| {
+            let mut days: BTreeMap<String, Vec<&ProductRow>> = BTreeMap::new();
+            for row in rows {
+                days.entry(format_date(row.timestamp))
+                    .or_default()
+                    .push(row);
+            }
+            days
+        })
+        .collect();
+    for (date, reference_rows) in &daily[0] {
+        for (sat, days) in sats.iter().zip(&daily) {
+            let day_rows = days.get(date).map(Vec::as_slice).unwrap_or_default();
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}",
+                date,
+                sat.name,
+                day_rows.len(),
+                fmt_value(dose_rate(day_rows), |v| format!("{:.4e}", v)),
+                fmt_value(ratio(dose_rate(day_rows), dose_rate(reference_rows)), |v| {
+                    format!("{:.4}", v)
+                }),
+            )?;
+        }
+    }
+
+    writeln!(
+        writer,
+        "# SAA passages of {} and the closest passages of the other satellites",
+        reference
+    )?;
+    writeln!(
+        writer,
+        "entry\texit\tsatellite\tentry_delta[s]\texit_delta[s]\tduration_delta[s]"
+    )?;
+    for pass in &passes[0] {
+        for (sat, other) in sats.iter().zip(&passes).skip(1) {
+            let Some(matched) = closest_pass(other, pass.0, saa_window) else {
+                writeln!(
+                    writer,
+                    "{}\t{}\t{}\t\t\t",
+                    format_time(pass.0),
+                    format_time(pass.1),
+                    sat.name
+                )?;
+                continue;
+            };
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{:.1}\t{:.1}\t{:.1}",
+                format_time(pass.0),
+                format_time(pass.1),
+                sat.name,
+                matched.0 - pass.0,
+                matched.1 - pass.1,
+                (matched.1 - matched.0) - (pass.1 - pass.0)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn info(rows: &[(f64, f64, &str, &str)]) -> String {
+        let mut text = String::from(
+            "# repro_hash: abc\nFrame Index\tFrame Timestamp\tTemp\tRegion\tDose Rate\tError ID\n",
+        );
+        for (i, (time, dose, region, error)) in rows.iter().enumerate() {
+            text += &format!(
+                "{}\t{}\t30.5\t{}\t{:e}\t{}\n",
+                i + 1,
+                time,
+                region,
+                dose,
+                error
+            );
+        }
+        text
+    }
+
+    #[test]
+    fn test_compare_sats() {
+        let rows = parse_info(&info(&[
+            (0.0, 1e-6, "low_latitude", "0"),
+            (100.0, 4e-6, "saa", "0"),
+            (200.0, 4e-6, "saa", "\"255, 31\""),
+            (300.0, 1e-6, "low_latitude", ""),
+            (6000.0, 1e-6, "saa", ""),
+        ]))
+        .unwrap();
+        assert_eq!(rows[2].error, Some(true));
+        assert_eq!(rows[3].error, Some(false));
+        assert_eq!(rows[1].saa, Some(true));
+        let refs: Vec<&ProductRow> = rows.iter().collect();
+        assert_eq!(saa_passes(&refs), vec![(100.0, 200.0), (6000.0, 6000.0)]);
+
+        let other = parse_info(&info(&[
+            (0.0, 2e-6, "low_latitude", "0"),
+            (160.0, 8e-6, "saa", "0"),
+            (300.0, 2e-6, "low_latitude", "0"),
+            (6000.0, 2e-6, "low_latitude", "0"),
+        ]))
+        .unwrap();
+        let sats = [
+            SatProducts {
+                name: String::from("ow-1"),
+                rows,
+            },
+            SatProducts {
+                name: String::from("ow-2"),
+                rows: other,
+            },
+        ];
+        let mut report = Vec::new();
+        write_report(&sats, 600.0, &mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(
+            lines[2]
+                .starts_with("ow-1\t5\t2.2000e-6\t1.0000\t2\t2\t0.0\t30.50\t0.00\t0.2000\t0.0000")
+        );
+        // the mean dose rates are 2.2e-6 and 3.5e-6
+        assert!(lines[3].starts_with("ow-2\t4\t3.5000e-6\t1.5909\t1\t1\t60.0\t"));
+        assert!(lines.contains(
+            &"1970-01-01 00:01:40.000\t1970-01-01 00:03:20.000\tow-2\t60.0\t-40.0\t-100.0"
+        ));
+        assert!(lines.contains(&"1970-01-01 01:40:00.000\t1970-01-01 01:40:00.000\tow-2\t\t\t"));
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_info_variants(
+            rows in prop::collection::vec(
+                (0.0..2e9f64, 0.0..1e-3f64, any::<bool>(), 0.0..100.0f64),
+                1..20,
+            ),
+        ) {
+            let mut clean = String::from("Frame Timestamp\tRegion\tDose Rate\tacq_time\n");
+            // CRLF, trailing spaces and exponent formatted acquisition times
+            let mut legacy = String::from("# legacy\r\nFrame Timestamp \tRegion\tDose Rate\tacq_time  \r\n");
+            for &(time, dose, saa, acq_time) in &rows {
+                let region = if saa { "saa" } else { "low_latitude" };
+                clean += &format!("{}\t{}\t{:e}\t{}\n", time, region, dose, acq_time);
+                legacy += &format!("{} \t{}\t{:e} \t{:e} \r\n", time, region, dose, acq_time);
+            }
+            let parsed = parse_info(&clean).unwrap();
+            prop_assert_eq!(parsed.len(), rows.len());
+            prop_assert_eq!(parsed[0].acq_time, Some(rows[0].3));
+            prop_assert_eq!(&parse_info(&legacy).unwrap(), &parsed);
+        }
+
+        #[test]
+        fn test_parse_info_never_panics(content in "(Frame Timestamp|[0-9.e\t -]|saa|\r?\n){0,64}") {
+            let _ = parse_info(&content);
+        }
+
+        #[test]
+        fn test_parse_clog_variants(
+            frames in prop::collection::vec(
+                (
+                    1e9..2e9f64,
+                    0.0..25.0f64,
+                    prop::collection::vec(
+                        prop::collection::vec((0..256u16, 0..256u16, 0..12000u16, 0..1000u16), 1..5),
+                        0..4,
+                    ),
+                ),
+                1..10,
+            ),
+            style in 0..4usize,
+        ) {
+            // as written by the converter, and with CRLF, trailing spaces and the acquisition
+            // time formatting of older versions
+            let mut clean = String::from("# repro_hash: abc\n");
+            let mut legacy = String::from("# legacy\r\n");
+            for (i, (timestamp, acq_time, clusters)) in frames.iter().enumerate() {
+                let acq_time = (acq_time * 1e6).round() / 1e6;
+                clean += &format!("Frame {} ({}, {} s)\n", i + 1, timestamp, acq_time);
+                let acq = match style {
+                    0 => format!("{:e} s", acq_time),
+                    1 => format!("{:.6} s", acq_time).replace('.', ","),
+                    2 => format!("{}s", acq_time),
+                    _ => format!("{:.6} sec", acq_time),
+                };
+                legacy += &format!("Frame {} ({}, {})  \r\n", i + 1, timestamp, acq);
+                for cluster in clusters {
+                    for (x, y, value, value2) in cluster {
+                        clean += &format!("[{}, {}, {}, {}] ", x, y, value, value2);
+                        // the Python prototype writes the pixels without spaces
+                        legacy += &format!("[{},{},{},{}]", x, y, value, value2);
+                    }
+                    clean += "\n";
+                    legacy += " \r\n";
+                }
+                clean += "\n";
+                legacy += "\r\n";
+            }
+            let parsed = parse_clog(&clean).unwrap();
+            prop_assert_eq!(parsed.len(), frames.len());
+            for (frame, (timestamp, _, clusters)) in parsed.iter().zip(&frames) {
+                prop_assert_eq!(frame.timestamp, *timestamp);
+                prop_assert_eq!(frame.clusters.len(), clusters.len());
+            }
+            let legacy = parse_clog(&legacy).unwrap();
+            prop_assert_eq!(legacy.len(), parsed.len());
+            for (old, new) in legacy.iter().zip(&parsed) {
+                prop_assert_eq!(old.number, new.number);
+                prop_assert_eq!(old.timestamp, new.timestamp);
+                prop_assert!((old.acq_time - new.acq_time).abs() < 1e-9);
+                prop_assert_eq!(&old.clusters, &new.clusters);
+            }
+        }
+
+        #[test]
+        fn test_parse_clog_never_panics(content in "(Frame |[0-9.,e\\[\\] ()s-]|\r?\n){0,64}") {
+            let _ = parse_clog(&content);
+        }
+    }
+}
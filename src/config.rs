@@ -0,0 +1,84 @@
+use crate::classification::ClassThresholds;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// Options read from the TOML configuration file (--config)
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    /// Columns of the .info metadata output, see the columns registry for the names
+    pub columns: Option<Vec<String>>,
+    /// Columns computed from the metadata columns (name = expression), appended to the
+    /// selected ones
+    pub derived_columns: Option<Vec<String>>,
+    /// Regions of interest of the pixel matrix (name:x1,y1,x2,y2), added to the --roi ones
+    pub rois: Option<Vec<String>>,
+    /// Per-pixel ToA offset/skew file (x y offset skew lines) applied to the second pixel
+    /// values, relative to the configuration file
+    pub toa_calibration: Option<String>,
+    /// Rotation of detector to spacecraft body vectors (rows), for the cluster directions
+    pub mounting: Option<[[f64; 3]; 3]>,
+    /// Temperature dependent per-pixel noise threshold applied before the clustering
+    pub noise_threshold: Option<NoiseThreshold>,
+    /// Thresholds of the cluster classification of the cluster feature tables
+    pub classification: Option<ClassThresholds>,
+}
+
+/// Parameters of the noise threshold model, see the noise module
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NoiseThreshold {
+    /// iToT counts a pixel must exceed at the reference temperature
+    pub base: f64,
+    /// Threshold increase in iToT counts per degree below the reference temperature
+    #[serde(default)]
+    pub slope: f64,
+    /// Temperature in the units of the measurement info temp column
+    #[serde(default)]
+    pub reference_temp: f64,
+    /// Per-pixel `x y base [slope]` file overriding the uniform parameters, relative to the
+    /// configuration file
+    pub pixels: Option<String>,
+}
+
+impl FileConfig {
+    pub fn load(path: &Path) -> Result<FileConfig> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("cannot read config {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("invalid config {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: FileConfig =
+            toml::from_str(r#"columns = ["frame_index", "timestamp", "lat"]"#).unwrap();
+        assert_eq!(config.columns.unwrap(), ["frame_index", "timestamp", "lat"]);
+        let config: FileConfig =
+            toml::from_str(r#"derived_columns = ["per_s = clusters / acq_time"]"#).unwrap();
+        assert_eq!(config.derived_columns.unwrap().len(), 1);
+        assert!(toml::from_str::<FileConfig>("colums = []").is_err());
+        let config: FileConfig = toml::from_str(r#"rois = ["shielded:0,0,127,255"]"#).unwrap();
+        assert_eq!(config.rois.unwrap(), ["shielded:0,0,127,255"]);
+        let config: FileConfig = toml::from_str(r#"toa_calibration = "toa.txt""#).unwrap();
+        assert_eq!(config.toa_calibration.unwrap(), "toa.txt");
+        let config: FileConfig =
+            toml::from_str("mounting = [[0, 0, -1], [0, 1, 0], [1, 0, 0]]").unwrap();
+        assert_eq!(config.mounting.unwrap()[2], [1.0, 0.0, 0.0]);
+        let config: FileConfig =
+            toml::from_str("[noise_threshold]\nbase = 2\nslope = 0.1\nreference_temp = 25")
+                .unwrap();
+        assert_eq!(config.noise_threshold.unwrap().slope, 0.1);
+        assert!(toml::from_str::<FileConfig>("[noise_threshold]\nslope = 0.1").is_err());
+        let config: FileConfig = toml::from_str("[classification]\nheavy_blob_kev = 200").unwrap();
+        let classification = config.classification.unwrap();
+        assert_eq!(classification.heavy_blob_kev, 200.0);
+        assert_eq!(classification.dot_max_pixels, 2);
+    }
+}
@@ -0,0 +1,510 @@
+use crate::data_processor::{DataProcessor, Frame, LayoutDetection, PacketLayout};
+use crate::error::OnewebError;
+use crate::line_reader::LineReader;
+use crate::orientation::Orientation;
+use crate::utils;
+use anyhow::{Context, Result};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+
+/// Start of readout with no header flags
+const HEADER: [u8; 6] = [0x71, 0xAF, 0x00, 0x00, 0x00, 0x00];
+/// End of readout
+const TERMINATOR: [u8; 4] = [0x71, 0xA0, 0x00, 0x00];
+/// Sequences the frame assembler searches the byte stream for
+const SYNC: [[u8; 4]; 3] = [[0x71, 0xAF, 0x00, 0x00], TERMINATOR, [0x00; 4]];
+/// Low nibble of the last packet byte
+const HIT: u8 = 0x0E;
+/// Largest 14-bit iToT (ToA) code
+pub const MAX_ITOT_CODE: u16 = 0x3FFF;
+/// Largest 10-bit event code
+pub const MAX_EVENT_CODE: u16 = 0x3FF;
+/// Time of the first generated line, 2024-01-01
+const START_TIME: f64 = 1704067200.0;
+
+/// Hit pixel with the raw codes of its packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownPixel {
+    pub x: u8,
+    pub y: u8,
+    pub itot_code: u16,
+    pub event_code: u16,
+}
+
+/// Reverses the order of the four address nibbles, its own inverse
+fn swap_nibbles(address: u16) -> u16 {
+    ((address & 0x000F) << 12)
+        | ((address & 0x00F0) << 4)
+        | ((address & 0x0F00) >> 4)
+        | ((address & 0xF000) >> 12)
+}
+
+/// Pixel packet of the ICD bit layout: header nibble 0xA, 16-bit address (7-bit end of
+/// column, 6-bit super pixel, 3-bit pixel), 14-bit iToT code, 10-bit event code and the
+/// hit nibble, the counterpart of the decoder's packet parser
+pub fn encode_pixel_packet(pixel: &KnownPixel, layout: PacketLayout) -> [u8; 6] {
+    let (x, y) = (pixel.x as u16, pixel.y as u16);
+    let address = ((x / 2) << 9) | ((y / 4) << 3) | ((x % 2) * 4 + y % 4);
+    let address = match layout {
+        PacketLayout::Standard => address,
+        PacketLayout::Swapped => swap_nibbles(address),
+    };
+    let itot = pixel.itot_code & MAX_ITOT_CODE;
+    let event = pixel.event_code & MAX_EVENT_CODE;
+    [
+        0xA0 | (address >> 12) as u8,
+        (address >> 4) as u8,
+        ((address & 0x0F) << 4 | itot >> 10) as u8,
+        (itot >> 2) as u8,
+        ((itot & 0x03) << 6 | event >> 4) as u8,
+        ((event & 0x0F) << 4) as u8 | HIT,
+    ]
+}
+
+/// The packet contains a start or end of readout sequence, the assembler would split the
+/// frame there. Sequences cannot span two packets: the first and last packet bytes are
+/// never 0x00 or 0x71.
+pub fn is_ambiguous(packet: &[u8; 6]) -> bool {
+    packet.windows(4).any(|w| SYNC.iter().any(|s| s[..] == *w))
+}
+
+/// Frame of known pixels with its packets
+#[derive(Debug, Clone, Default)]
+pub struct GeneratedFrame {
+    pub pixels: Vec<KnownPixel>,
+    pub packets: Vec<u8>,
+}
+
+/// Seeded generator of frames for one packet layout
+pub struct Generator {
+    state: u64,
+    pub layout: PacketLayout,
+    /// Packets drawn again because their bytes contain a sync sequence
+    pub ambiguous: usize,
+}
+
+impl Generator {
+    pub fn new(seed: u64, layout: PacketLayout) -> Self {
+        Generator {
+            state: seed,
+            layout,
+            ambiguous: 0,
+        }
+    }
+
+    /// splitmix64
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        utils::mix64(self.state)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    /// Adds the packet of the pixel, changing its event code while the packet is ambiguous
+    fn push(&mut self, frame: &mut GeneratedFrame, mut pixel: KnownPixel) {
+        let mut packet = encode_pixel_packet(&pixel, self.layout);
+        if is_ambiguous(&packet) {
+            self.ambiguous += 1;
+            while is_ambiguous(&packet) {
+                pixel.event_code = (pixel.event_code + 1) & MAX_EVENT_CODE;
+                packet = encode_pixel_packet(&pixel, self.layout);
+            }
+        }
+        frame.pixels.push(pixel);
+        frame.packets.extend_from_slice(&packet);
+    }
+
+    /// Frame of up to eight short random walk tracks with random codes
+    pub fn random_frame(&mut self) -> GeneratedFrame {
+        let mut frame = GeneratedFrame::default();
+        let mut hit = vec![false; 256 * 256];
+        for _ in 0..1 + self.below(8) {
+            let (mut x, mut y) = (self.below(256) as i32, self.below(256) as i32);
+            for _ in 0..1 + self.below(12) {
+                if !hit[y as usize * 256 + x as usize] {
+                    hit[y as usize * 256 + x as usize] = true;
+                    let pixel = KnownPixel {
+                        x: x as u8,
+                        y: y as u8,
+                        itot_code: self.below(MAX_ITOT_CODE as u64 + 1) as u16,
+                        event_code: self.below(MAX_EVENT_CODE as u64 + 1) as u16,
+                    };
+                    self.push(&mut frame, pixel);
+                }
+                x = (x + self.below(3) as i32 - 1).clamp(0, 255);
+                y = (y + self.below(3) as i32 - 1).clamp(0, 255);
+            }
+        }
+        frame
+    }
+
+    /// 16 frames of isolated pixels together hitting every pixel once, with every iToT code
+    /// four times and every event code 64 times
+    pub fn exhaustive_frames(&mut self) -> Vec<GeneratedFrame> {
+        let mut frames = vec![GeneratedFrame::default(); 16];
+        let offset = self.below(1 << 16) as u32;
+        for n in 0..1u32 << 16 {
+            let (f, k) = (n / 4096, n % 4096);
+            let code = n.wrapping_add(offset);
+            let pixel = KnownPixel {
+                x: ((k % 64) * 4 + f % 4) as u8,
+                y: ((k / 64) * 4 + f / 4) as u8,
+                itot_code: (code & MAX_ITOT_CODE as u32) as u16,
+                event_code: (code.wrapping_mul(7) & MAX_EVENT_CODE as u32) as u16,
+            };
+            self.push(&mut frames[f as usize], pixel);
+        }
+        frames
+    }
+
+    /// Data file of the frames, the packets split into lines of 1 to 40 packets with
+    /// uppercase hex payloads as sent by the satellite
+    pub fn render(&mut self, frames: &[GeneratedFrame]) -> String {
+        let mut csv = String::from("TIMESTAMP,DATA\n");
+        for (i, frame) in frames.iter().enumerate() {
+            let mut time = START_TIME + i as f64 * 10.0;
+            // the assembler looks for the end of readout from the line after the header on
+            let mut line = HEADER.to_vec();
+            let mut packets = frame.packets.chunks(6).peekable();
+            loop {
+                for _ in 0..1 + self.below(40) {
+                    match packets.next() {
+                        Some(packet) => line.extend_from_slice(packet),
+                        None => break,
+                    }
+                }
+                let last = packets.peek().is_none();
+                if last && line.starts_with(&HEADER) {
+                    writeln!(
+                        csv,
+                        "{},{}",
+                        utils::format_time(time),
+                        hex::encode_upper(&line)
+                    )
+                    .unwrap();
+                    time += 0.1;
+                    line.clear();
+                }
+                if last {
+                    line.extend_from_slice(&TERMINATOR);
+                }
+                writeln!(
+                    csv,
+                    "{},{}",
+                    utils::format_time(time),
+                    hex::encode_upper(&line)
+                )
+                .unwrap();
+                time += 0.1;
+                line.clear();
+                if last {
+                    break;
+                }
+            }
+        }
+        csv
+    }
+}
+
+/// Ground truth of the frames: frame number (1-based), x, y and the raw codes
+pub fn truth_csv(frames: &[GeneratedFrame]) -> String {
+    let mut csv = String::from("frame,x,y,itot_code,event_code\n");
+    for (i, frame) in frames.iter().enumerate() {
+        for pixel in &frame.pixels {
+            writeln!(
+                csv,
+                "{},{},{},{},{}",
+                i + 1,
+                pixel.x,
+                pixel.y,
+                pixel.itot_code,
+                pixel.event_code
+            )
+            .unwrap();
+        }
+    }
+    csv
+}
+
+/// Settings of a conformance run
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConformanceOptions {
+    /// Number of random frames, ignored when exhaustive
+    pub frames: usize,
+    /// Every pixel address, iToT code and event code instead of random frames
+    pub exhaustive: bool,
+    pub seed: u64,
+    /// Output orientation the decoded matrices are compared in
+    pub orientation: Orientation,
+}
+
+/// Result of the decoder check for one packet layout
+#[derive(Debug, Clone)]
+pub struct ConformanceReport {
+    pub layout: PacketLayout,
+    pub frames: usize,
+    pub pixels: usize,
+    /// Packets drawn again because their bytes contain a sync sequence
+    pub ambiguous: usize,
+    /// Distinct pixel addresses, iToT codes and event codes generated
+    pub addresses: usize,
+    pub itot_codes: usize,
+    pub event_codes: usize,
+    /// Layout autodetection of the random frames
+    pub detection: Option<LayoutDetection>,
+    /// Decoded values differing from the generated ones
+    pub mismatches: Vec<String>,
+    /// Generated data file and its ground truth
+    pub data: String,
+    pub truth: String,
+}
+
+impl ConformanceReport {
+    /// Writes conformance_<layout>.csv and conformance_<layout>_truth.csv into the directory
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        for (suffix, content) in [("", &self.data), ("_truth", &self.truth)] {
+            let path = dir.join(format!("conformance_{}{}.csv", self.layout, suffix));
+            fs::write(&path, content)
+                .with_context(|| format!("cannot write {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the decoded frames with the generated ones
+fn compare(
+    path: &str,
+    generated: &[GeneratedFrame],
+    decoded: &[Frame],
+    orientation: Orientation,
+    mismatches: &mut Vec<String>,
+) {
+    if decoded.len() != generated.len() {
+        mismatches.push(format!(
+            "{}: {} frames decoded, {} generated",
+            path,
+            decoded.len(),
+            generated.len()
+        ));
+    }
+    for (i, (expected, frame)) in generated.iter().zip(decoded).enumerate() {
+        let hits = frame.codes.hits.count();
+        if hits != expected.pixels.len() || frame.bytes.discarded != 0 {
+            mismatches.push(format!(
+                "{}: frame {} has {} hit pixels and {} discarded bytes, expected {} and 0",
+                path,
+                i + 1,
+                hits,
+                frame.bytes.discarded,
+                expected.pixels.len()
+            ));
+        }
+        let itot = orientation.matrix(&frame.codes.itot);
+        let event = orientation.matrix(&frame.codes.event);
+        for pixel in &expected.pixels {
+            let (x, y) = orientation.apply(pixel.x, pixel.y);
+            let idx = y as usize * 256 + x as usize;
+            let found = (itot[idx], event[idx]);
+            if !frame
+                .codes
+                .hits
+                .get(pixel.y as usize * 256 + pixel.x as usize)
+                || found != (pixel.itot_code, pixel.event_code)
+            {
+                mismatches.push(format!(
+                    "{}: frame {} pixel ({}, {}) decoded as ({}, {}), expected ({}, {})",
+                    path,
+                    i + 1,
+                    x,
+                    y,
+                    found.0,
+                    found.1,
+                    pixel.itot_code,
+                    pixel.event_code
+                ));
+            }
+        }
+    }
+}
+
+fn reader(data: &str) -> LineReader<Cursor<&[u8]>> {
+    LineReader::new(
+        BufReader::new(Cursor::new(data.as_bytes())),
+        "conformance.csv",
+    )
+}
+
+/// Generates frames for the layout, decodes them from the data file lines and from the raw
+/// byte stream and compares the codes at every generated pixel
+pub fn run(layout: PacketLayout, options: &ConformanceOptions) -> Result<ConformanceReport> {
+    let mut generator = Generator::new(options.seed, layout);
+    let frames = if options.exhaustive {
+        generator.exhaustive_frames()
+    } else {
+        (0..options.frames)
+            .map(|_| generator.random_frame())
+            .collect()
+    };
+    let data = generator.render(&frames);
+    let mut mismatches = Vec::new();
+
+    let mut processor = DataProcessor::new();
+    processor.layout = layout;
+    let mut lines = reader(&data);
+    let mut decoded = Vec::new();
+    loop {
+        match processor.get_next_frame(&mut lines) {
+            Ok(frame) => decoded.push(frame),
+            Err(e) if matches!(e.downcast_ref(), Some(OnewebError::EndOfData)) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    compare(
+        "lines",
+        &frames,
+        &decoded,
+        options.orientation,
+        &mut mismatches,
+    );
+
+    let mut processor = DataProcessor::new();
+    processor.layout = layout;
+    let mut decoded = Vec::new();
+    for line in data.lines().skip(1) {
+        let (timestamp, bytes) = DataProcessor::parse_line(line)?;
+        decoded.extend(processor.push_bytes(&bytes, timestamp));
+    }
+    compare(
+        "bytes",
+        &frames,
+        &decoded,
+        options.orientation,
+        &mut mismatches,
+    );
+
+    let detection = (!options.exhaustive && !frames.is_empty()).then(|| {
+        let detection = DataProcessor::detect_layout(&mut reader(&data), frames.len());
+        if detection.layout != layout {
+            mismatches.push(format!(
+                "layout detected as {} (standard {:.2}, swapped {:.2})",
+                detection.layout, detection.standard_score, detection.swapped_score
+            ));
+        }
+        detection
+    });
+
+    let pixels: Vec<&KnownPixel> = frames.iter().flat_map(|f| &f.pixels).collect();
+    let distinct = |key: &dyn Fn(&KnownPixel) -> usize, size: usize| {
+        let mut seen = vec![false; size];
+        pixels.iter().for_each(|&p| seen[key(p)] = true);
+        seen.iter().filter(|&&s| s).count()
+    };
+    Ok(ConformanceReport {
+        layout,
+        frames: frames.len(),
+        pixels: pixels.len(),
+        ambiguous: generator.ambiguous,
+        addresses: distinct(&|p| p.y as usize * 256 + p.x as usize, 1 << 16),
+        itot_codes: distinct(&|p| p.itot_code as usize, MAX_ITOT_CODE as usize + 1),
+        event_codes: distinct(&|p| p.event_code as usize, MAX_EVENT_CODE as usize + 1),
+        detection,
+        mismatches,
+        truth: truth_csv(&frames),
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn layout() -> impl Strategy<Value = PacketLayout> {
+        prop_oneof![Just(PacketLayout::Standard), Just(PacketLayout::Swapped)]
+    }
+
+    proptest! {
+        #[test]
+        fn test_packet_round_trip(
+            x: u8,
+            y: u8,
+            itot_code in 0..=MAX_ITOT_CODE,
+            event_code in 0..=MAX_EVENT_CODE,
+            layout in layout(),
+        ) {
+            let pixel = KnownPixel { x, y, itot_code, event_code };
+            let packet = encode_pixel_packet(&pixel, layout);
+            prop_assert_eq!(packet[0] & 0xF0, 0xA0);
+            prop_assert_eq!(packet[5] & 0x0F, HIT);
+            prop_assert_eq!(
+                DataProcessor::parse_pixel_packet(&packet, layout),
+                (y as u16 * 256 + x as u16, itot_code, event_code)
+            );
+        }
+    }
+
+    proptest! {
+        // every case decodes 20 frames twice and runs the layout detection
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn test_random_frames(seed: u64, layout in layout()) {
+            let options = ConformanceOptions {
+                frames: 20,
+                seed,
+                orientation: Orientation::Rot90,
+                ..Default::default()
+            };
+            let report = run(layout, &options).unwrap();
+            prop_assert!(report.mismatches.is_empty(), "{:?}", report.mismatches);
+            prop_assert_eq!(report.frames, 20);
+        }
+    }
+
+    #[test]
+    fn test_exhaustive_conformance() {
+        let packet = encode_pixel_packet(
+            &KnownPixel {
+                x: 52,
+                y: 10,
+                itot_code: 0x3C00,
+                event_code: 3,
+            },
+            PacketLayout::Standard,
+        );
+        assert_eq!(packet, [0xA3, 0x41, 0x2F, 0x00, 0x00, 0x3E]);
+        assert!(!is_ambiguous(&packet));
+        let packet = encode_pixel_packet(
+            &KnownPixel {
+                x: 0,
+                y: 0,
+                itot_code: 0,
+                event_code: 5,
+            },
+            PacketLayout::Swapped,
+        );
+        assert!(is_ambiguous(&packet));
+
+        for layout in [PacketLayout::Standard, PacketLayout::Swapped] {
+            let options = ConformanceOptions {
+                exhaustive: true,
+                orientation: Orientation::Pixet,
+                ..Default::default()
+            };
+            let report = run(layout, &options).unwrap();
+            assert!(
+                report.mismatches.is_empty(),
+                "{:?}",
+                &report.mismatches[..5]
+            );
+            assert_eq!((report.frames, report.pixels), (16, 1 << 16));
+            assert_eq!(report.addresses, 1 << 16);
+            assert_eq!(report.itot_codes, MAX_ITOT_CODE as usize + 1);
+            assert_eq!(report.event_codes, MAX_EVENT_CODE as usize + 1);
+        }
+    }
+}
@@ -1,25 +1,489 @@
-use std::io::{self, BufRead};
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
 
-use crate::clustering::{Cluster, Clusterer};
-use crate::tpx3lut::{LUT_ITOT, LUT_TOT, MAX_LUT_ITOT, MAX_LUT_TOT, WRONG_LUT_ITOT, WRONG_LUT_TOT};
-use crate::utils::{parse_time, print_buff_hex};
-use anyhow::{Result, bail};
-use hex;
+use crate::clock::{Clock, SystemClock};
+use crate::clustering::{self, Cluster, Clusterer, Labeler};
+use crate::energy_calibration::EnergyCalibration;
+use crate::error::OnewebError;
+use crate::firmware;
+use crate::hot_pixels::HotPixelMask;
+use crate::line_reader::{LineReader, Position};
+use crate::quality::{Issue, QualityLog};
+use crate::timing::{Stage, StageTimes};
+use crate::toa_calibration::ToaCalibration;
+use crate::tpx3lut::{Lut, MATRIX_SIZE, WRONG_LUT_ITOT, WRONG_LUT_TOT};
+use crate::utils::{format_buff_hex, format_time, parse_time};
+use crate::window::TimeWindow;
+use anyhow::{Context, Result, bail};
+
+/// Offset of the byte of the start of readout header (71 AF 00 00 <flags> <release>) holding
+/// the RLE flag; the flight frames of the sample data have 0x69 there, of unknown meaning
+pub const HEADER_FLAGS_OFFSET: usize = 4;
+/// Offset of the payload firmware release byte in the start of readout header
+pub const HEADER_RELEASE_OFFSET: usize = 5;
+/// Payload between the header and the terminator is run length encoded. Neither this flag
+/// nor the escape coding is defined by a payload document or a known firmware release: they
+/// are the format asked for the compressed downlink, so the payload is only decompressed
+/// with `--rle`, otherwise a flight frame setting the bit would be decoded into garbage
+pub const HEADER_FLAG_RLE: u8 = 0x80;
+/// RLE escape byte: `CC n v` repeats v n times, `CC 00` is a literal CC
+pub const RLE_ESCAPE: u8 = 0xCC;
+
+/// Handling of data lines that cannot be parsed or decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Stop the run with the error
+    Abort,
+    /// Warn and drop the line, frame assembly continues
+    #[default]
+    Skip,
+    /// Warn and keep the valid hex prefix of the payload
+    Salvage,
+}
+
+impl FromStr for ErrorPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "abort" => Ok(ErrorPolicy::Abort),
+            "skip" => Ok(ErrorPolicy::Skip),
+            "salvage" => Ok(ErrorPolicy::Salvage),
+            _ => bail!("expected abort, skip or salvage"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorPolicy::Abort => "abort",
+            ErrorPolicy::Skip => "skip",
+            ErrorPolicy::Salvage => "salvage",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Handling of pixel codes outside the ToT/iToT lookup tables (`WRONG_LUT_*` sentinels)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SentinelPolicy {
+    /// Keep the sentinel value, it looks like a large charge
+    #[default]
+    Keep,
+    /// Replace the sentinel by zero, the pixel counts as not hit
+    Zero,
+    /// Zero the pixel and report it in the invalid pixel columns of the outputs
+    Invalid,
+}
+
+impl FromStr for SentinelPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "keep" => Ok(SentinelPolicy::Keep),
+            "zero" => Ok(SentinelPolicy::Zero),
+            "invalid" => Ok(SentinelPolicy::Invalid),
+            _ => bail!("expected keep, zero or invalid"),
+        }
+    }
+}
+
+impl fmt::Display for SentinelPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SentinelPolicy::Keep => "keep",
+            SentinelPolicy::Zero => "zero",
+            SentinelPolicy::Invalid => "invalid",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One bit per matrix pixel
+#[derive(Debug, Clone, PartialEq)]
+pub struct PixelMask {
+    words: Vec<u64>,
+}
+
+impl Default for PixelMask {
+    fn default() -> Self {
+        PixelMask {
+            words: vec![0; MATRIX_SIZE / 64],
+        }
+    }
+}
+
+impl PixelMask {
+    pub fn set(&mut self, idx: usize) {
+        self.words[idx / 64] |= 1 << (idx % 64);
+    }
+
+    pub fn get(&self, idx: usize) -> bool {
+        self.words[idx / 64] & (1 << (idx % 64)) != 0
+    }
+
+    pub fn count(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Matrix indices of the set pixels in ascending order
+    pub fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..MATRIX_SIZE).filter(|&idx| self.get(idx))
+    }
+}
+
+/// Bit layout of the pixel packet address, differs between firmware releases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PacketLayout {
+    #[default]
+    Standard,
+    /// The four address nibbles in reverse order
+    Swapped,
+}
+
+impl FromStr for PacketLayout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "standard" => Ok(PacketLayout::Standard),
+            "swapped" => Ok(PacketLayout::Swapped),
+            _ => bail!("expected standard or swapped"),
+        }
+    }
+}
+
+impl fmt::Display for PacketLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PacketLayout::Standard => write!(f, "standard"),
+            PacketLayout::Swapped => write!(f, "swapped"),
+        }
+    }
+}
+
+/// Result of the packet layout autodetection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayoutDetection {
+    pub layout: PacketLayout,
+    /// Fraction of hit pixels with a hit neighbour for the standard and swapped layout
+    pub standard_score: f64,
+    pub swapped_score: f64,
+    pub frames: usize,
+    /// Firmware release byte of the first frame
+    pub release: Option<u8>,
+}
+
+/// Raw counter codes of the pixel packets, before the lookup tables
+#[derive(Debug, Clone, PartialEq)]
+pub struct PixelCodes {
+    pub itot: Vec<u16>,
+    pub event: Vec<u16>,
+    /// Pixels with a packet in the frame
+    pub hits: PixelMask,
+}
+
+impl Default for PixelCodes {
+    fn default() -> Self {
+        PixelCodes {
+            itot: vec![0; MATRIX_SIZE],
+            event: vec![0; MATRIX_SIZE],
+            hits: PixelMask::default(),
+        }
+    }
+}
+
+/// Assembled payload of a frame before the decoding
+#[derive(Debug, Clone, PartialEq)]
+pub struct Readout {
+    pub data: Vec<u8>,
+    /// Time of the line starting and of the line completing the frame
+    pub timestamp: f64,
+    pub end_timestamp: f64,
+    /// Closed by the start of readout of the next frame
+    pub truncated: bool,
+    /// Position of the data file after the readout, see [`Frame::resume_at`]
+    pub resume_at: Option<Position>,
+}
+
+/// Bytes of the payload of a frame (after the decompression) by how the decoder used them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ByteAccount {
+    pub payload: usize,
+    /// Bytes of the decoded pixel packets
+    pub packets: usize,
+    /// Frame headers, extra headers and the end of readout
+    pub headers: usize,
+    /// Unexpected data skipped while searching for the next packet and trailing bytes
+    pub discarded: usize,
+}
+
+impl ByteAccount {
+    /// Percentage of the payload decoded as pixel packets, 100 for an empty payload
+    pub fn efficiency(&self) -> f64 {
+        if self.payload == 0 {
+            return 100.0;
+        }
+        100.0 * self.packets as f64 / self.payload as f64
+    }
+}
+
+/// Pixel values of a frame decoded with a lookup table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Planes {
+    pub itot: Vec<u16>,
+    pub event: Vec<u16>,
+    /// Pixels with a code outside the lookup tables
+    pub invalid: PixelMask,
+    /// Pixels with the iToT code at the table maximum (saturated counter)
+    pub saturated: PixelMask,
+}
+
+impl PixelCodes {
+    /// Applies the tables to the hit pixels, codes outside the tables are handled by the policy
+    pub fn decode(&self, lut: &Lut, policy: SentinelPolicy) -> Planes {
+        let mut planes = Planes {
+            itot: vec![0; MATRIX_SIZE],
+            event: vec![0; MATRIX_SIZE],
+            invalid: PixelMask::default(),
+            saturated: PixelMask::default(),
+        };
+        let itot_max = lut.itot_max();
+        for idx in self.hits.indices() {
+            let itot = lut.itot(self.itot[idx]);
+            if itot == itot_max {
+                planes.saturated.set(idx);
+            }
+            let event = lut.tot(self.event[idx]);
+            let sentinel = itot == WRONG_LUT_ITOT || event == WRONG_LUT_TOT;
+            let (itot, event) = match policy {
+                _ if !sentinel => (itot, event),
+                SentinelPolicy::Keep => (itot, event),
+                SentinelPolicy::Zero => (
+                    if itot == WRONG_LUT_ITOT { 0 } else { itot },
+                    if event == WRONG_LUT_TOT { 0 } else { event },
+                ),
+                SentinelPolicy::Invalid => (0, 0),
+            };
+            if sentinel {
+                planes.invalid.set(idx);
+            }
+            planes.itot[idx] = itot;
+            planes.event[idx] = event;
+        }
+        planes
+    }
+}
 
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct Frame {
-    pub itot: Vec<u16>,
-    pub event: Vec<u16>,
+    /// Assembled payload the frame was decoded from
+    pub raw: Vec<u8>,
+    pub codes: PixelCodes,
+    /// Use of the payload bytes by the decoder
+    pub bytes: ByteAccount,
+    /// Closed by the start of readout of the next frame, its end of readout was lost
+    pub truncated: bool,
+    /// Tables and sentinel policy the planes are decoded with
+    pub lut: Arc<Lut>,
+    pub sentinel_policy: SentinelPolicy,
+    /// Per-pixel correction of the second pixel values when they are ToA
+    pub toa_calibration: Option<Arc<ToaCalibration>>,
+    /// Per-pixel conversion of the iToT values to energies
+    pub energy_calibration: Option<Arc<EnergyCalibration>>,
     pub clusters: Vec<Cluster>,
+    /// Frame time, the time of the line starting the frame until the processor applies
+    /// the frame time source
     pub timestamp: f64,
+    /// Time of the line completing the frame
+    pub end_timestamp: f64,
+    /// Position of the data file after the line completing the frame, where the decoding of
+    /// the next frames can resume; None when the next frame starts in that line
+    pub resume_at: Option<Position>,
+    /// Mean noise threshold in iToT counts applied to the frame, None without a noise model
+    pub noise_threshold: Option<f64>,
+    /// Pixels removed by the noise threshold
+    pub noise_pixels: usize,
+    /// Adaptive threshold in iToT counts applied to the frame, None when not applied
+    pub adaptive_threshold: Option<f64>,
+    /// Pixels removed by the adaptive threshold
+    pub adaptive_pixels: usize,
+    /// Hit pixels of the pixel mask, removed by the decoder
+    pub masked: PixelMask,
+    /// Planes decoded on first use
+    planes: OnceLock<Planes>,
+}
+
+#[allow(dead_code)]
+impl Frame {
+    /// Firmware release byte of the start of readout header, None without a header
+    pub fn release(&self) -> Option<u8> {
+        self.raw
+            .starts_with(&[0x71, 0xAF])
+            .then(|| self.raw.get(HEADER_RELEASE_OFFSET).copied())
+            .flatten()
+    }
+
+    pub fn new(raw: Vec<u8>, codes: PixelCodes, lut: Arc<Lut>, timestamp: f64) -> Self {
+        Frame {
+            raw,
+            codes,
+            bytes: ByteAccount::default(),
+            truncated: false,
+            lut,
+            sentinel_policy: SentinelPolicy::default(),
+            toa_calibration: None,
+            energy_calibration: None,
+            clusters: Vec::new(),
+            timestamp,
+            end_timestamp: timestamp,
+            resume_at: None,
+            noise_threshold: None,
+            noise_pixels: 0,
+            adaptive_threshold: None,
+            adaptive_pixels: 0,
+            masked: PixelMask::default(),
+            planes: OnceLock::new(),
+        }
+    }
+
+    /// Frame of already decoded values (e.g. an external matrix), it carries no counter codes;
+    /// pixels at the iToT maximum of the flight tables are flagged as saturated
+    pub fn from_planes(itot: Vec<u16>, event: Vec<u16>, timestamp: f64) -> Self {
+        let lut = Lut::builtin();
+        let mut saturated = PixelMask::default();
+        let itot_max = lut.itot_max();
+        for (idx, &value) in itot.iter().enumerate() {
+            if value == itot_max {
+                saturated.set(idx);
+            }
+        }
+        let frame = Frame::new(Vec::new(), PixelCodes::default(), Arc::new(lut), timestamp);
+        let _ = frame.planes.set(Planes {
+            itot,
+            event,
+            invalid: PixelMask::default(),
+            saturated,
+        });
+        frame
+    }
+
+    pub fn planes(&self) -> &Planes {
+        self.planes.get_or_init(|| {
+            let mut planes = self.codes.decode(&self.lut, self.sentinel_policy);
+            if let Some(calibration) = &self.toa_calibration {
+                for idx in self.codes.hits.indices() {
+                    if !planes.invalid.get(idx) {
+                        planes.event[idx] = calibration.apply_count(idx, planes.event[idx]);
+                    }
+                }
+            }
+            planes
+        })
+    }
+
+    pub fn itot(&self) -> &[u16] {
+        &self.planes().itot
+    }
+
+    pub fn event(&self) -> &[u16] {
+        &self.planes().event
+    }
+
+    /// Pixels with a code outside the lookup tables
+    pub fn invalid(&self) -> &PixelMask {
+        &self.planes().invalid
+    }
+
+    /// Pixels with a saturated iToT counter
+    pub fn saturated(&self) -> &PixelMask {
+        &self.planes().saturated
+    }
+
+    /// Zeroes the hit pixels the predicate selects from their index and iToT and returns
+    /// their number, the clusters are not updated
+    pub fn suppress_pixels(&mut self, mut noise: impl FnMut(usize, u16) -> bool) -> usize {
+        self.planes();
+        let planes = self.planes.get_mut().expect("planes decoded");
+        let mut removed = 0;
+        for idx in 0..MATRIX_SIZE {
+            if planes.itot[idx] != 0 && noise(idx, planes.itot[idx]) {
+                planes.itot[idx] = 0;
+                planes.event[idx] = 0;
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Planes decoded with other tables, e.g. to compare calibrations, the frame is unchanged
+    pub fn decode_with(&self, lut: &Lut) -> Planes {
+        self.codes.decode(lut, self.sentinel_policy)
+    }
 }
 
 pub struct DataProcessor {
     pub frame_data: Vec<u8>,
     pub skipped_lines: Vec<String>,
     pub timestamp: f64,
+    pub error_policy: ErrorPolicy,
+    pub layout: PacketLayout,
+    /// Frames of a release with a known packet layout are decoded with it, `layout` is
+    /// only used for the other frames
+    pub release_layouts: bool,
+    /// Payloads with the RLE header flag are decompressed, see `HEADER_FLAG_RLE`
+    pub rle: bool,
+    pub sentinel_policy: SentinelPolicy,
+    /// Per-pixel ToA correction of the decoded frames
+    pub toa_calibration: Option<Arc<ToaCalibration>>,
+    /// Per-pixel energy calibration of the decoded frames
+    pub energy_calibration: Option<Arc<EnergyCalibration>>,
+    /// Tables of the decoded frames
+    pub lut: Arc<Lut>,
+    /// Clusters separated by at most this many pixels are merged
+    pub merge_distance: Option<u8>,
+    /// Pixels dropped from the decoded frames
+    pub mask: Option<Arc<HotPixelMask>>,
+    /// Number of data lines that could not be parsed or decoded
+    pub bad_lines: usize,
+    /// Frames closed by the start of readout of the next frame
+    pub truncated_frames: usize,
+    pub timing: StageTimes,
+    /// Clock of the stage timers
+    pub clock: Arc<dyn Clock>,
+    /// Component labeling on the GPU, clustering on the CPU when None
+    pub labeler: Option<Arc<dyn Labeler>>,
+    /// Bad lines and lost frame syncs with their time
+    pub quality: QualityLog,
+    /// Frames are only assembled from the lines near the window
+    pub window: TimeWindow,
+    /// Data lines outside the window skipped without decoding
+    pub window_lines: usize,
+    /// Frames still assembled this many s after their start of readout are aborted as corrupt
+    pub max_assembly_time: Option<f64>,
+    /// Frames aborted by the max assembly time
+    pub overlong_frames: usize,
+    /// Time of the last decoded line
+    line_time: f64,
     seq_offset: usize,
+    /// The assembled frame is closed by an embedded start of readout
+    truncated: bool,
+    /// Line data from an embedded start of readout on, it starts the next frame
+    pending: Option<(f64, Vec<u8>, String)>,
+    /// Data file position after the assembled frame
+    resume_at: Option<Position>,
+}
+
+impl Default for DataProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[allow(dead_code)]
@@ -29,21 +493,72 @@ impl DataProcessor {
             frame_data: Vec::new(),
             skipped_lines: Vec::new(),
             timestamp: 0.0,
+            error_policy: ErrorPolicy::default(),
+            layout: PacketLayout::default(),
+            release_layouts: false,
+            rle: false,
+            sentinel_policy: SentinelPolicy::default(),
+            toa_calibration: None,
+            energy_calibration: None,
+            lut: Arc::new(Lut::builtin()),
+            merge_distance: None,
+            mask: None,
+            bad_lines: 0,
+            truncated_frames: 0,
+            timing: StageTimes::default(),
+            clock: Arc::new(SystemClock::default()),
+            labeler: None,
+            quality: QualityLog::default(),
+            window: TimeWindow::default(),
+            window_lines: 0,
+            max_assembly_time: None,
+            overlong_frames: 0,
+            line_time: 0.0,
             seq_offset: 0,
+            truncated: false,
+            pending: None,
+            resume_at: None,
         }
     }
 
-    fn parse_line(line: &str) -> Result<(f64, Vec<u8>)> {
+    pub(crate) fn parse_line(line: &str) -> Result<(f64, Vec<u8>)> {
         let parts: Vec<&str> = line.trim().split(',').collect();
         if parts.len() != 2 {
-            bail!("Invalid line format");
+            bail!(
+                "invalid line format, expected 2 columns, got {}",
+                parts.len()
+            );
         }
-        Ok((parse_time(parts[0])?, hex::decode(parts[1])?))
+        let timestamp = parse_time(parts[0])
+            .with_context(|| format!("cannot parse TIMESTAMP '{}'", parts[0]))?;
+        let data = hex::decode(parts[1]).context("cannot decode DATA")?;
+        Ok((timestamp, data))
+    }
+
+    /// Timestamp and the longest even length hex prefix of the payload of a damaged line
+    fn salvage_line(line: &str) -> Option<(f64, Vec<u8>)> {
+        let (time, payload) = line.trim().split_once(',')?;
+        let timestamp = parse_time(time).ok()?;
+        let payload = payload.trim();
+        let len = payload
+            .find(|c: char| !c.is_ascii_hexdigit())
+            .unwrap_or(payload.len());
+        let data = hex::decode(&payload[..len - len % 2]).ok()?;
+        if data.is_empty() {
+            return None;
+        }
+        Some((timestamp, data))
+    }
+
+    /// Time of a data line without decoding its payload
+    fn line_timestamp(line: &str) -> Option<f64> {
+        let (timestamp, _) = line.split_once(',')?;
+        parse_time(timestamp.trim()).ok()
     }
 
     fn find_sequence_in_data(seq: &[u8], data: &[u8], seq_offset: &mut usize) -> Option<usize> {
-        for i in 0..data.len() {
-            if seq[*seq_offset] == data[i] {
+        for (i, byte) in data.iter().enumerate() {
+            if seq[*seq_offset] == *byte {
                 *seq_offset += 1;
                 if *seq_offset == seq.len() {
                     return Some(i);
@@ -61,49 +576,186 @@ impl DataProcessor {
         self.skipped_lines.clear();
         self.timestamp = 0.0;
         self.seq_offset = 0;
+        self.truncated = false;
+    }
+
+    /// Index of the first byte of a start of readout sequence 71 AF 00 00 in data
+    fn find_frame_header(data: &[u8]) -> Option<usize> {
+        data.windows(4).position(|w| w == [0x71, 0xAF, 0x00, 0x00])
     }
 
     pub fn process_next_line(&mut self, line: &str) -> Result<bool> {
-        let (timestamp, data) = Self::parse_line(line)?;
+        let start = self.clock.now();
+        let parsed = Self::parse_line(line);
+        self.timing.add(Stage::HexDecode, self.clock.elapsed(start));
+        let (timestamp, data) = parsed?;
+        Ok(self.process_data(timestamp, data, line))
+    }
+
+    /// Adds the decoded payload of a line to the frame, returns true when the frame is complete
+    fn process_data(&mut self, timestamp: f64, data: Vec<u8>, line: &str) -> bool {
+        let start = self.clock.now();
+        self.line_time = timestamp;
+        let complete = self.assemble(timestamp, data, line);
+        self.timing.add(Stage::Assembly, self.clock.elapsed(start));
+        complete
+    }
+
+    /// Drops the frame being assembled when the data at the timestamp comes later than the
+    /// max assembly time after its start of readout, a readout never spans that long
+    fn abort_overlong(&mut self, timestamp: f64) {
+        let duration = timestamp - self.timestamp;
+        if self.frame_data.is_empty() || self.max_assembly_time.is_none_or(|max| duration <= max) {
+            return;
+        }
+        tracing::warn!(
+            "frame started at {} still assembled after {:.1} s, aborted as corrupt",
+            format_time(self.timestamp),
+            duration
+        );
+        self.overlong_frames += 1;
+        self.quality
+            .record(Issue::OverlongFrame, self.timestamp, duration);
+        self.frame_data.clear();
+        self.seq_offset = 0;
+        self.truncated = false;
+    }
 
+    fn assemble(&mut self, timestamp: f64, data: Vec<u8>, line: &str) -> bool {
+        self.abort_overlong(timestamp);
         if self.frame_data.is_empty() {
             if let Some(index) =
                 Self::find_sequence_in_data(&[0x71, 0xAF, 0x00, 0x00], &data, &mut self.seq_offset)
             {
                 self.seq_offset = 0;
                 self.frame_data.clear();
-                self.frame_data.extend_from_slice(&vec![0x71, 0xAF, 0x00]);
+                self.frame_data.extend_from_slice(&[0x71, 0xAF, 0x00]);
                 self.frame_data.extend_from_slice(&data[index..]);
                 self.timestamp = timestamp;
             } else {
+                if self.skipped_lines.is_empty() {
+                    self.quality.record(Issue::SyncLost, timestamp, 0.0);
+                }
                 self.skipped_lines.push(line.to_string());
             }
-            return Ok(false);
+            return false;
         }
 
-        if let Some(index) =
-            Self::find_sequence_in_data(&[0x71, 0xA0, 0x00, 0x00], &data, &mut self.seq_offset)
+        let end = self.find_frame_end(&data);
+        if let Some(header) =
+            Self::find_frame_header(&data).filter(|&header| end.is_none_or(|end| header < end))
         {
-            self.seq_offset = 0;
+            // the end of readout was lost, the header starts the next frame
+            self.frame_data.extend_from_slice(&data[..header]);
+            self.truncated = true;
+            self.pending = Some((timestamp, data[header..].to_vec(), line.to_string()));
+            return true;
+        }
+        if let Some(index) = end {
             self.frame_data.extend_from_slice(&data[..=index]);
-            return Ok(true);
-        } else if let Some(index) =
-            Self::find_sequence_in_data(&[0x00, 0x00, 0x00, 0x00], &data, &mut self.seq_offset)
-        {
-            self.seq_offset = 0;
+            return true;
+        }
+        self.frame_data.extend_from_slice(&data);
+        false
+    }
+
+    /// Index of the last byte of the end of readout (71 A0 00 00 or 00 00 00 00) in data
+    fn find_frame_end(&mut self, data: &[u8]) -> Option<usize> {
+        let index =
+            Self::find_sequence_in_data(&[0x71, 0xA0, 0x00, 0x00], data, &mut self.seq_offset)
+                .or_else(|| {
+                    Self::find_sequence_in_data(
+                        &[0x00, 0x00, 0x00, 0x00],
+                        data,
+                        &mut self.seq_offset,
+                    )
+                })?;
+        self.seq_offset = 0;
+        Some(index)
+    }
+
+    /// Feeds raw packet bytes received at the timestamp directly to the frame assembler,
+    /// bypassing the CSV/hex input, and returns the frames completed by the chunk
+    pub fn push_bytes(&mut self, data: &[u8], timestamp: f64) -> Vec<Frame> {
+        self.line_time = timestamp;
+        let mut frames = Vec::new();
+        let mut data = data;
+        self.abort_overlong(timestamp);
+        while !data.is_empty() {
+            if self.frame_data.is_empty() {
+                let Some(index) = Self::find_sequence_in_data(
+                    &[0x71, 0xAF, 0x00, 0x00],
+                    data,
+                    &mut self.seq_offset,
+                ) else {
+                    break;
+                };
+                self.seq_offset = 0;
+                self.frame_data.extend_from_slice(&[0x71, 0xAF, 0x00, 0x00]);
+                self.timestamp = timestamp;
+                data = &data[index + 1..];
+                continue;
+            }
+
+            let end = self.find_frame_end(data);
+            if let Some(header) =
+                Self::find_frame_header(data).filter(|&header| end.is_none_or(|end| header < end))
+            {
+                // the end of readout was lost, the header starts the next frame
+                self.frame_data.extend_from_slice(&data[..header]);
+                self.truncated = true;
+                frames.push(self.finish_frame());
+                data = &data[header..];
+                continue;
+            }
+            let Some(index) = end else {
+                self.frame_data.extend_from_slice(data);
+                break;
+            };
             self.frame_data.extend_from_slice(&data[..=index]);
-            return Ok(true);
-        } else {
-            self.frame_data.extend_from_slice(&data);
+            frames.push(self.finish_frame());
+            data = &data[index + 1..];
         }
+        frames
+    }
 
-        Ok(false)
+    /// Decodes the assembled frame and resets the assembler for the next one
+    fn finish_frame(&mut self) -> Frame {
+        let start = self.clock.now();
+        let mut frame = self.extract_frame();
+        frame.end_timestamp = self.line_time;
+        frame.planes();
+        self.timing
+            .add(Stage::PixelDecode, self.clock.elapsed(start));
+        let start = self.clock.now();
+        self.clusterize_frame(&mut frame);
+        self.timing
+            .add(Stage::Clustering, self.clock.elapsed(start));
+        self.timing.frames += 1;
+        if self.truncated {
+            frame.truncated = true;
+            self.truncated_frames += 1;
+        }
+        frame.raw = std::mem::take(&mut self.frame_data);
+        frame.resume_at = self.resume_at.take();
+        self.clear_data();
+        frame
     }
 
-    fn parse_pixel_packet(data: &[u8]) -> (u16, u16, u16) {
+    /// Pixel index and the raw iToT (ToA counter) and event codes of the packet
+    pub(crate) fn parse_pixel_packet(data: &[u8], layout: PacketLayout) -> (u16, u16, u16) {
         let address = (((data[0] as u16) & 0x0F) << 12)
             | ((data[1] as u16) << 4)
             | ((data[2] as u16 >> 4) & 0x0F);
+        let address = match layout {
+            PacketLayout::Standard => address,
+            PacketLayout::Swapped => {
+                ((address & 0x000F) << 12)
+                    | ((address & 0x00F0) << 4)
+                    | ((address & 0x0F00) >> 4)
+                    | ((address & 0xF000) >> 12)
+            }
+        };
         let toa: u16 = ((data[2] as u16 & 0x0F) << 10)
             | ((data[3] as u16) << 2)
             | ((data[4] as u16 >> 6) & 0x03);
@@ -116,114 +768,412 @@ impl DataProcessor {
         let y = sp * 4 + (pix % 4);
         let idx = y * 256 + x;
 
-        let itot = if toa >= 1 && toa < MAX_LUT_ITOT as u16 {
-            LUT_ITOT[toa as usize]
-        } else {
-            WRONG_LUT_ITOT
-        };
+        (idx, toa, event)
+    }
 
-        let event = if event >= 1 && event < MAX_LUT_TOT as u16 {
-            LUT_TOT[event as usize]
-        } else {
-            WRONG_LUT_TOT
-        };
+    /// Expands run length encoded data, see `RLE_ESCAPE`
+    fn decompress_rle(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len() * 2);
+        let mut i = 0;
+        while i < data.len() {
+            if data[i] != RLE_ESCAPE {
+                out.push(data[i]);
+                i += 1;
+                continue;
+            }
+            match (data.get(i + 1), data.get(i + 2)) {
+                (Some(0), _) => {
+                    out.push(RLE_ESCAPE);
+                    i += 2;
+                }
+                (Some(&count), Some(&value)) => {
+                    out.extend(std::iter::repeat_n(value, count as usize));
+                    i += 3;
+                }
+                _ => {
+                    // truncated escape sequence, keep as is
+                    out.extend_from_slice(&data[i..]);
+                    break;
+                }
+            }
+        }
+        out
+    }
 
-        (idx, itot, event)
+    /// Frame data with the payload decompressed when RLE is enabled and the header flags
+    /// announce it
+    fn payload(&self) -> Cow<'_, [u8]> {
+        let data = &self.frame_data;
+        let compressed = self.rle
+            && data.len() > HEADER_FLAGS_OFFSET
+            && data[0] == 0x71
+            && data[1] == 0xAF
+            && data[HEADER_FLAGS_OFFSET] & HEADER_FLAG_RLE != 0;
+        if !compressed || data.len() < 6 {
+            return Cow::Borrowed(data);
+        }
+
+        // the terminator is sent uncompressed
+        let end = data
+            .windows(4)
+            .skip(6)
+            .position(|w| w == [0x71, 0xA0, 0x00, 0x00])
+            .map(|pos| pos + 6)
+            .unwrap_or(data.len());
+        let mut out = data[..6].to_vec();
+        out.extend(Self::decompress_rle(&data[6..end]));
+        out.extend_from_slice(&data[end..]);
+        Cow::Owned(out)
+    }
+
+    /// Packet layout of the frame data: the one of its firmware release when the release
+    /// table gives it, otherwise the configured layout
+    fn frame_layout(&self, data: &[u8]) -> PacketLayout {
+        if !self.release_layouts || !data.starts_with(&[0x71, 0xAF]) {
+            return self.layout;
+        }
+        data.get(HEADER_RELEASE_OFFSET)
+            .and_then(|&byte| firmware::layout(byte))
+            .unwrap_or(self.layout)
     }
 
     pub fn extract_frame(&self) -> Frame {
-        let mut fr_itot = vec![0; 256 * 256];
-        let mut fr_event = vec![0; 256 * 256];
+        let mut codes = PixelCodes::default();
+        let mut masked = PixelMask::default();
         let mut bad_data: Vec<u8> = Vec::new();
         let mut bad_data_offset: usize = 0;
 
+        let data = self.payload();
+        let layout = self.frame_layout(&data);
+        let mut bytes = ByteAccount {
+            payload: data.len(),
+            ..Default::default()
+        };
         let mut offset = 0;
-        while offset < self.frame_data.len() {
-            if self.frame_data.len() - offset < 6 {
+        while offset < data.len() {
+            if data.len() - offset < 6 {
                 // not enough data for a pixel packet
                 break;
             }
 
-            if self.frame_data[offset] == 0x71 && self.frame_data[offset + 1] == 0xAF {
+            if data[offset] == 0x71 && data[offset + 1] == 0xAF {
+                bytes.headers += 6;
                 offset += 6;
                 continue;
             }
 
-            if self.frame_data[offset] == 0x71 && self.frame_data[offset + 1] == 0xA0 {
+            if data[offset] == 0x71 && data[offset + 1] == 0xA0 {
                 // end of readout
                 break;
             }
 
-            if self.frame_data[offset] == 0x14 && self.frame_data[offset + 5] == 0x02 {
+            if data[offset] == 0x14 && data[offset + 5] == 0x02 {
                 // skip extra header
-                // println!(
-                //     "skip extra header: {:02X}, offset: {}",
-                //     self.frame_data[offset], offset
-                // );
+                tracing::trace!(
+                    "skip extra header: {:02X}, offset: {}",
+                    data[offset],
+                    offset
+                );
+                bytes.headers += 8.min(data.len() - offset);
                 offset += 8;
                 continue;
             }
 
-            while offset + 6 < self.frame_data.len()
-                && self.frame_data[offset] & 0xF0 != 0xA0
-                && self.frame_data[offset + 5] != 0xEE
+            while offset + 6 < data.len() && data[offset] & 0xF0 != 0xA0 && data[offset + 5] != 0xEE
             {
-                bad_data.push(self.frame_data[offset]);
+                bad_data.push(data[offset]);
                 if bad_data_offset == 0 {
                     bad_data_offset = offset;
                 }
                 offset += 1;
             }
 
-            if bad_data.len() > 0 {
-                print!("unexpected data [{}]: ", bad_data_offset);
-                print_buff_hex(&bad_data);
+            if !bad_data.is_empty() {
+                bytes.discarded += bad_data.len();
+                tracing::warn!(
+                    offset = bad_data_offset,
+                    bytes = bad_data.len(),
+                    "unexpected data: {}",
+                    format_buff_hex(&bad_data)
+                );
                 bad_data.clear();
                 bad_data_offset = 0;
                 continue;
             }
 
-            let (idx, itot, event) = Self::parse_pixel_packet(&self.frame_data[offset..]);
-            // println!("idx: {}, itot: {}, event: {}", idx, itot, event);
-            fr_itot[idx as usize] = itot;
-            fr_event[idx as usize] = event;
+            let (idx, itot, event) = Self::parse_pixel_packet(&data[offset..], layout);
+            tracing::trace!("idx: {}, itot: {}, event: {}", idx, itot, event);
+            if self
+                .mask
+                .as_ref()
+                .is_some_and(|mask| mask.pixels.get(idx as usize))
+            {
+                masked.set(idx as usize);
+            } else {
+                codes.itot[idx as usize] = itot;
+                codes.event[idx as usize] = event;
+                codes.hits.set(idx as usize);
+            }
 
+            bytes.packets += 6;
             offset += 6;
         }
+        let rest = &data[offset.min(data.len())..];
+        let trailer = if rest.starts_with(&[0x71, 0xA0]) {
+            rest.len()
+        } else if rest.ends_with(&[0; 4]) {
+            4
+        } else {
+            0
+        };
+        bytes.headers += trailer;
+        bytes.discarded += rest.len() - trailer;
 
-        Frame {
-            itot: fr_itot,
-            event: fr_event,
-            clusters: Vec::new(),
-            timestamp: self.timestamp,
-        }
+        let mut frame = Frame::new(Vec::new(), codes, self.lut.clone(), self.timestamp);
+        frame.bytes = bytes;
+        frame.masked = masked;
+        frame.sentinel_policy = self.sentinel_policy;
+        frame.toa_calibration = self.toa_calibration.clone();
+        frame.energy_calibration = self.energy_calibration.clone();
+        frame
     }
 
     pub fn clusterize_frame(&self, frame: &mut Frame) {
         let clusterer = Clusterer::new();
-        frame.clusters = clusterer.search_frame(&frame.itot, &frame.event, 256, 256);
+        let mut clusters = match &self.labeler {
+            Some(labeler) => {
+                let labels = labeler.label(frame.itot()).unwrap_or_else(|e| {
+                    tracing::warn!("GPU labeling failed ({:#}), frame labeled on the CPU", e);
+                    let clusters = clusterer.search_frame(frame.itot(), frame.event(), 256, 256);
+                    clustering::labels_from_clusters(&clusters, 256, MATRIX_SIZE)
+                });
+                clustering::clusters_from_labels(frame.itot(), frame.event(), &labels, 256)
+            }
+            None => clusterer.search_frame(frame.itot(), frame.event(), 256, 256),
+        };
+        let saturated = frame.saturated();
+        for pixel in clusters.iter_mut().flat_map(|c| c.pixels.iter_mut()) {
+            pixel.saturated = saturated.get(pixel.y as usize * 256 + pixel.x as usize);
+        }
+        frame.clusters = clusters;
+        if let Some(distance) = self.merge_distance {
+            frame.clusters =
+                clustering::merge_clusters(std::mem::take(&mut frame.clusters), distance);
+        }
     }
 
-    pub fn get_next_frame<R>(&mut self, reader: &mut io::BufReader<R>) -> Result<Frame>
+    pub fn get_next_frame<R>(&mut self, reader: &mut LineReader<R>) -> Result<Frame>
     where
         R: io::Read,
     {
-        for line in reader.lines() {
-            let line = line?;
+        self.assemble_next(reader)?;
+        Ok(self.finish_frame())
+    }
+
+    /// Assembles the next complete readout of the reader, without decoding it
+    pub fn next_readout<R>(&mut self, reader: &mut LineReader<R>) -> Result<Readout>
+    where
+        R: io::Read,
+    {
+        self.assemble_next(reader)?;
+        let readout = Readout {
+            data: std::mem::take(&mut self.frame_data),
+            timestamp: self.timestamp,
+            end_timestamp: self.line_time,
+            truncated: self.truncated,
+            resume_at: self.resume_at.take(),
+        };
+        self.clear_data();
+        Ok(readout)
+    }
+
+    /// Decodes and clusters a readout of another processor
+    pub fn decode_readout(&mut self, readout: Readout) -> Frame {
+        self.frame_data = readout.data;
+        self.timestamp = readout.timestamp;
+        self.line_time = readout.end_timestamp;
+        self.truncated = readout.truncated;
+        self.resume_at = readout.resume_at;
+        self.finish_frame()
+    }
+
+    /// Processor with the same decoding settings and no state
+    pub fn worker(&self) -> DataProcessor {
+        DataProcessor {
+            error_policy: self.error_policy,
+            layout: self.layout,
+            release_layouts: self.release_layouts,
+            rle: self.rle,
+            sentinel_policy: self.sentinel_policy,
+            toa_calibration: self.toa_calibration.clone(),
+            energy_calibration: self.energy_calibration.clone(),
+            lut: self.lut.clone(),
+            merge_distance: self.merge_distance,
+            mask: self.mask.clone(),
+            clock: self.clock.clone(),
+            labeler: self.labeler.clone(),
+            window: self.window,
+            max_assembly_time: self.max_assembly_time,
+            ..DataProcessor::new()
+        }
+    }
+
+    /// Adds the counters, quality events and stage times of a worker
+    pub fn merge_stats(&mut self, worker: &DataProcessor) {
+        self.bad_lines += worker.bad_lines;
+        self.truncated_frames += worker.truncated_frames;
+        self.window_lines += worker.window_lines;
+        self.overlong_frames += worker.overlong_frames;
+        self.quality.merge(&worker.quality);
+        self.timing.merge(&worker.timing);
+    }
+
+    /// Reads lines until a frame is complete in the frame data
+    fn assemble_next<R>(&mut self, reader: &mut LineReader<R>) -> Result<()>
+    where
+        R: io::Read,
+    {
+        loop {
+            if let Some((timestamp, data, line)) = self.pending.take() {
+                if self.process_data(timestamp, data, &line) {
+                    self.resume_at = self.pending.is_none().then(|| reader.position());
+                    return Ok(());
+                }
+                continue;
+            }
+            let start = self.clock.now();
+            let line = reader.next_line()?;
+            self.timing.add(Stage::Read, self.clock.elapsed(start));
+            let Some(line) = line else {
+                break;
+            };
+            self.timing.input_bytes += line.len() as u64 + 1;
             let line = line.trim();
-            if line.starts_with("TIMESTAMP") {
+            if line.is_empty() || line.starts_with("TIMESTAMP") {
                 continue; // Skip header line
             }
+            if self.frame_data.is_empty()
+                && !self.window.is_none()
+                && let Some(timestamp) = Self::line_timestamp(line)
+            {
+                if self.window.line_after(timestamp) {
+                    break;
+                }
+                if self.window.line_before(timestamp) {
+                    self.window_lines += 1;
+                    self.seq_offset = 0;
+                    continue;
+                }
+            }
 
-            let res = self.process_next_line(&line)?;
+            let res = match self.process_next_line(line) {
+                Ok(res) => res,
+                Err(e) if self.error_policy == ErrorPolicy::Abort => {
+                    return Err(reader.error_at(e));
+                }
+                Err(e) => {
+                    self.bad_lines += 1;
+                    self.quality.record(Issue::BadLine, self.line_time, 0.0);
+                    let salvaged = match self.error_policy {
+                        ErrorPolicy::Salvage => Self::salvage_line(line),
+                        _ => None,
+                    };
+                    match salvaged {
+                        Some((timestamp, data)) => {
+                            tracing::warn!(
+                                "{:#}, salvaged {} bytes",
+                                reader.error_at(e),
+                                data.len()
+                            );
+                            self.process_data(timestamp, data, line)
+                        }
+                        None => {
+                            tracing::warn!("{:#}, line skipped", reader.error_at(e));
+                            false
+                        }
+                    }
+                }
+            };
             if res {
-                let mut frame = self.extract_frame();
-                self.clusterize_frame(&mut frame);
-                self.clear_data();
-                return Ok(frame);
+                self.resume_at = self.pending.is_none().then(|| reader.position());
+                return Ok(());
+            }
+        }
+        Err(OnewebError::EndOfData.into())
+    }
+
+    /// Number of hit pixels and of those having at least one hit 8-neighbour
+    fn neighbour_counts(itot: &[u16]) -> (usize, usize) {
+        let (mut hits, mut with_neighbour) = (0, 0);
+        for (idx, &value) in itot.iter().enumerate() {
+            if value == 0 {
+                continue;
+            }
+            hits += 1;
+            let (x, y) = ((idx % 256) as i32, (idx / 256) as i32);
+            let has_neighbour = (-1..=1).any(|dy| {
+                (-1..=1).any(|dx| {
+                    let (nx, ny) = (x + dx, y + dy);
+                    (dx, dy) != (0, 0)
+                        && (0..256).contains(&nx)
+                        && (0..256).contains(&ny)
+                        && itot[(ny * 256 + nx) as usize] != 0
+                })
+            });
+            if has_neighbour {
+                with_neighbour += 1;
+            }
+        }
+        (hits, with_neighbour)
+    }
+
+    /// Detects the packet layout from up to `max_frames` frames: particle tracks decoded
+    /// with the wrong address layout fall apart into isolated pixels
+    pub fn detect_layout<R>(reader: &mut LineReader<R>, max_frames: usize) -> LayoutDetection
+    where
+        R: io::Read,
+    {
+        let mut processor = DataProcessor::new();
+        let mut counts = [(0, 0); 2];
+        let mut frames = 0;
+        let mut release = None;
+        while frames < max_frames {
+            let Ok(frame) = processor.get_next_frame(reader) else {
+                break;
+            };
+            frames += 1;
+            release = release.or(frame.release());
+            for (i, layout) in [PacketLayout::Standard, PacketLayout::Swapped]
+                .into_iter()
+                .enumerate()
+            {
+                let mut decoder = DataProcessor::new();
+                decoder.layout = layout;
+                decoder.frame_data = frame.raw.clone();
+                let (hits, with_neighbour) = Self::neighbour_counts(decoder.extract_frame().itot());
+                counts[i].0 += hits;
+                counts[i].1 += with_neighbour;
             }
         }
-        bail!("No more data available");
+        let score = |(hits, with_neighbour): (usize, usize)| {
+            if hits > 0 {
+                with_neighbour as f64 / hits as f64
+            } else {
+                0.0
+            }
+        };
+        let (standard_score, swapped_score) = (score(counts[0]), score(counts[1]));
+        LayoutDetection {
+            layout: if swapped_score > standard_score {
+                PacketLayout::Swapped
+            } else {
+                PacketLayout::Standard
+            },
+            standard_score,
+            swapped_score,
+            frames,
+            release,
+        }
     }
 }
 
@@ -280,19 +1230,19 @@ mod tests {
         let mut processor = DataProcessor::new();
         let line = "2023-10-01 12:34:56.789,1234567890abcdef";
         let result = processor.process_next_line(line).unwrap();
-        assert_eq!(result, false);
+        assert!(!result);
         assert_eq!(processor.frame_data.len(), 0);
         assert_eq!(processor.skipped_lines, vec![line.to_string()]);
 
         let line2 = "2023-10-01 12:34:56.790,ABCD71AF000001020304";
         let result2 = processor.process_next_line(line2).unwrap();
-        assert_eq!(result2, false);
+        assert!(!result2);
         assert_eq!(processor.frame_data, vec![0x71, 0xAF, 0, 0, 1, 2, 3, 4]);
         assert_eq!(processor.skipped_lines, vec![line.to_string()]);
 
         let line2 = "2023-10-01 12:34:56.790,1234";
         let result2 = processor.process_next_line(line2).unwrap();
-        assert_eq!(result2, false);
+        assert!(!result2);
         assert_eq!(
             processor.frame_data,
             vec![0x71, 0xAF, 0, 0, 1, 2, 3, 4, 0x12, 0x34]
@@ -300,7 +1250,7 @@ mod tests {
 
         let line2 = "2023-10-01 12:34:56.790,ABCD71A0000000000000";
         let result2 = processor.process_next_line(line2).unwrap();
-        assert_eq!(result2, true);
+        assert!(result2);
         assert_eq!(
             processor.frame_data,
             vec![
@@ -311,17 +1261,20 @@ mod tests {
 
     #[test]
     fn test_parse_pixel_packet() {
+        let lut = Lut::builtin();
         let data = vec![0xA3, 0xED, 0x79, 0xC3, 0xFF, 0xEE];
-        let (idx, itot, event) = DataProcessor::parse_pixel_packet(&data);
-        assert_eq!(idx, 27455);
-        assert_eq!(itot, 21);
-        assert_eq!(event, 1);
+        let (idx, itot, event) = DataProcessor::parse_pixel_packet(&data, PacketLayout::Standard);
+        assert_eq!((idx, itot, event), (27455, 9999, 1022));
+        assert_eq!(lut.itot(itot), 21);
+        assert_eq!(lut.tot(event), 1);
 
         let data = vec![0xA3, 0xED, 0x79, 0xC3, 0x12, 0x34];
-        let (idx, itot, event) = DataProcessor::parse_pixel_packet(&data);
+        let (idx, itot, event) = DataProcessor::parse_pixel_packet(&data, PacketLayout::Standard);
         assert_eq!(idx, 27455);
-        assert_eq!(itot, 4357);
-        assert_eq!(event, 747);
+        assert_eq!(lut.itot(itot), 4357);
+        assert_eq!(lut.tot(event), 747);
+        assert_eq!(lut.itot(0), WRONG_LUT_ITOT);
+        assert_eq!(lut.tot(1024), WRONG_LUT_TOT);
     }
 
     #[test]
@@ -336,13 +1289,140 @@ mod tests {
             0x71, 0xA0, 0, 0, 0, 0, // end of readout
         ];
         let frame = processor.extract_frame();
-        assert_eq!(frame.itot.len(), 256 * 256);
-        assert_eq!(frame.event.len(), 256 * 256);
-        assert_eq!(frame.itot[27455], 21);
-        assert_eq!(frame.event[27455], 1);
-        assert_eq!(frame.itot[20287], 14);
-        assert_eq!(frame.event[20287], 1);
+        assert_eq!(frame.itot().len(), 256 * 256);
+        assert_eq!(frame.event().len(), 256 * 256);
+        assert_eq!(frame.itot()[27455], 21);
+        assert_eq!(frame.event()[27455], 1);
+        assert_eq!(frame.itot()[20287], 14);
+        assert_eq!(frame.event()[20287], 1);
         assert_eq!(frame.timestamp, 1696163696.789);
+        let bytes = ByteAccount {
+            payload: 32,
+            packets: 12,
+            headers: 20,
+            discarded: 0,
+        };
+        assert_eq!(frame.bytes, bytes);
+        assert_eq!(frame.bytes.efficiency(), 37.5);
+        assert_eq!(frame.masked.count(), 0);
+
+        // a masked pixel is dropped, its packet still counts as decoded
+        let x = 20287 % 256;
+        let y = 20287 / 256;
+        processor.mask = Some(Arc::new(
+            HotPixelMask::parse(&format!("{} {}\n", x, y)).unwrap(),
+        ));
+        let frame = processor.extract_frame();
+        assert_eq!(frame.itot()[20287], 0);
+        assert_eq!(frame.itot()[27455], 21);
+        assert_eq!(frame.masked.indices().collect::<Vec<_>>(), [20287]);
+        assert_eq!(frame.bytes, bytes);
+        processor.mask = None;
+
+        // truncated frame with a partial packet
+        processor.frame_data.truncate(15);
+        let frame = processor.extract_frame();
+        assert_eq!(frame.bytes.packets, 6);
+        assert_eq!(frame.bytes.discarded, 3);
+    }
+
+    #[test]
+    fn test_decode_with() {
+        let mut processor = DataProcessor::new();
+        processor.frame_data = vec![
+            0x71, 0xAF, 0, 0, 0, 0, // header
+            0xA3, 0xED, 0x79, 0xC3, 0xFF, 0xEE, // packet1
+            0x71, 0xA0, 0, 0, 0, 0, // end of readout
+        ];
+        let frame = processor.extract_frame();
+        assert_eq!(frame.codes.itot[27455], 9999);
+        assert_eq!(frame.codes.hits.count(), 1);
+
+        let mut lut = Lut::builtin();
+        lut.itot.to_mut()[9999] = 42;
+        let planes = frame.decode_with(&lut);
+        assert_eq!(planes.itot[27455], 42);
+        assert_eq!(planes.event[27455], 1);
+        assert_eq!(frame.itot()[27455], 21);
+        assert_eq!(planes.itot.iter().filter(|&&v| v != 0).count(), 1);
+        assert_eq!(frame.saturated().count(), 0);
+
+        // a code at the top of the table saturates the counter
+        lut.itot.to_mut()[9999] = Lut::builtin().itot_max();
+        assert_eq!(lut.itot_max(), 16382);
+        assert!(frame.decode_with(&lut).saturated.get(27455));
+    }
+
+    #[test]
+    fn test_decompress_rle() {
+        let data = [0x01, 0xCC, 0x03, 0xFF, 0x02, 0xCC, 0x00, 0xCC];
+        assert_eq!(
+            DataProcessor::decompress_rle(&data),
+            vec![0x01, 0xFF, 0xFF, 0xFF, 0x02, 0xCC, 0xCC]
+        );
+    }
+
+    #[test]
+    fn test_extract_frame_rle() {
+        let mut processor = DataProcessor::new();
+        processor.frame_data = vec![
+            0x71, 0xAF, 0, 0, 0x80, 0, // header with the RLE flag
+            0xA3, 0xED, 0x79, 0xC3, 0xFF, 0xEE, // packet1
+            0xA3, 0xE9, 0xF3, 0x33, 0xBF, 0xEE, // packet2
+            0x71, 0xA0, 0, 0, 0, 0, // end of readout
+        ];
+        let frame = processor.extract_frame();
+
+        processor.frame_data = vec![
+            0x71, 0xAF, 0, 0, 0x80, 0, // header with the RLE flag
+            0xA3, 0xED, 0x79, 0xC3, 0xFF, 0xEE, // packet1
+            0xA3, 0xE9, 0xF3, 0xCC, 0x01, 0x33, 0xBF, 0xEE, // packet2 compressed
+            0x71, 0xA0, 0, 0, 0, 0, // end of readout
+        ];
+        // the flag is ignored unless RLE is enabled
+        assert_ne!(processor.extract_frame().itot(), frame.itot());
+        processor.rle = true;
+        let frame_rle = processor.extract_frame();
+        assert_eq!(frame_rle.itot()[27455], 21);
+        assert_eq!(frame_rle.itot()[20287], 14);
+        assert_eq!(frame_rle.itot(), frame.itot());
+        assert_eq!(frame_rle.event(), frame.event());
+    }
+
+    #[test]
+    fn test_push_bytes() {
+        let frame_data = [
+            0x71, 0xAF, 0, 0, 0, 0, // header
+            0xA3, 0xED, 0x79, 0xC3, 0xFF, 0xEE, // packet1
+            0xA3, 0xE9, 0xF3, 0x33, 0xBF, 0xEE, // packet2
+            0x71, 0xA0, 0, 0, // end of readout
+        ];
+        let mut processor = DataProcessor::new();
+        assert!(processor.push_bytes(&[0x12, 0x71, 0xAF], 1.0).is_empty());
+        assert!(processor.push_bytes(&frame_data[2..10], 2.0).is_empty());
+        let frames = processor.push_bytes(&frame_data[10..], 3.0);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].raw, frame_data);
+        assert_eq!(frames[0].timestamp, 2.0);
+        assert_eq!(frames[0].itot()[27455], 21);
+        assert_eq!(frames[0].clusters.len(), 2);
+
+        // two frames and the start of a third in one chunk
+        let mut chunk = [frame_data, frame_data].concat();
+        chunk.extend_from_slice(&frame_data[..8]);
+        let frames = processor.push_bytes(&chunk, 4.0);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].raw, frame_data);
+        assert_eq!(processor.frame_data, &frame_data[..8]);
+
+        // a frame without its end of readout followed by a complete one
+        let mut processor = DataProcessor::new();
+        let chunk = [&frame_data[..18], &frame_data].concat();
+        let frames = processor.push_bytes(&chunk, 5.0);
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].truncated && !frames[1].truncated);
+        assert_eq!(frames[0].raw, &frame_data[..18]);
+        assert_eq!(frames[1].raw, frame_data);
     }
 
     #[test]
@@ -358,11 +1438,16 @@ mod tests {
         let mut frame = processor.extract_frame();
         processor.clusterize_frame(&mut frame);
         assert_eq!(frame.clusters.len(), 2);
+
+        processor.merge_distance = Some(255);
+        processor.clusterize_frame(&mut frame);
+        assert_eq!(frame.clusters.len(), 1);
+        assert_eq!(frame.clusters[0].merged, 1);
     }
 
     #[test]
     fn test_get_next_frame() {
-        let lines = vec![
+        let lines = [
             "TIMESTAMP,DATA",
             "2024-03-01 00:01:56.419,14584E000002290171AF00006974A4485FF33FEEA4486F10BFEEA470B35F3897A46EC999FFEEA46ED999FFEEA48ECF333F88A48E1FCCFFEEA48DFFE67FEEA48B91E081E7A48E2CCCFFEEA48E36673FEEA48E4CCE3FEEA48E5333BFEEA4AD9F333FEEA4AD7333BFEEA4ADA6673F",
             "2024-03-01 00:01:56.519,EEA4ADBF333FEEA4ADC6673FEEA4ADDF333FEEA4CD1999FFEEA4CCF999FFEEA4CD23387FEEA4CD3FCCFFEEA4CD4999FFEEA5AD1EAEFE37A6CAB48AB6E7A78B13387FEEA78B2906BFEEA78B36993FEEA7C7F6673FEEA7F78E667FEEA7F72F333FEEA7E8CCCCFFEEA7E803387FEE",
@@ -371,13 +1456,217 @@ mod tests {
         ];
         let input_data = lines.join("\n");
         let cursor = Cursor::new(input_data);
-        let mut reader = BufReader::new(cursor);
+        let mut reader = LineReader::new(BufReader::new(cursor), "data.csv");
 
         let mut processor = DataProcessor::new();
         let frame = processor.get_next_frame(&mut reader).unwrap();
-        assert_eq!(frame.itot.len(), 256 * 256);
-        assert_eq!(frame.event.len(), 256 * 256);
+        assert_eq!(frame.itot().len(), 256 * 256);
+        assert_eq!(frame.event().len(), 256 * 256);
         assert_eq!(frame.clusters.len(), 14);
-        assert_eq!(frame.timestamp, 1696163696.789);
+        assert_eq!(frame.timestamp, 1709251316.419);
+        assert_eq!(&frame.raw[..4], &[0x71, 0xAF, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_get_next_frame_embedded_header() {
+        // the end of readout of the first frame is lost, the second header follows packet 2
+        let data = "TIMESTAMP,DATA\n\
+                    2024-03-01 00:00:01.000,71AF00000000A3ED79C3FFEE\n\
+                    2024-03-01 00:00:02.000,A3E9F333BFEE71AF00000000A3ED79C3FFEE\n\
+                    2024-03-01 00:00:03.000,71A00000\n";
+        let mut reader = LineReader::new(BufReader::new(Cursor::new(data)), "data.csv");
+        let mut processor = DataProcessor::new();
+
+        let frame = processor.get_next_frame(&mut reader).unwrap();
+        assert!(frame.truncated);
+        assert_eq!(frame.timestamp, 1709251201.0);
+        assert_eq!(frame.codes.hits.count(), 2);
+        assert_eq!(frame.raw.len(), 18);
+
+        let frame = processor.get_next_frame(&mut reader).unwrap();
+        assert!(!frame.truncated);
+        assert_eq!(frame.timestamp, 1709251202.0);
+        assert_eq!(frame.codes.hits.count(), 1);
+        assert_eq!(frame.itot()[27455], 21);
+        assert_eq!(&frame.raw[..4], &[0x71, 0xAF, 0x00, 0x00]);
+        assert_eq!(processor.truncated_frames, 1);
+        assert!(processor.get_next_frame(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_get_next_frame_max_assembly_time() {
+        // the end of readout of the first frame is lost, its start of readout would collect
+        // the pixels of the next frame without a header
+        let data = "TIMESTAMP,DATA\n\
+                    2024-03-01 00:00:01.000,71AF00000000A3ED79C3FFEE\n\
+                    2024-03-01 00:02:00.000,A3E9F333BFEE\n\
+                    2024-03-01 00:02:01.000,71AF00000000A3ED79C3FFEE\n\
+                    2024-03-01 00:02:02.000,71A00000\n";
+        let mut processor = DataProcessor::new();
+        processor.max_assembly_time = Some(60.0);
+        let mut reader = LineReader::new(BufReader::new(Cursor::new(data)), "data.csv");
+        let frame = processor.get_next_frame(&mut reader).unwrap();
+        assert_eq!(frame.timestamp, 1709251321.0);
+        assert_eq!(frame.codes.hits.count(), 1);
+        assert_eq!(processor.overlong_frames, 1);
+        assert_eq!(processor.quality.events[0].issue, Issue::OverlongFrame);
+        assert_eq!(processor.quality.events[0].value, 119.0);
+
+        let mut processor = DataProcessor::new();
+        let mut reader = LineReader::new(BufReader::new(Cursor::new(data)), "data.csv");
+        let frame = processor.get_next_frame(&mut reader).unwrap();
+        assert_eq!(frame.timestamp, 1709251201.0);
+        assert_eq!(processor.overlong_frames, 0);
+    }
+
+    #[test]
+    fn test_get_next_frame_error_location() {
+        let data = "TIMESTAMP,DATA\n2024-03-01 00:01:56.419,14584E0\n";
+        let mut reader = LineReader::new(BufReader::new(Cursor::new(data)), "data.csv");
+        let mut processor = DataProcessor::new();
+        processor.error_policy = ErrorPolicy::Abort;
+        let err = processor.get_next_frame(&mut reader).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("data.csv:2: cannot decode DATA: Odd number of digits")
+        );
+    }
+
+    #[test]
+    fn test_get_next_frame_bad_lines() {
+        let data = [
+            "2024-03-01 00:01:56.419,71AF00000000A3ED79C3FFEE",
+            "2024-03-01 00:01:56.519,A3E9F333BFEEZZ",
+            "2024-03-01 00:01:56.619,71A00000",
+        ]
+        .join("\n");
+
+        let mut processor = DataProcessor::new();
+        let mut reader = LineReader::new(BufReader::new(Cursor::new(data.clone())), "data.csv");
+        let frame = processor.get_next_frame(&mut reader).unwrap();
+        assert_eq!(processor.bad_lines, 1);
+        assert_eq!(frame.itot()[27455], 21);
+        assert_eq!(frame.itot()[20287], 0);
+
+        let mut processor = DataProcessor::new();
+        processor.error_policy = ErrorPolicy::Salvage;
+        let mut reader = LineReader::new(BufReader::new(Cursor::new(data)), "data.csv");
+        let frame = processor.get_next_frame(&mut reader).unwrap();
+        assert_eq!(processor.bad_lines, 1);
+        assert_eq!(frame.itot()[20287], 14);
+        assert_eq!(
+            "salvage".parse::<ErrorPolicy>().unwrap(),
+            ErrorPolicy::Salvage
+        );
+    }
+
+    /// Encodes a pixel packet with the given layout
+    fn encode_packet(x: u16, y: u16, layout: PacketLayout) -> [u8; 6] {
+        let address = (x / 2) << 9 | (y / 4) << 3 | ((x % 2) * 4 + y % 4);
+        let n = [
+            address >> 12,
+            (address >> 8) & 0xF,
+            (address >> 4) & 0xF,
+            address & 0xF,
+        ];
+        let n = match layout {
+            PacketLayout::Standard => n,
+            PacketLayout::Swapped => [n[3], n[2], n[1], n[0]],
+        };
+        let toa: u16 = 0x100;
+        [
+            0xA0 | n[0] as u8,
+            (n[1] << 4 | n[2]) as u8,
+            (n[3] << 4 | toa >> 10) as u8,
+            (toa >> 2) as u8,
+            ((toa & 0x3) << 6) as u8,
+            0x1E,
+        ]
+    }
+
+    #[test]
+    fn test_sentinel_policy() {
+        let mut sentinel = encode_packet(3, 5, PacketLayout::Standard);
+        // ToA code 0 is outside the iToT lookup table
+        sentinel[2] &= 0xF0;
+        sentinel[3] = 0;
+        sentinel[4] &= 0x3F;
+        let mut data = vec![0x71, 0xAF, 0, 0, 0, 0];
+        data.extend_from_slice(&encode_packet(10, 10, PacketLayout::Standard));
+        data.extend_from_slice(&sentinel);
+        data.extend_from_slice(&[0x71, 0xA0, 0, 0, 0, 0]);
+
+        let mut processor = DataProcessor::new();
+        processor.frame_data = data;
+        let (valid, invalid) = (10 * 256 + 10, 5 * 256 + 3);
+        for (policy, itot) in [
+            (SentinelPolicy::Keep, WRONG_LUT_ITOT),
+            (SentinelPolicy::Zero, 0),
+            (SentinelPolicy::Invalid, 0),
+        ] {
+            processor.sentinel_policy = policy;
+            let frame = processor.extract_frame();
+            assert_eq!(frame.itot()[invalid], itot, "{}", policy);
+            assert_ne!(frame.itot()[valid], 0);
+            assert_eq!(frame.invalid().count(), 1);
+            assert!(frame.invalid().get(invalid) && !frame.invalid().get(valid));
+        }
+        assert_eq!(processor.extract_frame().event()[invalid], 0);
+        assert_eq!(
+            "zero".parse::<SentinelPolicy>().unwrap(),
+            SentinelPolicy::Zero
+        );
+        assert!("nan".parse::<SentinelPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_detect_layout() {
+        for layout in [PacketLayout::Standard, PacketLayout::Swapped] {
+            let mut payload = String::from("71AF00000000");
+            for i in 0..8 {
+                payload += &hex::encode_upper(encode_packet(100 + i, 37 + i / 2, layout));
+                payload += &hex::encode_upper(encode_packet(20, 200 + i, layout));
+            }
+            let data = format!(
+                "2024-03-01 00:01:56.419,{}\n2024-03-01 00:01:56.519,71A00000",
+                payload
+            );
+            let mut reader = LineReader::new(BufReader::new(Cursor::new(data)), "data.csv");
+            let detection = DataProcessor::detect_layout(&mut reader, 10);
+            assert_eq!(detection.frames, 1);
+            assert_eq!(detection.layout, layout);
+            assert_eq!(detection.release, Some(0));
+        }
+        let (idx, _, _) = DataProcessor::parse_pixel_packet(
+            &encode_packet(100, 37, PacketLayout::Swapped),
+            PacketLayout::Swapped,
+        );
+        assert_eq!(idx, 37 * 256 + 100);
+    }
+
+    #[test]
+    fn test_release_layouts() {
+        // a flight frame of the standard release 0x74 in a file of swapped frames of an
+        // unknown release
+        let frame = |release: u8, layout| {
+            let mut data = vec![0x71, 0xAF, 0, 0, 0x69, release];
+            data.extend_from_slice(&encode_packet(100, 37, layout));
+            data.extend_from_slice(&[0x71, 0xA0, 0, 0, 0, 0]);
+            data
+        };
+        let mut processor = DataProcessor::new();
+        processor.layout = PacketLayout::Swapped;
+        processor.release_layouts = true;
+        for data in [
+            frame(0x74, PacketLayout::Standard),
+            frame(0x42, PacketLayout::Swapped),
+        ] {
+            processor.frame_data = data;
+            assert_ne!(processor.extract_frame().itot()[37 * 256 + 100], 0);
+        }
+        // a forced layout applies to all frames
+        processor.release_layouts = false;
+        processor.frame_data = frame(0x74, PacketLayout::Standard);
+        assert_eq!(processor.extract_frame().itot()[37 * 256 + 100], 0);
     }
 }
@@ -0,0 +1,404 @@
+use crate::gps_processor::GpsData;
+use crate::info_processor::MeasInfoData;
+use crate::schema::FrameRecord;
+#[cfg(feature = "sqlite")]
+use anyhow::Context;
+use anyhow::{Result, bail};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Database written into the output directory by `--format sqlite`
+pub const DATABASE_NAME: &str = "data.sqlite";
+
+/// Tables of the database; a frame references the GPS and measurement info records it was
+/// matched to, a cluster its frame and a pixel its cluster
+#[cfg(feature = "sqlite")]
+const CREATE_TABLES: &str = "
+CREATE TABLE gps (
+    id INTEGER PRIMARY KEY,
+    timestamp REAL NOT NULL,
+    j2000_x REAL NOT NULL,
+    j2000_y REAL NOT NULL,
+    j2000_z REAL NOT NULL,
+    q_scalar REAL NOT NULL,
+    q_vector_1 REAL NOT NULL,
+    q_vector_2 REAL NOT NULL,
+    q_vector_3 REAL NOT NULL,
+    problems TEXT NOT NULL,
+    source TEXT NOT NULL
+);
+CREATE TABLE meas_info (
+    id INTEGER PRIMARY KEY,
+    timestamp REAL NOT NULL,
+    temp REAL,
+    pixel_short REAL,
+    pixel_long REAL,
+    pixel_saved REAL,
+    pixel_not_saved REAL,
+    error_id TEXT NOT NULL
+);
+CREATE TABLE frames (
+    id INTEGER PRIMARY KEY,
+    date TEXT NOT NULL,
+    frame INTEGER NOT NULL,
+    timestamp REAL NOT NULL,
+    frame_timestamp REAL NOT NULL,
+    acq_time REAL NOT NULL,
+    gps_id INTEGER REFERENCES gps (id),
+    meas_info_id INTEGER REFERENCES meas_info (id),
+    metadata TEXT NOT NULL
+);
+CREATE TABLE clusters (
+    id INTEGER PRIMARY KEY,
+    frame_id INTEGER NOT NULL REFERENCES frames (id),
+    size INTEGER NOT NULL,
+    energy REAL NOT NULL,
+    x REAL NOT NULL,
+    y REAL NOT NULL,
+    max_value INTEGER NOT NULL,
+    label TEXT NOT NULL,
+    merged INTEGER NOT NULL,
+    saturation REAL NOT NULL,
+    azimuth_sc REAL,
+    elevation_sc REAL,
+    ra_j2000 REAL,
+    dec_j2000 REAL
+);
+CREATE TABLE pixels (
+    cluster_id INTEGER NOT NULL REFERENCES clusters (id),
+    x INTEGER NOT NULL,
+    y INTEGER NOT NULL,
+    value INTEGER NOT NULL,
+    value2 INTEGER NOT NULL
+);
+CREATE INDEX frames_timestamp ON frames (timestamp);
+CREATE INDEX clusters_frame ON clusters (frame_id);
+CREATE INDEX pixels_cluster ON pixels (cluster_id);
+";
+
+#[cfg(feature = "sqlite")]
+const INSERT_GPS: &str = "INSERT INTO gps (timestamp, j2000_x, j2000_y, j2000_z, q_scalar, \
+     q_vector_1, q_vector_2, q_vector_3, problems, source) \
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)";
+
+#[cfg(feature = "sqlite")]
+const INSERT_MEAS_INFO: &str = "INSERT INTO meas_info (timestamp, temp, pixel_short, \
+     pixel_long, pixel_saved, pixel_not_saved, error_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)";
+
+#[cfg(feature = "sqlite")]
+const INSERT_FRAME: &str = "INSERT INTO frames (date, frame, timestamp, frame_timestamp, \
+     acq_time, gps_id, meas_info_id, metadata) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)";
+
+#[cfg(feature = "sqlite")]
+const INSERT_CLUSTER: &str = "INSERT INTO clusters (frame_id, size, energy, x, y, max_value, \
+     label, merged, saturation, azimuth_sc, elevation_sc, ra_j2000, dec_j2000) \
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)";
+
+#[cfg(feature = "sqlite")]
+const INSERT_PIXEL: &str =
+    "INSERT INTO pixels (cluster_id, x, y, value, value2) VALUES (?1, ?2, ?3, ?4, ?5)";
+
+/// Outputs of a conversion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// The daily .clog and .info files
+    #[default]
+    Files,
+    /// The daily files and the SQLite database of the run
+    Sqlite,
+    /// The daily files and a Parquet table of the clusters of each day
+    Parquet,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "files" => Ok(OutputFormat::Files),
+            "sqlite" => Ok(OutputFormat::Sqlite),
+            "parquet" => Ok(OutputFormat::Parquet),
+            _ => bail!("expected files, sqlite or parquet"),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputFormat::Files => write!(f, "files"),
+            OutputFormat::Sqlite => write!(f, "sqlite"),
+            OutputFormat::Parquet => write!(f, "parquet"),
+        }
+    }
+}
+
+/// SQLite database of the written frames with their clusters, pixels and matched GPS and
+/// measurement info records. The rows of a day are committed when the day is finalized.
+#[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+pub struct Database {
+    #[cfg(feature = "sqlite")]
+    connection: rusqlite::Connection,
+    #[cfg(not(feature = "sqlite"))]
+    connection: std::convert::Infallible,
+    /// Time and id of the last inserted GPS and measurement info record, the records are
+    /// shared by consecutive frames
+    last_gps: Option<(f64, i64)>,
+    last_meas_info: Option<(f64, i64)>,
+}
+
+#[cfg(feature = "sqlite")]
+impl Database {
+    /// Creates the database, replacing an existing file like the daily files are
+    pub fn create(path: &Path) -> Result<Self> {
+        if path.exists() {
+            std::fs::remove_file(path)
+                .with_context(|| format!("cannot replace {}", path.display()))?;
+        }
+        let connection = rusqlite::Connection::open(path)
+            .with_context(|| format!("cannot create database {}", path.display()))?;
+        connection.execute_batch(CREATE_TABLES)?;
+        connection.execute_batch("BEGIN")?;
+        Ok(Database {
+            connection,
+            last_gps: None,
+            last_meas_info: None,
+        })
+    }
+
+    /// Inserts the frame of the daily files with its clusters and pixels, and the records
+    /// it was matched to unless they are the ones of the previous frame
+    pub fn write_frame(
+        &mut self,
+        date: &str,
+        frame_time: f64,
+        record: &FrameRecord,
+        gps: Option<&GpsData>,
+        info: Option<&MeasInfoData>,
+    ) -> Result<()> {
+        let gps_id = gps.map(|gps| self.gps_id(gps)).transpose()?;
+        let meas_info_id = info.map(|info| self.meas_info_id(info)).transpose()?;
+        let metadata = serde_json::to_string(&record.metadata)?;
+        self.connection
+            .prepare_cached(INSERT_FRAME)?
+            .execute(rusqlite::params![
+                date,
+                record.frame as i64,
+                record.timestamp,
+                frame_time,
+                record.acq_time,
+                gps_id,
+                meas_info_id,
+                metadata
+            ])?;
+        let frame_id = self.connection.last_insert_rowid();
+        for cluster in &record.clusters {
+            let [azimuth, elevation] = cluster.direction_sc.map_or([None; 2], |d| d.map(Some));
+            let [ra, dec] = cluster.direction_j2000.map_or([None; 2], |d| d.map(Some));
+            self.connection
+                .prepare_cached(INSERT_CLUSTER)?
+                .execute(rusqlite::params![
+                    frame_id,
+                    cluster.pixels.len() as i64,
+                    cluster.energy,
+                    cluster.x,
+                    cluster.y,
+                    cluster.max_value,
+                    cluster.label,
+                    cluster.merged as i64,
+                    cluster.saturation,
+                    azimuth,
+                    elevation,
+                    ra,
+                    dec
+                ])?;
+            let cluster_id = self.connection.last_insert_rowid();
+            let mut insert = self.connection.prepare_cached(INSERT_PIXEL)?;
+            for [x, y, value, value2] in &cluster.pixels {
+                insert.execute(rusqlite::params![cluster_id, x, y, value, value2])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn gps_id(&mut self, gps: &GpsData) -> Result<i64> {
+        // propagated records are made for each frame
+        if let Some((timestamp, id)) = self.last_gps
+            && timestamp == gps.timestamp
+            && !gps.propagated
+        {
+            return Ok(id);
+        }
+        let source = if gps.propagated {
+            "tle"
+        } else if gps.repaired {
+            "interpolated"
+        } else {
+            "gps"
+        };
+        self.connection
+            .prepare_cached(INSERT_GPS)?
+            .execute(rusqlite::params![
+                gps.timestamp,
+                gps.j2000_x,
+                gps.j2000_y,
+                gps.j2000_z,
+                gps.q_est_prop_bj_scalar,
+                gps.q_est_prop_bj_vector_1,
+                gps.q_est_prop_bj_vector_2,
+                gps.q_est_prop_bj_vector_3,
+                gps.problems.join(", "),
+                source
+            ])?;
+        let id = self.connection.last_insert_rowid();
+        self.last_gps = Some((gps.timestamp, id));
+        Ok(id)
+    }
+
+    fn meas_info_id(&mut self, info: &MeasInfoData) -> Result<i64> {
+        if let Some((timestamp, id)) = self.last_meas_info
+            && timestamp == info.timestamp
+        {
+            return Ok(id);
+        }
+        // NaN counts of placeholder records are stored as NULL
+        let number = |value: f64| (!value.is_nan()).then_some(value);
+        self.connection
+            .prepare_cached(INSERT_MEAS_INFO)?
+            .execute(rusqlite::params![
+                info.timestamp,
+                number(info.temp),
+                number(info.pixel_short),
+                number(info.pixel_long),
+                number(info.pixel_saved),
+                number(info.pixel_not_saved),
+                info.error_id
+            ])?;
+        let id = self.connection.last_insert_rowid();
+        self.last_meas_info = Some((info.timestamp, id));
+        Ok(id)
+    }
+
+    /// Commits the rows written since the last commit
+    pub fn commit(&mut self) -> Result<()> {
+        self.connection.execute_batch("COMMIT; BEGIN")?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+impl Database {
+    pub fn create(_path: &Path) -> Result<Self> {
+        bail!("built without the sqlite feature (cargo build --release --features sqlite)")
+    }
+
+    pub fn write_frame(
+        &mut self,
+        _date: &str,
+        _frame_time: f64,
+        _record: &FrameRecord,
+        _gps: Option<&GpsData>,
+        _info: Option<&MeasInfoData>,
+    ) -> Result<()> {
+        match self.connection {}
+    }
+
+    pub fn commit(&mut self) -> Result<()> {
+        match self.connection {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{ClusterRecord, Value};
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    fn test_database() {
+        assert_eq!(
+            "sqlite".parse::<OutputFormat>().unwrap(),
+            OutputFormat::Sqlite
+        );
+        assert!("csv".parse::<OutputFormat>().is_err());
+
+        let path =
+            std::env::temp_dir().join(format!("oneweb-database-{}.sqlite", std::process::id()));
+        let gps = GpsData {
+            timestamp: 1709251200.0,
+            j2000_x: 7.0e6,
+            q_est_prop_bj_scalar: 1.0,
+            ..Default::default()
+        };
+        let info = MeasInfoData {
+            timestamp: 1709251206.3,
+            temp: -4.0,
+            ..Default::default()
+        };
+        let cluster = ClusterRecord {
+            pixels: vec![[10, 20, 30, 1], [11, 20, 5, 1]],
+            energy: 35.0,
+            x: 10.5,
+            y: 20.0,
+            max_value: 30,
+            label: "small blob".to_string(),
+            merged: 0,
+            saturation: 0.0,
+            direction_sc: None,
+            direction_j2000: None,
+        };
+        let record = |frame: usize| FrameRecord {
+            frame,
+            timestamp: info.timestamp,
+            acq_time: 25.0,
+            metadata: [("temp".to_string(), Value::Integer(-4))].into(),
+            clusters: vec![cluster.clone()],
+        };
+
+        #[cfg(feature = "sqlite")]
+        {
+            let mut database = Database::create(&path).unwrap();
+            for frame in 1..=2 {
+                database
+                    .write_frame(
+                        "2024-03-01",
+                        1709251201.3,
+                        &record(frame),
+                        Some(&gps),
+                        Some(&info),
+                    )
+                    .unwrap();
+            }
+            database.commit().unwrap();
+            drop(database);
+
+            let connection = rusqlite::Connection::open(&path).unwrap();
+            let count = |table: &str| -> i64 {
+                connection
+                    .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                        row.get(0)
+                    })
+                    .unwrap()
+            };
+            assert_eq!(count("frames"), 2);
+            assert_eq!(count("gps"), 1);
+            assert_eq!(count("meas_info"), 1);
+            assert_eq!(count("pixels"), 4);
+            let (energy, temp): (f64, i64) = connection
+                .query_row(
+                    "SELECT SUM(clusters.energy), json_extract(metadata, '$.temp') FROM frames \
+                     JOIN clusters ON clusters.frame_id = frames.id WHERE frame = 2",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap();
+            assert_eq!((energy, temp), (35.0, -4));
+            // the database of a new run replaces the old one
+            Database::create(&path).unwrap();
+            let _ = std::fs::remove_file(&path);
+        }
+        #[cfg(not(feature = "sqlite"))]
+        assert!(Database::create(&path).is_err());
+    }
+}
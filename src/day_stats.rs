@@ -0,0 +1,113 @@
+use crate::utils::{self, format_time};
+use anyhow::{Context, Result, bail};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Start of the header line with the statistics of the day written by `--two-pass`
+pub const DAY_STATS_PREFIX: &str = "# day: ";
+
+/// Content of the output files of a day, known before they are written when the first pass
+/// of a two-pass conversion gathered it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DayStats {
+    /// Frames written to the files of the day
+    pub frames: usize,
+    /// Times of the first and last frame
+    pub first_frame: f64,
+    pub last_frame: f64,
+    /// Acquisition time of the frames in s
+    pub exposure: f64,
+    /// Absorbed dose of the sensor in Gy, not weighted by the decimation
+    pub dose: f64,
+}
+
+/// Statistics of the days of a run
+pub type DayStatistics = BTreeMap<String, DayStats>;
+
+impl DayStats {
+    pub fn add_frame(&mut self, timestamp: f64, acq_time: f64, dose: f64) {
+        if self.frames == 0 {
+            self.first_frame = timestamp;
+        }
+        self.last_frame = timestamp;
+        self.frames += 1;
+        self.exposure += acq_time;
+        self.dose += dose;
+    }
+}
+
+impl fmt::Display for DayStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "frames {}, first {}, last {}, exposure {:.3} s, dose {:.6e} Gy",
+            self.frames,
+            format_time(self.first_frame),
+            format_time(self.last_frame),
+            self.exposure,
+            self.dose
+        )
+    }
+}
+
+impl FromStr for DayStats {
+    type Err = anyhow::Error;
+
+    /// Parses the header line or its value
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.strip_prefix(DAY_STATS_PREFIX).unwrap_or(s);
+        let mut stats = DayStats::default();
+        for item in s.split(',') {
+            let Some((name, value)) = item.trim().split_once(' ') else {
+                bail!("invalid day statistics item '{}'", item);
+            };
+            let number = |unit: &str| -> Result<f64> {
+                value
+                    .trim_end_matches(unit)
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("invalid {} '{}'", name, value))
+            };
+            match name {
+                "frames" => {
+                    stats.frames = value
+                        .parse()
+                        .with_context(|| format!("invalid frames '{}'", value))?
+                }
+                "first" => stats.first_frame = utils::parse_datetime_arg(value)?,
+                "last" => stats.last_frame = utils::parse_datetime_arg(value)?,
+                "exposure" => stats.exposure = number(" s")?,
+                "dose" => stats.dose = number(" Gy")?,
+                _ => bail!("unknown day statistics item '{}'", name),
+            }
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_day_stats() {
+        let mut stats = DayStats::default();
+        stats.add_frame(1709251201.3, 25.0, 1.5e-7);
+        stats.add_frame(1709251231.3, 20.5, 0.5e-7);
+        let line = format!("{}{}", DAY_STATS_PREFIX, stats);
+        assert_eq!(
+            line,
+            "# day: frames 2, first 2024-03-01 00:00:01.300, last 2024-03-01 00:00:31.300, \
+             exposure 45.500 s, dose 2.000000e-7 Gy"
+        );
+        let parsed: DayStats = line.parse().unwrap();
+        assert_eq!(parsed.frames, 2);
+        assert!((parsed.last_frame - 1709251231.3).abs() < 1e-3);
+        assert_eq!(parsed.exposure, 45.5);
+        assert_eq!(parsed.dose, 2e-7);
+        assert!("frames two".parse::<DayStats>().is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use crate::gps_processor::GpsData;
+use crate::orbit;
+use serde::{Deserialize, Serialize};
+
+/// Speed in m/s implied by consecutive GPS positions above which they cannot be on the same
+/// orbit (the LEO orbital velocity is about 7.6 km/s)
+pub const MAX_GPS_SPEED: f64 = 10_000.0;
+
+/// Repairs single-sample position outliers of the GPS record stream: a record far from both
+/// neighbours, which are consistent with each other, gets the position interpolated between
+/// them. Each record is held back until the next one is known.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GpsDeglitcher {
+    /// Last record passed on
+    prev: Option<GpsData>,
+    /// Record waiting for the next one
+    held: Option<GpsData>,
+}
+
+impl GpsDeglitcher {
+    /// Takes the next record of the file, returns the checked record before it
+    pub fn push(&mut self, record: GpsData) -> Option<GpsData> {
+        let mut held = self.held.replace(record)?;
+        if let (Some(prev), Some(next)) = (&self.prev, &self.held)
+            && is_outlier(prev, &held, next)
+        {
+            repair(&mut held, prev, next);
+        }
+        self.prev = Some(held.clone());
+        Some(held)
+    }
+
+    /// Record held back at the end of the file, it has no next record to be checked with
+    pub fn finish(&mut self) -> Option<GpsData> {
+        self.held.take()
+    }
+}
+
+fn position(record: &GpsData) -> [f64; 3] {
+    [record.j2000_x, record.j2000_y, record.j2000_z]
+}
+
+/// Speed implied by the positions and times of two records
+fn speed(a: &GpsData, b: &GpsData) -> f64 {
+    let (pa, pb) = (position(a), position(b));
+    let distance = (0..3).map(|i| (pb[i] - pa[i]).powi(2)).sum::<f64>().sqrt();
+    let dt = (b.timestamp - a.timestamp).abs();
+    if dt > 0.0 {
+        distance / dt
+    } else if distance > 0.0 {
+        f64::INFINITY
+    } else {
+        0.0
+    }
+}
+
+fn is_outlier(prev: &GpsData, record: &GpsData, next: &GpsData) -> bool {
+    speed(prev, record) > MAX_GPS_SPEED
+        && speed(record, next) > MAX_GPS_SPEED
+        && speed(prev, next) <= MAX_GPS_SPEED
+}
+
+/// Interpolates the position at the record time between the neighbours
+fn repair(record: &mut GpsData, prev: &GpsData, next: &GpsData) {
+    record.set_position(orbit::interpolate_position(
+        (prev.timestamp, position(prev)),
+        (next.timestamp, position(next)),
+        record.timestamp,
+    ));
+    record.repaired = true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deglitch() {
+        // circular orbit of 7000 km radius sampled every 10 s
+        let omega = 7.546e3 / 7.0e6;
+        let mut records: Vec<GpsData> = (0..5)
+            .map(|i| {
+                let t = i as f64 * 10.0;
+                GpsData {
+                    timestamp: t,
+                    j2000_x: 7.0e6 * (omega * t).cos(),
+                    j2000_y: 7.0e6 * (omega * t).sin(),
+                    q_est_prop_bj_scalar: 1.0,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let expected = records[2].clone();
+        records[2].j2000_z = 3.0e6;
+
+        let mut deglitcher = GpsDeglitcher::default();
+        let mut out: Vec<GpsData> = records
+            .into_iter()
+            .filter_map(|r| deglitcher.push(r))
+            .collect();
+        out.extend(deglitcher.finish());
+        assert_eq!(out.len(), 5);
+        let repaired: Vec<bool> = out.iter().map(|r| r.repaired).collect();
+        assert_eq!(repaired, [false, false, true, false, false]);
+        let error = (0..3)
+            .map(|i| (position(&out[2])[i] - position(&expected)[i]).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        assert!(error < 500.0, "{}", error);
+        assert!(out[2].is_valid());
+    }
+}
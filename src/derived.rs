@@ -0,0 +1,348 @@
+use crate::columns::{self, Column, MetaRow};
+use anyhow::{Result, bail};
+use std::fmt;
+use std::str::FromStr;
+
+/// Functions available in the expressions with their number of arguments
+const FUNCTIONS: [(&str, usize); 8] = [
+    ("abs", 1),
+    ("sqrt", 1),
+    ("exp", 1),
+    ("ln", 1),
+    ("log10", 1),
+    ("min", 2),
+    ("max", 2),
+    ("if_missing", 2),
+];
+
+/// Arithmetic expression over the metadata columns
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Number(f64),
+    Column(&'static Column),
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Call(&'static str, Vec<Expr>),
+}
+
+impl Expr {
+    /// Value for the row, NaN when a column is empty or not a number
+    pub fn eval(&self, row: &MetaRow) -> f64 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Column(column) => column.format(row).parse().unwrap_or(f64::NAN),
+            Expr::Neg(expr) => -expr.eval(row),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(row), b.eval(row));
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    _ => a.powf(b),
+                }
+            }
+            Expr::Call(name, args) => {
+                let values: Vec<f64> = args.iter().map(|arg| arg.eval(row)).collect();
+                match *name {
+                    "abs" => values[0].abs(),
+                    "sqrt" => values[0].sqrt(),
+                    "exp" => values[0].exp(),
+                    "ln" => values[0].ln(),
+                    "log10" => values[0].log10(),
+                    "min" => values[0].min(values[1]),
+                    "max" => values[0].max(values[1]),
+                    _ if values[0].is_nan() => values[1],
+                    _ => values[0],
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser {
+            chars: s.chars().collect(),
+            pos: 0,
+        };
+        let expr = parser.sum()?;
+        parser.skip_spaces();
+        if parser.pos < parser.chars.len() {
+            bail!(
+                "unexpected '{}' at {}",
+                parser.chars[parser.pos],
+                parser.pos + 1
+            );
+        }
+        Ok(expr)
+    }
+}
+
+/// Recursive descent parser, `^` binds tighter than the unary minus and is right associative
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn skip_spaces(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_spaces();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => bail!("expected '{}' at {}, found '{}'", expected, self.pos + 1, c),
+            None => bail!("expected '{}' at the end", expected),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr> {
+        let mut expr = self.product()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.pos += 1;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while let Some(op @ ('*' | '/')) = self.peek() {
+            self.pos += 1;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.peek() == Some('-') {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        let base = self.atom()?;
+        if self.peek() == Some('^') {
+            self.pos += 1;
+            return Ok(Expr::Binary('^', Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Expr> {
+        let start = self.pos;
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let expr = self.sum()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.pos;
+                while self.chars.get(self.pos).is_some_and(|c| {
+                    c.is_ascii_alphanumeric()
+                        || *c == '.'
+                        || matches!(c, '+' | '-') && matches!(self.chars[self.pos - 1], 'e' | 'E')
+                }) {
+                    self.pos += 1;
+                }
+                let number: String = self.chars[start..self.pos].iter().collect();
+                match number.parse() {
+                    Ok(value) => Ok(Expr::Number(value)),
+                    Err(_) => bail!("invalid number '{}' at {}", number, start + 1),
+                }
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let start = self.pos;
+                while self
+                    .chars
+                    .get(self.pos)
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_')
+                {
+                    self.pos += 1;
+                }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                if self.peek() == Some('(') {
+                    return self.call(&name, start);
+                }
+                match columns::find(&name) {
+                    Some(column) => Ok(Expr::Column(column)),
+                    None => bail!("unknown metadata column '{}'", name),
+                }
+            }
+            Some(c) => bail!("unexpected '{}' at {}", c, self.pos + 1),
+            None => bail!("expression ends after {} characters", start),
+        }
+    }
+
+    fn call(&mut self, name: &str, start: usize) -> Result<Expr> {
+        let Some(&(name, arity)) = FUNCTIONS.iter().find(|(f, _)| *f == name) else {
+            let available: Vec<&str> = FUNCTIONS.iter().map(|(f, _)| *f).collect();
+            bail!(
+                "unknown function '{}' at {}, available: {}",
+                name,
+                start + 1,
+                available.join(", ")
+            );
+        };
+        self.expect('(')?;
+        let mut args = vec![self.sum()?];
+        while self.peek() == Some(',') {
+            self.pos += 1;
+            args.push(self.sum()?);
+        }
+        self.expect(')')?;
+        if args.len() != arity {
+            bail!("{} takes {} argument(s), found {}", name, arity, args.len());
+        }
+        Ok(Expr::Call(name, args))
+    }
+}
+
+/// Metadata column computed from the other columns (`name = expression`), appended to the
+/// .info output and the record streams
+#[derive(Debug, Clone)]
+pub struct DerivedColumn {
+    pub name: String,
+    pub expr: Expr,
+    /// Text of the expression, part of the repro hash
+    pub source: String,
+}
+
+impl DerivedColumn {
+    /// Value of the column for the row, empty when it is not a finite number
+    pub fn format(&self, row: &MetaRow) -> String {
+        let value = self.expr.eval(row);
+        if value.is_finite() {
+            value.to_string()
+        } else {
+            String::new()
+        }
+    }
+}
+
+impl FromStr for DerivedColumn {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some((name, source)) = s.split_once('=') else {
+            bail!("expected name = expression");
+        };
+        let (name, source) = (name.trim(), source.trim());
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            bail!("invalid column name '{}'", name);
+        }
+        if columns::find(name).is_some() {
+            bail!("'{}' is a metadata column of the registry", name);
+        }
+        Ok(DerivedColumn {
+            name: name.to_string(),
+            expr: source.parse()?,
+            source: source.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for DerivedColumn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} = {}", self.name, self.source)
+    }
+}
+
+/// Parses the derived columns of the configuration, the names must be unique
+pub fn resolve<S: AsRef<str>>(definitions: &[S]) -> Result<Vec<DerivedColumn>> {
+    let mut derived: Vec<DerivedColumn> = Vec::new();
+    for definition in definitions {
+        let definition = definition.as_ref();
+        let column: DerivedColumn = definition
+            .parse()
+            .map_err(|e| anyhow::anyhow!("invalid derived column '{}': {}", definition, e))?;
+        if derived.iter().any(|c| c.name == column.name) {
+            bail!("derived column '{}' defined twice", column.name);
+        }
+        derived.push(column);
+    }
+    Ok(derived)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binning::PositionBinning;
+    use crate::data_processor::Frame;
+    use crate::direction;
+    use crate::dose_equivalent::QualityFactor;
+    use crate::gps_processor::GpsData;
+    use crate::info_processor::MeasInfoData;
+    use crate::orientation::Orientation;
+
+    #[test]
+    fn test_derived_columns() {
+        let frame = Frame::from_planes(Vec::new(), Vec::new(), 0.0);
+        let info = MeasInfoData {
+            temp: -4.0,
+            ..Default::default()
+        };
+        let gps = GpsData::default();
+        let row = MetaRow {
+            frame_index: 3,
+            frame_number: 1042,
+            frame: &frame,
+            info: &info,
+            gps: &gps,
+            gps_missing: true,
+            info_missing: false,
+            acq_time: 2.5,
+            kev_per_count: 1.0,
+            quality_factor: QualityFactor::default(),
+            mounting: direction::IDENTITY,
+            maneuver: false,
+            attitude_excluded: false,
+            tle_residual: None,
+            mode: None,
+            orientation: Orientation::Detector,
+            binning: PositionBinning::default(),
+            interpolated: None,
+            gps_columns: columns::GpsColumns::Nearest,
+            velocity: None,
+            pointing_frame: direction::PointingFrame::J2000,
+        };
+        let value = |definition: &str| definition.parse::<DerivedColumn>().unwrap().format(&row);
+        assert_eq!(value("a = frame_index * 2 + 1"), "7");
+        assert_eq!(value("a = 2 + frame_index * (temp - 1)"), "-13");
+        assert_eq!(value("a = -2^2 + acq_time / 5e-1"), "1");
+        assert_eq!(value("a = max(temp, 0) + abs(temp) + sqrt(16)"), "8");
+        assert_eq!(value("a = frame_index / 0"), "");
+        assert_eq!(value("a = gps_x / 1000"), "");
+        assert_eq!(value("a = if_missing(gps_x, -1)"), "-1");
+
+        for invalid in [
+            "a frame_index",
+            "temp = frame_index",
+            "a = speed * 2",
+            "a = frame_index +",
+            "a = (temp",
+            "a = temp temp",
+            "a = pow(temp, 2)",
+            "a = min(temp)",
+        ] {
+            assert!(invalid.parse::<DerivedColumn>().is_err(), "{}", invalid);
+        }
+        let derived = resolve(&["per_s = clusters / acq_time"]).unwrap();
+        assert_eq!(derived[0].to_string(), "per_s = clusters / acq_time");
+        assert!(resolve(&["a = temp", "a = temp"]).is_err());
+    }
+}
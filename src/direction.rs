@@ -0,0 +1,260 @@
+use crate::clustering::Cluster;
+use crate::dosimetry::{PIXEL_PITCH, SENSOR_THICKNESS};
+use crate::event_display;
+use crate::orbit::{self, ReferenceFrame};
+use anyhow::{Result, bail};
+use std::fmt;
+use std::str::FromStr;
+
+/// Rotation matrix applied as `v' = M v`
+pub type Matrix3 = [[f64; 3]; 3];
+
+pub const IDENTITY: Matrix3 = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+/// Allowed deviation of the mounting matrix from a rotation
+const ROTATION_TOLERANCE: f64 = 1e-3;
+
+/// Checks the detector mounting matrix is a proper rotation
+pub fn validate_mounting(m: &Matrix3) -> Result<()> {
+    for i in 0..3 {
+        for j in 0..3 {
+            let dot: f64 = (0..3).map(|k| m[i][k] * m[j][k]).sum();
+            let expected = if i == j { 1.0 } else { 0.0 };
+            if (dot - expected).abs() > ROTATION_TOLERANCE {
+                bail!("mounting matrix {:?} is not a rotation", m);
+            }
+        }
+    }
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+    if det < 0.0 {
+        bail!("mounting matrix {:?} is a reflection", m);
+    }
+    Ok(())
+}
+
+fn apply(m: &Matrix3, v: [f64; 3]) -> [f64; 3] {
+    [0, 1, 2].map(|i| m[i][0] * v[0] + m[i][1] * v[1] + m[i][2] * v[2])
+}
+
+/// Unit vector towards the source of the track in the detector frame (x along the columns,
+/// y along the rows, z the sensor normal on the irradiated side). The polar angle follows
+/// from the projected track length over the sensor thickness, the azimuth from the major
+/// axis; the sense along the track is unknown, the direction with x >= 0 is returned.
+pub fn detector_direction(cluster: &Cluster) -> [f64; 3] {
+    let projected = event_display::projected_length(cluster) * PIXEL_PITCH;
+    let (mut dx, mut dy) = event_display::major_axis(cluster);
+    if dx < 0.0 || (dx == 0.0 && dy < 0.0) {
+        (dx, dy) = (-dx, -dy);
+    }
+    let v = [projected * dx, projected * dy, SENSOR_THICKNESS];
+    let norm = v.iter().map(|c| c * c).sum::<f64>().sqrt();
+    v.map(|c| c / norm)
+}
+
+/// Direction in the spacecraft body frame, `mounting` rotates detector to body vectors
+pub fn spacecraft_direction(cluster: &Cluster, mounting: &Matrix3) -> [f64; 3] {
+    apply(mounting, detector_direction(cluster))
+}
+
+/// Sensor normal (boresight) in the spacecraft body frame
+pub fn boresight(mounting: &Matrix3) -> [f64; 3] {
+    apply(mounting, [0.0, 0.0, 1.0])
+}
+
+/// Rotates a body frame vector to J2000 with the attitude quaternion (scalar first, unit
+/// norm) rotating J2000 to body vectors
+pub fn body_to_j2000(q: [f64; 4], v: [f64; 3]) -> [f64; 3] {
+    let [q0, q1, q2, q3] = q;
+    // attitude matrix A(q), v_body = A v_j2000
+    let a = [
+        [
+            q0 * q0 + q1 * q1 - q2 * q2 - q3 * q3,
+            2.0 * (q1 * q2 + q0 * q3),
+            2.0 * (q1 * q3 - q0 * q2),
+        ],
+        [
+            2.0 * (q1 * q2 - q0 * q3),
+            q0 * q0 - q1 * q1 + q2 * q2 - q3 * q3,
+            2.0 * (q2 * q3 + q0 * q1),
+        ],
+        [
+            2.0 * (q1 * q3 + q0 * q2),
+            2.0 * (q2 * q3 - q0 * q1),
+            q0 * q0 - q1 * q1 - q2 * q2 + q3 * q3,
+        ],
+    ];
+    [0, 1, 2].map(|i| a[0][i] * v[0] + a[1][i] * v[1] + a[2][i] * v[2])
+}
+
+/// Longitude-like angle in [0, 360) and latitude-like angle in [-90, 90] of a unit vector in
+/// degrees: azimuth/elevation in the spacecraft frame, right ascension/declination in J2000
+pub fn angles(v: [f64; 3]) -> [f64; 2] {
+    let azimuth = v[1].atan2(v[0]).to_degrees().rem_euclid(360.0);
+    [azimuth, v[2].clamp(-1.0, 1.0).asin().to_degrees()]
+}
+
+/// Angle between two vectors in degrees, None for a zero vector
+pub fn angle_between(a: [f64; 3], b: [f64; 3]) -> Option<f64> {
+    let norms = (orbit::dot(a, a) * orbit::dot(b, b)).sqrt();
+    (norms > 0.0).then(|| {
+        (orbit::dot(a, b) / norms)
+            .clamp(-1.0, 1.0)
+            .acos()
+            .to_degrees()
+    })
+}
+
+/// Yaw, pitch and roll in degrees (z-y-x Euler sequence) of the rotation `m` of body to frame
+/// vectors
+pub fn euler_angles(m: &Matrix3) -> [f64; 3] {
+    [
+        m[1][0].atan2(m[0][0]),
+        (-m[2][0]).clamp(-1.0, 1.0).asin(),
+        m[2][1].atan2(m[2][2]),
+    ]
+    .map(f64::to_degrees)
+}
+
+fn unit(v: [f64; 3]) -> Option<[f64; 3]> {
+    let norm = orbit::dot(v, v).sqrt();
+    (norm > 0.0).then(|| v.map(|c| c / norm))
+}
+
+/// Frame of the boresight and Euler angle columns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PointingFrame {
+    #[default]
+    J2000,
+    Teme,
+    Itrf,
+    /// Local vertical local horizontal: z towards nadir, y against the orbit normal and x
+    /// along the velocity in the orbit plane
+    Lvlh,
+}
+
+impl PointingFrame {
+    /// Rotates a J2000 direction to the frame at the time of the J2000 position and velocity,
+    /// None in LVLH without the velocity
+    pub fn rotate(
+        &self,
+        v: [f64; 3],
+        timestamp: f64,
+        position: [f64; 3],
+        velocity: Option<[f64; 3]>,
+    ) -> Option<[f64; 3]> {
+        match self {
+            PointingFrame::J2000 => Some(v),
+            PointingFrame::Teme => Some(ReferenceFrame::Teme.transform(v, timestamp)),
+            PointingFrame::Itrf => Some(ReferenceFrame::Itrf.transform(v, timestamp)),
+            PointingFrame::Lvlh => {
+                let z = unit(position.map(|c| -c))?;
+                let y = unit(orbit::cross(velocity?, position))?;
+                let x = orbit::cross(y, z);
+                Some([x, y, z].map(|axis| orbit::dot(axis, v)))
+            }
+        }
+    }
+}
+
+impl FromStr for PointingFrame {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "j2000" => Ok(PointingFrame::J2000),
+            "teme" => Ok(PointingFrame::Teme),
+            "itrf" => Ok(PointingFrame::Itrf),
+            "lvlh" => Ok(PointingFrame::Lvlh),
+            _ => bail!("expected j2000, teme, itrf or lvlh"),
+        }
+    }
+}
+
+impl fmt::Display for PointingFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PointingFrame::J2000 => write!(f, "j2000"),
+            PointingFrame::Teme => write!(f, "teme"),
+            PointingFrame::Itrf => write!(f, "itrf"),
+            PointingFrame::Lvlh => write!(f, "lvlh"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clustering::Pixel;
+
+    #[test]
+    fn test_incidence_direction() {
+        let dot = Cluster {
+            pixels: vec![Pixel::new(5, 5, 10, 1)],
+            merged: 0,
+        };
+        assert_eq!(detector_direction(&dot), [0.0, 0.0, 1.0]);
+        // a track along the rows, 6 pixels (330 um) projected on the 300 um thick sensor
+        let track = Cluster {
+            pixels: (14..=20).map(|y| Pixel::new(10, y, 10, 1)).collect(),
+            merged: 0,
+        };
+        let [azimuth, elevation] = angles(detector_direction(&track));
+        let expected = (SENSOR_THICKNESS / (6.0 * PIXEL_PITCH)).atan().to_degrees();
+        assert!((azimuth - 90.0).abs() < 1e-9);
+        assert!((elevation - expected).abs() < 1e-9);
+
+        // detector z along the spacecraft -x axis
+        let mounting = [[0.0, 0.0, -1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]];
+        validate_mounting(&mounting).unwrap();
+        assert!(validate_mounting(&[[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, -1.0]]).is_err());
+        assert_eq!(spacecraft_direction(&dot, &mounting), [-1.0, 0.0, 0.0]);
+        assert_eq!(angles([-1.0, 0.0, 0.0]), [180.0, 0.0]);
+
+        // body rotated by 90 degrees about z: the body x axis is the J2000 y axis
+        let half = std::f64::consts::FRAC_PI_4;
+        let q = [half.cos(), 0.0, 0.0, half.sin()];
+        let j = body_to_j2000(q, [1.0, 0.0, 0.0]);
+        assert!((j[0]).abs() < 1e-12 && (j[1] - 1.0).abs() < 1e-12);
+        assert_eq!(
+            body_to_j2000([1.0, 0.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            [0.0, 0.0, 1.0]
+        );
+        assert_eq!(boresight(&mounting), [-1.0, 0.0, 0.0]);
+
+        // yaw 30, pitch 20, roll 10 composed as Rz Ry Rx
+        let (sy, cy) = 30f64.to_radians().sin_cos();
+        let (sp, cp) = 20f64.to_radians().sin_cos();
+        let (sr, cr) = 10f64.to_radians().sin_cos();
+        let m = [
+            [cy * cp, cy * sp * sr - sy * cr, cy * sp * cr + sy * sr],
+            [sy * cp, sy * sp * sr + cy * cr, sy * sp * cr - cy * sr],
+            [-sp, cp * sr, cp * cr],
+        ];
+        let [yaw, pitch, roll] = euler_angles(&m);
+        assert!((yaw - 30.0).abs() < 1e-9 && (pitch - 20.0).abs() < 1e-9);
+        assert!((roll - 10.0).abs() < 1e-9);
+
+        // LVLH of an equatorial orbit at +x moving towards +y
+        let (pos, vel) = ([7e6, 0.0, 0.0], Some([0.0, 7.5e3, 0.0]));
+        let lvlh = |v| PointingFrame::Lvlh.rotate(v, 0.0, pos, vel).unwrap();
+        assert_eq!(lvlh([0.0, 1.0, 0.0]), [1.0, 0.0, 0.0]);
+        assert_eq!(lvlh([0.0, 0.0, 1.0]), [0.0, -1.0, 0.0]);
+        assert_eq!(lvlh([-1.0, 0.0, 0.0]), [0.0, 0.0, 1.0]);
+        assert_eq!(
+            PointingFrame::Lvlh.rotate([1.0, 0.0, 0.0], 0.0, pos, None),
+            None
+        );
+        assert_eq!(angle_between([0.0, 1.0, 0.0], [0.0, 7.5e3, 0.0]), Some(0.0));
+        assert_eq!(
+            angle_between([0.0, 0.0, 1.0], [0.0, 7.5e3, 0.0]),
+            Some(90.0)
+        );
+        assert_eq!(
+            "lvlh".parse::<PointingFrame>().unwrap(),
+            PointingFrame::Lvlh
+        );
+        assert!("body".parse::<PointingFrame>().is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::{Context, Result, bail};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Written frames between two free space checks
+pub const CHECK_INTERVAL: usize = 50;
+/// Time between the free space checks while paused
+const PAUSE_RETRY: Duration = Duration::from_secs(30);
+
+/// Byte count given with an optional binary unit suffix (K, M, G, T)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let number = s.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        let shift = match s[number.len()..].to_ascii_uppercase().as_str() {
+            "" | "B" => 0,
+            "K" | "KB" | "KIB" => 10,
+            "M" | "MB" | "MIB" => 20,
+            "G" | "GB" | "GIB" => 30,
+            "T" | "TB" | "TIB" => 40,
+            unit => bail!("unknown size unit '{}', expected K, M, G or T", unit),
+        };
+        let value: f64 = number
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid size '{}'", s))?;
+        if value.is_nan() || value < 0.0 {
+            bail!("invalid size '{}'", s);
+        }
+        Ok(ByteSize((value * (1u64 << shift) as f64) as u64))
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+        let mut value = self.0 as f64;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < units.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            write!(f, "{} B", self.0)
+        } else {
+            write!(f, "{:.1} {}", value, units[unit])
+        }
+    }
+}
+
+/// Action when the free space of the output directory drops below the minimum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LowDiskPolicy {
+    /// Stop after the last complete frame with an error
+    #[default]
+    Abort,
+    /// Wait until space is freed
+    Pause,
+}
+
+impl FromStr for LowDiskPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "abort" => Ok(LowDiskPolicy::Abort),
+            "pause" => Ok(LowDiskPolicy::Pause),
+            _ => bail!("expected abort or pause"),
+        }
+    }
+}
+
+impl fmt::Display for LowDiskPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LowDiskPolicy::Abort => write!(f, "abort"),
+            LowDiskPolicy::Pause => write!(f, "pause"),
+        }
+    }
+}
+
+/// Free space available to the user on the file system of the path
+pub fn available_space(path: &Path) -> std::io::Result<u64> {
+    fs2::available_space(path)
+}
+
+/// Throttled check of the free space of the output directory
+pub struct DiskGuard {
+    dir: PathBuf,
+    min_free: ByteSize,
+    policy: LowDiskPolicy,
+    frames: usize,
+    probe: fn(&Path) -> std::io::Result<u64>,
+}
+
+impl DiskGuard {
+    pub fn new(dir: &Path, min_free: ByteSize, policy: LowDiskPolicy) -> Self {
+        DiskGuard {
+            dir: dir.to_path_buf(),
+            min_free,
+            policy,
+            frames: 0,
+            probe: available_space,
+        }
+    }
+
+    /// Checks the free space, errors or waits when it is below the minimum
+    pub fn check(&self) -> Result<()> {
+        loop {
+            let free = (self.probe)(&self.dir)
+                .with_context(|| format!("cannot read the free space of {}", self.dir.display()))?;
+            if free >= self.min_free.0 {
+                return Ok(());
+            }
+            match self.policy {
+                LowDiskPolicy::Abort => bail!(
+                    "free space in {} is {}, below the minimum of {}",
+                    self.dir.display(),
+                    ByteSize(free),
+                    self.min_free
+                ),
+                LowDiskPolicy::Pause => {
+                    tracing::warn!(
+                        "free space in {} is {}, below the minimum of {}, paused for {} s ...",
+                        self.dir.display(),
+                        ByteSize(free),
+                        self.min_free,
+                        PAUSE_RETRY.as_secs()
+                    );
+                    std::thread::sleep(PAUSE_RETRY);
+                }
+            }
+        }
+    }
+
+    /// Counts a written frame and checks the free space every `CHECK_INTERVAL` frames
+    pub fn frame_written(&mut self) -> Result<()> {
+        self.frames += 1;
+        if self.frames.is_multiple_of(CHECK_INTERVAL) {
+            self.check()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disk_guard() {
+        assert_eq!("512".parse::<ByteSize>().unwrap(), ByteSize(512));
+        assert_eq!("1.5K".parse::<ByteSize>().unwrap(), ByteSize(1536));
+        assert_eq!("2 GiB".parse::<ByteSize>().unwrap(), ByteSize(2 << 30));
+        assert_eq!(ByteSize(3 << 20).to_string(), "3.0 MiB");
+        assert!("10X".parse::<ByteSize>().is_err());
+        assert!("-1M".parse::<ByteSize>().is_err());
+
+        let mut guard = DiskGuard::new(Path::new("."), ByteSize(1000), LowDiskPolicy::Abort);
+        guard.probe = |_| Ok(5000);
+        assert!(guard.check().is_ok());
+        guard.probe = |_| Ok(999);
+        let err = guard.check().unwrap_err().to_string();
+        assert_eq!(err, "free space in . is 999 B, below the minimum of 1000 B");
+        for _ in 1..CHECK_INTERVAL {
+            assert!(guard.frame_written().is_ok());
+        }
+        assert!(guard.frame_written().is_err());
+    }
+}
@@ -0,0 +1,293 @@
+use crate::clustering::Cluster;
+use crate::dosimetry::{self, PIXEL_PITCH, SENSOR_THICKNESS};
+use crate::event_display;
+use crate::tpx3lut::MATRIX_SIZE;
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Ratio of the linear stopping powers of water and silicon, converts the LET measured in
+/// the sensor to the LET in water the quality factor is defined for
+const WATER_TO_SILICON_LET: f64 = 0.537;
+const LET_BINS_PER_DECADE: usize = 10;
+/// LET spectrum bins covering 0.1 to 1000 keV/um, the first and last bins also collect
+/// lower and higher LET
+const LET_BINS: usize = 4 * LET_BINS_PER_DECADE;
+const LET_MIN: f64 = 0.1;
+
+/// Quality factor curve Q(L) of the LET in water
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QualityFactor {
+    /// ICRP 60: 1 below 10 keV/um, 0.32 L - 2.2 up to 100 keV/um, 300/sqrt(L) above
+    #[default]
+    Icrp60,
+    /// ICRP 26: piecewise linear from 1 at 3.5 keV/um to 20 at 175 keV/um
+    Icrp26,
+}
+
+impl QualityFactor {
+    /// Quality factor of the LET in water in keV/um
+    pub fn q(&self, let_water: f64) -> f64 {
+        match self {
+            QualityFactor::Icrp60 => {
+                if let_water < 10.0 {
+                    1.0
+                } else if let_water <= 100.0 {
+                    0.32 * let_water - 2.2
+                } else {
+                    300.0 / let_water.sqrt()
+                }
+            }
+            QualityFactor::Icrp26 => {
+                const POINTS: [(f64, f64); 5] = [
+                    (3.5, 1.0),
+                    (7.0, 2.0),
+                    (23.0, 5.0),
+                    (53.0, 10.0),
+                    (175.0, 20.0),
+                ];
+                if let_water <= POINTS[0].0 {
+                    return POINTS[0].1;
+                }
+                for pair in POINTS.windows(2) {
+                    let ((l0, q0), (l1, q1)) = (pair[0], pair[1]);
+                    if let_water <= l1 {
+                        return q0 + (q1 - q0) * (let_water - l0) / (l1 - l0);
+                    }
+                }
+                POINTS[POINTS.len() - 1].1
+            }
+        }
+    }
+}
+
+impl FromStr for QualityFactor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "icrp60" => Ok(QualityFactor::Icrp60),
+            "icrp26" => Ok(QualityFactor::Icrp26),
+            _ => bail!(
+                "unknown quality factor curve '{}', expected icrp60 or icrp26",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for QualityFactor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QualityFactor::Icrp60 => write!(f, "icrp60"),
+            QualityFactor::Icrp26 => write!(f, "icrp26"),
+        }
+    }
+}
+
+/// LET in water in keV/um of the cluster, the energy over the path through the sensor
+/// estimated from the projected track length and the sensor thickness
+pub fn cluster_let(cluster: &Cluster, kev_per_count: f64) -> f64 {
+    let projected = event_display::projected_length(cluster) * PIXEL_PITCH;
+    let path_um = projected.hypot(SENSOR_THICKNESS) * 1e4;
+    event_display::cluster_energy(cluster, kev_per_count) / path_um * WATER_TO_SILICON_LET
+}
+
+/// Absorbed dose in Gy and dose equivalent in Sv of the sensor for the clusters of a frame
+pub fn frame_dose(clusters: &[Cluster], kev_per_count: f64, quality: QualityFactor) -> (f64, f64) {
+    let mass = dosimetry::pixel_mass() * MATRIX_SIZE as f64;
+    let (energy, weighted) = clusters.iter().fold((0.0, 0.0), |(energy, weighted), c| {
+        let e = event_display::cluster_energy(c, kev_per_count);
+        (
+            energy + e,
+            weighted + e * quality.q(cluster_let(c, kev_per_count)),
+        )
+    });
+    (
+        dosimetry::dose_gy(energy, mass),
+        dosimetry::dose_gy(weighted, mass),
+    )
+}
+
+fn let_bin(let_water: f64) -> usize {
+    let decades = (let_water.max(LET_MIN) / LET_MIN).log10();
+    ((decades * LET_BINS_PER_DECADE as f64).floor() as usize).min(LET_BINS - 1)
+}
+
+/// Lower edge of the LET bin in keV/um
+fn let_bin_edge(bin: usize) -> f64 {
+    LET_MIN * 10f64.powf(bin as f64 / LET_BINS_PER_DECADE as f64)
+}
+
+/// Sampling weighted dose totals of one day
+#[derive(Debug, Clone, Default)]
+pub struct DayDose {
+    pub frames: f64,
+    /// Exposure time in s
+    pub exposure: f64,
+    /// Absorbed dose of the sensor in Gy
+    pub dose: f64,
+    /// Dose equivalent in Sv
+    pub dose_equivalent: f64,
+}
+
+/// Per-day dose equivalent and the LET spectrum of the run
+#[derive(Debug, Clone)]
+pub struct DoseEquivalentReport {
+    pub quality: QualityFactor,
+    pub days: BTreeMap<String, DayDose>,
+    /// Clusters, absorbed dose and dose equivalent per LET bin
+    pub spectrum: Vec<(f64, f64, f64)>,
+}
+
+impl DoseEquivalentReport {
+    pub fn new(quality: QualityFactor) -> Self {
+        DoseEquivalentReport {
+            quality,
+            days: BTreeMap::new(),
+            spectrum: vec![(0.0, 0.0, 0.0); LET_BINS],
+        }
+    }
+
+    pub fn add_frame(
+        &mut self,
+        date: &str,
+        clusters: &[Cluster],
+        kev_per_count: f64,
+        acq_time: f64,
+        weight: f64,
+    ) {
+        let mass = dosimetry::pixel_mass() * MATRIX_SIZE as f64;
+        let day = self.days.entry(date.to_string()).or_default();
+        day.frames += weight;
+        day.exposure += weight * acq_time;
+        for cluster in clusters {
+            let let_water = cluster_let(cluster, kev_per_count);
+            let dose =
+                dosimetry::dose_gy(event_display::cluster_energy(cluster, kev_per_count), mass);
+            let dose_equivalent = dose * self.quality.q(let_water);
+            day.dose += weight * dose;
+            day.dose_equivalent += weight * dose_equivalent;
+            let bin = &mut self.spectrum[let_bin(let_water)];
+            bin.0 += weight;
+            bin.1 += weight * dose;
+            bin.2 += weight * dose_equivalent;
+        }
+    }
+
+    pub fn merge(&mut self, other: &DoseEquivalentReport) {
+        for (date, other) in &other.days {
+            let day = self.days.entry(date.clone()).or_default();
+            day.frames += other.frames;
+            day.exposure += other.exposure;
+            day.dose += other.dose;
+            day.dose_equivalent += other.dose_equivalent;
+        }
+        for (bin, other) in self.spectrum.iter_mut().zip(&other.spectrum) {
+            bin.0 += other.0;
+            bin.1 += other.1;
+            bin.2 += other.2;
+        }
+    }
+
+    /// Writes the daily dose equivalent and the LET spectrum
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(
+            fs::File::create(path)
+                .with_context(|| format!("cannot write dose equivalent {}", path.display()))?,
+        );
+        writeln!(
+            writer,
+            "# Dose equivalent of the sensor, quality factor {}",
+            self.quality
+        )?;
+        writeln!(
+            writer,
+            "date\tframes\texposure[s]\tdose[Gy]\tdose_equivalent[Sv]\tmean_quality_factor\tdose_equivalent_rate[Sv/s]"
+        )?;
+        for (date, day) in &self.days {
+            let mean_q = if day.dose > 0.0 {
+                day.dose_equivalent / day.dose
+            } else {
+                0.0
+            };
+            let rate = if day.exposure > 0.0 {
+                day.dose_equivalent / day.exposure
+            } else {
+                0.0
+            };
+            writeln!(
+                writer,
+                "{}\t{}\t{:.3}\t{:.6e}\t{:.6e}\t{:.3}\t{:.6e}",
+                date, day.frames, day.exposure, day.dose, day.dose_equivalent, mean_q, rate
+            )?;
+        }
+        writeln!(writer, "# LET spectrum in water")?;
+        writeln!(
+            writer,
+            "let_min[keV/um]\tclusters\tdose[Gy]\tdose_equivalent[Sv]"
+        )?;
+        for (bin, (clusters, dose, dose_equivalent)) in self.spectrum.iter().enumerate() {
+            writeln!(
+                writer,
+                "{:.4}\t{}\t{:.6e}\t{:.6e}",
+                let_bin_edge(bin),
+                clusters,
+                dose,
+                dose_equivalent
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clustering::Pixel;
+
+    #[test]
+    fn test_dose_equivalent() {
+        let icrp60 = QualityFactor::Icrp60;
+        assert_eq!(icrp60.q(5.0), 1.0);
+        assert!((icrp60.q(50.0) - 13.8).abs() < 1e-12);
+        assert_eq!(icrp60.q(400.0), 15.0);
+        let icrp26: QualityFactor = "icrp26".parse().unwrap();
+        assert_eq!(icrp26.q(1.0), 1.0);
+        assert_eq!(icrp26.q(15.0), 3.5);
+        assert_eq!(icrp26.q(1000.0), 20.0);
+        assert!("icrp103".parse::<QualityFactor>().is_err());
+
+        // a single pixel crosses the sensor perpendicularly, 300 um of silicon
+        let dot = Cluster {
+            pixels: vec![Pixel::new(10, 10, 300, 1)],
+            merged: 0,
+        };
+        assert!((cluster_let(&dot, 1.0) - 0.537).abs() < 1e-9);
+        let heavy = Cluster {
+            pixels: vec![Pixel::new(20, 20, 30000, 1)],
+            merged: 0,
+        };
+        let (dose, dose_equivalent) = frame_dose(&[dot.clone(), heavy.clone()], 1.0, icrp60);
+        let mass = dosimetry::pixel_mass() * MATRIX_SIZE as f64;
+        assert!((dose - dosimetry::dose_gy(30300.0, mass)).abs() < 1e-18);
+        let expected = dosimetry::dose_gy(300.0 + 30000.0 * (0.32 * 53.7 - 2.2), mass);
+        assert!((dose_equivalent - expected).abs() < 1e-15);
+
+        let mut report = DoseEquivalentReport::new(icrp60);
+        report.add_frame("2024-03-01", &[dot], 1.0, 2.0, 1.0);
+        let mut other = DoseEquivalentReport::new(icrp60);
+        other.add_frame("2024-03-01", &[heavy], 1.0, 2.0, 2.0);
+        report.merge(&other);
+        let day = &report.days["2024-03-01"];
+        assert_eq!((day.frames, day.exposure), (3.0, 6.0));
+        assert_eq!(report.spectrum[let_bin(0.537)].0, 1.0);
+        assert_eq!(report.spectrum[let_bin(53.7)].0, 2.0);
+        assert!((let_bin_edge(let_bin(53.7)) - 50.1187).abs() < 1e-3);
+    }
+}
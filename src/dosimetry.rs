@@ -0,0 +1,448 @@
+use crate::data_processor::Frame;
+use crate::roi::Roi;
+use crate::tpx3lut::{MATRIX_SIZE, WRONG_LUT_ITOT};
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Pixel pitch in cm
+pub const PIXEL_PITCH: f64 = 55e-4;
+/// Silicon sensor thickness in cm
+pub const SENSOR_THICKNESS: f64 = 300e-4;
+/// Silicon density in g/cm3
+pub const SI_DENSITY: f64 = 2.329;
+pub const KEV_TO_J: f64 = 1.602176634e-16;
+/// Frames of a dose map further apart in time start a new covered range
+const COVERAGE_GAP: f64 = 600.0;
+
+/// Mass of a single pixel volume in kg
+pub fn pixel_mass() -> f64 {
+    PIXEL_PITCH * PIXEL_PITCH * SENSOR_THICKNESS * SI_DENSITY * 1e-3
+}
+
+/// Absorbed dose in Gy for the energy in keV deposited in the given mass in kg
+pub fn dose_gy(energy_kev: f64, mass: f64) -> f64 {
+    energy_kev * KEV_TO_J / mass
+}
+
+/// Energy in keV deposited in the sensor during the frame, with the per-pixel energy
+/// calibration of the frame when loaded, otherwise `kev_per_count` per iToT count; the
+/// pixels at the lookup table sentinel are not counted
+pub fn frame_energy(frame: &Frame, kev_per_count: f64) -> f64 {
+    let pixels = frame
+        .itot()
+        .iter()
+        .enumerate()
+        .filter(|&(_, &v)| v != 0 && v != WRONG_LUT_ITOT);
+    match &frame.energy_calibration {
+        Some(calibration) => pixels.fold(0.0, |sum, (idx, &v)| sum + calibration.energy(idx, v)),
+        None => pixels.map(|(_, &v)| v as u64).sum::<u64>() as f64 * kev_per_count,
+    }
+}
+
+/// Mean absorbed dose rate in Gy/s of the whole sensor over the acquisition
+pub fn frame_dose_rate(frame: &Frame, kev_per_count: f64, acq_time: f64) -> f64 {
+    if acq_time <= 0.0 {
+        return 0.0;
+    }
+    let mass = pixel_mass() * MATRIX_SIZE as f64;
+    dose_gy(frame_energy(frame, kev_per_count), mass) / acq_time
+}
+
+/// Sampling weighted absorbed dose of the sensor of one day
+#[derive(Debug, Clone, Default)]
+pub struct DayDose {
+    pub frames: f64,
+    /// Exposure time in s
+    pub exposure: f64,
+    /// Absorbed dose in Gy
+    pub dose: f64,
+    /// Highest frame dose rate in Gy/s
+    pub peak_rate: f64,
+}
+
+/// Absorbed dose of the sensor per day, the frame dose rates times the acquisition times
+#[derive(Debug, Clone, Default)]
+pub struct DailyDose {
+    pub days: BTreeMap<String, DayDose>,
+    /// The energies come from the per-pixel calibration
+    pub calibrated: bool,
+}
+
+impl DailyDose {
+    pub fn add_frame(
+        &mut self,
+        date: &str,
+        frame: &Frame,
+        kev_per_count: f64,
+        acq_time: f64,
+        weight: f64,
+    ) {
+        self.calibrated |= frame.energy_calibration.is_some();
+        let rate = frame_dose_rate(frame, kev_per_count, acq_time);
+        let day = self.days.entry(date.to_string()).or_default();
+        day.frames += weight;
+        day.exposure += weight * acq_time;
+        day.dose += weight * rate * acq_time;
+        day.peak_rate = day.peak_rate.max(rate);
+    }
+
+    pub fn merge(&mut self, other: &DailyDose) {
+        self.calibrated |= other.calibrated;
+        for (date, other) in &other.days {
+            let day = self.days.entry(date.clone()).or_default();
+            day.frames += other.frames;
+            day.exposure += other.exposure;
+            day.dose += other.dose;
+            day.peak_rate = day.peak_rate.max(other.peak_rate);
+        }
+    }
+
+    /// Writes the dose, mean and peak dose rate of each day
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(
+            fs::File::create(path)
+                .with_context(|| format!("cannot write dose summary {}", path.display()))?,
+        );
+        writeln!(
+            writer,
+            "# Absorbed dose of the sensor (Si, {:.4} g), energies from {}",
+            pixel_mass() * MATRIX_SIZE as f64 * 1e3,
+            if self.calibrated {
+                "the per-pixel calibration"
+            } else {
+                "--kev-per-count"
+            }
+        )?;
+        writeln!(
+            writer,
+            "date\tframes\texposure[s]\tdose[Gy]\tmean_dose_rate[Gy/s]\tpeak_dose_rate[Gy/s]"
+        )?;
+        for (date, day) in &self.days {
+            let mean_rate = if day.exposure > 0.0 {
+                day.dose / day.exposure
+            } else {
+                0.0
+            };
+            writeln!(
+                writer,
+                "{}\t{}\t{:.3}\t{:.6e}\t{:.6e}\t{:.6e}",
+                date, day.frames, day.exposure, day.dose, mean_rate, day.peak_rate
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Adds the time to the (first, last) frame time ranges, joining it to a range less than
+/// `COVERAGE_GAP` away
+fn extend_ranges(ranges: &mut Vec<(f64, f64)>, time: f64) {
+    match ranges
+        .iter_mut()
+        .find(|(first, last)| time >= first - COVERAGE_GAP && time <= last + COVERAGE_GAP)
+    {
+        Some(range) => *range = (range.0.min(time), range.1.max(time)),
+        None => ranges.push((time, time)),
+    }
+}
+
+/// Sorted ranges of both lists, the ranges less than `COVERAGE_GAP` apart joined
+fn join_ranges(a: &[(f64, f64)], b: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let mut ranges: Vec<(f64, f64)> = a.iter().chain(b).copied().collect();
+    ranges.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut joined: Vec<(f64, f64)> = Vec::with_capacity(ranges.len());
+    for (first, last) in ranges {
+        match joined.last_mut() {
+            Some(range) if first <= range.1 + COVERAGE_GAP => range.1 = range.1.max(last),
+            _ => joined.push((first, last)),
+        }
+    }
+    joined
+}
+
+/// Cumulative absorbed dose per pixel, persisted as an ASCII matrix between runs with the
+/// frame time ranges it covers, so frames processed again in a later run are not added twice
+#[derive(Debug, Clone)]
+pub struct DoseMap {
+    /// Dose per pixel in Gy
+    pub dose: Vec<f64>,
+    pub frames: u64,
+    /// Exposure time in s, sampling weighted
+    pub exposure: f64,
+    /// Sampling weighting scheme of the accumulated frames
+    pub weighting: String,
+    /// Frame time ranges accumulated by earlier runs, frames inside them are skipped
+    pub covered: Vec<(f64, f64)>,
+    /// Frame time ranges added by this run
+    added: Vec<(f64, f64)>,
+    /// Frames skipped as covered already
+    pub skipped: u64,
+}
+
+impl Default for DoseMap {
+    fn default() -> Self {
+        DoseMap {
+            dose: vec![0.0; MATRIX_SIZE],
+            frames: 0,
+            exposure: 0.0,
+            weighting: String::from("none"),
+            covered: Vec::new(),
+            added: Vec::new(),
+            skipped: 0,
+        }
+    }
+}
+
+impl DoseMap {
+    /// Loads the map, a missing file gives an empty map
+    pub fn load(path: &Path) -> Result<DoseMap> {
+        if !path.exists() {
+            return Ok(DoseMap::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("cannot load dose map {}", path.display()))?;
+        let mut map = DoseMap {
+            dose: Vec::with_capacity(MATRIX_SIZE),
+            ..Default::default()
+        };
+        for line in content.lines() {
+            if let Some(header) = line.strip_prefix('#') {
+                for item in header.split(',') {
+                    match item.trim().split_once(':') {
+                        Some(("frames", v)) => map.frames = v.trim().parse()?,
+                        Some(("exposure", v)) => map.exposure = v.trim().parse()?,
+                        Some(("weighting", v)) => map.weighting = v.trim().to_string(),
+                        Some(("covered", v)) => {
+                            let Some((first, last)) = v.trim().split_once(' ') else {
+                                bail!("{}: invalid covered range '{}'", path.display(), v);
+                            };
+                            map.covered.push((first.parse()?, last.trim().parse()?));
+                        }
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+            for value in line.split_whitespace() {
+                map.dose.push(value.parse()?);
+            }
+        }
+        if map.dose.len() != MATRIX_SIZE {
+            bail!(
+                "{}: expected {} values, found {}",
+                path.display(),
+                MATRIX_SIZE,
+                map.dose.len()
+            );
+        }
+        Ok(map)
+    }
+
+    /// Saves the map through a temporary file so an interrupted write keeps the old state,
+    /// the mean dose of each ROI is noted in the header
+    pub fn save(&self, path: &Path, rois: &[Roi]) -> Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+        writeln!(
+            writer,
+            "# cumulative dose [Gy], frames: {}, exposure: {}, weighting: {}",
+            self.frames, self.exposure, self.weighting
+        )?;
+        for (first, last) in join_ranges(&self.covered, &self.added) {
+            writeln!(writer, "# covered: {} {}", first, last)?;
+        }
+        for roi in rois {
+            writeln!(
+                writer,
+                "# roi {} x {}-{} y {}-{}, mean dose: {:e}",
+                roi.name,
+                roi.x_min,
+                roi.x_max,
+                roi.y_min,
+                roi.y_max,
+                roi.mean_dose(self)
+            )?;
+        }
+        for row in self.dose.chunks(256) {
+            let line: Vec<String> = row.iter().map(|v| format!("{:e}", v)).collect();
+            writeln!(writer, "{}", line.join(" "))?;
+        }
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("cannot save dose map {}", path.display()))
+    }
+
+    /// Sets the weighting scheme of the frames added next, a map accumulated with
+    /// different schemes is marked mixed
+    pub fn use_weighting(&mut self, weighting: &str) {
+        if self.frames > 0 && self.weighting != weighting {
+            self.weighting = String::from("mixed");
+        } else {
+            self.weighting = weighting.to_string();
+        }
+    }
+
+    /// Empty map for a part of the run, covering the ranges of this map
+    pub fn fresh(&self) -> DoseMap {
+        DoseMap {
+            weighting: self.weighting.clone(),
+            covered: join_ranges(&self.covered, &self.added),
+            ..Default::default()
+        }
+    }
+
+    /// Adds the energy deposits of the frame taken at the time, scaled by the sampling weight
+    /// of the frame, `kev_per_count` converts iToT to keV; a frame in a covered range is
+    /// skipped
+    pub fn add_frame(
+        &mut self,
+        itot: &[u16],
+        timestamp: f64,
+        kev_per_count: f64,
+        acq_time: f64,
+        weight: f64,
+    ) {
+        if self
+            .covered
+            .iter()
+            .any(|&(first, last)| timestamp >= first && timestamp <= last)
+        {
+            self.skipped += 1;
+            return;
+        }
+        extend_ranges(&mut self.added, timestamp);
+        let mass = pixel_mass();
+        for (dose, &value) in self.dose.iter_mut().zip(itot) {
+            if value == 0 || value == WRONG_LUT_ITOT {
+                continue;
+            }
+            *dose += weight * dose_gy(value as f64 * kev_per_count, mass);
+        }
+        self.frames += 1;
+        self.exposure += weight * acq_time;
+    }
+
+    pub fn merge(&mut self, other: &DoseMap) {
+        if other.frames > 0 {
+            self.use_weighting(&other.weighting);
+        }
+        for (dose, value) in self.dose.iter_mut().zip(&other.dose) {
+            *dose += value;
+        }
+        self.frames += other.frames;
+        self.exposure += other.exposure;
+        self.covered = join_ranges(&self.covered, &other.covered);
+        self.added = join_ranges(&self.added, &other.added);
+        self.skipped += other.skipped;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixel_mass() {
+        assert!((pixel_mass() - 2.1136e-9).abs() < 1e-12);
+        assert!((dose_gy(1.0, pixel_mass()) - 7.58e-8).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_daily_dose() {
+        let mut itot = vec![0u16; MATRIX_SIZE];
+        itot[5] = 100;
+        itot[7] = WRONG_LUT_ITOT;
+        let frame = Frame::from_planes(itot, vec![0; MATRIX_SIZE], 0.0);
+        assert_eq!(frame_energy(&frame, 2.0), 200.0);
+        let rate = frame_dose_rate(&frame, 2.0, 4.0);
+        let mass = pixel_mass() * MATRIX_SIZE as f64;
+        assert!((rate - dose_gy(200.0, mass) / 4.0).abs() < 1e-20);
+
+        let mut daily = DailyDose::default();
+        daily.add_frame("2024-03-01", &frame, 2.0, 4.0, 1.0);
+        let mut other = DailyDose::default();
+        other.add_frame("2024-03-01", &frame, 2.0, 2.0, 2.0);
+        other.add_frame("2024-03-02", &frame, 2.0, 4.0, 1.0);
+        daily.merge(&other);
+        let day = &daily.days["2024-03-01"];
+        assert_eq!((day.frames, day.exposure), (3.0, 8.0));
+        assert!((day.dose - 3.0 * dose_gy(200.0, mass)).abs() < 1e-18);
+        assert_eq!(day.peak_rate, 2.0 * rate);
+        assert_eq!(daily.days.len(), 2);
+    }
+
+    #[test]
+    fn test_dose_map_save_load() {
+        let path = std::env::temp_dir().join(format!("oneweb-dose-{}.txt", std::process::id()));
+        let mut itot = vec![0u16; MATRIX_SIZE];
+        itot[5] = 100;
+        itot[7] = WRONG_LUT_ITOT;
+
+        let mut map = DoseMap::load(&path).unwrap();
+        map.add_frame(&itot, 1709251200.0, 1.0, 2.5, 1.0);
+        let roi: Roi = "corner:0,0,7,0".parse().unwrap();
+        map.save(&path, &[roi]).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        let expected = dose_gy(100.0, pixel_mass()) / 8.0;
+        assert!(content.contains(&format!(
+            "# roi corner x 0-7 y 0-0, mean dose: {:e}",
+            expected
+        )));
+
+        let mut map = DoseMap::load(&path).unwrap();
+        assert_eq!(map.frames, 1);
+        assert_eq!(map.exposure, 2.5);
+        assert_eq!(map.weighting, "none");
+        assert_eq!(map.covered, vec![(1709251200.0, 1709251200.0)]);
+        map.add_frame(&itot, 1709251210.0, 1.0, 2.5, 1.0);
+        assert!((map.dose[5] - 2.0 * dose_gy(100.0, pixel_mass())).abs() < 1e-15);
+        assert_eq!(map.dose[7], 0.0);
+        assert_eq!(map.frames, 2);
+
+        map.use_weighting("decimate 1/4");
+        assert_eq!(map.weighting, "mixed");
+        map.add_frame(&itot, 1709251220.0, 1.0, 2.5, 4.0);
+        assert!((map.dose[5] - 6.0 * dose_gy(100.0, pixel_mass())).abs() < 1e-15);
+        assert_eq!(map.exposure, 15.0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_dose_map_covered() {
+        let path =
+            std::env::temp_dir().join(format!("oneweb-dose-covered-{}.txt", std::process::id()));
+        let mut itot = vec![0u16; MATRIX_SIZE];
+        itot[5] = 100;
+        let start = 1709251200.0;
+
+        // two passes about an hour apart, frames every 10 s
+        let mut map = DoseMap::default();
+        for i in (0..10).chain(400..410) {
+            map.add_frame(&itot, start + 10.0 * i as f64, 1.0, 10.0, 1.0);
+        }
+        map.save(&path, &[]).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("# covered: 1709251200 1709251290\n"));
+        assert!(content.contains("# covered: 1709255200 1709255290\n"));
+
+        // a later run over the same downlink and the gap between the passes
+        let mut map = DoseMap::load(&path).unwrap();
+        let mut part = map.fresh();
+        for i in 0..410 {
+            part.add_frame(&itot, start + 10.0 * i as f64, 1.0, 10.0, 1.0);
+        }
+        assert_eq!(part.skipped, 20);
+        map.merge(&part);
+        assert_eq!(map.frames, 410);
+        assert_eq!(map.skipped, 20);
+        assert!((map.dose[5] - 410.0 * dose_gy(100.0, pixel_mass())).abs() < 1e-12);
+        map.save(&path, &[]).unwrap();
+        let map = DoseMap::load(&path).unwrap();
+        assert_eq!(map.covered, vec![(start, start + 4090.0)]);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+use crate::utils::parse_time;
+use crate::validate::{self, InputKind};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Why a GPS or measurement info line did not match the expected schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DriftKind {
+    ExtraColumns,
+    MissingColumns,
+    /// A header line differing from the expected column names
+    HeaderVariant,
+    /// The expected number of columns with a value that does not parse
+    InvalidValue,
+}
+
+impl fmt::Display for DriftKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DriftKind::ExtraColumns => write!(f, "extra columns"),
+            DriftKind::MissingColumns => write!(f, "missing columns"),
+            DriftKind::HeaderVariant => write!(f, "new header variant"),
+            DriftKind::InvalidValue => write!(f, "invalid value"),
+        }
+    }
+}
+
+/// Number of comma separated fields, commas inside double quotes do not separate
+fn field_count(line: &str) -> usize {
+    let mut quoted = false;
+    let mut count = 1;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => count += 1,
+            _ => {}
+        }
+    }
+    count
+}
+
+/// Classifies a line of the input that failed to parse or was skipped as a header
+pub fn classify(kind: InputKind, line: &str) -> Option<DriftKind> {
+    let line = line.trim();
+    let first = line.split(',').next().unwrap_or_default();
+    if parse_time(first.trim().trim_matches('"')).is_err() {
+        let names: Vec<&str> = line.split(',').map(validate::header_name).collect();
+        return (names != kind.header()).then_some(DriftKind::HeaderVariant);
+    }
+    let expected = kind.header().len();
+    Some(match field_count(line) {
+        n if n > expected => DriftKind::ExtraColumns,
+        n if n < expected => DriftKind::MissingColumns,
+        _ => DriftKind::InvalidValue,
+    })
+}
+
+/// A line not matching the schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct DriftLine {
+    pub kind: DriftKind,
+    /// The line verbatim, kept for the first lines of each file
+    pub sample: Option<String>,
+}
+
+/// GPS and measurement info lines skipped because they do not match the expected schema,
+/// by file and line number so lines read more than once (parallel jobs) count once
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SchemaDrift {
+    pub lines: BTreeMap<(String, usize), DriftLine>,
+}
+
+impl SchemaDrift {
+    /// Records the line at the location, keeping it verbatim when the file has fewer than
+    /// `max_samples` samples; returns false for lines matching the schema (the known header)
+    pub fn record(
+        &mut self,
+        kind: InputKind,
+        source: &str,
+        line_no: usize,
+        line: &str,
+        max_samples: usize,
+    ) -> bool {
+        let Some(drift) = classify(kind, line) else {
+            return false;
+        };
+        let samples = self
+            .lines
+            .range((source.to_string(), 0)..=(source.to_string(), usize::MAX))
+            .filter(|(_, l)| l.sample.is_some())
+            .count();
+        self.lines
+            .entry((source.to_string(), line_no))
+            .or_insert_with(|| DriftLine {
+                kind: drift,
+                sample: (samples < max_samples).then(|| line.trim().to_string()),
+            });
+        true
+    }
+
+    pub fn merge(&mut self, other: &SchemaDrift) {
+        for (key, line) in &other.lines {
+            self.lines
+                .entry(key.clone())
+                .or_insert_with(|| line.clone());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Summary lines: the counts per file and kind followed by the samples of the first
+    /// `max_samples` lines of each file
+    pub fn summary(&self, max_samples: usize) -> Vec<String> {
+        let mut counts: BTreeMap<&str, BTreeMap<DriftKind, usize>> = BTreeMap::new();
+        for ((source, _), line) in &self.lines {
+            *counts
+                .entry(source)
+                .or_default()
+                .entry(line.kind)
+                .or_default() += 1;
+        }
+        let mut summary = Vec::new();
+        for (source, kinds) in counts {
+            let kinds: Vec<String> = kinds
+                .iter()
+                .map(|(kind, count)| format!("{} {}", count, kind))
+                .collect();
+            summary.push(format!(
+                "Schema drift in {}, lines skipped: {}.",
+                source,
+                kinds.join(", ")
+            ));
+            let samples = self
+                .lines
+                .iter()
+                .filter(|((s, _), line)| s == source && line.sample.is_some())
+                .take(max_samples);
+            for ((_, line_no), line) in samples {
+                summary.push(format!(
+                    "  {}:{} ({}): {}",
+                    source,
+                    line_no,
+                    line.kind,
+                    line.sample.as_deref().unwrap_or_default()
+                ));
+            }
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_drift() {
+        let header = "\"TIME\",\"J2000_X (m)\",\"J2000_Y (m)\",\"J2000_Z (m)\",\"iae_qEstProp_BJ.scalar\",\"iae_qEstProp_BJ.vector(1)\",\"iae_qEstProp_BJ.vector(2)\",\"iae_qEstProp_BJ.vector(3)\"";
+        assert_eq!(classify(InputKind::Gps, header), None);
+        assert_eq!(
+            classify(InputKind::Gps, &format!("{},\"SPEED\"", header)),
+            Some(DriftKind::HeaderVariant)
+        );
+        assert_eq!(
+            classify(InputKind::Gps, "2024-03-01 00:00:09.000,1,2,3,4,5,6,7,8"),
+            Some(DriftKind::ExtraColumns)
+        );
+        assert_eq!(
+            classify(
+                InputKind::MeasInfo,
+                "2024-03-01 00:00:51.297,-4,5,35,\"1, 2\""
+            ),
+            Some(DriftKind::MissingColumns)
+        );
+        assert_eq!(
+            classify(InputKind::MeasInfo, "2024-03-01 00:00:51.297,x,5,35,320,0,"),
+            Some(DriftKind::InvalidValue)
+        );
+
+        let mut drift = SchemaDrift::default();
+        for line_no in [3, 5, 9] {
+            drift.record(
+                InputKind::Gps,
+                "gps.csv",
+                line_no,
+                "2024-03-01 00:00:09.000,1,2,3",
+                2,
+            );
+        }
+        let mut other = SchemaDrift::default();
+        other.record(
+            InputKind::Gps,
+            "gps.csv",
+            5,
+            "2024-03-01 00:00:09.000,1,2,3",
+            2,
+        );
+        other.record(InputKind::MeasInfo, "meas.csv", 1, "Time,Temp", 2);
+        drift.merge(&other);
+        assert_eq!(
+            drift.summary(1),
+            [
+                "Schema drift in gps.csv, lines skipped: 3 missing columns.",
+                "  gps.csv:3 (missing columns): 2024-03-01 00:00:09.000,1,2,3",
+                "Schema drift in meas.csv, lines skipped: 1 new header variant.",
+                "  meas.csv:1 (new header variant): Time,Temp",
+            ]
+        );
+    }
+}
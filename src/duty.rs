@@ -0,0 +1,236 @@
+use crate::gps_processor::GpsData;
+use crate::orbit;
+use crate::utils::format_time;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Acquisition statistics of one orbit, from ascending node to ascending node
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrbitDuty {
+    pub start: f64,
+    pub end: f64,
+    /// The orbit is cut by the start or the end of the data
+    pub partial: bool,
+    pub frames: usize,
+    /// Sum of the acquisition times in s
+    pub acq_time: f64,
+    /// Intervals without frames longer than the gap
+    pub missing_periods: usize,
+    /// Total length of the missing periods in s
+    pub missing_time: f64,
+}
+
+impl OrbitDuty {
+    /// Fraction of the orbit spent acquiring
+    pub fn duty_cycle(&self) -> f64 {
+        let duration = self.end - self.start;
+        if duration > 0.0 {
+            self.acq_time / duration
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Decoded frames of the run split into orbits at the ascending nodes of the GPS track
+#[derive(Debug, Clone, Default)]
+pub struct DutyCycle {
+    /// Time without frames in s above which a missing measurement period is counted
+    pub gap: f64,
+    /// Ascending node times of the GPS track
+    pub nodes: Vec<f64>,
+    /// Times of the first and last valid GPS records
+    pub span: Option<(f64, f64)>,
+    /// Frame timestamp and acquisition time of the decoded frames
+    pub frames: Vec<(f64, f64)>,
+}
+
+impl DutyCycle {
+    pub fn new(gap: f64) -> Self {
+        DutyCycle {
+            gap,
+            ..Default::default()
+        }
+    }
+
+    /// Splits the orbits at the ascending nodes of the valid GPS records
+    pub fn use_track(&mut self, records: &[GpsData]) {
+        let valid: Vec<&GpsData> = records.iter().filter(|r| r.is_valid()).collect();
+        self.nodes = orbit::ascending_nodes(
+            valid
+                .iter()
+                .map(|r| (r.timestamp, [r.j2000_x, r.j2000_y, r.j2000_z])),
+        );
+        self.span = match (valid.first(), valid.last()) {
+            (Some(first), Some(last)) => Some((first.timestamp, last.timestamp)),
+            _ => None,
+        };
+    }
+
+    pub fn add_frame(&mut self, timestamp: f64, acq_time: f64) {
+        self.frames.push((timestamp, acq_time));
+    }
+
+    pub fn merge(&mut self, other: &DutyCycle) {
+        self.frames.extend_from_slice(&other.frames);
+    }
+
+    /// Orbits covering the GPS track and the frames, in time order
+    pub fn orbits(&self) -> Vec<OrbitDuty> {
+        let mut frames = self.frames.clone();
+        frames.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let frame_span = frames.first().zip(frames.last()).map(|(f, l)| (f.0, l.0));
+        let (start, end) = match (self.span, frame_span) {
+            (Some(gps), Some(data)) => (gps.0.min(data.0), gps.1.max(data.1)),
+            (Some(span), None) | (None, Some(span)) => span,
+            (None, None) => return Vec::new(),
+        };
+
+        let mut bounds = vec![(start, false)];
+        bounds.extend(
+            self.nodes
+                .iter()
+                .filter(|&&t| t > start && t < end)
+                .map(|&t| (t, true)),
+        );
+        bounds.push((end, false));
+
+        let mut orbits = Vec::new();
+        let mut next = 0;
+        for (i, pair) in bounds.windows(2).enumerate() {
+            let ((from, from_node), (to, to_node)) = (pair[0], pair[1]);
+            let last = i == bounds.len() - 2;
+            let mut orbit = OrbitDuty {
+                start: from,
+                end: to,
+                partial: !(from_node && to_node),
+                frames: 0,
+                acq_time: 0.0,
+                missing_periods: 0,
+                missing_time: 0.0,
+            };
+            let mut prev = from;
+            while let Some(&(timestamp, acq_time)) = frames.get(next) {
+                if timestamp >= to && !last {
+                    break;
+                }
+                orbit.frames += 1;
+                orbit.acq_time += acq_time;
+                self.count_gap(&mut orbit, timestamp - prev);
+                prev = timestamp;
+                next += 1;
+            }
+            self.count_gap(&mut orbit, to - prev);
+            orbits.push(orbit);
+        }
+        orbits
+    }
+
+    fn count_gap(&self, orbit: &mut OrbitDuty, interval: f64) {
+        if interval > self.gap {
+            orbit.missing_periods += 1;
+            orbit.missing_time += interval;
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(
+            fs::File::create(path)
+                .with_context(|| format!("cannot write duty cycle report {}", path.display()))?,
+        );
+        let orbits = self.orbits();
+        let duration: f64 = orbits.iter().map(|o| o.end - o.start).sum();
+        let acq_time: f64 = orbits.iter().map(|o| o.acq_time).sum();
+        writeln!(
+            writer,
+            "# Duty cycle per orbit (ascending node to ascending node), gap: {} s, orbits: {}, frames: {}, overall duty cycle: {:.4}",
+            self.gap,
+            orbits.len(),
+            self.frames.len(),
+            if duration > 0.0 {
+                acq_time / duration
+            } else {
+                0.0
+            }
+        )?;
+        writeln!(
+            writer,
+            "orbit\tstart\tend\tduration[s]\tpartial\tframes\tacq_time[s]\tduty_cycle\tmissing_periods\tmissing_time[s]"
+        )?;
+        for (i, orbit) in orbits.iter().enumerate() {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{:.3}\t{}\t{}\t{:.3}\t{:.4}\t{}\t{:.3}",
+                i + 1,
+                format_time(orbit.start),
+                format_time(orbit.end),
+                orbit.end - orbit.start,
+                orbit.partial,
+                orbit.frames,
+                orbit.acq_time,
+                orbit.duty_cycle(),
+                orbit.missing_periods,
+                orbit.missing_time
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gps(timestamp: f64, z: f64) -> GpsData {
+        GpsData {
+            timestamp,
+            j2000_x: 7e6,
+            j2000_z: z,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_duty_cycle() {
+        let mut duty = DutyCycle::new(50.0);
+        duty.use_track(&[
+            gps(0.0, -100.0),
+            gps(100.0, 100.0),
+            gps(200.0, -100.0),
+            gps(300.0, 100.0),
+            gps(400.0, -100.0),
+        ]);
+        assert_eq!(duty.nodes, vec![50.0, 250.0]);
+
+        for t in [10.0, 60.0, 90.0, 120.0] {
+            duty.add_frame(t, 20.0);
+        }
+        let mut other = DutyCycle::new(50.0);
+        for t in [350.0, 260.0] {
+            other.add_frame(t, 10.0);
+        }
+        duty.merge(&other);
+
+        let orbits = duty.orbits();
+        assert_eq!(orbits.len(), 3);
+        assert!(orbits[0].partial && !orbits[1].partial && orbits[2].partial);
+        assert_eq!((orbits[0].start, orbits[0].end), (0.0, 50.0));
+        assert_eq!((orbits[0].frames, orbits[0].missing_periods), (1, 0));
+
+        // frames at 60, 90 and 120 s, nothing from 120 to 250 s
+        let complete = &orbits[1];
+        assert_eq!((complete.frames, complete.acq_time), (3, 60.0));
+        assert_eq!(complete.duty_cycle(), 0.3);
+        assert_eq!(
+            (complete.missing_periods, complete.missing_time),
+            (1, 130.0)
+        );
+
+        // frames at 260 and 350 s, the track ends at 400 s
+        assert_eq!((orbits[2].frames, orbits[2].missing_periods), (2, 1));
+        assert_eq!(orbits[2].end, 400.0);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::orientation::Orientation;
+use crate::tpx3lut::MATRIX_SIZE;
+use crate::utils;
+use anyhow::{Result, bail};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// Names of the calibration matrices in the calibration directory
+const MATRICES: [&str; 4] = ["a.txt", "b.txt", "c.txt", "t.txt"];
+
+/// Per-pixel energy calibration of the Timepix surrogate function
+/// `tot = a * E + b - c / (E - t)` with E in keV
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergyCalibration {
+    pub a: Vec<f64>,
+    pub b: Vec<f64>,
+    pub c: Vec<f64>,
+    pub t: Vec<f64>,
+    /// SHA-256 of the four matrices, part of the repro hash
+    pub digest: String,
+}
+
+impl EnergyCalibration {
+    /// Loads the a, b, c and t matrices (a.txt, b.txt, c.txt, t.txt: 256 rows of 256
+    /// whitespace separated values) of the directory
+    pub fn load_dir(dir: &Path) -> Result<EnergyCalibration> {
+        let mut hasher = Sha256::new();
+        let mut matrices = Vec::with_capacity(MATRICES.len());
+        for name in MATRICES {
+            let path = dir.join(name);
+            let values = utils::load_ascii_matrix::<f64>(&path)?;
+            if values.len() != MATRIX_SIZE {
+                bail!(
+                    "{}: expected {} values (256x256), found {}",
+                    path.display(),
+                    MATRIX_SIZE,
+                    values.len()
+                );
+            }
+            if let Some(idx) = values.iter().position(|v| !v.is_finite()) {
+                bail!(
+                    "{}: value of pixel ({}, {}) is not finite",
+                    path.display(),
+                    idx % 256,
+                    idx / 256
+                );
+            }
+            for value in &values {
+                hasher.update(value.to_le_bytes());
+            }
+            matrices.push(values);
+        }
+        let t = matrices.pop().unwrap();
+        let c = matrices.pop().unwrap();
+        let b = matrices.pop().unwrap();
+        let a = matrices.pop().unwrap();
+        Ok(EnergyCalibration {
+            a,
+            b,
+            c,
+            t,
+            digest: hex::encode(hasher.finalize()),
+        })
+    }
+
+    /// Calibration indexed by the pixel coordinates in the orientation
+    pub fn oriented(&self, orientation: Orientation) -> EnergyCalibration {
+        EnergyCalibration {
+            a: orientation.matrix(&self.a).into_owned(),
+            b: orientation.matrix(&self.b).into_owned(),
+            c: orientation.matrix(&self.c).into_owned(),
+            t: orientation.matrix(&self.t).into_owned(),
+            digest: self.digest.clone(),
+        }
+    }
+
+    /// Energy in keV of the ToT (iToT) value of the pixel, the larger root of the surrogate
+    /// function; 0 for pixels without a calibration (a not positive)
+    pub fn energy(&self, idx: usize, tot: u16) -> f64 {
+        let (a, b, c, t) = (self.a[idx], self.b[idx], self.c[idx], self.t[idx]);
+        if a <= 0.0 || tot == 0 {
+            return 0.0;
+        }
+        let tot = tot as f64;
+        let p = a * t + tot - b;
+        let discriminant = (b + a * t - tot).powi(2) + 4.0 * a * c;
+        (p + discriminant.max(0.0).sqrt()) / (2.0 * a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_energy_calibration() {
+        let dir = std::env::temp_dir().join(format!("oneweb-calib-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let matrix = |value: f64| {
+            let row = vec![value.to_string(); 256].join(" ");
+            vec![row; 256].join("\n")
+        };
+        for (name, value) in MATRICES.iter().zip([1.6, 24.0, 250.0, 1.0]) {
+            fs::write(dir.join(name), matrix(value)).unwrap();
+        }
+        let calibration = EnergyCalibration::load_dir(&dir).unwrap();
+        // surrogate function at 60 keV: 1.6 * 60 + 24 - 250 / 59
+        let tot: f64 = 1.6 * 60.0 + 24.0 - 250.0 / 59.0;
+        let energy = calibration.energy(300, tot.round() as u16);
+        assert!((energy - 60.0).abs() < 1.0, "{}", energy);
+        assert!(calibration.energy(300, 200) > energy);
+        assert_eq!(calibration.energy(300, 0), 0.0);
+
+        fs::write(dir.join("t.txt"), "1 2 3").unwrap();
+        assert!(EnergyCalibration::load_dir(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,32 @@
+use std::io;
+use thiserror::Error;
+
+/// Errors of the decoding callers can match on, carried in `anyhow::Error` and recovered with
+/// `downcast_ref::<OnewebError>()`
+#[derive(Debug, Error)]
+pub enum OnewebError {
+    /// The data file is exhausted, the normal end of a conversion
+    #[error("No more data available")]
+    EndOfData,
+    /// The GPS file ended before the data file, the conversion stops at the last matched frame
+    #[error("No more GPS records available")]
+    GpsMissing,
+    /// The measurement info file has no record to match the frames to
+    #[error("No measurement info records available")]
+    MeasInfoMissing,
+    /// A line of an input file could not be decoded
+    #[error("{location}: {message}")]
+    Parse {
+        /// `file:line` of the line
+        location: String,
+        message: String,
+    },
+    /// Reading an input file failed
+    #[error("{path}: {source}")]
+    Io {
+        /// File, with the line number when reading a line
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+}
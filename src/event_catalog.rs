@@ -0,0 +1,325 @@
+use crate::binning::PositionBinning;
+use crate::classification::ClassThresholds;
+use crate::clustering::Cluster;
+use crate::event_display::cluster_energy;
+use crate::gps_processor::GpsData;
+use crate::orbit;
+use crate::utils::format_time;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Side of the pixel matrix
+const SIDE: usize = 256;
+/// Frame pixels around the cluster bounding box included in the crop
+const CROP_MARGIN: usize = 3;
+
+/// Part of the frame iToT matrix around a cluster
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Crop {
+    /// Column and row of the top left pixel
+    pub x: usize,
+    pub y: usize,
+    /// Rows of the crop, all frame pixels (also of other clusters) in the output orientation
+    pub itot: Vec<Vec<u16>>,
+}
+
+impl Crop {
+    pub fn new(matrix: &[u16], cluster: &Cluster) -> Self {
+        let (x_min, y_min, x_max, y_max) = cluster.bounding_box();
+        let x = (x_min as usize).saturating_sub(CROP_MARGIN);
+        let y = (y_min as usize).saturating_sub(CROP_MARGIN);
+        let x_end = (x_max as usize + CROP_MARGIN + 1).min(SIDE);
+        let y_end = (y_max as usize + CROP_MARGIN + 1).min(SIDE);
+        Crop {
+            x,
+            y,
+            itot: (y..y_end)
+                .map(|row| matrix[row * SIDE + x..row * SIDE + x_end].to_vec())
+                .collect(),
+        }
+    }
+}
+
+/// Subsatellite point of the GPS record matched to the frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude_km: f64,
+    pub l_shell: f64,
+    /// Radiation region (SAA, outer belt, polar or quiet)
+    pub region: String,
+}
+
+impl Location {
+    /// Location of the record with the binning of the position columns
+    pub fn new(gps_data: &GpsData, binning: &PositionBinning) -> Self {
+        let ecef = orbit::j2000_to_ecef(
+            [gps_data.j2000_x, gps_data.j2000_y, gps_data.j2000_z],
+            gps_data.timestamp,
+        );
+        let geo = orbit::ecef_to_geodetic(ecef);
+        let l_shell = orbit::dipole_l_shell(ecef);
+        Location {
+            latitude: PositionBinning::apply(binning.lat, geo.latitude),
+            longitude: PositionBinning::apply(binning.lon, geo.longitude),
+            altitude_km: PositionBinning::apply(binning.alt, geo.altitude / 1000.0),
+            l_shell: PositionBinning::apply(binning.l_shell, l_shell),
+            region: orbit::radiation_region(&geo, l_shell).to_string(),
+        }
+    }
+}
+
+/// Energetic cluster with the context needed to review it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogEvent {
+    pub time: String,
+    pub timestamp: f64,
+    /// Number of the frame in the output files
+    pub frame: usize,
+    pub orbit: Option<usize>,
+    pub energy_kev: f64,
+    pub pixels: usize,
+    pub class: String,
+    /// Fraction of saturated pixels, the energy is a lower bound when above 0
+    pub saturation: f64,
+    /// Position at the frame time, None without a current GPS record
+    pub location: Option<Location>,
+    /// Attitude quaternion (scalar first), None without a measured attitude
+    pub attitude: Option<[f64; 4]>,
+    pub crop: Crop,
+}
+
+/// Frame and GPS context shared by the clusters of a frame
+pub struct FrameContext<'a> {
+    pub timestamp: f64,
+    pub frame: usize,
+    /// iToT matrix in the output orientation
+    pub itot: &'a [u16],
+    /// Matched GPS record, None when stale or missing
+    pub gps: Option<&'a GpsData>,
+    pub binning: PositionBinning,
+}
+
+impl CatalogEvent {
+    pub fn new(
+        cluster: &Cluster,
+        context: &FrameContext,
+        classification: &ClassThresholds,
+        kev_per_count: f64,
+    ) -> Self {
+        CatalogEvent {
+            time: format_time(context.timestamp),
+            timestamp: context.timestamp,
+            frame: context.frame,
+            orbit: None,
+            energy_kev: cluster_energy(cluster, kev_per_count),
+            pixels: cluster.pixels.len(),
+            class: classification
+                .classify(&cluster.analyze(), kev_per_count)
+                .to_string(),
+            saturation: cluster.saturation(),
+            location: context.gps.map(|gps| Location::new(gps, &context.binning)),
+            attitude: context
+                .gps
+                .filter(|gps| !gps.propagated)
+                .map(|gps| gps.quaternion()),
+            crop: Crop::new(context.itot, cluster),
+        }
+    }
+}
+
+/// Top K most energetic clusters of each day and of each orbit part within the day, the
+/// entries of a day are written to `events_<date>.json` once the day is finished
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventCatalog {
+    pub top_k: usize,
+    /// Ascending node times of the GPS track, orbit 1 ends at the first; None without a track
+    pub nodes: Option<Vec<f64>>,
+    pub per_day: BTreeMap<String, Vec<CatalogEvent>>,
+    /// Lists of the orbits by day and orbit
+    pub per_orbit: BTreeMap<String, BTreeMap<usize, Vec<CatalogEvent>>>,
+}
+
+#[derive(Serialize)]
+struct OrbitEvents<'a> {
+    orbit: usize,
+    events: &'a [CatalogEvent],
+}
+
+#[derive(Serialize)]
+struct DayEvents<'a> {
+    date: &'a str,
+    top: usize,
+    events: &'a [CatalogEvent],
+    orbits: Vec<OrbitEvents<'a>>,
+}
+
+impl EventCatalog {
+    pub fn new(top_k: usize) -> Self {
+        EventCatalog {
+            top_k,
+            ..Default::default()
+        }
+    }
+
+    /// Numbers the orbits from the ascending nodes of the valid GPS records, like the duty
+    /// cycle report
+    pub fn use_track(&mut self, records: &[GpsData]) {
+        self.nodes = Some(orbit::ascending_nodes(
+            records
+                .iter()
+                .filter(|r| r.is_valid())
+                .map(|r| (r.timestamp, [r.j2000_x, r.j2000_y, r.j2000_z])),
+        ));
+    }
+
+    /// Orbit of the time, None without a GPS track
+    pub fn orbit(&self, timestamp: f64) -> Option<usize> {
+        self.nodes
+            .as_ref()
+            .map(|nodes| 1 + nodes.partition_point(|&t| t <= timestamp))
+    }
+
+    /// Whether a cluster of the energy would enter the day or orbit list, so the context is
+    /// only collected for the clusters kept
+    pub fn accepts(&self, date: &str, orbit: Option<usize>, energy: f64) -> bool {
+        let enters = |events: Option<&Vec<CatalogEvent>>| {
+            events.is_none_or(|events| {
+                events.len() < self.top_k || events.last().is_some_and(|l| l.energy_kev < energy)
+            })
+        };
+        enters(self.per_day.get(date))
+            || orbit.is_some_and(|orbit| {
+                enters(
+                    self.per_orbit
+                        .get(date)
+                        .and_then(|orbits| orbits.get(&orbit)),
+                )
+            })
+    }
+
+    pub fn add(&mut self, date: &str, mut event: CatalogEvent) {
+        event.orbit = self.orbit(event.timestamp);
+        if let Some(orbit) = event.orbit {
+            let events = self
+                .per_orbit
+                .entry(date.to_string())
+                .or_default()
+                .entry(orbit)
+                .or_default();
+            events.push(event.clone());
+            Self::sort_and_truncate(events, self.top_k);
+        }
+        let events = self.per_day.entry(date.to_string()).or_default();
+        events.push(event);
+        Self::sort_and_truncate(events, self.top_k);
+    }
+
+    fn sort_and_truncate(events: &mut Vec<CatalogEvent>, top_k: usize) {
+        events.sort_by(|a, b| {
+            b.energy_kev
+                .total_cmp(&a.energy_kev)
+                .then(a.timestamp.total_cmp(&b.timestamp))
+        });
+        events.truncate(top_k);
+    }
+
+    /// File name of the catalog of the day
+    pub fn file_name(date: &str) -> String {
+        format!("events_{}.json", date)
+    }
+
+    /// Writes the catalog of the finished day into the directory and drops its entries
+    pub fn save_day(&mut self, dir: &Path, date: &str) -> Result<()> {
+        let events = self.per_day.remove(date).unwrap_or_default();
+        let orbits = self.per_orbit.remove(date).unwrap_or_default();
+        let day = DayEvents {
+            date,
+            top: self.top_k,
+            events: &events,
+            orbits: orbits
+                .iter()
+                .map(|(orbit, events)| OrbitEvents {
+                    orbit: *orbit,
+                    events,
+                })
+                .collect(),
+        };
+        let path = dir.join(Self::file_name(date));
+        fs::write(&path, serde_json::to_string_pretty(&day)? + "\n")
+            .with_context(|| format!("cannot save event catalog {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clustering::Pixel;
+
+    #[test]
+    fn test_event_catalog() {
+        let mut itot = vec![0; SIDE * SIDE];
+        itot[2 * SIDE + 1] = 7;
+        let mut catalog = EventCatalog::new(2);
+        // ascending nodes at 100 s and 210 s
+        let track: Vec<GpsData> = [(50.0, -1.0), (150.0, 1.0), (160.0, -1.0), (260.0, 1.0)]
+            .iter()
+            .map(|&(timestamp, z)| GpsData {
+                timestamp,
+                j2000_x: 7.0e6,
+                j2000_z: z * 1.0e6,
+                ..Default::default()
+            })
+            .collect();
+        assert_eq!(catalog.orbit(20.0), None);
+        catalog.use_track(&track);
+        assert_eq!(catalog.orbit(20.0), Some(1));
+        assert_eq!(catalog.orbit(120.0), Some(2));
+
+        for (timestamp, value) in [(10.0, 30), (20.0, 10), (120.0, 20), (130.0, 5)] {
+            let cluster = Cluster {
+                pixels: vec![Pixel::new(1, 1, value, 1), Pixel::new(1, 2, 7, 1)],
+                merged: 0,
+            };
+            let energy = cluster_energy(&cluster, 1.0);
+            if !catalog.accepts("2024-03-01", catalog.orbit(timestamp), energy) {
+                continue;
+            }
+            let context = FrameContext {
+                timestamp,
+                frame: timestamp as usize,
+                itot: &itot,
+                gps: Some(&track[0]),
+                binning: PositionBinning::default(),
+            };
+            let event = CatalogEvent::new(&cluster, &context, &ClassThresholds::default(), 1.0);
+            catalog.add("2024-03-01", event);
+        }
+        let day = &catalog.per_day["2024-03-01"];
+        assert_eq!(
+            day.iter().map(|e| e.energy_kev).collect::<Vec<_>>(),
+            [37.0, 27.0]
+        );
+        // the weaker cluster of orbit 2 is kept in the orbit list only
+        assert_eq!(catalog.per_orbit["2024-03-01"][&2].len(), 2);
+        assert_eq!(day[0].class, "dot");
+        assert_eq!((day[0].crop.x, day[0].crop.y), (0, 0));
+        assert_eq!(day[0].crop.itot.len(), 6);
+        assert_eq!(day[0].crop.itot[2][1], 7);
+
+        let dir = std::env::temp_dir().join(format!("oneweb-catalog-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        catalog.save_day(&dir, "2024-03-01").unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("events_2024-03-01.json")).unwrap())
+                .unwrap();
+        assert_eq!(json["events"].as_array().unwrap().len(), 2);
+        assert_eq!(json["orbits"][1]["orbit"], 2);
+        assert!(catalog.per_orbit.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
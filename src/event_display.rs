@@ -0,0 +1,356 @@
+use crate::clustering::Cluster;
+use crate::utils::format_time;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+/// Size of a detector pixel in the rendered image
+const PIXEL_SCALE: f64 = 24.0;
+/// Empty pixels drawn around the cluster
+const MARGIN: i32 = 2;
+/// Mean pixel energy in keV above which a blob is labelled heavy
+const HEAVY_BLOB_KEV: f64 = 150.0;
+
+/// Pixel coordinates in the cluster principal axes
+struct Axes {
+    cx: f64,
+    cy: f64,
+    /// Unit vector of the major axis
+    dir: (f64, f64),
+    sigma_major: f64,
+    sigma_minor: f64,
+}
+
+fn principal_axes(cluster: &Cluster) -> Axes {
+    let n = cluster.pixels.len().max(1) as f64;
+    let cx = cluster.pixels.iter().map(|p| p.x as f64).sum::<f64>() / n;
+    let cy = cluster.pixels.iter().map(|p| p.y as f64).sum::<f64>() / n;
+    let (mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0);
+    for pix in &cluster.pixels {
+        let (dx, dy) = (pix.x as f64 - cx, pix.y as f64 - cy);
+        sxx += dx * dx / n;
+        syy += dy * dy / n;
+        sxy += dx * dy / n;
+    }
+    let angle = 0.5 * (2.0 * sxy).atan2(sxx - syy);
+    let root = (0.25 * (sxx - syy).powi(2) + sxy * sxy).sqrt();
+    let mean = 0.5 * (sxx + syy);
+    Axes {
+        cx,
+        cy,
+        dir: (angle.cos(), angle.sin()),
+        sigma_major: (mean + root).sqrt(),
+        sigma_minor: (mean - root).max(0.0).sqrt(),
+    }
+}
+
+/// Simple morphological label of the cluster (dot, blob or track), clusters with saturated
+/// pixels are labeled saturated as their energy is only a lower bound
+pub fn classify(cluster: &Cluster, kev_per_count: f64) -> &'static str {
+    if cluster.saturation() > 0.0 {
+        return "saturated";
+    }
+    let size = cluster.pixels.len();
+    if size <= 2 {
+        return "dot";
+    }
+    let axes = principal_axes(cluster);
+    let max_offset = cluster
+        .pixels
+        .iter()
+        .map(|p| ((p.x as f64 - axes.cx) * -axes.dir.1 + (p.y as f64 - axes.cy) * axes.dir.0).abs())
+        .fold(0.0, f64::max);
+    if axes.sigma_major > 2.0 * axes.sigma_minor.max(0.5) && max_offset <= 1.5 {
+        return "straight track";
+    }
+
+    let occupied: HashSet<(i32, i32)> = cluster
+        .pixels
+        .iter()
+        .map(|p| (p.x as i32, p.y as i32))
+        .collect();
+    let has_inner = occupied.iter().any(|&(x, y)| {
+        [(-1, 0), (1, 0), (0, -1), (0, 1)]
+            .iter()
+            .all(|(dx, dy)| occupied.contains(&(x + dx, y + dy)))
+    });
+    let (x_min, x_max, y_min, y_max) = occupied.iter().fold(
+        (i32::MAX, i32::MIN, i32::MAX, i32::MIN),
+        |(x0, x1, y0, y1), &(x, y)| (x0.min(x), x1.max(x), y0.min(y), y1.max(y)),
+    );
+    let area = ((x_max - x_min + 1) * (y_max - y_min + 1)) as f64;
+    if size <= 4 || has_inner || size as f64 / area > 0.5 {
+        let mean_energy = cluster_energy(cluster, kev_per_count) / size as f64;
+        return if mean_energy > HEAVY_BLOB_KEV {
+            "heavy blob"
+        } else {
+            "small blob"
+        };
+    }
+    "curly track"
+}
+
+pub fn cluster_energy(cluster: &Cluster, kev_per_count: f64) -> f64 {
+    cluster.pixels.iter().map(|p| p.value as f64).sum::<f64>() * kev_per_count
+}
+
+/// Unit vector of the cluster major axis in pixel coordinates (column, row)
+pub fn major_axis(cluster: &Cluster) -> (f64, f64) {
+    principal_axes(cluster).dir
+}
+
+/// Extent of the cluster along its major axis in pixels, 0 for a single pixel
+pub fn projected_length(cluster: &Cluster) -> f64 {
+    let axes = principal_axes(cluster);
+    let (min, max) = cluster
+        .pixels
+        .iter()
+        .fold((0.0f64, 0.0f64), |(min, max), p| {
+            let t = (p.x as f64 - axes.cx) * axes.dir.0 + (p.y as f64 - axes.cy) * axes.dir.1;
+            (min.min(t), max.max(t))
+        });
+    max - min
+}
+
+/// Polyline through the centroids of the one pixel wide slices along the major axis
+pub fn skeleton(cluster: &Cluster) -> Vec<(f64, f64)> {
+    let axes = principal_axes(cluster);
+    let mut slices: BTreeMap<i64, (f64, f64, f64)> = BTreeMap::new();
+    for pix in &cluster.pixels {
+        let (x, y) = (pix.x as f64 + 0.5, pix.y as f64 + 0.5);
+        let t = (x - axes.cx - 0.5) * axes.dir.0 + (y - axes.cy - 0.5) * axes.dir.1;
+        let slice = slices.entry(t.round() as i64).or_default();
+        let weight = pix.value.max(1) as f64;
+        slice.0 += x * weight;
+        slice.1 += y * weight;
+        slice.2 += weight;
+    }
+    slices
+        .values()
+        .map(|&(x, y, weight)| (x / weight, y / weight))
+        .collect()
+}
+
+/// Heat colour of the value relative to the maximum, blue (low) to red (high)
+fn heat_color(value: f64, max: f64) -> String {
+    let ratio = if max > 0.0 { value / max } else { 0.0 };
+    format!("hsl({:.0},85%,50%)", 240.0 * (1.0 - ratio.clamp(0.0, 1.0)))
+}
+
+/// Cluster selected for rendering
+#[derive(Debug, Clone)]
+pub struct EventDisplay {
+    pub timestamp: f64,
+    pub energy: f64,
+    pub label: &'static str,
+    pub cluster: Cluster,
+}
+
+impl EventDisplay {
+    pub fn new(cluster: &Cluster, timestamp: f64, kev_per_count: f64) -> Self {
+        EventDisplay {
+            timestamp,
+            energy: cluster_energy(cluster, kev_per_count),
+            label: classify(cluster, kev_per_count),
+            cluster: cluster.clone(),
+        }
+    }
+
+    /// Renders the cluster with pixel energies (keV), skeleton and label as SVG
+    pub fn render_svg(&self, kev_per_count: f64) -> String {
+        let pixels = &self.cluster.pixels;
+        let x_min = pixels.iter().map(|p| p.x as i32).min().unwrap_or(0) - MARGIN;
+        let y_min = pixels.iter().map(|p| p.y as i32).min().unwrap_or(0) - MARGIN;
+        let x_max = pixels.iter().map(|p| p.x as i32).max().unwrap_or(0) + MARGIN + 1;
+        let y_max = pixels.iter().map(|p| p.y as i32).max().unwrap_or(0) + MARGIN + 1;
+        let header = 3.0 * PIXEL_SCALE;
+        let width = ((x_max - x_min) as f64 * PIXEL_SCALE).max(12.0 * PIXEL_SCALE);
+        let height = (y_max - y_min) as f64 * PIXEL_SCALE + header;
+        let max_value = pixels.iter().map(|p| p.value).max().unwrap_or(0) as f64;
+        let px = |x: f64| (x - x_min as f64) * PIXEL_SCALE;
+        let py = |y: f64| (y - y_min as f64) * PIXEL_SCALE + header;
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" font-family="sans-serif">"#,
+            width, height
+        );
+        let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+        let _ = writeln!(
+            svg,
+            r#"<text x="4" y="{:.0}" font-size="14">{} | {:.1} keV | {} px</text>"#,
+            PIXEL_SCALE,
+            self.label,
+            self.energy,
+            pixels.len()
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="4" y="{:.0}" font-size="12">{} UTC</text>"#,
+            2.0 * PIXEL_SCALE,
+            format_time(self.timestamp)
+        );
+        let _ = writeln!(
+            svg,
+            r##"<rect x="0" y="{:.0}" width="{:.0}" height="{:.0}" fill="#f4f4f4"/>"##,
+            header,
+            (x_max - x_min) as f64 * PIXEL_SCALE,
+            height - header
+        );
+        for pix in pixels {
+            let (x, y) = (px(pix.x as f64), py(pix.y as f64));
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{:.0}" y="{:.0}" width="{:.0}" height="{:.0}" fill="{}" stroke="white"/>"#,
+                x,
+                y,
+                PIXEL_SCALE,
+                PIXEL_SCALE,
+                heat_color(pix.value as f64, max_value)
+            );
+            let _ = writeln!(
+                svg,
+                r#"<text x="{:.1}" y="{:.1}" font-size="7" text-anchor="middle" fill="white">{:.0}</text>"#,
+                x + 0.5 * PIXEL_SCALE,
+                y + 0.6 * PIXEL_SCALE,
+                pix.value as f64 * kev_per_count
+            );
+        }
+        let points: Vec<String> = skeleton(&self.cluster)
+            .iter()
+            .map(|&(x, y)| format!("{:.1},{:.1}", px(x), py(y)))
+            .collect();
+        if points.len() > 1 {
+            let _ = writeln!(
+                svg,
+                r#"<polyline points="{}" fill="none" stroke="black" stroke-width="2"/>"#,
+                points.join(" ")
+            );
+        }
+        let _ = writeln!(
+            svg,
+            r#"<text x="4" y="{:.0}" font-size="10">x {}..{}, y {}..{}</text>"#,
+            height - 4.0,
+            x_min + MARGIN,
+            x_max - MARGIN - 1,
+            y_min + MARGIN,
+            y_max - MARGIN - 1
+        );
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+/// Keeps the top N clusters by energy of each day
+#[derive(Debug, Clone, Default)]
+pub struct EventSelection {
+    pub top_n: usize,
+    pub per_day: BTreeMap<String, Vec<EventDisplay>>,
+}
+
+impl EventSelection {
+    pub fn new(top_n: usize) -> Self {
+        EventSelection {
+            top_n,
+            per_day: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, date: &str, event: EventDisplay) {
+        let events = self.per_day.entry(date.to_string()).or_default();
+        if events.len() >= self.top_n
+            && events.last().is_none_or(|last| last.energy >= event.energy)
+        {
+            return;
+        }
+        events.push(event);
+        Self::sort_and_truncate(events, self.top_n);
+    }
+
+    pub fn merge(&mut self, other: EventSelection) {
+        for (date, events) in other.per_day {
+            let merged = self.per_day.entry(date).or_default();
+            merged.extend(events);
+            Self::sort_and_truncate(merged, self.top_n);
+        }
+    }
+
+    fn sort_and_truncate(events: &mut Vec<EventDisplay>, top_n: usize) {
+        events.sort_by(|a, b| {
+            b.energy
+                .total_cmp(&a.energy)
+                .then(a.timestamp.total_cmp(&b.timestamp))
+        });
+        events.truncate(top_n);
+    }
+
+    /// Writes event_<date>_<rank>.svg files into the directory
+    pub fn save(&self, dir: &Path, kev_per_count: f64) -> Result<()> {
+        fs::create_dir_all(dir).with_context(|| format!("{}", dir.display()))?;
+        for (date, events) in &self.per_day {
+            for (rank, event) in events.iter().enumerate() {
+                let path = dir.join(format!("event_{}_{:02}.svg", date, rank + 1));
+                fs::write(&path, event.render_svg(kev_per_count))
+                    .with_context(|| format!("cannot save event display {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clustering::Pixel;
+
+    fn cluster(pixels: &[(u8, u8, u16)]) -> Cluster {
+        Cluster {
+            pixels: pixels
+                .iter()
+                .map(|&(x, y, value)| Pixel::new(x, y, value, 1))
+                .collect(),
+            merged: 0,
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify(&cluster(&[(5, 5, 10)]), 1.0), "dot");
+        let blob = cluster(&[(5, 5, 400), (6, 5, 400), (5, 6, 400), (6, 6, 400)]);
+        assert_eq!(classify(&blob, 1.0), "heavy blob");
+        assert_eq!(classify(&blob, 0.1), "small blob");
+        let track: Vec<_> = (0..10).map(|i| (10 + i, 20 + i / 3, 20)).collect();
+        assert_eq!(classify(&cluster(&track), 1.0), "straight track");
+        let curly: Vec<_> = (0..10)
+            .map(|i| (10 + i, 20 + (i as i32 - 5).unsigned_abs() as u8, 20))
+            .collect();
+        assert_eq!(classify(&cluster(&curly), 1.0), "curly track");
+
+        let mut saturated = blob.clone();
+        saturated.pixels[0].saturated = true;
+        assert_eq!(saturated.saturation(), 0.25);
+        assert_eq!(classify(&saturated, 1.0), "saturated");
+    }
+
+    #[test]
+    fn test_event_selection() {
+        let mut selection = EventSelection::new(2);
+        for (i, value) in [10, 30, 20, 5].iter().enumerate() {
+            let event = EventDisplay::new(&cluster(&[(1, 1, *value)]), i as f64, 1.0);
+            selection.add("2024-03-01", event);
+        }
+        let events = &selection.per_day["2024-03-01"];
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].energy, 30.0);
+        assert_eq!(events[1].energy, 20.0);
+
+        let track: Vec<_> = (0..6).map(|i| (10 + i, 20, 20)).collect();
+        let svg = EventDisplay::new(&cluster(&track), 1709251501.3, 1.0).render_svg(1.0);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("straight track | 120.0 keV | 6 px"));
+        assert!(svg.contains("<polyline"));
+    }
+}
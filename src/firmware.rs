@@ -0,0 +1,147 @@
+use crate::data_processor::PacketLayout;
+use anyhow::{Result, bail};
+use std::fmt;
+use std::str::FromStr;
+
+/// Payload firmware release identified by the release byte of the start of readout header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Release {
+    pub byte: u8,
+    pub name: &'static str,
+    /// Pixel packet layout of the release, None when its frames may use either layout
+    pub layout: Option<PacketLayout>,
+    /// Why the decoder cannot read the frames of the release, None when it can
+    pub unsupported: Option<&'static str>,
+}
+
+/// Releases known to the decoder. No payload ICD listing the release bytes is available,
+/// so the table only holds the releases seen in data: 0x74 is the release byte of the flight
+/// frames of the data file sample (`71AF0000 69 74`, see the `data_processor` tests), which
+/// decode with the standard layout, and the simulated frames of `conformance::Generator`
+/// leave the byte empty. Other release bytes are reported as unknown.
+pub const RELEASES: &[Release] = &[
+    Release {
+        byte: 0x00,
+        name: "simulated",
+        layout: None,
+        unsupported: None,
+    },
+    Release {
+        byte: 0x74,
+        name: "flight",
+        layout: Some(PacketLayout::Standard),
+        unsupported: None,
+    },
+];
+
+pub fn release(byte: u8) -> Option<&'static Release> {
+    RELEASES.iter().find(|r| r.byte == byte)
+}
+
+/// Whether the decoder reads the frames of a release byte correctly
+#[derive(Debug, Clone, PartialEq)]
+pub enum Compatibility {
+    Supported(&'static Release),
+    /// Release byte missing from the table, the frames may or may not be decoded correctly
+    Unknown(u8),
+    Unsupported(&'static Release, String),
+}
+
+/// Packet layout of the frames of the release byte, None when the table does not give it
+pub fn layout(byte: u8) -> Option<PacketLayout> {
+    release(byte).and_then(|release| release.layout)
+}
+
+/// Compatibility of the frames of the release byte; they are decoded with the layout of the
+/// release unless `forced` overrides it for all frames
+pub fn check(byte: u8, forced: Option<PacketLayout>) -> Compatibility {
+    let Some(release) = release(byte) else {
+        return Compatibility::Unknown(byte);
+    };
+    if let Some(reason) = release.unsupported {
+        return Compatibility::Unsupported(release, reason.to_string());
+    }
+    match (release.layout, forced) {
+        (Some(expected), Some(layout)) if expected != layout => Compatibility::Unsupported(
+            release,
+            format!(
+                "its frames use the {} packet layout, decoded as {}",
+                expected, layout
+            ),
+        ),
+        _ => Compatibility::Supported(release),
+    }
+}
+
+impl fmt::Display for Release {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({:#04x})", self.name, self.byte)
+    }
+}
+
+/// Handling of frames of a firmware release the decoder does not support
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FirmwarePolicy {
+    /// Stop the run at the first such frame
+    #[default]
+    Abort,
+    /// Warn once per release and decode the frames anyway
+    Warn,
+}
+
+impl FromStr for FirmwarePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "abort" => Ok(FirmwarePolicy::Abort),
+            "warn" => Ok(FirmwarePolicy::Warn),
+            _ => bail!("expected abort or warn"),
+        }
+    }
+}
+
+impl fmt::Display for FirmwarePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FirmwarePolicy::Abort => write!(f, "abort"),
+            FirmwarePolicy::Warn => write!(f, "warn"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let flight = release(0x74).unwrap();
+        assert_eq!(flight.to_string(), "flight (0x74)");
+        assert_eq!(check(0x74, None), Compatibility::Supported(flight));
+        assert_eq!(
+            check(0x74, Some(PacketLayout::Standard)),
+            Compatibility::Supported(flight)
+        );
+        assert_eq!(
+            check(0x74, Some(PacketLayout::Swapped)),
+            Compatibility::Unsupported(
+                flight,
+                "its frames use the standard packet layout, decoded as swapped".to_string()
+            )
+        );
+        // the layout of simulated frames is detected
+        assert!(matches!(
+            check(0x00, Some(PacketLayout::Swapped)),
+            Compatibility::Supported(_)
+        ));
+        assert_eq!(layout(0x00), None);
+        assert_eq!(layout(0x74), Some(PacketLayout::Standard));
+        assert_eq!(check(0x75, None), Compatibility::Unknown(0x75));
+        assert_eq!(
+            "warn".parse::<FirmwarePolicy>().unwrap(),
+            FirmwarePolicy::Warn
+        );
+        assert!("ignore".parse::<FirmwarePolicy>().is_err());
+    }
+}
@@ -0,0 +1,322 @@
+use crate::data_processor::Frame;
+use crate::orientation::Orientation;
+use anyhow::{Context, Result, bail};
+use std::fmt;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Side of the pixel matrix
+const SIZE: u32 = 256;
+
+/// Viridis at nine evenly spaced points
+const VIRIDIS: [[u8; 3]; 9] = [
+    [0x44, 0x01, 0x54],
+    [0x47, 0x2d, 0x7b],
+    [0x3b, 0x52, 0x8b],
+    [0x2c, 0x72, 0x8e],
+    [0x21, 0x91, 0x8c],
+    [0x28, 0xae, 0x80],
+    [0x5e, 0xc9, 0x62],
+    [0xad, 0xdc, 0x30],
+    [0xfd, 0xe7, 0x25],
+];
+
+/// Inferno at nine evenly spaced points
+const INFERNO: [[u8; 3]; 9] = [
+    [0x00, 0x00, 0x04],
+    [0x1f, 0x0c, 0x48],
+    [0x55, 0x0f, 0x6d],
+    [0x88, 0x22, 0x6a],
+    [0xba, 0x36, 0x55],
+    [0xe3, 0x59, 0x33],
+    [0xf9, 0x8e, 0x09],
+    [0xf8, 0xc9, 0x32],
+    [0xfc, 0xff, 0xa4],
+];
+
+/// Matrices of a frame rendered as images
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameMatrices {
+    #[default]
+    Itot,
+    Event,
+    Both,
+}
+
+impl FromStr for FrameMatrices {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "itot" => Ok(FrameMatrices::Itot),
+            "event" => Ok(FrameMatrices::Event),
+            "both" => Ok(FrameMatrices::Both),
+            _ => bail!("expected itot, event or both"),
+        }
+    }
+}
+
+impl fmt::Display for FrameMatrices {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            FrameMatrices::Itot => "itot",
+            FrameMatrices::Event => "event",
+            FrameMatrices::Both => "both",
+        })
+    }
+}
+
+/// File format of the frame images: colormapped 8-bit PNG for viewing, 16-bit grayscale
+/// TIFF with the raw matrix values for quantitative use
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Tiff,
+    Both,
+}
+
+impl FromStr for ImageFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "png" => Ok(ImageFormat::Png),
+            "tiff" => Ok(ImageFormat::Tiff),
+            "both" => Ok(ImageFormat::Both),
+            _ => bail!("expected png, tiff or both"),
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Tiff => "tiff",
+            ImageFormat::Both => "both",
+        })
+    }
+}
+
+/// Colors of the PNG images from the lowest to the highest value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Colormap {
+    Gray,
+    #[default]
+    Viridis,
+    Inferno,
+    /// Black, red, yellow, white
+    Hot,
+}
+
+impl Colormap {
+    /// Color of the normalized value (0 to 1)
+    pub fn color(self, t: f64) -> [u8; 3] {
+        let t = t.clamp(0.0, 1.0);
+        let channel = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        match self {
+            Colormap::Gray => [channel(t); 3],
+            Colormap::Viridis => interpolate(&VIRIDIS, t),
+            Colormap::Inferno => interpolate(&INFERNO, t),
+            Colormap::Hot => [
+                channel(3.0 * t),
+                channel(3.0 * t - 1.0),
+                channel(3.0 * t - 2.0),
+            ],
+        }
+    }
+}
+
+/// Linear interpolation between evenly spaced colors
+fn interpolate(points: &[[u8; 3]], t: f64) -> [u8; 3] {
+    let position = t * (points.len() - 1) as f64;
+    let i = (position.floor() as usize).min(points.len() - 2);
+    let fraction = position - i as f64;
+    let mut color = [0; 3];
+    for (c, value) in color.iter_mut().enumerate() {
+        let (a, b) = (points[i][c] as f64, points[i + 1][c] as f64);
+        *value = (a + (b - a) * fraction).round() as u8;
+    }
+    color
+}
+
+impl FromStr for Colormap {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "gray" => Ok(Colormap::Gray),
+            "viridis" => Ok(Colormap::Viridis),
+            "inferno" => Ok(Colormap::Inferno),
+            "hot" => Ok(Colormap::Hot),
+            _ => bail!("expected gray, viridis, inferno or hot"),
+        }
+    }
+}
+
+impl fmt::Display for Colormap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Colormap::Gray => "gray",
+            Colormap::Viridis => "viridis",
+            Colormap::Inferno => "inferno",
+            Colormap::Hot => "hot",
+        })
+    }
+}
+
+/// Mapping of the pixel values to the colormap, from 0 to the largest value of the matrix
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageScale {
+    Linear,
+    /// log(1 + value), shows the faint tracks next to the bright clusters
+    #[default]
+    Log,
+}
+
+impl ImageScale {
+    fn normalize(self, value: u16, max: u16) -> f64 {
+        if max == 0 {
+            return 0.0;
+        }
+        match self {
+            ImageScale::Linear => value as f64 / max as f64,
+            ImageScale::Log => (value as f64).ln_1p() / (max as f64).ln_1p(),
+        }
+    }
+}
+
+impl FromStr for ImageScale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "linear" => Ok(ImageScale::Linear),
+            "log" => Ok(ImageScale::Log),
+            _ => bail!("expected linear or log"),
+        }
+    }
+}
+
+impl fmt::Display for ImageScale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ImageScale::Linear => "linear",
+            ImageScale::Log => "log",
+        })
+    }
+}
+
+/// Image export of the frame matrices
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameImages {
+    pub matrices: FrameMatrices,
+    pub format: ImageFormat,
+    pub colormap: Colormap,
+    pub scale: ImageScale,
+}
+
+impl FrameImages {
+    /// Writes the images of the frame as <stem>_itot.png, <stem>_event.tiff etc. into the
+    /// directory, in the output orientation
+    pub fn write(
+        &self,
+        dir: &Path,
+        stem: &str,
+        frame: &Frame,
+        orientation: Orientation,
+    ) -> Result<()> {
+        let mut matrices = Vec::new();
+        if self.matrices != FrameMatrices::Event {
+            matrices.push(("itot", frame.itot()));
+        }
+        if self.matrices != FrameMatrices::Itot {
+            matrices.push(("event", frame.event()));
+        }
+        for (name, values) in matrices {
+            let values = orientation.matrix(values);
+            let mut images = Vec::new();
+            if self.format != ImageFormat::Tiff {
+                images.push(("png", encode_png(&values, self.colormap, self.scale)?));
+            }
+            if self.format != ImageFormat::Png {
+                images.push(("tiff", encode_tiff(&values)?));
+            }
+            for (extension, image) in images {
+                let path = dir.join(format!("{}_{}.{}", stem, name, extension));
+                fs::write(&path, image)
+                    .with_context(|| format!("cannot save frame image {}", path.display()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 8-bit RGB PNG of the 256x256 matrix (row major)
+pub fn encode_png(values: &[u16], colormap: Colormap, scale: ImageScale) -> Result<Vec<u8>> {
+    let max = values.iter().copied().max().unwrap_or(0);
+    let pixels: Vec<u8> = values
+        .iter()
+        .flat_map(|&value| colormap.color(scale.normalize(value, max)))
+        .collect();
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, SIZE, SIZE);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(out)
+}
+
+/// Uncompressed 16-bit grayscale TIFF of the 256x256 matrix (row major) with the raw values
+pub fn encode_tiff(values: &[u16]) -> Result<Vec<u8>> {
+    let mut out = Cursor::new(Vec::new());
+    tiff::encoder::TiffEncoder::new(&mut out)?
+        .write_image::<tiff::encoder::colortype::Gray16>(SIZE, SIZE, values)?;
+    Ok(out.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_images() {
+        assert_eq!(Colormap::Gray.color(0.0), [0, 0, 0]);
+        assert_eq!(Colormap::Viridis.color(1.0), [0xfd, 0xe7, 0x25]);
+        assert_eq!(Colormap::Hot.color(0.5), [255, 128, 0]);
+        assert_eq!(Colormap::Inferno.color(0.0625), [0x10, 0x06, 0x26]);
+        assert_eq!(ImageScale::Log.normalize(0, 0), 0.0);
+        assert!((ImageScale::Log.normalize(15, 255) - 0.5).abs() < 1e-12);
+
+        let mut values = vec![0u16; 256 * 256];
+        values[3 * 256 + 7] = 400;
+        values[3 * 256 + 8] = 20;
+
+        let png = encode_png(&values, Colormap::Gray, ImageScale::Linear).unwrap();
+        let mut reader = png::Decoder::new(Cursor::new(png)).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (256, 256));
+        assert_eq!(&pixels[(3 * 256 + 7) * 3..][..3], &[255, 255, 255]);
+        assert_eq!(&pixels[(3 * 256 + 8) * 3..][..3], &[13, 13, 13]);
+
+        let tiff = encode_tiff(&values).unwrap();
+        let mut decoder = tiff::decoder::Decoder::new(Cursor::new(tiff)).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (256, 256));
+        match decoder.read_image().unwrap() {
+            tiff::decoder::DecodingResult::U16(decoded) => assert_eq!(decoded, values),
+            _ => panic!("expected 16-bit values"),
+        }
+
+        for name in ["itot", "event", "both"] {
+            assert_eq!(name.parse::<FrameMatrices>().unwrap().to_string(), name);
+        }
+        assert_eq!("tiff".parse::<ImageFormat>().unwrap(), ImageFormat::Tiff);
+        assert!("jet".parse::<Colormap>().is_err());
+    }
+}
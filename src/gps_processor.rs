@@ -1,9 +1,31 @@
-use crate::utils::parse_time;
+use crate::drift::SchemaDrift;
+use crate::line_reader::LineReader;
+use crate::utils::{parse_field, parse_time};
+use crate::validate::InputKind;
 use anyhow::{Context, Result, bail};
-use std::io::{self, BufRead};
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// Plausible orbit radius band in m (100 km to 2000 km above the equatorial radius)
+pub const MIN_ORBIT_RADIUS: f64 = 6.478e6;
+pub const MAX_ORBIT_RADIUS: f64 = 8.378e6;
+/// Allowed deviation of the attitude quaternion norm from 1
+pub const QUATERNION_NORM_TOLERANCE: f64 = 1e-2;
+
+/// Column names of the GPS file header, without the units
+pub const COLUMNS: [&str; 8] = [
+    "TIME",
+    "J2000_X",
+    "J2000_Y",
+    "J2000_Z",
+    "iae_qEstProp_BJ.scalar",
+    "iae_qEstProp_BJ.vector(1)",
+    "iae_qEstProp_BJ.vector(2)",
+    "iae_qEstProp_BJ.vector(3)",
+];
 
 #[allow(dead_code)]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct GpsData {
     pub timestamp: f64,
     pub j2000_x: f64,
@@ -13,61 +35,230 @@ pub struct GpsData {
     pub q_est_prop_bj_vector_1: f64,
     pub q_est_prop_bj_vector_2: f64,
     pub q_est_prop_bj_vector_3: f64,
+    /// Problems found by the validity checks, empty for a valid record
+    pub problems: Vec<String>,
+    /// Position propagated from a TLE in place of a missing or invalid record, no attitude
+    pub propagated: bool,
+    /// Outlier position replaced by the interpolation between the neighbouring records
+    #[serde(default)]
+    pub repaired: bool,
+}
+
+#[allow(dead_code)]
+impl GpsData {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    pub fn quaternion(&self) -> [f64; 4] {
+        [
+            self.q_est_prop_bj_scalar,
+            self.q_est_prop_bj_vector_1,
+            self.q_est_prop_bj_vector_2,
+            self.q_est_prop_bj_vector_3,
+        ]
+    }
+
+    /// Record of a J2000 position in m propagated from a TLE, without attitude
+    pub fn propagated(timestamp: f64, position: [f64; 3]) -> GpsData {
+        GpsData {
+            timestamp,
+            j2000_x: position[0],
+            j2000_y: position[1],
+            j2000_z: position[2],
+            propagated: true,
+            ..Default::default()
+        }
+    }
+
+    /// Attitude quaternion scaled to unit norm, None for a zero quaternion
+    pub fn quaternion_normalized(&self) -> Option<[f64; 4]> {
+        let q = self.quaternion();
+        let norm = q.iter().map(|v| v * v).sum::<f64>().sqrt();
+        if norm == 0.0 || !norm.is_finite() {
+            return None;
+        }
+        Some(q.map(|v| v / norm))
+    }
+
+    /// Checks the position is in the LEO band and the quaternion has unit norm
+    fn validate(&mut self) {
+        self.validate_position();
+        let norm = self.quaternion().iter().map(|v| v * v).sum::<f64>().sqrt();
+        if norm == 0.0 {
+            self.problems.push("zero quaternion".to_string());
+        } else if !norm.is_finite() || (norm - 1.0).abs() > QUATERNION_NORM_TOLERANCE {
+            self.problems.push(format!("quaternion norm {:.6}", norm));
+        }
+    }
+
+    fn validate_position(&mut self) {
+        let radius = (self.j2000_x.powi(2) + self.j2000_y.powi(2) + self.j2000_z.powi(2)).sqrt();
+        if !(MIN_ORBIT_RADIUS..=MAX_ORBIT_RADIUS).contains(&radius) {
+            self.problems.push(format!(
+                "position radius {:.0} m outside the LEO band",
+                radius
+            ));
+        }
+    }
+
+    /// Replaces the position, its problems are checked again
+    pub fn set_position(&mut self, position: [f64; 3]) {
+        [self.j2000_x, self.j2000_y, self.j2000_z] = position;
+        self.problems.retain(|problem| {
+            !problem.starts_with("position radius")
+                && !COLUMNS[1..4].iter().any(|name| problem.contains(name))
+        });
+        self.validate_position();
+    }
 }
 
 #[allow(dead_code)]
 pub struct GpsProcessor {}
 
+impl Default for GpsProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[allow(dead_code)]
 impl GpsProcessor {
     pub fn new() -> GpsProcessor {
         GpsProcessor {}
     }
 
-    fn parse_line(line: &str) -> Result<GpsData> {
+    pub(crate) fn parse_line(line: &str) -> Result<GpsData> {
         //"TIME","J2000_X (m)","J2000_Y (m)","J2000_Z (m)","iae_qEstProp_BJ.scalar","iae_qEstProp_BJ.vector(1)","iae_qEstProp_BJ.vector(2)","iae_qEstProp_BJ.vector(3)"
         //2024-03-01 00:00:09.000,2.51279e+6,5.64324e+5,-6.50431e+6,9.64920e-1,5.96500e-3,-1.87169e-1,1.84013e-1
 
         let parts: Vec<&str> = line.trim().split(',').collect();
         if parts.len() != 8 {
-            bail!("Invalid line format");
+            bail!(
+                "invalid line format, expected 8 columns, got {}",
+                parts.len()
+            );
         }
 
-        let timestamp = parse_time(parts[0]).context(format!("invalid line: {}", &parts[0]))?;
-        let j2000_x: f64 = parts[1].parse().unwrap_or(0.0);
-        let j2000_y: f64 = parts[2].parse().unwrap_or(0.0);
-        let j2000_z: f64 = parts[3].parse().unwrap_or(0.0);
-        let q_est_prop_bj_scalar: f64 = parts[4].parse().unwrap_or(0.0);
-        let q_est_prop_bj_vector_1: f64 = parts[5].parse().unwrap_or(0.0);
-        let q_est_prop_bj_vector_2: f64 = parts[6].parse().unwrap_or(0.0);
-        let q_est_prop_bj_vector_3: f64 = parts[7].parse().unwrap_or(0.0);
-        Ok(GpsData {
+        let timestamp =
+            parse_time(parts[0]).with_context(|| format!("cannot parse TIME '{}'", parts[0]))?;
+        let mut problems = Vec::new();
+        let mut values = [0.0; 7];
+        for (i, value) in values.iter_mut().enumerate() {
+            match parse_field::<f64>(parts[i + 1], COLUMNS[i + 1]) {
+                Ok(v) => *value = v,
+                Err(e) => problems.push(e.to_string()),
+            }
+        }
+        let mut data = GpsData {
             timestamp,
-            j2000_x,
-            j2000_y,
-            j2000_z,
-            q_est_prop_bj_scalar,
-            q_est_prop_bj_vector_1,
-            q_est_prop_bj_vector_2,
-            q_est_prop_bj_vector_3,
-        })
+            j2000_x: values[0],
+            j2000_y: values[1],
+            j2000_z: values[2],
+            q_est_prop_bj_scalar: values[3],
+            q_est_prop_bj_vector_1: values[4],
+            q_est_prop_bj_vector_2: values[5],
+            q_est_prop_bj_vector_3: values[6],
+            problems,
+            propagated: false,
+            repaired: false,
+        };
+        data.validate();
+        Ok(data)
+    }
+
+    /// Returns the next GPS record, None at the end of the file
+    pub fn get_next_gps_data<R>(&self, reader: &mut LineReader<R>) -> Result<Option<GpsData>>
+    where
+        R: io::Read,
+    {
+        self.next_gps_data(reader, None)
     }
 
-    pub fn get_next_gps_data<R>(&self, reader: &mut io::BufReader<R>) -> Result<GpsData>
+    /// Returns the next GPS record, lines not matching the schema are recorded in the drift
+    /// log (up to `max_samples` verbatim per file) and skipped instead of failing
+    pub fn get_next_gps_data_tolerant<R>(
+        &self,
+        reader: &mut LineReader<R>,
+        drift: &mut SchemaDrift,
+        max_samples: usize,
+    ) -> Result<Option<GpsData>>
     where
         R: io::Read,
     {
-        for line in reader.lines() {
-            let line = line?;
+        self.next_gps_data(reader, Some((drift, max_samples)))
+    }
+
+    fn next_gps_data<R>(
+        &self,
+        reader: &mut LineReader<R>,
+        mut drift: Option<(&mut SchemaDrift, usize)>,
+    ) -> Result<Option<GpsData>>
+    where
+        R: io::Read,
+    {
+        while let Some(line) = reader.next_line()? {
             let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
             if !line.starts_with("20") {
-                continue; // Skip header line
+                // header line
+                if let Some((drift, max_samples)) = &mut drift {
+                    drift.record(
+                        InputKind::Gps,
+                        reader.source(),
+                        reader.line_no(),
+                        line,
+                        *max_samples,
+                    );
+                }
+                continue;
+            }
+            match (GpsProcessor::parse_line(line), &mut drift) {
+                (Ok(data), _) => return Ok(Some(data)),
+                (Err(_), Some((drift, max_samples))) => {
+                    drift.record(
+                        InputKind::Gps,
+                        reader.source(),
+                        reader.line_no(),
+                        line,
+                        *max_samples,
+                    );
+                }
+                (Err(e), None) => return Err(reader.error_at(e)),
             }
-            return Ok(
-                GpsProcessor::parse_line(&line).context(format!("cannot parse gps: {}", &line))?
-            );
         }
-        bail!("No more GPS data available");
+        Ok(None)
+    }
+
+    /// Reads all remaining GPS records
+    pub fn read_all<R>(&self, reader: &mut LineReader<R>) -> Result<Vec<GpsData>>
+    where
+        R: io::Read,
+    {
+        let mut records = Vec::new();
+        while let Some(data) = self.get_next_gps_data(reader)? {
+            records.push(data);
+        }
+        Ok(records)
+    }
+
+    /// Reads all remaining GPS records, skipping the lines not matching the schema
+    pub fn read_all_tolerant<R>(
+        &self,
+        reader: &mut LineReader<R>,
+        drift: &mut SchemaDrift,
+        max_samples: usize,
+    ) -> Result<Vec<GpsData>>
+    where
+        R: io::Read,
+    {
+        let mut records = Vec::new();
+        while let Some(data) = self.get_next_gps_data_tolerant(reader, drift, max_samples)? {
+            records.push(data);
+        }
+        Ok(records)
     }
 }
 
@@ -88,19 +279,58 @@ mod tests {
         assert_eq!(gps_data.q_est_prop_bj_vector_1, 5.96500e-3);
         assert_eq!(gps_data.q_est_prop_bj_vector_2, -1.87169e-1);
         assert_eq!(gps_data.q_est_prop_bj_vector_3, 1.84013e-1);
+        assert!(gps_data.is_valid());
+    }
+
+    #[test]
+    fn test_validate() {
+        let line = "2024-03-01 00:00:09.000,2.51279e+6,5.64324e+5,-6.50431e+6,0,0,0,0";
+        let gps_data = GpsProcessor::parse_line(line).unwrap();
+        assert_eq!(gps_data.problems, vec!["zero quaternion"]);
+        assert!(gps_data.quaternion_normalized().is_none());
+
+        let line = "2024-03-01 00:00:09.000,x,0,0,0.5,0.5,0.5,0.5";
+        let gps_data = GpsProcessor::parse_line(line).unwrap();
+        assert_eq!(gps_data.problems.len(), 2);
+        assert_eq!(gps_data.problems[0], "cannot parse J2000_X 'x'");
+        assert!(gps_data.problems[1].starts_with("position radius 0 m"));
+
+        let line = "2024-03-01 00:00:09.000,7e6,0,0,1.5,0,0,0";
+        let gps_data = GpsProcessor::parse_line(line).unwrap();
+        assert_eq!(gps_data.problems, vec!["quaternion norm 1.500000"]);
+        assert_eq!(gps_data.quaternion_normalized(), Some([1.0, 0.0, 0.0, 0.0]));
     }
 
     #[test]
     fn test_get_next_gps_data() {
-        let lines = vec![
+        let lines = [
             "\"TIME\",\"J2000_X (m)\",\"J2000_Y (m)\",\"J2000_Z (m)\",\"iae_qEstProp_BJ.scalar\",\"iae_qEstProp_BJ.vector(1)\",\"iae_qEstProp_BJ.vector(2)\",\"iae_qEstProp_BJ.vector(3)\"",
             "2024-03-01 00:00:09.000,2.51279e+6,5.64324e+5,-6.50431e+6,9.64920e-1,5.96500e-3,-1.87169e-1,1.84013e-1",
         ];
         let data = lines.join("\n");
         let cursor = Cursor::new(data);
-        let mut reader = io::BufReader::new(cursor);
+        let mut reader = LineReader::new(io::BufReader::new(cursor), "gps.csv");
         let gps_processor = GpsProcessor::new();
         let gps_data = gps_processor.get_next_gps_data(&mut reader).unwrap();
-        assert_eq!(gps_data.timestamp, 1709251209.0);
+        assert_eq!(gps_data.unwrap().timestamp, 1709251209.0);
+        assert!(
+            gps_processor
+                .get_next_gps_data(&mut reader)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_get_next_gps_data_error_location() {
+        let data = "\"TIME\"\n2024-03-01 00:00:09.000,1,2,3,4,5,6,7\n2024-03-01 0x:00:19.000,1,2,3,4,5,6,7";
+        let mut reader = LineReader::new(io::BufReader::new(Cursor::new(data)), "gps.csv");
+        let gps_processor = GpsProcessor::new();
+        assert!(gps_processor.get_next_gps_data(&mut reader).is_ok());
+        let err = gps_processor.get_next_gps_data(&mut reader).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("gps.csv:3: cannot parse TIME '2024-03-01 0x:00:19.000'")
+        );
     }
 }
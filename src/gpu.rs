@@ -0,0 +1,345 @@
+use crate::clustering::Labeler;
+use anyhow::Result;
+#[cfg(not(feature = "gpu"))]
+use anyhow::bail;
+use std::sync::Arc;
+
+/// Opens the GPU labeler, an error when no adapter is found or the build has no GPU support
+#[cfg(not(feature = "gpu"))]
+pub fn open() -> Result<Arc<dyn Labeler>> {
+    bail!("built without the gpu feature (cargo build --release --features gpu)")
+}
+
+/// Opens the GPU labeler, an error when no adapter is found or the build has no GPU support
+#[cfg(feature = "gpu")]
+pub fn open() -> Result<Arc<dyn Labeler>> {
+    Ok(Arc::new(wgpu_labeler::WgpuLabeler::new()?))
+}
+
+#[cfg(feature = "gpu")]
+mod wgpu_labeler {
+    use crate::clustering::{Labeler, NO_LABEL};
+    use anyhow::{Context, Result, anyhow};
+    use std::fmt;
+    use std::sync::Mutex;
+
+    const WIDTH: u32 = 256;
+    const PIXELS: u32 = WIDTH * WIDTH;
+    const WORKGROUP_SIZE: u32 = 256;
+    /// Propagation passes submitted between two convergence checks
+    const PASSES_PER_CHECK: usize = 8;
+
+    /// Every hit pixel starts with its own index and takes the minimum label of its
+    /// 8-connected neighbours and of its label pixel until no label changes; the result
+    /// is the smallest index of the component.
+    const SHADER: &str = r#"
+@group(0) @binding(0) var<storage, read> hits: array<u32>;
+@group(0) @binding(1) var<storage, read_write> labels: array<atomic<u32>>;
+@group(0) @binding(2) var<storage, read_write> changed: atomic<u32>;
+
+const WIDTH: i32 = 256;
+const NO_LABEL: u32 = 0xffffffffu;
+
+@compute @workgroup_size(256)
+fn init(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if (hits[i] != 0u) {
+        atomicStore(&labels[i], i);
+    } else {
+        atomicStore(&labels[i], NO_LABEL);
+    }
+}
+
+@compute @workgroup_size(256)
+fn propagate(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    let own = atomicLoad(&labels[i]);
+    if (own == NO_LABEL) {
+        return;
+    }
+    var m = min(own, atomicLoad(&labels[own]));
+    let x = i32(i) % WIDTH;
+    let y = i32(i) / WIDTH;
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            let nx = x + dx;
+            let ny = y + dy;
+            if (nx >= 0 && ny >= 0 && nx < WIDTH && ny < WIDTH) {
+                m = min(m, atomicLoad(&labels[u32(ny * WIDTH + nx)]));
+            }
+        }
+    }
+    if (m < own) {
+        atomicMin(&labels[i], m);
+        atomicStore(&changed, 1u);
+    }
+}
+"#;
+
+    struct Buffers {
+        hits: wgpu::Buffer,
+        labels: wgpu::Buffer,
+        changed: wgpu::Buffer,
+        readback: wgpu::Buffer,
+        bind_group: wgpu::BindGroup,
+    }
+
+    /// Connected component labeling with a wgpu compute shader
+    pub struct WgpuLabeler {
+        adapter: String,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        init: wgpu::ComputePipeline,
+        propagate: wgpu::ComputePipeline,
+        /// The buffers are shared by the decoding threads
+        buffers: Mutex<Buffers>,
+    }
+
+    impl fmt::Debug for WgpuLabeler {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}", self.adapter)
+        }
+    }
+
+    impl WgpuLabeler {
+        pub fn new() -> Result<Self> {
+            let instance = wgpu::Instance::default();
+            let adapter =
+                pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    ..Default::default()
+                }))
+                .ok_or_else(|| anyhow!("no GPU adapter found"))?;
+            let info = adapter.get_info();
+            let (device, queue) = pollster::block_on(adapter.request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("clustering"),
+                    required_limits: adapter.limits(),
+                    ..Default::default()
+                },
+                None,
+            ))
+            .with_context(|| format!("cannot open GPU {}", info.name))?;
+
+            let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("labeling"),
+                source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+            });
+            let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            };
+            // both entry points share the bindings, init does not use all of them
+            let bind_group_layout =
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("labeling"),
+                    entries: &[storage(0, true), storage(1, false), storage(2, false)],
+                });
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("labeling"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            let pipeline = |entry_point| {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: Some(&layout),
+                    module: &module,
+                    entry_point: Some(entry_point),
+                    compilation_options: Default::default(),
+                    cache: None,
+                })
+            };
+            let (init, propagate) = (pipeline("init"), pipeline("propagate"));
+
+            let buffer = |label, size, usage| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(label),
+                    size,
+                    usage,
+                    mapped_at_creation: false,
+                })
+            };
+            let labels_size = (PIXELS * 4) as u64;
+            let hits = buffer(
+                "hits",
+                labels_size,
+                wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            );
+            let labels = buffer(
+                "labels",
+                labels_size,
+                wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            );
+            let changed = buffer(
+                "changed",
+                4,
+                wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+            );
+            let readback = buffer(
+                "readback",
+                labels_size,
+                wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            );
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("labeling"),
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: hits.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: labels.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: changed.as_entire_binding(),
+                    },
+                ],
+            });
+            Ok(WgpuLabeler {
+                adapter: format!("{} ({:?})", info.name, info.backend),
+                device,
+                queue,
+                init,
+                propagate,
+                buffers: Mutex::new(Buffers {
+                    hits,
+                    labels,
+                    changed,
+                    readback,
+                    bind_group,
+                }),
+            })
+        }
+
+        fn dispatch(
+            &self,
+            encoder: &mut wgpu::CommandEncoder,
+            pipeline: &wgpu::ComputePipeline,
+            bind_group: &wgpu::BindGroup,
+        ) {
+            let mut pass = encoder.begin_compute_pass(&Default::default());
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.dispatch_workgroups(PIXELS / WORKGROUP_SIZE, 1, 1);
+        }
+
+        /// Copies the first `size` bytes of the buffer to the readback buffer and reads them
+        fn read(&self, buffers: &Buffers, source: &wgpu::Buffer, size: u64) -> Result<Vec<u32>> {
+            let mut encoder = self.device.create_command_encoder(&Default::default());
+            encoder.copy_buffer_to_buffer(source, 0, &buffers.readback, 0, size);
+            self.queue.submit([encoder.finish()]);
+            let slice = buffers.readback.slice(..size);
+            let (sender, receiver) = std::sync::mpsc::channel();
+            slice.map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+            self.device.poll(wgpu::Maintain::Wait);
+            receiver
+                .recv()
+                .context("GPU readback")?
+                .context("GPU readback")?;
+            let values = slice
+                .get_mapped_range()
+                .chunks_exact(4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            buffers.readback.unmap();
+            Ok(values)
+        }
+    }
+
+    impl Labeler for WgpuLabeler {
+        fn label(&self, frame: &[u16]) -> Result<Vec<u32>> {
+            if frame.len() != PIXELS as usize {
+                return Err(anyhow!(
+                    "GPU labeling expects {} pixels, got {}",
+                    PIXELS,
+                    frame.len()
+                ));
+            }
+            if frame.iter().all(|&v| v == 0) {
+                return Ok(vec![NO_LABEL; frame.len()]);
+            }
+            let buffers = self
+                .buffers
+                .lock()
+                .map_err(|_| anyhow!("GPU labeler poisoned"))?;
+            let hits: Vec<u8> = frame
+                .iter()
+                .flat_map(|&v| u32::from(v).to_le_bytes())
+                .collect();
+            self.queue.write_buffer(&buffers.hits, 0, &hits);
+            let mut encoder = self.device.create_command_encoder(&Default::default());
+            self.dispatch(&mut encoder, &self.init, &buffers.bind_group);
+            self.queue.submit([encoder.finish()]);
+            loop {
+                self.queue
+                    .write_buffer(&buffers.changed, 0, &0u32.to_le_bytes());
+                let mut encoder = self.device.create_command_encoder(&Default::default());
+                for _ in 0..PASSES_PER_CHECK {
+                    self.dispatch(&mut encoder, &self.propagate, &buffers.bind_group);
+                }
+                self.queue.submit([encoder.finish()]);
+                if self.read(&buffers, &buffers.changed, 4)?[0] == 0 {
+                    break;
+                }
+            }
+            self.read(&buffers, &buffers.labels, (PIXELS * 4) as u64)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clustering::{self, Clusterer};
+
+    #[test]
+    fn test_gpu_labels() {
+        let labeler = match open() {
+            Ok(labeler) => labeler,
+            Err(e) => {
+                // machines without the feature or an adapter fall back to the CPU
+                assert!(!e.to_string().is_empty());
+                return;
+            }
+        };
+        let mut frame = vec![0u16; 256 * 256];
+        // spiral track, a snake joined only at its end and edge pixels
+        for i in 0..200 {
+            frame[(100 + i % 50) + 256 * (30 + 2 * (i / 50))] = 1 + i as u16;
+        }
+        for y in 30..37 {
+            frame[150 + 256 * y] = 9;
+        }
+        for idx in [0, 255, 256 * 255, 256 * 256 - 1, 256 * 128 + 255, 256 * 129] {
+            frame[idx] = 3;
+        }
+        let event = vec![0u16; frame.len()];
+        let cpu = Clusterer::new().search_frame(&frame, &event, 256, 256);
+        let labels = labeler.label(&frame).unwrap();
+        assert_eq!(
+            labels,
+            clustering::labels_from_clusters(&cpu, 256, frame.len())
+        );
+        assert!(
+            labeler
+                .label(&vec![0u16; frame.len()])
+                .unwrap()
+                .iter()
+                .all(|&l| l == clustering::NO_LABEL)
+        );
+    }
+}
@@ -0,0 +1,300 @@
+use crate::data_processor::PixelMask;
+use crate::tpx3lut::{MATRIX_SIZE, WRONG_LUT_ITOT};
+use crate::utils;
+use anyhow::{Context, Result, bail};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Magic and version of the persisted sketches
+const MAGIC: &[u8] = b"ONEWEB-HOTPIX-1\n";
+/// Register index bits of the sketch of all frames (4096 registers, 1.6% standard error)
+const FRAME_PRECISION: u32 = 12;
+/// Register index bits of the per-pixel sketches (64 registers, 13% standard error)
+const PIXEL_PRECISION: u32 = 6;
+const PIXEL_REGISTERS: usize = 1 << PIXEL_PRECISION;
+
+/// Updates the registers with the hash, the top bits select the register and the
+/// position of the first set bit of the rest is kept as the maximum
+fn insert(registers: &mut [u8], precision: u32, hash: u64) {
+    let index = (hash >> (64 - precision)) as usize;
+    let rank = ((hash << precision).leading_zeros() + 1).min(64 - precision + 1) as u8;
+    if registers[index] < rank {
+        registers[index] = rank;
+    }
+}
+
+/// HyperLogLog estimate of the number of distinct hashes inserted into the registers,
+/// with the linear counting correction for small numbers
+fn estimate(registers: &[u8]) -> f64 {
+    let m = registers.len() as f64;
+    let alpha = match registers.len() {
+        16 => 0.673,
+        32 => 0.697,
+        64 => 0.709,
+        _ => 0.7213 / (1.0 + 1.079 / m),
+    };
+    let sum: f64 = registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+    let raw = alpha * m * m / sum;
+    let zeros = registers.iter().filter(|&&r| r == 0).count();
+    if raw <= 2.5 * m && zeros > 0 {
+        m * (m / zeros as f64).ln()
+    } else {
+        raw
+    }
+}
+
+/// Per-pixel firing statistics over any number of runs in a fixed 4 MiB: HyperLogLog
+/// sketches of the distinct frames (by frame time) in which each pixel was hit and of all
+/// frames, so frames processed again in a later run are not counted twice
+#[derive(Debug, Clone)]
+pub struct HotPixelStats {
+    frames: Vec<u8>,
+    /// PIXEL_REGISTERS registers per pixel
+    pixels: Vec<u8>,
+}
+
+impl Default for HotPixelStats {
+    fn default() -> Self {
+        HotPixelStats {
+            frames: vec![0; 1 << FRAME_PRECISION],
+            pixels: vec![0; MATRIX_SIZE * PIXEL_REGISTERS],
+        }
+    }
+}
+
+impl HotPixelStats {
+    /// Loads the sketches, a missing file gives empty statistics
+    pub fn load(path: &Path) -> Result<HotPixelStats> {
+        if !path.exists() {
+            return Ok(HotPixelStats::default());
+        }
+        let content = fs::read(path)
+            .with_context(|| format!("cannot load hot pixel statistics {}", path.display()))?;
+        let mut stats = HotPixelStats::default();
+        let Some(registers) = content.strip_prefix(MAGIC) else {
+            bail!("{}: not a hot pixel statistics file", path.display());
+        };
+        if registers.len() != stats.frames.len() + stats.pixels.len() {
+            bail!(
+                "{}: expected {} registers, found {}",
+                path.display(),
+                stats.frames.len() + stats.pixels.len(),
+                registers.len()
+            );
+        }
+        let (frames, pixels) = registers.split_at(stats.frames.len());
+        stats.frames.copy_from_slice(frames);
+        stats.pixels.copy_from_slice(pixels);
+        Ok(stats)
+    }
+
+    /// Saves the sketches through a temporary file so an interrupted write keeps the old state
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&self.frames)?;
+        writer.write_all(&self.pixels)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("cannot save hot pixel statistics {}", path.display()))
+    }
+
+    /// Adds the hit pixels of the frame taken at the time
+    pub fn add_frame(&mut self, itot: &[u16], timestamp: f64) {
+        let hash = utils::mix64(timestamp.to_bits());
+        insert(&mut self.frames, FRAME_PRECISION, hash);
+        for (idx, &value) in itot.iter().enumerate() {
+            if value == 0 || value == WRONG_LUT_ITOT {
+                continue;
+            }
+            let registers = &mut self.pixels[idx * PIXEL_REGISTERS..(idx + 1) * PIXEL_REGISTERS];
+            insert(registers, PIXEL_PRECISION, hash);
+        }
+    }
+
+    /// Adds the pixels of the frame taken at the time removed by the mask, so they stay hot
+    /// in the statistics
+    pub fn add_masked(&mut self, masked: &PixelMask, timestamp: f64) {
+        let hash = utils::mix64(timestamp.to_bits());
+        for idx in masked.indices() {
+            let registers = &mut self.pixels[idx * PIXEL_REGISTERS..(idx + 1) * PIXEL_REGISTERS];
+            insert(registers, PIXEL_PRECISION, hash);
+        }
+    }
+
+    pub fn merge(&mut self, other: &HotPixelStats) {
+        for (register, &other) in self.frames.iter_mut().zip(&other.frames) {
+            *register = (*register).max(other);
+        }
+        for (register, &other) in self.pixels.iter_mut().zip(&other.pixels) {
+            *register = (*register).max(other);
+        }
+    }
+
+    /// Estimated number of distinct frames
+    pub fn frames(&self) -> f64 {
+        estimate(&self.frames)
+    }
+
+    fn fraction_of(&self, idx: usize, frames: f64) -> f64 {
+        if frames < 1.0 {
+            return 0.0;
+        }
+        let fired = estimate(&self.pixels[idx * PIXEL_REGISTERS..(idx + 1) * PIXEL_REGISTERS]);
+        (fired / frames).min(1.0)
+    }
+
+    /// Estimated fraction of the frames in which the pixel was hit
+    pub fn firing_fraction(&self, idx: usize) -> f64 {
+        self.fraction_of(idx, self.frames())
+    }
+
+    /// Pixels hit in at least the fraction of the frames with their firing fraction
+    pub fn hot_pixels(&self, min_fraction: f64) -> Vec<(usize, f64)> {
+        let frames = self.frames();
+        (0..MATRIX_SIZE)
+            .map(|idx| (idx, self.fraction_of(idx, frames)))
+            .filter(|&(_, fraction)| fraction >= min_fraction)
+            .collect()
+    }
+
+    /// Writes the mask of the hot pixels as `x y fraction` lines
+    pub fn save_mask(&self, path: &Path, min_fraction: f64) -> Result<()> {
+        let hot = self.hot_pixels(min_fraction);
+        let mut writer = BufWriter::new(
+            fs::File::create(path)
+                .with_context(|| format!("cannot write hot pixel mask {}", path.display()))?,
+        );
+        writeln!(
+            writer,
+            "# hot pixels hit in at least {} of about {:.0} frames: {}",
+            min_fraction,
+            self.frames(),
+            hot.len()
+        )?;
+        writeln!(writer, "# x y fraction")?;
+        for (idx, fraction) in hot {
+            writeln!(writer, "{} {} {:.4}", idx % 256, idx / 256, fraction)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Pixels removed by the decoder, e.g. the hot pixels of an earlier `--hot-pixel-mask`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HotPixelMask {
+    pub pixels: PixelMask,
+    /// SHA-256 of the mask file, part of the repro hash
+    pub digest: String,
+}
+
+impl HotPixelMask {
+    pub fn load(path: &Path) -> Result<HotPixelMask> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("cannot read pixel mask {}", path.display()))?;
+        HotPixelMask::parse(&content)
+            .with_context(|| format!("invalid pixel mask {}", path.display()))
+    }
+
+    /// Parses `x y` lines, further fields such as the firing fraction of the hot pixel
+    /// masks are ignored and `#` starts a comment
+    pub fn parse(content: &str) -> Result<HotPixelMask> {
+        let mut pixels = PixelMask::default();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let fields: Vec<&str> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|f| !f.is_empty())
+                .collect();
+            if fields.is_empty() {
+                continue;
+            }
+            if fields.len() < 2 {
+                bail!("line {}: expected x y", i + 1);
+            }
+            let x: u8 = fields[0]
+                .parse()
+                .with_context(|| format!("line {}: invalid column '{}'", i + 1, fields[0]))?;
+            let y: u8 = fields[1]
+                .parse()
+                .with_context(|| format!("line {}: invalid row '{}'", i + 1, fields[1]))?;
+            pixels.set(y as usize * 256 + x as usize);
+        }
+        Ok(HotPixelMask {
+            pixels,
+            digest: hex::encode(Sha256::digest(content.as_bytes())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hot_pixel_stats() {
+        let mut stats = HotPixelStats::default();
+        let mut other = HotPixelStats::default();
+        let mut itot = vec![0u16; MATRIX_SIZE];
+        for i in 0..1000 {
+            itot.fill(0);
+            itot[300] = 5;
+            if i % 10 == 0 {
+                itot[1000] = 7;
+            }
+            // the second half is processed twice
+            stats.add_frame(&itot, i as f64 * 10.0);
+            if i >= 500 {
+                other.add_frame(&itot, i as f64 * 10.0);
+            }
+        }
+        stats.merge(&other);
+        assert!((stats.frames() - 1000.0).abs() < 60.0, "{}", stats.frames());
+        assert!(stats.firing_fraction(300) > 0.75);
+        let quiet = stats.firing_fraction(1000);
+        assert!(quiet > 0.05 && quiet < 0.15, "{}", quiet);
+        assert_eq!(stats.firing_fraction(5), 0.0);
+        let hot = stats.hot_pixels(0.5);
+        assert_eq!(hot.len(), 1);
+        assert_eq!(hot[0].0, 300);
+
+        let path = std::env::temp_dir().join(format!("oneweb-hotpix-{}", std::process::id()));
+        stats.save(&path).unwrap();
+        let loaded = HotPixelStats::load(&path).unwrap();
+        assert_eq!(loaded.frames, stats.frames);
+        assert_eq!(loaded.pixels, stats.pixels);
+        fs::write(&path, b"other").unwrap();
+        assert!(HotPixelStats::load(&path).is_err());
+
+        // a masked pixel keeps firing in the statistics
+        let mut masked = PixelMask::default();
+        masked.set(2000);
+        itot.fill(0);
+        for i in 0..100 {
+            stats.add_frame(&itot, 1e5 + i as f64);
+            stats.add_masked(&masked, 1e5 + i as f64);
+        }
+        assert!(stats.firing_fraction(2000) > 0.05);
+
+        stats.save_mask(&path, 0.5).unwrap();
+        let mask = HotPixelMask::load(&path).unwrap();
+        assert_eq!(mask.pixels.indices().collect::<Vec<_>>(), [300]);
+        assert_eq!(mask.digest.len(), 64);
+        assert_eq!(
+            HotPixelMask::parse("5, 1\n# x y\n255 255 0.9\n")
+                .unwrap()
+                .pixels
+                .indices()
+                .collect::<Vec<_>>(),
+            [261, MATRIX_SIZE - 1]
+        );
+        assert!(HotPixelMask::parse("256 0\n").is_err());
+        assert!(HotPixelMask::parse("7\n").is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
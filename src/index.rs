@@ -0,0 +1,421 @@
+use crate::info_processor::MeasInfoProcessor;
+use crate::line_reader::LineReader;
+use crate::utils::{format_time, parse_time};
+use anyhow::{Result, bail};
+use chrono::{TimeZone, Utc};
+use std::fmt;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+
+/// Position of a line containing a start of readout header in the data file
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    /// Byte offset of the line start
+    pub offset: u64,
+    /// Line number (1-based)
+    pub line_no: usize,
+    pub timestamp: f64,
+}
+
+/// Continuous part of the data file whose frames all go to the output files of one day
+#[derive(Debug, Clone, PartialEq)]
+pub struct DaySegment {
+    pub date: String,
+    pub start: u64,
+    pub end: u64,
+    /// Line number of the line preceding the segment
+    pub line_no: usize,
+    /// Frames of the data file before the segment
+    pub first_frame: usize,
+}
+
+/// Numbering of the frames in the .clog and .info output files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameNumbering {
+    /// Restarts at 1 in each output file
+    #[default]
+    File,
+    /// Frame number in the data file, continues across the output files
+    Global,
+}
+
+impl FromStr for FrameNumbering {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "file" => Ok(FrameNumbering::File),
+            "global" => Ok(FrameNumbering::Global),
+            _ => bail!("expected file or global"),
+        }
+    }
+}
+
+impl fmt::Display for FrameNumbering {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameNumbering::File => write!(f, "file"),
+            FrameNumbering::Global => write!(f, "global"),
+        }
+    }
+}
+
+/// Frame of the data file selected by the user
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameSelector {
+    /// Frame number in the data file (1-based)
+    Index(usize),
+    /// Frame closest to the time
+    Timestamp(f64),
+}
+
+impl FrameSelector {
+    pub fn select<'a>(&self, entries: &'a [IndexEntry]) -> Option<&'a IndexEntry> {
+        match *self {
+            FrameSelector::Index(index) => index.checked_sub(1).and_then(|i| entries.get(i)),
+            FrameSelector::Timestamp(timestamp) => entries.iter().min_by(|a, b| {
+                (a.timestamp - timestamp)
+                    .abs()
+                    .total_cmp(&(b.timestamp - timestamp).abs())
+            }),
+        }
+    }
+}
+
+impl fmt::Display for FrameSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameSelector::Index(index) => write!(f, "frame {}", index),
+            FrameSelector::Timestamp(timestamp) => {
+                write!(f, "frame at {}", format_time(*timestamp))
+            }
+        }
+    }
+}
+
+/// Checks whether the hex payload contains the start of readout sequence 71AF0000
+/// at a byte boundary
+fn has_frame_header(payload: &str) -> bool {
+    let bytes = payload.as_bytes();
+    let pattern = b"71AF0000";
+    (0..bytes.len().saturating_sub(pattern.len() - 1))
+        .step_by(2)
+        .any(|i| bytes[i..i + pattern.len()].eq_ignore_ascii_case(pattern))
+}
+
+/// Scans the data file and records all lines starting a frame
+pub fn index_frames<R>(reader: &mut io::BufReader<R>) -> Result<(Vec<IndexEntry>, u64)>
+where
+    R: io::Read,
+{
+    let mut entries = Vec::new();
+    let mut offset: u64 = 0;
+    let mut line_no = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        if read == 0 {
+            break;
+        }
+        line_no += 1;
+        if let Some((time, payload)) = line.trim().split_once(',')
+            && has_frame_header(payload)
+            && let Ok(timestamp) = parse_time(time)
+        {
+            entries.push(IndexEntry {
+                offset,
+                line_no,
+                timestamp,
+            });
+        }
+        offset += read as u64;
+    }
+    Ok((entries, offset))
+}
+
+/// Reads all timestamps of the measurement info file
+pub fn read_info_timestamps<R>(reader: &mut LineReader<R>) -> Result<Vec<f64>>
+where
+    R: io::Read,
+{
+    let proc = MeasInfoProcessor::new();
+    let mut timestamps = Vec::new();
+    while let Some(data) = proc.get_next_meas_info(reader)? {
+        timestamps.push(data.timestamp);
+    }
+    Ok(timestamps)
+}
+
+/// Timestamp of the info record closest to the given time (info timestamps sorted)
+fn closest_info(info_times: &[f64], timestamp: f64) -> Option<f64> {
+    let i = info_times.partition_point(|&t| t < timestamp);
+    let next = info_times.get(i).copied();
+    let prev = if i > 0 {
+        info_times.get(i - 1).copied()
+    } else {
+        None
+    };
+    match (prev, next) {
+        (Some(p), Some(n)) => Some(if timestamp - p < n - timestamp { p } else { n }),
+        (p, n) => p.or(n),
+    }
+}
+
+/// UTC date of the timestamp (YYYY-MM-DD)
+pub fn format_date(timestamp: f64) -> String {
+    Utc.timestamp_opt(timestamp.floor() as i64, 0)
+        .unwrap()
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+/// Timestamp deciding the daily output file of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DaySplit {
+    /// Readout time of the frame
+    #[default]
+    Frame,
+    /// Time of the matched measurement info record (legacy naming, a stale record can
+    /// move frames near midnight to the neighbouring day)
+    Info,
+}
+
+impl DaySplit {
+    /// Date of the output file of a frame
+    pub fn date(&self, frame_time: f64, info_time: f64) -> String {
+        match self {
+            DaySplit::Frame => format_date(frame_time),
+            DaySplit::Info => format_date(info_time),
+        }
+    }
+}
+
+impl FromStr for DaySplit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "frame" => Ok(DaySplit::Frame),
+            "info" => Ok(DaySplit::Info),
+            _ => bail!("expected frame or info"),
+        }
+    }
+}
+
+impl fmt::Display for DaySplit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DaySplit::Frame => write!(f, "frame"),
+            DaySplit::Info => write!(f, "info"),
+        }
+    }
+}
+
+/// Convention for the time of a frame, used to match the GPS record and written as the frame
+/// time of the outputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameTimeSource {
+    /// Time of the data line starting the frame
+    #[default]
+    FirstLine,
+    /// Time of the data line completing the frame
+    LastLine,
+    /// Time of the matched measurement info record
+    Info,
+    /// Middle of the exposure, the info time minus half the acquisition time
+    MidExposure,
+}
+
+impl FrameTimeSource {
+    /// Time the measurement info record is matched with
+    pub fn packet_time(&self, first_line: f64, last_line: f64) -> f64 {
+        match self {
+            FrameTimeSource::LastLine => last_line,
+            _ => first_line,
+        }
+    }
+
+    /// Frame time from the packet line times, the matched info time and the acquisition time
+    pub fn frame_time(
+        &self,
+        first_line: f64,
+        last_line: f64,
+        info_time: f64,
+        acq_time: f64,
+    ) -> f64 {
+        match self {
+            FrameTimeSource::FirstLine => first_line,
+            FrameTimeSource::LastLine => last_line,
+            FrameTimeSource::Info => info_time,
+            FrameTimeSource::MidExposure => info_time - acq_time / 2.0,
+        }
+    }
+
+    /// The frame time is known from the index of the frame starts and the info times, so
+    /// the data file can be split into days before decoding
+    pub fn indexed(&self) -> bool {
+        matches!(self, FrameTimeSource::FirstLine | FrameTimeSource::Info)
+    }
+}
+
+impl FromStr for FrameTimeSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "first-line" => Ok(FrameTimeSource::FirstLine),
+            "last-line" => Ok(FrameTimeSource::LastLine),
+            "info" => Ok(FrameTimeSource::Info),
+            "mid-exposure" => Ok(FrameTimeSource::MidExposure),
+            _ => bail!("expected first-line, last-line, info or mid-exposure"),
+        }
+    }
+}
+
+impl fmt::Display for FrameTimeSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameTimeSource::FirstLine => write!(f, "first-line"),
+            FrameTimeSource::LastLine => write!(f, "last-line"),
+            FrameTimeSource::Info => write!(f, "info"),
+            FrameTimeSource::MidExposure => write!(f, "mid-exposure"),
+        }
+    }
+}
+
+/// Splits the indexed data file at the first frame of each day, the day of a frame is
+/// the one used for file naming under the split policy
+pub fn day_segments(
+    entries: &[IndexEntry],
+    info_times: &[f64],
+    file_len: u64,
+    split: DaySplit,
+) -> Vec<DaySegment> {
+    let mut segments: Vec<DaySegment> = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let Some(info_time) = closest_info(info_times, entry.timestamp) else {
+            continue;
+        };
+        let date = split.date(entry.timestamp, info_time);
+        match segments.last_mut() {
+            Some(last) if last.date == date => continue,
+            Some(last) => last.end = entry.offset,
+            None => {}
+        }
+        segments.push(DaySegment {
+            date,
+            start: if segments.is_empty() { 0 } else { entry.offset },
+            end: file_len,
+            line_no: if segments.is_empty() {
+                0
+            } else {
+                entry.line_no - 1
+            },
+            first_frame: if segments.is_empty() { 0 } else { i },
+        });
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_has_frame_header() {
+        assert!(has_frame_header("14584E000002290171AF00006974"));
+        assert!(has_frame_header("71af0000"));
+        assert!(!has_frame_header("171AF0000A"));
+        assert!(!has_frame_header("71A00000"));
+    }
+
+    #[test]
+    fn test_day_split() {
+        let frame = parse_time("2024-02-29 23:59:59.900").unwrap();
+        let info = parse_time("2024-03-01 00:00:04.900").unwrap();
+        assert_eq!(DaySplit::Frame.date(frame, info), "2024-02-29");
+        assert_eq!(DaySplit::Info.date(frame, info), "2024-03-01");
+        let midnight = parse_time("2024-03-01 00:00:00.000").unwrap();
+        assert_eq!(DaySplit::Frame.date(midnight, info), "2024-03-01");
+        assert_eq!(DaySplit::Frame.date(midnight - 0.001, info), "2024-02-29");
+        assert_eq!(DaySplit::Info.date(frame, frame), "2024-02-29");
+        assert_eq!("info".parse::<DaySplit>().unwrap(), DaySplit::Info);
+        assert_eq!(DaySplit::default().to_string(), "frame");
+        assert!("gps".parse::<DaySplit>().is_err());
+    }
+
+    #[test]
+    fn test_frame_time_source() {
+        let (first, last, info) = (100.0, 102.5, 110.0);
+        let times: Vec<f64> = ["first-line", "last-line", "info", "mid-exposure"]
+            .iter()
+            .map(|s| {
+                let source: FrameTimeSource = s.parse().unwrap();
+                assert_eq!(source.to_string(), *s);
+                source.frame_time(first, last, info, 5.0)
+            })
+            .collect();
+        assert_eq!(times, vec![100.0, 102.5, 110.0, 107.5]);
+        assert_eq!(FrameTimeSource::LastLine.packet_time(first, last), last);
+        assert_eq!(FrameTimeSource::MidExposure.packet_time(first, last), first);
+        assert!(!FrameTimeSource::MidExposure.indexed());
+        assert!("gps".parse::<FrameTimeSource>().is_err());
+    }
+
+    #[test]
+    fn test_day_segments() {
+        let data = [
+            "TIMESTAMP,DATA",
+            "2024-02-29 23:59:50.000,71AF00000000",
+            "2024-02-29 23:59:51.000,A000000000EE71A00000",
+            "2024-02-29 23:59:58.000,71AF00000000",
+            "2024-02-29 23:59:59.000,A000000000EE71A00000",
+            "2024-03-01 00:00:10.000,71AF00000000",
+            "2024-03-01 00:00:11.000,A000000000EE71A00000",
+        ]
+        .join("\n");
+        let file_len = data.len() as u64;
+        let mut reader = io::BufReader::new(Cursor::new(data));
+        let (entries, len) = index_frames(&mut reader).unwrap();
+        assert_eq!(len, file_len);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1].line_no, 4);
+
+        // info of the second frame is after midnight
+        let info_times = [
+            parse_time("2024-02-29 23:59:55.000").unwrap(),
+            parse_time("2024-03-01 00:00:01.000").unwrap(),
+            parse_time("2024-03-01 00:00:15.000").unwrap(),
+        ];
+        let segments = day_segments(&entries, &info_times, len, DaySplit::Info);
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].date, "2024-02-29");
+        assert_eq!(segments[0].start, 0);
+        assert_eq!(segments[0].end, entries[1].offset);
+        assert_eq!(segments[1].date, "2024-03-01");
+        assert_eq!(segments[1].start, entries[1].offset);
+        assert_eq!(segments[1].end, len);
+        assert_eq!(segments[1].line_no, 3);
+        assert_eq!(segments[1].first_frame, 1);
+
+        // split on the frame time the second frame stays in the day of its readout
+        let segments = day_segments(&entries, &info_times, len, DaySplit::Frame);
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].end, entries[2].offset);
+        assert_eq!(segments[1].date, "2024-03-01");
+        assert_eq!(segments[1].start, entries[2].offset);
+        assert_eq!(segments[1].line_no, 5);
+        assert_eq!(segments[1].first_frame, 2);
+
+        let at = parse_time("2024-02-29 23:59:59.500").unwrap();
+        assert_eq!(
+            FrameSelector::Timestamp(at).select(&entries),
+            Some(&entries[1])
+        );
+        assert_eq!(FrameSelector::Index(3).select(&entries), Some(&entries[2]));
+        assert_eq!(FrameSelector::Index(0).select(&entries), None);
+        assert_eq!(FrameSelector::Index(4).select(&entries), None);
+    }
+}
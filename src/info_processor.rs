@@ -1,9 +1,24 @@
-use crate::utils::parse_time;
-use anyhow::{Result, bail};
-use std::io::{self, BufRead};
+use crate::drift::SchemaDrift;
+use crate::line_reader::LineReader;
+use crate::utils::{parse_field, parse_time};
+use crate::validate::InputKind;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// Column names of the measurement info file header
+pub const COLUMNS: [&str; 7] = [
+    "TIMESTAMP",
+    "Temp",
+    "N°pixel_short",
+    "N°pixel_long",
+    "N°pixel_saved",
+    "N°pixel_not_saved",
+    "Error_id",
+];
 
 #[allow(dead_code)]
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct MeasInfoData {
     pub timestamp: f64,
     pub temp: f64,
@@ -14,29 +29,53 @@ pub struct MeasInfoData {
     pub error_id: String,
 }
 
+impl MeasInfoData {
+    /// Stand-in record at the frame time when running without the measurement info file,
+    /// the temperature is unknown and the test counts give an acquisition time of 0 s
+    pub fn placeholder(timestamp: f64) -> Self {
+        MeasInfoData {
+            timestamp,
+            temp: f64::NAN,
+            pixel_saved: f64::NAN,
+            pixel_not_saved: f64::NAN,
+            ..Default::default()
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub struct MeasInfoProcessor {}
 
+impl Default for MeasInfoProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[allow(dead_code)]
 impl MeasInfoProcessor {
     pub fn new() -> MeasInfoProcessor {
         MeasInfoProcessor {}
     }
 
-    fn parse_line(line: &str) -> Result<MeasInfoData> {
+    pub(crate) fn parse_line(line: &str) -> Result<MeasInfoData> {
         // TIMESTAMP,Temp,N°pixel_short,N°pixel_long,N°pixel_saved,N°pixel_not_saved,Error_id
         //2024-03-01 00:00:51.297,-4,5,35,320,0,
         let parts: Vec<&str> = line.trim().splitn(7, ',').collect();
         if parts.len() != 7 {
-            bail!("Invalid line format");
+            bail!(
+                "invalid line format, expected 7 columns, got {}",
+                parts.len()
+            );
         }
 
-        let timestamp = parse_time(parts[0])?;
-        let temp: i32 = parts[1].parse()?;
-        let pixel_short: i32 = parts[2].parse()?;
-        let pixel_long: i32 = parts[3].parse()?;
-        let pixel_saved: i32 = parts[4].parse()?;
-        let pixel_not_saved: i32 = parts[5].parse()?;
+        let timestamp = parse_time(parts[0])
+            .with_context(|| format!("cannot parse TIMESTAMP '{}'", parts[0]))?;
+        let temp: i32 = parse_field(parts[1], COLUMNS[1])?;
+        let pixel_short: i32 = parse_field(parts[2], COLUMNS[2])?;
+        let pixel_long: i32 = parse_field(parts[3], COLUMNS[3])?;
+        let pixel_saved: i32 = parse_field(parts[4], COLUMNS[4])?;
+        let pixel_not_saved: i32 = parse_field(parts[5], COLUMNS[5])?;
         let error_id: String = parts[6].to_string();
         Ok(MeasInfoData {
             timestamp,
@@ -45,24 +84,103 @@ impl MeasInfoProcessor {
             pixel_long: pixel_long as f64,
             pixel_saved: pixel_saved as f64,
             pixel_not_saved: pixel_not_saved as f64,
-            error_id: error_id,
+            error_id,
         })
     }
 
-    pub fn get_next_meas_info<R>(&self, reader: &mut io::BufReader<R>) -> Result<MeasInfoData>
+    /// Returns the next measurement info record, None at the end of the file
+    pub fn get_next_meas_info<R>(&self, reader: &mut LineReader<R>) -> Result<Option<MeasInfoData>>
+    where
+        R: io::Read,
+    {
+        self.next_meas_info(reader, None)
+    }
+
+    /// Returns the next measurement info record, lines not matching the schema are recorded
+    /// in the drift log (up to `max_samples` verbatim per file) and skipped instead of failing
+    pub fn get_next_meas_info_tolerant<R>(
+        &self,
+        reader: &mut LineReader<R>,
+        drift: &mut SchemaDrift,
+        max_samples: usize,
+    ) -> Result<Option<MeasInfoData>>
+    where
+        R: io::Read,
+    {
+        self.next_meas_info(reader, Some((drift, max_samples)))
+    }
+
+    fn next_meas_info<R>(
+        &self,
+        reader: &mut LineReader<R>,
+        mut drift: Option<(&mut SchemaDrift, usize)>,
+    ) -> Result<Option<MeasInfoData>>
     where
         R: io::Read,
     {
-        for line in reader.lines() {
-            let line = line?;
+        while let Some(line) = reader.next_line()? {
             let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
             if line.starts_with("TIMESTAMP") {
-                continue; // Skip header line
+                // header line
+                if let Some((drift, max_samples)) = &mut drift {
+                    drift.record(
+                        InputKind::MeasInfo,
+                        reader.source(),
+                        reader.line_no(),
+                        line,
+                        *max_samples,
+                    );
+                }
+                continue;
+            }
+            match (MeasInfoProcessor::parse_line(line), &mut drift) {
+                (Ok(data), _) => return Ok(Some(data)),
+                (Err(_), Some((drift, max_samples))) => {
+                    drift.record(
+                        InputKind::MeasInfo,
+                        reader.source(),
+                        reader.line_no(),
+                        line,
+                        *max_samples,
+                    );
+                }
+                (Err(e), None) => return Err(reader.error_at(e)),
             }
+        }
+        Ok(None)
+    }
 
-            return Ok(MeasInfoProcessor::parse_line(&line)?);
+    /// Reads all remaining measurement info records
+    pub fn read_all<R>(&self, reader: &mut LineReader<R>) -> Result<Vec<MeasInfoData>>
+    where
+        R: io::Read,
+    {
+        let mut records = Vec::new();
+        while let Some(data) = self.get_next_meas_info(reader)? {
+            records.push(data);
         }
-        bail!("No more info data available");
+        Ok(records)
+    }
+
+    /// Reads all remaining measurement info records, skipping the lines not matching the
+    /// schema
+    pub fn read_all_tolerant<R>(
+        &self,
+        reader: &mut LineReader<R>,
+        drift: &mut SchemaDrift,
+        max_samples: usize,
+    ) -> Result<Vec<MeasInfoData>>
+    where
+        R: io::Read,
+    {
+        let mut records = Vec::new();
+        while let Some(data) = self.get_next_meas_info_tolerant(reader, drift, max_samples)? {
+            records.push(data);
+        }
+        Ok(records)
     }
 }
 
@@ -98,16 +216,35 @@ mod tests {
     }
 
     #[test]
-    fn test_get_next_gps_data() {
-        let lines = vec![
-            "\"TIME\",\"J2000_X (m)\",\"J2000_Y (m)\",\"J2000_Z (m)\",\"iae_qEstProp_BJ.scalar\",\"iae_qEstProp_BJ.vector(1)\",\"iae_qEstProp_BJ.vector(2)\",\"iae_qEstProp_BJ.vector(3)\"",
-            "2024-03-01 00:00:09.000,2.51279e+6,5.64324e+5,-6.50431e+6,9.64920e-1,5.96500e-3,-1.87169e-1,1.84013e-1",
+    fn test_get_next_meas_info() {
+        let lines = [
+            "TIMESTAMP,Temp,N°pixel_short,N°pixel_long,N°pixel_saved,N°pixel_not_saved,Error_id",
+            "2024-03-01 00:00:51.297,-4,5,35,320,0,",
         ];
         let data = lines.join("\n");
         let cursor = Cursor::new(data);
-        let mut reader = io::BufReader::new(cursor);
-        let gps_processor = MeasInfoProcessor::new();
-        let info_data = gps_processor.get_next_meas_info(&mut reader).unwrap();
-        assert_eq!(info_data.timestamp, 1709251209.0);
+        let mut reader = LineReader::new(io::BufReader::new(cursor), "meas.csv");
+        let info_processor = MeasInfoProcessor::new();
+        let info_data = info_processor.get_next_meas_info(&mut reader).unwrap();
+        assert_eq!(info_data.unwrap().timestamp, 1709251251.297);
+        assert!(
+            info_processor
+                .get_next_meas_info(&mut reader)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_get_next_meas_info_error_location() {
+        let data = "TIMESTAMP,Temp\n2024-03-01 00:00:51.297,-4,5,35,320,0,\n2024-03-01 00:01:51.297,-4,5,3a5,320,0,";
+        let mut reader = LineReader::new(io::BufReader::new(Cursor::new(data)), "meas.csv");
+        let info_processor = MeasInfoProcessor::new();
+        assert!(info_processor.get_next_meas_info(&mut reader).is_ok());
+        let err = info_processor.get_next_meas_info(&mut reader).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "meas.csv:3: cannot parse N°pixel_long '3a5'"
+        );
     }
 }
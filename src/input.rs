@@ -0,0 +1,299 @@
+use crate::error::OnewebError;
+use crate::utils;
+use anyhow::{Context, Result, bail};
+use std::cmp::Ordering;
+use std::fs;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// One file of the input, without the header line when it is not the first file
+struct Part {
+    file: fs::File,
+    /// Bytes of the header line skipped at the start of the file
+    skip: u64,
+    /// Bytes read from the file after the skipped header
+    len: u64,
+    /// A line ending is added after a file not ending with one
+    newline: bool,
+    /// Offset of the part in the concatenated input
+    start: u64,
+}
+
+impl Part {
+    fn size(&self) -> u64 {
+        self.len + u64::from(self.newline)
+    }
+}
+
+/// An input file, a directory of .csv files or a glob of downlink chunks
+/// (`dosimeter_image_packets_*.csv`) read as one file: the files are concatenated in the
+/// order of their first record time and the header lines of all but the first are skipped
+pub struct InputFile {
+    parts: Vec<Part>,
+    paths: Vec<PathBuf>,
+    source: String,
+    pos: u64,
+    /// Part whose file is positioned at `pos`
+    current: Option<usize>,
+}
+
+fn has_wildcard(s: &str) -> bool {
+    s.contains(['*', '?'])
+}
+
+/// Matches a file name against a pattern with `*` (any characters) and `?` (one character)
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], name) || (!name.is_empty() && glob_match(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// Time of the first record of a file, None for files without records
+fn first_timestamp(path: &Path) -> Result<Option<f64>> {
+    let file = fs::File::open(path).with_context(|| format!("{}", path.display()))?;
+    for line in io::BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("{}", path.display()))?;
+        let field = line.split(',').next().unwrap_or_default();
+        if let Ok(timestamp) = utils::parse_time(field.trim().trim_matches('"')) {
+            return Ok(Some(timestamp));
+        }
+    }
+    Ok(None)
+}
+
+/// Files of an input argument: the path itself, the .csv files of a directory or the files
+/// matching a glob in the file name, the latter two ordered by the time of their first record
+pub fn resolve(spec: &str) -> Result<Vec<PathBuf>> {
+    let path = Path::new(spec);
+    let mut paths: Vec<PathBuf> = if path.is_dir() {
+        fs::read_dir(path)
+            .with_context(|| format!("cannot list {}", spec))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|p| {
+                p.is_file()
+                    && p.extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+            })
+            .collect()
+    } else if has_wildcard(spec) {
+        let pattern = path.file_name().unwrap_or_default().to_string_lossy();
+        let dir = match path.parent() {
+            Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+            Some(dir) => dir,
+            None => Path::new("."),
+        };
+        if has_wildcard(&dir.to_string_lossy()) {
+            bail!("{}: wildcards are only supported in the file name", spec);
+        }
+        fs::read_dir(dir)
+            .with_context(|| format!("cannot list {}", dir.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|p| {
+                p.is_file()
+                    && p.file_name().is_some_and(|name| {
+                        glob_match(pattern.as_bytes(), name.to_string_lossy().as_bytes())
+                    })
+            })
+            .collect()
+    } else {
+        return Ok(vec![path.to_path_buf()]);
+    };
+    if paths.is_empty() {
+        bail!("no input files in {}", spec);
+    }
+    let mut keyed = Vec::with_capacity(paths.len());
+    for path in paths.drain(..) {
+        keyed.push((first_timestamp(&path)?, path));
+    }
+    // files without records last, ties in name order
+    keyed.sort_by(|(a, pa), (b, pb)| match (a, b) {
+        (Some(a), Some(b)) => a.total_cmp(b).then_with(|| pa.cmp(pb)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => pa.cmp(pb),
+    });
+    Ok(keyed.into_iter().map(|(_, path)| path).collect())
+}
+
+impl InputFile {
+    pub fn open(spec: &str) -> Result<Self> {
+        let paths = resolve(spec)?;
+        let mut parts = Vec::with_capacity(paths.len());
+        let mut start = 0;
+        for (i, path) in paths.iter().enumerate() {
+            let mut file = fs::File::open(path).map_err(|source| OnewebError::Io {
+                path: path.display().to_string(),
+                source,
+            })?;
+            let file_len = file.metadata()?.len();
+            let mut skip = 0;
+            if i > 0 {
+                let mut first = Vec::new();
+                io::BufReader::new(&file).read_until(b'\n', &mut first)?;
+                if !first.first().is_some_and(u8::is_ascii_digit) {
+                    skip = first.len() as u64;
+                }
+            }
+            let mut newline = false;
+            if paths.len() > 1 && file_len > skip {
+                let mut last = [0];
+                file.seek(SeekFrom::End(-1))?;
+                file.read_exact(&mut last)?;
+                newline = last[0] != b'\n';
+            }
+            let part = Part {
+                file,
+                skip,
+                len: file_len - skip,
+                newline,
+                start,
+            };
+            start += part.size();
+            parts.push(part);
+        }
+        let source = if paths.len() == 1 {
+            paths[0].file_name()
+        } else {
+            Path::new(spec).file_name()
+        }
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| spec.to_string());
+        Ok(InputFile {
+            parts,
+            paths,
+            source,
+            pos: 0,
+            current: None,
+        })
+    }
+
+    /// Name of the file, or of the directory or glob for several files
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The concatenated files in reading order
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    pub fn len(&self) -> u64 {
+        self.parts.last().map_or(0, |part| part.start + part.size())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Read for InputFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let i = self
+            .parts
+            .partition_point(|part| part.start + part.size() <= self.pos);
+        let Some(part) = self.parts.get_mut(i) else {
+            return Ok(0);
+        };
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let offset = self.pos - part.start;
+        if offset == part.len {
+            buf[0] = b'\n';
+            self.pos += 1;
+            return Ok(1);
+        }
+        if self.current != Some(i) {
+            part.file.seek(SeekFrom::Start(part.skip + offset))?;
+            self.current = Some(i);
+        }
+        let max = buf.len().min((part.len - offset) as usize);
+        let read = part.file.read(&mut buf[..max])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for InputFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.len().checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        let Some(pos) = pos else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start of the input",
+            ));
+        };
+        if pos != self.pos {
+            self.pos = pos;
+            self.current = None;
+        }
+        Ok(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_file() {
+        let dir = std::env::temp_dir().join(format!("oneweb-input-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let header = "TIMESTAMP,DATA\n";
+        fs::write(
+            dir.join("packets_b.csv"),
+            format!(
+                "{}2024-03-01 00:00:01.000,71AF\n2024-03-01 00:00:02.000,00",
+                header
+            ),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("packets_a.csv"),
+            format!("{}2024-03-01 00:00:03.000,71AF\n", header),
+        )
+        .unwrap();
+        fs::write(dir.join("packets_c.csv"), header).unwrap();
+        fs::write(dir.join("notes.txt"), "not an input").unwrap();
+
+        assert!(glob_match(b"packets_*.csv", b"packets_a.csv"));
+        assert!(!glob_match(b"packets_?.csv", b"packets_ab.csv"));
+
+        let spec = dir.join("packets_*.csv").display().to_string();
+        let mut input = InputFile::open(&spec).unwrap();
+        let names: Vec<_> = input
+            .paths()
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, ["packets_b.csv", "packets_a.csv", "packets_c.csv"]);
+        assert_eq!(input.source(), "packets_*.csv");
+
+        let expected = "TIMESTAMP,DATA\n2024-03-01 00:00:01.000,71AF\n2024-03-01 00:00:02.000,00\n\
+                        2024-03-01 00:00:03.000,71AF\n";
+        let mut content = String::new();
+        input.read_to_string(&mut content).unwrap();
+        assert_eq!(content, expected);
+        assert_eq!(input.len(), expected.len() as u64);
+
+        let offset = expected.find("2024-03-01 00:00:02").unwrap() as u64;
+        input.seek(SeekFrom::Start(offset)).unwrap();
+        let mut rest = String::new();
+        input.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, &expected[offset as usize..]);
+
+        assert_eq!(resolve(&dir.display().to_string()).unwrap().len(), 3);
+        assert!(resolve(&dir.join("none_*.csv").display().to_string()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
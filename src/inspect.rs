@@ -0,0 +1,84 @@
+use crate::clusterize;
+use crate::data_processor::{DataProcessor, Frame};
+use crate::error::OnewebError;
+use crate::index::{self, FrameSelector};
+use crate::input::InputFile;
+use crate::line_reader::LineReader;
+use crate::utils::format_time;
+use anyhow::{Result, bail};
+use std::io::{BufReader, Write};
+
+fn hit_pixels(frame: &Frame) -> usize {
+    frame.itot().iter().filter(|&&v| v != 0).count()
+}
+
+/// Prints a line per frame of the data file: number, time, payload size, hit, invalid and
+/// saturated pixels and clusters. Nothing is written to disk, returns the frame count
+pub fn inspect_frames<W: Write>(
+    data_file: &str,
+    data_processor: &mut DataProcessor,
+    writer: &mut W,
+) -> Result<usize> {
+    let mut reader = LineReader::open(data_file)?;
+    writeln!(
+        writer,
+        "Frame\tTime\tBytes\tHit Pixels\tInvalid Pixels\tSaturated Pixels\tClusters"
+    )?;
+    let mut frames = 0;
+    loop {
+        let frame = match data_processor.get_next_frame(&mut reader) {
+            Ok(frame) => frame,
+            Err(e) if matches!(e.downcast_ref(), Some(OnewebError::EndOfData)) => break,
+            Err(e) => return Err(e),
+        };
+        frames += 1;
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            frames,
+            format_time(frame.timestamp),
+            frame.raw.len(),
+            hit_pixels(&frame),
+            frame.invalid().count(),
+            frame.saturated().count(),
+            frame.clusters.len()
+        )?;
+    }
+    Ok(frames)
+}
+
+/// Prints the selected frame of the data file with the features of its clusters
+pub fn inspect_frame<W: Write>(
+    data_file: &str,
+    selector: FrameSelector,
+    data_processor: &mut DataProcessor,
+    kev_per_count: f64,
+    writer: &mut W,
+) -> Result<()> {
+    let (entries, _) = index::index_frames(&mut BufReader::new(InputFile::open(data_file)?))?;
+    let Some(entry) = selector.select(&entries) else {
+        bail!(
+            "no {} in {} ({} frames)",
+            selector,
+            data_file,
+            entries.len()
+        );
+    };
+    let frame_no = entries.iter().position(|e| e == entry).unwrap_or(0) + 1;
+    let mut reader = LineReader::open_at(data_file, entry.offset, entry.line_no - 1)?;
+    let location = format!("{}:{}", reader.source(), entry.line_no);
+    let frame = data_processor.get_next_frame(&mut reader)?;
+    writeln!(writer, "Frame: {} of {}", frame_no, entries.len())?;
+    writeln!(writer, "Source: {}", location)?;
+    writeln!(writer, "Frame time: {}", format_time(frame.timestamp))?;
+    writeln!(writer, "Raw payload: {} bytes", frame.raw.len())?;
+    writeln!(writer, "Hit pixels: {}", hit_pixels(&frame))?;
+    writeln!(writer, "Invalid pixels: {}", frame.invalid().count())?;
+    writeln!(writer, "Saturated pixels: {}", frame.saturated().count())?;
+    writeln!(writer, "Clusters: {}", frame.clusters.len())?;
+    if !frame.clusters.is_empty() {
+        writeln!(writer)?;
+        clusterize::write_features(writer, &frame.clusters, kev_per_count)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,108 @@
+//! Decoding, clustering and metadata matching of the OneWeb Timepix dosimeter data.
+//!
+//! The stable API is re-exported at the crate root: [`DataProcessor`] decodes the image
+//! packets of the data file into [`Frame`]s, [`Clusterer`] groups the hit pixels of a frame,
+//! [`GpsProcessor`] and [`MeasInfoProcessor`] read the GPS and measurement info files and
+//! [`Processor`] runs the whole conversion of the `one-web-extractor` binary, or returns its
+//! products as [`CampaignProducts`] with [`Processor::process_in_memory`]. The modules
+//! are public for the binary and may change between versions. Errors are `anyhow::Error`s,
+//! the ones callers may want to handle are [`OnewebError`] variants. Warnings and progress
+//! messages are `tracing` events, shown once the caller installs a subscriber.
+//!
+//! ```no_run
+//! use one_web_extractor::{DataProcessor, LineReader, OnewebError};
+//!
+//! let mut reader = LineReader::open("dosimeter_image_packets.csv")?;
+//! let mut data = DataProcessor::new();
+//! loop {
+//!     let mut frame = match data.get_next_frame(&mut reader) {
+//!         Ok(frame) => frame,
+//!         Err(e) if matches!(e.downcast_ref(), Some(OnewebError::EndOfData)) => break,
+//!         Err(e) => return Err(e),
+//!     };
+//!     data.clusterize_frame(&mut frame);
+//!     println!("{} {} clusters", frame.timestamp, frame.clusters.len());
+//! }
+//! # anyhow::Ok(())
+//! ```
+
+pub mod backfill;
+pub mod binning;
+pub mod catalog;
+pub mod checkpoint;
+pub mod classification;
+pub mod clock;
+pub mod cluster_table;
+pub mod clustering;
+pub mod clusterize;
+pub mod columns;
+pub mod compare;
+pub mod config;
+pub mod conformance;
+pub mod data_processor;
+pub mod database;
+pub mod day_stats;
+pub mod deglitch;
+pub mod derived;
+pub mod direction;
+pub mod disk;
+pub mod dose_equivalent;
+pub mod dosimetry;
+pub mod drift;
+pub mod duty;
+pub mod energy_calibration;
+pub mod error;
+pub mod event_catalog;
+pub mod event_display;
+pub mod firmware;
+pub mod frame_image;
+pub mod gps_processor;
+pub mod gpu;
+pub mod hot_pixels;
+pub mod index;
+pub mod info_processor;
+pub mod input;
+pub mod inspect;
+pub mod line_reader;
+pub mod logging;
+pub mod maneuver;
+pub mod manifest;
+pub mod mode;
+pub mod noise;
+pub mod orbit;
+pub mod orientation;
+pub mod phase;
+pub mod pipeline;
+pub mod pixet;
+pub mod processor;
+pub mod progress;
+pub mod provenance;
+pub mod quality;
+pub mod read_ahead;
+pub mod records;
+pub mod replay;
+pub mod repro;
+pub mod roi;
+pub mod schema;
+pub mod see;
+pub mod source;
+pub mod spectra;
+pub mod summary;
+pub mod thermal;
+pub mod timing;
+pub mod tle;
+pub mod toa_calibration;
+pub mod tpx3lut;
+pub mod tui;
+pub mod utils;
+pub mod validate;
+pub mod watch;
+pub mod window;
+
+pub use clustering::{Cluster, Clusterer, Pixel};
+pub use data_processor::{DataProcessor, Frame};
+pub use error::OnewebError;
+pub use gps_processor::{GpsData, GpsProcessor};
+pub use info_processor::{MeasInfoData, MeasInfoProcessor};
+pub use line_reader::LineReader;
+pub use processor::{CampaignProducts, Processor, ProcessorConfig};
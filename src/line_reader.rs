@@ -0,0 +1,181 @@
+use crate::error::OnewebError;
+use crate::input::InputFile;
+use crate::read_ahead::ReadAhead;
+use crate::source::{InputSource, SourceReader};
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, Seek, SeekFrom};
+use std::sync::Arc;
+
+/// Byte offset and number of the lines read, where a reader opened at the offset continues
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    pub offset: u64,
+    pub line_no: usize,
+}
+
+/// Line reader keeping track of the source name and current line number,
+/// so parse errors can point to "file:line"
+pub struct LineReader<R> {
+    reader: io::BufReader<R>,
+    source: String,
+    line_no: usize,
+    /// Bytes of the lines read, from the start of the input
+    offset: u64,
+}
+
+#[allow(dead_code)]
+impl<R> LineReader<R>
+where
+    R: io::Read,
+{
+    pub fn new(reader: io::BufReader<R>, source: &str) -> Self {
+        LineReader {
+            reader,
+            source: source.to_string(),
+            line_no: 0,
+            offset: 0,
+        }
+    }
+
+    /// Starts counting from the given line, for readers positioned inside a file
+    pub fn with_line_no(mut self, line_no: usize) -> Self {
+        self.line_no = line_no;
+        self
+    }
+
+    /// Starts counting from the position, for readers opened at its offset
+    pub fn with_position(mut self, position: Position) -> Self {
+        self.line_no = position.line_no;
+        self.offset = position.offset;
+        self
+    }
+
+    /// Moves the reading of the input to a read-ahead thread handing over chunks of the
+    /// given size, must be called before the first line is read
+    pub fn read_ahead(self, chunk_size: usize) -> LineReader<ReadAhead>
+    where
+        R: Send + 'static,
+    {
+        LineReader {
+            reader: io::BufReader::new(ReadAhead::new(self.reader.into_inner(), chunk_size)),
+            source: self.source,
+            line_no: self.line_no,
+            offset: self.offset,
+        }
+    }
+
+    /// Reads the next line without the line ending, None at the end of input
+    pub fn next_line(&mut self) -> Result<Option<String>> {
+        let mut line = String::new();
+        let read = self
+            .reader
+            .read_line(&mut line)
+            .map_err(|source| OnewebError::Io {
+                path: format!("{}:{}", self.source, self.line_no + 1),
+                source,
+            })?;
+        if read == 0 {
+            return Ok(None);
+        }
+        self.line_no += 1;
+        self.offset += read as u64;
+        let len = line.trim_end_matches(['\r', '\n']).len();
+        line.truncate(len);
+        Ok(Some(line))
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn line_no(&self) -> usize {
+        self.line_no
+    }
+
+    /// Position after the last read line
+    pub fn position(&self) -> Position {
+        Position {
+            offset: self.offset,
+            line_no: self.line_no,
+        }
+    }
+
+    pub fn location(&self) -> String {
+        format!("{}:{}", self.source, self.line_no)
+    }
+
+    /// Parse error at the location of the last read line
+    pub fn error_at(&self, err: Error) -> Error {
+        OnewebError::Parse {
+            location: self.location(),
+            message: format!("{:#}", err),
+        }
+        .into()
+    }
+}
+
+impl LineReader<InputFile> {
+    /// Opens an input file, a directory or a glob of files read as one (see [`InputFile`])
+    pub fn open(path: &str) -> Result<Self> {
+        Self::open_at(path, 0, 0)
+    }
+
+    /// Opens the input positioned at the byte offset of the given (already read) line
+    pub fn open_at(path: &str, offset: u64, line_no: usize) -> Result<Self> {
+        let mut input = InputFile::open(path)?;
+        input.seek(SeekFrom::Start(offset))?;
+        let source = input.source().to_string();
+        Ok(LineReader::new(io::BufReader::new(input), &source)
+            .with_position(Position { offset, line_no }))
+    }
+}
+
+impl LineReader<SourceReader> {
+    /// Opens an input source positioned at the byte offset of the given (already read) line
+    pub fn from_source(source: &Arc<dyn InputSource>, offset: u64, line_no: usize) -> Result<Self> {
+        let reader = SourceReader::open(source.clone(), offset)?;
+        Ok(LineReader::new(io::BufReader::new(reader), source.name())
+            .with_position(Position { offset, line_no }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_next_line() {
+        let cursor = Cursor::new("first\r\nsecond\nthird");
+        let mut reader = LineReader::new(io::BufReader::new(cursor), "test.csv");
+        assert_eq!(reader.next_line().unwrap().unwrap(), "first");
+        assert_eq!(reader.next_line().unwrap().unwrap(), "second");
+        assert_eq!(reader.location(), "test.csv:2");
+        assert_eq!(
+            reader.position(),
+            Position {
+                offset: 14,
+                line_no: 2
+            }
+        );
+        assert_eq!(reader.next_line().unwrap().unwrap(), "third");
+        assert!(reader.next_line().unwrap().is_none());
+        assert_eq!(reader.line_no(), 3);
+
+        let err = reader.error_at(anyhow!("cannot parse Temp 'x'"));
+        assert_eq!(err.to_string(), "test.csv:3: cannot parse Temp 'x'");
+        assert!(matches!(
+            err.downcast_ref::<OnewebError>(),
+            Some(OnewebError::Parse { location, .. }) if location == "test.csv:3"
+        ));
+        assert!(matches!(
+            LineReader::open("/nonexistent/gps.csv")
+                .map(|_| ())
+                .unwrap_err()
+                .downcast_ref(),
+            Some(OnewebError::Io { .. })
+        ));
+    }
+}
@@ -0,0 +1,95 @@
+use crate::progress;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::*;
+
+/// Target of the line logged for every decoded frame, left out of the terminal while the
+/// progress bar is drawn
+pub const FRAMES: &str = "frames";
+
+/// Level of the log: warnings only when quiet, the progress messages by default, the
+/// diagnostics of the decoding with `-v` and everything with `-vv`
+pub fn level(verbose: u8, quiet: bool) -> LevelFilter {
+    match (quiet, verbose) {
+        (true, 0) => LevelFilter::WARN,
+        (_, 0) => LevelFilter::INFO,
+        (_, 1) => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
+
+/// Installs the subscriber of the binary; the events go to stderr, with the progress bar
+/// cleared while a line is written, or with timestamps into the log file
+pub fn init(verbose: u8, quiet: bool, log_file: Option<&Path>) -> Result<()> {
+    let level = level(verbose, quiet);
+    let layer = match log_file {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("cannot create log file {}", path.display()))?;
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .with_filter(level)
+                .boxed()
+        }
+        None => tracing_subscriber::fmt::layer()
+            .without_time()
+            .with_target(false)
+            .with_ansi(io::stderr().is_terminal())
+            .with_writer(Stderr)
+            .with_filter(level)
+            .with_filter(filter_fn(|meta| {
+                meta.target() != FRAMES || !progress::is_drawn()
+            }))
+            .boxed(),
+    };
+    tracing_subscriber::registry()
+        .with(layer)
+        .try_init()
+        .context("cannot install the logger")
+}
+
+/// Stderr with the progress bar cleared during the writes
+struct Stderr;
+
+impl Write for Stderr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_all(buf).map(|_| buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        progress::suspend_drawn(|| io::stderr().write_all(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for Stderr {
+    type Writer = Stderr;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Stderr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level() {
+        assert_eq!(level(0, false), LevelFilter::INFO);
+        assert_eq!(level(0, true), LevelFilter::WARN);
+        // the verbosity wins over quiet, which still hides the progress bar
+        assert_eq!(level(1, true), LevelFilter::DEBUG);
+        assert_eq!(level(3, false), LevelFilter::TRACE);
+    }
+}
@@ -1,27 +1,342 @@
-use clap::Parser;
+use anyhow::Context;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use one_web_extractor::Processor;
+use one_web_extractor::{
+    backfill, binning, catalog, clock, clustering, clusterize, columns, compare, config,
+    conformance, data_processor, database, derived, direction, disk, dose_equivalent,
+    energy_calibration, firmware, frame_image, gps_processor, hot_pixels, index, info_processor,
+    inspect, line_reader, logging, maneuver, manifest, noise, orbit, orientation, pixet, processor,
+    records, replay, repro, roi, schema, source, summary, tle, toa_calibration, tui, utils,
+    validate, watch, window,
+};
 use std::fs;
-
-mod clustering;
-mod data_processor;
-mod gps_processor;
-mod info_processor;
-mod processor;
-mod tpx3lut;
-mod utils;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Convertor of oneweb timepix data
 #[derive(Parser, Debug)]
-#[command(version = "1.0", about = "Convertor of oneweb timepix data")]
+#[command(
+    version = "1.0",
+    about = "Convertor of oneweb timepix data",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
-    /// Path to gps file (dosimeter_gps_info.csv)
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    convert: Option<ConvertArgs>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Convert the input files into daily cluster logs and metadata (also the default without a command)
+    Convert(Box<ConvertArgs>),
+    /// Print the frames of a data file, or one frame with its clusters, without writing files
+    Inspect(InspectArgs),
+    /// Decode a frame of the raw payloads again with other decoder settings and print the pixels decoded differently
+    Replay(ReplayArgs),
+    /// Print per-day statistics of the .info files of an output directory
+    Summarize(SummarizeArgs),
+    /// Check the headers, record format and time order of the input CSV files
+    Validate(ValidateArgs),
+    /// Extract a single frame with its metadata to standalone files
+    Extract(ExtractArgs),
+    /// List the available metadata columns
+    Columns,
+    /// Browse the frames of a data file in an interactive terminal UI
+    Tui(TuiArgs),
+    /// Add geolocation columns to existing .info metadata files from a GPS file, fill the empty GPS and measurement info columns of runs without the files
+    Backfill(BackfillArgs),
+    /// Cluster an external 256x256 pixel matrix (ASCII or .npy) with the flight clustering
+    Clusterize(ClusterizeArgs),
+    /// Decode generated pixel packets with known pixels and codes and report every difference to the ICD bit layout
+    Conformance(ConformanceArgs),
+    /// Print the JSON Schema of the --records frame and cluster records
+    Schema,
+    /// Check the sizes and SHA-256 checksums listed by the daily manifests of an archive tree
+    VerifyArchive(VerifyArchiveArgs),
+    /// Compare the products of several satellites over their common period
+    CompareSats(CompareSatsArgs),
+    /// Print the shell completion script (e.g. one-web-extractor completions bash > /etc/bash_completion.d/one-web-extractor)
+    Completions(CompletionsArgs),
+    /// Print the man page, or write the pages of all commands to a directory
+    Man(ManArgs),
+}
+
+#[derive(Args, Debug)]
+#[command(group = clap::ArgGroup::new("frame").multiple(false))]
+struct InspectArgs {
+    /// Path to data file (dosimeter_image_packets.csv), or a directory or glob of its chunks
+    #[arg(short = 'd', long)]
+    data_file: String,
+
+    /// Time of the frame (UTC, "YYYY-MM-DD HH:MM:SS[.fff]"), the closest frame is printed with its clusters
+    #[arg(long, group = "frame")]
+    at: Option<String>,
+
+    /// Frame number in the data file (1-based) printed with its clusters
+    #[arg(long, group = "frame")]
+    index: Option<usize>,
+
+    /// Energy per iToT count in keV used for the cluster features
+    #[arg(long, default_value = "1.0")]
+    kev_per_count: f64,
+
+    /// Merge clusters with bounding boxes separated by at most N pixels
+    #[arg(long)]
+    merge_distance: Option<u8>,
+
+    /// Pixel packet layout of the firmware (standard, swapped), detected from the first frames when not given
+    #[arg(long)]
+    firmware: Option<data_processor::PacketLayout>,
+}
+
+#[derive(Args, Debug)]
+struct ReplayArgs {
+    /// Raw frame payloads (dosimeter_image_packets.csv), or a directory or glob of its chunks
+    #[arg(long)]
+    raw_archive: String,
+
+    /// Frame number in the raw payloads (1-based)
+    #[arg(long)]
+    frame: usize,
+
+    /// Decoder setting of the replay (firmware=standard|swapped, lut-sentinels=keep|zero|invalid, merge-distance=N, toa-calibration=FILE), can be repeated
+    #[arg(long, value_name = "KEY=VALUE")]
+    decoder_option: Vec<replay::DecoderOption>,
+
+    /// Pixel packet layout of the baseline decoding (standard, swapped), detected from the first frames when not given
+    #[arg(long)]
+    firmware: Option<data_processor::PacketLayout>,
+}
+
+#[derive(Args, Debug)]
+struct SummarizeArgs {
+    /// Output directory of a conversion, searched recursively for data_<date>.info files
+    output_directory: String,
+
+    /// File for the summary table, printed when not given
+    #[arg(short = 'o', long)]
+    out: Option<String>,
+}
+
+#[derive(Args, Debug)]
+#[command(group = clap::ArgGroup::new("inputs").required(true).multiple(true))]
+struct ValidateArgs {
+    /// Path to gps file (dosimeter_gps_info.csv), or a directory or glob of its chunks
+    #[arg(short = 'g', long, group = "inputs")]
+    gps_file: Option<String>,
+
+    /// Path to measurement file (dosimeter_measure_info.csv), or a directory or glob of its chunks
+    #[arg(short = 'm', long, group = "inputs")]
+    meas_file: Option<String>,
+
+    /// Path to data file (dosimeter_image_packets.csv), or a directory or glob of its chunks
+    #[arg(short = 'd', long, group = "inputs")]
+    data_file: Option<String>,
+
+    /// Number of problems printed per file
+    #[arg(long, default_value = "20")]
+    max_problems: usize,
+}
+
+#[derive(Args, Debug)]
+struct CompletionsArgs {
+    /// Shell of the completion script
+    shell: clap_complete::Shell,
+}
+
+#[derive(Args, Debug)]
+struct ManArgs {
+    /// Directory for one page per command (one-web-extractor.1, one-web-extractor-extract.1, ...)
+    #[arg(short = 'o', long)]
+    out: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct CompareSatsArgs {
+    /// Output directories of the satellites as NAME=DIR or DIR (named by the directory), the first is the reference
+    #[arg(required = true, num_args = 2..)]
+    satellites: Vec<String>,
+
+    /// Report file, printed when not given
+    #[arg(short = 'o', long)]
+    out: Option<String>,
+
+    /// Maximum time in s between the SAA entries of matched passages
+    #[arg(long, default_value = "3600")]
+    saa_window: f64,
+}
+
+#[derive(Args, Debug)]
+struct VerifyArchiveArgs {
+    /// Archive directory, searched recursively for MANIFEST_<date>.json files
+    archive_dir: String,
+}
+
+#[derive(Args, Debug)]
+struct ClusterizeArgs {
+    /// Matrix of the pixel values (iToT), whitespace separated rows or NumPy .npy
+    matrix_file: String,
+
+    /// Matrix of the second pixel values (event count or ToA) written to the clog
+    #[arg(long)]
+    event_matrix: Option<String>,
+
+    /// Output directory
+    #[arg(short = 'o', long, default_value = ".")]
+    out: String,
+
+    /// Energy per iToT count in keV used for the cluster features
+    #[arg(long, default_value = "1.0")]
+    kev_per_count: f64,
+
+    /// Acquisition time of the matrix in s written to the clog
+    #[arg(long, default_value = "0")]
+    acq_time: f64,
+
+    /// Merge clusters with bounding boxes separated by at most N pixels
+    #[arg(long)]
+    merge_distance: Option<u8>,
+}
+
+#[derive(Args, Debug)]
+struct ConformanceArgs {
+    /// Number of random frames of short tracks
+    #[arg(long, default_value = "100", conflicts_with = "exhaustive")]
+    frames: usize,
+
+    /// Every pixel address, iToT code and event code in 16 frames instead of random frames
+    #[arg(long)]
+    exhaustive: bool,
+
+    /// Seed of the generated frames
+    #[arg(long, default_value = "0")]
+    seed: u64,
+
+    /// Pixel packet layout to check (standard, swapped), both when not given
+    #[arg(long)]
+    firmware: Option<data_processor::PacketLayout>,
+
+    /// Orientation the decoded matrices are compared in (see --orientation of convert)
+    #[arg(long, default_value = "detector")]
+    orientation: orientation::Orientation,
+
+    /// Directory for the generated data files and their ground truth (conformance_<layout>.csv, conformance_<layout>_truth.csv)
+    #[arg(short = 'o', long)]
+    out: Option<String>,
+}
+
+#[derive(Args, Debug)]
+#[command(group = clap::ArgGroup::new("inputs").required(true).multiple(true))]
+struct BackfillArgs {
+    /// Path to gps file (dosimeter_gps_info.csv), or a directory or glob of its chunks
+    #[arg(short = 'g', long, group = "inputs")]
+    gps_file: Option<String>,
+
+    /// Path to measurement file (dosimeter_measure_info.csv), fills the empty measurement info columns of runs without it
+    #[arg(short = 'm', long, group = "inputs")]
+    meas_file: Option<String>,
+
+    /// Hit pixel count ending a frame acquisition, for the filled acq_time column
+    #[arg(short = 'x', long, default_value = "1638")]
+    max_pix_count: usize,
+
+    /// Directory for the upgraded files, next to the originals when not given
+    #[arg(short = 'o', long)]
+    out: Option<String>,
+
+    /// Metadata files (.info) produced by the conversion
+    #[arg(required = true)]
+    info_files: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+#[command(group = clap::ArgGroup::new("frame").required(true))]
+struct ExtractArgs {
+    /// Path to gps file (dosimeter_gps_info.csv), or a directory or glob of its chunks
     #[arg(short = 'g', long)]
     gps_file: String,
 
-    /// Path to measurement file (dosimeter_measure_info.csv)
+    /// Path to measurement file (dosimeter_measure_info.csv), or a directory or glob of its chunks
     #[arg(short = 'm', long)]
     meas_file: String,
 
-    /// Path to data file (dosimeter_image_packets.csv)
+    /// Path to data file (dosimeter_image_packets.csv), or a directory or glob of its chunks
+    #[arg(short = 'd', long)]
+    data_file: String,
+
+    /// Time of the frame (UTC, "YYYY-MM-DD HH:MM:SS[.fff]"), the closest frame is taken
+    #[arg(long, group = "frame")]
+    at: Option<String>,
+
+    /// Frame number in the data file (1-based)
+    #[arg(long, group = "frame")]
+    index: Option<usize>,
+
+    /// Output directory
+    #[arg(short = 'o', long)]
+    out: String,
+
+    /// Hit pixel count ending a frame acquisition, used to estimate acq_time from the test counts
+    #[arg(short = 'x', long, default_value = "1638")]
+    max_pix_count: u32,
+}
+
+#[derive(Args, Debug)]
+#[command(group = clap::ArgGroup::new("start").multiple(false))]
+struct TuiArgs {
+    /// Path to gps file (dosimeter_gps_info.csv), or a directory or glob of its chunks
+    #[arg(short = 'g', long)]
+    gps_file: String,
+
+    /// Path to measurement file (dosimeter_measure_info.csv), or a directory or glob of its chunks
+    #[arg(short = 'm', long)]
+    meas_file: String,
+
+    /// Path to data file (dosimeter_image_packets.csv), or a directory or glob of its chunks
+    #[arg(short = 'd', long)]
+    data_file: String,
+
+    /// Time of the first frame shown (UTC, "YYYY-MM-DD HH:MM:SS[.fff]")
+    #[arg(long, group = "start")]
+    at: Option<String>,
+
+    /// Number of the first frame shown (1-based)
+    #[arg(long, group = "start")]
+    index: Option<usize>,
+
+    /// Hit pixel count ending a frame acquisition, used to estimate acq_time from the test counts
+    #[arg(short = 'x', long, default_value = "1638")]
+    max_pix_count: u32,
+
+    /// Pixel packet layout of the firmware (standard, swapped), detected from the first frames when not given
+    #[arg(long)]
+    firmware: Option<data_processor::PacketLayout>,
+}
+
+#[derive(Args, Debug)]
+struct ConvertArgs {
+    /// Path to gps file (dosimeter_gps_info.csv), or a directory or glob of its chunks
+    #[arg(short = 'g', long, required_unless_present = "no_gps")]
+    gps_file: Option<String>,
+
+    /// Path to measurement file (dosimeter_measure_info.csv), or a directory or glob of its chunks
+    #[arg(short = 'm', long, required_unless_present = "no_meas")]
+    meas_file: Option<String>,
+
+    /// Run without the GPS file: the GPS columns are left empty, to be added later with the backfill command
+    #[arg(long, conflicts_with = "gps_file")]
+    no_gps: bool,
+
+    /// Run without the measurement info file: the measurement info columns are left empty and the acquisition times unknown (0 s in the products), to be added later with the backfill command
+    #[arg(long, conflicts_with = "meas_file")]
+    no_meas: bool,
+
+    /// Path to data file (dosimeter_image_packets.csv), or a directory or glob of its chunks
     #[arg(short = 'd', long)]
     data_file: String,
 
@@ -29,34 +344,1462 @@ struct Cli {
     #[arg(short = 'o', long)]
     output_directory: String,
 
-    /// Max pixel hit count
+    /// Hit pixel count ending a frame acquisition, used to estimate acq_time from the test counts
     #[arg(short = 'x', long, default_value = "1638")]
     max_pix_count: u32,
+
+    /// Only write frames with the subsatellite point in the region (lat1,lon1,lat2,lon2)
+    #[arg(long, allow_hyphen_values = true)]
+    bbox: Option<orbit::BoundingBox>,
+
+    /// Maximum time in s between a frame and its GPS record, the GPS columns of frames without a record this close are left empty
+    #[arg(long)]
+    max_gps_staleness: Option<f64>,
+
+    /// Maximum time in s between the start of readout of a frame and its last data line, frames still assembled after it are aborted as corrupt
+    #[arg(long)]
+    max_assembly_time: Option<f64>,
+
+    /// Number of days of the data file decoded in parallel
+    #[arg(short = 'j', long, default_value = "1")]
+    jobs: usize,
+
+    /// Threads decoding and clustering the frames of each job while another one reads and assembles them, 1 decodes on the reading thread
+    #[arg(long, default_value = "1")]
+    decode_threads: usize,
+
+    /// Chunk size of the thread reading ahead in the data file (e.g. 4M), 0 reads on the decoding thread
+    #[arg(long, default_value = "1M")]
+    read_ahead: disk::ByteSize,
+
+    /// Reconnection of the tcp:// and http:// inputs: attempts,initial backoff in s (doubling up to 60 s)
+    #[arg(long, default_value = "5,1")]
+    retry: source::RetryPolicy,
+
+    /// Keep the inputs open and convert the lines appended to them until interrupted (Ctrl-C) or idle, for live ingestion during downlink passes
+    #[arg(long, conflicts_with = "verify_repro")]
+    watch: bool,
+
+    /// Time in s between the checks of the watched inputs for appended data
+    #[arg(long, default_value = "1", requires = "watch")]
+    watch_interval: f64,
+
+    /// End the watching after this many seconds without new data in an input
+    #[arg(long, requires = "watch")]
+    watch_idle: Option<f64>,
+
+    /// Save the state of the conversion to this file, an interrupted run continues from it with --resume
+    #[arg(long, conflicts_with = "verify_repro")]
+    checkpoint: Option<String>,
+
+    /// Written frames between the checkpoints
+    #[arg(long, default_value = "1000", requires = "checkpoint")]
+    checkpoint_every: usize,
+
+    /// Continue from the checkpoint, appending to the existing output files
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    /// Cumulative per-pixel dose map file, created or updated by the run
+    #[arg(long)]
+    dose_map: Option<String>,
+
+    /// Energy per iToT count in keV used for the dose map
+    #[arg(long, default_value = "1.0")]
+    kev_per_count: f64,
+
+    /// Persistent per-pixel firing statistics (fixed size sketches of the distinct frames), created or updated by the run
+    #[arg(long)]
+    hot_pixel_stats: Option<String>,
+
+    /// Mask of the pixels hit in at least --hot-pixel-fraction of the frames of the firing statistics, as x y fraction lines
+    #[arg(long, requires = "hot_pixel_stats")]
+    hot_pixel_mask: Option<String>,
+
+    /// Fraction of the frames a pixel must be hit in to be masked as hot
+    #[arg(long, default_value = "0.2")]
+    hot_pixel_fraction: f64,
+
+    /// Pixels removed from the decoded frames before the clustering, as x y lines (e.g. a --hot-pixel-mask of an earlier run)
+    #[arg(long, value_name = "FILE")]
+    mask_file: Option<String>,
+
+    /// Directory with the per-pixel energy calibration matrices a.txt, b.txt, c.txt and t.txt
+    #[arg(long)]
+    calib_dir: Option<String>,
+
+    /// Write the calibrated pixel energies in keV to the .clog files instead of the iToT counts
+    #[arg(long, requires = "calib_dir")]
+    clog_energy: bool,
+
+    /// Orientation of the pixel coordinates of the .clog files, records, cluster features, event displays and previews: detector (as decoded), pixet (Pixet display, rows flipped), rot90, rot180, rot270, flip-x, flip-y or transpose
+    #[arg(long, default_value = "detector")]
+    orientation: orientation::Orientation,
+
+    /// Handling of undecodable data lines: abort, skip (warn and drop) or salvage (keep the valid prefix); GPS and measurement info lines not matching the schema are skipped unless abort
+    #[arg(long, default_value = "skip")]
+    on_bad_line: data_processor::ErrorPolicy,
+
+    /// Skipped GPS and measurement info lines quoted per file in the schema drift summary
+    #[arg(long, default_value = "5")]
+    drift_samples: usize,
+
+    /// Merge clusters with bounding boxes separated by at most N pixels (tracks split by dead pixels)
+    #[arg(long)]
+    merge_distance: Option<u8>,
+
+    /// Per-frame threshold of the hit pixels from the iToT distribution of the frame: percentile:P or fence:K (median minus K median absolute deviations)
+    #[arg(long)]
+    adaptive_threshold: Option<noise::AdaptiveThreshold>,
+
+    /// Pixel codes outside the ToT/iToT lookup tables: keep (sentinel value), zero, or invalid (zeroed and listed in the metadata)
+    #[arg(long, default_value = "keep")]
+    lut_sentinels: data_processor::SentinelPolicy,
+
+    /// Skip GPS records with an implausible position or attitude quaternion instead of flagging them
+    #[arg(long)]
+    reject_invalid_gps: bool,
+
+    /// Replace single-sample GPS position outliers (jumps faster than an orbit) by the interpolation between their neighbours before the matching
+    #[arg(long)]
+    deglitch_gps: bool,
+
+    /// Attitude change in degrees between consecutive GPS samples taken as a maneuver, the matched frames are handled by --on-maneuver
+    #[arg(long)]
+    max_attitude_jump: Option<f64>,
+
+    /// Attitude dependent products of frames matched to a maneuver: flag (maneuver column) or exclude (empty quaternion columns and J2000 cluster directions)
+    #[arg(long, default_value = "flag")]
+    on_maneuver: maneuver::ManeuverPolicy,
+
+    /// TLE file propagated (SGP4) to cross-check the GPS positions, adds the residuals to the run summary
+    #[arg(long)]
+    tle_file: Option<String>,
+
+    /// Position frames without a current valid GPS record from the --tle-file propagation (no attitude)
+    #[arg(long, requires = "tle_file")]
+    tle_substitute: bool,
+
+    /// Keep about one in N frames (selected by timestamp), aggregated products are weighted by N
+    #[arg(long, default_value = "1")]
+    decimate: usize,
+
+    /// Only decode and write the frames from this time on (UTC, YYYY-MM-DD HH:MM:SS[.fff])
+    #[arg(long)]
+    start: Option<String>,
+
+    /// Only decode and write the frames before this time (UTC, YYYY-MM-DD HH:MM:SS[.fff])
+    #[arg(long)]
+    end: Option<String>,
+
+    /// Region of interest of the pixel matrix (name:x1,y1,x2,y2), can be repeated
+    #[arg(long)]
+    roi: Vec<roi::Roi>,
+
+    /// File for the per-ROI counts, dose and cluster spectra of the run
+    #[arg(long)]
+    roi_report: Option<String>,
+
+    /// CSV file listing the time windows with quality problems and the options suggested for reprocessing them
+    #[arg(long)]
+    reprocess_list: Option<String>,
+
+    /// Report of clusters above --see-threshold coinciding with temperature jumps or error_id changes
+    #[arg(long)]
+    see_report: Option<String>,
+
+    /// Cluster energy in keV above which clusters are checked for single event effect coincidences
+    #[arg(long, default_value = "5000")]
+    see_threshold: f64,
+
+    /// Maximum time in s between a cluster and a housekeeping anomaly in the SEE report
+    #[arg(long, default_value = "30")]
+    see_window: f64,
+
+    /// Report of the per-orbit duty cycle (acquisition time over orbit duration) and missing measurement periods
+    #[arg(long)]
+    duty_cycle: Option<String>,
+
+    /// Time in s without frames counted as a missing measurement period in the duty cycle report
+    #[arg(long, default_value = "120")]
+    duty_gap: f64,
+
+    /// Report of the hit pixel and dose rates folded by orbit phase (argument of latitude), mean and sigma per day and per ISO week
+    #[arg(long)]
+    phase_profile: Option<String>,
+
+    /// Number of orbit phase bins of the --phase-profile report
+    #[arg(long, default_value = "36")]
+    phase_bins: usize,
+
+    /// Report of the frame temperatures per day, sunlit and in eclipse, with the orbital thermal cycle fitted over the orbit phase
+    #[arg(long)]
+    thermal_report: Option<String>,
+
+    /// Report of the payload modes inferred from the measurement cadence with the per-mode frame totals and cluster spectra
+    #[arg(long)]
+    mode_report: Option<String>,
+
+    /// Report of the cluster energy spectra per radiation region (saa, polar, low_latitude) and cluster type
+    #[arg(long)]
+    region_spectra: Option<String>,
+
+    /// Add the SAA and horn spectra minus the low latitude (quiet) background to the --region-spectra report
+    #[arg(long, requires = "region_spectra")]
+    subtract_quiet: bool,
+
+    /// Report of the daily absorbed dose, mean and peak dose rate of the sensor
+    #[arg(long)]
+    dose_summary: Option<String>,
+
+    /// Report of the daily dose equivalent (H*(10)) and the LET spectrum of the clusters
+    #[arg(long)]
+    dose_equivalent: Option<String>,
+
+    /// Quality factor curve Q(L) of the dose equivalent: icrp60 or icrp26
+    #[arg(long, default_value = "icrp60")]
+    quality_factor: dose_equivalent::QualityFactor,
+
+    /// Print a coarse terminal heatmap (log scale summed iToT) of every Nth frame during the conversion
+    #[arg(long)]
+    preview_every: Option<usize>,
+
+    /// Print neither the progress bar nor a line per frame, only warnings and the summary
+    #[arg(short = 'q', long)]
+    quiet: bool,
+
+    /// Log the decoding diagnostics (-v) and the pixel packets (-vv)
+    #[arg(short = 'v', long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Write the log (progress, warnings and unexpected data) with timestamps into the file instead of stderr
+    #[arg(long)]
+    log_file: Option<String>,
+
+    /// Render the matrices of every written frame as images into frames_<date> directories of the output directory: itot, event or both
+    #[arg(long)]
+    frame_images: Option<frame_image::FrameMatrices>,
+
+    /// Format of the frame images: png (colormapped), tiff (16-bit grayscale raw values) or both
+    #[arg(long, default_value = "png")]
+    image_format: frame_image::ImageFormat,
+
+    /// Colormap of the PNG frame images: gray, viridis, inferno or hot
+    #[arg(long, default_value = "viridis")]
+    colormap: frame_image::Colormap,
+
+    /// Scaling of the PNG frame images from 0 to the frame maximum: linear or log
+    #[arg(long, default_value = "log")]
+    image_scale: frame_image::ImageScale,
+
+    /// Also write the frames of each day as Pixet files: pmf (ASCII iToT matrices with a .pmf.dsc descriptor), t3pa (pixel list) or both
+    #[arg(long)]
+    pixet: Option<pixet::PixetFormat>,
+
+    /// Timestamp deciding the daily output file of a frame: frame (readout time) or info (legacy, matched measurement info record)
+    #[arg(long, default_value = "frame")]
+    day_split: index::DaySplit,
+
+    /// Frame numbers in the .clog and .info files: file (restart at 1 in each daily file) or global (frame number in the data file)
+    #[arg(long, default_value = "file")]
+    frame_numbering: index::FrameNumbering,
+
+    /// Frame time used for the GPS matching, the day split and the frame_timestamp column: first-line, last-line (of the frame packets), info (matched measurement info time) or mid-exposure (info time minus half the acquisition time)
+    #[arg(long, default_value = "first-line")]
+    frame_time_source: index::FrameTimeSource,
+
+    /// Also write each frame with its metadata and clusters as a record to data_<date>.<format>: cbor or jsonl (see the schema command)
+    #[arg(long)]
+    records: Option<records::RecordFormat>,
+
+    /// Outputs: files (the daily .clog and .info files), sqlite (also the frames, clusters, pixels and matched GPS and measurement info records in the database data.sqlite) or parquet (also one row per cluster in data_<date>.clusters.parquet)
+    #[arg(long, default_value = "files")]
+    format: database::OutputFormat,
+
+    /// Frames of a row group of the --format parquet cluster tables, the rows of a group are held in memory until it is written
+    #[arg(long, default_value = "1000")]
+    chunk_frames: usize,
+
+    /// Also write the morphology features of each cluster (size, values, bounding box, roundness, linearity, border flag, weighted centroid) to data_<date>.clusters.csv
+    #[arg(long)]
+    cluster_features: bool,
+
+    /// Minimum free space of the output directory (e.g. 500M, 2G), checked before and during the run
+    #[arg(long)]
+    min_free_space: Option<disk::ByteSize>,
+
+    /// Action below --min-free-space: abort (stop after the last complete frame) or pause (wait for free space)
+    #[arg(long, default_value = "abort")]
+    on_low_disk: disk::LowDiskPolicy,
+
+    /// Reference frame of the exported positions: j2000 (GPS records), teme or itrf (Earth fixed), replaces the gps_x/y/z columns
+    #[arg(long, default_value = "j2000")]
+    position_frame: orbit::ReferenceFrame,
+
+    /// GPS position columns: nearest (closest GPS record), interpolated (to the frame time between the records around it) or both (nearest with the interpolated position in the interp_x/y/z and interp_offset columns)
+    #[arg(long, default_value = "nearest")]
+    gps_columns: columns::GpsColumns,
+
+    /// Append the sensor boresight, spacecraft yaw/pitch/roll and ram angle columns derived from the attitude quaternions, with the directions in j2000, teme, itrf (Earth fixed) or lvlh (local vertical local horizontal)
+    #[arg(long, value_name = "FRAME")]
+    pointing_frame: Option<direction::PointingFrame>,
+
+    /// Round the lat, lon, alt and l_shell columns to bins for public releases, e.g. lat=0.1,lon=0.1,alt=1,l_shell=0.1 (recorded in the output headers)
+    #[arg(long, default_value = "none")]
+    position_binning: binning::PositionBinning,
+
+    /// Seed of the pseudo-random frame selection of --decimate, recorded in the output headers
+    #[arg(long, default_value = "0")]
+    seed: u64,
+
+    /// Time every timed step as 1 ms so the --timing report is reproducible (for tests)
+    #[arg(long)]
+    simulated_clock: bool,
+
+    /// TOML configuration file (e.g. columns = ["frame_index", "timestamp", "lat", "lon"])
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Pixel packet layout of all frames (standard, swapped); when not given each frame uses the layout of its firmware release, or the one detected from the first frames
+    #[arg(long)]
+    firmware: Option<data_processor::PacketLayout>,
+
+    /// Action on frames of a firmware release the decoder does not support: abort or warn (decode them anyway)
+    #[arg(long, default_value = "abort")]
+    on_unsupported_firmware: firmware::FirmwarePolicy,
+
+    /// Decompress the payload of frames whose start of readout header has bit 7 of byte 4 set as run length encoded (CC n v escapes); no firmware release documents this flag, so it is off by default
+    #[arg(long)]
+    rle: bool,
+
+    /// Clustering hardware: cpu or gpu (needs a build with the gpu feature, falls back to cpu without a GPU)
+    #[arg(long, default_value = "cpu")]
+    backend: clustering::ClusterBackend,
+
+    /// Print the per-stage timing breakdown and throughput at the end of the run
+    #[arg(long)]
+    timing: bool,
+
+    /// Directory for SVG event displays of the most energetic clusters of each day
+    #[arg(long)]
+    event_display: Option<String>,
+
+    /// Number of clusters rendered per day
+    #[arg(long, default_value = "10")]
+    event_display_top: usize,
+
+    /// Catalog the K most energetic clusters of each day and orbit with their frame crop, position, attitude and class in events_<date>.json
+    #[arg(long, value_name = "K")]
+    event_catalog: Option<usize>,
+
+    /// Convert twice, the first pass gathers the frame count, time coverage and dose of each day written into the headers of its files
+    #[arg(long)]
+    two_pass: bool,
+
+    /// Reprocess the inputs and check the existing outputs are reproduced exactly
+    #[arg(long)]
+    verify_repro: bool,
+
+    /// Register the run, its days and files in a catalog: a SQLite file or a postgres:// connection string (needs a build with the postgres feature)
+    #[arg(long, requires = "satellite")]
+    catalog: Option<catalog::CatalogTarget>,
+
+    /// Satellite of the data file, recorded in the catalog
+    #[arg(long)]
+    satellite: Option<String>,
+}
+
+/// Runs the conversion into out_dir, returns false on error
+fn run(
+    processor: &mut Processor,
+    gps_file: Option<&str>,
+    meas_file: Option<&str>,
+    data_file: &str,
+    out_dir: &str,
+) -> bool {
+    if let Err(e) = processor.process_files(gps_file, meas_file, data_file, out_dir)
+        && !processor::is_end_of_data(&e)
+    {
+        eprintln!("Error processing files: {:#}", e);
+        return false;
+    }
+    let ledger = processor.ledger();
+    println!(
+        "Written {} frames ({:.3} s), skipped {} frames ({:.3} s).",
+        ledger.written_frames, ledger.written_time, ledger.skipped_frames, ledger.skipped_time
+    );
+    if ledger.bad_lines > 0 {
+        println!("Undecodable data lines: {}.", ledger.bad_lines);
+    }
+    if ledger.truncated_frames > 0 {
+        println!(
+            "Frames without an end of readout, closed by the next start of readout: {}.",
+            ledger.truncated_frames
+        );
+    }
+    if ledger.overlong_frames > 0 {
+        println!(
+            "Frames aborted as corrupt after {} s of assembly: {}.",
+            processor.config().max_assembly_time.unwrap_or_default(),
+            ledger.overlong_frames
+        );
+    }
+    for line in ledger
+        .schema_drift
+        .summary(processor.config().drift_samples)
+    {
+        println!("{}", line);
+    }
+    if ledger.sentinel_pixels > 0 {
+        println!(
+            "Pixels with codes outside the lookup tables: {} ({}).",
+            ledger.sentinel_pixels,
+            processor.config().sentinel_policy
+        );
+    }
+    if ledger.noise_pixels > 0 {
+        println!("Pixels below the noise threshold: {}.", ledger.noise_pixels);
+    }
+    if ledger.masked_pixels > 0 {
+        println!("Hits of masked pixels removed: {}.", ledger.masked_pixels);
+    }
+    if ledger.adaptive_pixels > 0 {
+        println!(
+            "Pixels below the adaptive threshold: {}.",
+            ledger.adaptive_pixels
+        );
+    }
+    if ledger.merged_clusters > 0 {
+        println!(
+            "Clusters merged within {} pixels: {}.",
+            processor.config().merge_distance.unwrap_or_default(),
+            ledger.merged_clusters
+        );
+    }
+    if ledger.decimated_frames > 0 {
+        println!(
+            "Decimated {} frames ({:.3} s), kept frames weighted by {}.",
+            ledger.decimated_frames,
+            ledger.decimated_time,
+            processor.config().decimate
+        );
+    }
+    if let Some(report) = processor.roi_report() {
+        for (i, (roi, stats)) in report.rois.iter().zip(&report.stats).enumerate() {
+            println!(
+                "ROI {}: {} hits, {} clusters, dose {:e} Gy.",
+                roi,
+                stats.hits,
+                stats.clusters,
+                report.dose(i)
+            );
+        }
+    }
+    if ledger.over_max_pix_frames > 0 {
+        println!(
+            "Frames with more hit pixels than the max pixel count {}: {} (check --max-pix-count).",
+            processor.config().max_pix_count,
+            ledger.over_max_pix_frames
+        );
+    }
+    if ledger.window_lines > 0 || ledger.window_frames > 0 {
+        println!(
+            "Outside the time window: {} data lines skipped, {} decoded frames dropped.",
+            ledger.window_lines, ledger.window_frames
+        );
+    }
+    if ledger.unknown_firmware_frames > 0 {
+        println!(
+            "Frames of unknown firmware releases: {}.",
+            ledger.unknown_firmware_frames
+        );
+    }
+    if ledger.unsupported_firmware_frames > 0 {
+        println!(
+            "Frames of unsupported firmware releases, possibly decoded wrongly: {}.",
+            ledger.unsupported_firmware_frames
+        );
+    }
+    if ledger.repaired_gps_records > 0 {
+        println!(
+            "GPS records with an outlier position replaced by the interpolation: {}.",
+            ledger.repaired_gps_records
+        );
+    }
+    if ledger.invalid_gps_frames > 0 {
+        println!(
+            "Frames with invalid GPS records: {}.",
+            ledger.invalid_gps_frames
+        );
+    }
+    if processor.config().no_gps {
+        println!(
+            "Run without the GPS file, the GPS columns of {} frames are empty.",
+            ledger.stale_gps_frames
+        );
+    } else if ledger.stale_gps_frames > 0 {
+        println!(
+            "Frames without a GPS record within {} s: {}.",
+            processor.config().max_gps_staleness.unwrap_or_default(),
+            ledger.stale_gps_frames
+        );
+    }
+    if ledger.maneuver_frames > 0 {
+        println!(
+            "Frames matched to GPS records during attitude maneuvers: {} ({}).",
+            ledger.maneuver_frames,
+            processor.config().on_maneuver
+        );
+    }
+    if ledger.tle_residual_frames > 0 {
+        println!(
+            "GPS positions against the TLE propagation: mean residual {:.3} km, max {:.3} km over {} frames.",
+            ledger.tle_residual_sum / ledger.tle_residual_frames as f64 / 1e3,
+            ledger.tle_residual_max / 1e3,
+            ledger.tle_residual_frames
+        );
+    }
+    if ledger.tle_substituted_frames > 0 {
+        println!(
+            "Frames positioned by the TLE propagation: {}.",
+            ledger.tle_substituted_frames
+        );
+    }
+    for mismatch in &ledger.pairing_mismatches {
+        println!("WARNING: clog/info mismatch, {}.", mismatch);
+    }
+    for window in processor.reprocess_windows() {
+        println!("Suggestion: {}.", window);
+    }
+    true
+}
+
+fn verify_repro(
+    processor: &mut Processor,
+    gps_file: Option<&str>,
+    meas_file: Option<&str>,
+    data_file: &str,
+    out_dir: &str,
+) -> bool {
+    let verify_dir = Path::new(out_dir).join(".repro-verify");
+    if fs::create_dir_all(&verify_dir).is_err() {
+        eprintln!("Error creating directory: {}", verify_dir.display());
+        return false;
+    }
+    let verify_dir_str = verify_dir.to_string_lossy().to_string();
+    if !run(processor, gps_file, meas_file, data_file, &verify_dir_str) {
+        return false;
+    }
+
+    let result = repro::compare_outputs(Path::new(out_dir), &verify_dir, processor.repro_hash());
+    let _ = fs::remove_dir_all(&verify_dir);
+    match result {
+        Ok(mismatches) if mismatches.is_empty() => {
+            println!(
+                "Outputs reproduced exactly (repro hash {}).",
+                processor.repro_hash()
+            );
+            true
+        }
+        Ok(mismatches) => {
+            for mismatch in &mismatches {
+                eprintln!("{}", mismatch);
+            }
+            eprintln!("Reproduction check failed for {} files.", mismatches.len());
+            false
+        }
+        Err(e) => {
+            eprintln!("Error comparing outputs: {:#}", e);
+            false
+        }
+    }
+}
+
+fn extract(args: ExtractArgs) -> bool {
+    let selector = match (&args.at, args.index) {
+        (Some(at), _) => match utils::parse_datetime_arg(at) {
+            Ok(timestamp) => index::FrameSelector::Timestamp(timestamp),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                return false;
+            }
+        },
+        (None, Some(index)) => index::FrameSelector::Index(index),
+        (None, None) => unreachable!("clap requires --at or --index"),
+    };
+    if fs::create_dir_all(&args.out).is_err() {
+        eprintln!("Error creating output directory: {}", args.out);
+        return false;
+    }
+    let config = processor::ProcessorConfig {
+        max_pix_count: args.max_pix_count as usize,
+        ..Default::default()
+    };
+    if let Err(e) = config.validate() {
+        eprintln!("Error: {:#}", e);
+        return false;
+    }
+    let mut processor = Processor::new(config);
+    match processor.extract_frame(
+        &args.gps_file,
+        &args.meas_file,
+        &args.data_file,
+        selector,
+        &args.out,
+    ) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Error extracting frame: {:#}", e);
+            false
+        }
+    }
+}
+
+fn inspect(args: InspectArgs) -> bool {
+    let selector = match (&args.at, args.index) {
+        (Some(at), _) => match utils::parse_datetime_arg(at) {
+            Ok(timestamp) => Some(index::FrameSelector::Timestamp(timestamp)),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                return false;
+            }
+        },
+        (None, index) => index.map(index::FrameSelector::Index),
+    };
+    let layout = match args.firmware {
+        Some(layout) => Ok(layout),
+        None => line_reader::LineReader::open(&args.data_file)
+            .map(|mut reader| data_processor::DataProcessor::detect_layout(&mut reader, 20).layout),
+    };
+    let mut data_processor = data_processor::DataProcessor::new();
+    data_processor.merge_distance = args.merge_distance;
+    let mut out = std::io::stdout().lock();
+    let result = layout.and_then(|layout| {
+        data_processor.layout = layout;
+        match selector {
+            Some(selector) => inspect::inspect_frame(
+                &args.data_file,
+                selector,
+                &mut data_processor,
+                args.kev_per_count,
+                &mut out,
+            ),
+            None => {
+                inspect::inspect_frames(&args.data_file, &mut data_processor, &mut out).map(|_| ())
+            }
+        }
+    });
+    if let Err(e) = result {
+        eprintln!("Error: {:#}", e);
+        return false;
+    }
+    true
+}
+
+fn replay(args: ReplayArgs) -> bool {
+    let layout = match args.firmware {
+        Some(layout) => Ok(layout),
+        None => line_reader::LineReader::open(&args.raw_archive)
+            .map(|mut reader| data_processor::DataProcessor::detect_layout(&mut reader, 20).layout),
+    };
+    let mut baseline = data_processor::DataProcessor::new();
+    let mut out = std::io::stdout().lock();
+    let result = layout.and_then(|layout| {
+        baseline.layout = layout;
+        let mut replay = baseline.worker();
+        for option in &args.decoder_option {
+            option.apply(&mut replay)?;
+        }
+        replay::replay_frame(
+            &args.raw_archive,
+            args.frame,
+            &mut baseline,
+            &mut replay,
+            &args.decoder_option,
+            &mut out,
+        )
+    });
+    if let Err(e) = result {
+        eprintln!("Error: {:#}", e);
+        return false;
+    }
+    true
+}
+
+fn summarize(args: SummarizeArgs) -> bool {
+    let products = compare::SatProducts::load("", Path::new(&args.output_directory));
+    let mut report = Vec::new();
+    let written = products
+        .and_then(|products| {
+            summary::write_summary(&summary::summarize_days(&products.rows), &mut report)
+        })
+        .and_then(|_| match &args.out {
+            Some(path) => {
+                fs::write(path, &report).with_context(|| format!("cannot write {}", path))
+            }
+            None => {
+                print!("{}", String::from_utf8_lossy(&report));
+                Ok(())
+            }
+        });
+    if let Err(e) = written {
+        eprintln!("Error: {:#}", e);
+        return false;
+    }
+    true
+}
+
+fn validate(args: ValidateArgs) -> bool {
+    let inputs = [
+        (validate::InputKind::Gps, &args.gps_file),
+        (validate::InputKind::MeasInfo, &args.meas_file),
+        (validate::InputKind::Data, &args.data_file),
+    ];
+    let mut ok = true;
+    for (kind, path) in inputs {
+        let Some(path) = path else {
+            continue;
+        };
+        let report = match line_reader::LineReader::open(path)
+            .and_then(|mut reader| validate::validate(kind, &mut reader))
+        {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                ok = false;
+                continue;
+            }
+        };
+        for problem in report.problems.iter().take(args.max_problems) {
+            eprintln!("{}", problem);
+        }
+        if report.problems.len() > args.max_problems {
+            eprintln!(
+                "... {} more problems",
+                report.problems.len() - args.max_problems
+            );
+        }
+        let span = report
+            .span
+            .map(|(first, last)| {
+                format!(
+                    ", {} to {}",
+                    utils::format_time(first),
+                    utils::format_time(last)
+                )
+            })
+            .unwrap_or_default();
+        println!(
+            "{} file {}: {} records{}, {} problems.",
+            kind,
+            path,
+            report.records,
+            span,
+            report.problems.len()
+        );
+        ok &= report.problems.is_empty();
+    }
+    ok
+}
+
+fn backfill(args: BackfillArgs) -> bool {
+    let mut inputs = backfill::BackfillInputs {
+        max_pix_count: args.max_pix_count,
+        ..Default::default()
+    };
+    if let Some(file) = &args.gps_file {
+        let gps = line_reader::LineReader::open(file)
+            .and_then(|mut reader| gps_processor::GpsProcessor::new().read_all(&mut reader));
+        match gps {
+            Ok(gps) => inputs.gps = Some((gps, file.clone())),
+            Err(e) => {
+                eprintln!("Error reading GPS file: {:#}", e);
+                return false;
+            }
+        }
+    }
+    if let Some(file) = &args.meas_file {
+        let meas = line_reader::LineReader::open(file)
+            .and_then(|mut reader| info_processor::MeasInfoProcessor::new().read_all(&mut reader));
+        match meas {
+            Ok(meas) => inputs.meas = Some((meas, file.clone())),
+            Err(e) => {
+                eprintln!("Error reading measurement info file: {:#}", e);
+                return false;
+            }
+        }
+    }
+    let out_dir = args.out.as_deref().map(Path::new);
+    if let Some(dir) = out_dir
+        && fs::create_dir_all(dir).is_err()
+    {
+        eprintln!("Error creating output directory: {}", dir.display());
+        return false;
+    }
+    let mut ok = true;
+    for info_file in &args.info_files {
+        match backfill::backfill_file(Path::new(info_file), &inputs, out_dir) {
+            Ok((path, rows)) => println!("{} -> {} ({} rows)", info_file, path.display(), rows),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+fn compare_sats(args: CompareSatsArgs) -> bool {
+    let mut sats = Vec::new();
+    for sat in &args.satellites {
+        let (name, dir) = match sat.split_once('=') {
+            Some((name, dir)) => (name.to_string(), dir),
+            None => (
+                Path::new(sat)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| sat.clone()),
+                sat.as_str(),
+            ),
+        };
+        match compare::SatProducts::load(&name, Path::new(dir)) {
+            Ok(products) => sats.push(products),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                return false;
+            }
+        }
+    }
+    let mut report = Vec::new();
+    let written =
+        compare::write_report(&sats, args.saa_window, &mut report).and_then(|_| match &args.out {
+            Some(path) => {
+                fs::write(path, &report).with_context(|| format!("cannot write {}", path))
+            }
+            None => {
+                print!("{}", String::from_utf8_lossy(&report));
+                Ok(())
+            }
+        });
+    if let Err(e) = written {
+        eprintln!("Error: {:#}", e);
+        return false;
+    }
+    true
+}
+
+fn man(args: ManArgs) -> bool {
+    let command = Cli::command();
+    let result = match &args.out {
+        Some(dir) => fs::create_dir_all(dir).and_then(|_| clap_mangen::generate_to(command, dir)),
+        None => clap_mangen::Man::new(command).render(&mut std::io::stdout()),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: cannot write the man page: {}", e);
+        return false;
+    }
+    true
+}
+
+fn verify_archive(args: VerifyArchiveArgs) -> bool {
+    match manifest::verify_archive(Path::new(&args.archive_dir)) {
+        Ok(report) => {
+            for problem in &report.problems {
+                eprintln!("{}", problem);
+            }
+            println!(
+                "Checked {} manifests listing {} files, {} problems.",
+                report.manifests,
+                report.files,
+                report.problems.len()
+            );
+            report.problems.is_empty()
+        }
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            false
+        }
+    }
+}
+
+fn clusterize(args: ClusterizeArgs) -> bool {
+    if fs::create_dir_all(&args.out).is_err() {
+        eprintln!("Error creating output directory: {}", args.out);
+        return false;
+    }
+    match clusterize::clusterize_file(
+        Path::new(&args.matrix_file),
+        args.event_matrix.as_deref().map(Path::new),
+        Path::new(&args.out),
+        args.kev_per_count,
+        args.acq_time,
+        args.merge_distance,
+    ) {
+        Ok((path, clusters)) => {
+            println!(
+                "{} -> {} ({} clusters)",
+                args.matrix_file,
+                path.display(),
+                clusters
+            );
+            true
+        }
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            false
+        }
+    }
+}
+
+fn conformance(args: ConformanceArgs) -> bool {
+    if let Some(out) = &args.out
+        && fs::create_dir_all(out).is_err()
+    {
+        eprintln!("Error creating output directory: {}", out);
+        return false;
+    }
+    let options = conformance::ConformanceOptions {
+        frames: args.frames,
+        exhaustive: args.exhaustive,
+        seed: args.seed,
+        orientation: args.orientation,
+    };
+    let layouts = match args.firmware {
+        Some(layout) => vec![layout],
+        None => vec![
+            data_processor::PacketLayout::Standard,
+            data_processor::PacketLayout::Swapped,
+        ],
+    };
+    let mut ok = true;
+    for layout in layouts {
+        let report = match conformance::run(layout, &options).and_then(|report| {
+            if let Some(out) = &args.out {
+                report.save(Path::new(out))?;
+            }
+            Ok(report)
+        }) {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                return false;
+            }
+        };
+        for mismatch in report.mismatches.iter().take(20) {
+            eprintln!("{}: {}", layout, mismatch);
+        }
+        let detection = report
+            .detection
+            .map(|d| {
+                format!(
+                    ", detected {} (standard {:.2}, swapped {:.2})",
+                    d.layout, d.standard_score, d.swapped_score
+                )
+            })
+            .unwrap_or_default();
+        println!(
+            "{}: {} frames, {} pixels ({} addresses, {} iToT codes, {} event codes), {} ambiguous packets redrawn{}, {} mismatches",
+            layout,
+            report.frames,
+            report.pixels,
+            report.addresses,
+            report.itot_codes,
+            report.event_codes,
+            report.ambiguous,
+            detection,
+            report.mismatches.len()
+        );
+        ok &= report.mismatches.is_empty();
+    }
+    ok
+}
+
+fn tui(args: TuiArgs) -> bool {
+    let start = match (&args.at, args.index) {
+        (Some(at), _) => match utils::parse_datetime_arg(at) {
+            Ok(timestamp) => index::FrameSelector::Timestamp(timestamp),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                return false;
+            }
+        },
+        (None, index) => index::FrameSelector::Index(index.unwrap_or(1)),
+    };
+    let config = processor::ProcessorConfig {
+        max_pix_count: args.max_pix_count as usize,
+        ..Default::default()
+    };
+    if let Err(e) = config.validate() {
+        eprintln!("Error: {:#}", e);
+        return false;
+    }
+    match tui::run(
+        &args.gps_file,
+        &args.meas_file,
+        &args.data_file,
+        start,
+        args.max_pix_count as usize,
+        args.firmware,
+    ) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            false
+        }
+    }
 }
 
 fn main() {
-    let args = Cli::parse();
+    let cli = Cli::parse();
+    let convert = match (&cli.command, &cli.convert) {
+        (Some(Command::Convert(args)), _) => Some(args.as_ref()),
+        (None, args) => args.as_ref(),
+        _ => None,
+    };
+    let (verbose, quiet, log_file) = convert.map_or((0, false, None), |a| {
+        (a.verbose, a.quiet, a.log_file.as_deref())
+    });
+    if let Err(e) = logging::init(verbose, quiet, log_file.map(Path::new)) {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+    let args = match (cli.command, cli.convert) {
+        (Some(Command::Extract(args)), _) => {
+            if !extract(args) {
+                std::process::exit(1);
+            }
+            println!("Done.");
+            return;
+        }
+        (Some(Command::Inspect(args)), _) => {
+            if !inspect(args) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        (Some(Command::Replay(args)), _) => {
+            if !replay(args) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        (Some(Command::Summarize(args)), _) => {
+            if !summarize(args) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        (Some(Command::Validate(args)), _) => {
+            if !validate(args) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        (Some(Command::Backfill(args)), _) => {
+            if !backfill(args) {
+                std::process::exit(1);
+            }
+            println!("Done.");
+            return;
+        }
+        (Some(Command::Clusterize(args)), _) => {
+            if !clusterize(args) {
+                std::process::exit(1);
+            }
+            println!("Done.");
+            return;
+        }
+        (Some(Command::Conformance(args)), _) => {
+            if !conformance(args) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        (Some(Command::Tui(args)), _) => {
+            if !tui(args) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        (Some(Command::Schema), _) => {
+            match serde_json::to_string_pretty(&schema::json_schema()) {
+                Ok(schema) => println!("{}", schema),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        (Some(Command::Completions(args)), _) => {
+            let mut command = Cli::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(args.shell, &mut command, name, &mut std::io::stdout());
+            return;
+        }
+        (Some(Command::Man(args)), _) => {
+            if !man(args) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        (Some(Command::VerifyArchive(args)), _) => {
+            if !verify_archive(args) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        (Some(Command::CompareSats(args)), _) => {
+            if !compare_sats(args) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        (Some(Command::Columns), _) => {
+            for column in columns::COLUMNS {
+                println!("{:<18}{}", column.name, column.description);
+            }
+            return;
+        }
+        (Some(Command::Convert(args)), _) => *args,
+        (None, Some(args)) => args,
+        (None, None) => unreachable!("clap requires the conversion arguments"),
+    };
+
+    let file_config = match &args.config {
+        Some(path) => config::FileConfig::load(Path::new(path)),
+        None => Ok(config::FileConfig::default()),
+    };
+    let resolved = file_config.and_then(|c| {
+        let mut columns = match c.columns {
+            Some(names) => columns::resolve(&names)?,
+            None => columns::default_columns(),
+        };
+        if args.position_frame != orbit::ReferenceFrame::J2000 {
+            let j2000 = orbit::ReferenceFrame::J2000.columns();
+            let tagged = columns::resolve(&args.position_frame.columns())?;
+            for column in columns.iter_mut() {
+                if let Some(i) = j2000.iter().position(|&name| name == column.name) {
+                    *column = tagged[i];
+                }
+            }
+        }
+        if args.gps_columns == columns::GpsColumns::Both {
+            for column in columns::resolve(&columns::INTERPOLATED_COLUMNS)? {
+                if !columns.iter().any(|c| c.name == column.name) {
+                    columns.push(column);
+                }
+            }
+        }
+        if args.pointing_frame.is_some() {
+            for column in columns::resolve(&columns::POINTING_COLUMNS)? {
+                if !columns.iter().any(|c| c.name == column.name) {
+                    columns.push(column);
+                }
+            }
+        }
+        if args.lut_sentinels == data_processor::SentinelPolicy::Invalid {
+            for column in columns::resolve(&columns::INVALID_PIXEL_COLUMNS)? {
+                if !columns.iter().any(|c| c.name == column.name) {
+                    columns.push(column);
+                }
+            }
+        }
+        let derived_columns = derived::resolve(&c.derived_columns.unwrap_or_default())?;
+        let mut rois = c
+            .rois
+            .unwrap_or_default()
+            .iter()
+            .map(|s| s.parse().with_context(|| format!("invalid ROI '{}'", s)))
+            .collect::<anyhow::Result<Vec<roi::Roi>>>()?;
+        rois.extend(args.roi.iter().cloned());
+        let toa_calibration = match (&c.toa_calibration, &args.config) {
+            (Some(file), Some(config)) => {
+                let dir = Path::new(config).parent().unwrap_or(Path::new(""));
+                Some(Arc::new(toa_calibration::ToaCalibration::load(
+                    &dir.join(file),
+                )?))
+            }
+            _ => None,
+        };
+        let mounting = c.mounting.unwrap_or(direction::IDENTITY);
+        let noise_model = match (&c.noise_threshold, &args.config) {
+            (Some(threshold), Some(config)) => {
+                let dir = Path::new(config).parent().unwrap_or(Path::new(""));
+                Some(Arc::new(noise::NoiseModel::load(threshold, dir)?))
+            }
+            _ => None,
+        };
+        let classification = c.classification.unwrap_or_default();
+        Ok((
+            columns,
+            derived_columns,
+            rois,
+            toa_calibration,
+            mounting,
+            noise_model,
+            classification,
+        ))
+    });
+    let (columns, derived_columns, rois, toa_calibration, mounting, noise_model, classification) =
+        match resolved {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                std::process::exit(1);
+            }
+        };
 
-    let mut processor = processor::Processor::new();
+    let window = match window::TimeWindow::parse(args.start.as_deref(), args.end.as_deref()) {
+        Ok(window) => window,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let tle = match args
+        .tle_file
+        .as_deref()
+        .map(|file| tle::TleSet::load(Path::new(file)))
+    {
+        Some(Ok(tle)) => Some(Arc::new(tle)),
+        Some(Err(e)) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    let energy_calibration = match args
+        .calib_dir
+        .as_deref()
+        .map(|dir| energy_calibration::EnergyCalibration::load_dir(Path::new(dir)))
+    {
+        Some(Ok(calibration)) => Some(Arc::new(calibration)),
+        Some(Err(e)) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    let pixel_mask = match args
+        .mask_file
+        .as_deref()
+        .map(|path| hot_pixels::HotPixelMask::load(Path::new(path)))
+    {
+        Some(Ok(mask)) => Some(Arc::new(mask)),
+        Some(Err(e)) => {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    let watch = if args.watch {
+        match watch::WatchPolicy::new(args.watch_interval, args.watch_idle) {
+            Ok(policy) => Some(policy),
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut config = processor::ProcessorConfig {
+        max_pix_count: args.max_pix_count as usize,
+        bbox: args.bbox,
+        max_gps_staleness: args.max_gps_staleness,
+        max_assembly_time: args.max_assembly_time,
+        no_gps: args.no_gps,
+        no_meas: args.no_meas,
+        jobs: args.jobs.max(1),
+        decode_threads: args.decode_threads.max(1),
+        read_ahead: Some(args.read_ahead).filter(|size| size.0 > 0),
+        retry: args.retry,
+        watch,
+        checkpoint: args.checkpoint,
+        checkpoint_every: args.checkpoint_every,
+        resume: args.resume,
+        min_free_space: args.min_free_space,
+        on_low_disk: args.on_low_disk,
+        dose_map: args.dose_map,
+        hot_pixel_stats: args.hot_pixel_stats,
+        hot_pixel_mask: args.hot_pixel_mask,
+        hot_pixel_fraction: args.hot_pixel_fraction,
+        kev_per_count: args.kev_per_count,
+        event_display: args.event_display,
+        event_display_top: args.event_display_top,
+        event_catalog: args.event_catalog,
+        error_policy: args.on_bad_line,
+        drift_samples: args.drift_samples,
+        sentinel_policy: args.lut_sentinels,
+        toa_calibration,
+        energy_calibration,
+        pixel_mask,
+        clog_energy: args.clog_energy,
+        orientation: args.orientation,
+        mounting,
+        noise_model,
+        merge_distance: args.merge_distance,
+        adaptive_threshold: args.adaptive_threshold,
+        reject_invalid_gps: args.reject_invalid_gps,
+        deglitch_gps: args.deglitch_gps,
+        max_attitude_jump: args.max_attitude_jump,
+        on_maneuver: args.on_maneuver,
+        tle,
+        tle_substitute: args.tle_substitute,
+        decimate: args.decimate.max(1),
+        seed: args.seed,
+        window,
+        day_split: args.day_split,
+        frame_time_source: args.frame_time_source,
+        frame_numbering: args.frame_numbering,
+        records: args.records,
+        format: args.format,
+        chunk_frames: args.chunk_frames,
+        cluster_features: args.cluster_features,
+        classification,
+        position_frame: args.position_frame,
+        gps_columns: args.gps_columns,
+        pointing_frame: args.pointing_frame.unwrap_or_default(),
+        position_binning: args.position_binning,
+        clock: if args.simulated_clock {
+            Arc::new(clock::SimulatedClock {
+                step: Duration::from_millis(1),
+            })
+        } else {
+            Arc::new(clock::SystemClock::default())
+        },
+        columns,
+        derived_columns,
+        firmware: args.firmware,
+        detected_layout: None,
+        on_unsupported_firmware: args.on_unsupported_firmware,
+        rle: args.rle,
+        backend: args.backend,
+        labeler: None,
+        rois,
+        roi_report: args.roi_report,
+        reprocess_list: args.reprocess_list,
+        see_report: args.see_report,
+        see_threshold: args.see_threshold,
+        see_window: args.see_window,
+        duty_cycle: args.duty_cycle,
+        duty_gap: args.duty_gap,
+        phase_profile: args.phase_profile,
+        phase_bins: args.phase_bins,
+        thermal_report: args.thermal_report,
+        mode_report: args.mode_report,
+        region_spectra: args.region_spectra,
+        subtract_quiet: args.subtract_quiet,
+        dose_summary: args.dose_summary,
+        dose_equivalent: args.dose_equivalent,
+        quality_factor: args.quality_factor,
+        preview_every: args.preview_every,
+        quiet: args.quiet,
+        frame_images: args.frame_images.map(|matrices| frame_image::FrameImages {
+            matrices,
+            format: args.image_format,
+            colormap: args.colormap,
+            scale: args.image_scale,
+        }),
+        pixet: args.pixet,
+        two_pass: args.two_pass,
+        day_stats: None,
+    };
+    if args.verify_repro {
+        // the cumulative dose map, the event displays, the frame images and the reports are not part of the
+        // reproduced outputs
+        config.dose_map = None;
+        config.hot_pixel_stats = None;
+        config.hot_pixel_mask = None;
+        config.event_display = None;
+        config.frame_images = None;
+        config.roi_report = None;
+        config.reprocess_list = None;
+        config.see_report = None;
+        config.duty_cycle = None;
+        config.phase_profile = None;
+        config.thermal_report = None;
+        config.mode_report = None;
+        config.region_spectra = None;
+        config.dose_summary = None;
+        config.dose_equivalent = None;
+    }
+    if let Err(e) = config.validate() {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+    let mut processor = Processor::new(config);
     let gps_file = args.gps_file;
     let meas_file = args.meas_file;
     let data_file = args.data_file;
     let out_dir = args.output_directory;
-    let max_pix_count = args.max_pix_count as usize;
 
     if fs::create_dir_all(&out_dir).is_err() {
         eprintln!("Error creating output directory: {}", out_dir);
         return;
     }
 
-    if let Err(e) =
-        processor.process_files(&gps_file, &meas_file, &data_file, &out_dir, max_pix_count)
+    if watch.is_some()
+        && let Err(e) = ctrlc::set_handler(|| {
+            if watch::stop() {
+                std::process::exit(130);
+            }
+            eprintln!("Stopping the watch, press Ctrl-C again to abort");
+        })
     {
-        let error_message = e.to_string();
-        if error_message.contains("No more data available") {
-            println!("Done.");
-            return;
+        eprintln!(
+            "Warning: cannot handle Ctrl-C ({}), the last day stays open",
+            e
+        );
+    }
+
+    let clock = processor.config().clock.clone();
+    let start = clock.now();
+    let ok = if args.verify_repro {
+        verify_repro(
+            &mut processor,
+            gps_file.as_deref(),
+            meas_file.as_deref(),
+            &data_file,
+            &out_dir,
+        )
+    } else {
+        run(
+            &mut processor,
+            gps_file.as_deref(),
+            meas_file.as_deref(),
+            &data_file,
+            &out_dir,
+        )
+    };
+    if args.timing {
+        println!("{}", processor.timing().report(clock.elapsed(start)));
+    }
+    if !ok {
+        std::process::exit(1);
+    }
+    if let Some(target) = args.catalog.as_ref().filter(|_| !args.verify_repro) {
+        let registered = catalog::RunEntry::new(
+            args.satellite.as_deref().unwrap_or_default(),
+            processor.repro_hash(),
+            processor.ledger(),
+            &data_file,
+            &out_dir,
+        )
+        .and_then(|entry| catalog::register(target, &entry));
+        match registered {
+            Ok(id) => println!("Registered run {} in the catalog {}.", id, target),
+            Err(e) => {
+                eprintln!("Error registering the run in the catalog: {:#}", e);
+                std::process::exit(1);
+            }
         }
-        eprintln!("Error processing files: {:?}", e);
     }
+    println!("Done.");
 }
@@ -0,0 +1,129 @@
+use crate::gps_processor::GpsData;
+use anyhow::{Result, bail};
+use std::fmt;
+use std::str::FromStr;
+
+/// Handling of the attitude dependent products of frames matched to a maneuver epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ManeuverPolicy {
+    /// Keep the products, the maneuver column marks the frames
+    #[default]
+    Flag,
+    /// Leave the quaternion columns and the J2000 cluster directions empty
+    Exclude,
+}
+
+impl FromStr for ManeuverPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "flag" => Ok(ManeuverPolicy::Flag),
+            "exclude" => Ok(ManeuverPolicy::Exclude),
+            _ => bail!("expected flag or exclude"),
+        }
+    }
+}
+
+impl fmt::Display for ManeuverPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ManeuverPolicy::Flag => write!(f, "flag"),
+            ManeuverPolicy::Exclude => write!(f, "exclude"),
+        }
+    }
+}
+
+/// Rotation angle in degrees between two unit attitude quaternions
+pub fn attitude_jump(a: [f64; 4], b: [f64; 4]) -> f64 {
+    let dot: f64 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+    // q and -q are the same attitude
+    2.0 * dot.abs().min(1.0).acos().to_degrees()
+}
+
+/// GPS epochs around attitude discontinuities, where the propagated attitude is unreliable
+#[derive(Debug, Clone, Default)]
+pub struct Maneuvers {
+    /// Times of the GPS samples before and after each jump, sorted
+    pub windows: Vec<(f64, f64)>,
+}
+
+impl Maneuvers {
+    /// Finds the consecutive samples of the track whose attitudes differ by more than
+    /// `max_jump` degrees, samples without a usable quaternion are skipped
+    pub fn detect(track: &[GpsData], max_jump: f64) -> Self {
+        let mut windows: Vec<(f64, f64)> = Vec::new();
+        let mut previous: Option<(f64, [f64; 4])> = None;
+        for gps in track {
+            let Some(q) = gps.quaternion_normalized() else {
+                continue;
+            };
+            if let Some((time, prev_q)) = previous
+                && attitude_jump(prev_q, q) > max_jump
+            {
+                match windows.last_mut() {
+                    // consecutive jumps form one maneuver
+                    Some(last) if last.1 == time => last.1 = gps.timestamp,
+                    _ => windows.push((time, gps.timestamp)),
+                }
+            }
+            previous = Some((gps.timestamp, q));
+        }
+        Maneuvers { windows }
+    }
+
+    /// True for a GPS sample at either end of or inside a maneuver
+    pub fn contains(&self, timestamp: f64) -> bool {
+        let i = self.windows.partition_point(|w| w.1 < timestamp);
+        self.windows.get(i).is_some_and(|w| w.0 <= timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(timestamp: f64, angle_deg: f64) -> GpsData {
+        // rotation about z
+        let half = angle_deg.to_radians() / 2.0;
+        GpsData {
+            timestamp,
+            q_est_prop_bj_scalar: half.cos(),
+            q_est_prop_bj_vector_3: half.sin(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_maneuvers() {
+        assert!((attitude_jump([1.0, 0.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0]) - 180.0).abs() < 1e-9);
+        assert_eq!(
+            attitude_jump([1.0, 0.0, 0.0, 0.0], [-1.0, 0.0, 0.0, 0.0]),
+            0.0
+        );
+
+        let track = vec![
+            sample(0.0, 0.0),
+            sample(10.0, 0.5),
+            sample(20.0, 30.0),
+            sample(30.0, 60.0),
+            GpsData {
+                timestamp: 35.0,
+                ..Default::default()
+            },
+            sample(40.0, 60.5),
+            sample(50.0, 61.0),
+            sample(60.0, 10.0),
+        ];
+        let maneuvers = Maneuvers::detect(&track, 5.0);
+        assert_eq!(maneuvers.windows, [(10.0, 30.0), (50.0, 60.0)]);
+        assert!(!maneuvers.contains(0.0));
+        assert!(maneuvers.contains(10.0) && maneuvers.contains(20.0) && maneuvers.contains(30.0));
+        assert!(!maneuvers.contains(40.0));
+        assert!(maneuvers.contains(60.0));
+        assert_eq!(
+            "exclude".parse::<ManeuverPolicy>().unwrap(),
+            ManeuverPolicy::Exclude
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use crate::provenance::BuildInfo;
+use crate::repro;
+use crate::utils::format_time;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_VERSION: u32 = 1;
+pub const MANIFEST_PREFIX: &str = "MANIFEST_";
+
+/// Output file listed in a manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Description of the finalized output files of one day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub manifest_version: u32,
+    pub date: String,
+    pub repro_hash: String,
+    /// Decoder build that wrote the files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<BuildInfo>,
+    /// Frames written to the daily files
+    pub frames: usize,
+    /// Timestamps of the first and last written frames
+    pub first_frame: String,
+    pub last_frame: String,
+    pub files: Vec<FileEntry>,
+    /// Problems found when the day was finalized, e.g. clog/info files that do not pair up
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl Manifest {
+    pub fn file_name(date: &str) -> String {
+        format!("{}{}.json", MANIFEST_PREFIX, date)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text =
+            fs::read_to_string(path).with_context(|| format!("cannot read {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("invalid manifest {}", path.display()))
+    }
+
+    /// Differences between the listed files and the files next to the manifest
+    pub fn verify(&self, dir: &Path) -> Result<Vec<String>> {
+        let mut problems = Vec::new();
+        for entry in &self.files {
+            let path = dir.join(&entry.name);
+            let Ok(metadata) = fs::metadata(&path) else {
+                problems.push(format!("{}: missing", path.display()));
+                continue;
+            };
+            if metadata.len() != entry.size {
+                problems.push(format!(
+                    "{}: size {} != {} in the manifest",
+                    path.display(),
+                    metadata.len(),
+                    entry.size
+                ));
+                continue;
+            }
+            let sha256 = repro::sha256_file(&path)?;
+            if sha256 != entry.sha256 {
+                problems.push(format!(
+                    "{}: sha256 {} != {} in the manifest",
+                    path.display(),
+                    sha256,
+                    entry.sha256
+                ));
+            }
+        }
+        Ok(problems)
+    }
+}
+
+/// Daily output files being written, finalized into a manifest when the day is complete
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DayFiles {
+    pub date: String,
+    pub names: Vec<String>,
+    pub frames: usize,
+    pub first_frame: f64,
+    pub last_frame: f64,
+    pub warnings: Vec<String>,
+}
+
+/// Lines of the file matching the filter, read line by line
+fn count_lines(path: &Path, filter: impl Fn(&str) -> bool) -> Result<usize> {
+    let file = fs::File::open(path).with_context(|| format!("cannot read {}", path.display()))?;
+    let mut count = 0;
+    for line in BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("cannot read {}", path.display()))?;
+        if filter(&line) {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+impl DayFiles {
+    pub fn new(date: &str, names: Vec<String>) -> Self {
+        DayFiles {
+            date: date.to_string(),
+            names,
+            frames: 0,
+            first_frame: 0.0,
+            last_frame: 0.0,
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn add_frame(&mut self, timestamp: f64) {
+        if self.frames == 0 {
+            self.first_frame = timestamp;
+        }
+        self.last_frame = timestamp;
+        self.frames += 1;
+    }
+
+    /// Compares the frames of the .clog file, the rows of the .info file and the frame index
+    /// of the writer, the files must be flushed; a mismatch is returned and kept as a
+    /// warning of the manifest
+    pub fn check_pairing(&mut self, dir: &Path, frame_index: usize) -> Result<Option<String>> {
+        let mut clog_frames = None;
+        let mut info_rows = None;
+        for name in &self.names {
+            let path = dir.join(name);
+            if name.ends_with(".clog") {
+                clog_frames = Some(count_lines(&path, |l| l.starts_with("Frame "))?);
+            } else if name.ends_with(".info") {
+                let lines = count_lines(&path, |l| !l.is_empty() && !l.starts_with('#'))?;
+                // without the header line
+                info_rows = Some(lines.saturating_sub(1));
+            }
+        }
+        let (Some(clog_frames), Some(info_rows)) = (clog_frames, info_rows) else {
+            return Ok(None);
+        };
+        if clog_frames == info_rows && info_rows == frame_index {
+            return Ok(None);
+        }
+        let warning = format!(
+            "{}: .clog has {} frames, .info has {} rows, {} frames were written",
+            self.date, clog_frames, info_rows, frame_index
+        );
+        self.warnings.push(warning.clone());
+        Ok(Some(warning))
+    }
+
+    /// Writes `MANIFEST_<date>.json` to dir, the files must be flushed
+    pub fn write_manifest(&self, dir: &Path, repro_hash: &str) -> Result<PathBuf> {
+        let files = self
+            .names
+            .iter()
+            .map(|name| {
+                let path = dir.join(name);
+                Ok(FileEntry {
+                    name: name.clone(),
+                    size: fs::metadata(&path)
+                        .with_context(|| format!("{}", path.display()))?
+                        .len(),
+                    sha256: repro::sha256_file(&path)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let manifest = Manifest {
+            manifest_version: MANIFEST_VERSION,
+            date: self.date.clone(),
+            repro_hash: repro_hash.to_string(),
+            build: Some(BuildInfo::current()),
+            frames: self.frames,
+            first_frame: format_time(self.first_frame),
+            last_frame: format_time(self.last_frame),
+            files,
+            warnings: self.warnings.clone(),
+        };
+        let path = dir.join(Manifest::file_name(&self.date));
+        fs::write(&path, serde_json::to_string_pretty(&manifest)? + "\n")
+            .with_context(|| format!("cannot write manifest {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// Result of checking the manifests of an archive tree
+#[derive(Debug, Default)]
+pub struct ArchiveReport {
+    pub manifests: usize,
+    pub files: usize,
+    pub problems: Vec<String>,
+}
+
+/// Checks every manifest below root and reports daily files (`data_<date>.<ext>`) not listed
+/// in a manifest.
+/// Hidden directories (e.g. the .repro-verify scratch directory) are skipped.
+pub fn verify_archive(root: &Path) -> Result<ArchiveReport> {
+    let mut report = ArchiveReport::default();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries: Vec<PathBuf> = fs::read_dir(&dir)
+            .with_context(|| format!("cannot read {}", dir.display()))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect();
+        entries.sort();
+        let name = |path: &PathBuf| {
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        };
+
+        let mut listed = Vec::new();
+        for path in entries.iter().filter(|p| p.is_file()) {
+            let file_name = name(path);
+            if !(file_name.starts_with(MANIFEST_PREFIX) && file_name.ends_with(".json")) {
+                continue;
+            }
+            match Manifest::load(path).and_then(|m| Ok((m.verify(&dir)?, m))) {
+                Ok((problems, manifest)) => {
+                    report.manifests += 1;
+                    report.files += manifest.files.len();
+                    report.problems.extend(problems);
+                    listed.extend(manifest.files.into_iter().map(|f| f.name));
+                }
+                Err(e) => report.problems.push(format!("{:#}", e)),
+            }
+        }
+        for path in &entries {
+            let file_name = name(path);
+            if path.is_dir() {
+                if !file_name.starts_with('.') {
+                    dirs.push(path.clone());
+                }
+            } else if file_name.starts_with("data_")
+                && file_name.matches('.').count() == 1
+                && !listed.contains(&file_name)
+            {
+                report
+                    .problems
+                    .push(format!("{}: not listed in a manifest", path.display()));
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let root = std::env::temp_dir().join(format!("oneweb-manifest-{}", std::process::id()));
+        let dir = root.join("2024").join("03");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("data_2024-03-01.clog"), "Frame 1\n").unwrap();
+        fs::write(dir.join("data_2024-03-01.info"), "frame\n1\n").unwrap();
+
+        let mut day = DayFiles::new(
+            "2024-03-01",
+            vec![
+                String::from("data_2024-03-01.clog"),
+                String::from("data_2024-03-01.info"),
+            ],
+        );
+        day.add_frame(1709251200.0);
+        day.add_frame(1709251230.5);
+        assert_eq!(day.check_pairing(&dir, 1).unwrap(), None);
+        let path = day.write_manifest(&dir, "abc").unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("warnings"));
+        assert_eq!(
+            day.check_pairing(&dir, 2).unwrap().unwrap(),
+            "2024-03-01: .clog has 1 frames, .info has 1 rows, 2 frames were written"
+        );
+        let path = day.write_manifest(&dir, "abc").unwrap();
+        let manifest = Manifest::load(&path).unwrap();
+        assert_eq!(manifest.frames, 2);
+        assert_eq!(manifest.warnings.len(), 1);
+        assert_eq!(manifest.last_frame, "2024-03-01 00:00:30.500");
+        assert_eq!(manifest.files[0].size, 8);
+
+        let report = verify_archive(&root).unwrap();
+        assert_eq!((report.manifests, report.files), (1, 2));
+        assert!(report.problems.is_empty());
+
+        // same size, different content
+        fs::write(dir.join("data_2024-03-01.clog"), "Frame 2\n").unwrap();
+        fs::write(dir.join("data_2024-03-02.info"), "").unwrap();
+        let report = verify_archive(&root).unwrap();
+        assert_eq!(report.problems.len(), 2);
+        assert!(report.problems[0].contains("sha256"));
+        assert!(report.problems[1].ends_with("data_2024-03-02.info: not listed in a manifest"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,298 @@
+use crate::data_processor::Frame;
+use crate::event_display;
+use crate::roi::{self, SPECTRUM_BINS};
+use crate::utils::format_time;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Relative cadence change taken as a mode switch
+const MODE_TOLERANCE: f64 = 0.25;
+/// Consecutive measurement intervals at the new cadence confirming a switch, shorter
+/// deviations (e.g. a data gap) keep the mode
+const MODE_CONFIRM: usize = 3;
+/// Recent intervals the current cadence is the median of
+const CADENCE_WINDOW: usize = 15;
+
+/// Payload acquisition mode inferred from the measurement cadence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PayloadMode {
+    /// Cadence of most of the run
+    Nominal,
+    /// Faster cadence, the high resolution mode the payload switches to at high event rates
+    HighRes,
+    /// Slower cadence
+    LowRate,
+}
+
+impl fmt::Display for PayloadMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PayloadMode::Nominal => write!(f, "nominal"),
+            PayloadMode::HighRes => write!(f, "high_res"),
+            PayloadMode::LowRate => write!(f, "low_rate"),
+        }
+    }
+}
+
+/// Period of the run with a stable measurement cadence
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModeSegment {
+    /// Times of the first and last measurement info records of the segment
+    pub start: f64,
+    pub end: f64,
+    /// Median interval of the measurement info records in s
+    pub cadence: f64,
+    pub records: usize,
+    pub mode: PayloadMode,
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    values[values.len() / 2]
+}
+
+fn deviates(interval: f64, cadence: f64) -> bool {
+    (interval - cadence).abs() > MODE_TOLERANCE * cadence
+}
+
+/// Splits the sorted measurement info times into segments of stable cadence, the mode of a
+/// segment compares its cadence to the cadence covering most of the run
+pub fn detect_segments(times: &[f64]) -> Vec<ModeSegment> {
+    if times.is_empty() {
+        return Vec::new();
+    }
+    // record index where each segment starts
+    let mut starts = vec![0];
+    let mut intervals: Vec<f64> = Vec::new();
+    let mut pending: Vec<f64> = Vec::new();
+    for (i, pair) in times.windows(2).enumerate() {
+        let interval = pair[1] - pair[0];
+        let recent = intervals.len().saturating_sub(CADENCE_WINDOW);
+        let cadence = median(&mut intervals[recent..].to_vec());
+        if intervals.is_empty() || !deviates(interval, cadence) {
+            pending.clear();
+            intervals.push(interval);
+            continue;
+        }
+        if pending.first().is_some_and(|&p| deviates(interval, p)) {
+            pending.clear();
+        }
+        pending.push(interval);
+        if pending.len() == MODE_CONFIRM {
+            // the first record after a deviating interval is acquired in the new mode
+            starts.push(i + 2 - MODE_CONFIRM);
+            intervals = std::mem::take(&mut pending);
+        }
+    }
+
+    let mut segments: Vec<ModeSegment> = starts
+        .iter()
+        .enumerate()
+        .map(|(s, &start)| {
+            let end = starts.get(s + 1).map_or(times.len() - 1, |next| next - 1);
+            let mut intervals: Vec<f64> =
+                times[start..=end].windows(2).map(|p| p[1] - p[0]).collect();
+            ModeSegment {
+                start: times[start],
+                end: times[end],
+                cadence: median(&mut intervals),
+                records: end - start + 1,
+                mode: PayloadMode::Nominal,
+            }
+        })
+        .collect();
+    let nominal = segments
+        .iter()
+        .max_by(|a, b| (a.end - a.start).total_cmp(&(b.end - b.start)))
+        .map(|s| s.cadence)
+        .unwrap_or(0.0);
+    for segment in &mut segments {
+        segment.mode = if !deviates(segment.cadence, nominal) {
+            PayloadMode::Nominal
+        } else if segment.cadence < nominal {
+            PayloadMode::HighRes
+        } else {
+            PayloadMode::LowRate
+        };
+    }
+    segments
+}
+
+/// Sampling weighted frames, exposure and cluster spectrum of one mode
+#[derive(Debug, Clone)]
+pub struct ModeStats {
+    pub frames: f64,
+    /// Exposure time in s
+    pub exposure: f64,
+    pub clusters: f64,
+    /// Deposited energy of the clusters in keV
+    pub energy: f64,
+    /// Energy spectrum of the clusters without saturated pixels
+    pub spectrum: Vec<f64>,
+}
+
+impl Default for ModeStats {
+    fn default() -> Self {
+        ModeStats {
+            frames: 0.0,
+            exposure: 0.0,
+            clusters: 0.0,
+            energy: 0.0,
+            spectrum: vec![0.0; SPECTRUM_BINS],
+        }
+    }
+}
+
+/// Payload modes of the run and the products accumulated separately per mode
+#[derive(Debug, Clone)]
+pub struct ModeReport {
+    pub segments: Vec<ModeSegment>,
+    pub stats: BTreeMap<PayloadMode, ModeStats>,
+}
+
+impl ModeReport {
+    pub fn new(segments: Vec<ModeSegment>) -> Self {
+        ModeReport {
+            segments,
+            stats: BTreeMap::new(),
+        }
+    }
+
+    /// Mode of the segment containing the measurement info time
+    pub fn mode_at(&self, timestamp: f64) -> PayloadMode {
+        let i = self.segments.partition_point(|s| s.start <= timestamp);
+        self.segments
+            .get(i.saturating_sub(1))
+            .map(|s| s.mode)
+            .unwrap_or(PayloadMode::Nominal)
+    }
+
+    pub fn add_frame(
+        &mut self,
+        mode: PayloadMode,
+        frame: &Frame,
+        kev_per_count: f64,
+        acq_time: f64,
+        weight: f64,
+    ) {
+        let stats = self.stats.entry(mode).or_default();
+        stats.frames += weight;
+        stats.exposure += weight * acq_time;
+        for cluster in &frame.clusters {
+            let energy = event_display::cluster_energy(cluster, kev_per_count);
+            stats.clusters += weight;
+            stats.energy += weight * energy;
+            if cluster.saturation() == 0.0 {
+                stats.spectrum[roi::spectrum_bin(energy)] += weight;
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &ModeReport) {
+        for (mode, other) in &other.stats {
+            let stats = self.stats.entry(*mode).or_default();
+            stats.frames += other.frames;
+            stats.exposure += other.exposure;
+            stats.clusters += other.clusters;
+            stats.energy += other.energy;
+            for (count, other) in stats.spectrum.iter_mut().zip(&other.spectrum) {
+                *count += other;
+            }
+        }
+    }
+
+    /// Writes the mode segments, the per-mode totals and the per-mode cluster spectra
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(
+            fs::File::create(path)
+                .with_context(|| format!("cannot write mode report {}", path.display()))?,
+        );
+        writeln!(
+            writer,
+            "# Payload modes from the measurement cadence, segments: {}, switches at {:.0}% cadence change lasting {} records",
+            self.segments.len(),
+            MODE_TOLERANCE * 100.0,
+            MODE_CONFIRM
+        )?;
+        writeln!(writer, "start\tend\tmode\tcadence[s]\trecords")?;
+        for segment in &self.segments {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{:.3}\t{}",
+                format_time(segment.start),
+                format_time(segment.end),
+                segment.mode,
+                segment.cadence,
+                segment.records
+            )?;
+        }
+        writeln!(writer, "# Frames per mode")?;
+        writeln!(writer, "mode\tframes\texposure[s]\tclusters\tenergy[keV]")?;
+        for (mode, stats) in &self.stats {
+            writeln!(
+                writer,
+                "{}\t{}\t{:.3}\t{}\t{}",
+                mode, stats.frames, stats.exposure, stats.clusters, stats.energy
+            )?;
+        }
+        let modes: Vec<String> = self.stats.keys().map(|m| m.to_string()).collect();
+        writeln!(
+            writer,
+            "# Cluster energy spectrum per mode (without saturated clusters)"
+        )?;
+        writeln!(writer, "energy_min[keV]\t{}", modes.join("\t"))?;
+        for bin in 0..SPECTRUM_BINS {
+            let counts: Vec<String> = self
+                .stats
+                .values()
+                .map(|s| s.spectrum[bin].to_string())
+                .collect();
+            writeln!(writer, "{:.4}\t{}", roi::bin_edge(bin), counts.join("\t"))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_segments() {
+        // 30 s cadence with a data gap, 10 s from 580 s, back to 30 s from 710 s
+        let mut times: Vec<f64> = (0..10).map(|i| i as f64 * 30.0).collect();
+        times.extend((0..7).map(|i| 390.0 + i as f64 * 30.0));
+        times.extend((1..12).map(|i| 570.0 + i as f64 * 10.0));
+        times.extend((1..20).map(|i| 680.0 + i as f64 * 30.0));
+
+        let segments = detect_segments(&times);
+        assert_eq!(segments.len(), 3);
+        assert_eq!((segments[0].start, segments[0].end), (0.0, 570.0));
+        assert_eq!(segments[0].cadence, 30.0);
+        assert_eq!(segments[1].mode, PayloadMode::HighRes);
+        assert_eq!((segments[1].start, segments[1].cadence), (580.0, 10.0));
+        assert_eq!((segments[1].end, segments[2].start), (680.0, 710.0));
+        assert_eq!(segments[2].mode, PayloadMode::Nominal);
+
+        let mut report = ModeReport::new(segments);
+        assert_eq!(report.mode_at(-5.0), PayloadMode::Nominal);
+        assert_eq!(report.mode_at(650.0), PayloadMode::HighRes);
+        assert_eq!(report.mode_at(1000.0), PayloadMode::Nominal);
+
+        let frame = Frame::from_planes(Vec::new(), Vec::new(), 650.0);
+        report.add_frame(PayloadMode::HighRes, &frame, 1.0, 2.0, 1.0);
+        let mut other = ModeReport::new(report.segments.clone());
+        other.add_frame(PayloadMode::HighRes, &frame, 1.0, 2.0, 3.0);
+        report.merge(&other);
+        assert_eq!(report.stats[&PayloadMode::HighRes].exposure, 8.0);
+        assert!(!report.stats.contains_key(&PayloadMode::Nominal));
+    }
+}
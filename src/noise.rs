@@ -0,0 +1,286 @@
+use crate::config::NoiseThreshold;
+use crate::data_processor::Frame;
+use crate::tpx3lut::MATRIX_SIZE;
+use anyhow::{Context, Result, bail};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Fewest hit pixels a frame needs for the adaptive threshold, sparser frames are kept whole
+const ADAPTIVE_MIN_PIXELS: usize = 20;
+
+/// Per-pixel iToT threshold rising linearly as the sensor cools, a pixel is a hit when its
+/// iToT exceeds `base + slope * (reference_temp - temp)`; a zero threshold keeps every
+/// non-zero pixel as before
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoiseModel {
+    /// Threshold of the pixel in iToT counts at the reference temperature
+    pub base: Vec<f64>,
+    /// Threshold increase of the pixel in iToT counts per degree below the reference
+    pub slope: Vec<f64>,
+    /// Temperature in the units of the measurement info temp column
+    pub reference_temp: f64,
+    /// SHA-256 of the parameters and the per-pixel file, part of the repro hash
+    pub digest: String,
+}
+
+impl NoiseModel {
+    /// Model of the payload profile table, the per-pixel file is relative to `dir`
+    pub fn load(config: &NoiseThreshold, dir: &Path) -> Result<NoiseModel> {
+        let pixels = match &config.pixels {
+            Some(file) => {
+                let path = dir.join(file);
+                let content = fs::read_to_string(&path)
+                    .with_context(|| format!("cannot read noise thresholds {}", path.display()))?;
+                Some((path, content))
+            }
+            None => None,
+        };
+        NoiseModel::new(config, pixels.as_ref().map(|(_, content)| content.as_str())).with_context(
+            || match &pixels {
+                Some((path, _)) => format!("invalid noise thresholds {}", path.display()),
+                None => String::from("invalid noise threshold"),
+            },
+        )
+    }
+
+    /// Uniform base and slope, overridden by the `x y base [slope]` lines of the per-pixel
+    /// content (whitespace or comma separated, `#` starts a comment)
+    pub fn new(config: &NoiseThreshold, pixels: Option<&str>) -> Result<NoiseModel> {
+        if !(config.base >= 0.0 && config.base.is_finite() && config.slope.is_finite()) {
+            bail!("base must be a non-negative and slope a finite number of iToT counts");
+        }
+        if !config.reference_temp.is_finite() {
+            bail!("reference temperature must be finite");
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(format!(
+            "base={}\nslope={}\nreference_temp={}\n",
+            config.base, config.slope, config.reference_temp
+        ));
+        let mut model = NoiseModel {
+            base: vec![config.base; MATRIX_SIZE],
+            slope: vec![config.slope; MATRIX_SIZE],
+            reference_temp: config.reference_temp,
+            digest: String::new(),
+        };
+        for (i, line) in pixels.unwrap_or_default().lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let fields: Vec<&str> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|f| !f.is_empty())
+                .collect();
+            if fields.is_empty() {
+                continue;
+            }
+            if !(3..=4).contains(&fields.len()) {
+                bail!(
+                    "line {}: expected x y base [slope], got {} fields",
+                    i + 1,
+                    fields.len()
+                );
+            }
+            let x: u8 = fields[0]
+                .parse()
+                .with_context(|| format!("line {}: invalid column '{}'", i + 1, fields[0]))?;
+            let y: u8 = fields[1]
+                .parse()
+                .with_context(|| format!("line {}: invalid row '{}'", i + 1, fields[1]))?;
+            let base: f64 = fields[2]
+                .parse()
+                .with_context(|| format!("line {}: invalid base '{}'", i + 1, fields[2]))?;
+            let slope: f64 = match fields.get(3) {
+                Some(field) => field
+                    .parse()
+                    .with_context(|| format!("line {}: invalid slope '{}'", i + 1, field))?,
+                None => config.slope,
+            };
+            if !(base >= 0.0 && base.is_finite() && slope.is_finite()) {
+                bail!("line {}: base must be non-negative, slope finite", i + 1);
+            }
+            let idx = y as usize * 256 + x as usize;
+            model.base[idx] = base;
+            model.slope[idx] = slope;
+        }
+        if let Some(pixels) = pixels {
+            hasher.update(pixels.as_bytes());
+        }
+        model.digest = hex::encode(hasher.finalize());
+        Ok(model)
+    }
+
+    /// Threshold of the pixel in iToT counts at the temperature
+    pub fn threshold(&self, idx: usize, temp: f64) -> f64 {
+        (self.base[idx] + self.slope[idx] * (self.reference_temp - temp)).max(0.0)
+    }
+
+    /// Removes the pixels at or below their threshold from the frame and records the mean
+    /// applied threshold and the removed pixel count on it, returns the count; the caller
+    /// re-clusters the frame when pixels were removed
+    pub fn apply(&self, frame: &mut Frame, temp: f64) -> usize {
+        let threshold = |idx| self.threshold(idx, temp);
+        let mean = (0..MATRIX_SIZE).map(threshold).sum::<f64>() / MATRIX_SIZE as f64;
+        let removed = frame.suppress_pixels(|idx, value| value as f64 <= threshold(idx));
+        frame.noise_threshold = Some(mean);
+        frame.noise_pixels = removed;
+        removed
+    }
+}
+
+/// Threshold of the hit pixels derived from the iToT distribution of each frame, so the cut
+/// follows the temperature and flux changes over the orbit; pixels below it are removed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdaptiveThreshold {
+    /// The given percentile (0-100) of the hit pixel iToT
+    Percentile(f64),
+    /// Median minus the given number of median absolute deviations of the hit pixel iToT
+    Fence(f64),
+}
+
+impl AdaptiveThreshold {
+    /// Threshold of the sorted hit pixel values, None for too few pixels
+    pub fn threshold(&self, sorted: &[u16]) -> Option<f64> {
+        if sorted.len() < ADAPTIVE_MIN_PIXELS {
+            return None;
+        }
+        let median = sorted[sorted.len() / 2] as f64;
+        Some(match *self {
+            AdaptiveThreshold::Percentile(p) => {
+                let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+                sorted[rank.saturating_sub(1).min(sorted.len() - 1)] as f64
+            }
+            AdaptiveThreshold::Fence(k) => {
+                let mut deviations: Vec<f64> =
+                    sorted.iter().map(|&v| (v as f64 - median).abs()).collect();
+                deviations.sort_unstable_by(f64::total_cmp);
+                (median - k * deviations[deviations.len() / 2]).max(0.0)
+            }
+        })
+    }
+
+    /// Removes the pixels below the threshold of the frame and records the threshold and
+    /// the removed pixel count on it, returns the count; the caller re-clusters the frame
+    /// when pixels were removed
+    pub fn apply(&self, frame: &mut Frame) -> usize {
+        let mut values: Vec<u16> = frame.itot().iter().copied().filter(|&v| v != 0).collect();
+        values.sort_unstable();
+        let Some(threshold) = self.threshold(&values) else {
+            return 0;
+        };
+        let removed = frame.suppress_pixels(|_, value| (value as f64) < threshold);
+        frame.adaptive_threshold = Some(threshold);
+        frame.adaptive_pixels = removed;
+        removed
+    }
+}
+
+impl FromStr for AdaptiveThreshold {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some((kind, value)) = s.split_once(':') else {
+            bail!(
+                "invalid adaptive threshold '{}', expected percentile:P or fence:K",
+                s
+            );
+        };
+        let value: f64 = value
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid adaptive threshold value '{}'", value))?;
+        match kind.trim() {
+            "percentile" if (0.0..=100.0).contains(&value) => {
+                Ok(AdaptiveThreshold::Percentile(value))
+            }
+            "percentile" => bail!("percentile {} outside 0-100", value),
+            "fence" if value >= 0.0 && value.is_finite() => Ok(AdaptiveThreshold::Fence(value)),
+            "fence" => bail!(
+                "fence {} must be a non-negative number of deviations",
+                value
+            ),
+            other => bail!(
+                "unknown adaptive threshold '{}', expected percentile or fence",
+                other
+            ),
+        }
+    }
+}
+
+impl fmt::Display for AdaptiveThreshold {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AdaptiveThreshold::Percentile(p) => write!(f, "percentile:{}", p),
+            AdaptiveThreshold::Fence(k) => write!(f, "fence:{}", k),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_model() {
+        let config = NoiseThreshold {
+            base: 2.0,
+            slope: 0.5,
+            reference_temp: 20.0,
+            pixels: None,
+        };
+        let model = NoiseModel::new(&config, Some("# x y base slope\n1 0 0\n2,0,10,0\n")).unwrap();
+        assert_eq!(model.threshold(0, 20.0), 2.0);
+        assert_eq!(model.threshold(0, 0.0), 12.0);
+        assert_eq!(model.threshold(0, 40.0), 0.0);
+        assert_eq!(model.threshold(1, 10.0), 5.0);
+        assert_eq!(model.threshold(2, -30.0), 10.0);
+        assert_ne!(model.digest, NoiseModel::new(&config, None).unwrap().digest);
+        assert!(NoiseModel::new(&config, Some("1 0")).is_err());
+        assert!(
+            NoiseModel::new(
+                &NoiseThreshold {
+                    base: -1.0,
+                    ..config.clone()
+                },
+                None
+            )
+            .is_err()
+        );
+
+        let mut itot = vec![0; MATRIX_SIZE];
+        itot[0] = 12;
+        itot[3] = 13;
+        itot[256] = 3;
+        let mut frame = Frame::from_planes(itot.clone(), itot, 0.0);
+        assert_eq!(model.apply(&mut frame, 0.0), 2);
+        assert_eq!(frame.noise_pixels, 2);
+        assert_eq!(frame.itot().iter().filter(|&&v| v != 0).count(), 1);
+        assert_eq!(frame.itot()[3], 13);
+        assert_eq!(frame.event()[0], 0);
+        assert!(frame.noise_threshold.unwrap() > 11.0);
+
+        let sorted: Vec<u16> = (1..=40).collect();
+        let percentile: AdaptiveThreshold = "percentile:10".parse().unwrap();
+        assert_eq!(percentile.threshold(&sorted), Some(4.0));
+        assert_eq!(percentile.threshold(&sorted[..10]), None);
+        assert_eq!(
+            "fence:1"
+                .parse::<AdaptiveThreshold>()
+                .unwrap()
+                .threshold(&sorted),
+            Some(11.0)
+        );
+        assert!("percentile:120".parse::<AdaptiveThreshold>().is_err());
+        assert_eq!(percentile.to_string(), "percentile:10");
+
+        let mut itot = vec![0; MATRIX_SIZE];
+        for (i, value) in sorted.iter().enumerate() {
+            itot[i * 3] = *value;
+        }
+        let mut frame = Frame::from_planes(itot.clone(), itot, 0.0);
+        assert_eq!(percentile.apply(&mut frame), 3);
+        assert_eq!(frame.adaptive_threshold, Some(4.0));
+        assert_eq!(frame.itot()[9], 4);
+    }
+}
@@ -0,0 +1,615 @@
+use anyhow::{Result, bail};
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+// WGS84 ellipsoid
+const WGS84_A: f64 = 6378137.0;
+const WGS84_F: f64 = 1.0 / 298.257223563;
+const WGS84_E2: f64 = WGS84_F * (2.0 - WGS84_F);
+
+/// Reference radius of the geomagnetic field model
+const EARTH_RADIUS: f64 = 6371.2e3;
+/// North geomagnetic pole of the centered dipole (IGRF-13, epoch 2020) in degrees
+const DIPOLE_POLE_LAT: f64 = 80.65;
+const DIPOLE_POLE_LON: f64 = -72.68;
+/// L-shell above which the orbit crosses the outer belt horns
+const POLAR_L_SHELL: f64 = 3.0;
+
+/// GPS samples further apart are not interpolated between
+pub const MAX_INTERPOLATION_GAP: f64 = 600.0;
+
+const ARCSEC_TO_RAD: f64 = PI / (180.0 * 3600.0);
+const UNIX_EPOCH_JD: f64 = 2440587.5;
+const J2000_JD: f64 = 2451545.0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Geodetic {
+    /// Geodetic latitude in degrees
+    pub latitude: f64,
+    /// Longitude in degrees (-180, 180]
+    pub longitude: f64,
+    /// Height above the WGS84 ellipsoid in meters
+    pub altitude: f64,
+}
+
+/// Julian centuries since J2000 for a unix timestamp
+fn julian_centuries(timestamp: f64) -> f64 {
+    (timestamp / 86400.0 + UNIX_EPOCH_JD - J2000_JD) / 36525.0
+}
+
+/// Rotation of the coordinate frame about the z axis
+fn rot_z(v: [f64; 3], angle: f64) -> [f64; 3] {
+    let (s, c) = angle.sin_cos();
+    [c * v[0] + s * v[1], -s * v[0] + c * v[1], v[2]]
+}
+
+/// Rotation of the coordinate frame about the x axis
+fn rot_x(v: [f64; 3], angle: f64) -> [f64; 3] {
+    let (s, c) = angle.sin_cos();
+    [v[0], c * v[1] + s * v[2], -s * v[1] + c * v[2]]
+}
+
+/// Rotation of the coordinate frame about the y axis
+fn rot_y(v: [f64; 3], angle: f64) -> [f64; 3] {
+    let (s, c) = angle.sin_cos();
+    [c * v[0] - s * v[2], v[1], s * v[0] + c * v[2]]
+}
+
+/// Greenwich mean sidereal time (IAU 1982) in radians
+pub fn gmst(timestamp: f64) -> f64 {
+    let t = julian_centuries(timestamp);
+    // the 876600 h * T term is added in degrees to keep precision
+    let secs = 67310.54841 + 8640184.812866 * t + 0.093104 * t * t - 6.2e-6 * t * t * t;
+    let gmst = (secs / 240.0 + 360.0 * 36525.0 * t).to_radians();
+    gmst.rem_euclid(2.0 * PI)
+}
+
+/// Precession angles zeta, z and theta (IAU 1976) in radians
+fn precession_angles(timestamp: f64) -> (f64, f64, f64) {
+    let t = julian_centuries(timestamp);
+    let zeta = (2306.2181 * t + 0.30188 * t * t + 0.017998 * t * t * t) * ARCSEC_TO_RAD;
+    let z = (2306.2181 * t + 1.09468 * t * t + 0.018203 * t * t * t) * ARCSEC_TO_RAD;
+    let theta = (2004.3109 * t - 0.42665 * t * t - 0.041833 * t * t * t) * ARCSEC_TO_RAD;
+    (zeta, z, theta)
+}
+
+/// Precession from J2000 to the mean equator of date
+fn precess_j2000_to_mod(v: [f64; 3], timestamp: f64) -> [f64; 3] {
+    let (zeta, z, theta) = precession_angles(timestamp);
+    rot_z(rot_y(rot_z(v, -zeta), theta), -z)
+}
+
+/// Nutation in longitude and obliquity and the mean obliquity of date in radians, from the
+/// four largest terms of the IAU 1980 series (accurate to about 0.5 arcsec)
+fn nutation(timestamp: f64) -> (f64, f64, f64) {
+    let t = julian_centuries(timestamp);
+    let node = (125.04452 - 1934.136261 * t).to_radians();
+    let sun = (280.4665 + 36000.7698 * t).to_radians();
+    let moon = (218.3165 + 481267.8813 * t).to_radians();
+    let dpsi = -17.20 * node.sin() - 1.32 * (2.0 * sun).sin() - 0.23 * (2.0 * moon).sin()
+        + 0.21 * (2.0 * node).sin();
+    let deps = 9.20 * node.cos() + 0.57 * (2.0 * sun).cos() + 0.10 * (2.0 * moon).cos()
+        - 0.09 * (2.0 * node).cos();
+    let eps = 84381.448 - 46.8150 * t - 0.00059 * t * t + 0.001813 * t * t * t;
+    (
+        dpsi * ARCSEC_TO_RAD,
+        deps * ARCSEC_TO_RAD,
+        eps * ARCSEC_TO_RAD,
+    )
+}
+
+/// Converts a J2000 position to TEME, the true equator and mean equinox of date frame of
+/// the SGP4 orbit propagation (precession + nutation, then back by the equation of the equinoxes)
+pub fn j2000_to_teme(pos: [f64; 3], timestamp: f64) -> [f64; 3] {
+    let (dpsi, deps, eps) = nutation(timestamp);
+    let mean_of_date = precess_j2000_to_mod(pos, timestamp);
+    let true_of_date = rot_x(rot_z(rot_x(mean_of_date, eps), -dpsi), -(eps + deps));
+    rot_z(true_of_date, dpsi * eps.cos())
+}
+
+/// Converts a TEME position (SGP4 output) to J2000, the inverse of [`j2000_to_teme`]
+pub fn teme_to_j2000(pos: [f64; 3], timestamp: f64) -> [f64; 3] {
+    let (dpsi, deps, eps) = nutation(timestamp);
+    let true_of_date = rot_z(pos, -dpsi * eps.cos());
+    let mean_of_date = rot_x(rot_z(rot_x(true_of_date, eps + deps), dpsi), -eps);
+    let (zeta, z, theta) = precession_angles(timestamp);
+    rot_z(rot_y(rot_z(mean_of_date, z), -theta), zeta)
+}
+
+/// Converts a J2000 position to Earth fixed coordinates: TEME rotated by the Greenwich mean
+/// sidereal time (UTC is used as UT1 and polar motion is neglected)
+pub fn j2000_to_ecef(pos: [f64; 3], timestamp: f64) -> [f64; 3] {
+    rot_z(j2000_to_teme(pos, timestamp), gmst(timestamp))
+}
+
+/// Reference frame of exported positions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReferenceFrame {
+    /// Inertial J2000 (EME2000) of the GPS records
+    #[default]
+    J2000,
+    /// True equator mean equinox of date, used by TLEs and SGP4
+    Teme,
+    /// Earth fixed ITRF/ECEF
+    Itrf,
+}
+
+impl ReferenceFrame {
+    pub fn transform(&self, pos: [f64; 3], timestamp: f64) -> [f64; 3] {
+        match self {
+            ReferenceFrame::J2000 => pos,
+            ReferenceFrame::Teme => j2000_to_teme(pos, timestamp),
+            ReferenceFrame::Itrf => j2000_to_ecef(pos, timestamp),
+        }
+    }
+
+    /// Metadata columns of the x, y, z position in the frame
+    pub fn columns(&self) -> [&'static str; 3] {
+        match self {
+            ReferenceFrame::J2000 => ["gps_x", "gps_y", "gps_z"],
+            ReferenceFrame::Teme => ["teme_x", "teme_y", "teme_z"],
+            ReferenceFrame::Itrf => ["itrf_x", "itrf_y", "itrf_z"],
+        }
+    }
+}
+
+impl FromStr for ReferenceFrame {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "j2000" => Ok(ReferenceFrame::J2000),
+            "teme" => Ok(ReferenceFrame::Teme),
+            "itrf" | "ecef" => Ok(ReferenceFrame::Itrf),
+            _ => bail!("expected j2000, teme or itrf"),
+        }
+    }
+}
+
+impl fmt::Display for ReferenceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReferenceFrame::J2000 => write!(f, "j2000"),
+            ReferenceFrame::Teme => write!(f, "teme"),
+            ReferenceFrame::Itrf => write!(f, "itrf"),
+        }
+    }
+}
+
+/// Converts Earth fixed coordinates to WGS84 geodetic coordinates (Bowring)
+pub fn ecef_to_geodetic(pos: [f64; 3]) -> Geodetic {
+    let [x, y, z] = pos;
+    let b = WGS84_A * (1.0 - WGS84_F);
+    let ep2 = (WGS84_A * WGS84_A - b * b) / (b * b);
+    let p = x.hypot(y);
+    let theta = (z * WGS84_A).atan2(p * b);
+    let (st, ct) = theta.sin_cos();
+    let lat = (z + ep2 * b * st * st * st).atan2(p - WGS84_E2 * WGS84_A * ct * ct * ct);
+    let lon = y.atan2(x);
+    let (sl, cl) = lat.sin_cos();
+    let alt = p * cl + z * sl - WGS84_A * (1.0 - WGS84_E2 * sl * sl).sqrt();
+    Geodetic {
+        latitude: lat.to_degrees(),
+        longitude: lon.to_degrees(),
+        altitude: alt,
+    }
+}
+
+/// Subsatellite point of a J2000 position at the given time
+pub fn geodetic_from_j2000(pos: [f64; 3], timestamp: f64) -> Geodetic {
+    ecef_to_geodetic(j2000_to_ecef(pos, timestamp))
+}
+
+/// Times of the ascending node crossings (J2000 z changing from negative to non-negative)
+/// of the time ordered (timestamp, J2000 position) samples, interpolated linearly
+pub fn ascending_nodes(samples: impl IntoIterator<Item = (f64, [f64; 3])>) -> Vec<f64> {
+    let mut nodes = Vec::new();
+    let mut prev: Option<(f64, f64)> = None;
+    for (timestamp, pos) in samples {
+        let z = pos[2];
+        if let Some((t0, z0)) = prev
+            && z0 < 0.0
+            && z >= 0.0
+        {
+            nodes.push(t0 + (timestamp - t0) * -z0 / (z - z0));
+        }
+        prev = Some((timestamp, z));
+    }
+    nodes
+}
+
+pub fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Argument of latitude in degrees (0-360, angle from the ascending node in the direction of
+/// motion) of the J2000 position, the orbit plane is spanned by the position and a later one;
+/// None when the two positions do not define a plane
+pub fn argument_of_latitude(pos: [f64; 3], later: [f64; 3]) -> Option<f64> {
+    let normal = cross(pos, later);
+    let norm = dot(normal, normal).sqrt();
+    // the node line is the intersection of the orbit plane and the equator
+    let node = cross([0.0, 0.0, 1.0], normal);
+    let node_norm = dot(node, node).sqrt();
+    if norm == 0.0 || node_norm == 0.0 {
+        return None;
+    }
+    let node = node.map(|c| c / node_norm);
+    let sin_u = dot(cross(node, pos), normal) / norm;
+    let cos_u = dot(node, pos);
+    Some(sin_u.atan2(cos_u).to_degrees().rem_euclid(360.0))
+}
+
+/// Position at the time between two (timestamp, position) samples of the orbit: the point of
+/// the chord lifted to the interpolated orbit radius, close to the arc for samples a small
+/// part of an orbit apart. Times outside the samples are clamped to them.
+pub fn interpolate_position(a: (f64, [f64; 3]), b: (f64, [f64; 3]), timestamp: f64) -> [f64; 3] {
+    let span = b.0 - a.0;
+    let f = if span > 0.0 {
+        ((timestamp - a.0) / span).clamp(0.0, 1.0)
+    } else {
+        0.5
+    };
+    let (a, b) = (a.1, b.1);
+    let p: [f64; 3] = std::array::from_fn(|i| a[i] + f * (b[i] - a[i]));
+    let norm = |v: [f64; 3]| dot(v, v).sqrt();
+    let radius = norm(a) + f * (norm(b) - norm(a));
+    let chord = norm(p);
+    if chord > 0.0 {
+        p.map(|c| c * radius / chord)
+    } else {
+        p
+    }
+}
+
+/// Time ordered (timestamp, J2000 position) samples of the orbit
+pub type Track = Vec<(f64, [f64; 3])>;
+
+/// Position at the time interpolated between the samples of the time ordered track around
+/// it, None outside the track and between samples more than `MAX_INTERPOLATION_GAP` apart
+pub fn interpolate_track(track: &[(f64, [f64; 3])], timestamp: f64) -> Option<[f64; 3]> {
+    let i = track.partition_point(|s| s.0 <= timestamp);
+    let before = *track.get(i.checked_sub(1)?)?;
+    if before.0 == timestamp {
+        return Some(before.1);
+    }
+    let after = *track.get(i)?;
+    (after.0 - before.0 <= MAX_INTERPOLATION_GAP)
+        .then(|| interpolate_position(before, after, timestamp))
+}
+
+/// J2000 velocity in m/s at the time from the samples of the time ordered track around it:
+/// the speed along the arc in the direction of the chord turned perpendicular to the
+/// interpolated position, plus the rate of the interpolated radius. None outside the track and in gaps of more than
+/// `MAX_INTERPOLATION_GAP`.
+pub fn track_velocity(track: &[(f64, [f64; 3])], timestamp: f64) -> Option<[f64; 3]> {
+    let i = track.partition_point(|s| s.0 < timestamp).max(1);
+    // a time on a sample may take the segment before or after it
+    let (before, after) = [i, i + 1]
+        .into_iter()
+        .filter_map(|i| Some((*track.get(i - 1)?, *track.get(i)?)))
+        .find(|(a, b)| {
+            a.0 <= timestamp && timestamp <= b.0 && b.0 > a.0 && b.0 - a.0 <= MAX_INTERPOLATION_GAP
+        })?;
+    let span = after.0 - before.0;
+    let pos = interpolate_position(before, after, timestamp);
+    let radius = dot(pos, pos).sqrt();
+    let chord: [f64; 3] = std::array::from_fn(|k| (after.1[k] - before.1[k]) / span);
+    let radial_rate = (dot(after.1, after.1).sqrt() - dot(before.1, before.1).sqrt()) / span;
+    let radial = pos.map(|c| c / radius);
+    let along = dot(chord, radial);
+    let tangent: [f64; 3] = std::array::from_fn(|k| chord[k] - along * radial[k]);
+    let norm = dot(tangent, tangent).sqrt();
+    if radius == 0.0 || norm == 0.0 {
+        return None;
+    }
+    // the arc between the samples is longer than the chord by angle / (2 sin(angle / 2))
+    let cos_angle =
+        dot(before.1, after.1) / (dot(before.1, before.1) * dot(after.1, after.1)).sqrt();
+    let half = cos_angle.clamp(-1.0, 1.0).acos() / 2.0;
+    let arc = if half > 0.0 { half / half.sin() } else { 1.0 };
+    let speed = (dot(chord, chord) - radial_rate * radial_rate)
+        .max(0.0)
+        .sqrt()
+        * arc;
+    Some(std::array::from_fn(|k| {
+        tangent[k] * speed / norm + radial_rate * radial[k]
+    }))
+}
+
+/// J2000 unit vector from the Earth to the Sun, low precision solar coordinates of the
+/// Astronomical Almanac (about 0.01 degrees)
+pub fn sun_direction(timestamp: f64) -> [f64; 3] {
+    let days = julian_centuries(timestamp) * 36525.0;
+    let mean_lon = (280.460 + 0.9856474 * days).to_radians();
+    let anomaly = (357.528 + 0.9856003 * days).to_radians();
+    let lon = mean_lon + (1.915 * anomaly.sin() + 0.020 * (2.0 * anomaly).sin()).to_radians();
+    let obliquity = (23.439 - 4e-7 * days).to_radians();
+    [
+        lon.cos(),
+        obliquity.cos() * lon.sin(),
+        obliquity.sin() * lon.sin(),
+    ]
+}
+
+/// Whether the J2000 position is in the Earth shadow at the time, cylindrical shadow model
+pub fn in_eclipse(pos: [f64; 3], timestamp: f64) -> bool {
+    let sun = sun_direction(timestamp);
+    let along = dot(pos, sun);
+    let off_axis = dot(pos, pos) - along * along;
+    along < 0.0 && off_axis < WGS84_A * WGS84_A
+}
+
+/// McIlwain L of the centered dipole field line through the Earth fixed position
+pub fn dipole_l_shell(pos: [f64; 3]) -> f64 {
+    let [x, y, z] = pos;
+    let r = (x * x + y * y + z * z).sqrt();
+    let lat = (z / r).asin();
+    let lon = y.atan2(x);
+    let (pole_lat, pole_lon) = (DIPOLE_POLE_LAT.to_radians(), DIPOLE_POLE_LON.to_radians());
+    let sin_mlat = lat.sin() * pole_lat.sin() + lat.cos() * pole_lat.cos() * (lon - pole_lon).cos();
+    r / EARTH_RADIUS / (1.0 - sin_mlat * sin_mlat)
+}
+
+/// South Atlantic Anomaly region used for the region classification
+pub const SAA: BoundingBox = BoundingBox {
+    lat_min: -50.0,
+    lat_max: 0.0,
+    lon_west: -90.0,
+    lon_east: 40.0,
+};
+
+/// Radiation environment of the subsatellite point: saa, polar (outer belt horns) or
+/// low_latitude
+pub fn radiation_region(geo: &Geodetic, l_shell: f64) -> &'static str {
+    if SAA.contains(geo) {
+        "saa"
+    } else if l_shell >= POLAR_L_SHELL {
+        "polar"
+    } else {
+        "low_latitude"
+    }
+}
+
+/// Geographic region given by two corners, longitudes run eastward from the first corner
+/// to the second so boxes across the antimeridian are possible (e.g. 170,-170)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub lat_min: f64,
+    pub lat_max: f64,
+    pub lon_west: f64,
+    pub lon_east: f64,
+}
+
+impl BoundingBox {
+    pub fn contains(&self, geo: &Geodetic) -> bool {
+        if !(geo.latitude >= self.lat_min && geo.latitude <= self.lat_max) {
+            return false;
+        }
+        if self.lon_west <= self.lon_east {
+            geo.longitude >= self.lon_west && geo.longitude <= self.lon_east
+        } else {
+            geo.longitude >= self.lon_west || geo.longitude <= self.lon_east
+        }
+    }
+}
+
+impl FromStr for BoundingBox {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // lat1,lon1,lat2,lon2
+        let parts: Vec<f64> = s
+            .split(',')
+            .map(|p| p.trim().parse::<f64>())
+            .collect::<Result<_, _>>()?;
+        if parts.len() != 4 {
+            bail!("expected lat1,lon1,lat2,lon2");
+        }
+        let (lat1, lon1, lat2, lon2) = (parts[0], parts[1], parts[2], parts[3]);
+        if lat1.abs() > 90.0 || lat2.abs() > 90.0 || lon1.abs() > 180.0 || lon2.abs() > 180.0 {
+            bail!("coordinates out of range");
+        }
+        Ok(BoundingBox {
+            lat_min: lat1.min(lat2),
+            lat_max: lat1.max(lat2),
+            lon_west: lon1,
+            lon_east: lon2,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::parse_time;
+
+    #[test]
+    fn test_gmst() {
+        // 2000-01-01 12:00:00 UT1
+        let gmst = gmst(946728000.0).to_degrees();
+        assert!((gmst - 280.46061837).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_reference_frames() {
+        // Vallado, Fundamentals of Astrodynamics, example 3-15 (2004-04-06 07:51:28.386 UTC)
+        let timestamp = parse_time("2004-04-06 07:51:28.386").unwrap();
+        let j2000 = [5102508.958, 6123011.401, 6378136.928];
+        let teme = ReferenceFrame::Teme.transform(j2000, timestamp);
+        let expected_teme = [5094180.162, 6127644.656, 6380344.533];
+        let itrf = ReferenceFrame::Itrf.transform(j2000, timestamp);
+        let expected_itrf = [-1033479.383, 7901295.275, 6380356.595];
+        // UT1-UTC (-0.44 s, 260 m along the orbit here) and polar motion are neglected
+        for i in 0..3 {
+            assert!((teme[i] - expected_teme[i]).abs() < 30.0, "{:?}", teme);
+            assert!((itrf[i] - expected_itrf[i]).abs() < 300.0, "{:?}", itrf);
+        }
+        assert_eq!(ReferenceFrame::J2000.transform(j2000, timestamp), j2000);
+        assert_eq!(
+            "ITRF".parse::<ReferenceFrame>().unwrap().to_string(),
+            "itrf"
+        );
+        assert!("gcrf".parse::<ReferenceFrame>().is_err());
+    }
+
+    #[test]
+    fn test_ecef_to_geodetic() {
+        let geo = ecef_to_geodetic([WGS84_A + 500e3, 0.0, 0.0]);
+        assert!(geo.latitude.abs() < 1e-9);
+        assert!(geo.longitude.abs() < 1e-9);
+        assert!((geo.altitude - 500e3).abs() < 1e-6);
+
+        let b = WGS84_A * (1.0 - WGS84_F);
+        let geo = ecef_to_geodetic([0.0, 0.0, -(b + 1000.0)]);
+        assert!((geo.latitude + 90.0).abs() < 1e-9);
+        assert!((geo.altitude - 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_geodetic_from_j2000() {
+        let timestamp = parse_time("2024-03-01 00:00:09.000").unwrap();
+        let pos = [2.51279e+6, 5.64324e+5, -6.50431e+6];
+        let geo = geodetic_from_j2000(pos, timestamp);
+        let radius = (pos[0] * pos[0] + pos[1] * pos[1] + pos[2] * pos[2]).sqrt();
+        let ecef = j2000_to_ecef(pos, timestamp);
+        let radius_ecef = (ecef[0] * ecef[0] + ecef[1] * ecef[1] + ecef[2] * ecef[2]).sqrt();
+        assert!((radius - radius_ecef).abs() < 1e-6);
+        assert!((geo.latitude + 68.39).abs() < 0.01);
+        assert!(geo.altitude > 600e3 && geo.altitude < 700e3);
+    }
+
+    #[test]
+    fn test_ascending_nodes() {
+        let samples = [
+            (0.0, [7e6, 0.0, -1000.0]),
+            (10.0, [7e6, 0.0, 3000.0]),
+            (20.0, [7e6, 0.0, -500.0]),
+            (30.0, [7e6, 0.0, 0.0]),
+            (40.0, [7e6, 0.0, 100.0]),
+        ];
+        assert_eq!(ascending_nodes(samples), vec![2.5, 30.0]);
+    }
+
+    #[test]
+    fn test_interpolate_track() {
+        // circular orbit of 7000 km radius sampled every 60 s, with a gap after 120 s
+        let omega = 7.546e3 / 7.0e6;
+        let pos = |t: f64| [7e6 * (omega * t).cos(), 7e6 * (omega * t).sin(), 0.0];
+        let track: Vec<(f64, [f64; 3])> = [0.0, 60.0, 120.0, 1000.0]
+            .into_iter()
+            .map(|t| (t, pos(t)))
+            .collect();
+        let p = interpolate_track(&track, 90.0).unwrap();
+        let error = (0..3).map(|i| (p[i] - pos(90.0)[i]).powi(2)).sum::<f64>();
+        assert!(error.sqrt() < 1.0, "{}", error.sqrt());
+        assert_eq!(interpolate_track(&track, 60.0), Some(pos(60.0)));
+        assert_eq!(interpolate_track(&track, 1000.0), Some(pos(1000.0)));
+        assert_eq!(interpolate_track(&track, 500.0), None);
+        assert_eq!(interpolate_track(&track, -1.0), None);
+        assert_eq!(interpolate_track(&track, 1001.0), None);
+
+        let velocity = |t: f64| {
+            [
+                -7.546e3 * (omega * t).sin(),
+                7.546e3 * (omega * t).cos(),
+                0.0,
+            ]
+        };
+        for t in [0.0, 20.0, 60.0, 120.0] {
+            let v = track_velocity(&track, t).unwrap();
+            let error = (0..3).map(|i| (v[i] - velocity(t)[i]).powi(2)).sum::<f64>();
+            assert!(error.sqrt() < 1.0, "{} {}", t, error.sqrt());
+        }
+        assert_eq!(track_velocity(&track, 500.0), None);
+        assert_eq!(track_velocity(&track, 1000.0), None);
+        assert_eq!(track_velocity(&track[..1], 0.0), None);
+    }
+
+    #[test]
+    fn test_dipole_l_shell() {
+        let (pole_lat, pole_lon) = (DIPOLE_POLE_LAT.to_radians(), DIPOLE_POLE_LON.to_radians());
+        let pole = [
+            pole_lat.cos() * pole_lon.cos(),
+            pole_lat.cos() * pole_lon.sin(),
+            pole_lat.sin(),
+        ];
+        let equator = [
+            -pole_lat.sin() * pole_lon.cos(),
+            -pole_lat.sin() * pole_lon.sin(),
+            pole_lat.cos(),
+        ];
+        // on the dipole equator the L-shell is the radius in Earth radii
+        let pos = equator.map(|v| 2.0 * EARTH_RADIUS * v);
+        assert!((dipole_l_shell(pos) - 2.0).abs() < 1e-9);
+        // 60 deg geomagnetic latitude at the surface gives L = 4
+        let mlat = 60f64.to_radians();
+        let pos: [f64; 3] = std::array::from_fn(|i| {
+            EARTH_RADIUS * (mlat.cos() * equator[i] + mlat.sin() * pole[i])
+        });
+        assert!((dipole_l_shell(pos) - 4.0).abs() < 1e-9);
+
+        let geo = |latitude, longitude| Geodetic {
+            latitude,
+            longitude,
+            altitude: 0.0,
+        };
+        assert_eq!(radiation_region(&geo(-30.0, -45.0), 1.2), "saa");
+        assert_eq!(radiation_region(&geo(70.0, 10.0), 5.0), "polar");
+        assert_eq!(radiation_region(&geo(10.0, 100.0), 1.1), "low_latitude");
+    }
+
+    #[test]
+    fn test_eclipse() {
+        // March equinox 2024-03-20 03:06 UTC, the Sun is on the x axis
+        let sun = sun_direction(1710903960.0);
+        assert!(
+            (sun[0] - 1.0).abs() < 1e-4 && sun[2].abs() < 1e-3,
+            "{:?}",
+            sun
+        );
+        // June solstice, the Sun is at the obliquity north of the equator
+        let sun = sun_direction(1718931600.0);
+        assert!(
+            (sun[2].asin().to_degrees() - 23.44).abs() < 0.05,
+            "{:?}",
+            sun
+        );
+
+        let t = 1710903960.0;
+        assert!(in_eclipse([-7e6, 0.0, 0.0], t));
+        assert!(!in_eclipse([7e6, 0.0, 0.0], t));
+        assert!(!in_eclipse([-7e6, 0.0, 6.5e6], t));
+        assert!(!in_eclipse([0.0, 7e6, 0.0], t));
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let bbox: BoundingBox = "-50,-90,0,40".parse().unwrap();
+        let geo = |latitude, longitude| Geodetic {
+            latitude,
+            longitude,
+            altitude: 0.0,
+        };
+        assert!(bbox.contains(&geo(-30.0, -45.0)));
+        assert!(!bbox.contains(&geo(10.0, -45.0)));
+        assert!(!bbox.contains(&geo(-30.0, 50.0)));
+
+        let bbox: BoundingBox = "10,170,-10,-170".parse().unwrap();
+        assert!(bbox.contains(&geo(0.0, 175.0)));
+        assert!(bbox.contains(&geo(0.0, -175.0)));
+        assert!(!bbox.contains(&geo(0.0, 0.0)));
+
+        assert!("1,2,3".parse::<BoundingBox>().is_err());
+        assert!("100,0,0,0".parse::<BoundingBox>().is_err());
+    }
+}
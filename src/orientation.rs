@@ -0,0 +1,156 @@
+use crate::clustering::Cluster;
+use anyhow::{Result, bail};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+/// Largest pixel coordinate of the matrix
+const MAX: u8 = 255;
+
+/// Orientation of the pixel coordinates in the outputs. The decoder numbers the columns
+/// (x) and rows (y) from the chip periphery, Pixet draws the matrix with the periphery at
+/// the bottom, i.e. flipped vertically.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Orientation {
+    /// As decoded
+    #[default]
+    Detector,
+    /// Pixet display orientation: (x, 255 - y)
+    Pixet,
+    /// Rotated by 90 degrees clockwise (rows drawn downwards): (255 - y, x)
+    Rot90,
+    /// (255 - x, 255 - y)
+    Rot180,
+    /// Rotated by 90 degrees counterclockwise: (y, 255 - x)
+    Rot270,
+    /// Mirrored columns: (255 - x, y)
+    FlipX,
+    /// Mirrored rows: (x, 255 - y)
+    FlipY,
+    /// Rows and columns swapped: (y, x)
+    Transpose,
+}
+
+impl Orientation {
+    /// Output coordinates of the decoded pixel
+    pub fn apply(self, x: u8, y: u8) -> (u8, u8) {
+        match self {
+            Orientation::Detector => (x, y),
+            Orientation::Pixet | Orientation::FlipY => (x, MAX - y),
+            Orientation::Rot90 => (MAX - y, x),
+            Orientation::Rot180 => (MAX - x, MAX - y),
+            Orientation::Rot270 => (y, MAX - x),
+            Orientation::FlipX => (MAX - x, y),
+            Orientation::Transpose => (y, x),
+        }
+    }
+
+    /// Clusters with the output coordinates of their pixels
+    pub fn clusters(self, clusters: &[Cluster]) -> Cow<'_, [Cluster]> {
+        if self == Orientation::Detector {
+            return Cow::Borrowed(clusters);
+        }
+        Cow::Owned(
+            clusters
+                .iter()
+                .map(|cluster| {
+                    let mut cluster = cluster.clone();
+                    for pixel in &mut cluster.pixels {
+                        (pixel.x, pixel.y) = self.apply(pixel.x, pixel.y);
+                    }
+                    cluster
+                })
+                .collect(),
+        )
+    }
+
+    /// 256x256 matrix (row major) in the output orientation
+    pub fn matrix<T: Copy + Default>(self, values: &[T]) -> Cow<'_, [T]> {
+        if self == Orientation::Detector {
+            return Cow::Borrowed(values);
+        }
+        let mut out = vec![T::default(); values.len()];
+        for (idx, &value) in values.iter().enumerate() {
+            let (x, y) = self.apply((idx % 256) as u8, (idx / 256) as u8);
+            out[y as usize * 256 + x as usize] = value;
+        }
+        Cow::Owned(out)
+    }
+}
+
+impl FromStr for Orientation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "detector" => Ok(Orientation::Detector),
+            "pixet" => Ok(Orientation::Pixet),
+            "rot90" => Ok(Orientation::Rot90),
+            "rot180" => Ok(Orientation::Rot180),
+            "rot270" => Ok(Orientation::Rot270),
+            "flip-x" => Ok(Orientation::FlipX),
+            "flip-y" => Ok(Orientation::FlipY),
+            "transpose" => Ok(Orientation::Transpose),
+            _ => bail!(
+                "expected detector, pixet, rot90, rot180, rot270, flip-x, flip-y or transpose"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Orientation::Detector => "detector",
+            Orientation::Pixet => "pixet",
+            Orientation::Rot90 => "rot90",
+            Orientation::Rot180 => "rot180",
+            Orientation::Rot270 => "rot270",
+            Orientation::FlipX => "flip-x",
+            Orientation::FlipY => "flip-y",
+            Orientation::Transpose => "transpose",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clustering::Pixel;
+
+    #[test]
+    fn test_orientation() {
+        assert_eq!(Orientation::Pixet.apply(10, 0), (10, 255));
+        assert_eq!(Orientation::Rot90.apply(10, 0), (255, 10));
+        assert_eq!(Orientation::Rot270.apply(10, 0), (0, 245));
+        assert_eq!(Orientation::Rot180.apply(10, 0), (245, 255));
+        assert_eq!(Orientation::Transpose.apply(10, 3), (3, 10));
+        // four quarter turns and two flips are the identity
+        let mut p = (17, 200);
+        for _ in 0..4 {
+            p = Orientation::Rot90.apply(p.0, p.1);
+        }
+        assert_eq!(p, (17, 200));
+        let (x, y) = Orientation::FlipX.apply(17, 200);
+        assert_eq!(Orientation::FlipX.apply(x, y), (17, 200));
+
+        let clusters = [Cluster {
+            pixels: vec![Pixel::new(1, 2, 30, 0)],
+            merged: 0,
+        }];
+        assert!(matches!(
+            Orientation::Detector.clusters(&clusters),
+            Cow::Borrowed(_)
+        ));
+        let oriented = Orientation::Pixet.clusters(&clusters);
+        assert_eq!((oriented[0].pixels[0].x, oriented[0].pixels[0].y), (1, 253));
+        let mut matrix = vec![0u16; 256 * 256];
+        matrix[2 * 256 + 1] = 30;
+        assert_eq!(Orientation::Pixet.matrix(&matrix)[253 * 256 + 1], 30);
+
+        for name in ["detector", "pixet", "rot90", "flip-x", "transpose"] {
+            assert_eq!(name.parse::<Orientation>().unwrap().to_string(), name);
+        }
+        assert!("mirror".parse::<Orientation>().is_err());
+    }
+}
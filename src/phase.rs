@@ -0,0 +1,228 @@
+use crate::data_processor::Frame;
+use crate::dosimetry;
+use crate::gps_processor::GpsData;
+use crate::orbit;
+use anyhow::{Context, Result};
+use chrono::{TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// GPS samples further apart do not give the orbit phase of the frames between them
+const MAX_SAMPLE_GAP: f64 = 600.0;
+
+/// Weighted sums of the rates of the frames in one phase bin
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RateSums {
+    pub frames: f64,
+    pub count_rate: f64,
+    pub count_rate_sq: f64,
+    pub dose_rate: f64,
+    pub dose_rate_sq: f64,
+}
+
+impl RateSums {
+    fn add(&mut self, count_rate: f64, dose_rate: f64, weight: f64) {
+        self.frames += weight;
+        self.count_rate += weight * count_rate;
+        self.count_rate_sq += weight * count_rate * count_rate;
+        self.dose_rate += weight * dose_rate;
+        self.dose_rate_sq += weight * dose_rate * dose_rate;
+    }
+
+    fn merge(&mut self, other: &RateSums) {
+        self.frames += other.frames;
+        self.count_rate += other.count_rate;
+        self.count_rate_sq += other.count_rate_sq;
+        self.dose_rate += other.dose_rate;
+        self.dose_rate_sq += other.dose_rate_sq;
+    }
+
+    /// Mean and standard deviation of the summed values
+    fn mean_sigma(&self, sum: f64, sum_sq: f64) -> (f64, f64) {
+        if self.frames <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let mean = sum / self.frames;
+        (mean, (sum_sq / self.frames - mean * mean).max(0.0).sqrt())
+    }
+}
+
+/// Profiles of the count and dose rates folded by the orbit phase (argument of latitude),
+/// per UTC day and per ISO week
+#[derive(Debug, Clone)]
+pub struct PhaseFolding {
+    /// Number of phase bins over 360 degrees
+    pub bins: usize,
+    /// Time ordered (timestamp, J2000 position) of the valid GPS records
+    pub track: Arc<Vec<(f64, [f64; 3])>>,
+    /// Rate sums per period (date or ISO week) and phase bin
+    pub profiles: BTreeMap<(String, usize), RateSums>,
+}
+
+/// Time ordered (timestamp, J2000 position) of the valid GPS records
+pub fn track(records: &[GpsData]) -> Vec<(f64, [f64; 3])> {
+    records
+        .iter()
+        .filter(|r| r.is_valid())
+        .map(|r| (r.timestamp, [r.j2000_x, r.j2000_y, r.j2000_z]))
+        .collect()
+}
+
+/// Position at the time interpolated between the samples of the track around it and its
+/// argument of latitude in degrees; None outside the track and in its gaps
+pub fn locate(track: &[(f64, [f64; 3])], timestamp: f64) -> Option<([f64; 3], f64)> {
+    let i = track.partition_point(|s| s.0 <= timestamp);
+    let (t0, p0) = *track.get(i.checked_sub(1)?)?;
+    let (t1, p1) = *track.get(i)?;
+    if t1 - t0 > MAX_SAMPLE_GAP {
+        return None;
+    }
+    let f = (timestamp - t0) / (t1 - t0);
+    let pos = [0, 1, 2].map(|k| p0[k] + (p1[k] - p0[k]) * f);
+    let phase =
+        orbit::argument_of_latitude(pos, p1).or_else(|| orbit::argument_of_latitude(p0, pos))?;
+    Some((pos, phase))
+}
+
+impl PhaseFolding {
+    pub fn new(bins: usize, records: &[GpsData]) -> Self {
+        PhaseFolding {
+            bins,
+            track: Arc::new(track(records)),
+            profiles: BTreeMap::new(),
+        }
+    }
+
+    /// Empty profiles sharing the track, for a parallel job
+    pub fn fresh(&self) -> Self {
+        PhaseFolding {
+            bins: self.bins,
+            track: self.track.clone(),
+            profiles: BTreeMap::new(),
+        }
+    }
+
+    /// Argument of latitude in degrees at the time, from the position interpolated between
+    /// the GPS samples around it
+    pub fn phase(&self, timestamp: f64) -> Option<f64> {
+        locate(&self.track, timestamp).map(|(_, phase)| phase)
+    }
+
+    /// Adds the hit pixel and dose rates of the frame, frames outside the GPS track are not
+    /// folded
+    pub fn add_frame(&mut self, frame: &Frame, kev_per_count: f64, acq_time: f64, weight: f64) {
+        if acq_time <= 0.0 {
+            return;
+        }
+        let Some(phase) = self.phase(frame.timestamp) else {
+            return;
+        };
+        let bin = ((phase / 360.0 * self.bins as f64) as usize).min(self.bins - 1);
+        let hits = frame.itot().iter().filter(|&&v| v != 0).count();
+        let count_rate = hits as f64 / acq_time;
+        let dose_rate = dosimetry::frame_dose_rate(frame, kev_per_count, acq_time);
+        let time = Utc
+            .timestamp_opt(frame.timestamp.floor() as i64, 0)
+            .unwrap();
+        for period in [
+            time.format("%Y-%m-%d").to_string(),
+            time.format("%G-W%V").to_string(),
+        ] {
+            self.profiles
+                .entry((period, bin))
+                .or_default()
+                .add(count_rate, dose_rate, weight);
+        }
+    }
+
+    pub fn merge(&mut self, other: &PhaseFolding) {
+        for (key, sums) in &other.profiles {
+            self.profiles.entry(key.clone()).or_default().merge(sums);
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(
+            fs::File::create(path)
+                .with_context(|| format!("cannot write phase profiles {}", path.display()))?,
+        );
+        writeln!(
+            writer,
+            "# Count and dose rates folded by the argument of latitude, {} bins, per day (YYYY-MM-DD) and ISO week (YYYY-Www)",
+            self.bins
+        )?;
+        writeln!(
+            writer,
+            "period\tphase_from[deg]\tphase_to[deg]\tframes\tcount_rate_mean[1/s]\tcount_rate_sigma[1/s]\tdose_rate_mean[Gy/s]\tdose_rate_sigma[Gy/s]"
+        )?;
+        let width = 360.0 / self.bins as f64;
+        for ((period, bin), sums) in &self.profiles {
+            let (count_mean, count_sigma) = sums.mean_sigma(sums.count_rate, sums.count_rate_sq);
+            let (dose_mean, dose_sigma) = sums.mean_sigma(sums.dose_rate, sums.dose_rate_sq);
+            writeln!(
+                writer,
+                "{}\t{:.1}\t{:.1}\t{}\t{:.3}\t{:.3}\t{:.4e}\t{:.4e}",
+                period,
+                *bin as f64 * width,
+                (*bin + 1) as f64 * width,
+                sums.frames,
+                count_mean,
+                count_sigma,
+                dose_mean,
+                dose_sigma
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tpx3lut::MATRIX_SIZE;
+
+    /// Circular orbit inclined by 90 degrees with the ascending node on the x axis
+    fn gps(timestamp: f64, u_deg: f64) -> GpsData {
+        let (sin_u, cos_u) = u_deg.to_radians().sin_cos();
+        GpsData {
+            timestamp,
+            j2000_x: 7e6 * cos_u,
+            j2000_z: 7e6 * sin_u,
+            q_est_prop_bj_scalar: 1.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_phase_folding() {
+        let track: Vec<GpsData> = (0..=12)
+            .map(|i| gps(i as f64 * 100.0, i as f64 * 30.0))
+            .collect();
+        let mut folding = PhaseFolding::new(4, &track);
+        assert!((folding.phase(0.0).unwrap()).abs() < 1e-9);
+        assert!((folding.phase(250.0).unwrap() - 75.0).abs() < 1.0);
+        assert!((folding.phase(1150.0).unwrap() - 345.0).abs() < 1.0);
+        assert_eq!(folding.phase(1300.0), None);
+
+        let mut itot = vec![0; MATRIX_SIZE];
+        itot[..10].fill(5);
+        let frame = |timestamp| Frame::from_planes(itot.clone(), itot.clone(), timestamp);
+        folding.add_frame(&frame(50.0), 1.0, 10.0, 1.0);
+        let mut other = folding.fresh();
+        other.add_frame(&frame(60.0), 1.0, 5.0, 1.0);
+        other.add_frame(&frame(400.0), 1.0, 5.0, 2.0);
+        folding.merge(&other);
+
+        // 1970-01-01 is a Thursday of ISO week 1
+        let day = &folding.profiles[&(String::from("1970-01-01"), 0)];
+        assert_eq!(day.frames, 2.0);
+        let (mean, sigma) = day.mean_sigma(day.count_rate, day.count_rate_sq);
+        assert_eq!((mean, sigma), (1.5, 0.5));
+        assert_eq!(folding.profiles[&(String::from("1970-W01"), 1)].frames, 2.0);
+        assert_eq!(folding.profiles.len(), 4);
+    }
+}
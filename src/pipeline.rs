@@ -0,0 +1,191 @@
+use crate::data_processor::{DataProcessor, Frame, Readout};
+use crate::line_reader::LineReader;
+use anyhow::{Result, bail};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Source of the decoded frames of a data stream in input order
+pub trait FrameSource {
+    /// Next frame, `OnewebError::EndOfData` after the last one; the decoding counters end up
+    /// in the processor
+    fn next_frame(&mut self, processor: &mut DataProcessor) -> Result<Frame>;
+}
+
+impl<R: Read> FrameSource for LineReader<R> {
+    fn next_frame(&mut self, processor: &mut DataProcessor) -> Result<Frame> {
+        processor.get_next_frame(self)
+    }
+}
+
+/// Frames decoded on worker threads: one thread reads the lines and assembles the readouts,
+/// the decoder threads decode and cluster them and the frames are returned in input order,
+/// so the output does not depend on the number of threads
+pub struct FramePipeline {
+    /// Decoded frames and the error ending the input, numbered in input order
+    results: Receiver<(usize, Result<Frame>)>,
+    /// Returned permits of the assembler, bound the readouts in flight
+    permits: SyncSender<()>,
+    /// Frames received ahead of the next one
+    pending: BTreeMap<usize, Result<Frame>>,
+    next: usize,
+    threads: Vec<JoinHandle<DataProcessor>>,
+}
+
+impl FramePipeline {
+    /// Starts the assembler and `decoders` decoder threads with the settings of the processor
+    pub fn new<R: Read + Send + 'static>(
+        processor: &DataProcessor,
+        mut reader: LineReader<R>,
+        decoders: usize,
+    ) -> Self {
+        let decoders = decoders.max(1);
+        let in_flight = 2 * decoders;
+        let (permits, permit_receiver) = sync_channel::<()>(in_flight);
+        for _ in 0..in_flight {
+            let _ = permits.send(());
+        }
+        let (job_sender, jobs) = sync_channel::<(usize, Readout)>(in_flight);
+        let (result_sender, results) = sync_channel(in_flight);
+
+        let mut assembler = processor.worker();
+        let errors = result_sender.clone();
+        let mut threads = vec![thread::spawn(move || {
+            // ends at the end of the input or when the consumer is dropped
+            let mut seq = 0;
+            while permit_receiver.recv().is_ok() {
+                match assembler.next_readout(&mut reader) {
+                    Ok(readout) => {
+                        if job_sender.send((seq, readout)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let _ = errors.send((seq, Err(e)));
+                        break;
+                    }
+                }
+                seq += 1;
+            }
+            assembler
+        })];
+
+        let jobs = Arc::new(Mutex::new(jobs));
+        for _ in 0..decoders {
+            let jobs = jobs.clone();
+            let results = result_sender.clone();
+            let mut decoder = processor.worker();
+            threads.push(thread::spawn(move || {
+                loop {
+                    let job = jobs.lock().unwrap().recv();
+                    let Ok((seq, readout)) = job else {
+                        break;
+                    };
+                    if results
+                        .send((seq, Ok(decoder.decode_readout(readout))))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                decoder
+            }));
+        }
+
+        FramePipeline {
+            results,
+            permits,
+            pending: BTreeMap::new(),
+            next: 0,
+            threads,
+        }
+    }
+
+    /// Waits for the threads and adds their counters to the processor
+    fn join(&mut self, processor: &mut DataProcessor) -> Result<()> {
+        for thread in self.threads.drain(..) {
+            match thread.join() {
+                Ok(worker) => processor.merge_stats(&worker),
+                Err(_) => bail!("frame decoding thread panicked"),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FrameSource for FramePipeline {
+    fn next_frame(&mut self, processor: &mut DataProcessor) -> Result<Frame> {
+        loop {
+            if let Some(result) = self.pending.remove(&self.next) {
+                self.next += 1;
+                let _ = self.permits.send(());
+                if result.is_err() {
+                    self.join(processor)?;
+                }
+                return result;
+            }
+            match self.results.recv() {
+                Ok((seq, result)) => {
+                    self.pending.insert(seq, result);
+                }
+                Err(_) => {
+                    self.join(processor)?;
+                    bail!(
+                        "frame decoding threads stopped before frame {}",
+                        self.next + 1
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::OnewebError;
+    use std::io::{BufReader, Cursor};
+
+    #[test]
+    fn test_frame_pipeline() {
+        let mut data = String::from("TIMESTAMP,DATA\n");
+        for i in 0..40 {
+            data += &format!(
+                "2024-03-01 00:{:02}:00.000,71AF00000000A3ED79C3FFEE\n\
+                 2024-03-01 00:{:02}:01.000,A3E9F333BFEE71A00000\n",
+                i, i
+            );
+        }
+        data += "2024-03-01 00:59:00.000,71AF0000\n2024-03-01 00:59:01.000,zz\n";
+        let reader =
+            |data: &str| LineReader::new(BufReader::new(Cursor::new(data.to_string())), "data.csv");
+
+        let mut serial = DataProcessor::new();
+        let mut lines = reader(&data);
+        let mut pipelined = DataProcessor::new();
+        let mut pipeline = FramePipeline::new(&pipelined, reader(&data), 3);
+        loop {
+            match (
+                lines.next_frame(&mut serial),
+                pipeline.next_frame(&mut pipelined),
+            ) {
+                (Ok(a), Ok(b)) => {
+                    assert_eq!(a.timestamp, b.timestamp);
+                    assert_eq!(a.raw, b.raw);
+                    assert_eq!(a.clusters.len(), b.clusters.len());
+                }
+                (Err(a), Err(b)) => {
+                    assert!(a.downcast_ref::<OnewebError>().is_some());
+                    assert!(b.downcast_ref::<OnewebError>().is_some());
+                    break;
+                }
+                (a, b) => panic!("{:?} {:?}", a.is_ok(), b.is_ok()),
+            }
+        }
+        assert_eq!(pipelined.timing.frames, 40);
+        assert_eq!(pipelined.bad_lines, serial.bad_lines);
+        assert_eq!(pipelined.bad_lines, 1);
+    }
+}
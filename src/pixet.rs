@@ -0,0 +1,205 @@
+use crate::utils::format_time;
+use anyhow::{Context, Result, bail};
+use std::fmt::{self, Write as _};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Side of the pixel matrix
+const SIDE: usize = 256;
+/// Time unit of the ToA column of the pixel list (the 40 MHz Timepix3 clock)
+const TOA_TICK: f64 = 25e-9;
+/// Header of the t3pa pixel list
+const T3PA_HEADER: &str = "Index\tMatrix Index\tToA\tToT\tFToA\tOverflow";
+
+/// Pixet compatible frame files: `pmf` writes the iToT matrices as an ASCII multi-frame file
+/// with its `.pmf.dsc` descriptor, `t3pa` the hit pixels as a Timepix3 pixel list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixetFormat {
+    Pmf,
+    T3pa,
+    Both,
+}
+
+impl PixetFormat {
+    fn pmf(self) -> bool {
+        self != PixetFormat::T3pa
+    }
+
+    fn t3pa(self) -> bool {
+        self != PixetFormat::Pmf
+    }
+
+    /// Names of the files of the day
+    pub fn names(self, date: &str) -> Vec<String> {
+        let mut names = Vec::new();
+        if self.pmf() {
+            names.push(format!("data_{}.pmf", date));
+            names.push(format!("data_{}.pmf.dsc", date));
+        }
+        if self.t3pa() {
+            names.push(format!("data_{}.t3pa", date));
+        }
+        names
+    }
+}
+
+impl FromStr for PixetFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pmf" => Ok(PixetFormat::Pmf),
+            "t3pa" => Ok(PixetFormat::T3pa),
+            "both" => Ok(PixetFormat::Both),
+            _ => bail!("expected pmf, t3pa or both"),
+        }
+    }
+}
+
+impl fmt::Display for PixetFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PixetFormat::Pmf => "pmf",
+            PixetFormat::T3pa => "t3pa",
+            PixetFormat::Both => "both",
+        })
+    }
+}
+
+/// Writer of the Pixet files of a day; the descriptor starts with the frame count, so it is
+/// kept in memory and written by [`PixetWriter::finish`]
+pub struct PixetWriter {
+    pmf: Option<BufWriter<File>>,
+    t3pa: Option<BufWriter<File>>,
+    dsc_path: PathBuf,
+    dsc: String,
+    frames: usize,
+    pixels: usize,
+    /// Time of the first frame, the origin of the pixel list ToA
+    origin: Option<f64>,
+}
+
+impl PixetWriter {
+    pub fn create(dir: &Path, date: &str, format: PixetFormat) -> Result<Self> {
+        let create = |name: String| -> Result<BufWriter<File>> {
+            let path = dir.join(name);
+            let file =
+                File::create(&path).with_context(|| format!("cannot create {}", path.display()))?;
+            Ok(BufWriter::new(file))
+        };
+        let pmf = format
+            .pmf()
+            .then(|| create(format!("data_{}.pmf", date)))
+            .transpose()?;
+        let t3pa = if format.t3pa() {
+            let mut writer = create(format!("data_{}.t3pa", date))?;
+            writeln!(writer, "{}", T3PA_HEADER)?;
+            Some(writer)
+        } else {
+            None
+        };
+        Ok(PixetWriter {
+            pmf,
+            t3pa,
+            dsc_path: dir.join(format!("data_{}.pmf.dsc", date)),
+            dsc: String::new(),
+            frames: 0,
+            pixels: 0,
+            origin: None,
+        })
+    }
+
+    /// Appends the iToT matrix (in the output orientation) of the frame
+    pub fn write_frame(&mut self, itot: &[u16], timestamp: f64, acq_time: f64) -> Result<()> {
+        if let Some(pmf) = &mut self.pmf {
+            for row in itot.chunks(SIDE) {
+                let values: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+                writeln!(pmf, "{}", values.join(" "))?;
+            }
+            let _ = write!(
+                self.dsc,
+                "[F{}]\nType=i16 [X,Y,C] width={} height={}\n\
+                 \"Acq Serie Index\" (\"Acquisition serie index\"):\ni32[1]\n{}\n\n\
+                 \"Acq time\" (\"Acquisition time [s]\"):\ndouble[1]\n{:.6}\n\n\
+                 \"Start time\" (\"Acquisition start time\"):\ndouble[1]\n{:.6}\n\n\
+                 \"Start time (string)\" (\"Acquisition start time (string)\"):\nchar[{}]\n{}\n\n",
+                self.frames,
+                SIDE,
+                SIDE,
+                self.frames,
+                acq_time,
+                timestamp,
+                format_time(timestamp).len(),
+                format_time(timestamp)
+            );
+        }
+        if let Some(t3pa) = &mut self.t3pa {
+            let origin = *self.origin.get_or_insert(timestamp);
+            let toa = ((timestamp - origin) / TOA_TICK).round().max(0.0) as u64;
+            for (idx, &value) in itot.iter().enumerate().filter(|(_, v)| **v != 0) {
+                writeln!(t3pa, "{}\t{}\t{}\t{}\t0\t0", self.pixels, idx, toa, value)?;
+                self.pixels += 1;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        for writer in [&mut self.pmf, &mut self.t3pa].into_iter().flatten() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the files and writes the descriptor of the multi-frame file
+    pub fn finish(mut self) -> Result<()> {
+        self.flush()?;
+        if self.pmf.is_some() {
+            let dsc = format!("A{:09}\n{}", self.frames, self.dsc);
+            fs::write(&self.dsc_path, dsc)
+                .with_context(|| format!("cannot write {}", self.dsc_path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixet_writer() {
+        let dir = std::env::temp_dir().join(format!("oneweb-pixet-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let format: PixetFormat = "both".parse().unwrap();
+        let mut writer = PixetWriter::create(&dir, "2024-03-01", format).unwrap();
+        let mut itot = vec![0u16; SIDE * SIDE];
+        itot[SIDE + 2] = 40;
+        writer.write_frame(&itot, 1709251201.3, 0.5).unwrap();
+        itot[5] = 7;
+        writer.write_frame(&itot, 1709251201.8, 0.5).unwrap();
+        writer.finish().unwrap();
+
+        let names = format.names("2024-03-01");
+        assert_eq!(names.len(), 3);
+        let pmf = fs::read_to_string(dir.join(&names[0])).unwrap();
+        let rows: Vec<&str> = pmf.lines().collect();
+        assert_eq!(rows.len(), 2 * SIDE);
+        assert!(rows[1].starts_with("0 0 40 0"));
+        assert!(rows[SIDE].starts_with("0 0 0 0 0 7"));
+        let dsc = fs::read_to_string(dir.join(&names[1])).unwrap();
+        assert!(dsc.starts_with("A000000002\n[F0]\nType=i16 [X,Y,C] width=256 height=256\n"));
+        assert!(dsc.contains("[F1]") && dsc.contains("1709251201.800000"));
+        let t3pa = fs::read_to_string(dir.join(&names[2])).unwrap();
+        let lines: Vec<&str> = t3pa.lines().collect();
+        assert_eq!(lines[0], T3PA_HEADER);
+        assert_eq!(lines[1], "0\t258\t0\t40\t0\t0");
+        // 0.5 s later in 25 ns ticks
+        assert_eq!(lines[2], "1\t5\t20000000\t7\t0\t0");
+        assert_eq!(lines.len(), 4);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
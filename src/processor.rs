@@ -1,29 +1,897 @@
-use crate::data_processor::{DataProcessor, Frame};
+use crate::binning::{self, PositionBinning};
+use crate::checkpoint::{self, Checkpoint};
+use crate::classification::ClassThresholds;
+use crate::clock::{Clock, SystemClock};
+use crate::cluster_table::{self, ClusterTable};
+use crate::clustering::{Cluster, ClusterBackend, Labeler};
+use crate::clusterize;
+use crate::columns::{self, Column, GpsColumns, MetaRow};
+use crate::data_processor::{DataProcessor, ErrorPolicy, Frame, PacketLayout, SentinelPolicy};
+use crate::database::{DATABASE_NAME, Database, OutputFormat};
+use crate::day_stats::{DAY_STATS_PREFIX, DayStatistics};
+use crate::deglitch::GpsDeglitcher;
+use crate::derived::DerivedColumn;
+use crate::direction::{self, Matrix3, PointingFrame};
+use crate::disk::{ByteSize, DiskGuard, LowDiskPolicy};
+use crate::dose_equivalent::{DoseEquivalentReport, QualityFactor};
+use crate::dosimetry::{self, DailyDose, DoseMap};
+use crate::drift::SchemaDrift;
+use crate::duty::DutyCycle;
+use crate::energy_calibration::EnergyCalibration;
+use crate::error::OnewebError;
+use crate::event_catalog::{CatalogEvent, EventCatalog, FrameContext};
+use crate::event_display::{self, EventDisplay, EventSelection};
+use crate::firmware::{self, Compatibility, FirmwarePolicy};
+use crate::frame_image::FrameImages;
 use crate::gps_processor::{GpsData, GpsProcessor};
+use crate::gpu;
+use crate::hot_pixels::{HotPixelMask, HotPixelStats};
+use crate::index::{self, DaySegment, DaySplit, FrameNumbering, FrameSelector, FrameTimeSource};
 use crate::info_processor::{MeasInfoData, MeasInfoProcessor};
-use anyhow::{Result, bail};
+use crate::input::InputFile;
+use crate::line_reader::{LineReader, Position};
+use crate::logging;
+use crate::maneuver::{ManeuverPolicy, Maneuvers};
+use crate::manifest::DayFiles;
+use crate::mode::{self, ModeReport};
+use crate::noise::{AdaptiveThreshold, NoiseModel};
+use crate::orbit::{self, BoundingBox, ReferenceFrame};
+use crate::orientation::Orientation;
+use crate::phase::{self, PhaseFolding};
+use crate::pipeline::{FramePipeline, FrameSource};
+use crate::pixet::{PixetFormat, PixetWriter};
+use crate::progress::Progress;
+use crate::provenance::{self, BuildInfo};
+use crate::quality::{self, Issue, QualityLog, ReprocessWindow, RunSettings};
+use crate::records::{self, RecordFormat};
+use crate::repro;
+use crate::roi::{Roi, RoiReport};
+use crate::schema::FrameRecord;
+use crate::see::{self, SeeAnalysis};
+use crate::source::{self, InputSource, RetryPolicy, SourceReader};
+use crate::spectra::RegionSpectra;
+use crate::thermal::ThermalModel;
+use crate::timing::{Stage, StageTimes};
+use crate::tle::TleSet;
+use crate::toa_calibration::ToaCalibration;
+use crate::tpx3lut::MATRIX_SIZE;
+use crate::tui;
+use crate::utils;
+use crate::watch::{self, WatchPolicy};
+use crate::window::TimeWindow;
+use anyhow::{Context, Result, bail};
 use chrono::{self, TimeZone};
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 use std::io::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Size of the --preview-every heatmaps in characters, a cell covers 4x8 pixels
+const PREVIEW_WIDTH: usize = 64;
+const PREVIEW_HEIGHT: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct ProcessorConfig {
+    /// Hit pixel count at which the detector ends a frame acquisition, the acquisition
+    /// time of a frame is the time to reach it at the rate of the short/long test counts
+    pub max_pix_count: usize,
+    /// Only frames with the subsatellite point inside the box are written
+    pub bbox: Option<BoundingBox>,
+    /// Maximum time in s between the frame and its GPS record, the position of frames
+    /// without a record this close is written as missing
+    pub max_gps_staleness: Option<f64>,
+    /// Maximum time in s between the start of readout of a frame and its last data line,
+    /// frames assembled longer are aborted as corrupt
+    pub max_assembly_time: Option<f64>,
+    /// Run without the GPS file, the GPS columns of all frames are empty
+    pub no_gps: bool,
+    /// Run without the measurement info file, the measurement info columns are empty and
+    /// the acquisition times unknown
+    pub no_meas: bool,
+    /// Number of days decoded in parallel
+    pub jobs: usize,
+    /// Threads decoding and clustering the frames of a data stream, on the reading thread
+    /// when 1
+    pub decode_threads: usize,
+    /// Chunk size of the read-ahead thread of the data file, read directly when None
+    pub read_ahead: Option<ByteSize>,
+    /// Reconnection of the socket and object store inputs
+    pub retry: RetryPolicy,
+    /// Keep following the inputs for appended data instead of ending at their end
+    pub watch: Option<WatchPolicy>,
+    /// File the state of the conversion is saved to every `checkpoint_every` written frames
+    pub checkpoint: Option<String>,
+    pub checkpoint_every: usize,
+    /// Continue the conversion from the checkpoint, appending to the files of its day
+    pub resume: bool,
+    /// Free space of the output directory below which the run pauses or stops
+    pub min_free_space: Option<ByteSize>,
+    pub on_low_disk: LowDiskPolicy,
+    /// Persistent cumulative per-pixel dose map updated by the run
+    pub dose_map: Option<String>,
+    /// Persistent per-pixel firing statistics updated by the run
+    pub hot_pixel_stats: Option<String>,
+    /// File for the mask of the hot pixels of the firing statistics
+    pub hot_pixel_mask: Option<String>,
+    /// Fraction of the frames from which a pixel is hot
+    pub hot_pixel_fraction: f64,
+    /// Energy per iToT count in keV, used until per-pixel calibration is applied
+    pub kev_per_count: f64,
+    /// Directory for the SVG event displays of the most energetic clusters
+    pub event_display: Option<String>,
+    /// Number of clusters rendered per day
+    pub event_display_top: usize,
+    /// Number of most energetic clusters of each day and orbit in the `events_<date>.json`
+    /// catalogs of the output directory, no catalog when None
+    pub event_catalog: Option<usize>,
+    /// Handling of undecodable data lines, GPS and measurement info lines not matching the
+    /// schema are skipped unless it is abort
+    pub error_policy: ErrorPolicy,
+    /// Skipped GPS and measurement info lines quoted per file in the schema drift summary
+    pub drift_samples: usize,
+    /// Handling of pixel codes outside the lookup tables
+    pub sentinel_policy: SentinelPolicy,
+    /// Per-pixel ToA offset/skew applied to the second pixel values, from the payload profile
+    pub toa_calibration: Option<Arc<ToaCalibration>>,
+    /// Per-pixel a/b/c/t energy calibration of the iToT values
+    pub energy_calibration: Option<Arc<EnergyCalibration>>,
+    /// Pixels dropped by the decoder, from `--mask-file`
+    pub pixel_mask: Option<Arc<HotPixelMask>>,
+    /// Write the calibrated pixel energies in keV to the .clog instead of the iToT counts
+    pub clog_energy: bool,
+    /// Orientation of the pixel coordinates of the .clog files, records, cluster features,
+    /// event displays and previews
+    pub orientation: Orientation,
+    /// Rotation of detector to spacecraft body vectors for the cluster directions, from the
+    /// payload profile
+    pub mounting: Matrix3,
+    /// Temperature dependent per-pixel threshold of the hit pixels, from the payload profile
+    pub noise_model: Option<Arc<NoiseModel>>,
+    /// Threshold of the hit pixels derived from the statistics of each frame
+    pub adaptive_threshold: Option<AdaptiveThreshold>,
+    /// Clusters with bounding boxes separated by at most this many pixels are merged
+    pub merge_distance: Option<u8>,
+    /// Skip GPS records failing the position/attitude checks instead of flagging them
+    pub reject_invalid_gps: bool,
+    /// Replace single-sample GPS position outliers by the interpolation between their
+    /// neighbours before the matching
+    pub deglitch_gps: bool,
+    /// Attitude jump in degrees between consecutive GPS samples marking a maneuver, no
+    /// maneuver detection when None
+    pub max_attitude_jump: Option<f64>,
+    /// Handling of the attitude dependent products of frames matched to a maneuver
+    pub on_maneuver: ManeuverPolicy,
+    /// Element sets propagated to cross-check the GPS positions
+    pub tle: Option<Arc<TleSet>>,
+    /// Use the TLE position for frames without a current valid GPS record
+    pub tle_substitute: bool,
+    /// Keep about one in N frames, the kept frames carry the sampling weight N
+    pub decimate: usize,
+    /// Seed of the pseudo-random frame selection of the decimation
+    pub seed: u64,
+    /// Only the frames of this time range are decoded and written
+    pub window: TimeWindow,
+    /// Timestamp deciding the daily output file of a frame
+    pub day_split: DaySplit,
+    /// Numbering of the frames in the output files
+    pub frame_numbering: FrameNumbering,
+    /// Time of a frame for the GPS matching and the frame time outputs
+    pub frame_time_source: FrameTimeSource,
+    /// Also write the frames with their clusters as daily CBOR record streams
+    pub records: Option<RecordFormat>,
+    /// Also write the frames, clusters, pixels and matched records into a SQLite database
+    pub format: OutputFormat,
+    /// Frames of a row group of the Parquet cluster tables
+    pub chunk_frames: usize,
+    /// Also write the morphology features of the clusters as daily CSV tables
+    pub cluster_features: bool,
+    /// Thresholds of the cluster class column of the cluster feature tables
+    pub classification: ClassThresholds,
+    /// Clock of the stage timers
+    pub clock: Arc<dyn Clock>,
+    /// Columns of the .info metadata output
+    pub columns: Vec<&'static Column>,
+    /// Columns computed from the metadata columns, written after them
+    pub derived_columns: Vec<DerivedColumn>,
+    /// Reference frame of the exported positions, recorded in the .info header
+    pub position_frame: ReferenceFrame,
+    /// Nearest, interpolated or both positions in the GPS columns, recorded in the .info
+    /// header
+    pub gps_columns: GpsColumns,
+    /// Frame of the boresight and Euler angle columns, recorded in the .info header
+    pub pointing_frame: PointingFrame,
+    /// Rounding of the geodetic position and L-shell in all outputs
+    pub position_binning: PositionBinning,
+    /// Pixel packet layout forced on all frames; when None each frame is decoded with the
+    /// layout of its firmware release, or the detected one when the release table lacks it
+    pub firmware: Option<PacketLayout>,
+    /// Packet layout detected from the first frames of the data file
+    pub detected_layout: Option<PacketLayout>,
+    /// Handling of frames of a firmware release the decoder does not support
+    pub on_unsupported_firmware: FirmwarePolicy,
+    /// Decompress the payloads with the RLE header flag
+    pub rle: bool,
+    /// Hardware running the clustering
+    pub backend: ClusterBackend,
+    /// GPU labeler of the gpu backend, opened when the run starts
+    pub labeler: Option<Arc<dyn Labeler>>,
+    /// Regions of the pixel matrix reported separately in the aggregated products
+    pub rois: Vec<Roi>,
+    /// File for the per-ROI counts, dose and spectra of the run
+    pub roi_report: Option<String>,
+    /// CSV file listing the time windows recommended for reprocessing
+    pub reprocess_list: Option<String>,
+    /// Report of heavy clusters coinciding with housekeeping anomalies
+    pub see_report: Option<String>,
+    /// Cluster energy in keV above which clusters are correlated with the anomalies
+    pub see_threshold: f64,
+    /// Maximum time between a heavy cluster and an anomaly in s
+    pub see_window: f64,
+    /// File for the per-orbit duty cycle and missing measurement periods
+    pub duty_cycle: Option<String>,
+    /// Time without frames in s counted as a missing measurement period
+    pub duty_gap: f64,
+    /// File for the count and dose rate profiles folded by the orbit phase per day and week
+    pub phase_profile: Option<String>,
+    /// Number of orbit phase bins of the profiles
+    pub phase_bins: usize,
+    /// File for the daily frame temperatures by eclipse state and the fitted orbital thermal
+    /// cycle
+    pub thermal_report: Option<String>,
+    /// File for the payload modes inferred from the measurement cadence and the per-mode
+    /// frame totals and spectra
+    pub mode_report: Option<String>,
+    /// File for the cluster spectra per radiation region and cluster type
+    pub region_spectra: Option<String>,
+    /// Add the SAA and horn spectra minus the quiet region background to the region spectra
+    pub subtract_quiet: bool,
+    /// File for the daily absorbed dose and dose rates
+    pub dose_summary: Option<String>,
+    /// File for the daily dose equivalent and the LET spectrum
+    pub dose_equivalent: Option<String>,
+    /// Quality factor curve of the dose equivalent
+    pub quality_factor: QualityFactor,
+    /// Print a terminal heatmap of every Nth frame of the data file
+    pub preview_every: Option<usize>,
+    /// No progress bar
+    pub quiet: bool,
+    /// Also render the matrices of every written frame as images into frames_<date>
+    /// directories of the output directory
+    pub frame_images: Option<FrameImages>,
+    /// Pixet compatible frame files of each day
+    pub pixet: Option<PixetFormat>,
+    /// Convert twice, the first pass gathers the statistics of the days written into the
+    /// headers of their files by the second
+    pub two_pass: bool,
+    /// Statistics of the days written into the file headers, from the first pass
+    pub day_stats: Option<Arc<DayStatistics>>,
+}
+
+impl ProcessorConfig {
+    /// Checks the options against the payload profile
+    pub fn validate(&self) -> Result<()> {
+        if self.max_pix_count == 0 || self.max_pix_count > MATRIX_SIZE {
+            bail!(
+                "max pixel count {} outside the sensor range 1-{}",
+                self.max_pix_count,
+                MATRIX_SIZE
+            );
+        }
+        if let Some(max) = self.max_gps_staleness
+            && (max.is_nan() || max < 0.0)
+        {
+            bail!("max GPS staleness {} must be a non-negative time in s", max);
+        }
+        if let Some(max) = self.max_assembly_time
+            && !(max > 0.0 && max.is_finite())
+        {
+            bail!("max assembly time {} must be a positive time in s", max);
+        }
+        direction::validate_mounting(&self.mounting)?;
+        if let Some(max) = self.max_attitude_jump
+            && !(max > 0.0 && max.is_finite())
+        {
+            bail!(
+                "max attitude jump {} must be a positive angle in degrees",
+                max
+            );
+        }
+        if self.resume && self.checkpoint.is_none() {
+            bail!("resuming needs the checkpoint file");
+        }
+        if self.two_pass && (self.watch.is_some() || self.checkpoint.is_some()) {
+            bail!("a two-pass conversion can neither watch the inputs nor be checkpointed");
+        }
+        if self.pixet.is_some() && self.checkpoint.is_some() {
+            bail!("the Pixet frame files cannot be resumed from a checkpoint");
+        }
+        if self.format == OutputFormat::Sqlite && self.checkpoint.is_some() {
+            bail!("the SQLite database cannot be resumed from a checkpoint");
+        }
+        if self.format == OutputFormat::Parquet && self.checkpoint.is_some() {
+            bail!("the Parquet cluster tables cannot be resumed from a checkpoint");
+        }
+        if self.event_catalog == Some(0) {
+            bail!("the event catalog needs at least 1 cluster per day and orbit");
+        }
+        if self.chunk_frames == 0 {
+            bail!("the row groups of the cluster tables need at least 1 frame");
+        }
+        if self.checkpoint_every == 0 {
+            bail!("the checkpoint interval must be at least 1 frame");
+        }
+        if self.tle_substitute && self.tle.is_none() {
+            bail!("substituting TLE positions needs a TLE file");
+        }
+        if self.phase_bins == 0 {
+            bail!("the orbit phase profiles need at least 1 bin");
+        }
+        if !(self.hot_pixel_fraction > 0.0 && self.hot_pixel_fraction <= 1.0) {
+            bail!(
+                "hot pixel fraction {} must be above 0 and at most 1",
+                self.hot_pixel_fraction
+            );
+        }
+        if self.preview_every == Some(0) {
+            bail!("preview interval must be at least 1 frame");
+        }
+        if self.no_gps {
+            if self.max_attitude_jump.is_some() {
+                bail!("detecting maneuvers needs the GPS file");
+            }
+            if self.phase_profile.is_some() {
+                bail!("the orbit phase profiles need the GPS file");
+            }
+            if self.thermal_report.is_some() {
+                bail!("the thermal report needs the GPS file");
+            }
+            if self.gps_columns != GpsColumns::Nearest {
+                bail!("the interpolated GPS columns need the GPS file");
+            }
+        }
+        if self.no_meas {
+            if self.noise_model.is_some() {
+                bail!("the noise threshold needs the temperature of the measurement info file");
+            }
+            if self.see_report.is_some() || self.mode_report.is_some() {
+                bail!("the SEE and mode reports need the measurement info file");
+            }
+            if self.thermal_report.is_some() {
+                bail!("the thermal report needs the temperature of the measurement info file");
+            }
+            if self.day_split == DaySplit::Info || !self.frame_time_source.indexed() {
+                bail!("the info day split and frame times need the measurement info file");
+            }
+        }
+        Ok(())
+    }
+
+    /// Canonical text form of all options affecting the outputs, used for the repro hash
+    pub fn fingerprint(&self) -> String {
+        let bbox = match &self.bbox {
+            Some(b) => format!("{},{},{},{}", b.lat_min, b.lon_west, b.lat_max, b.lon_east),
+            None => String::from("none"),
+        };
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .map(|c| c.name.to_string())
+            .chain(self.derived_columns.iter().map(|c| c.to_string()))
+            .collect();
+        format!(
+            "max_pix_count={}\nbbox={}\nmax_gps_staleness={}\nmax_assembly_time={}\nbad_lines={}\nlut_sentinels={}\nmerge_distance={}\nreject_invalid_gps={}\ndecimate={}\nseed={}\nday_split={}\nframe_numbering={}\ncolumns={}\nposition_frame={}\ngps_columns={}\npointing_frame={}\nposition_binning={}\nfirmware={}\nrle={}\nbackend={}\nframe_time_source={}\ntoa_calibration={}\nquality_factor={}\nkev_per_count={}\nclassification={:?}\nmounting={:?}\nmax_attitude_jump={}\non_maneuver={}\ntle={}\ntle_substitute={}\nnoise_threshold={}\nadaptive_threshold={}\nenergy_calibration={}\npixel_mask={}\nclog_energy={}\norientation={}\nno_gps={}\nno_meas={}\ntwo_pass={}\ndeglitch_gps={}\nwindow={}\n",
+            self.max_pix_count,
+            bbox,
+            self.max_gps_staleness
+                .map(|max| max.to_string())
+                .unwrap_or_else(|| String::from("none")),
+            self.max_assembly_time
+                .map(|max| max.to_string())
+                .unwrap_or_else(|| String::from("none")),
+            self.error_policy,
+            self.sentinel_policy,
+            self.merge_distance
+                .map(|distance| distance.to_string())
+                .unwrap_or_else(|| String::from("none")),
+            self.reject_invalid_gps,
+            self.decimate,
+            self.seed,
+            self.day_split,
+            self.frame_numbering,
+            columns.join(","),
+            self.position_frame,
+            self.gps_columns,
+            self.pointing_frame,
+            self.position_binning,
+            self.firmware
+                .map(|layout| layout.to_string())
+                .unwrap_or_else(|| String::from("auto")),
+            self.rle,
+            self.backend,
+            self.frame_time_source,
+            self.toa_calibration
+                .as_ref()
+                .map(|calibration| calibration.digest.as_str())
+                .unwrap_or("none"),
+            self.quality_factor,
+            self.kev_per_count,
+            self.classification,
+            self.mounting,
+            self.max_attitude_jump
+                .map(|max| max.to_string())
+                .unwrap_or_else(|| String::from("none")),
+            self.on_maneuver,
+            self.tle
+                .as_ref()
+                .map(|tle| tle.digest.as_str())
+                .unwrap_or("none"),
+            self.tle_substitute,
+            self.noise_model
+                .as_ref()
+                .map(|model| model.digest.as_str())
+                .unwrap_or("none"),
+            self.adaptive_threshold
+                .map(|threshold| threshold.to_string())
+                .unwrap_or_else(|| String::from("none")),
+            self.energy_calibration
+                .as_ref()
+                .map(|calibration| calibration.digest.as_str())
+                .unwrap_or("none"),
+            self.pixel_mask
+                .as_ref()
+                .map(|mask| mask.digest.as_str())
+                .unwrap_or("none"),
+            self.clog_energy,
+            self.orientation,
+            self.no_gps,
+            self.no_meas,
+            self.two_pass,
+            self.deglitch_gps,
+            self.window
+        )
+    }
+
+    /// Configuration writing only the day files, without the reports, persistent maps and
+    /// optional files, e.g. for the first pass of a two-pass conversion
+    fn without_reports(&self) -> Self {
+        ProcessorConfig {
+            dose_map: None,
+            hot_pixel_stats: None,
+            hot_pixel_mask: None,
+            event_display: None,
+            event_catalog: None,
+            roi_report: None,
+            reprocess_list: None,
+            see_report: None,
+            duty_cycle: None,
+            phase_profile: None,
+            thermal_report: None,
+            mode_report: None,
+            region_spectra: None,
+            dose_summary: None,
+            dose_equivalent: None,
+            preview_every: None,
+            frame_images: None,
+            pixet: None,
+            records: None,
+            format: OutputFormat::Files,
+            cluster_features: false,
+            ..self.clone()
+        }
+    }
+
+    /// Configuration of an in-memory conversion: no files, no checkpoints and no output per
+    /// frame, the days are decoded sequentially
+    fn in_memory(&self) -> Self {
+        ProcessorConfig {
+            checkpoint: None,
+            resume: false,
+            min_free_space: None,
+            watch: None,
+            jobs: 1,
+            two_pass: false,
+            day_stats: None,
+            quiet: true,
+            ..self.without_reports()
+        }
+    }
+
+    /// Packet layout of the frames without a release known to the firmware table
+    pub fn layout(&self) -> PacketLayout {
+        self.firmware.or(self.detected_layout).unwrap_or_default()
+    }
+
+    /// Description of the frame sampling weights recorded in the product headers
+    pub fn weighting(&self) -> String {
+        if self.decimate > 1 {
+            format!("decimate 1/{} seed {}", self.decimate, self.seed)
+        } else {
+            String::from("none")
+        }
+    }
+}
+
+impl Default for ProcessorConfig {
+    fn default() -> Self {
+        ProcessorConfig {
+            max_pix_count: 1638,
+            bbox: None,
+            max_gps_staleness: None,
+            max_assembly_time: None,
+            no_gps: false,
+            no_meas: false,
+            jobs: 1,
+            decode_threads: 1,
+            read_ahead: None,
+            retry: RetryPolicy::default(),
+            watch: None,
+            checkpoint: None,
+            checkpoint_every: 1000,
+            resume: false,
+            min_free_space: None,
+            on_low_disk: LowDiskPolicy::default(),
+            dose_map: None,
+            hot_pixel_stats: None,
+            hot_pixel_mask: None,
+            hot_pixel_fraction: 0.2,
+            kev_per_count: 1.0,
+            event_display: None,
+            event_display_top: 10,
+            event_catalog: None,
+            error_policy: ErrorPolicy::default(),
+            drift_samples: 5,
+            sentinel_policy: SentinelPolicy::default(),
+            toa_calibration: None,
+            energy_calibration: None,
+            pixel_mask: None,
+            orientation: Orientation::Detector,
+            clog_energy: false,
+            mounting: direction::IDENTITY,
+            noise_model: None,
+            adaptive_threshold: None,
+            merge_distance: None,
+            reject_invalid_gps: false,
+            deglitch_gps: false,
+            max_attitude_jump: None,
+            on_maneuver: ManeuverPolicy::default(),
+            tle: None,
+            tle_substitute: false,
+            decimate: 1,
+            seed: 0,
+            window: TimeWindow::default(),
+            day_split: DaySplit::default(),
+            frame_numbering: FrameNumbering::default(),
+            frame_time_source: FrameTimeSource::default(),
+            records: None,
+            format: OutputFormat::default(),
+            chunk_frames: 1000,
+            cluster_features: false,
+            classification: ClassThresholds::default(),
+            clock: Arc::new(SystemClock::default()),
+            columns: columns::default_columns(),
+            derived_columns: Vec::new(),
+            position_frame: ReferenceFrame::default(),
+            gps_columns: GpsColumns::default(),
+            pointing_frame: PointingFrame::default(),
+            position_binning: PositionBinning::default(),
+            firmware: None,
+            detected_layout: None,
+            on_unsupported_firmware: FirmwarePolicy::default(),
+            rle: false,
+            backend: ClusterBackend::Cpu,
+            labeler: None,
+            rois: Vec::new(),
+            roi_report: None,
+            reprocess_list: None,
+            see_report: None,
+            see_threshold: 5000.0,
+            see_window: 30.0,
+            duty_cycle: None,
+            duty_gap: 120.0,
+            phase_profile: None,
+            phase_bins: 36,
+            thermal_report: None,
+            mode_report: None,
+            region_spectra: None,
+            subtract_quiet: false,
+            dose_summary: None,
+            dose_equivalent: None,
+            quality_factor: QualityFactor::default(),
+            preview_every: None,
+            quiet: false,
+            frame_images: None,
+            pixet: None,
+            two_pass: false,
+            day_stats: None,
+        }
+    }
+}
+
+/// Products of an in-memory conversion, see [`Processor::process_in_memory`]
+#[derive(Debug, Default, Clone)]
+pub struct CampaignProducts {
+    /// Frames of each day as in the record streams: metadata and derived columns by name
+    /// and the clusters with their features
+    pub frames: BTreeMap<String, Vec<FrameRecord>>,
+    /// Frame count, time coverage and dose of each day
+    pub days: DayStatistics,
+    /// Exposure of the written, skipped and decimated frames
+    pub ledger: ExposureLedger,
+}
+
+/// Bookkeeping of the exposure time of written and skipped frames
+#[derive(Debug, Default, Clone)]
+pub struct ExposureLedger {
+    pub written_frames: usize,
+    pub written_time: f64,
+    pub skipped_frames: usize,
+    pub skipped_time: f64,
+    /// Data lines dropped or salvaged under the error policy
+    pub bad_lines: usize,
+    /// Frames closed by the start of readout of the next frame (end of readout lost)
+    pub truncated_frames: usize,
+    /// Frames aborted as corrupt by the max assembly time
+    pub overlong_frames: usize,
+    /// Frames matched to a GPS record failing the validity checks
+    pub invalid_gps_frames: usize,
+    /// Frames without a GPS record within the max GPS staleness
+    pub stale_gps_frames: usize,
+    /// Pixels with a code outside the lookup tables
+    pub sentinel_pixels: usize,
+    /// Clusters merged into another by the merge distance
+    pub merged_clusters: usize,
+    /// Pixels at or below the noise threshold removed before the clustering
+    pub noise_pixels: usize,
+    /// Pixels below the adaptive threshold removed before the clustering
+    pub adaptive_pixels: usize,
+    /// Hits of the pixels of the pixel mask removed by the decoder
+    pub masked_pixels: usize,
+    /// Frames dropped by the decimation
+    pub decimated_frames: usize,
+    pub decimated_time: f64,
+    /// Frames with more hit pixels than the max pixel count, their acq_time is underestimated
+    pub over_max_pix_frames: usize,
+    /// Data lines skipped without decoding and decoded frames dropped outside the window
+    pub window_lines: usize,
+    pub window_frames: usize,
+    /// Frames of a firmware release missing from the compatibility table
+    pub unknown_firmware_frames: usize,
+    /// Frames of an unsupported firmware release decoded under the warn policy
+    pub unsupported_firmware_frames: usize,
+    /// GPS records whose outlier position was replaced by the interpolation
+    pub repaired_gps_records: usize,
+    /// Days whose .clog frames, .info rows and written frame count disagree
+    pub pairing_mismatches: Vec<String>,
+    /// Dates of the days whose files were finalized with a manifest
+    pub finalized_days: Vec<String>,
+    /// Frames matched to a GPS record of an attitude maneuver
+    pub maneuver_frames: usize,
+    /// Frames positioned by the TLE propagation instead of a GPS record
+    pub tle_substituted_frames: usize,
+    /// GPS records compared with the TLE propagation, sum and maximum of the residuals in m
+    pub tle_residual_frames: usize,
+    pub tle_residual_sum: f64,
+    pub tle_residual_max: f64,
+    /// GPS and measurement info lines skipped for not matching the schema
+    pub schema_drift: SchemaDrift,
+}
+
+impl ExposureLedger {
+    pub fn add_written(&mut self, acq_time: f64) {
+        self.written_frames += 1;
+        self.written_time += acq_time;
+    }
+
+    pub fn add_skipped(&mut self, acq_time: f64) {
+        self.skipped_frames += 1;
+        self.skipped_time += acq_time;
+    }
+
+    pub fn add_decimated(&mut self, acq_time: f64) {
+        self.decimated_frames += 1;
+        self.decimated_time += acq_time;
+    }
+
+    pub fn merge(&mut self, other: &ExposureLedger) {
+        self.written_frames += other.written_frames;
+        self.written_time += other.written_time;
+        self.skipped_frames += other.skipped_frames;
+        self.skipped_time += other.skipped_time;
+        self.bad_lines += other.bad_lines;
+        self.truncated_frames += other.truncated_frames;
+        self.overlong_frames += other.overlong_frames;
+        self.sentinel_pixels += other.sentinel_pixels;
+        self.merged_clusters += other.merged_clusters;
+        self.noise_pixels += other.noise_pixels;
+        self.adaptive_pixels += other.adaptive_pixels;
+        self.masked_pixels += other.masked_pixels;
+        self.schema_drift.merge(&other.schema_drift);
+        self.invalid_gps_frames += other.invalid_gps_frames;
+        self.stale_gps_frames += other.stale_gps_frames;
+        self.decimated_frames += other.decimated_frames;
+        self.decimated_time += other.decimated_time;
+        self.over_max_pix_frames += other.over_max_pix_frames;
+        self.window_lines += other.window_lines;
+        self.window_frames += other.window_frames;
+        self.unknown_firmware_frames += other.unknown_firmware_frames;
+        self.unsupported_firmware_frames += other.unsupported_firmware_frames;
+        self.repaired_gps_records += other.repaired_gps_records;
+        self.maneuver_frames += other.maneuver_frames;
+        self.tle_substituted_frames += other.tle_substituted_frames;
+        self.tle_residual_frames += other.tle_residual_frames;
+        self.tle_residual_sum += other.tle_residual_sum;
+        self.tle_residual_max = self.tle_residual_max.max(other.tle_residual_max);
+        self.pairing_mismatches
+            .extend(other.pairing_mismatches.iter().cloned());
+        self.finalized_days
+            .extend(other.finalized_days.iter().cloned());
+    }
+}
+
+/// Number of frames used for the packet layout autodetection
+const LAYOUT_DETECT_FRAMES: usize = 20;
+
+/// Writes one frame of the clusterlog: the frame line followed by one line per cluster,
+/// with the calibration the pixel values are the energies in keV
+pub fn write_clog_frame<W: std::io::Write>(
+    writer: &mut W,
+    number: usize,
+    timestamp: f64,
+    acq_time: f64,
+    clusters: &[Cluster],
+    calibration: Option<&EnergyCalibration>,
+    lend: &str,
+) -> Result<()> {
+    //Frame 1 (1484036406.350515, 85.762486 s)
+    write!(
+        writer,
+        "Frame {} ({}, {} s){}",
+        number,
+        timestamp,
+        Processor::fmt_acq_time(acq_time),
+        lend,
+    )?;
+
+    for cluster in clusters {
+        for pix in &cluster.pixels {
+            match calibration {
+                Some(calibration) => write!(
+                    writer,
+                    "[{}, {}, {:.3}, {}] ",
+                    pix.x,
+                    pix.y,
+                    calibration.energy(pix.y as usize * 256 + pix.x as usize, pix.value),
+                    pix.value2
+                )?,
+                None => write!(
+                    writer,
+                    "[{}, {}, {}, {}] ",
+                    pix.x, pix.y, pix.value, pix.value2
+                )?,
+            }
+        }
+        write!(writer, "{}", lend)?;
+    }
+    write!(writer, "{}", lend)?;
+
+    Ok(())
+}
+
+/// The processing loop always ends with this error once the data file is exhausted
+pub fn is_end_of_data(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<OnewebError>(),
+        Some(OnewebError::EndOfData | OnewebError::GpsMissing)
+    )
+}
 
 pub struct Processor {
+    config: ProcessorConfig,
     last_gps_data: GpsData,
+    /// Outlier repair of the GPS records read for the matching
+    deglitch: Option<GpsDeglitcher>,
+    /// Time ordered (timestamp, J2000 position) of the valid GPS records for the
+    /// interpolated GPS columns
+    gps_track: Option<Arc<orbit::Track>>,
+    /// Record read past the last matched frame, kept for the next frame
+    pending_gps_data: Option<GpsData>,
     last_info_data: MeasInfoData,
     frame_index: usize,
+    /// Frames of the data file before the decoded stream
+    frame_offset: usize,
+    /// Number of the current frame in the data file (1-based)
+    frame_number: usize,
+    /// Firmware release bytes of the decoded frames, reported once each
+    releases: BTreeSet<u8>,
+    ledger: ExposureLedger,
+    dose_map: Option<DoseMap>,
+    hot_pixels: Option<HotPixelStats>,
+    events: Option<EventSelection>,
+    catalog: Option<EventCatalog>,
+    /// SQLite database of `--format sqlite`
+    database: Option<Database>,
+    roi_report: Option<RoiReport>,
+    quality: QualityLog,
+    see: Option<SeeAnalysis>,
+    duty: Option<DutyCycle>,
+    modes: Option<ModeReport>,
+    phase: Option<PhaseFolding>,
+    thermal: Option<ThermalModel>,
+    region_spectra: Option<RegionSpectra>,
+    maneuvers: Option<Maneuvers>,
+    daily_dose: Option<DailyDose>,
+    dose_equivalent: Option<DoseEquivalentReport>,
+    timing: StageTimes,
+    repro_hash: String,
+    /// Energy calibration of the .clog pixel values in the output orientation
+    clog_calibration: Option<Arc<EnergyCalibration>>,
+    /// Checkpoint the conversion continues from
+    resume: Option<Checkpoint>,
+    /// Product files of the last saved or resumed checkpoint
+    checkpoint_products: Vec<PathBuf>,
+    progress: Progress,
+    /// Statistics of the written days, gathered in two-pass conversions
+    day_stats: DayStatistics,
+    /// Run as the first pass of a two-pass conversion, only the day statistics are gathered
+    first_pass: bool,
+    /// Products collected instead of writing files, see [`Processor::process_in_memory`]
+    products: Option<CampaignProducts>,
     lend: String,
 }
 
 impl Processor {
-    pub fn new() -> Self {
+    pub fn new(config: ProcessorConfig) -> Self {
+        let clog_calibration = config
+            .energy_calibration
+            .as_ref()
+            .filter(|_| config.clog_energy)
+            .map(|calibration| match config.orientation {
+                Orientation::Detector => calibration.clone(),
+                orientation => Arc::new(calibration.oriented(orientation)),
+            });
         Processor {
+            config,
             last_gps_data: GpsData {
                 ..Default::default()
             },
+            deglitch: None,
+            gps_track: None,
+            pending_gps_data: None,
             last_info_data: MeasInfoData {
                 ..Default::default()
             },
             frame_index: 0,
+            frame_offset: 0,
+            frame_number: 0,
+            releases: BTreeSet::new(),
+            ledger: ExposureLedger::default(),
+            dose_map: None,
+            hot_pixels: None,
+            events: None,
+            catalog: None,
+            database: None,
+            roi_report: None,
+            quality: QualityLog::default(),
+            see: None,
+            duty: None,
+            modes: None,
+            phase: None,
+            thermal: None,
+            region_spectra: None,
+            maneuvers: None,
+            daily_dose: None,
+            dose_equivalent: None,
+            timing: StageTimes::default(),
+            repro_hash: String::new(),
+            clog_calibration,
+            resume: None,
+            checkpoint_products: Vec::new(),
+            progress: Progress::default(),
+            day_stats: DayStatistics::new(),
+            first_pass: false,
+            products: None,
             lend: if env::consts::OS == "windows" {
                 String::from("\r\n")
             } else {
@@ -32,27 +900,124 @@ impl Processor {
         }
     }
 
-    fn find_next_closest_gps_data(
+    pub fn config(&self) -> &ProcessorConfig {
+        &self.config
+    }
+
+    pub fn timing(&self) -> &StageTimes {
+        &self.timing
+    }
+
+    pub fn ledger(&self) -> &ExposureLedger {
+        &self.ledger
+    }
+
+    pub fn day_stats(&self) -> &DayStatistics {
+        &self.day_stats
+    }
+
+    pub fn roi_report(&self) -> Option<&RoiReport> {
+        self.roi_report.as_ref()
+    }
+
+    /// Time windows with quality problems and the settings suggested for reprocessing them
+    pub fn reprocess_windows(&self) -> Vec<ReprocessWindow> {
+        self.quality.windows(&RunSettings {
+            salvage: self.config.error_policy == ErrorPolicy::Salvage,
+            reject_invalid_gps: self.config.reject_invalid_gps,
+        })
+    }
+
+    pub fn repro_hash(&self) -> &str {
+        &self.repro_hash
+    }
+
+    /// Sampling weight of the frame, None for frames dropped by the decimation. Frames are
+    /// selected by their timestamp so sequential and parallel runs keep the same frames
+    fn sampling_weight(&self, timestamp: f64) -> Option<f64> {
+        let n = self.config.decimate.max(1);
+        let key = utils::mix64(
+            ((timestamp * 1000.0).round() as u64).wrapping_add(utils::mix64(self.config.seed)),
+        );
+        key.is_multiple_of(n as u64).then_some(n as f64)
+    }
+
+    /// The matched GPS record is further from the frame time than the max GPS staleness
+    fn is_gps_stale(&self, frame: &Frame, gps_data: &GpsData) -> bool {
+        self.config.no_gps
+            || self
+                .config
+                .max_gps_staleness
+                .is_some_and(|max| (gps_data.timestamp - frame.timestamp).abs() > max)
+    }
+
+    /// The frame is matched to a GPS record of an attitude maneuver
+    fn is_maneuver(&self, frame: &Frame, gps_data: &GpsData) -> bool {
+        !gps_data.propagated
+            && !self.is_gps_stale(frame, gps_data)
+            && self
+                .maneuvers
+                .as_ref()
+                .is_some_and(|maneuvers| maneuvers.contains(gps_data.timestamp))
+    }
+
+    /// Distance in m between a valid GPS record and the TLE propagation at its time
+    fn tle_residual(&self, gps_data: &GpsData) -> Option<f64> {
+        if gps_data.propagated || !gps_data.is_valid() {
+            return None;
+        }
+        let tle = self.config.tle.as_ref()?.position(gps_data.timestamp)?;
+        let gps = [gps_data.j2000_x, gps_data.j2000_y, gps_data.j2000_z];
+        Some(
+            gps.iter()
+                .zip(tle)
+                .map(|(g, t)| (g - t).powi(2))
+                .sum::<f64>()
+                .sqrt(),
+        )
+    }
+
+    fn is_in_bbox(&self, gps_data: &GpsData) -> bool {
+        match &self.config.bbox {
+            Some(bbox) => {
+                let pos = [gps_data.j2000_x, gps_data.j2000_y, gps_data.j2000_z];
+                bbox.contains(&orbit::geodetic_from_j2000(pos, gps_data.timestamp))
+            }
+            None => true,
+        }
+    }
+
+    fn find_next_closest_gps_data<R: Read>(
         &mut self,
         proc: &GpsProcessor,
-        reader: &mut std::io::BufReader<std::fs::File>,
+        reader: &mut LineReader<R>,
         timestamp: f64,
     ) -> Result<GpsData> {
         loop {
             let last_data = self.last_gps_data.clone();
+            let next = match self.pending_gps_data.take() {
+                Some(data) => Some(data),
+                None => self.read_gps_data(proc, reader)?,
+            };
 
-            if let Ok(data) = proc.get_next_gps_data(reader) {
+            if let Some(data) = next {
+                if self.config.reject_invalid_gps && !data.is_valid() {
+                    continue;
+                }
                 let diff_last = (last_data.timestamp - timestamp).abs();
                 let diff_cur = (data.timestamp - timestamp).abs();
-                self.last_gps_data = data.clone();
 
                 if data.timestamp < timestamp {
+                    self.last_gps_data = data;
                     continue;
                 }
 
                 if diff_last < diff_cur {
+                    // a record after a GPS gap may still be the closest to later frames
+                    self.pending_gps_data = Some(data);
                     return Ok(last_data);
                 } else {
+                    self.last_gps_data = data.clone();
                     return Ok(data);
                 }
             } else {
@@ -61,22 +1026,67 @@ impl Processor {
                     self.last_gps_data.timestamp = 0.0;
                     return Ok(last_data);
                 } else {
-                    bail!("No more data available");
+                    return Err(OnewebError::GpsMissing.into());
+                }
+            }
+        }
+    }
+
+    /// Next record of the GPS file, after the outlier repair when enabled
+    fn read_gps_data<R: Read>(
+        &mut self,
+        proc: &GpsProcessor,
+        reader: &mut LineReader<R>,
+    ) -> Result<Option<GpsData>> {
+        loop {
+            let next = if self.config.error_policy == ErrorPolicy::Abort {
+                proc.get_next_gps_data(reader)?
+            } else {
+                proc.get_next_gps_data_tolerant(
+                    reader,
+                    &mut self.ledger.schema_drift,
+                    self.config.drift_samples,
+                )?
+            };
+            let Some(deglitch) = &mut self.deglitch else {
+                return Ok(next);
+            };
+            let checked = match next {
+                Some(data) => deglitch.push(data),
+                None => return Ok(deglitch.finish()),
+            };
+            if let Some(data) = checked {
+                if data.repaired {
+                    self.ledger.repaired_gps_records += 1;
+                    tracing::debug!(
+                        "GPS record {} repaired by interpolation",
+                        utils::format_time(data.timestamp)
+                    );
                 }
+                return Ok(Some(data));
             }
         }
     }
 
-    fn find_next_closest_info_data(
+    fn find_next_closest_info_data<R: Read>(
         &mut self,
         proc: &MeasInfoProcessor,
-        reader: &mut std::io::BufReader<std::fs::File>,
+        reader: &mut LineReader<R>,
         timestamp: f64,
     ) -> Result<MeasInfoData> {
         loop {
             let last_data = self.last_info_data.clone();
 
-            if let Ok(data) = proc.get_next_meas_info(reader) {
+            let next = if self.config.error_policy == ErrorPolicy::Abort {
+                proc.get_next_meas_info(reader)?
+            } else {
+                proc.get_next_meas_info_tolerant(
+                    reader,
+                    &mut self.ledger.schema_drift,
+                    self.config.drift_samples,
+                )?
+            };
+            if let Some(data) = next {
                 let diff_last = (last_data.timestamp - timestamp).abs();
                 let diff_cur = (data.timestamp - timestamp).abs();
                 self.last_info_data = data.clone();
@@ -96,15 +1106,18 @@ impl Processor {
                     self.last_gps_data.timestamp = 0.0;
                     return Ok(last_data);
                 } else {
-                    bail!("No mor edata available");
+                    return Err(OnewebError::MeasInfoMissing.into());
                 }
             }
         }
     }
 
-    fn calculate_acq_time(info_data: &MeasInfoData, max_pix_count: usize) -> f64 {
-        let pix_short = info_data.pixel_short as f64;
-        let pix_long = info_data.pixel_long as f64;
+    /// Estimates the frame acquisition time in s: the pixel counts of the 0.1 s and 1 s test
+    /// acquisitions give a linear count rate and the frame is acquired until `max_pix_count`
+    /// pixels are hit, at most 25 s
+    pub fn calculate_acq_time(info_data: &MeasInfoData, max_pix_count: usize) -> f64 {
+        let pix_short = info_data.pixel_short;
+        let pix_long = info_data.pixel_long;
         let time_short = 0.1;
         let time_long = 1.0;
         let a = (pix_long - pix_short) / (time_long - time_short);
@@ -120,7 +1133,7 @@ impl Processor {
         acq_time
     }
 
-    fn fmt_acq_time(acq_time: f64) -> String {
+    pub fn fmt_acq_time(acq_time: f64) -> String {
         let acq_time_fmt = format!("{:.6}", acq_time);
         if acq_time_fmt.contains('.') {
             acq_time_fmt
@@ -132,6 +1145,26 @@ impl Processor {
         }
     }
 
+    /// Prints a coarse log scale heatmap of the summed iToT of the frame
+    fn print_preview(&self, frame: &Frame) {
+        let itot = self.config.orientation.matrix(frame.itot());
+        let (rows, max) = tui::block_heatmap(&itot, PREVIEW_WIDTH, PREVIEW_HEIGHT);
+        let border = "-".repeat(PREVIEW_WIDTH);
+        println!(
+            "Preview of frame {} ({}): {} hit pixels, {} clusters, max cell {} iToT",
+            self.frame_number,
+            utils::format_time(frame.timestamp),
+            frame.itot().iter().filter(|&&v| v != 0).count(),
+            frame.clusters.len(),
+            max
+        );
+        println!("+{}+", border);
+        for row in rows {
+            println!("|{}|", row);
+        }
+        println!("+{}+", border);
+    }
+
     fn save_frame_to_clusterlog<R>(
         &mut self,
         frame: &Frame,
@@ -142,29 +1175,71 @@ impl Processor {
     where
         R: std::io::Write,
     {
-        //Frame 1 (1484036406.350515, 85.762486 s)
-        write!(
+        write_clog_frame(
             writer,
-            "Frame {} ({}, {} s){}",
-            self.frame_index + 1,
+            self.output_index(),
             info_data.timestamp,
-            Self::fmt_acq_time(acq_time),
+            // unknown without the measurement info
+            if self.config.no_meas {
+                f64::NAN
+            } else {
+                acq_time
+            },
+            &self.config.orientation.clusters(&frame.clusters),
+            self.clog_calibration.as_deref(),
             &self.lend,
-        )?;
+        )
+    }
 
-        for cluster in &frame.clusters {
-            for pix in &cluster.pixels {
-                write!(
-                    writer,
-                    "[{}, {}, {}, {}] ",
-                    pix.x, pix.y, pix.value, pix.value2
-                )?;
-            }
-            write!(writer, "{}", &self.lend)?;
+    /// Number of the current frame in the output files
+    fn output_index(&self) -> usize {
+        match self.config.frame_numbering {
+            FrameNumbering::File => self.frame_index + 1,
+            FrameNumbering::Global => self.frame_number,
         }
-        write!(writer, "{}", self.lend)?;
+    }
 
-        Ok(())
+    fn meta_row<'a>(
+        &self,
+        frame: &'a Frame,
+        info_data: &'a MeasInfoData,
+        gps_data: &'a GpsData,
+        acq_time: f64,
+    ) -> MetaRow<'a> {
+        MetaRow {
+            frame_index: self.output_index(),
+            frame_number: self.frame_number,
+            frame,
+            info: info_data,
+            gps: gps_data,
+            gps_missing: self.is_gps_stale(frame, gps_data),
+            info_missing: self.config.no_meas,
+            acq_time,
+            kev_per_count: self.config.kev_per_count,
+            quality_factor: self.config.quality_factor,
+            mounting: self.config.mounting,
+            maneuver: self.is_maneuver(frame, gps_data),
+            attitude_excluded: gps_data.propagated
+                || self.config.on_maneuver == ManeuverPolicy::Exclude
+                    && self.is_maneuver(frame, gps_data),
+            tle_residual: self.tle_residual(gps_data),
+            orientation: self.config.orientation,
+            binning: self.config.position_binning,
+            interpolated: self
+                .gps_track
+                .as_ref()
+                .and_then(|track| orbit::interpolate_track(track, frame.timestamp)),
+            gps_columns: self.config.gps_columns,
+            velocity: self
+                .gps_track
+                .as_ref()
+                .and_then(|track| orbit::track_velocity(track, gps_data.timestamp)),
+            pointing_frame: self.config.pointing_frame,
+            mode: self
+                .modes
+                .as_ref()
+                .map(|modes| modes.mode_at(info_data.timestamp)),
+        }
     }
 
     fn save_metadata<R>(
@@ -179,32 +1254,136 @@ impl Processor {
         R: std::io::Write,
     {
         if self.frame_index == 0 {
-            write!(
-                writer,
-                "Frame Index\tTimestamp\tFrame Timestamp\tTemp\tGPS J2000 X\tGPS J2000 Y\tGPS J2000 Z\tGPS Q Scalar\tGPS Q Vector 1\tGPS Q Vector 2\tGPS Q Vector 3\tacq_time\tpixels short\tpixels long{}",
-                self.lend,
-            )?;
+            let headers: Vec<&str> = self
+                .config
+                .columns
+                .iter()
+                .map(|c| c.header)
+                .chain(self.config.derived_columns.iter().map(|c| c.name.as_str()))
+                .collect();
+            write!(writer, "{}{}", headers.join("\t"), self.lend)?;
         }
-        write!(
+        let row = self.meta_row(frame, info_data, gps_data, acq_time);
+        let values: Vec<String> = self
+            .config
+            .columns
+            .iter()
+            .map(|c| c.format(&row))
+            .chain(self.config.derived_columns.iter().map(|c| c.format(&row)))
+            .collect();
+        write!(writer, "{}{}", values.join("\t"), self.lend)?;
+        Ok(())
+    }
+
+    /// Appends the frame to the record stream, before it is counted by save_to_files
+    fn save_record<R>(
+        &self,
+        frame: &Frame,
+        info_data: &MeasInfoData,
+        gps_data: &GpsData,
+        acq_time: f64,
+        format: RecordFormat,
+        writer: &mut std::io::BufWriter<R>,
+    ) -> Result<()>
+    where
+        R: std::io::Write,
+    {
+        records::write_frame(
             writer,
-            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}{}",
-            self.frame_index + 1,
+            format,
+            &self.frame_record(frame, info_data, gps_data, acq_time),
+        )
+    }
+
+    /// Inserts the frame into the database, before it is counted by save_to_files
+    fn save_to_database(
+        &mut self,
+        frame: &Frame,
+        info_data: &MeasInfoData,
+        gps_data: &GpsData,
+        acq_time: f64,
+        date: &str,
+    ) -> Result<()> {
+        let record = self.frame_record(frame, info_data, gps_data, acq_time);
+        let gps = (!self.config.no_gps).then_some(gps_data);
+        let info = (!self.config.no_meas).then_some(info_data);
+        match &mut self.database {
+            Some(database) => database.write_frame(date, frame.timestamp, &record, gps, info),
+            None => Ok(()),
+        }
+    }
+
+    fn frame_record(
+        &self,
+        frame: &Frame,
+        info_data: &MeasInfoData,
+        gps_data: &GpsData,
+        acq_time: f64,
+    ) -> FrameRecord {
+        let row = self.meta_row(frame, info_data, gps_data, acq_time);
+        let metadata = self
+            .config
+            .columns
+            .iter()
+            .map(|c| (c.name.to_string(), c.format(&row)))
+            .chain(
+                self.config
+                    .derived_columns
+                    .iter()
+                    .map(|c| (c.name.clone(), c.format(&row))),
+            );
+        records::frame_record(
+            self.output_index(),
             info_data.timestamp,
-            frame.timestamp,
-            info_data.temp,
-            gps_data.j2000_x,
-            gps_data.j2000_y,
-            gps_data.j2000_z,
-            gps_data.q_est_prop_bj_scalar,
-            gps_data.q_est_prop_bj_vector_1,
-            gps_data.q_est_prop_bj_vector_2,
-            gps_data.q_est_prop_bj_vector_3,
-            acq_time,
-            info_data.pixel_short,
-            info_data.pixel_long,
-            self.lend,
-        )?;
-        Ok(())
+            &row,
+            metadata,
+            &frame.clusters,
+        )
+    }
+
+    /// Counts the written frame in the statistics of its day
+    fn add_day_stats(&mut self, frame: &Frame, acq_time: f64, date: &str) {
+        let dose =
+            dosimetry::frame_dose_rate(frame, self.config.kev_per_count, acq_time) * acq_time;
+        self.day_stats
+            .entry(date.to_string())
+            .or_default()
+            .add_frame(frame.timestamp, acq_time, dose);
+    }
+
+    /// Keeps the frame in the products of an in-memory conversion
+    fn collect_frame(
+        &mut self,
+        frame: &Frame,
+        info_data: &MeasInfoData,
+        gps_data: &GpsData,
+        acq_time: f64,
+        date: &str,
+    ) {
+        let new_day = self
+            .products
+            .as_ref()
+            .is_some_and(|products| !products.frames.contains_key(date));
+        if new_day {
+            self.frame_index = 0;
+        }
+        let record = self.frame_record(frame, info_data, gps_data, acq_time);
+        let dose =
+            dosimetry::frame_dose_rate(frame, self.config.kev_per_count, acq_time) * acq_time;
+        self.frame_index += 1;
+        self.ledger.add_written(acq_time);
+        if let Some(products) = &mut self.products {
+            products
+                .frames
+                .entry(date.to_string())
+                .or_default()
+                .push(record);
+            products
+                .days
+                .entry(date.to_string())
+                .or_default()
+                .add_frame(frame.timestamp, acq_time, dose);
+        }
     }
 
     fn save_to_files<R>(
@@ -219,90 +1398,1944 @@ impl Processor {
     where
         R: std::io::Write,
     {
-        self.save_frame_to_clusterlog(&frame, &info_data, acq_time, clog_writer)?;
-        self.save_metadata(&frame, &info_data, &gps_data, acq_time, meta_writer)?;
+        self.save_frame_to_clusterlog(frame, info_data, acq_time, clog_writer)?;
+        self.save_metadata(frame, info_data, gps_data, acq_time, meta_writer)?;
         self.frame_index += 1;
         Ok(())
     }
 
+    /// Converts the input files, each of them may also be a directory or glob of chunks read
+    /// as one stream in time order, or another source (see [`source::parse`]). The GPS and
+    /// measurement info files are None when running without them (`no_gps`, `no_meas`).
     pub fn process_files(
         &mut self,
-        gps_file: &str,
-        meas_file: &str,
+        gps_file: Option<&str>,
+        meas_file: Option<&str>,
         data_file: &str,
         out_dir: &str,
-        max_pix_count: usize,
     ) -> Result<(), anyhow::Error> {
-        let gps_processor = GpsProcessor::new();
-        let info_processor = MeasInfoProcessor::new();
-        let mut data_processor = DataProcessor::new();
-
-        let gps_file = std::fs::File::open(gps_file)?;
-        let meas_file = std::fs::File::open(meas_file)?;
-        let data_file = std::fs::File::open(data_file)?;
-        let mut gps_reader = std::io::BufReader::new(gps_file);
-        let mut meas_reader = std::io::BufReader::new(meas_file);
-        let mut data_reader = std::io::BufReader::new(data_file);
-        let mut clog_write: Option<std::io::BufWriter<std::fs::File>> = None;
-        let mut meta_write: Option<std::io::BufWriter<std::fs::File>> = None;
-
-        let dir_path = Path::new(out_dir);
-        let mut idx = 0;
-        let mut date = String::from("");
-
-        loop {
-            let frame = data_processor.get_next_frame(&mut data_reader)?;
-
-            let gps_data =
-                self.find_next_closest_gps_data(&gps_processor, &mut gps_reader, frame.timestamp)?;
+        if gps_file.is_none() != self.config.no_gps || meas_file.is_none() != self.config.no_meas {
+            bail!("the GPS and measurement info files must be given unless running without them");
+        }
+        let retry = self.config.retry;
+        // the GPS and measurement info are read more than once
+        let open = |file: Option<&str>, name: &str| match file {
+            Some(file) => source::spool(source::parse(file, retry)?),
+            None => Ok(source::missing(name)),
+        };
+        let gps = open(gps_file, "GPS file")?;
+        let meas = open(meas_file, "measurement info file")?;
+        let data = source::parse(data_file, retry)?;
+        self.process_sources(&gps, &meas, &data, out_dir)
+    }
 
-            let info_data = self.find_next_closest_info_data(
-                &info_processor,
-                &mut meas_reader,
-                frame.timestamp,
-            )?;
+    /// Converts the inputs read from the sources, the GPS and measurement info sources must
+    /// be seekable
+    pub fn process_sources(
+        &mut self,
+        gps: &Arc<dyn InputSource>,
+        meas: &Arc<dyn InputSource>,
+        data: &Arc<dyn InputSource>,
+        out_dir: &str,
+    ) -> Result<()> {
+        self.resolve_backend();
+        let mut inputs = Vec::new();
+        let given = [
+            (gps, !self.config.no_gps),
+            (meas, !self.config.no_meas),
+            (data, true),
+        ];
+        for source in given.iter().filter(|(_, given)| *given).map(|(s, _)| s) {
+            let files = source.files()?;
+            if files.is_empty() {
+                tracing::warn!(
+                    "the repro hash does not cover the content of {}",
+                    source.name()
+                );
+            }
+            inputs.extend(files);
+        }
+        self.repro_hash = repro::run_hash(&self.config.fingerprint(), &inputs)?;
+        if self.config.two_pass && !self.first_pass && self.config.day_stats.is_none() {
+            let day_stats = self.gather_day_stats(gps, meas, data, out_dir)?;
+            self.config.day_stats = Some(Arc::new(day_stats));
+        }
+        self.day_stats.clear();
 
-            idx += 1;
+        let result = self.process_inputs(gps, meas, data, out_dir);
+        self.progress.finish();
+        if let Err(e) = &result
+            && is_end_of_data(e)
+        {
+            self.check_day_stats();
+            self.finish()?;
+            // the run is complete, a later --resume would convert nothing
+            if let Some(path) = &self.config.checkpoint {
+                let _ = std::fs::remove_file(path);
+                for product in &self.checkpoint_products {
+                    let _ = std::fs::remove_file(product);
+                }
+            }
+        }
+        result
+    }
 
-            let info_date = chrono::Utc
-                .timestamp_opt(info_data.timestamp as i64, 0 as u32)
-                .unwrap();
+    /// Converts CSV contents held in memory and returns the products instead of writing
+    /// files, for small datasets, tests and embedding; the reports, checkpoints and optional
+    /// files of the configuration are not produced. The GPS and measurement info contents
+    /// are ignored when running without them (`no_gps`, `no_meas`).
+    pub fn process_in_memory(
+        &mut self,
+        gps: &[u8],
+        meas: &[u8],
+        data: &[u8],
+    ) -> Result<CampaignProducts> {
+        self.config = self.config.in_memory();
+        self.resolve_backend();
+        self.ledger = ExposureLedger::default();
+        self.products = Some(CampaignProducts::default());
+        let result = self.process_inputs(
+            &source::memory("GPS file", gps),
+            &source::memory("measurement info file", meas),
+            &source::memory("data file", data),
+            "",
+        );
+        self.progress.finish();
+        let products = self.products.take().unwrap_or_default();
+        match result {
+            Err(e) if !is_end_of_data(&e) => Err(e),
+            _ => Ok(CampaignProducts {
+                ledger: self.ledger.clone(),
+                ..products
+            }),
+        }
+    }
 
-            let cur_date = info_date.format("%Y-%m-%d").to_string();
-            let acq_time = Self::calculate_acq_time(&info_data, max_pix_count);
+    /// First pass of a two-pass conversion: decodes and matches the frames for the statistics
+    /// of the days without writing any file
+    fn gather_day_stats(
+        &mut self,
+        gps: &Arc<dyn InputSource>,
+        meas: &Arc<dyn InputSource>,
+        data: &Arc<dyn InputSource>,
+        out_dir: &str,
+    ) -> Result<DayStatistics> {
+        if !data.seekable() {
+            bail!(
+                "the stream {} cannot be read twice for a two-pass conversion",
+                data.name()
+            );
+        }
+        tracing::info!("First pass, gathering the statistics of the days ...");
+        let mut first = Processor::new(ProcessorConfig {
+            min_free_space: None,
+            ..self.config.without_reports()
+        });
+        first.first_pass = true;
+        let result = first.process_sources(gps, meas, data, out_dir);
+        if let Err(e) = result
+            && !is_end_of_data(&e)
+        {
+            return Err(e.context("first pass"));
+        }
+        // the packet layout is detected once
+        self.config.detected_layout = first.config.detected_layout;
+        tracing::info!("Second pass, writing the outputs ...");
+        Ok(first.day_stats)
+    }
 
-            if clog_write.is_none() || meta_write.is_none() || date != cur_date {
-                // Reuse existing files
-                self.frame_index = 0;
-                let time_suffix = info_date.format("%Y-%m-%d").to_string();
-                let clog_file_path = dir_path.join(format!("data_{}.clog", time_suffix));
-                let meta_file_path = dir_path.join(format!("data_{}.info", time_suffix));
-                let clog_file = std::fs::File::create(&clog_file_path)?;
-                let meta_file = std::fs::File::create(&meta_file_path)?;
-                clog_write = Some(std::io::BufWriter::new(clog_file));
-                meta_write = Some(std::io::BufWriter::new(meta_file));
-                date = cur_date;
+    /// Warns about the days whose written content differs from the first pass statistics in
+    /// their headers, e.g. when an input changed between the passes
+    fn check_day_stats(&self) {
+        let Some(expected) = self
+            .config
+            .day_stats
+            .as_ref()
+            .filter(|_| self.config.two_pass)
+        else {
+            return;
+        };
+        for (date, stats) in &self.day_stats {
+            if expected.get(date) != Some(stats) {
+                tracing::warn!(
+                    "the files of {} do not match the statistics of the first pass in their headers",
+                    date
+                );
             }
+        }
+    }
 
-            if clog_write.is_some() && meta_write.is_some() {
-                let clog_writer = clog_write.as_mut().unwrap();
-                let meta_writer = meta_write.as_mut().unwrap();
-                self.save_to_files(
-                    &frame,
-                    &info_data,
-                    &gps_data,
-                    acq_time,
-                    clog_writer,
-                    meta_writer,
-                )?;
+    /// Opens the GPU of the gpu backend, falls back to the CPU when it is not available
+    fn resolve_backend(&mut self) {
+        if self.config.backend != ClusterBackend::Gpu || self.config.labeler.is_some() {
+            return;
+        }
+        match gpu::open() {
+            Ok(labeler) => {
+                tracing::info!("Clustering on the GPU {:?}", labeler);
+                self.config.labeler = Some(labeler);
+            }
+            Err(e) => {
+                tracing::warn!("GPU backend not available ({:#}), clustering on the CPU", e);
+                self.config.backend = ClusterBackend::Cpu;
             }
+        }
+    }
 
-            println!(
-                "Processing frame {} ({}, {} s) ...",
-                idx,
-                info_date,
-                Self::fmt_acq_time(acq_time)
+    /// Detects the packet layout of the frames without a known firmware release from the
+    /// release or the first frames of the data file, unless a layout is forced
+    fn resolve_firmware(&mut self, data: &Arc<dyn InputSource>) -> Result<()> {
+        if self.config.firmware.is_none() && self.config.detected_layout.is_none() {
+            if !data.seekable() {
+                bail!(
+                    "the packet layout cannot be detected from the stream {}, give --firmware",
+                    data.name()
+                );
+            }
+            let detection = DataProcessor::detect_layout(
+                &mut LineReader::from_source(data, 0, 0)?,
+                LAYOUT_DETECT_FRAMES,
+            );
+            if detection.frames == 0 && self.config.watch.is_some() {
+                bail!(
+                    "no frames in {} yet to detect the packet layout from, give --firmware",
+                    data.name()
+                );
+            }
+            let known = detection
+                .release
+                .and_then(firmware::release)
+                .and_then(|release| release.layout.map(|layout| (release, layout)));
+            if let Some((release, layout)) = known {
+                tracing::info!(
+                    "Firmware release {} uses the {} packet layout",
+                    release,
+                    layout
+                );
+                self.config.detected_layout = Some(layout);
+                return Ok(());
+            }
+            tracing::info!(
+                "Detected {} packet layout from {} frames (neighbour fraction standard {:.3}, swapped {:.3})",
+                detection.layout,
+                detection.frames,
+                detection.standard_score,
+                detection.swapped_score
             );
+            self.config.detected_layout = Some(detection.layout);
         }
+        Ok(())
+    }
+
+    /// Checks the firmware release of the frame header against the decoder, the frames of
+    /// an unsupported release stop the run unless the policy is warn
+    fn check_firmware(&mut self, frame: &Frame) -> Result<()> {
+        let Some(byte) = frame.release() else {
+            return Ok(());
+        };
+        let first = self.releases.insert(byte);
+        match firmware::check(byte, self.config.firmware) {
+            Compatibility::Supported(release) => {
+                if first {
+                    tracing::debug!("firmware release {}", release);
+                }
+            }
+            Compatibility::Unknown(byte) => {
+                self.ledger.unknown_firmware_frames += 1;
+                if first {
+                    tracing::warn!(
+                        "unknown firmware release {:#04x} from frame {} on, its frames may be decoded wrongly",
+                        byte,
+                        self.frame_number
+                    );
+                }
+            }
+            Compatibility::Unsupported(release, reason) => {
+                if self.config.on_unsupported_firmware == FirmwarePolicy::Abort {
+                    bail!(
+                        "frame {} is from the unsupported firmware release {}: {} (--on-unsupported-firmware warn decodes it anyway)",
+                        self.frame_number,
+                        release,
+                        reason
+                    );
+                }
+                self.ledger.unsupported_firmware_frames += 1;
+                if first {
+                    tracing::warn!(
+                        "frame {} and the next ones are from the unsupported firmware release {}: {}",
+                        self.frame_number,
+                        release,
+                        reason
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a single frame of the data file into standalone files in out_dir: raw
+    /// payload, iToT and event matrices, cluster log, metadata and an annotated summary
+    pub fn extract_frame(
+        &mut self,
+        gps_file: &str,
+        meas_file: &str,
+        data_file: &str,
+        selector: FrameSelector,
+        out_dir: &str,
+    ) -> Result<()> {
+        let (entries, _) =
+            index::index_frames(&mut std::io::BufReader::new(InputFile::open(data_file)?))?;
+        let Some(entry) = selector.select(&entries) else {
+            bail!(
+                "no {} in {} ({} frames)",
+                selector,
+                data_file,
+                entries.len()
+            );
+        };
+        let frame_no = entries.iter().position(|e| e == entry).unwrap_or(0) + 1;
+
+        let mut data_reader = LineReader::open_at(data_file, entry.offset, entry.line_no - 1)?;
+        let location = format!("{}:{}", data_reader.source(), entry.line_no);
+        self.resolve_firmware(&source::parse(data_file, RetryPolicy::NONE)?)?;
+        let mut data_processor = DataProcessor::new();
+        data_processor.layout = self.config.layout();
+        data_processor.release_layouts = self.config.firmware.is_none();
+        data_processor.rle = self.config.rle;
+        data_processor.sentinel_policy = self.config.sentinel_policy;
+        data_processor.toa_calibration = self.config.toa_calibration.clone();
+        data_processor.energy_calibration = self.config.energy_calibration.clone();
+        data_processor.mask = self.config.pixel_mask.clone();
+        data_processor.merge_distance = self.config.merge_distance;
+        data_processor.max_assembly_time = self.config.max_assembly_time;
+        data_processor.clock = self.config.clock.clone();
+        let mut frame = data_processor.get_next_frame(&mut data_reader)?;
+        let gps_data = self.find_next_closest_gps_data(
+            &GpsProcessor::new(),
+            &mut LineReader::open(gps_file)?,
+            frame.timestamp,
+        )?;
+        let info_data = self.find_next_closest_info_data(
+            &MeasInfoProcessor::new(),
+            &mut LineReader::open(meas_file)?,
+            frame.timestamp,
+        )?;
+        let acq_time = Self::calculate_acq_time(&info_data, self.config.max_pix_count);
+        if self.apply_thresholds(&mut frame, info_data.temp) > 0 {
+            data_processor.clusterize_frame(&mut frame);
+        }
+
+        let dir_path = Path::new(out_dir);
+        std::fs::write(dir_path.join("frame.bin"), &frame.raw)?;
+        utils::save_ascii_matrix(&dir_path.join("frame_itot.txt"), frame.itot(), 256)?;
+        utils::save_ascii_matrix(&dir_path.join("frame_event.txt"), frame.event(), 256)?;
+        let mut clog_writer =
+            std::io::BufWriter::new(std::fs::File::create(dir_path.join("frame.clog"))?);
+        let mut meta_writer =
+            std::io::BufWriter::new(std::fs::File::create(dir_path.join("frame.info"))?);
+        self.frame_index = 0;
+        self.frame_number = frame_no;
+        self.save_to_files(
+            &frame,
+            &info_data,
+            &gps_data,
+            acq_time,
+            &mut clog_writer,
+            &mut meta_writer,
+        )?;
+
+        let position = orbit::geodetic_from_j2000(
+            [gps_data.j2000_x, gps_data.j2000_y, gps_data.j2000_z],
+            gps_data.timestamp,
+        );
+        let hit_pixels = frame.itot().iter().filter(|&&v| v != 0).count();
+        let summary = [
+            format!("Frame: {} of {}", frame_no, entries.len()),
+            format!("Source: {}", location),
+            format!("Frame time: {}", utils::format_time(frame.timestamp)),
+            format!("Info time: {}", utils::format_time(info_data.timestamp)),
+            format!("GPS time: {}", utils::format_time(gps_data.timestamp)),
+            format!("Acquisition time: {} s", Self::fmt_acq_time(acq_time)),
+            format!("Temp: {}", info_data.temp),
+            format!("Pixels short: {}", info_data.pixel_short),
+            format!("Pixels long: {}", info_data.pixel_long),
+            format!("Error ID: {}", info_data.error_id),
+            format!(
+                "GPS problems: {}",
+                if gps_data.is_valid() {
+                    "none".to_string()
+                } else {
+                    gps_data.problems.join(", ")
+                }
+            ),
+            format!("Latitude: {:.4} deg", position.latitude),
+            format!("Longitude: {:.4} deg", position.longitude),
+            format!("Altitude: {:.1} km", position.altitude / 1000.0),
+            format!("Raw payload: {} bytes", frame.raw.len()),
+            format!("Hit pixels: {}", hit_pixels),
+            format!(
+                "Invalid pixels: {} ({})",
+                frame.invalid().count(),
+                self.config.sentinel_policy
+            ),
+            format!("Clusters: {}", frame.clusters.len()),
+        ];
+        std::fs::write(
+            dir_path.join("frame.txt"),
+            summary.join(&self.lend) + &self.lend,
+        )?;
+        Ok(())
+    }
+
+    /// Reads all GPS records, with the schema drift handling of the decoding
+    fn read_gps(&mut self, gps: &Arc<dyn InputSource>) -> Result<Vec<GpsData>> {
+        if self.config.no_gps {
+            return Ok(Vec::new());
+        }
+        let mut reader = LineReader::from_source(gps, 0, 0)?;
+        if self.config.error_policy == ErrorPolicy::Abort {
+            return GpsProcessor::new().read_all(&mut reader);
+        }
+        GpsProcessor::new().read_all_tolerant(
+            &mut reader,
+            &mut self.ledger.schema_drift,
+            self.config.drift_samples,
+        )
+    }
+
+    /// Reads all measurement info records, with the schema drift handling of the decoding
+    fn read_meas_info(&mut self, meas: &Arc<dyn InputSource>) -> Result<Vec<MeasInfoData>> {
+        if self.config.no_meas {
+            return Ok(Vec::new());
+        }
+        let mut reader = LineReader::from_source(meas, 0, 0)?;
+        if self.config.error_policy == ErrorPolicy::Abort {
+            return MeasInfoProcessor::new().read_all(&mut reader);
+        }
+        MeasInfoProcessor::new().read_all_tolerant(
+            &mut reader,
+            &mut self.ledger.schema_drift,
+            self.config.drift_samples,
+        )
+    }
+
+    /// Removes the pixels below the noise model and the adaptive threshold from the frame,
+    /// returns their count
+    fn apply_thresholds(&self, frame: &mut Frame, temp: f64) -> usize {
+        let mut removed = 0;
+        if let Some(noise) = &self.config.noise_model {
+            removed += noise.apply(frame, temp);
+        }
+        if let Some(adaptive) = self.config.adaptive_threshold {
+            removed += adaptive.apply(frame);
+        }
+        removed
+    }
+
+    /// Persists the products accumulated over the whole run
+    fn finish(&mut self) -> Result<()> {
+        if let (Some(path), Some(dose_map)) = (&self.config.dose_map, &self.dose_map) {
+            if dose_map.skipped > 0 {
+                tracing::warn!(
+                    "{} frames were in the dose map {} already and are not added again",
+                    dose_map.skipped,
+                    path
+                );
+            }
+            dose_map.save(Path::new(path), &self.config.rois)?;
+        }
+        if let (Some(path), Some(stats)) = (&self.config.hot_pixel_stats, &self.hot_pixels) {
+            stats.save(Path::new(path))?;
+            if let Some(mask) = &self.config.hot_pixel_mask {
+                stats.save_mask(Path::new(mask), self.config.hot_pixel_fraction)?;
+            }
+        }
+        if let (Some(path), Some(report)) = (&self.config.roi_report, &self.roi_report) {
+            report.save(Path::new(path))?;
+        }
+        if let Some(path) = &self.config.reprocess_list {
+            quality::save_windows(&self.reprocess_windows(), Path::new(path))?;
+        }
+        if let (Some(path), Some(see)) = (&self.config.see_report, &self.see) {
+            see.save(Path::new(path))?;
+        }
+        if let (Some(dir), Some(events)) = (&self.config.event_display, &self.events) {
+            events.save(Path::new(dir), self.config.kev_per_count)?;
+        }
+        if let (Some(path), Some(duty)) = (&self.config.duty_cycle, &self.duty) {
+            duty.save(Path::new(path))?;
+        }
+        if let (Some(path), Some(modes)) = (&self.config.mode_report, &self.modes) {
+            modes.save(Path::new(path))?;
+        }
+        if let (Some(path), Some(phase)) = (&self.config.phase_profile, &self.phase) {
+            phase.save(Path::new(path))?;
+        }
+        if let (Some(path), Some(thermal)) = (&self.config.thermal_report, &self.thermal) {
+            thermal.save(Path::new(path))?;
+        }
+        if let (Some(path), Some(spectra)) = (&self.config.region_spectra, &self.region_spectra) {
+            spectra.save(Path::new(path))?;
+        }
+        if let (Some(path), Some(daily)) = (&self.config.dose_summary, &self.daily_dose) {
+            daily.save(Path::new(path))?;
+        }
+        if let (Some(path), Some(report)) = (&self.config.dose_equivalent, &self.dose_equivalent) {
+            report.save(Path::new(path))?;
+        }
+        Ok(())
+    }
+
+    fn process_inputs(
+        &mut self,
+        gps: &Arc<dyn InputSource>,
+        meas: &Arc<dyn InputSource>,
+        data: &Arc<dyn InputSource>,
+        out_dir: &str,
+    ) -> Result<()> {
+        if let Some(path) = &self.config.dose_map {
+            let mut dose_map = DoseMap::load(Path::new(path))?;
+            dose_map.use_weighting(&self.config.weighting());
+            self.dose_map = Some(dose_map);
+        }
+        if let Some(path) = &self.config.hot_pixel_stats {
+            self.hot_pixels = Some(HotPixelStats::load(Path::new(path))?);
+        }
+        if let Some(min_free) = self.config.min_free_space {
+            DiskGuard::new(Path::new(out_dir), min_free, self.config.on_low_disk).check()?;
+        }
+        self.resolve_firmware(data)?;
+        // the size of a watched file is not final
+        let size = data.size().filter(|_| self.config.watch.is_none());
+        self.progress = Progress::new(size, self.config.quiet);
+        if let (true, Some(path)) = (self.config.resume, &self.config.checkpoint) {
+            let checkpoint = Checkpoint::load(Path::new(path))?;
+            if checkpoint.repro_hash != self.repro_hash {
+                bail!(
+                    "the checkpoint {} is of a run with other inputs or settings (repro hash {})",
+                    path,
+                    checkpoint.repro_hash
+                );
+            }
+            if !data.seekable() {
+                bail!("the stream {} cannot be resumed", data.name());
+            }
+            tracing::info!(
+                "Resuming after frame {} at {}:{}",
+                checkpoint.frames,
+                data.name(),
+                checkpoint.data.line_no
+            );
+            self.progress.resume_at(checkpoint.data.offset);
+            self.resume = Some(checkpoint);
+        }
+        if self.config.see_report.is_some() {
+            let mut see = SeeAnalysis::new(self.config.see_threshold, self.config.see_window);
+            let records = self.read_meas_info(meas)?;
+            see.anomalies = see::find_anomalies(&records);
+            self.see = Some(see);
+        }
+        if let Some(max_jump) = self.config.max_attitude_jump {
+            let track = self.read_gps(gps)?;
+            self.maneuvers = Some(Maneuvers::detect(&track, max_jump));
+        }
+        if self.config.duty_cycle.is_some() {
+            let mut duty = DutyCycle::new(self.config.duty_gap);
+            duty.use_track(&self.read_gps(gps)?);
+            self.duty = Some(duty);
+        }
+        if self.config.phase_profile.is_some() {
+            let track = self.read_gps(gps)?;
+            self.phase = Some(PhaseFolding::new(self.config.phase_bins, &track));
+        }
+        if self.config.thermal_report.is_some() {
+            self.thermal = Some(ThermalModel::new(&self.read_gps(gps)?));
+        }
+        let pointing = self
+            .config
+            .columns
+            .iter()
+            .any(|c| columns::POINTING_COLUMNS.contains(&c.name));
+        if self.config.gps_columns != GpsColumns::Nearest || pointing && !self.config.no_gps {
+            let mut records = self.read_gps(gps)?;
+            if self.config.deglitch_gps {
+                let mut deglitcher = GpsDeglitcher::default();
+                records = records
+                    .into_iter()
+                    .filter_map(|r| deglitcher.push(r))
+                    .collect();
+                records.extend(deglitcher.finish());
+            }
+            self.gps_track = Some(Arc::new(phase::track(&records)));
+        }
+        if self.config.region_spectra.is_some() {
+            self.region_spectra = Some(RegionSpectra::new(self.config.subtract_quiet));
+        }
+        if self.config.mode_report.is_some() || self.config.columns.iter().any(|c| c.name == "mode")
+        {
+            let times = self
+                .read_meas_info(meas)?
+                .iter()
+                .map(|r| r.timestamp)
+                .collect::<Vec<_>>();
+            self.modes = Some(ModeReport::new(mode::detect_segments(&times)));
+        }
+        if self.config.dose_summary.is_some() {
+            self.daily_dose = Some(DailyDose::default());
+        }
+        if self.config.dose_equivalent.is_some() {
+            self.dose_equivalent = Some(DoseEquivalentReport::new(self.config.quality_factor));
+        }
+        if !self.config.rois.is_empty() {
+            self.roi_report = Some(RoiReport::new(&self.config.rois, &self.config.weighting()));
+        }
+        if self.config.event_display.is_some() {
+            self.events = Some(EventSelection::new(self.config.event_display_top));
+        }
+        if self.config.format == OutputFormat::Sqlite {
+            self.database = Some(Database::create(&Path::new(out_dir).join(DATABASE_NAME))?);
+        }
+        if let Some(top_k) = self.config.event_catalog {
+            let mut catalog = EventCatalog::new(top_k);
+            if !self.config.no_gps {
+                catalog.use_track(&self.read_gps(gps)?);
+            }
+            self.catalog = Some(catalog);
+        }
+
+        let exact: Vec<&str> = self
+            .config
+            .columns
+            .iter()
+            .map(|c| c.name)
+            .filter(|name| columns::POSITION_COLUMNS.contains(name))
+            .collect();
+        if !self.config.position_binning.is_none() && !exact.is_empty() {
+            tracing::warn!(
+                "the {} columns are not binned and give the exact position",
+                exact.join(", ")
+            );
+        }
+
+        if self.config.jobs > 1 && self.config.watch.is_some() {
+            tracing::warn!(
+                "the watched data file is still growing, the days are decoded sequentially"
+            );
+        } else if self.config.jobs > 1 && self.config.checkpoint.is_some() {
+            tracing::warn!("with checkpoints the days are decoded sequentially");
+        } else if self.config.jobs > 1 && !self.config.frame_time_source.indexed() {
+            tracing::warn!(
+                "the {} frame time is only known after decoding, the days are decoded sequentially",
+                self.config.frame_time_source
+            );
+        } else if self.config.jobs > 1 && self.config.format == OutputFormat::Sqlite {
+            tracing::warn!(
+                "the SQLite database is written by one thread, the days are decoded sequentially"
+            );
+        } else if self.config.jobs > 1 && !self.config.window.is_none() {
+            tracing::warn!("with a time window the days are decoded sequentially");
+        } else if self.config.jobs > 1 && !data.seekable() {
+            tracing::warn!(
+                "the stream {} cannot be indexed, the days are decoded sequentially",
+                data.name()
+            );
+        } else if self.config.jobs > 1 {
+            let (entries, file_len) = index::index_frames(&mut std::io::BufReader::new(
+                SourceReader::open(data.clone(), 0)?,
+            ))?;
+            // without the measurement info the frames are split by their own time
+            let info_times = if self.config.no_meas {
+                entries.iter().map(|e| e.timestamp).collect()
+            } else {
+                self.read_meas_info(meas)?
+                    .iter()
+                    .map(|r| r.timestamp)
+                    .collect::<Vec<_>>()
+            };
+            // with info frame times the frame day is the day of the matched info record
+            let split = match self.config.frame_time_source {
+                FrameTimeSource::Info => DaySplit::Info,
+                _ => self.config.day_split,
+            };
+            let segments = index::day_segments(&entries, &info_times, file_len, split);
+            if segments.len() > 1 {
+                return self.process_segments(&segments, gps, meas, data, out_dir);
+            }
+        }
+
+        let start = self.resume.as_ref().map(|c| c.data).unwrap_or_default();
+        let data_reader = watch::line_reader(data, start, self.config.watch)?;
+        self.process_stream(gps, meas, data_reader, out_dir)
+    }
+
+    /// Decodes the day segments of the data file in parallel, each day is written by
+    /// its own processor so the output files stay the same as in sequential mode
+    fn process_segments(
+        &mut self,
+        segments: &[DaySegment],
+        gps: &Arc<dyn InputSource>,
+        meas: &Arc<dyn InputSource>,
+        data: &Arc<dyn InputSource>,
+        out_dir: &str,
+    ) -> Result<()> {
+        let next_segment = AtomicUsize::new(0);
+        let results: Mutex<Vec<Result<Processor>>> = Mutex::new(Vec::new());
+
+        std::thread::scope(|scope| {
+            for _ in 0..self.config.jobs.min(segments.len()) {
+                scope.spawn(|| {
+                    loop {
+                        let i = next_segment.fetch_add(1, Ordering::SeqCst);
+                        let Some(segment) = segments.get(i) else {
+                            break;
+                        };
+                        let mut processor = Processor::new(self.config.clone());
+                        processor.repro_hash = self.repro_hash.clone();
+                        processor.first_pass = self.first_pass;
+                        processor.frame_offset = segment.first_frame;
+                        processor.progress = self.progress.segment(segment.start);
+                        processor.dose_map = self.dose_map.as_ref().map(DoseMap::fresh);
+                        if self.hot_pixels.is_some() {
+                            processor.hot_pixels = Some(HotPixelStats::default());
+                        }
+                        processor.events = self
+                            .events
+                            .as_ref()
+                            .map(|events| EventSelection::new(events.top_n));
+                        // each day is cataloged by the processor writing it
+                        processor.catalog = self.catalog.clone();
+                        processor.roi_report = self
+                            .roi_report
+                            .as_ref()
+                            .map(|report| RoiReport::new(&report.rois, &report.weighting));
+                        processor.see = self
+                            .see
+                            .as_ref()
+                            .map(|see| SeeAnalysis::new(see.threshold, see.window));
+                        processor.maneuvers = self.maneuvers.clone();
+                        processor.gps_track = self.gps_track.clone();
+                        processor.duty = self.duty.as_ref().map(|duty| DutyCycle::new(duty.gap));
+                        processor.phase = self.phase.as_ref().map(PhaseFolding::fresh);
+                        processor.thermal = self.thermal.as_ref().map(ThermalModel::fresh);
+                        processor.region_spectra = self
+                            .region_spectra
+                            .as_ref()
+                            .map(|spectra| RegionSpectra::new(spectra.subtract_quiet));
+                        processor.modes = self
+                            .modes
+                            .as_ref()
+                            .map(|modes| ModeReport::new(modes.segments.clone()));
+                        if self.daily_dose.is_some() {
+                            processor.daily_dose = Some(DailyDose::default());
+                        }
+                        processor.dose_equivalent = self
+                            .dose_equivalent
+                            .as_ref()
+                            .map(|report| DoseEquivalentReport::new(report.quality));
+                        let result = Self::open_segment(data, segment)
+                            .and_then(|reader| processor.process_stream(gps, meas, reader, out_dir))
+                            .or_else(|e| if is_end_of_data(&e) { Ok(()) } else { Err(e) })
+                            .map(|_| processor);
+                        results.lock().unwrap().push(result);
+                    }
+                });
+            }
+        });
+
+        for result in results.into_inner().unwrap() {
+            let processor = result?;
+            self.ledger.merge(&processor.ledger);
+            self.quality.merge(&processor.quality);
+            self.timing.merge(&processor.timing);
+            if let (Some(dose_map), Some(other)) = (&mut self.dose_map, &processor.dose_map) {
+                dose_map.merge(other);
+            }
+            if let (Some(stats), Some(other)) = (&mut self.hot_pixels, &processor.hot_pixels) {
+                stats.merge(other);
+            }
+            if let (Some(report), Some(other)) = (&mut self.roi_report, &processor.roi_report) {
+                report.merge(other);
+            }
+            if let (Some(see), Some(other)) = (&mut self.see, &processor.see) {
+                see.merge(other);
+            }
+            if let (Some(duty), Some(other)) = (&mut self.duty, &processor.duty) {
+                duty.merge(other);
+            }
+            if let (Some(modes), Some(other)) = (&mut self.modes, &processor.modes) {
+                modes.merge(other);
+            }
+            if let (Some(phase), Some(other)) = (&mut self.phase, &processor.phase) {
+                phase.merge(other);
+            }
+            if let (Some(thermal), Some(other)) = (&mut self.thermal, &processor.thermal) {
+                thermal.merge(other);
+            }
+            if let (Some(spectra), Some(other)) =
+                (&mut self.region_spectra, &processor.region_spectra)
+            {
+                spectra.merge(other);
+            }
+            if let (Some(daily), Some(other)) = (&mut self.daily_dose, &processor.daily_dose) {
+                daily.merge(other);
+            }
+            if let (Some(report), Some(other)) =
+                (&mut self.dose_equivalent, &processor.dose_equivalent)
+            {
+                report.merge(other);
+            }
+            self.day_stats.extend(processor.day_stats);
+            if let (Some(events), Some(other)) = (&mut self.events, processor.events) {
+                events.merge(other);
+            }
+        }
+        self.ledger.pairing_mismatches.sort();
+        self.ledger.finalized_days.sort();
+        Err(OnewebError::EndOfData.into())
+    }
+
+    fn open_segment(
+        data: &Arc<dyn InputSource>,
+        segment: &DaySegment,
+    ) -> Result<LineReader<std::io::Take<SourceReader>>> {
+        let reader = SourceReader::open(data.clone(), segment.start)?;
+        let reader = std::io::BufReader::new(reader.take(segment.end - segment.start));
+        Ok(
+            LineReader::new(reader, data.name()).with_position(Position {
+                offset: segment.start,
+                line_no: segment.line_no,
+            }),
+        )
+    }
+
+    fn process_stream<R: Read + Send + 'static>(
+        &mut self,
+        gps: &Arc<dyn InputSource>,
+        meas: &Arc<dyn InputSource>,
+        data_reader: LineReader<R>,
+        out_dir: &str,
+    ) -> Result<()> {
+        let mut data_processor = DataProcessor::new();
+        data_processor.error_policy = self.config.error_policy;
+        data_processor.sentinel_policy = self.config.sentinel_policy;
+        data_processor.toa_calibration = self.config.toa_calibration.clone();
+        data_processor.energy_calibration = self.config.energy_calibration.clone();
+        data_processor.mask = self.config.pixel_mask.clone();
+        data_processor.merge_distance = self.config.merge_distance;
+        data_processor.layout = self.config.layout();
+        data_processor.release_layouts = self.config.firmware.is_none();
+        data_processor.rle = self.config.rle;
+        data_processor.clock = self.config.clock.clone();
+        data_processor.labeler = self.config.labeler.clone();
+        data_processor.window = self.config.window;
+        data_processor.max_assembly_time = self.config.max_assembly_time;
+        // the read-ahead thread would wait for a full chunk of the watched data
+        let read_ahead = self
+            .config
+            .read_ahead
+            .filter(|_| self.config.watch.is_none());
+        let result = match read_ahead {
+            Some(chunk_size) => self.decode_lines(
+                &mut data_processor,
+                gps,
+                meas,
+                data_reader.read_ahead(chunk_size.0 as usize),
+                out_dir,
+            ),
+            None => self.decode_lines(&mut data_processor, gps, meas, data_reader, out_dir),
+        };
+        self.ledger.bad_lines += data_processor.bad_lines;
+        self.ledger.truncated_frames += data_processor.truncated_frames;
+        self.ledger.overlong_frames += data_processor.overlong_frames;
+        self.ledger.window_lines += data_processor.window_lines;
+        self.quality.merge(&data_processor.quality);
+        self.timing.merge(&data_processor.timing);
+        result
+    }
+
+    /// Saves the checkpoint with the dose map and hot pixel statistics accumulated so far,
+    /// the product files of the previous checkpoint are removed once it is replaced
+    fn save_checkpoint(&mut self, path: &Path, mut checkpoint: Checkpoint) -> Result<()> {
+        if let Some(dose_map) = &self.dose_map {
+            let file = checkpoint::product_path(path, checkpoint.frames, "dose_map");
+            dose_map.save(&file, &self.config.rois)?;
+            checkpoint.dose_map = Some(file);
+        }
+        if let Some(stats) = &self.hot_pixels {
+            let file = checkpoint::product_path(path, checkpoint.frames, "hot_pixels");
+            stats.save(&file)?;
+            checkpoint.hot_pixel_stats = Some(file);
+        }
+        checkpoint.save(path)?;
+        let products: Vec<PathBuf> = checkpoint.products().cloned().collect();
+        for old in std::mem::replace(&mut self.checkpoint_products, products) {
+            if !self.checkpoint_products.contains(&old) {
+                let _ = std::fs::remove_file(old);
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes and closes the files of the finished day, checks that they pair up and writes
+    /// their manifest
+    fn finalize_day(
+        &mut self,
+        day: Option<DayFiles>,
+        writers: [&mut Option<std::io::BufWriter<std::fs::File>>; 4],
+        pixet: &mut Option<PixetWriter>,
+        cluster_table: &mut Option<ClusterTable>,
+        dir: &Path,
+    ) -> Result<()> {
+        for writer in writers {
+            if let Some(mut writer) = writer.take() {
+                writer.flush()?;
+            }
+        }
+        if let Some(pixet) = pixet.take() {
+            pixet.finish()?;
+        }
+        if let Some(table) = cluster_table.take() {
+            table.finish()?;
+        }
+        if let Some(mut day) = day {
+            if let Some(warning) = day.check_pairing(dir, self.frame_index)? {
+                tracing::warn!("clog/info mismatch, {}", warning);
+                self.ledger.pairing_mismatches.push(warning);
+            }
+            if let Some(format) = self.config.pixet {
+                day.names.extend(format.names(&day.date));
+            }
+            if let Some(catalog) = &mut self.catalog {
+                catalog.save_day(dir, &day.date)?;
+                day.names.push(EventCatalog::file_name(&day.date));
+            }
+            day.write_manifest(dir, &self.repro_hash)?;
+            self.ledger.finalized_days.push(day.date);
+        }
+        if let Some(database) = &mut self.database {
+            database.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Decodes the lines on this thread or, with several decode threads, in a frame pipeline
+    fn decode_lines<R: Read + Send + 'static>(
+        &mut self,
+        data_processor: &mut DataProcessor,
+        gps: &Arc<dyn InputSource>,
+        meas: &Arc<dyn InputSource>,
+        data_reader: LineReader<R>,
+        out_dir: &str,
+    ) -> Result<()> {
+        if self.config.decode_threads > 1 {
+            let frames =
+                FramePipeline::new(data_processor, data_reader, self.config.decode_threads);
+            self.decode_stream(data_processor, gps, meas, frames, out_dir)
+        } else {
+            self.decode_stream(data_processor, gps, meas, data_reader, out_dir)
+        }
+    }
+
+    fn decode_stream<S: FrameSource>(
+        &mut self,
+        data_processor: &mut DataProcessor,
+        gps: &Arc<dyn InputSource>,
+        meas: &Arc<dyn InputSource>,
+        mut frames: S,
+        out_dir: &str,
+    ) -> Result<()> {
+        let gps_processor = GpsProcessor::new();
+        let info_processor = MeasInfoProcessor::new();
+
+        let resume = self.resume.take();
+        self.deglitch = self.config.deglitch_gps.then(GpsDeglitcher::default);
+        let (gps_start, meas_start) = resume
+            .as_ref()
+            .map_or_else(Default::default, |c| (c.gps, c.meas));
+        let mut gps_reader = watch::line_reader(gps, gps_start, self.config.watch)?;
+        let mut meas_reader = watch::line_reader(meas, meas_start, self.config.watch)?;
+        let mut clog_write: Option<std::io::BufWriter<std::fs::File>> = None;
+        let mut meta_write: Option<std::io::BufWriter<std::fs::File>> = None;
+        let mut records_write: Option<std::io::BufWriter<std::fs::File>> = None;
+        let mut features_write: Option<std::io::BufWriter<std::fs::File>> = None;
+        let mut pixet_write: Option<PixetWriter> = None;
+        let mut table_write: Option<ClusterTable> = None;
+
+        let dir_path = Path::new(out_dir);
+        let mut disk_guard = self
+            .config
+            .min_free_space
+            .map(|min_free| DiskGuard::new(dir_path, min_free, self.config.on_low_disk));
+        let mut idx = 0;
+        let mut date = String::from("");
+        let mut day: Option<DayFiles> = None;
+        let mut since_checkpoint = 0;
+
+        if let Some(checkpoint) = resume {
+            self.checkpoint_products = checkpoint.products().cloned().collect();
+            self.last_gps_data = checkpoint.last_gps_data;
+            self.pending_gps_data = checkpoint.pending_gps_data;
+            self.last_info_data = checkpoint.last_info_data;
+            self.frame_index = checkpoint.frame_index;
+            if checkpoint.catalog.is_some() {
+                self.catalog = checkpoint.catalog;
+            }
+            if checkpoint.deglitch.is_some() {
+                self.deglitch = checkpoint.deglitch;
+            }
+            // the cumulative products continue from the checkpoint, not from the state the
+            // run was started with
+            if let (Some(path), Some(_)) = (&checkpoint.dose_map, &self.dose_map) {
+                if !path.exists() {
+                    bail!(
+                        "the dose map {} of the checkpoint is missing",
+                        path.display()
+                    );
+                }
+                let mut dose_map = DoseMap::load(path)?;
+                dose_map.use_weighting(&self.config.weighting());
+                self.dose_map = Some(dose_map);
+            }
+            if let (Some(path), Some(_)) = (&checkpoint.hot_pixel_stats, &self.hot_pixels) {
+                if !path.exists() {
+                    bail!(
+                        "the hot pixel statistics {} of the checkpoint are missing",
+                        path.display()
+                    );
+                }
+                self.hot_pixels = Some(HotPixelStats::load(path)?);
+            }
+            idx = checkpoint.frames;
+            if let Some(files) = checkpoint.day {
+                // the files of the day are continued, rows written after the checkpoint
+                // are dropped
+                for (name, &len) in files.names.iter().zip(&checkpoint.file_lengths) {
+                    let writer = checkpoint::reopen(&dir_path.join(name), len)?;
+                    let slot = if name.ends_with(".clog") {
+                        &mut clog_write
+                    } else if name.ends_with(".info") {
+                        &mut meta_write
+                    } else if name.ends_with(".clusters.csv") {
+                        &mut features_write
+                    } else {
+                        &mut records_write
+                    };
+                    *slot = Some(writer);
+                }
+                date = files.date.clone();
+                day = Some(files);
+            }
+        }
+
+        loop {
+            let mut frame = match frames.next_frame(data_processor) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    if is_end_of_data(&e) {
+                        self.finalize_day(
+                            day.take(),
+                            [
+                                &mut clog_write,
+                                &mut meta_write,
+                                &mut records_write,
+                                &mut features_write,
+                            ],
+                            &mut pixet_write,
+                            &mut table_write,
+                            dir_path,
+                        )?;
+                    }
+                    return Err(e);
+                }
+            };
+
+            let start = self.config.clock.now();
+            let source = self.config.frame_time_source;
+            let packet_time = source.packet_time(frame.timestamp, frame.end_timestamp);
+            let info_data = if self.config.no_meas {
+                MeasInfoData::placeholder(packet_time)
+            } else {
+                self.find_next_closest_info_data(&info_processor, &mut meas_reader, packet_time)?
+            };
+            // the unknown acquisition time of frames without measurement info counts as 0 s
+            let acq_time = Self::calculate_acq_time(&info_data, self.config.max_pix_count);
+            frame.timestamp = source.frame_time(
+                frame.timestamp,
+                frame.end_timestamp,
+                info_data.timestamp,
+                acq_time,
+            );
+            let mut gps_data = if self.config.no_gps {
+                GpsData::default()
+            } else {
+                self.find_next_closest_gps_data(&gps_processor, &mut gps_reader, frame.timestamp)?
+            };
+            self.timing
+                .add(Stage::Matching, self.config.clock.elapsed(start));
+            if !self.config.window.contains(frame.timestamp) {
+                self.ledger.window_frames += 1;
+                continue;
+            }
+            if self.config.noise_model.is_some() || self.config.adaptive_threshold.is_some() {
+                let start = self.config.clock.now();
+                if self.apply_thresholds(&mut frame, info_data.temp) > 0 {
+                    data_processor.clusterize_frame(&mut frame);
+                }
+                self.timing
+                    .add(Stage::Clustering, self.config.clock.elapsed(start));
+            }
+
+            idx += 1;
+            self.frame_number = self.frame_offset + idx;
+            let _frame = tracing::debug_span!(
+                "frame",
+                number = self.frame_number,
+                time = %utils::format_time(frame.timestamp)
+            )
+            .entered();
+            self.check_firmware(&frame)?;
+            self.progress.frame(frame.resume_at);
+            if let Some(every) = self.config.preview_every
+                && self.frame_number.is_multiple_of(every)
+            {
+                self.progress.suspend(|| self.print_preview(&frame));
+            }
+
+            if !self.config.no_gps && !gps_data.is_valid() {
+                self.ledger.invalid_gps_frames += 1;
+                self.quality.record(Issue::InvalidGps, frame.timestamp, 0.0);
+                tracing::warn!(
+                    "GPS record {} of frame {} is invalid: {}",
+                    utils::format_time(gps_data.timestamp),
+                    self.frame_number,
+                    gps_data.problems.join(", ")
+                );
+            }
+
+            let mut gps_stale = self.is_gps_stale(&frame, &gps_data);
+            if gps_stale {
+                self.ledger.stale_gps_frames += 1;
+            }
+            if let Some(residual) = self.tle_residual(&gps_data)
+                && !gps_stale
+            {
+                self.ledger.tle_residual_frames += 1;
+                self.ledger.tle_residual_sum += residual;
+                self.ledger.tle_residual_max = self.ledger.tle_residual_max.max(residual);
+            }
+            if self.config.tle_substitute
+                && (gps_stale || !gps_data.is_valid())
+                && let Some(position) = self
+                    .config
+                    .tle
+                    .as_ref()
+                    .and_then(|tle| tle.position(frame.timestamp))
+            {
+                gps_data = GpsData::propagated(frame.timestamp, position);
+                gps_stale = false;
+                self.ledger.tle_substituted_frames += 1;
+            }
+            if self.is_maneuver(&frame, &gps_data) {
+                self.ledger.maneuver_frames += 1;
+            }
+
+            let info_date = chrono::Utc
+                .timestamp_opt(info_data.timestamp as i64, 0_u32)
+                .unwrap();
+
+            let cur_date = self
+                .config
+                .day_split
+                .date(frame.timestamp, info_data.timestamp);
+            if let Some(duty) = &mut self.duty {
+                duty.add_frame(frame.timestamp, acq_time);
+            }
+
+            let Some(weight) = self.sampling_weight(frame.timestamp) else {
+                self.ledger.add_decimated(acq_time);
+                continue;
+            };
+
+            self.ledger.sentinel_pixels += frame.invalid().count();
+            self.ledger.merged_clusters += frame.clusters.iter().map(|c| c.merged).sum::<usize>();
+            self.ledger.noise_pixels += frame.noise_pixels;
+            self.ledger.adaptive_pixels += frame.adaptive_pixels;
+            self.ledger.masked_pixels += frame.masked.count();
+            let hit_pixels = frame.itot().iter().filter(|&&v| v != 0).count();
+            if hit_pixels > self.config.max_pix_count {
+                self.ledger.over_max_pix_frames += 1;
+                self.quality
+                    .record(Issue::OverMaxPix, frame.timestamp, hit_pixels as f64);
+            }
+
+            if let Some(dose_map) = &mut self.dose_map {
+                dose_map.add_frame(
+                    frame.itot(),
+                    frame.timestamp,
+                    self.config.kev_per_count,
+                    acq_time,
+                    weight,
+                );
+            }
+            if let Some(stats) = &mut self.hot_pixels {
+                stats.add_frame(frame.itot(), frame.timestamp);
+                stats.add_masked(&frame.masked, frame.timestamp);
+            }
+            if let Some(report) = &mut self.roi_report {
+                report.add_frame(&frame, self.config.kev_per_count, acq_time, weight);
+            }
+            if let Some(modes) = &mut self.modes {
+                let mode = modes.mode_at(info_data.timestamp);
+                modes.add_frame(mode, &frame, self.config.kev_per_count, acq_time, weight);
+            }
+            if let Some(phase) = &mut self.phase {
+                phase.add_frame(&frame, self.config.kev_per_count, acq_time, weight);
+            }
+            if let Some(thermal) = &mut self.thermal {
+                thermal.add_frame(frame.timestamp, info_data.temp, weight);
+            }
+            // frames without a current position are not tagged with a region
+            if let (Some(spectra), false) = (&mut self.region_spectra, gps_stale) {
+                let ecef = orbit::j2000_to_ecef(
+                    [gps_data.j2000_x, gps_data.j2000_y, gps_data.j2000_z],
+                    gps_data.timestamp,
+                );
+                let region = orbit::radiation_region(
+                    &orbit::ecef_to_geodetic(ecef),
+                    orbit::dipole_l_shell(ecef),
+                );
+                spectra.add_frame(region, &frame, self.config.kev_per_count, acq_time, weight);
+            }
+            if let Some(daily) = &mut self.daily_dose {
+                daily.add_frame(
+                    &cur_date,
+                    &frame,
+                    self.config.kev_per_count,
+                    acq_time,
+                    weight,
+                );
+            }
+            if let Some(report) = &mut self.dose_equivalent {
+                report.add_frame(
+                    &cur_date,
+                    &frame.clusters,
+                    self.config.kev_per_count,
+                    acq_time,
+                    weight,
+                );
+            }
+            if let Some(see) = &mut self.see {
+                see.add_frame(&frame.clusters, frame.timestamp, self.config.kev_per_count);
+            }
+
+            // the region of a frame without a current position is unknown
+            let skip_reason = if gps_stale && self.config.bbox.is_some() {
+                Some("without a GPS position")
+            } else if !self.is_in_bbox(&gps_data) {
+                Some("outside bbox")
+            } else {
+                None
+            };
+            if let Some(reason) = skip_reason {
+                self.ledger.add_skipped(acq_time);
+                tracing::info!(
+                    target: logging::FRAMES,
+                    "Skipping frame {} ({}, {} s) {} ...",
+                    self.frame_number,
+                    info_date,
+                    Self::fmt_acq_time(acq_time),
+                    reason
+                );
+                continue;
+            }
+            if self.products.is_some() {
+                self.collect_frame(&frame, &info_data, &gps_data, acq_time, &cur_date);
+                continue;
+            }
+            if self.first_pass {
+                self.add_day_stats(&frame, acq_time, &cur_date);
+                continue;
+            }
+
+            let start = self.config.clock.now();
+            if clog_write.is_none() || meta_write.is_none() || date != cur_date {
+                if let Some(guard) = &disk_guard {
+                    guard.check().with_context(|| {
+                        format!(
+                            "stopped before frame {} ({})",
+                            self.frame_number,
+                            utils::format_time(frame.timestamp)
+                        )
+                    })?;
+                }
+                self.finalize_day(
+                    day.take(),
+                    [
+                        &mut clog_write,
+                        &mut meta_write,
+                        &mut records_write,
+                        &mut features_write,
+                    ],
+                    &mut pixet_write,
+                    &mut table_write,
+                    dir_path,
+                )?;
+                // Reuse existing files
+                self.frame_index = 0;
+                let clog_file_path = dir_path.join(format!("data_{}.clog", cur_date));
+                let meta_file_path = dir_path.join(format!("data_{}.info", cur_date));
+                let clog_file = std::fs::File::create(&clog_file_path)?;
+                let meta_file = std::fs::File::create(&meta_file_path)?;
+                let mut clog_writer = std::io::BufWriter::new(clog_file);
+                let mut meta_writer = std::io::BufWriter::new(meta_file);
+                let day_stats = self
+                    .config
+                    .day_stats
+                    .as_ref()
+                    .and_then(|days| days.get(&cur_date));
+                for writer in [&mut clog_writer, &mut meta_writer] {
+                    write!(
+                        writer,
+                        "{}{}{}",
+                        repro::REPRO_HASH_PREFIX,
+                        self.repro_hash,
+                        self.lend
+                    )?;
+                    write!(
+                        writer,
+                        "{}{}{}",
+                        provenance::BUILD_PREFIX,
+                        BuildInfo::current(),
+                        self.lend
+                    )?;
+                    if self.config.decimate > 1 {
+                        write!(
+                            writer,
+                            "# weighting: {}, frame weight {}{}",
+                            self.config.weighting(),
+                            self.config.decimate,
+                            self.lend
+                        )?;
+                    }
+                    if let Some(stats) = day_stats {
+                        write!(writer, "{}{}{}", DAY_STATS_PREFIX, stats, self.lend)?;
+                    }
+                }
+                if self.config.position_frame != ReferenceFrame::J2000 {
+                    write!(
+                        meta_writer,
+                        "# position_frame: {}{}",
+                        self.config.position_frame, self.lend
+                    )?;
+                }
+                if self.config.gps_columns != GpsColumns::Nearest {
+                    write!(
+                        meta_writer,
+                        "# gps_columns: {}{}",
+                        self.config.gps_columns, self.lend
+                    )?;
+                }
+                if self.config.pointing_frame != PointingFrame::J2000 {
+                    write!(
+                        meta_writer,
+                        "# pointing_frame: {}{}",
+                        self.config.pointing_frame, self.lend
+                    )?;
+                }
+                if !self.config.position_binning.is_none() {
+                    write!(
+                        meta_writer,
+                        "{}{}{}",
+                        binning::BINNING_PREFIX,
+                        self.config.position_binning,
+                        self.lend
+                    )?;
+                }
+                let missing: Vec<&str> =
+                    [("gps", self.config.no_gps), ("meas", self.config.no_meas)]
+                        .iter()
+                        .filter(|(_, missing)| *missing)
+                        .map(|(input, _)| *input)
+                        .collect();
+                if !missing.is_empty() {
+                    write!(
+                        meta_writer,
+                        "# missing inputs: {} (see the backfill command){}",
+                        missing.join(", "),
+                        self.lend
+                    )?;
+                }
+                clog_write = Some(clog_writer);
+                meta_write = Some(meta_writer);
+                let mut names = vec![
+                    format!("data_{}.clog", cur_date),
+                    format!("data_{}.info", cur_date),
+                ];
+                records_write = if let Some(format) = self.config.records {
+                    names.push(format!("data_{}.{}", cur_date, format.extension()));
+                    let path = dir_path.join(&names[2]);
+                    let mut writer = std::io::BufWriter::new(std::fs::File::create(&path)?);
+                    let weighting = (self.config.decimate > 1).then(|| self.config.weighting());
+                    records::write_header(
+                        &mut writer,
+                        format,
+                        &self.repro_hash,
+                        weighting,
+                        self.config.position_frame,
+                        self.config.position_binning,
+                        day_stats.cloned(),
+                    )?;
+                    Some(writer)
+                } else {
+                    None
+                };
+                features_write = if self.config.cluster_features {
+                    let name = format!("data_{}.clusters.csv", cur_date);
+                    let mut writer =
+                        std::io::BufWriter::new(std::fs::File::create(dir_path.join(&name))?);
+                    names.push(name);
+                    write!(
+                        writer,
+                        "{}{}{}{}{}{}",
+                        repro::REPRO_HASH_PREFIX,
+                        self.repro_hash,
+                        self.lend,
+                        provenance::BUILD_PREFIX,
+                        BuildInfo::current(),
+                        self.lend,
+                    )?;
+                    if let Some(stats) = day_stats {
+                        write!(writer, "{}{}{}", DAY_STATS_PREFIX, stats, self.lend)?;
+                    }
+                    write!(writer, "{}{}", clusterize::MORPHOLOGY_HEADER, self.lend)?;
+                    Some(writer)
+                } else {
+                    None
+                };
+                pixet_write = self
+                    .config
+                    .pixet
+                    .map(|format| PixetWriter::create(dir_path, &cur_date, format))
+                    .transpose()?;
+                table_write = if self.config.format == OutputFormat::Parquet {
+                    names.push(cluster_table::file_name(&cur_date));
+                    Some(ClusterTable::create(
+                        dir_path,
+                        &cur_date,
+                        self.config.chunk_frames,
+                    )?)
+                } else {
+                    None
+                };
+                if self.config.frame_images.is_some() {
+                    let images = dir_path.join(format!("frames_{}", cur_date));
+                    std::fs::create_dir_all(&images)
+                        .with_context(|| format!("{}", images.display()))?;
+                }
+                day = Some(DayFiles::new(&cur_date, names));
+                date = cur_date;
+            }
+
+            if let (Some(format), Some(records_writer)) =
+                (self.config.records, records_write.as_mut())
+            {
+                self.save_record(
+                    &frame,
+                    &info_data,
+                    &gps_data,
+                    acq_time,
+                    format,
+                    records_writer,
+                )?;
+            }
+            if self.database.is_some() {
+                self.save_to_database(&frame, &info_data, &gps_data, acq_time, &date)?;
+            }
+            if let Some(features_writer) = features_write.as_mut() {
+                clusterize::write_morphology(
+                    features_writer,
+                    self.output_index(),
+                    &self.config.orientation.clusters(&frame.clusters),
+                    &self.config.classification,
+                    self.config.kev_per_count,
+                    &self.lend,
+                )?;
+            }
+            if let Some(images) = &self.config.frame_images {
+                images.write(
+                    &dir_path.join(format!("frames_{}", date)),
+                    &format!("{:06}", self.output_index()),
+                    &frame,
+                    self.config.orientation,
+                )?;
+            }
+            if let Some(pixet) = &mut pixet_write {
+                let itot = self.config.orientation.matrix(frame.itot());
+                pixet.write_frame(&itot, frame.timestamp, acq_time)?;
+            }
+            if let Some(table) = &mut table_write {
+                let gps = (!self.config.no_gps && !gps_stale).then_some(&gps_data);
+                table.write_frame(
+                    self.output_index(),
+                    frame.timestamp,
+                    &self.config.orientation.clusters(&frame.clusters),
+                    gps,
+                )?;
+            }
+            let frame_no = self.output_index();
+            if let Some(catalog) = &mut self.catalog {
+                let orbit = catalog.orbit(frame.timestamp);
+                let itot = self.config.orientation.matrix(frame.itot());
+                let context = FrameContext {
+                    timestamp: frame.timestamp,
+                    frame: frame_no,
+                    itot: &itot,
+                    gps: (!gps_stale).then_some(&gps_data),
+                    binning: self.config.position_binning,
+                };
+                for cluster in self.config.orientation.clusters(&frame.clusters).iter() {
+                    let energy = event_display::cluster_energy(cluster, self.config.kev_per_count);
+                    if catalog.accepts(&date, orbit, energy) {
+                        let event = CatalogEvent::new(
+                            cluster,
+                            &context,
+                            &self.config.classification,
+                            self.config.kev_per_count,
+                        );
+                        catalog.add(&date, event);
+                    }
+                }
+            }
+            if let (Some(clog_writer), Some(meta_writer)) =
+                (clog_write.as_mut(), meta_write.as_mut())
+            {
+                self.save_to_files(
+                    &frame,
+                    &info_data,
+                    &gps_data,
+                    acq_time,
+                    clog_writer,
+                    meta_writer,
+                )?;
+                self.ledger.add_written(acq_time);
+                if let Some(day) = &mut day {
+                    day.add_frame(frame.timestamp);
+                }
+                if self.config.two_pass {
+                    self.add_day_stats(&frame, acq_time, &date);
+                }
+            }
+            if self.config.watch.is_some() {
+                // rows of a live conversion are readable as soon as the frame arrived
+                for writer in [
+                    &mut clog_write,
+                    &mut meta_write,
+                    &mut records_write,
+                    &mut features_write,
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    writer.flush()?;
+                }
+                if let Some(pixet) = &mut pixet_write {
+                    pixet.flush()?;
+                }
+            }
+            self.timing
+                .add(Stage::Writing, self.config.clock.elapsed(start));
+
+            // on low disk space the run stops after the checkpoint and day of this frame
+            let disk_full = disk_guard
+                .as_mut()
+                .and_then(|guard| guard.frame_written().err());
+
+            if let Some(events) = &mut self.events {
+                for cluster in self.config.orientation.clusters(&frame.clusters).iter() {
+                    let event =
+                        EventDisplay::new(cluster, frame.timestamp, self.config.kev_per_count);
+                    events.add(&date, event);
+                }
+            }
+
+            since_checkpoint += 1;
+            if let (Some(path), Some(data)) = (&self.config.checkpoint, frame.resume_at)
+                && (since_checkpoint >= self.config.checkpoint_every || disk_full.is_some())
+            {
+                for writer in [
+                    &mut clog_write,
+                    &mut meta_write,
+                    &mut records_write,
+                    &mut features_write,
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    writer.flush()?;
+                }
+                let file_lengths = match &day {
+                    Some(day) => day
+                        .names
+                        .iter()
+                        .map(|name| Ok(std::fs::metadata(dir_path.join(name))?.len()))
+                        .collect::<Result<Vec<_>>>()?,
+                    None => Vec::new(),
+                };
+                let checkpoint = Checkpoint {
+                    repro_hash: self.repro_hash.clone(),
+                    frames: idx,
+                    data,
+                    gps: gps_reader.position(),
+                    meas: meas_reader.position(),
+                    last_gps_data: self.last_gps_data.clone(),
+                    pending_gps_data: self.pending_gps_data.clone(),
+                    last_info_data: self.last_info_data.clone(),
+                    frame_index: self.frame_index,
+                    day: day.clone(),
+                    file_lengths,
+                    catalog: self.catalog.clone(),
+                    deglitch: self.deglitch.clone(),
+                    dose_map: None,
+                    hot_pixel_stats: None,
+                };
+                self.save_checkpoint(&PathBuf::from(path), checkpoint)?;
+                since_checkpoint = 0;
+            }
+            if let Some(e) = disk_full {
+                // leave the daily files complete up to this frame
+                self.finalize_day(
+                    day.take(),
+                    [
+                        &mut clog_write,
+                        &mut meta_write,
+                        &mut records_write,
+                        &mut features_write,
+                    ],
+                    &mut pixet_write,
+                    &mut table_write,
+                    dir_path,
+                )?;
+                return Err(e.context(format!(
+                    "stopped after frame {} ({}), the output files end with this frame",
+                    self.frame_number,
+                    utils::format_time(frame.timestamp)
+                )));
+            }
+
+            tracing::info!(
+                target: logging::FRAMES,
+                "Processing frame {} ({}, {} s) ...",
+                self.frame_number,
+                info_date,
+                Self::fmt_acq_time(acq_time)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::Generator;
+
+    #[test]
+    fn test_max_pix_count() {
+        let info = MeasInfoData {
+            pixel_short: 10.0,
+            pixel_long: 100.0,
+            ..Default::default()
+        };
+        // 100 pixels/s with no offset, 500 pixels are reached in 5 s
+        assert!((Processor::calculate_acq_time(&info, 500) - 5.0).abs() < 1e-9);
+        assert_eq!(Processor::calculate_acq_time(&info, 1638), 16.38);
+        assert_eq!(Processor::calculate_acq_time(&info, 10000), 25.0);
+
+        let mut config = ProcessorConfig::default();
+        assert!(config.validate().is_ok());
+        config.max_pix_count = 0;
+        assert!(config.validate().is_err());
+        config.max_pix_count = MATRIX_SIZE + 1;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_gps_staleness() {
+        let frame = Frame::from_planes(Vec::new(), Vec::new(), 100.0);
+        let gps = |timestamp| GpsData {
+            timestamp,
+            ..Default::default()
+        };
+        let processor = Processor::new(ProcessorConfig::default());
+        assert!(!processor.is_gps_stale(&frame, &gps(-1e9)));
+
+        let config = ProcessorConfig {
+            max_gps_staleness: Some(10.0),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        let processor = Processor::new(config);
+        assert!(!processor.is_gps_stale(&frame, &gps(90.0)));
+        assert!(!processor.is_gps_stale(&frame, &gps(110.0)));
+        assert!(processor.is_gps_stale(&frame, &gps(110.5)));
+        assert!(processor.is_gps_stale(&frame, &gps(0.0)));
+
+        let config = ProcessorConfig {
+            max_gps_staleness: Some(-1.0),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_gps_matching_after_gap() {
+        let content = "TIME,X,Y,Z,Q0,Q1,Q2,Q3\n\
+                       2024-03-01 00:00:00.000,7e6,0,0,1,0,0,0\n\
+                       2024-03-01 00:00:10.000,7e6,0,0,1,0,0,0\n\
+                       2024-03-01 00:00:20.000,7e6,0,0,1,0,0,0\n\
+                       2024-03-01 00:01:40.000,7e6,0,0,1,0,0,0\n\
+                       2024-03-01 00:01:50.000,7e6,0,0,1,0,0,0\n";
+        let mut reader = LineReader::new(std::io::BufReader::new(content.as_bytes()), "gps.csv");
+        let mut processor = Processor::new(ProcessorConfig::default());
+        let start = 1709251200.0;
+        // the record after the gap is not used up by the frames inside the gap
+        let matched: Vec<f64> = [21.0, 51.0, 101.0]
+            .iter()
+            .map(|t| {
+                processor
+                    .find_next_closest_gps_data(&GpsProcessor::new(), &mut reader, start + t)
+                    .unwrap()
+                    .timestamp
+                    - start
+            })
+            .collect();
+        assert_eq!(matched, [20.0, 20.0, 100.0]);
+    }
+
+    #[test]
+    fn test_seeded_decimation() {
+        let kept = |seed| {
+            let processor = Processor::new(ProcessorConfig {
+                decimate: 4,
+                seed,
+                ..Default::default()
+            });
+            (0..1000)
+                .filter(|i| {
+                    processor
+                        .sampling_weight(1709251201.3 + 30.0 * *i as f64)
+                        .is_some()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kept(0), kept(0));
+        assert_ne!(kept(0), kept(7));
+        assert!((200..300).contains(&kept(7).len()));
+        assert!(
+            ProcessorConfig::default()
+                .fingerprint()
+                .contains("seed=0\n")
+        );
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let hash = |config: &ProcessorConfig| {
+            repro::run_hash::<&Path>(&config.fingerprint(), &[]).unwrap()
+        };
+        let config = ProcessorConfig::default();
+        // the energy scale and the thresholds change the dose rate and cluster type columns
+        let kev_per_count = ProcessorConfig {
+            kev_per_count: 2.0 * config.kev_per_count,
+            ..config.clone()
+        };
+        let classification = ProcessorConfig {
+            classification: ClassThresholds {
+                heavy_blob_kev: 200.0,
+                ..Default::default()
+            },
+            ..config.clone()
+        };
+        assert_eq!(hash(&config), hash(&config.clone()));
+        assert_ne!(hash(&config), hash(&kev_per_count));
+        assert_ne!(hash(&config), hash(&classification));
+        assert_ne!(hash(&kev_per_count), hash(&classification));
+    }
+
+    #[test]
+    fn test_process_in_memory() {
+        let mut generator = Generator::new(3, PacketLayout::default());
+        let generated: Vec<_> = (0..5).map(|_| generator.random_frame()).collect();
+        let data = generator.render(&generated);
+        let mut processor = Processor::new(ProcessorConfig {
+            no_gps: true,
+            no_meas: true,
+            firmware: Some(PacketLayout::default()),
+            dose_summary: Some(String::from("/nonexistent/dose.tsv")),
+            ..Default::default()
+        });
+        let products = processor
+            .process_in_memory(&[], &[], data.as_bytes())
+            .unwrap();
+
+        let frames = &products.frames["2024-01-01"];
+        assert_eq!(frames.len(), 5);
+        assert_eq!(frames[4].frame, 5);
+        for (record, frame) in frames.iter().zip(&generated) {
+            let pixels: usize = record.clusters.iter().map(|c| c.pixels.len()).sum();
+            assert_eq!(pixels, frame.pixels.len());
+            assert!(record.metadata.contains_key("frame_index"));
+        }
+        assert_eq!(products.days["2024-01-01"].frames, 5);
+        assert_eq!(products.ledger.written_frames, 5);
+    }
+
+    #[test]
+    fn test_two_pass() {
+        let mut generator = Generator::new(4, PacketLayout::default());
+        let generated: Vec<_> = (0..5).map(|_| generator.random_frame()).collect();
+        let data = source::memory("data file", generator.render(&generated).as_bytes());
+        let missing = source::missing("info file");
+        let dir = std::env::temp_dir().join(format!("oneweb-two-pass-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out_dir = dir.to_string_lossy();
+        let mut processor = Processor::new(ProcessorConfig {
+            no_gps: true,
+            no_meas: true,
+            firmware: Some(PacketLayout::default()),
+            two_pass: true,
+            quiet: true,
+            ..Default::default()
+        });
+
+        // the first pass writes nothing
+        let stats = processor
+            .gather_day_stats(&missing, &missing, &data, &out_dir)
+            .unwrap();
+        assert_eq!(stats["2024-01-01"].frames, 5);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        let result = processor.process_sources(&missing, &missing, &data, &out_dir);
+        assert!(is_end_of_data(&result.unwrap_err()));
+        let clog = std::fs::read_to_string(dir.join("data_2024-01-01.clog")).unwrap();
+        assert!(clog.contains(&format!("{}{}", DAY_STATS_PREFIX, stats["2024-01-01"])));
+        let mut names: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "MANIFEST_2024-01-01.json",
+                "data_2024-01-01.clog",
+                "data_2024-01-01.info"
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Data file whose reads fail after a number of bytes, like a run killed mid-file
+    #[derive(Debug)]
+    struct Crashing {
+        content: Vec<u8>,
+        limit: usize,
+    }
+
+    struct Killed;
+
+    impl Read for Killed {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("killed"))
+        }
+    }
+
+    impl InputSource for Crashing {
+        fn name(&self) -> &str {
+            "data file"
+        }
+
+        fn open_at(&self, offset: u64) -> Result<Box<dyn Read + Send>> {
+            let read = self.content[offset as usize..self.limit].to_vec();
+            Ok(Box::new(std::io::Cursor::new(read).chain(Killed)))
+        }
+    }
+
+    #[test]
+    fn test_resume_dose_map() {
+        let mut generator = Generator::new(5, PacketLayout::default());
+        let generated: Vec<_> = (0..6).map(|_| generator.random_frame()).collect();
+        let content = generator.render(&generated);
+        let missing = source::missing("info file");
+        let dir = std::env::temp_dir().join(format!("oneweb-resume-{}", std::process::id()));
+        let run = |name: &str, data: Arc<dyn InputSource>, resume: bool| {
+            let out = dir.join(name);
+            std::fs::create_dir_all(&out).unwrap();
+            let file = |extension: &str| {
+                Some(
+                    dir.join(format!("{}.{}", name, extension))
+                        .to_string_lossy()
+                        .into_owned(),
+                )
+            };
+            let mut processor = Processor::new(ProcessorConfig {
+                no_gps: true,
+                no_meas: true,
+                firmware: Some(PacketLayout::default()),
+                checkpoint: file("ckpt"),
+                checkpoint_every: 1,
+                resume,
+                dose_map: file("dose"),
+                hot_pixel_stats: file("hot"),
+                quiet: true,
+                ..Default::default()
+            });
+            processor.process_sources(&missing, &missing, &data, &out.to_string_lossy())
+        };
+
+        let data = source::memory("data file", content.as_bytes());
+        assert!(is_end_of_data(
+            &run("full", data.clone(), false).unwrap_err()
+        ));
+        let crashing = Arc::new(Crashing {
+            content: content.clone().into_bytes(),
+            limit: content.len() / 2,
+        });
+        assert!(!is_end_of_data(
+            &run("resumed", crashing, false).unwrap_err()
+        ));
+        assert!(!dir.join("resumed.dose").exists());
+        let checkpoint = Checkpoint::load(&dir.join("resumed.ckpt")).unwrap();
+        assert!(checkpoint.frames > 0 && checkpoint.products().all(|path| path.exists()));
+        assert!(is_end_of_data(&run("resumed", data, true).unwrap_err()));
+
+        let full = DoseMap::load(&dir.join("full.dose")).unwrap();
+        let resumed = DoseMap::load(&dir.join("resumed.dose")).unwrap();
+        assert_eq!(resumed.frames, 6);
+        assert_eq!(resumed.frames, full.frames);
+        assert_eq!(resumed.exposure, full.exposure);
+        assert_eq!(resumed.dose, full.dose);
+        assert_eq!(resumed.covered, full.covered);
+        assert_eq!(
+            std::fs::read(dir.join("resumed.hot")).unwrap(),
+            std::fs::read(dir.join("full.hot")).unwrap()
+        );
+        // the checkpoint and its products are removed with the complete run
+        let mut names: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "full",
+                "full.dose",
+                "full.hot",
+                "resumed",
+                "resumed.dose",
+                "resumed.hot"
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
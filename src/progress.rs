@@ -0,0 +1,152 @@
+use crate::line_reader::Position;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Bar drawn on the terminal, the log lines are written with it cleared
+static DRAWN: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+/// Progress bar of a conversion on stderr, advanced by the data file bytes of the decoded
+/// frames, with the frame throughput and the ETA; the processors of parallel days share it
+#[derive(Debug, Clone)]
+pub struct Progress {
+    bar: ProgressBar,
+    /// Frames decoded by all processors
+    frames: Arc<AtomicUsize>,
+    /// Data file offset reached by this processor
+    offset: u64,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress::with_bar(ProgressBar::hidden())
+    }
+}
+
+impl Progress {
+    /// Bar of a data file of the size in bytes, a spinner when the size is unknown; not drawn
+    /// when quiet or when stderr is not a terminal
+    pub fn new(size: Option<u64>, quiet: bool) -> Self {
+        let target = if quiet {
+            ProgressDrawTarget::hidden()
+        } else {
+            ProgressDrawTarget::stderr()
+        };
+        let bar = match size {
+            Some(size) => ProgressBar::with_draw_target(Some(size), target).with_style(
+                ProgressStyle::with_template(
+                    "[{elapsed_precise}] {wide_bar} {bytes}/{total_bytes} {msg}, ETA {eta}",
+                )
+                .expect("valid template"),
+            ),
+            None => ProgressBar::with_draw_target(None, target).with_style(
+                ProgressStyle::with_template("{spinner} [{elapsed_precise}] {bytes} {msg}")
+                    .expect("valid template"),
+            ),
+        };
+        if !bar.is_hidden() {
+            *DRAWN.lock().unwrap() = Some(bar.clone());
+        }
+        Progress::with_bar(bar)
+    }
+
+    fn with_bar(bar: ProgressBar) -> Self {
+        Progress {
+            bar,
+            frames: Arc::new(AtomicUsize::new(0)),
+            offset: 0,
+        }
+    }
+
+    /// Whether the bar is not drawn, the frames are then reported line by line
+    pub fn is_hidden(&self) -> bool {
+        self.bar.is_hidden()
+    }
+
+    /// Continues at the data file offset of a resumed run
+    pub fn resume_at(&mut self, offset: u64) {
+        self.offset = offset;
+        self.bar.set_position(offset);
+    }
+
+    /// Progress of the processor of a parallel day starting at the data file offset
+    pub fn segment(&self, offset: u64) -> Self {
+        Progress {
+            bar: self.bar.clone(),
+            frames: self.frames.clone(),
+            offset,
+        }
+    }
+
+    /// Counts a decoded frame ending at the data file position
+    pub fn frame(&mut self, position: Option<Position>) {
+        let frames = self.frames.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(position) = position {
+            self.bar.inc(position.offset.saturating_sub(self.offset));
+            self.offset = self.offset.max(position.offset);
+        }
+        let elapsed = self.bar.elapsed().as_secs_f64();
+        if !self.bar.is_hidden() && elapsed > 0.0 {
+            self.bar.set_message(format!(
+                "{} frames, {:.1} frames/s",
+                frames,
+                frames as f64 / elapsed
+            ));
+        }
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames.load(Ordering::Relaxed)
+    }
+
+    /// Runs the printing of the closure with the bar cleared
+    pub fn suspend<T>(&self, print: impl FnOnce() -> T) -> T {
+        self.bar.suspend(print)
+    }
+
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+        if !self.bar.is_hidden() {
+            *DRAWN.lock().unwrap() = None;
+        }
+    }
+}
+
+/// Whether a progress bar is drawn on the terminal
+pub fn is_drawn() -> bool {
+    DRAWN.lock().unwrap().is_some()
+}
+
+/// Runs the printing of the closure with the drawn bar cleared
+pub fn suspend_drawn<T>(print: impl FnOnce() -> T) -> T {
+    let drawn = DRAWN.lock().unwrap().clone();
+    match drawn {
+        Some(bar) => bar.suspend(print),
+        None => print(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let bar = ProgressBar::hidden();
+        bar.set_length(1000);
+        let mut progress = Progress::with_bar(bar.clone());
+        assert!(progress.is_hidden());
+        let position = |offset| Some(Position { offset, line_no: 0 });
+        progress.resume_at(40);
+        progress.frame(position(100));
+        // a frame completed within the line of the previous one has no position
+        progress.frame(None);
+        assert_eq!(bar.position(), 100);
+        // the days decoded in parallel add their bytes to the shared bar
+        let mut day = progress.segment(600);
+        day.frame(position(650));
+        progress.frame(position(300));
+        assert_eq!(bar.position(), 350);
+        assert_eq!(day.frames(), 4);
+    }
+}
@@ -0,0 +1,75 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Header line of the .clog and .info files naming the decoder build
+pub const BUILD_PREFIX: &str = "# build: ";
+
+/// Decoder build that wrote the products, recorded by the build script
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct BuildInfo {
+    /// Crate version
+    pub version: String,
+    /// Abbreviated git commit, with -dirty for uncommitted changes, or unknown outside a checkout
+    pub commit: String,
+    /// UTC date of the build (YYYY-MM-DD)
+    pub build_date: String,
+    /// Enabled cargo features
+    pub features: Vec<String>,
+}
+
+impl BuildInfo {
+    /// The build of the running decoder
+    pub fn current() -> Self {
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            commit: env!("ONEWEB_GIT_COMMIT").to_string(),
+            build_date: env!("ONEWEB_BUILD_DATE").to_string(),
+            features: env!("ONEWEB_FEATURES")
+                .split(',')
+                .filter(|f| !f.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}, commit {}, built {}, features: {}",
+            env!("CARGO_PKG_NAME"),
+            self.version,
+            self.commit,
+            self.build_date,
+            if self.features.is_empty() {
+                "none".to_string()
+            } else {
+                self.features.join(",")
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_info() {
+        let build = BuildInfo::current();
+        assert_eq!(build.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(build.build_date.len(), 10);
+        let info = BuildInfo {
+            version: "0.1.0".to_string(),
+            commit: "0123456789ab".to_string(),
+            build_date: "2026-10-16".to_string(),
+            features: vec![],
+        };
+        assert_eq!(
+            info.to_string(),
+            "one-web-extractor 0.1.0, commit 0123456789ab, built 2026-10-16, features: none"
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use crate::utils::format_time;
+use anyhow::{Context, Result};
+use std::fmt;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Events of the same issue closer than this (in s) are grouped into one window
+pub const WINDOW_GAP: f64 = 600.0;
+
+/// Data quality problem found during the run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Issue {
+    /// Data line that could not be parsed or decoded
+    BadLine,
+    /// Data lines without a start of readout header were dropped
+    SyncLost,
+    /// Frame matched to a GPS record failing the validity checks
+    InvalidGps,
+    /// Frame with more hit pixels than the max pixel count
+    OverMaxPix,
+    /// Frame aborted by the max assembly time
+    OverlongFrame,
+}
+
+impl Issue {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Issue::BadLine => "bad_line",
+            Issue::SyncLost => "sync_lost",
+            Issue::InvalidGps => "invalid_gps",
+            Issue::OverMaxPix => "over_max_pix",
+            Issue::OverlongFrame => "overlong_frame",
+        }
+    }
+}
+
+/// Quality event at the time of the data line or frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityEvent {
+    pub issue: Issue,
+    pub timestamp: f64,
+    /// Issue specific value (hit pixels of the frame for OverMaxPix, assembly time in s for
+    /// OverlongFrame)
+    pub value: f64,
+}
+
+/// Time window with repeated occurrences of one issue
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReprocessWindow {
+    pub issue: Issue,
+    pub start: f64,
+    pub end: f64,
+    pub count: usize,
+    /// Options recommended for reprocessing the window
+    pub options: String,
+}
+
+impl fmt::Display for ReprocessWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let what = match self.issue {
+            Issue::BadLine => "undecodable data lines",
+            Issue::SyncLost => "frame sync lost",
+            Issue::InvalidGps => "frames with invalid GPS records",
+            Issue::OverMaxPix => "frames over the max pixel count",
+            Issue::OverlongFrame => "frames aborted by the max assembly time",
+        };
+        write!(
+            f,
+            "{} {} times between {} and {}; consider {}",
+            what,
+            self.count,
+            format_time(self.start),
+            format_time(self.end),
+            self.options
+        )
+    }
+}
+
+/// Settings of the run the suggestions are based on
+#[derive(Debug, Clone, Default)]
+pub struct RunSettings {
+    pub salvage: bool,
+    pub reject_invalid_gps: bool,
+}
+
+/// Quality events collected over the run
+#[derive(Debug, Clone, Default)]
+pub struct QualityLog {
+    pub events: Vec<QualityEvent>,
+}
+
+impl QualityLog {
+    pub fn record(&mut self, issue: Issue, timestamp: f64, value: f64) {
+        self.events.push(QualityEvent {
+            issue,
+            timestamp,
+            value,
+        });
+    }
+
+    pub fn merge(&mut self, other: &QualityLog) {
+        self.events.extend_from_slice(&other.events);
+    }
+
+    /// Alternate settings for the issue, None when the run already used them
+    fn options(issue: Issue, max_value: f64, settings: &RunSettings) -> Option<String> {
+        match issue {
+            Issue::BadLine | Issue::SyncLost if !settings.salvage => {
+                Some(String::from("--on-bad-line salvage"))
+            }
+            Issue::InvalidGps if !settings.reject_invalid_gps => {
+                Some(String::from("--reject-invalid-gps"))
+            }
+            Issue::OverMaxPix => Some(format!("--max-pix-count {}", max_value)),
+            Issue::OverlongFrame => Some(format!("--max-assembly-time {}", max_value.ceil())),
+            _ => None,
+        }
+    }
+
+    /// Groups the events of each issue into windows separated by more than `WINDOW_GAP`,
+    /// sorted by start time. Only windows with alternate settings to try are returned.
+    pub fn windows(&self, settings: &RunSettings) -> Vec<ReprocessWindow> {
+        let mut events = self.events.clone();
+        events.sort_by(|a, b| {
+            a.issue
+                .cmp(&b.issue)
+                .then(a.timestamp.total_cmp(&b.timestamp))
+        });
+
+        let mut windows = Vec::new();
+        let mut current: Option<(ReprocessWindow, f64)> = None;
+        for event in events {
+            match &mut current {
+                Some((window, max_value))
+                    if window.issue == event.issue
+                        && event.timestamp - window.end <= WINDOW_GAP =>
+                {
+                    window.end = event.timestamp;
+                    window.count += 1;
+                    *max_value = max_value.max(event.value);
+                }
+                _ => {
+                    if let Some(done) = current.take() {
+                        windows.push(done);
+                    }
+                    let window = ReprocessWindow {
+                        issue: event.issue,
+                        start: event.timestamp,
+                        end: event.timestamp,
+                        count: 1,
+                        options: String::new(),
+                    };
+                    current = Some((window, event.value));
+                }
+            }
+        }
+        windows.extend(current);
+        let mut windows: Vec<ReprocessWindow> = windows
+            .into_iter()
+            .filter_map(|(mut window, max_value)| {
+                window.options = Self::options(window.issue, max_value, settings)?;
+                Some(window)
+            })
+            .collect();
+        windows.sort_by(|a, b| a.start.total_cmp(&b.start));
+        windows
+    }
+}
+
+/// Writes the windows as CSV for automated reprocessing
+pub fn save_windows(windows: &[ReprocessWindow], path: &Path) -> Result<()> {
+    let mut writer = BufWriter::new(
+        fs::File::create(path)
+            .with_context(|| format!("cannot write reprocessing list {}", path.display()))?,
+    );
+    writeln!(writer, "start,end,issue,count,options")?;
+    for window in windows {
+        writeln!(
+            writer,
+            "{},{},{},{},{}",
+            format_time(window.start),
+            format_time(window.end),
+            window.issue.name(),
+            window.count,
+            window.options
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows() {
+        let mut log = QualityLog::default();
+        log.record(Issue::SyncLost, 1000.0, 0.0);
+        log.record(Issue::OverMaxPix, 1200.0, 2000.0);
+        log.record(Issue::SyncLost, 1500.0, 0.0);
+        let mut other = QualityLog::default();
+        other.record(Issue::SyncLost, 1100.0, 0.0);
+        other.record(Issue::OverMaxPix, 1300.0, 2500.0);
+        log.merge(&other);
+
+        let windows = log.windows(&RunSettings::default());
+        assert_eq!(windows.len(), 2);
+        assert_eq!((windows[0].start, windows[0].end), (1000.0, 1500.0));
+        assert_eq!(windows[0].count, 3);
+        assert_eq!(
+            windows[0].to_string(),
+            "frame sync lost 3 times between 1970-01-01 00:16:40.000 and 1970-01-01 00:25:00.000; \
+             consider --on-bad-line salvage"
+        );
+        assert_eq!(windows[1].options, "--max-pix-count 2500");
+        assert_eq!(windows[1].count, 2);
+
+        log.record(Issue::SyncLost, 2000.0, 0.0);
+        assert_eq!(log.windows(&RunSettings::default()).len(), 2);
+        log.record(Issue::SyncLost, 2601.0, 0.0);
+        assert_eq!(log.windows(&RunSettings::default()).len(), 3);
+
+        let settings = RunSettings {
+            salvage: true,
+            reject_invalid_gps: false,
+        };
+        assert_eq!(log.windows(&settings).len(), 1);
+    }
+}
@@ -0,0 +1,134 @@
+use std::io::{self, Read};
+use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
+use std::thread;
+
+/// Buffers handed between the reader thread and the consumer, one is filled while the
+/// other is consumed
+const BUFFERS: usize = 2;
+
+/// Reader filling chunks of the input on its own thread, so the decoder does not wait for
+/// slow storage (e.g. NFS) while the next chunk is read
+pub struct ReadAhead {
+    /// Filled chunks in input order, an empty chunk marks the end of input
+    full: Receiver<io::Result<Vec<u8>>>,
+    /// Consumed chunks returned to the reader thread
+    free: SyncSender<Vec<u8>>,
+    current: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl ReadAhead {
+    pub fn new<R: Read + Send + 'static>(mut inner: R, chunk_size: usize) -> Self {
+        let chunk_size = chunk_size.max(1);
+        let (full_sender, full) = sync_channel(BUFFERS);
+        let (free, free_receiver) = sync_channel::<Vec<u8>>(BUFFERS);
+        for _ in 0..BUFFERS {
+            let _ = free.send(Vec::with_capacity(chunk_size));
+        }
+        thread::spawn(move || {
+            // ends when the input ends or the consumer is dropped
+            while let Ok(mut buffer) = free_receiver.recv() {
+                buffer.resize(chunk_size, 0);
+                match read_chunk(&mut inner, &mut buffer) {
+                    Ok(len) => {
+                        buffer.truncate(len);
+                        if full_sender.send(Ok(buffer)).is_err() || len == 0 {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let _ = full_sender.send(Err(e));
+                        break;
+                    }
+                }
+            }
+        });
+        ReadAhead {
+            full,
+            free,
+            current: Vec::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+}
+
+/// Fills the buffer unless the input ends first, returns the number of bytes read
+fn read_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buffer.len() {
+        match reader.read(&mut buffer[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.current.len() {
+            if self.done {
+                return Ok(0);
+            }
+            let next = match self.full.recv() {
+                Ok(Ok(chunk)) => chunk,
+                Ok(Err(e)) => {
+                    self.done = true;
+                    return Err(e);
+                }
+                Err(_) => {
+                    self.done = true;
+                    return Err(io::Error::other("read-ahead thread stopped"));
+                }
+            };
+            self.done = next.is_empty();
+            let consumed = std::mem::replace(&mut self.current, next);
+            self.pos = 0;
+            let _ = self.free.try_send(consumed);
+        }
+        let len = buf.len().min(self.current.len() - self.pos);
+        buf[..len].copy_from_slice(&self.current[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Cursor};
+
+    struct FailingReader(usize);
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0 == 0 {
+                return Err(io::Error::other("NFS timeout"));
+            }
+            let len = buf.len().min(self.0);
+            buf[..len].fill(b'x');
+            self.0 -= len;
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        let text: String = (0..1000).map(|i| format!("line {}\n", i)).collect();
+        let reader = BufReader::new(ReadAhead::new(Cursor::new(text.clone()), 7));
+        let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines.len(), 1000);
+        assert_eq!(lines.join("\n") + "\n", text);
+
+        let mut content = Vec::new();
+        let err = ReadAhead::new(FailingReader(20), 8)
+            .read_to_end(&mut content)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "NFS timeout");
+        assert_eq!(content.len(), 16);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::binning::PositionBinning;
+use crate::clustering::Cluster;
+use crate::columns::MetaRow;
+use crate::day_stats::DayStats;
+use crate::direction;
+use crate::orbit::ReferenceFrame;
+use crate::provenance::BuildInfo;
+use crate::schema::{self, ClusterRecord, FrameRecord, StreamHeader, Value};
+use anyhow::{Result, bail};
+use serde::Serialize;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Encoding of the daily record streams
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// Sequence of CBOR data items (RFC 8742)
+    Cbor,
+    /// One JSON object per line
+    Jsonl,
+}
+
+impl RecordFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RecordFormat::Cbor => "cbor",
+            RecordFormat::Jsonl => "jsonl",
+        }
+    }
+
+    fn encode<W: Write, T: Serialize>(&self, writer: &mut W, item: &T) -> Result<()> {
+        match self {
+            RecordFormat::Cbor => ciborium::into_writer(item, writer)
+                .map_err(|e| anyhow::anyhow!("CBOR encoding: {}", e)),
+            RecordFormat::Jsonl => {
+                serde_json::to_writer(&mut *writer, item)?;
+                writeln!(writer)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for RecordFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "cbor" => Ok(RecordFormat::Cbor),
+            "jsonl" => Ok(RecordFormat::Jsonl),
+            _ => bail!("expected cbor or jsonl"),
+        }
+    }
+}
+
+impl fmt::Display for RecordFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+/// Writes the header record starting a daily record stream
+pub fn write_header<W: Write>(
+    writer: &mut W,
+    format: RecordFormat,
+    repro_hash: &str,
+    weighting: Option<String>,
+    position_frame: ReferenceFrame,
+    binning: PositionBinning,
+    day: Option<DayStats>,
+) -> Result<()> {
+    format.encode(
+        writer,
+        &StreamHeader {
+            schema_version: schema::SCHEMA_VERSION,
+            repro_hash: repro_hash.to_string(),
+            weighting,
+            position_frame: position_frame.to_string(),
+            position_binning: (!binning.is_none()).then(|| binning.to_string()),
+            build: Some(BuildInfo::current()),
+            day,
+        },
+    )
+}
+
+/// Appends the record of a frame
+pub fn write_frame<W: Write>(
+    writer: &mut W,
+    format: RecordFormat,
+    record: &FrameRecord,
+) -> Result<()> {
+    format.encode(writer, record)
+}
+
+/// Record of a frame, `number` and `timestamp` are the ones of the .clog frame header and
+/// `metadata` the names and values of the metadata columns
+pub fn frame_record(
+    number: usize,
+    timestamp: f64,
+    row: &MetaRow,
+    metadata: impl IntoIterator<Item = (String, String)>,
+    clusters: &[Cluster],
+) -> FrameRecord {
+    let attitude = if row.gps_missing || row.attitude_excluded {
+        None
+    } else {
+        row.gps.quaternion_normalized()
+    };
+    FrameRecord {
+        frame: number,
+        timestamp,
+        acq_time: row.acq_time,
+        metadata: metadata
+            .into_iter()
+            .map(|(name, value)| (name, Value::from(value)))
+            .collect(),
+        clusters: clusters
+            .iter()
+            .zip(row.orientation.clusters(clusters).iter())
+            .map(|(cluster, oriented)| {
+                // the directions are computed in the detector orientation
+                let mut record = ClusterRecord::new(oriented, row.kev_per_count);
+                let sc = direction::spacecraft_direction(cluster, &row.mounting);
+                record.direction_sc = Some(direction::angles(sc));
+                record.direction_j2000 =
+                    attitude.map(|q| direction::angles(direction::body_to_j2000(q, sc)));
+                record
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clustering::Pixel;
+    use crate::columns;
+    use crate::data_processor::Frame;
+    use crate::derived;
+    use crate::dose_equivalent::QualityFactor;
+    use crate::gps_processor::GpsData;
+    use crate::info_processor::MeasInfoData;
+    use crate::orientation::Orientation;
+    use crate::schema::StreamItem;
+
+    #[test]
+    fn test_record_stream() {
+        let frame = Frame::from_planes(Vec::new(), Vec::new(), 1709251501.3);
+        let info = MeasInfoData {
+            timestamp: 1709251500.0,
+            temp: -4.5,
+            ..Default::default()
+        };
+        let gps = GpsData::default();
+        let row = MetaRow {
+            frame_index: 3,
+            frame_number: 42,
+            frame: &frame,
+            info: &info,
+            gps: &gps,
+            gps_missing: true,
+            info_missing: false,
+            acq_time: 2.5,
+            kev_per_count: 1.0,
+            quality_factor: QualityFactor::default(),
+            mounting: direction::IDENTITY,
+            maneuver: false,
+            attitude_excluded: false,
+            tle_residual: None,
+            mode: None,
+            orientation: Orientation::Detector,
+            binning: PositionBinning::default(),
+            interpolated: None,
+            gps_columns: columns::GpsColumns::Nearest,
+            velocity: None,
+            pointing_frame: direction::PointingFrame::J2000,
+        };
+        let columns = columns::resolve(&["frame_index", "temp", "gps_x"]).unwrap();
+        let derived = derived::resolve(&["temp_k = temp + 273.15"]).unwrap();
+        let clusters = [Cluster {
+            pixels: vec![Pixel::new(10, 20, 300, 7), Pixel::new(11, 20, 5, 7)],
+            merged: 0,
+        }];
+
+        let mut cbor = Vec::new();
+        let mut jsonl = Vec::new();
+        for (format, stream) in [
+            (RecordFormat::Cbor, &mut cbor),
+            (RecordFormat::Jsonl, &mut jsonl),
+        ] {
+            write_header(
+                stream,
+                format,
+                "abc",
+                None,
+                ReferenceFrame::Teme,
+                "lat=1,lon=1".parse().unwrap(),
+                None,
+            )
+            .unwrap();
+            let record = frame_record(
+                3,
+                info.timestamp,
+                &row,
+                columns
+                    .iter()
+                    .map(|c| (c.name.to_string(), c.format(&row)))
+                    .chain(derived.iter().map(|c| (c.name.clone(), c.format(&row)))),
+                &clusters,
+            );
+            write_frame(stream, format, &record).unwrap();
+        }
+
+        let mut reader = cbor.as_slice();
+        let header: StreamItem = ciborium::from_reader(&mut reader).unwrap();
+        let record: StreamItem = ciborium::from_reader(&mut reader).unwrap();
+        assert!(reader.is_empty());
+        let jsonl = String::from_utf8(jsonl).unwrap();
+        let lines: Vec<&str> = jsonl.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            serde_json::from_str::<StreamItem>(lines[0]).unwrap(),
+            header
+        );
+        assert_eq!(
+            serde_json::from_str::<StreamItem>(lines[1]).unwrap(),
+            record
+        );
+
+        let StreamItem::Header(header) = header else {
+            panic!("expected the stream header");
+        };
+        assert_eq!(header.schema_version, schema::SCHEMA_VERSION);
+        assert_eq!(header.position_frame, "teme");
+        assert_eq!(header.position_binning.as_deref(), Some("lat=1,lon=1"));
+        let StreamItem::Frame(record) = record else {
+            panic!("expected a frame record");
+        };
+        assert_eq!((record.frame, record.timestamp), (3, 1709251500.0));
+        assert_eq!(record.metadata["frame_index"], Value::Integer(3));
+        assert_eq!(record.metadata["temp"], Value::Float(-4.5));
+        assert_eq!(record.metadata["gps_x"], Value::Null);
+        assert_eq!(record.metadata["temp_k"], Value::Float(268.65));
+        assert_eq!(record.clusters[0].pixels[0], [10, 20, 300, 7]);
+        assert_eq!(record.clusters[0].energy, 305.0);
+        // the two pixel track lies along the detector x axis, no attitude without GPS
+        let [azimuth, elevation] = record.clusters[0].direction_sc.unwrap();
+        assert!(azimuth.abs() < 1e-9 && elevation > 0.0 && elevation < 90.0);
+        assert_eq!(record.clusters[0].direction_j2000, None);
+    }
+}
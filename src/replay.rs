@@ -0,0 +1,214 @@
+use crate::data_processor::{DataProcessor, Frame, PacketLayout, SentinelPolicy};
+use crate::index;
+use crate::input::InputFile;
+use crate::line_reader::LineReader;
+use crate::toa_calibration::ToaCalibration;
+use crate::tpx3lut::MATRIX_SIZE;
+use crate::utils::format_time;
+use anyhow::{Context, Result, bail};
+use std::fmt;
+use std::io::{BufReader, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Decoder setting of a replay, `key=value`
+#[derive(Debug, Clone)]
+pub enum DecoderOption {
+    Firmware(PacketLayout),
+    LutSentinels(SentinelPolicy),
+    MergeDistance(u8),
+    ToaCalibration(String),
+}
+
+impl DecoderOption {
+    pub fn apply(&self, data_processor: &mut DataProcessor) -> Result<()> {
+        match self {
+            DecoderOption::Firmware(layout) => data_processor.layout = *layout,
+            DecoderOption::LutSentinels(policy) => data_processor.sentinel_policy = *policy,
+            DecoderOption::MergeDistance(distance) => {
+                data_processor.merge_distance = Some(*distance)
+            }
+            DecoderOption::ToaCalibration(path) => {
+                data_processor.toa_calibration =
+                    Some(Arc::new(ToaCalibration::load(Path::new(path))?))
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DecoderOption {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some((key, value)) = s.split_once('=') else {
+            bail!("expected key=value");
+        };
+        let invalid = || format!("invalid {} '{}'", key, value);
+        Ok(match key {
+            "firmware" => DecoderOption::Firmware(value.parse().with_context(invalid)?),
+            "lut-sentinels" => DecoderOption::LutSentinels(value.parse().with_context(invalid)?),
+            "merge-distance" => DecoderOption::MergeDistance(value.parse().with_context(invalid)?),
+            "toa-calibration" => DecoderOption::ToaCalibration(value.to_string()),
+            _ => bail!(
+                "unknown decoder option '{}', expected firmware, lut-sentinels, merge-distance \
+                 or toa-calibration",
+                key
+            ),
+        })
+    }
+}
+
+impl fmt::Display for DecoderOption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecoderOption::Firmware(layout) => write!(f, "firmware={}", layout),
+            DecoderOption::LutSentinels(policy) => write!(f, "lut-sentinels={}", policy),
+            DecoderOption::MergeDistance(distance) => write!(f, "merge-distance={}", distance),
+            DecoderOption::ToaCalibration(path) => write!(f, "toa-calibration={}", path),
+        }
+    }
+}
+
+/// Pixel decoded differently by the baseline and the replay: index, iToT and event value
+/// of both, 0 when the pixel is not hit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelDiff {
+    pub idx: usize,
+    pub baseline: (u16, u16),
+    pub replay: (u16, u16),
+}
+
+/// Pixels whose iToT or event value differs between the frames
+pub fn diff_pixels(baseline: &Frame, replay: &Frame) -> Vec<PixelDiff> {
+    let value = |frame: &Frame, idx: usize| (frame.itot()[idx], frame.event()[idx]);
+    (0..MATRIX_SIZE)
+        .map(|idx| PixelDiff {
+            idx,
+            baseline: value(baseline, idx),
+            replay: value(replay, idx),
+        })
+        .filter(|diff| diff.baseline != diff.replay)
+        .collect()
+}
+
+/// Pixel, cluster and payload byte counts of the frame
+fn frame_stats(frame: &Frame) -> [(&'static str, usize); 6] {
+    [
+        (
+            "Hit pixels",
+            frame.itot().iter().filter(|&&v| v != 0).count(),
+        ),
+        ("Invalid pixels", frame.invalid().count()),
+        ("Saturated pixels", frame.saturated().count()),
+        ("Clusters", frame.clusters.len()),
+        ("Packet bytes", frame.bytes.packets),
+        ("Discarded bytes", frame.bytes.discarded),
+    ]
+}
+
+/// Decodes the frame of the raw payload file (1-based number) with both processors, prints
+/// their statistics side by side and the pixels they decode differently, returns the number
+/// of those pixels
+pub fn replay_frame<W: Write>(
+    data_file: &str,
+    frame_no: usize,
+    baseline: &mut DataProcessor,
+    replay: &mut DataProcessor,
+    options: &[DecoderOption],
+    writer: &mut W,
+) -> Result<usize> {
+    let (entries, _) = index::index_frames(&mut BufReader::new(InputFile::open(data_file)?))?;
+    let Some(entry) = frame_no.checked_sub(1).and_then(|i| entries.get(i)) else {
+        bail!(
+            "no frame {} in {} ({} frames)",
+            frame_no,
+            data_file,
+            entries.len()
+        );
+    };
+    let decode = |data_processor: &mut DataProcessor| -> Result<(Frame, String)> {
+        let mut reader = LineReader::open_at(data_file, entry.offset, entry.line_no - 1)?;
+        let location = format!("{}:{}", reader.source(), entry.line_no);
+        Ok((data_processor.get_next_frame(&mut reader)?, location))
+    };
+    let (old, location) = decode(baseline)?;
+    let (new, _) = decode(replay)?;
+
+    writeln!(writer, "Frame: {} of {}", frame_no, entries.len())?;
+    writeln!(writer, "Source: {}", location)?;
+    writeln!(writer, "Frame time: {}", format_time(old.timestamp))?;
+    let options: Vec<String> = options.iter().map(|o| o.to_string()).collect();
+    writeln!(writer, "Replay options: {}", options.join(" "))?;
+    writeln!(writer)?;
+    writeln!(writer, "\tBaseline\tReplay")?;
+    for ((name, a), (_, b)) in frame_stats(&old).into_iter().zip(frame_stats(&new)) {
+        writeln!(writer, "{}\t{}\t{}", name, a, b)?;
+    }
+
+    let diffs = diff_pixels(&old, &new);
+    writeln!(writer)?;
+    writeln!(writer, "Pixel differences: {}", diffs.len())?;
+    if !diffs.is_empty() {
+        writeln!(
+            writer,
+            "X\tY\tBaseline iToT\tBaseline Event\tReplay iToT\tReplay Event"
+        )?;
+        for diff in &diffs {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                diff.idx % 256,
+                diff.idx / 256,
+                diff.baseline.0,
+                diff.baseline.1,
+                diff.replay.0,
+                diff.replay.1
+            )?;
+        }
+    }
+    Ok(diffs.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::Generator;
+
+    #[test]
+    fn test_replay_frame() {
+        let path = std::env::temp_dir().join(format!("oneweb-replay-{}.csv", std::process::id()));
+        let mut generator = Generator::new(3, PacketLayout::Standard);
+        let frames = [generator.random_frame(), generator.random_frame()];
+        std::fs::write(&path, generator.render(&frames)).unwrap();
+        let data_file = path.to_str().unwrap();
+
+        let options: Vec<DecoderOption> = vec!["firmware=swapped".parse().unwrap()];
+        let mut baseline = DataProcessor::new();
+        let mut replay = DataProcessor::new();
+        for option in &options {
+            option.apply(&mut replay).unwrap();
+        }
+        let mut out = Vec::new();
+        let diffs =
+            replay_frame(data_file, 2, &mut baseline, &mut replay, &options, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(diffs > 0);
+        assert!(out.starts_with("Frame: 2 of 2\n"));
+        assert!(out.contains("Replay options: firmware=swapped\n"));
+        assert!(out.contains(&format!("Pixel differences: {}\n", diffs)));
+
+        // the same settings decode the same pixels
+        let mut same = DataProcessor::new();
+        let diffs =
+            replay_frame(data_file, 1, &mut baseline, &mut same, &[], &mut Vec::new()).unwrap();
+        assert_eq!(diffs, 0);
+        assert!(
+            replay_frame(data_file, 3, &mut baseline, &mut same, &[], &mut Vec::new()).is_err()
+        );
+        assert!("layout=swapped".parse::<DecoderOption>().is_err());
+        assert!("merge-distance=far".parse::<DecoderOption>().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+pub const REPRO_HASH_PREFIX: &str = "# repro_hash: ";
+
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path).with_context(|| format!("{}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Deterministic hash of the decoder version, the configuration fingerprint and the
+/// content of all input files
+pub fn run_hash<P: AsRef<Path>>(config_fingerprint: &str, inputs: &[P]) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("version={}\n", env!("CARGO_PKG_VERSION")));
+    hasher.update(config_fingerprint);
+    for input in inputs {
+        hasher.update(sha256_file(input.as_ref())?);
+        hasher.update("\n");
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Reads the hash embedded in the first line of an output file, binary outputs have none
+pub fn read_embedded_hash(path: &Path) -> Result<Option<String>> {
+    let file = fs::File::open(path).with_context(|| format!("{}", path.display()))?;
+    let mut line = Vec::new();
+    io::BufReader::new(file).read_until(b'\n', &mut line)?;
+    Ok(String::from_utf8_lossy(&line)
+        .trim_end()
+        .strip_prefix(REPRO_HASH_PREFIX)
+        .map(|hash| hash.to_string()))
+}
+
+/// Compares the files produced in `reproduced` with the same named files in `original`,
+/// returns the list of differences
+pub fn compare_outputs(original: &Path, reproduced: &Path, run_hash: &str) -> Result<Vec<String>> {
+    let mut names: Vec<_> = fs::read_dir(reproduced)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| entry.file_name())
+        .collect();
+    names.sort();
+
+    let mut mismatches = Vec::new();
+    for name in names {
+        let original_file = original.join(&name);
+        let name = name.to_string_lossy();
+        if !original_file.exists() {
+            mismatches.push(format!("{}: missing in archive", name));
+            continue;
+        }
+        if let Some(hash) = read_embedded_hash(&original_file)?
+            && hash != run_hash
+        {
+            mismatches.push(format!(
+                "{}: repro hash {} != {} (different inputs or configuration)",
+                name, hash, run_hash
+            ));
+            continue;
+        }
+        let hash_orig = sha256_file(&original_file)?;
+        let hash_repro = sha256_file(&reproduced.join(name.as_ref()))?;
+        if hash_orig != hash_repro {
+            mismatches.push(format!(
+                "{}: sha256 {} != reproduced {}",
+                name, hash_orig, hash_repro
+            ));
+        }
+    }
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_outputs() {
+        let dir = std::env::temp_dir().join(format!("oneweb-repro-{}", std::process::id()));
+        let orig = dir.join("orig");
+        let repro = dir.join("repro");
+        fs::create_dir_all(&orig).unwrap();
+        fs::create_dir_all(&repro).unwrap();
+        fs::write(orig.join("a.info"), "# repro_hash: abc\n1\t2\n").unwrap();
+        fs::write(repro.join("a.info"), "# repro_hash: abc\n1\t2\n").unwrap();
+        fs::write(orig.join("b.clog"), "Frame 1\n").unwrap();
+        fs::write(repro.join("b.clog"), "Frame 2\n").unwrap();
+        fs::write(repro.join("c.clog"), "Frame 1\n").unwrap();
+
+        let mismatches = compare_outputs(&orig, &repro, "abc").unwrap();
+        assert_eq!(mismatches.len(), 2);
+        assert!(mismatches[0].starts_with("b.clog: sha256"));
+        assert_eq!(mismatches[1], "c.clog: missing in archive");
+
+        let mismatches = compare_outputs(&orig, &repro, "abd").unwrap();
+        assert_eq!(mismatches.len(), 3);
+        assert!(mismatches[0].starts_with("a.info: repro hash abc != abd"));
+        assert_eq!(
+            read_embedded_hash(&orig.join("a.info")).unwrap(),
+            Some("abc".to_string())
+        );
+        assert_eq!(read_embedded_hash(&orig.join("b.clog")).unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
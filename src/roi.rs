@@ -0,0 +1,319 @@
+use crate::data_processor::Frame;
+use crate::dosimetry::{self, DoseMap};
+use crate::event_display;
+use crate::tpx3lut::WRONG_LUT_ITOT;
+use anyhow::{Context, Result, bail};
+use std::fmt;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Cluster energy spectrum bins per decade, starting at 1 keV
+const SPECTRUM_BINS_PER_DECADE: usize = 10;
+/// Spectrum bins covering 1 keV to 100 MeV, the last bin also collects higher energies
+pub const SPECTRUM_BINS: usize = 5 * SPECTRUM_BINS_PER_DECADE;
+
+/// Rectangular region of the pixel matrix, the corners are inclusive
+#[derive(Debug, Clone, PartialEq)]
+pub struct Roi {
+    pub name: String,
+    pub x_min: u8,
+    pub y_min: u8,
+    pub x_max: u8,
+    pub y_max: u8,
+}
+
+impl Roi {
+    pub fn contains(&self, x: u8, y: u8) -> bool {
+        (self.x_min..=self.x_max).contains(&x) && (self.y_min..=self.y_max).contains(&y)
+    }
+
+    pub fn pixels(&self) -> usize {
+        (self.x_max as usize - self.x_min as usize + 1)
+            * (self.y_max as usize - self.y_min as usize + 1)
+    }
+
+    /// Matrix indices of the ROI pixels
+    fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        (self.y_min as usize..=self.y_max as usize)
+            .flat_map(|y| (self.x_min as usize..=self.x_max as usize).map(move |x| y * 256 + x))
+    }
+
+    /// Mean cumulative dose of the ROI pixels in the dose map
+    pub fn mean_dose(&self, dose_map: &DoseMap) -> f64 {
+        self.indices().map(|idx| dose_map.dose[idx]).sum::<f64>() / self.pixels() as f64
+    }
+}
+
+impl FromStr for Roi {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // name:x1,y1,x2,y2
+        let Some((name, corners)) = s.split_once(':') else {
+            bail!("expected name:x1,y1,x2,y2");
+        };
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            bail!("invalid ROI name '{}'", name);
+        }
+        let parts: Vec<u8> = corners
+            .split(',')
+            .map(|p| p.trim().parse::<u8>())
+            .collect::<Result<_, _>>()
+            .context("pixel coordinates must be 0-255")?;
+        if parts.len() != 4 {
+            bail!("expected name:x1,y1,x2,y2");
+        }
+        Ok(Roi {
+            name: name.to_string(),
+            x_min: parts[0].min(parts[2]),
+            y_min: parts[1].min(parts[3]),
+            x_max: parts[0].max(parts[2]),
+            y_max: parts[1].max(parts[3]),
+        })
+    }
+}
+
+impl fmt::Display for Roi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{},{},{},{}",
+            self.name, self.x_min, self.y_min, self.x_max, self.y_max
+        )
+    }
+}
+
+/// Sampling weighted counts, energy and cluster spectrum of one ROI
+#[derive(Debug, Clone)]
+pub struct RoiStats {
+    pub hits: f64,
+    /// Clusters with the centroid inside the ROI
+    pub clusters: f64,
+    /// Deposited energy in keV
+    pub energy: f64,
+    /// Energy spectrum of the clusters without saturated pixels
+    pub spectrum: Vec<f64>,
+    /// Clusters with saturated pixels, their energy is a lower bound and they are not in the spectrum
+    pub saturated: f64,
+}
+
+impl Default for RoiStats {
+    fn default() -> Self {
+        RoiStats {
+            hits: 0.0,
+            clusters: 0.0,
+            energy: 0.0,
+            spectrum: vec![0.0; SPECTRUM_BINS],
+            saturated: 0.0,
+        }
+    }
+}
+
+/// Lower edge of the spectrum bin in keV
+pub fn bin_edge(bin: usize) -> f64 {
+    10f64.powf(bin as f64 / SPECTRUM_BINS_PER_DECADE as f64)
+}
+
+pub fn spectrum_bin(energy: f64) -> usize {
+    let bin = (energy.max(1.0).log10() * SPECTRUM_BINS_PER_DECADE as f64).floor() as usize;
+    bin.min(SPECTRUM_BINS - 1)
+}
+
+/// Per-ROI statistics accumulated over the run
+#[derive(Debug, Clone)]
+pub struct RoiReport {
+    pub rois: Vec<Roi>,
+    pub stats: Vec<RoiStats>,
+    pub frames: u64,
+    /// Exposure time in s, sampling weighted
+    pub exposure: f64,
+    /// Sampling weighting scheme of the accumulated frames
+    pub weighting: String,
+}
+
+impl RoiReport {
+    pub fn new(rois: &[Roi], weighting: &str) -> Self {
+        RoiReport {
+            rois: rois.to_vec(),
+            stats: vec![RoiStats::default(); rois.len()],
+            frames: 0,
+            exposure: 0.0,
+            weighting: weighting.to_string(),
+        }
+    }
+
+    /// Adds the frame hits and clusters scaled by the sampling weight of the frame,
+    /// `kev_per_count` converts iToT to keV
+    pub fn add_frame(&mut self, frame: &Frame, kev_per_count: f64, acq_time: f64, weight: f64) {
+        for (roi, stats) in self.rois.iter().zip(&mut self.stats) {
+            for idx in roi.indices() {
+                let value = frame.itot()[idx];
+                if value == 0 || value == WRONG_LUT_ITOT {
+                    continue;
+                }
+                stats.hits += weight;
+                stats.energy += weight * value as f64 * kev_per_count;
+            }
+            for cluster in &frame.clusters {
+                let n = cluster.pixels.len() as f64;
+                let cx = cluster.pixels.iter().map(|p| p.x as f64).sum::<f64>() / n;
+                let cy = cluster.pixels.iter().map(|p| p.y as f64).sum::<f64>() / n;
+                if !roi.contains(cx.round() as u8, cy.round() as u8) {
+                    continue;
+                }
+                stats.clusters += weight;
+                if cluster.saturation() > 0.0 {
+                    stats.saturated += weight;
+                    continue;
+                }
+                let energy = event_display::cluster_energy(cluster, kev_per_count);
+                stats.spectrum[spectrum_bin(energy)] += weight;
+            }
+        }
+        self.frames += 1;
+        self.exposure += weight * acq_time;
+    }
+
+    pub fn merge(&mut self, other: &RoiReport) {
+        for (stats, other) in self.stats.iter_mut().zip(&other.stats) {
+            stats.hits += other.hits;
+            stats.clusters += other.clusters;
+            stats.energy += other.energy;
+            stats.saturated += other.saturated;
+            for (count, other) in stats.spectrum.iter_mut().zip(&other.spectrum) {
+                *count += other;
+            }
+        }
+        self.frames += other.frames;
+        self.exposure += other.exposure;
+    }
+
+    /// Mean absorbed dose of the ROI in Gy
+    pub fn dose(&self, i: usize) -> f64 {
+        let mass = dosimetry::pixel_mass() * self.rois[i].pixels() as f64;
+        dosimetry::dose_gy(self.stats[i].energy, mass)
+    }
+
+    /// Writes the ROI table followed by the cluster energy spectra
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(
+            fs::File::create(path)
+                .with_context(|| format!("cannot write ROI report {}", path.display()))?,
+        );
+        writeln!(
+            writer,
+            "# roi report, frames: {}, exposure: {}, weighting: {}",
+            self.frames, self.exposure, self.weighting
+        )?;
+        writeln!(
+            writer,
+            "# name x_min y_min x_max y_max pixels hits clusters saturated_clusters energy[keV] dose[Gy] dose_rate[Gy/s]"
+        )?;
+        for (i, (roi, stats)) in self.rois.iter().zip(&self.stats).enumerate() {
+            let dose = self.dose(i);
+            let rate = if self.exposure > 0.0 {
+                dose / self.exposure
+            } else {
+                0.0
+            };
+            writeln!(
+                writer,
+                "{} {} {} {} {} {} {} {} {} {} {:e} {:e}",
+                roi.name,
+                roi.x_min,
+                roi.y_min,
+                roi.x_max,
+                roi.y_max,
+                roi.pixels(),
+                stats.hits,
+                stats.clusters,
+                stats.saturated,
+                stats.energy,
+                dose,
+                rate
+            )?;
+        }
+        let names: Vec<&str> = self.rois.iter().map(|r| r.name.as_str()).collect();
+        writeln!(
+            writer,
+            "# cluster energy spectrum (without saturated clusters)"
+        )?;
+        writeln!(writer, "# energy_min[keV] {}", names.join(" "))?;
+        for bin in 0..SPECTRUM_BINS {
+            let counts: Vec<String> = self
+                .stats
+                .iter()
+                .map(|s| s.spectrum[bin].to_string())
+                .collect();
+            writeln!(writer, "{:.4} {}", bin_edge(bin), counts.join(" "))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clustering::{Cluster, Pixel};
+    use crate::tpx3lut::MATRIX_SIZE;
+
+    #[test]
+    fn test_parse_roi() {
+        let roi: Roi = "shielded:127,0,0,255".parse().unwrap();
+        assert_eq!(
+            (roi.x_min, roi.y_min, roi.x_max, roi.y_max),
+            (0, 0, 127, 255)
+        );
+        assert_eq!(roi.pixels(), 128 * 256);
+        assert_eq!(roi.to_string(), "shielded:0,0,127,255");
+        assert!(roi.contains(127, 3) && !roi.contains(128, 3));
+        assert!("open:0,0,256,10".parse::<Roi>().is_err());
+        assert!("0,0,10,10".parse::<Roi>().is_err());
+        assert!(":0,0,10,10".parse::<Roi>().is_err());
+    }
+
+    #[test]
+    fn test_roi_report() {
+        let rois = ["left:0,0,127,255", "corner:0,0,0,0"].map(|s| s.parse::<Roi>().unwrap());
+        let mut report = RoiReport::new(&rois, "none");
+        let mut itot = vec![0u16; MATRIX_SIZE];
+        itot[0] = 50;
+        itot[1] = 50;
+        itot[200] = 30;
+        let mut frame = Frame::from_planes(itot, vec![0; MATRIX_SIZE], 0.0);
+        frame.clusters = vec![
+            Cluster {
+                pixels: vec![Pixel::new(0, 0, 50, 1), Pixel::new(1, 0, 50, 1)],
+                merged: 0,
+            },
+            Cluster {
+                pixels: vec![Pixel::new(200, 0, 30, 1)],
+                merged: 0,
+            },
+            Cluster {
+                pixels: vec![Pixel::new(10, 10, 16382, 1)],
+                merged: 0,
+            },
+        ];
+        frame.clusters[2].pixels[0].saturated = true;
+        report.add_frame(&frame, 1.0, 2.0, 1.0);
+        let mut other = RoiReport::new(&rois, "none");
+        other.add_frame(&frame, 1.0, 2.0, 3.0);
+        report.merge(&other);
+
+        assert_eq!(report.stats[0].hits, 8.0);
+        assert_eq!(report.stats[0].clusters, 8.0);
+        assert_eq!(report.stats[0].saturated, 4.0);
+        assert_eq!(report.stats[0].energy, 400.0);
+        assert_eq!(report.stats[0].spectrum[spectrum_bin(100.0)], 4.0);
+        assert_eq!(report.stats[1].hits, 4.0);
+        assert_eq!(report.stats[1].clusters, 0.0);
+        assert_eq!(report.exposure, 8.0);
+        let expected = dosimetry::dose_gy(200.0, dosimetry::pixel_mass());
+        assert!((report.dose(1) - expected).abs() < 1e-15);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::clustering::Cluster;
+use crate::day_stats::DayStats;
+use crate::event_display;
+use crate::provenance::BuildInfo;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Version of the record schema. Adding an optional field keeps the version, consumers
+/// ignore fields they do not know; removing, renaming or retyping a field increments it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// First record of a stream, identifies the run and the schema version of the records
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct StreamHeader {
+    pub schema_version: u32,
+    /// Hash of the decoder version, configuration and inputs (as in the .clog/.info headers)
+    pub repro_hash: String,
+    /// Sampling weighting of the frames when decimating
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weighting: Option<String>,
+    /// Reference frame of the position columns (j2000, teme or itrf)
+    pub position_frame: String,
+    /// Bin widths the geodetic position and L-shell columns are rounded to, e.g. lat=0.1,lon=0.1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position_binning: Option<String>,
+    /// Decoder build that wrote the stream
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<BuildInfo>,
+    /// Frames, time coverage and dose of the stream, from the first pass of `--two-pass`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub day: Option<DayStats>,
+}
+
+/// Metadata column value, missing values are null
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        if text.is_empty() {
+            Value::Null
+        } else if let Ok(value) = text.parse::<i64>() {
+            Value::Integer(value)
+        } else if let Ok(value) = text.parse::<f64>() {
+            Value::Float(value)
+        } else {
+            Value::Text(text)
+        }
+    }
+}
+
+/// Cluster with its features
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ClusterRecord {
+    /// Pixels as [x, y, value, value2] like in the .clog file
+    pub pixels: Vec<[u16; 4]>,
+    /// Deposited energy in keV
+    pub energy: f64,
+    /// Centroid column
+    pub x: f64,
+    /// Centroid row
+    pub y: f64,
+    pub max_value: u16,
+    /// Morphology class (dot, small blob, curly track, heavy blob, straight track) or
+    /// saturated for clusters with saturated pixels
+    pub label: String,
+    /// Number of clusters merged into this one by the merge distance
+    pub merged: usize,
+    /// Fraction of the pixels with a saturated iToT counter, the energy is a lower bound
+    #[serde(default)]
+    pub saturation: f64,
+    /// Arrival direction [azimuth, elevation] in degrees in the spacecraft frame, the sense
+    /// along the track is ambiguous
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction_sc: Option<[f64; 2]>,
+    /// Arrival direction [right ascension, declination] in degrees in J2000, missing without
+    /// an attitude
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction_j2000: Option<[f64; 2]>,
+}
+
+impl ClusterRecord {
+    pub fn new(cluster: &Cluster, kev_per_count: f64) -> Self {
+        let n = cluster.pixels.len() as f64;
+        ClusterRecord {
+            pixels: cluster
+                .pixels
+                .iter()
+                .map(|p| [p.x as u16, p.y as u16, p.value, p.value2])
+                .collect(),
+            energy: event_display::cluster_energy(cluster, kev_per_count),
+            x: cluster.pixels.iter().map(|p| p.x as f64).sum::<f64>() / n,
+            y: cluster.pixels.iter().map(|p| p.y as f64).sum::<f64>() / n,
+            max_value: cluster.pixels.iter().map(|p| p.value).max().unwrap_or(0),
+            label: event_display::classify(cluster, kev_per_count).to_string(),
+            merged: cluster.merged,
+            saturation: cluster.saturation(),
+            direction_sc: None,
+            direction_j2000: None,
+        }
+    }
+}
+
+/// Written frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FrameRecord {
+    /// Frame number as in the .clog file
+    pub frame: usize,
+    /// Measurement info time (unix s) as in the .clog file
+    pub timestamp: f64,
+    /// Acquisition time in s
+    pub acq_time: f64,
+    /// Configured .info columns by name
+    pub metadata: BTreeMap<String, Value>,
+    pub clusters: Vec<ClusterRecord>,
+}
+
+/// Item of a record stream: the header followed by the frames
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum StreamItem {
+    Header(StreamHeader),
+    Frame(FrameRecord),
+}
+
+/// JSON Schema of the stream items
+pub fn json_schema() -> serde_json::Value {
+    let mut schema = schemars::schema_for!(StreamItem);
+    schema.insert(
+        String::from("title"),
+        format!("one-web-extractor record stream v{}", SCHEMA_VERSION).into(),
+    );
+    schema.to_value()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clustering::Pixel;
+
+    #[test]
+    fn test_schema() {
+        let cluster = Cluster {
+            pixels: vec![Pixel::new(10, 20, 300, 7), Pixel::new(11, 20, 100, 7)],
+            merged: 0,
+        };
+        let record = ClusterRecord::new(&cluster, 2.0);
+        assert_eq!(record.pixels[1], [11, 20, 100, 7]);
+        assert_eq!(
+            (record.energy, record.x, record.max_value),
+            (800.0, 10.5, 300)
+        );
+
+        assert_eq!(Value::from(String::from("3")), Value::Integer(3));
+        assert_eq!(Value::from(String::from("-4.5")), Value::Float(-4.5));
+        assert_eq!(Value::from(String::new()), Value::Null);
+        assert_eq!(
+            Value::from(String::from("saa")),
+            Value::Text(String::from("saa"))
+        );
+
+        let item: StreamItem = serde_json::from_str(
+            r#"{"schema_version": 1, "repro_hash": "abc", "position_frame": "j2000", "new_field": 1}"#,
+        )
+        .unwrap();
+        assert!(matches!(item, StreamItem::Header(h) if h.weighting.is_none()));
+
+        let schema = json_schema();
+        assert_eq!(
+            schema["title"],
+            format!("one-web-extractor record stream v{}", SCHEMA_VERSION)
+        );
+        let defs = schema["$defs"].as_object().unwrap();
+        assert!(defs.contains_key("FrameRecord") && defs.contains_key("ClusterRecord"));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::clustering::Cluster;
+use crate::event_display;
+use crate::info_processor::MeasInfoData;
+use crate::utils::format_time;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Temperature change between consecutive measurement info records reported as an anomaly
+pub const TEMP_JUMP: f64 = 2.0;
+
+/// Housekeeping anomaly found in the measurement info stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anomaly {
+    pub timestamp: f64,
+    pub description: String,
+}
+
+/// Temperature jumps and newly reported error ids of the time ordered records
+pub fn find_anomalies(records: &[MeasInfoData]) -> Vec<Anomaly> {
+    let mut anomalies = Vec::new();
+    for pair in records.windows(2) {
+        let (prev, cur) = (&pair[0], &pair[1]);
+        if (cur.temp - prev.temp).abs() >= TEMP_JUMP {
+            anomalies.push(Anomaly {
+                timestamp: cur.timestamp,
+                description: format!("temperature jump {} -> {}", prev.temp, cur.temp),
+            });
+        }
+        let error_id = cur.error_id.trim().trim_matches('"');
+        if !error_id.is_empty() && cur.error_id != prev.error_id {
+            anomalies.push(Anomaly {
+                timestamp: cur.timestamp,
+                description: format!("error_id {}", error_id),
+            });
+        }
+    }
+    anomalies
+}
+
+/// Cluster above the energy threshold
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeavyCluster {
+    pub timestamp: f64,
+    /// Deposited energy in keV
+    pub energy: f64,
+    pub x: u8,
+    pub y: u8,
+    pub label: &'static str,
+}
+
+/// Candidate single event effect: a heavy cluster close in time to an anomaly
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coincidence {
+    pub cluster: HeavyCluster,
+    pub anomaly: Anomaly,
+}
+
+/// Heavy clusters of the run correlated with the housekeeping anomalies at the end
+#[derive(Debug, Clone, Default)]
+pub struct SeeAnalysis {
+    /// Cluster energy threshold in keV
+    pub threshold: f64,
+    /// Maximum time between the cluster and the anomaly in s
+    pub window: f64,
+    pub anomalies: Vec<Anomaly>,
+    pub clusters: Vec<HeavyCluster>,
+}
+
+impl SeeAnalysis {
+    pub fn new(threshold: f64, window: f64) -> Self {
+        SeeAnalysis {
+            threshold,
+            window,
+            ..Default::default()
+        }
+    }
+
+    pub fn add_frame(&mut self, clusters: &[Cluster], timestamp: f64, kev_per_count: f64) {
+        for cluster in clusters {
+            let energy = event_display::cluster_energy(cluster, kev_per_count);
+            if energy < self.threshold {
+                continue;
+            }
+            let n = cluster.pixels.len() as f64;
+            self.clusters.push(HeavyCluster {
+                timestamp,
+                energy,
+                x: (cluster.pixels.iter().map(|p| p.x as f64).sum::<f64>() / n).round() as u8,
+                y: (cluster.pixels.iter().map(|p| p.y as f64).sum::<f64>() / n).round() as u8,
+                label: event_display::classify(cluster, kev_per_count),
+            });
+        }
+    }
+
+    pub fn merge(&mut self, other: &SeeAnalysis) {
+        self.clusters.extend_from_slice(&other.clusters);
+    }
+
+    /// Pairs of heavy clusters and anomalies within the window, ordered by cluster time
+    pub fn coincidences(&self) -> Vec<Coincidence> {
+        let mut clusters = self.clusters.clone();
+        clusters.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+        let mut result = Vec::new();
+        for cluster in clusters {
+            for anomaly in &self.anomalies {
+                if (anomaly.timestamp - cluster.timestamp).abs() <= self.window {
+                    result.push(Coincidence {
+                        cluster: cluster.clone(),
+                        anomaly: anomaly.clone(),
+                    });
+                }
+            }
+        }
+        result
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(
+            fs::File::create(path)
+                .with_context(|| format!("cannot write SEE report {}", path.display()))?,
+        );
+        writeln!(
+            writer,
+            "# SEE coincidences, threshold: {} keV, window: {} s, heavy clusters: {}, anomalies: {}",
+            self.threshold,
+            self.window,
+            self.clusters.len(),
+            self.anomalies.len()
+        )?;
+        writeln!(
+            writer,
+            "cluster_time\tenergy[keV]\tx\ty\tlabel\tanomaly_time\tdt[s]\tanomaly"
+        )?;
+        for c in self.coincidences() {
+            writeln!(
+                writer,
+                "{}\t{:.1}\t{}\t{}\t{}\t{}\t{:.3}\t{}",
+                format_time(c.cluster.timestamp),
+                c.cluster.energy,
+                c.cluster.x,
+                c.cluster.y,
+                c.cluster.label,
+                format_time(c.anomaly.timestamp),
+                c.anomaly.timestamp - c.cluster.timestamp,
+                c.anomaly.description
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clustering::Pixel;
+
+    fn info(timestamp: f64, temp: f64, error_id: &str) -> MeasInfoData {
+        MeasInfoData {
+            timestamp,
+            temp,
+            error_id: error_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_coincidences() {
+        let records = [
+            info(0.0, -4.0, ""),
+            info(30.0, -3.0, "\"255, 31\""),
+            info(60.0, -3.0, "\"255, 31\""),
+            info(90.0, 0.0, ""),
+        ];
+        let anomalies = find_anomalies(&records);
+        assert_eq!(anomalies.len(), 2);
+        assert_eq!(anomalies[0].description, "error_id 255, 31");
+        assert_eq!(anomalies[1].description, "temperature jump -3 -> 0");
+
+        let mut see = SeeAnalysis::new(500.0, 10.0);
+        see.anomalies = anomalies;
+        let heavy = Cluster {
+            pixels: vec![Pixel::new(10, 20, 400, 1), Pixel::new(11, 20, 300, 1)],
+            merged: 0,
+        };
+        let light = Cluster {
+            pixels: vec![Pixel::new(50, 50, 100, 1)],
+            merged: 0,
+        };
+        see.add_frame(&[heavy.clone(), light], 25.0, 1.0);
+        let mut other = SeeAnalysis::new(500.0, 10.0);
+        other.add_frame(&[heavy], 50.0, 1.0);
+        see.merge(&other);
+
+        assert_eq!(see.clusters.len(), 2);
+        let coincidences = see.coincidences();
+        assert_eq!(coincidences.len(), 1);
+        assert_eq!(coincidences[0].cluster.timestamp, 25.0);
+        assert_eq!(
+            (coincidences[0].cluster.x, coincidences[0].cluster.y),
+            (11, 20)
+        );
+        assert_eq!(coincidences[0].anomaly.timestamp, 30.0);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::data_processor::Frame;
+use crate::event_display;
+use crate::roi::{self, SPECTRUM_BINS};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Radiation regions of orbit::radiation_region, the last one is the quiet background
+pub const REGIONS: [&str; 3] = ["saa", "polar", "low_latitude"];
+const QUIET: &str = "low_latitude";
+/// Cluster types of event_display::classify with a meaningful energy (not saturated)
+pub const CLUSTER_TYPES: [&str; 5] = [
+    "dot",
+    "small blob",
+    "heavy blob",
+    "straight track",
+    "curly track",
+];
+
+/// Cluster energy spectra per radiation region and cluster type, the background of the
+/// quiet region can be subtracted from the SAA and horn spectra
+#[derive(Debug, Clone)]
+pub struct RegionSpectra {
+    /// Exposure in s per region
+    pub exposure: BTreeMap<&'static str, f64>,
+    /// Weighted cluster counts per region and cluster type
+    pub spectra: BTreeMap<(&'static str, &'static str), Vec<f64>>,
+    /// Also write the quiet region subtracted spectra
+    pub subtract_quiet: bool,
+}
+
+impl RegionSpectra {
+    pub fn new(subtract_quiet: bool) -> Self {
+        RegionSpectra {
+            exposure: BTreeMap::new(),
+            spectra: BTreeMap::new(),
+            subtract_quiet,
+        }
+    }
+
+    pub fn add_frame(
+        &mut self,
+        region: &'static str,
+        frame: &Frame,
+        kev_per_count: f64,
+        acq_time: f64,
+        weight: f64,
+    ) {
+        *self.exposure.entry(region).or_default() += weight * acq_time;
+        for cluster in &frame.clusters {
+            let kind = event_display::classify(cluster, kev_per_count);
+            if kind == "saturated" {
+                continue;
+            }
+            let energy = event_display::cluster_energy(cluster, kev_per_count);
+            self.spectra
+                .entry((region, kind))
+                .or_insert_with(|| vec![0.0; SPECTRUM_BINS])[roi::spectrum_bin(energy)] += weight;
+        }
+    }
+
+    pub fn merge(&mut self, other: &RegionSpectra) {
+        for (region, exposure) in &other.exposure {
+            *self.exposure.entry(region).or_default() += exposure;
+        }
+        for (key, counts) in &other.spectra {
+            let spectrum = self
+                .spectra
+                .entry(*key)
+                .or_insert_with(|| vec![0.0; SPECTRUM_BINS]);
+            for (count, other) in spectrum.iter_mut().zip(counts) {
+                *count += other;
+            }
+        }
+    }
+
+    fn counts(&self, region: &str, kind: &str, bin: usize) -> f64 {
+        self.spectra.get(&(region, kind)).map_or(0.0, |s| s[bin])
+    }
+
+    /// Count rate per s of exposure and its Poisson sigma
+    pub fn rate(&self, region: &str, kind: &str, bin: usize) -> (f64, f64) {
+        let exposure = self.exposure.get(region).copied().unwrap_or_default();
+        if exposure <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let counts = self.counts(region, kind, bin);
+        (counts / exposure, counts.sqrt() / exposure)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(
+            fs::File::create(path)
+                .with_context(|| format!("cannot write region spectra {}", path.display()))?,
+        );
+        let exposure: Vec<String> = REGIONS
+            .iter()
+            .map(|r| format!("{} {:.3}", r, self.exposure.get(r).unwrap_or(&0.0)))
+            .collect();
+        writeln!(
+            writer,
+            "# Cluster energy spectra per radiation region and cluster type (without saturated clusters), exposure[s]: {}",
+            exposure.join(", ")
+        )?;
+        writeln!(
+            writer,
+            "region\ttype\tenergy_min[keV]\tclusters\trate[1/s]\trate_sigma[1/s]"
+        )?;
+        for region in REGIONS {
+            for kind in CLUSTER_TYPES {
+                for bin in 0..SPECTRUM_BINS {
+                    let (rate, sigma) = self.rate(region, kind, bin);
+                    writeln!(
+                        writer,
+                        "{}\t{}\t{:.4}\t{}\t{:.4e}\t{:.4e}",
+                        region,
+                        kind,
+                        roi::bin_edge(bin),
+                        self.counts(region, kind, bin),
+                        rate,
+                        sigma
+                    )?;
+                }
+            }
+        }
+        if self.subtract_quiet {
+            writeln!(
+                writer,
+                "# SAA and horn (polar) spectra minus the {} background rate",
+                QUIET
+            )?;
+            writeln!(
+                writer,
+                "region\ttype\tenergy_min[keV]\tnet_rate[1/s]\tnet_rate_sigma[1/s]"
+            )?;
+            // regions not crossed have no rate to subtract from
+            for region in REGIONS
+                .iter()
+                .filter(|&&r| r != QUIET && self.exposure.get(r).is_some_and(|&e| e > 0.0))
+            {
+                for kind in CLUSTER_TYPES {
+                    for bin in 0..SPECTRUM_BINS {
+                        let (rate, sigma) = self.rate(region, kind, bin);
+                        let (background, background_sigma) = self.rate(QUIET, kind, bin);
+                        writeln!(
+                            writer,
+                            "{}\t{}\t{:.4}\t{:.4e}\t{:.4e}",
+                            region,
+                            kind,
+                            roi::bin_edge(bin),
+                            rate - background,
+                            sigma.hypot(background_sigma)
+                        )?;
+                    }
+                }
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clustering::{Cluster, Pixel};
+
+    #[test]
+    fn test_region_spectra() {
+        let dot = |value| Cluster {
+            pixels: vec![Pixel::new(10, 10, value, 0)],
+            merged: 0,
+        };
+        let mut frame = Frame::from_planes(Vec::new(), Vec::new(), 0.0);
+        frame.clusters = vec![dot(20), dot(20), dot(300)];
+        let mut spectra = RegionSpectra::new(true);
+        spectra.add_frame("saa", &frame, 1.0, 2.0, 1.0);
+        let mut other = RegionSpectra::new(true);
+        frame.clusters.truncate(1);
+        other.add_frame("low_latitude", &frame, 1.0, 10.0, 1.0);
+        spectra.merge(&other);
+
+        let bin = roi::spectrum_bin(20.0);
+        assert_eq!(spectra.rate("saa", "dot", bin).0, 1.0);
+        assert_eq!(spectra.rate("low_latitude", "dot", bin).0, 0.1);
+        assert_eq!(spectra.rate("saa", "dot", roi::spectrum_bin(300.0)).0, 0.5);
+        assert_eq!(spectra.rate("polar", "dot", bin), (0.0, 0.0));
+        assert_eq!(spectra.exposure["saa"], 2.0);
+    }
+}
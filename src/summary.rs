@@ -0,0 +1,133 @@
+use crate::compare::ProductRow;
+use crate::index::format_date;
+use crate::utils::format_time;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Statistics of the converted frames of one day
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DaySummary {
+    pub date: String,
+    pub frames: usize,
+    /// Time of the first and last frame
+    pub span: (f64, f64),
+    /// Summed acquisition time in s
+    pub acq_time: f64,
+    /// Absorbed dose in Gy (dose rate times acquisition time), None without the columns
+    pub dose: Option<f64>,
+    pub temp_range: Option<(f64, f64)>,
+    /// Frames whose measurement has an error id
+    pub error_frames: usize,
+    /// Frames in the South Atlantic Anomaly
+    pub saa_frames: usize,
+    /// Summed payload and pixel packet bytes, None without the columns
+    pub bytes: Option<(f64, f64)>,
+}
+
+impl DaySummary {
+    /// Percentage of the payload bytes decoded as pixel packets
+    pub fn decode_efficiency(&self) -> Option<f64> {
+        self.bytes
+            .filter(|&(payload, _)| payload > 0.0)
+            .map(|(payload, packets)| 100.0 * packets / payload)
+    }
+}
+
+/// Groups the rows of the .info files by UTC day
+pub fn summarize_days(rows: &[ProductRow]) -> Vec<DaySummary> {
+    let mut days: BTreeMap<String, DaySummary> = BTreeMap::new();
+    for row in rows {
+        let date = format_date(row.timestamp);
+        let day = days.entry(date.clone()).or_insert_with(|| DaySummary {
+            date,
+            span: (row.timestamp, row.timestamp),
+            ..Default::default()
+        });
+        day.frames += 1;
+        day.span = (day.span.0.min(row.timestamp), day.span.1.max(row.timestamp));
+        day.acq_time += row.acq_time.unwrap_or_default();
+        if let Some((rate, acq_time)) = row.dose_rate.zip(row.acq_time) {
+            *day.dose.get_or_insert(0.0) += rate * acq_time;
+        }
+        if let Some(temp) = row.temp {
+            let (min, max) = day.temp_range.get_or_insert((temp, temp));
+            *min = min.min(temp);
+            *max = max.max(temp);
+        }
+        day.error_frames += usize::from(row.error == Some(true));
+        day.saa_frames += usize::from(row.saa == Some(true));
+        if let Some((payload, packets)) = row.bytes {
+            let bytes = day.bytes.get_or_insert((0.0, 0.0));
+            bytes.0 += payload;
+            bytes.1 += packets;
+        }
+    }
+    days.into_values().collect()
+}
+
+/// Writes the days as a tab separated table, values missing from the products are empty
+pub fn write_summary<W: Write>(days: &[DaySummary], writer: &mut W) -> Result<()> {
+    writeln!(
+        writer,
+        "Date\tFrames\tFirst\tLast\tAcq Time[s]\tDose[Gy]\tTemp Min\tTemp Max\tError Frames\tSAA Frames\tDecode Efficiency[%]"
+    )?;
+    for day in days {
+        let (temp_min, temp_max) = day
+            .temp_range
+            .map(|(min, max)| (min.to_string(), max.to_string()))
+            .unwrap_or_default();
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{:.3}\t{}\t{}\t{}\t{}\t{}\t{}",
+            day.date,
+            day.frames,
+            format_time(day.span.0),
+            format_time(day.span.1),
+            day.acq_time,
+            day.dose
+                .map(|dose| format!("{:e}", dose))
+                .unwrap_or_default(),
+            temp_min,
+            temp_max,
+            day.error_frames,
+            day.saa_frames,
+            day.decode_efficiency()
+                .map(|efficiency| format!("{:.2}", efficiency))
+                .unwrap_or_default()
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare::parse_info;
+
+    #[test]
+    fn test_summarize_days() {
+        let info = "# repro_hash: 0\n\
+                    Timestamp\tTemp\tacq_time\tDose Rate\tRegion\tError ID\tPayload Bytes\tPacket Bytes\n\
+                    1709251200\t-4\t10\t1e-6\tsaa\t\t100\t60\n\
+                    1709251230\t-2\t5\t2e-6\tpolar\t255\t300\t240\n\
+                    1709337600\t3\t1\t\tsaa\t\t\t\n";
+        let days = summarize_days(&parse_info(info).unwrap());
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].date, "2024-03-01");
+        assert_eq!(days[0].frames, 2);
+        assert_eq!(days[0].acq_time, 15.0);
+        assert!((days[0].dose.unwrap() - 2e-5).abs() < 1e-12);
+        assert_eq!(days[0].temp_range, Some((-4.0, -2.0)));
+        assert_eq!((days[0].error_frames, days[0].saa_frames), (1, 1));
+        assert_eq!(days[1].dose, None);
+        assert_eq!(days[0].decode_efficiency(), Some(75.0));
+        assert_eq!(days[1].decode_efficiency(), None);
+
+        let mut out = Vec::new();
+        write_summary(&days, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 3);
+        assert!(out.lines().nth(2).unwrap().starts_with("2024-03-02\t1\t"));
+    }
+}
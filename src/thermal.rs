@@ -0,0 +1,264 @@
+use crate::gps_processor::GpsData;
+use crate::orbit;
+use crate::phase;
+use anyhow::{Context, Result};
+use chrono::{TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Weighted sums of the frame temperatures of a day: sunlit and eclipse means and the normal
+/// equations of the least squares fit T(u) = T0 + a cos u + b sin u over the argument of
+/// latitude u
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ThermalSums {
+    pub frames: f64,
+    pub eclipse_frames: f64,
+    pub eclipse_temp: f64,
+    pub sunlit_temp: f64,
+    pub temp_sq: f64,
+    /// Sums of the products of the basis functions (1, cos u, sin u)
+    pub basis: [[f64; 3]; 3],
+    /// Sums of the basis functions times the temperature
+    pub basis_temp: [f64; 3],
+}
+
+/// Orbital thermal cycle fitted to the temperatures of a day
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermalFit {
+    /// Orbit mean temperature
+    pub mean: f64,
+    /// Half of the peak to peak temperature swing over the orbit
+    pub amplitude: f64,
+    /// Argument of latitude of the maximum in degrees
+    pub max_phase: f64,
+    /// RMS of the frame temperatures around the fitted cycle
+    pub rms: f64,
+}
+
+impl ThermalSums {
+    fn add(&mut self, phase: f64, eclipse: bool, temp: f64, weight: f64) {
+        let (sin_u, cos_u) = phase.to_radians().sin_cos();
+        let basis = [1.0, cos_u, sin_u];
+        self.frames += weight;
+        if eclipse {
+            self.eclipse_frames += weight;
+            self.eclipse_temp += weight * temp;
+        } else {
+            self.sunlit_temp += weight * temp;
+        }
+        self.temp_sq += weight * temp * temp;
+        for i in 0..3 {
+            for j in 0..3 {
+                self.basis[i][j] += weight * basis[i] * basis[j];
+            }
+            self.basis_temp[i] += weight * basis[i] * temp;
+        }
+    }
+
+    fn merge(&mut self, other: &ThermalSums) {
+        self.frames += other.frames;
+        self.eclipse_frames += other.eclipse_frames;
+        self.eclipse_temp += other.eclipse_temp;
+        self.sunlit_temp += other.sunlit_temp;
+        self.temp_sq += other.temp_sq;
+        for i in 0..3 {
+            for j in 0..3 {
+                self.basis[i][j] += other.basis[i][j];
+            }
+            self.basis_temp[i] += other.basis_temp[i];
+        }
+    }
+
+    /// Mean temperature of the sunlit and of the eclipse frames, None without such frames
+    pub fn means(&self) -> (Option<f64>, Option<f64>) {
+        let sunlit_frames = self.frames - self.eclipse_frames;
+        (
+            (sunlit_frames > 0.0).then(|| self.sunlit_temp / sunlit_frames),
+            (self.eclipse_frames > 0.0).then(|| self.eclipse_temp / self.eclipse_frames),
+        )
+    }
+
+    /// Least squares cycle, None when the phases of the frames do not constrain it
+    pub fn fit(&self) -> Option<ThermalFit> {
+        let m = &self.basis;
+        let det = |m: &[[f64; 3]; 3]| {
+            m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+                - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+                + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+        };
+        let d = det(m);
+        // the determinant scales with the cube of the summed weights
+        if self.frames <= 0.0 || d.abs() <= 1e-9 * self.frames.powi(3) {
+            return None;
+        }
+        // Cramer's rule
+        let x: [f64; 3] = std::array::from_fn(|k| {
+            let mut mk = *m;
+            for (row, b) in mk.iter_mut().zip(self.basis_temp) {
+                row[k] = b;
+            }
+            det(&mk) / d
+        });
+        let mx: f64 = (0..3)
+            .map(|i| x[i] * (0..3).map(|j| m[i][j] * x[j]).sum::<f64>())
+            .sum();
+        let bx: f64 = (0..3).map(|i| x[i] * self.basis_temp[i]).sum();
+        let residual = (self.temp_sq - 2.0 * bx + mx).max(0.0) / self.frames;
+        Some(ThermalFit {
+            mean: x[0],
+            amplitude: x[1].hypot(x[2]),
+            max_phase: x[2].atan2(x[1]).to_degrees().rem_euclid(360.0),
+            rms: residual.sqrt(),
+        })
+    }
+}
+
+/// Frame temperatures correlated with the orbit position and the eclipse state, per UTC day
+#[derive(Debug, Clone)]
+pub struct ThermalModel {
+    /// Time ordered (timestamp, J2000 position) of the valid GPS records
+    pub track: Arc<Vec<(f64, [f64; 3])>>,
+    pub days: BTreeMap<String, ThermalSums>,
+}
+
+impl ThermalModel {
+    pub fn new(records: &[GpsData]) -> Self {
+        ThermalModel {
+            track: Arc::new(phase::track(records)),
+            days: BTreeMap::new(),
+        }
+    }
+
+    /// Empty sums sharing the track, for a parallel job
+    pub fn fresh(&self) -> Self {
+        ThermalModel {
+            track: self.track.clone(),
+            days: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the temperature of the frame, frames outside the GPS track or without a
+    /// temperature are left out
+    pub fn add_frame(&mut self, timestamp: f64, temp: f64, weight: f64) {
+        if !temp.is_finite() {
+            return;
+        }
+        let Some((pos, phase)) = phase::locate(&self.track, timestamp) else {
+            return;
+        };
+        let eclipse = orbit::in_eclipse(pos, timestamp);
+        let day = Utc
+            .timestamp_opt(timestamp.floor() as i64, 0)
+            .unwrap()
+            .format("%Y-%m-%d")
+            .to_string();
+        self.days
+            .entry(day)
+            .or_default()
+            .add(phase, eclipse, temp, weight);
+    }
+
+    pub fn merge(&mut self, other: &ThermalModel) {
+        for (day, sums) in &other.days {
+            self.days.entry(day.clone()).or_default().merge(sums);
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(
+            fs::File::create(path)
+                .with_context(|| format!("cannot write thermal report {}", path.display()))?,
+        );
+        writeln!(
+            writer,
+            "# Frame temperatures per day by eclipse state and fitted to the orbit phase (argument of latitude) as T0 + A cos(u - u_max)"
+        )?;
+        writeln!(
+            writer,
+            "date\tframes\teclipse_fraction\ttemp_sunlit[C]\ttemp_eclipse[C]\tfit_t0[C]\tfit_amplitude[C]\tfit_max_phase[deg]\tfit_rms[C]"
+        )?;
+        let value = |v: Option<f64>, precision: usize| {
+            v.map(|v| format!("{:.*}", precision, v))
+                .unwrap_or_default()
+        };
+        for (day, sums) in &self.days {
+            let (sunlit, eclipse) = sums.means();
+            let fit = sums.fit();
+            writeln!(
+                writer,
+                "{}\t{}\t{:.3}\t{}\t{}\t{}\t{}\t{}\t{}",
+                day,
+                sums.frames,
+                sums.eclipse_frames / sums.frames,
+                value(sunlit, 2),
+                value(eclipse, 2),
+                value(fit.map(|f| f.mean), 2),
+                value(fit.map(|f| f.amplitude), 2),
+                value(fit.map(|f| f.max_phase), 1),
+                value(fit.map(|f| f.rms), 3)
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thermal_model() {
+        // polar orbit of 100 min period in the plane containing the Sun direction at the
+        // March equinox, the satellite is in eclipse around the argument of latitude 180
+        let t0 = 1710903960.0;
+        let period = 6000.0;
+        let track: Vec<GpsData> = (0..=100)
+            .map(|i| {
+                let t = i as f64 * 60.0;
+                let (sin_u, cos_u) = (t / period * 360.0).to_radians().sin_cos();
+                GpsData {
+                    timestamp: t0 + t,
+                    j2000_x: 7e6 * cos_u,
+                    j2000_z: 7e6 * sin_u,
+                    q_est_prop_bj_scalar: 1.0,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let mut model = ThermalModel::new(&track);
+        let mut other = model.fresh();
+        for i in 0..300 {
+            let t = i as f64 * 20.0;
+            let u = (t / period * 360.0).to_radians();
+            let temp = 20.0 + 5.0 * (u - 1.0).cos();
+            let target = if i % 2 == 0 { &mut model } else { &mut other };
+            target.add_frame(t0 + t, temp, 1.0);
+        }
+        model.add_frame(t0 + 1e5, 20.0, 1.0);
+        model.add_frame(t0 + 100.0, f64::NAN, 1.0);
+        model.merge(&other);
+
+        assert_eq!(model.days.len(), 1);
+        let sums = &model.days["2024-03-20"];
+        assert_eq!(sums.frames, 300.0);
+        let fraction = sums.eclipse_frames / sums.frames;
+        assert!(fraction > 0.3 && fraction < 0.4, "{}", fraction);
+        let (sunlit, eclipse) = sums.means();
+        assert!(sunlit.unwrap() > eclipse.unwrap());
+        let fit = sums.fit().unwrap();
+        assert!((fit.mean - 20.0).abs() < 1e-6);
+        assert!((fit.amplitude - 5.0).abs() < 1e-6);
+        assert!((fit.max_phase - 1f64.to_degrees()).abs() < 1e-3);
+        assert!(fit.rms < 1e-3);
+
+        // a single orbit phase does not constrain the cycle
+        let mut single = ThermalSums::default();
+        single.add(90.0, false, 20.0, 1.0);
+        single.add(90.0, false, 21.0, 1.0);
+        assert_eq!(single.fit(), None);
+    }
+}
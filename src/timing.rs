@@ -0,0 +1,127 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Pipeline stages with separate timers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Read,
+    HexDecode,
+    Assembly,
+    PixelDecode,
+    Clustering,
+    Matching,
+    Writing,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 7] = [
+        Stage::Read,
+        Stage::HexDecode,
+        Stage::Assembly,
+        Stage::PixelDecode,
+        Stage::Clustering,
+        Stage::Matching,
+        Stage::Writing,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Read => "read",
+            Stage::HexDecode => "hex decode",
+            Stage::Assembly => "frame assembly",
+            Stage::PixelDecode => "pixel decode",
+            Stage::Clustering => "clustering",
+            Stage::Matching => "matching",
+            Stage::Writing => "writing",
+        }
+    }
+}
+
+/// Time spent in each stage and the amount of processed input
+#[derive(Debug, Clone, Default)]
+pub struct StageTimes {
+    durations: [Duration; 7],
+    /// Bytes of the data file read
+    pub input_bytes: u64,
+    /// Frames decoded
+    pub frames: u64,
+}
+
+impl StageTimes {
+    pub fn add(&mut self, stage: Stage, elapsed: Duration) {
+        self.durations[stage as usize] += elapsed;
+    }
+
+    pub fn get(&self, stage: Stage) -> Duration {
+        self.durations[stage as usize]
+    }
+
+    pub fn total(&self) -> Duration {
+        self.durations.iter().sum()
+    }
+
+    pub fn merge(&mut self, other: &StageTimes) {
+        for (duration, other) in self.durations.iter_mut().zip(&other.durations) {
+            *duration += *other;
+        }
+        self.input_bytes += other.input_bytes;
+        self.frames += other.frames;
+    }
+
+    /// Timing breakdown and throughput for the given wall clock time of the run
+    pub fn report(&self, wall_time: Duration) -> String {
+        let total = self.total().as_secs_f64();
+        let mut report = String::from("Stage timing (summed over jobs):\n");
+        for stage in Stage::ALL {
+            let secs = self.get(stage).as_secs_f64();
+            let share = if total > 0.0 {
+                100.0 * secs / total
+            } else {
+                0.0
+            };
+            let _ = writeln!(
+                report,
+                "  {:<15}{:>10.3} s {:>6.1} %",
+                stage.name(),
+                secs,
+                share
+            );
+        }
+        let wall = wall_time.as_secs_f64().max(1e-9);
+        let _ = write!(
+            report,
+            "Throughput: {} frames, {:.1} MB in {:.3} s ({:.1} frames/s, {:.2} MB/s)",
+            self.frames,
+            self.input_bytes as f64 / 1e6,
+            wall,
+            self.frames as f64 / wall,
+            self.input_bytes as f64 / 1e6 / wall
+        );
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_times() {
+        let mut times = StageTimes::default();
+        times.add(Stage::Read, Duration::from_millis(300));
+        times.add(Stage::Writing, Duration::from_millis(100));
+        let mut other = StageTimes {
+            frames: 4,
+            input_bytes: 2_000_000,
+            ..Default::default()
+        };
+        other.add(Stage::Read, Duration::from_millis(100));
+        times.merge(&other);
+        assert_eq!(times.get(Stage::Read), Duration::from_millis(400));
+        assert_eq!(times.total(), Duration::from_millis(500));
+
+        let report = times.report(Duration::from_secs(2));
+        assert!(report.contains("  read                0.400 s   80.0 %"));
+        assert!(report.ends_with("(2.0 frames/s, 1.00 MB/s)"));
+    }
+}
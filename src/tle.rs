@@ -0,0 +1,490 @@
+use crate::orbit;
+use anyhow::{Context, Result, bail};
+use sha2::{Digest, Sha256};
+use std::f64::consts::PI;
+use std::fs;
+use std::path::Path;
+
+// WGS72 constants of the SGP4 model
+const EARTH_RADIUS_KM: f64 = 6378.135;
+const MU: f64 = 398600.8;
+const J2: f64 = 0.001082616;
+const J3: f64 = -0.00000253881;
+const J4: f64 = -0.00000165597;
+const J3OJ2: f64 = J3 / J2;
+const TWO_THIRDS: f64 = 2.0 / 3.0;
+/// Orbital period in minutes from which SDP4 (deep space) would be needed
+const DEEP_SPACE_PERIOD: f64 = 225.0;
+
+/// Square root of mu in Earth radii^1.5 per minute
+fn xke() -> f64 {
+    60.0 / (EARTH_RADIUS_KM.powi(3) / MU).sqrt()
+}
+
+/// Mean orbital elements of a two-line element set
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tle {
+    /// Epoch as a unix timestamp
+    pub epoch: f64,
+    /// Drag term in 1/Earth radii
+    pub bstar: f64,
+    /// Angles in radians
+    pub inclination: f64,
+    pub raan: f64,
+    pub eccentricity: f64,
+    pub arg_perigee: f64,
+    pub mean_anomaly: f64,
+    /// Kozai mean motion in radians per minute
+    pub mean_motion: f64,
+}
+
+/// Checks the modulo 10 checksum in the last column of a TLE line
+fn check_line(line: &str, number: char) -> Result<()> {
+    if line.len() < 69 || !line.is_ascii() || !line.starts_with(number) {
+        bail!("expected TLE line {} of 69 characters: '{}'", number, line);
+    }
+    let sum: u32 = line[..68]
+        .chars()
+        .map(|c| match c {
+            '-' => 1,
+            c => c.to_digit(10).unwrap_or(0),
+        })
+        .sum();
+    let expected = line[68..69].parse::<u32>().ok();
+    if expected != Some(sum % 10) {
+        bail!("checksum mismatch in TLE line {}: '{}'", number, line);
+    }
+    Ok(())
+}
+
+fn field(line: &str, range: std::ops::Range<usize>, name: &str) -> Result<f64> {
+    let text = line[range].trim();
+    text.parse()
+        .with_context(|| format!("invalid TLE {} '{}'", name, text))
+}
+
+/// Parses the implied decimal point exponential notation of the TLE drag terms (` 28098-4`)
+fn exponential(text: &str) -> Result<f64> {
+    let text = text.trim();
+    let (mantissa, exponent) = text.split_at(
+        text.rfind(['-', '+'])
+            .filter(|&i| i > 0)
+            .unwrap_or(text.len()),
+    );
+    let (sign, digits) = match mantissa.strip_prefix('-') {
+        Some(digits) => (-1.0, digits),
+        None => (1.0, mantissa.trim_start_matches('+')),
+    };
+    let mantissa: f64 = format!("0.{}", digits)
+        .parse()
+        .with_context(|| format!("invalid TLE exponential '{}'", text))?;
+    let exponent: i32 = if exponent.is_empty() {
+        0
+    } else {
+        exponent
+            .parse()
+            .with_context(|| format!("invalid TLE exponential '{}'", text))?
+    };
+    Ok(sign * mantissa * 10f64.powi(exponent))
+}
+
+impl Tle {
+    pub fn parse(line1: &str, line2: &str) -> Result<Tle> {
+        check_line(line1, '1')?;
+        check_line(line2, '2')?;
+        let year = field(line1, 18..20, "epoch year")? as i32;
+        let year = if year < 57 { 2000 + year } else { 1900 + year };
+        let day = field(line1, 20..32, "epoch day")?;
+        let start = chrono::NaiveDate::from_ymd_opt(year, 1, 1)
+            .context("invalid TLE epoch year")?
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc()
+            .timestamp() as f64;
+        Ok(Tle {
+            epoch: start + (day - 1.0) * 86400.0,
+            bstar: exponential(&line1[53..61])?,
+            inclination: field(line2, 8..16, "inclination")?.to_radians(),
+            raan: field(line2, 17..25, "right ascension")?.to_radians(),
+            eccentricity: field(line2, 26..33, "eccentricity")? * 1e-7,
+            arg_perigee: field(line2, 34..42, "argument of perigee")?.to_radians(),
+            mean_anomaly: field(line2, 43..51, "mean anomaly")?.to_radians(),
+            mean_motion: field(line2, 52..63, "mean motion")? * 2.0 * PI / 1440.0,
+        })
+    }
+}
+
+/// Near-Earth SGP4 propagator of one element set (Vallado et al. 2006 revision, without the
+/// deep space SDP4 terms the LEO orbits do not need)
+#[derive(Debug, Clone)]
+pub struct Sgp4 {
+    tle: Tle,
+    simple: bool,
+    /// Un-Kozai'd mean motion in radians per minute
+    n: f64,
+    aycof: f64,
+    con41: f64,
+    cc1: f64,
+    cc4: f64,
+    cc5: f64,
+    d2: f64,
+    d3: f64,
+    d4: f64,
+    delmo: f64,
+    eta: f64,
+    argpdot: f64,
+    omgcof: f64,
+    sinmao: f64,
+    t2cof: f64,
+    t3cof: f64,
+    t4cof: f64,
+    t5cof: f64,
+    x1mth2: f64,
+    x7thm1: f64,
+    mdot: f64,
+    nodedot: f64,
+    xlcof: f64,
+    xmcof: f64,
+    nodecf: f64,
+}
+
+impl Sgp4 {
+    pub fn new(tle: Tle) -> Result<Sgp4> {
+        let xke = xke();
+        let (ecco, inclo, argpo) = (tle.eccentricity, tle.inclination, tle.arg_perigee);
+        if !(0.0..1.0).contains(&ecco) || tle.mean_motion <= 0.0 {
+            bail!("TLE eccentricity or mean motion out of range");
+        }
+
+        // recover the original mean motion and semi-major axis from the Kozai mean motion
+        let cosio = inclo.cos();
+        let cosio2 = cosio * cosio;
+        let omeosq = 1.0 - ecco * ecco;
+        let rteosq = omeosq.sqrt();
+        let ak = (xke / tle.mean_motion).powf(TWO_THIRDS);
+        let d1 = 0.75 * J2 * (3.0 * cosio2 - 1.0) / (rteosq * omeosq);
+        let del = d1 / (ak * ak);
+        let adel = ak * (1.0 - del * del - del * (1.0 / 3.0 + 134.0 * del * del / 81.0));
+        let n = tle.mean_motion / (1.0 + d1 / (adel * adel));
+        if 2.0 * PI / n >= DEEP_SPACE_PERIOD {
+            bail!("TLE of a deep space orbit, only near-Earth orbits are supported");
+        }
+        let ao = (xke / n).powf(TWO_THIRDS);
+        let sinio = inclo.sin();
+        let po = ao * omeosq;
+        let con42 = 1.0 - 5.0 * cosio2;
+        let con41 = -con42 - 2.0 * cosio2;
+        let posq = po * po;
+        let rp = ao * (1.0 - ecco);
+        let simple = rp < 220.0 / EARTH_RADIUS_KM + 1.0;
+
+        // atmospheric density parameters for low perigees
+        let mut sfour = 78.0 / EARTH_RADIUS_KM + 1.0;
+        let mut qzms24 = ((120.0 - 78.0) / EARTH_RADIUS_KM).powi(4);
+        let perigee = (rp - 1.0) * EARTH_RADIUS_KM;
+        if perigee < 156.0 {
+            let s = if perigee < 98.0 { 20.0 } else { perigee - 78.0 };
+            qzms24 = ((120.0 - s) / EARTH_RADIUS_KM).powi(4);
+            sfour = s / EARTH_RADIUS_KM + 1.0;
+        }
+
+        let pinvsq = 1.0 / posq;
+        let tsi = 1.0 / (ao - sfour);
+        let eta = ao * ecco * tsi;
+        let etasq = eta * eta;
+        let eeta = ecco * eta;
+        let psisq = (1.0 - etasq).abs();
+        let coef = qzms24 * tsi.powi(4);
+        let coef1 = coef / psisq.powf(3.5);
+        let cc2 = coef1
+            * n
+            * (ao * (1.0 + 1.5 * etasq + eeta * (4.0 + etasq))
+                + 0.375 * J2 * tsi / psisq * con41 * (8.0 + 3.0 * etasq * (8.0 + etasq)));
+        let cc1 = tle.bstar * cc2;
+        let cc3 = if ecco > 1e-4 {
+            -2.0 * coef * tsi * J3OJ2 * n * sinio / ecco
+        } else {
+            0.0
+        };
+        let x1mth2 = 1.0 - cosio2;
+        let cc4 = 2.0
+            * n
+            * coef1
+            * ao
+            * omeosq
+            * (eta * (2.0 + 0.5 * etasq) + ecco * (0.5 + 2.0 * etasq)
+                - J2 * tsi / (ao * psisq)
+                    * (-3.0 * con41 * (1.0 - 2.0 * eeta + etasq * (1.5 - 0.5 * eeta))
+                        + 0.75
+                            * x1mth2
+                            * (2.0 * etasq - eeta * (1.0 + etasq))
+                            * (2.0 * argpo).cos()));
+        let cc5 = 2.0 * coef1 * ao * omeosq * (1.0 + 2.75 * (etasq + eeta) + eeta * etasq);
+
+        // secular rates of the mean anomaly, argument of perigee and node
+        let cosio4 = cosio2 * cosio2;
+        let temp1 = 1.5 * J2 * pinvsq * n;
+        let temp2 = 0.5 * temp1 * J2 * pinvsq;
+        let temp3 = -0.46875 * J4 * pinvsq * pinvsq * n;
+        let mdot = n
+            + 0.5 * temp1 * rteosq * con41
+            + 0.0625 * temp2 * rteosq * (13.0 - 78.0 * cosio2 + 137.0 * cosio4);
+        let argpdot = -0.5 * temp1 * con42
+            + 0.0625 * temp2 * (7.0 - 114.0 * cosio2 + 395.0 * cosio4)
+            + temp3 * (3.0 - 36.0 * cosio2 + 49.0 * cosio4);
+        let xhdot1 = -temp1 * cosio;
+        let nodedot = xhdot1
+            + (0.5 * temp2 * (4.0 - 19.0 * cosio2) + 2.0 * temp3 * (3.0 - 7.0 * cosio2)) * cosio;
+        let xmcof = if ecco > 1e-4 {
+            -TWO_THIRDS * coef * tle.bstar / eeta
+        } else {
+            0.0
+        };
+        // avoid the division by zero of equatorial retrograde orbits
+        let one_plus_cosio = if (cosio + 1.0).abs() > 1.5e-12 {
+            1.0 + cosio
+        } else {
+            1.5e-12
+        };
+
+        let mut sgp4 = Sgp4 {
+            simple,
+            n,
+            aycof: -0.5 * J3OJ2 * sinio,
+            con41,
+            cc1,
+            cc4,
+            cc5,
+            d2: 0.0,
+            d3: 0.0,
+            d4: 0.0,
+            delmo: (1.0 + eta * tle.mean_anomaly.cos()).powi(3),
+            eta,
+            argpdot,
+            omgcof: tle.bstar * cc3 * argpo.cos(),
+            sinmao: tle.mean_anomaly.sin(),
+            t2cof: 1.5 * cc1,
+            t3cof: 0.0,
+            t4cof: 0.0,
+            t5cof: 0.0,
+            x1mth2,
+            x7thm1: 7.0 * cosio2 - 1.0,
+            mdot,
+            nodedot,
+            xlcof: -0.25 * J3OJ2 * sinio * (3.0 + 5.0 * cosio) / one_plus_cosio,
+            xmcof,
+            nodecf: 3.5 * omeosq * xhdot1 * cc1,
+            tle,
+        };
+        if !simple {
+            let cc1sq = cc1 * cc1;
+            sgp4.d2 = 4.0 * ao * tsi * cc1sq;
+            let temp = sgp4.d2 * tsi * cc1 / 3.0;
+            sgp4.d3 = (17.0 * ao + sfour) * temp;
+            sgp4.d4 = 0.5 * temp * ao * tsi * (221.0 * ao + 31.0 * sfour) * cc1;
+            sgp4.t3cof = sgp4.d2 + 2.0 * cc1sq;
+            sgp4.t4cof = 0.25 * (3.0 * sgp4.d3 + cc1 * (12.0 * sgp4.d2 + 10.0 * cc1sq));
+            sgp4.t5cof = 0.2
+                * (3.0 * sgp4.d4
+                    + 12.0 * cc1 * sgp4.d3
+                    + 6.0 * sgp4.d2 * sgp4.d2
+                    + 15.0 * cc1sq * (2.0 * sgp4.d2 + cc1sq));
+        }
+        Ok(sgp4)
+    }
+
+    pub fn epoch(&self) -> f64 {
+        self.tle.epoch
+    }
+
+    /// TEME position in km `minutes` after the epoch
+    pub fn propagate(&self, minutes: f64) -> Result<[f64; 3]> {
+        let tle = &self.tle;
+        let t = minutes;
+        let xke = xke();
+
+        // secular gravity and drag
+        let xmdf = tle.mean_anomaly + self.mdot * t;
+        let argpdf = tle.arg_perigee + self.argpdot * t;
+        let nodedf = tle.raan + self.nodedot * t;
+        let t2 = t * t;
+        let mut argpm = argpdf;
+        let mut mm = xmdf;
+        let mut nodem = nodedf + self.nodecf * t2;
+        let mut tempa = 1.0 - self.cc1 * t;
+        let mut tempe = tle.bstar * self.cc4 * t;
+        let mut templ = self.t2cof * t2;
+        if !self.simple {
+            let delomg = self.omgcof * t;
+            let delm = self.xmcof * ((1.0 + self.eta * xmdf.cos()).powi(3) - self.delmo);
+            mm = xmdf + delomg + delm;
+            argpm = argpdf - delomg - delm;
+            let t3 = t2 * t;
+            let t4 = t3 * t;
+            tempa -= self.d2 * t2 + self.d3 * t3 + self.d4 * t4;
+            tempe += tle.bstar * self.cc5 * (mm.sin() - self.sinmao);
+            templ += self.t3cof * t3 + t4 * (self.t4cof + t * self.t5cof);
+        }
+        let am = (xke / self.n).powf(TWO_THIRDS) * tempa * tempa;
+        let mut em = tle.eccentricity - tempe;
+        if !(-0.001..1.0).contains(&em) || am <= 0.0 {
+            bail!("SGP4 mean elements out of range {} min from the epoch", t);
+        }
+        em = em.max(1e-6);
+        mm += self.n * templ;
+        let xlm = (mm + argpm + nodem) % (2.0 * PI);
+        nodem %= 2.0 * PI;
+        argpm %= 2.0 * PI;
+        let mp = (xlm - argpm - nodem).rem_euclid(2.0 * PI);
+        let (sinip, cosip) = tle.inclination.sin_cos();
+
+        // long period periodics
+        let axnl = em * argpm.cos();
+        let temp = 1.0 / (am * (1.0 - em * em));
+        let aynl = em * argpm.sin() + temp * self.aycof;
+        let xl = mp + argpm + nodem + temp * self.xlcof * axnl;
+
+        // Kepler's equation
+        let u = (xl - nodem).rem_euclid(2.0 * PI);
+        let mut eo1 = u;
+        let (mut sineo1, mut coseo1) = eo1.sin_cos();
+        for _ in 0..10 {
+            (sineo1, coseo1) = eo1.sin_cos();
+            let step =
+                (u - aynl * coseo1 + axnl * sineo1 - eo1) / (1.0 - coseo1 * axnl - sineo1 * aynl);
+            eo1 += step.clamp(-0.95, 0.95);
+            if step.abs() < 1e-12 {
+                break;
+            }
+        }
+
+        // short period periodics
+        let ecose = axnl * coseo1 + aynl * sineo1;
+        let esine = axnl * sineo1 - aynl * coseo1;
+        let el2 = axnl * axnl + aynl * aynl;
+        let pl = am * (1.0 - el2);
+        if pl < 0.0 {
+            bail!("SGP4 semi-latus rectum negative {} min from the epoch", t);
+        }
+        let rl = am * (1.0 - ecose);
+        let betal = (1.0 - el2).sqrt();
+        let temp = esine / (1.0 + betal);
+        let sinu = am / rl * (sineo1 - aynl - axnl * temp);
+        let cosu = am / rl * (coseo1 - axnl + aynl * temp);
+        let su = sinu.atan2(cosu);
+        let sin2u = 2.0 * cosu * sinu;
+        let cos2u = 1.0 - 2.0 * sinu * sinu;
+        let temp1 = 0.5 * J2 / pl;
+        let temp2 = temp1 / pl;
+        let mrt = rl * (1.0 - 1.5 * temp2 * betal * self.con41) + 0.5 * temp1 * self.x1mth2 * cos2u;
+        if mrt < 1.0 {
+            bail!("SGP4 orbit decayed {} min from the epoch", t);
+        }
+        let su = su - 0.25 * temp2 * self.x7thm1 * sin2u;
+        let xnode = nodem + 1.5 * temp2 * cosip * sin2u;
+        let xinc = tle.inclination + 1.5 * temp2 * cosip * sinip * cos2u;
+
+        // orientation of the orbit plane
+        let (sinsu, cossu) = su.sin_cos();
+        let (snod, cnod) = xnode.sin_cos();
+        let (sini, cosi) = xinc.sin_cos();
+        let ux = -snod * cosi * sinsu + cnod * cossu;
+        let uy = cnod * cosi * sinsu + snod * cossu;
+        let uz = sini * sinsu;
+        Ok([ux, uy, uz].map(|c| c * mrt * EARTH_RADIUS_KM))
+    }
+}
+
+/// Element sets of a TLE file, a position is propagated from the set with the closest epoch
+#[derive(Debug, Clone)]
+pub struct TleSet {
+    /// Propagators sorted by epoch
+    pub sets: Vec<Sgp4>,
+    /// SHA-256 of the TLE file, part of the repro hash
+    pub digest: String,
+}
+
+impl TleSet {
+    pub fn load(path: &Path) -> Result<TleSet> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("cannot read TLE file {}", path.display()))?;
+        TleSet::parse(&content).with_context(|| format!("invalid TLE file {}", path.display()))
+    }
+
+    /// Parses consecutive line 1/line 2 pairs, optional name lines (three-line format) and
+    /// blank lines are ignored
+    pub fn parse(content: &str) -> Result<TleSet> {
+        let mut sets = Vec::new();
+        let mut lines = content.lines().map(str::trim_end).enumerate().peekable();
+        while let Some((i, line)) = lines.next() {
+            if !line.starts_with("1 ") {
+                continue;
+            }
+            let Some((_, line2)) = lines.next_if(|(_, l)| l.starts_with("2 ")) else {
+                bail!("line {}: TLE line 1 without line 2", i + 1);
+            };
+            let tle = Tle::parse(line, line2).with_context(|| format!("line {}", i + 1))?;
+            sets.push(Sgp4::new(tle).with_context(|| format!("line {}", i + 1))?);
+        }
+        if sets.is_empty() {
+            bail!("no two-line element sets");
+        }
+        sets.sort_by(|a, b| a.epoch().total_cmp(&b.epoch()));
+        Ok(TleSet {
+            sets,
+            digest: hex::encode(Sha256::digest(content.as_bytes())),
+        })
+    }
+
+    /// J2000 position in m at the unix timestamp, None when the propagation fails
+    pub fn position(&self, timestamp: f64) -> Option<[f64; 3]> {
+        let i = self.sets.partition_point(|s| s.epoch() < timestamp);
+        let set = [i.checked_sub(1), Some(i)]
+            .into_iter()
+            .flatten()
+            .filter_map(|i| self.sets.get(i))
+            .min_by(|a, b| {
+                (a.epoch() - timestamp)
+                    .abs()
+                    .total_cmp(&(b.epoch() - timestamp).abs())
+            })?;
+        let teme = set.propagate((timestamp - set.epoch()) / 60.0).ok()?;
+        Some(orbit::teme_to_j2000(teme.map(|c| c * 1e3), timestamp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Vanguard 1, the first SGP4 verification case of Vallado et al. 2006
+    const VANGUARD: &str = "1 00005U 58002B   00179.78495062  .00000023  00000-0  28098-4 0  4753
+2 00005  34.2682 348.7242 1859667 331.7664  19.3264 10.82419157413667";
+
+    #[test]
+    fn test_sgp4() {
+        let set = TleSet::parse(&format!("VANGUARD 1\n{}\n", VANGUARD)).unwrap();
+        let sgp4 = &set.sets[0];
+        assert!((sgp4.tle.bstar - 2.8098e-5).abs() < 1e-12);
+        let expected = [
+            (0.0, [7022.46529266, -1400.08296755, 0.03995155]),
+            (360.0, [-7154.03120202, -3783.17682504, -3536.19412294]),
+        ];
+        for (minutes, expected) in expected {
+            let pos = sgp4.propagate(minutes).unwrap();
+            for (p, e) in pos.iter().zip(expected) {
+                assert!((p - e).abs() < 1e-3, "{:?} at {} min", pos, minutes);
+            }
+        }
+
+        let j2000 = set.position(sgp4.epoch()).unwrap();
+        let teme = orbit::j2000_to_teme(j2000, sgp4.epoch());
+        assert!((teme[0] - 7022465.29266).abs() < 1.0);
+
+        assert!(TleSet::parse(&VANGUARD.replace("4753", "4754")).is_err());
+        assert!(TleSet::parse("no elements").is_err());
+        assert_eq!(exponential(" 28098-4").unwrap(), 0.28098e-4);
+        assert_eq!(exponential("-11606-4").unwrap(), -0.11606e-4);
+        assert_eq!(exponential(" 00000-0").unwrap(), 0.0);
+    }
+}
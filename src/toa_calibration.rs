@@ -0,0 +1,109 @@
+use crate::tpx3lut::{MATRIX_SIZE, WRONG_LUT_TOT};
+use anyhow::{Context, Result, bail};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+/// Per-pixel ToA offset and skew, a ToA value of the pixel is corrected to
+/// `(toa - offset) / (1 + skew)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToaCalibration {
+    /// Delay of the pixel in ToA counts
+    pub offset: Vec<f64>,
+    /// Relative deviation of the pixel clock
+    pub skew: Vec<f64>,
+    /// SHA-256 of the calibration file, part of the repro hash
+    pub digest: String,
+}
+
+impl ToaCalibration {
+    pub fn load(path: &Path) -> Result<ToaCalibration> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("cannot read ToA calibration {}", path.display()))?;
+        ToaCalibration::parse(&content)
+            .with_context(|| format!("invalid ToA calibration {}", path.display()))
+    }
+
+    /// Parses `x y offset skew` lines separated by whitespace or commas, `#` starts a
+    /// comment; pixels not listed are not corrected
+    pub fn parse(content: &str) -> Result<ToaCalibration> {
+        let mut calibration = ToaCalibration {
+            offset: vec![0.0; MATRIX_SIZE],
+            skew: vec![0.0; MATRIX_SIZE],
+            digest: hex::encode(Sha256::digest(content.as_bytes())),
+        };
+        for (i, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let fields: Vec<&str> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|f| !f.is_empty())
+                .collect();
+            if fields.is_empty() {
+                continue;
+            }
+            if fields.len() != 4 {
+                bail!(
+                    "line {}: expected x y offset skew, got {} fields",
+                    i + 1,
+                    fields.len()
+                );
+            }
+            let x: u8 = fields[0]
+                .parse()
+                .with_context(|| format!("line {}: invalid column '{}'", i + 1, fields[0]))?;
+            let y: u8 = fields[1]
+                .parse()
+                .with_context(|| format!("line {}: invalid row '{}'", i + 1, fields[1]))?;
+            let offset: f64 = fields[2]
+                .parse()
+                .with_context(|| format!("line {}: invalid offset '{}'", i + 1, fields[2]))?;
+            let skew: f64 = fields[3]
+                .parse()
+                .with_context(|| format!("line {}: invalid skew '{}'", i + 1, fields[3]))?;
+            if !offset.is_finite() || !skew.is_finite() || skew <= -1.0 {
+                bail!(
+                    "line {}: offset and skew must be finite, skew above -1",
+                    i + 1
+                );
+            }
+            let idx = y as usize * 256 + x as usize;
+            calibration.offset[idx] = offset;
+            calibration.skew[idx] = skew;
+        }
+        Ok(calibration)
+    }
+
+    /// Corrected ToA of the pixel
+    pub fn apply(&self, idx: usize, toa: u16) -> f64 {
+        (toa as f64 - self.offset[idx]) / (1.0 + self.skew[idx])
+    }
+
+    /// Corrected ToA rounded to a count within the range of the second pixel value
+    pub fn apply_count(&self, idx: usize, toa: u16) -> u16 {
+        self.apply(idx, toa)
+            .round()
+            .clamp(0.0, (WRONG_LUT_TOT - 1) as f64) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toa_calibration() {
+        let calibration =
+            ToaCalibration::parse("# x y offset skew\n3 0 2.5 0\n0, 1, -4, 0.25 # skewed\n\n")
+                .unwrap();
+        assert_eq!(calibration.apply(3, 10), 7.5);
+        assert_eq!(calibration.apply(256, 16), 16.0);
+        assert_eq!(calibration.apply(4, 10), 10.0);
+        assert_eq!(calibration.apply_count(3, 10), 8);
+        assert_eq!(calibration.apply_count(3, 1), 0);
+        assert_eq!(calibration.digest.len(), 64);
+
+        assert!(ToaCalibration::parse("3 0 2.5").is_err());
+        assert!(ToaCalibration::parse("256 0 1 0").is_err());
+        assert!(ToaCalibration::parse("1 0 1 -1").is_err());
+    }
+}
@@ -1,5 +1,7 @@
 #![allow(dead_code)]
 
+use std::borrow::Cow;
+
 pub const MATRIX_SIZE: usize = 65536;
 pub const MAX_LUT_EVENT: usize = 16;
 pub const MAX_LUT_ITOT: usize = 16384;
@@ -10,9 +12,58 @@ pub const WRONG_LUT_TOT: u16 = 1023;
 pub const WRONG_LUT_ITOT: u16 = 16383;
 pub const WRONG_LUT_TOA: u16 = 16383;
 
+/// Tables converting the pixel counter codes to iToT and event (ToT) values
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut {
+    pub itot: Cow<'static, [u16]>,
+    pub tot: Cow<'static, [u16]>,
+}
+
+impl Lut {
+    /// Tables of the flight detector
+    pub fn builtin() -> Lut {
+        Lut {
+            itot: Cow::Borrowed(&LUT_ITOT),
+            tot: Cow::Borrowed(&LUT_TOT),
+        }
+    }
+
+    /// iToT of the counter code, `WRONG_LUT_ITOT` for codes outside the table
+    pub fn itot(&self, code: u16) -> u16 {
+        match self.itot.get(code as usize) {
+            Some(&value) if code >= 1 => value,
+            _ => WRONG_LUT_ITOT,
+        }
+    }
+
+    /// Largest iToT of the table, reached by a saturated counter
+    pub fn itot_max(&self) -> u16 {
+        self.itot
+            .iter()
+            .copied()
+            .filter(|&v| v != WRONG_LUT_ITOT)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Event count of the counter code, `WRONG_LUT_TOT` for codes outside the table
+    pub fn tot(&self, code: u16) -> u16 {
+        match self.tot.get(code as usize) {
+            Some(&value) if code >= 1 => value,
+            _ => WRONG_LUT_TOT,
+        }
+    }
+}
+
+impl Default for Lut {
+    fn default() -> Self {
+        Lut::builtin()
+    }
+}
+
 pub const LUT_EVENT: [u16; MAX_LUT_EVENT] = [0, 4, 5, 8, 6, 12, 9, 14, 3, 7, 11, 13, 2, 10, 1, 0];
 
-pub const LUT_ITOT: [u16; MAX_LUT_ITOT] = [
+pub static LUT_ITOT: [u16; MAX_LUT_ITOT] = [
     0, 12869, 12870, 9355, 5841, 12871, 5853, 9356, 5842, 153, 12872, 2339, 2327, 5854, 9689, 9357,
     5843, 15220, 154, 6175, 15196, 12873, 2593, 2340, 2328, 14758, 5855, 13022, 15208, 9690, 16170,
     9358, 5844, 4125, 15221, 12656, 11694, 155, 3396, 6176, 15197, 2673, 12874, 11244, 9508, 2594,
@@ -1124,7 +1175,7 @@ pub const LUT_ITOT: [u16; MAX_LUT_ITOT] = [
     3668, 6107, 4, 13204, 3301, 9369, 1889, 3667, 3, 13203, 9368, 2, 9367, 1, 0,
 ];
 
-pub const LUT_TOA: [u16; MAX_LUT_TOA] = [
+pub static LUT_TOA: [u16; MAX_LUT_TOA] = [
     0, 1, 3, 2, 7, 6, 4, 5, 15, 14, 12, 13, 8, 9, 11, 10, 31, 30, 28, 29, 24, 25, 27, 26, 16, 17,
     19, 18, 23, 22, 20, 21, 63, 62, 60, 61, 56, 57, 59, 58, 48, 49, 51, 50, 55, 54, 52, 53, 32, 33,
     35, 34, 39, 38, 36, 37, 47, 46, 44, 45, 40, 41, 43, 42, 127, 126, 124, 125, 120, 121, 123, 122,
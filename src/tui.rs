@@ -0,0 +1,380 @@
+use crate::data_processor::{DataProcessor, Frame, PacketLayout};
+use crate::event_display;
+use crate::gps_processor::{GpsData, GpsProcessor};
+use crate::index::{self, FrameSelector, IndexEntry};
+use crate::info_processor::{MeasInfoData, MeasInfoProcessor};
+use crate::input::InputFile;
+use crate::line_reader::LineReader;
+use crate::orbit;
+use crate::processor::Processor;
+use crate::utils::{self, format_time};
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::symbols::Marker;
+use ratatui::text::Line;
+use ratatui::widgets::canvas::{Canvas, Points};
+use ratatui::widgets::{Block, List, Paragraph};
+
+/// Occupancy levels of the ASCII heatmap, from empty to fully hit
+const HEATMAP_LEVELS: &[u8] = b" .:-=+*#%@";
+
+/// Renders the hit pixel occupancy of a 256x256 frame downsampled to width x height characters
+pub fn ascii_heatmap(itot: &[u16], width: usize, height: usize) -> Vec<String> {
+    let (width, height) = (width.clamp(1, 256), height.clamp(1, 256));
+    (0..height)
+        .map(|row| {
+            let (y0, y1) = (row * 256 / height, (row + 1) * 256 / height);
+            (0..width)
+                .map(|col| {
+                    let (x0, x1) = (col * 256 / width, (col + 1) * 256 / width);
+                    let hits = (y0..y1)
+                        .flat_map(|y| (x0..x1).map(move |x| y * 256 + x))
+                        .filter(|&idx| itot[idx] != 0)
+                        .count();
+                    let area = (y1 - y0) * (x1 - x0);
+                    let levels = HEATMAP_LEVELS.len() - 1;
+                    let level = (hits * levels).div_ceil(area);
+                    HEATMAP_LEVELS[level] as char
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Shades of the block heatmap, from empty to the largest cell
+const BLOCK_LEVELS: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+/// Renders the summed iToT of a 256x256 frame downsampled to width x height unicode block
+/// characters on a log scale relative to the largest cell, with the largest cell sum
+pub fn block_heatmap(itot: &[u16], width: usize, height: usize) -> (Vec<String>, u64) {
+    let (width, height) = (width.clamp(1, 256), height.clamp(1, 256));
+    let mut sums = vec![0u64; width * height];
+    for (idx, &value) in itot.iter().enumerate() {
+        let (x, y) = (idx % 256, idx / 256);
+        sums[(y * height / 256) * width + x * width / 256] += value as u64;
+    }
+    let max = sums.iter().copied().max().unwrap_or(0);
+    let scale = (1.0 + max as f64).ln();
+    let levels = BLOCK_LEVELS.len() - 1;
+    let rows = sums
+        .chunks(width)
+        .map(|row| {
+            row.iter()
+                .map(|&sum| {
+                    let level = if sum == 0 {
+                        0
+                    } else {
+                        // every hit cell is visible, the largest is full
+                        ((1.0 + sum as f64).ln() / scale * levels as f64).ceil() as usize
+                    };
+                    BLOCK_LEVELS[level.clamp(0, levels)]
+                })
+                .collect()
+        })
+        .collect();
+    (rows, max)
+}
+
+struct TuiState {
+    data_file: String,
+    entries: Vec<IndexEntry>,
+    gps: Vec<GpsData>,
+    info: Vec<MeasInfoData>,
+    layout: PacketLayout,
+    max_pix_count: usize,
+    current: usize,
+    frame: Option<Frame>,
+    message: Option<String>,
+    /// Timestamp being typed for a jump
+    input: Option<String>,
+    braille: bool,
+}
+
+impl TuiState {
+    fn load_frame(&mut self) {
+        let entry = &self.entries[self.current];
+        let result = LineReader::open_at(&self.data_file, entry.offset, entry.line_no - 1)
+            .and_then(|mut reader| {
+                let mut data_processor = DataProcessor::new();
+                data_processor.layout = self.layout;
+                data_processor.get_next_frame(&mut reader)
+            });
+        match result {
+            Ok(frame) => {
+                self.frame = Some(frame);
+                self.message = None;
+            }
+            Err(e) => {
+                self.frame = None;
+                self.message = Some(format!("{:#}", e));
+            }
+        }
+    }
+
+    fn go_to(&mut self, index: isize) {
+        let index = index.clamp(0, self.entries.len() as isize - 1) as usize;
+        if index != self.current || self.frame.is_none() {
+            self.current = index;
+            self.load_frame();
+        }
+    }
+
+    fn jump(&mut self, input: &str) {
+        match utils::parse_datetime_arg(input) {
+            Ok(timestamp) => {
+                if let Some(entry) = FrameSelector::Timestamp(timestamp).select(&self.entries) {
+                    let index = self.entries.iter().position(|e| e == entry).unwrap_or(0);
+                    self.go_to(index as isize);
+                }
+            }
+            Err(e) => self.message = Some(format!("{:#}", e)),
+        }
+    }
+
+    fn metadata(&self, frame: &Frame) -> Vec<Line<'static>> {
+        let mut lines = vec![
+            Line::from(format!("Frame time: {}", format_time(frame.timestamp))),
+            Line::from(format!(
+                "Source: {}:{}",
+                self.data_file, self.entries[self.current].line_no
+            )),
+        ];
+        if let Some(info) = utils::nearest(&self.info, frame.timestamp, |i| i.timestamp) {
+            let acq_time = Processor::calculate_acq_time(info, self.max_pix_count);
+            lines.push(Line::from(format!(
+                "Info time: {}",
+                format_time(info.timestamp)
+            )));
+            lines.push(Line::from(format!(
+                "Acq time: {} s",
+                Processor::fmt_acq_time(acq_time)
+            )));
+            lines.push(Line::from(format!("Temp: {}", info.temp)));
+            lines.push(Line::from(format!(
+                "Pixels short/long: {}/{}",
+                info.pixel_short, info.pixel_long
+            )));
+        }
+        if let Some(gps) = utils::nearest(&self.gps, frame.timestamp, |g| g.timestamp) {
+            let pos =
+                orbit::geodetic_from_j2000([gps.j2000_x, gps.j2000_y, gps.j2000_z], gps.timestamp);
+            lines.push(Line::from(format!(
+                "GPS time: {}",
+                format_time(gps.timestamp)
+            )));
+            lines.push(Line::from(format!(
+                "Lat/Lon: {:.3} / {:.3} deg",
+                pos.latitude, pos.longitude
+            )));
+            lines.push(Line::from(format!("Alt: {:.1} km", pos.altitude / 1000.0)));
+            if !gps.is_valid() {
+                lines.push(Line::from(format!("GPS invalid: {}", gps.problems.join(", "))).red());
+            }
+        }
+        let hits = frame.itot().iter().filter(|&&v| v != 0).count();
+        lines.push(Line::from(format!(
+            "Hit pixels: {}, clusters: {}",
+            hits,
+            frame.clusters.len()
+        )));
+        lines
+    }
+
+    fn draw(&self, f: &mut ratatui::Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(10), Constraint::Length(1)]).areas(f.area());
+        let [map_area, side] =
+            Layout::horizontal([Constraint::Min(20), Constraint::Length(52)]).areas(main);
+        let [meta_area, cluster_area] =
+            Layout::vertical([Constraint::Length(12), Constraint::Min(3)]).areas(side);
+
+        let title = format!(" Frame {}/{} ", self.current + 1, self.entries.len());
+        let block = Block::bordered().title(title);
+        match &self.frame {
+            Some(frame) if self.braille => {
+                let points: Vec<(f64, f64)> = frame
+                    .itot()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, v)| **v != 0)
+                    .map(|(idx, _)| ((idx % 256) as f64, 255.0 - (idx / 256) as f64))
+                    .collect();
+                let canvas = Canvas::default()
+                    .block(block)
+                    .marker(Marker::Braille)
+                    .x_bounds([0.0, 256.0])
+                    .y_bounds([0.0, 256.0])
+                    .paint(|ctx| {
+                        ctx.draw(&Points {
+                            coords: &points,
+                            color: Color::Yellow,
+                        })
+                    });
+                f.render_widget(canvas, map_area);
+            }
+            Some(frame) => {
+                let inner = block.inner(map_area);
+                let lines: Vec<Line> =
+                    ascii_heatmap(frame.itot(), inner.width as usize, inner.height as usize)
+                        .into_iter()
+                        .map(Line::from)
+                        .collect();
+                f.render_widget(Paragraph::new(lines).block(block), map_area);
+            }
+            None => f.render_widget(Paragraph::new("no frame").block(block), map_area),
+        }
+
+        if let Some(frame) = &self.frame {
+            f.render_widget(
+                Paragraph::new(self.metadata(frame)).block(Block::bordered().title(" Metadata ")),
+                meta_area,
+            );
+            let mut clusters: Vec<(f64, String)> = frame
+                .clusters
+                .iter()
+                .map(|cluster| {
+                    let energy = event_display::cluster_energy(cluster, 1.0);
+                    let pix = &cluster.pixels[0];
+                    let text = format!(
+                        "({:>3},{:>3}) {:>4} px {:>7.0} iToT  {}",
+                        pix.x,
+                        pix.y,
+                        cluster.pixels.len(),
+                        energy,
+                        event_display::classify(cluster, 1.0)
+                    );
+                    (energy, text)
+                })
+                .collect();
+            clusters.sort_by(|a, b| b.0.total_cmp(&a.0));
+            let items: Vec<String> = clusters.into_iter().map(|(_, text)| text).collect();
+            f.render_widget(
+                List::new(items).block(Block::bordered().title(" Clusters ")),
+                cluster_area,
+            );
+        }
+
+        let status_line = match (&self.input, &self.message) {
+            (Some(input), _) => format!("Jump to (YYYY-MM-DD HH:MM:SS): {}_", input),
+            (None, Some(message)) => message.clone(),
+            (None, None) => String::from(
+                "←/→ frame  PgUp/PgDn ±10  Home/End  g jump to time  b braille/ASCII  q quit",
+            ),
+        };
+        f.render_widget(
+            Paragraph::new(status_line).style(Style::default().reversed()),
+            status,
+        );
+    }
+
+    /// Handles a key press, returns false to quit
+    fn on_key(&mut self, code: KeyCode) -> bool {
+        if let Some(input) = &mut self.input {
+            match code {
+                KeyCode::Enter => {
+                    let input = self.input.take().unwrap_or_default();
+                    self.jump(&input);
+                }
+                KeyCode::Esc => self.input = None,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => {}
+            }
+            return true;
+        }
+        let current = self.current as isize;
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Right | KeyCode::Char('n') => self.go_to(current + 1),
+            KeyCode::Left | KeyCode::Char('p') => self.go_to(current - 1),
+            KeyCode::PageDown => self.go_to(current + 10),
+            KeyCode::PageUp => self.go_to(current - 10),
+            KeyCode::Home => self.go_to(0),
+            KeyCode::End => self.go_to(self.entries.len() as isize - 1),
+            KeyCode::Char('g') => self.input = Some(String::new()),
+            KeyCode::Char('b') => self.braille = !self.braille,
+            _ => {}
+        }
+        true
+    }
+}
+
+/// Interactive terminal browser of the frames of a data file
+pub fn run(
+    gps_file: &str,
+    meas_file: &str,
+    data_file: &str,
+    start: FrameSelector,
+    max_pix_count: usize,
+    firmware: Option<PacketLayout>,
+) -> Result<()> {
+    let (entries, _) =
+        index::index_frames(&mut std::io::BufReader::new(InputFile::open(data_file)?))?;
+    let Some(start_entry) = start.select(&entries) else {
+        anyhow::bail!("no {} in {} ({} frames)", start, data_file, entries.len());
+    };
+    let current = entries.iter().position(|e| e == start_entry).unwrap_or(0);
+
+    let gps = GpsProcessor::new().read_all(&mut LineReader::open(gps_file)?)?;
+    let info = MeasInfoProcessor::new().read_all(&mut LineReader::open(meas_file)?)?;
+    let layout = match firmware {
+        Some(layout) => layout,
+        None => DataProcessor::detect_layout(&mut LineReader::open(data_file)?, 20).layout,
+    };
+
+    let mut state = TuiState {
+        data_file: data_file.to_string(),
+        entries,
+        gps,
+        info,
+        layout,
+        max_pix_count,
+        current,
+        frame: None,
+        message: None,
+        input: None,
+        braille: false,
+    };
+    state.load_frame();
+
+    let mut terminal = ratatui::try_init()?;
+    let result = (|| -> Result<()> {
+        loop {
+            terminal.draw(|f| state.draw(f))?;
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !state.on_key(key.code)
+            {
+                return Ok(());
+            }
+        }
+    })();
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_heatmap() {
+        let mut itot = vec![0u16; 256 * 256];
+        itot[0] = 10;
+        for x in 128..256 {
+            for y in 128..256 {
+                itot[y * 256 + x] = 1;
+            }
+        }
+        let map = ascii_heatmap(&itot, 4, 2);
+        assert_eq!(map, vec![".   ", "  @@"]);
+
+        let (map, max) = block_heatmap(&itot, 4, 2);
+        assert_eq!(max, 64 * 128);
+        assert_eq!(map, vec!["▒   ", "  ██"]);
+    }
+}
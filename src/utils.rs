@@ -1,10 +1,12 @@
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
 
 pub fn parse_time(datetime: &str) -> Result<f64> {
     let format = "%Y-%m-%d %H:%M:%S%.3f";
-    if datetime.ends_with(" Z") {
+    if let Some(datetime) = datetime.strip_suffix(" Z") {
         // Remove the 'Z' at the end
-        let datetime = &datetime[..datetime.len() - 2];
         return parse_time(datetime);
     }
 
@@ -14,31 +16,63 @@ pub fn parse_time(datetime: &str) -> Result<f64> {
         / 1000.0)
 }
 
-#[allow(dead_code)]
-pub fn print_buff_hex(buff: &[u8]) {
-    let mut s = String::new();
-    for b in buff {
-        s.push_str(&format!("{:02X} ", b));
+/// Parses a user supplied date time, fractional seconds are optional
+pub fn parse_datetime_arg(datetime: &str) -> Result<f64> {
+    let format = "%Y-%m-%d %H:%M:%S%.f";
+    Ok(
+        chrono::NaiveDateTime::parse_from_str(datetime.trim(), format)
+            .with_context(|| format!("invalid date time '{}', expected {}", datetime, format))?
+            .and_utc()
+            .timestamp_millis() as f64
+            / 1000.0,
+    )
+}
+
+/// Formats a unix timestamp as UTC date time with milliseconds
+pub fn format_time(timestamp: f64) -> String {
+    chrono::DateTime::from_timestamp_millis((timestamp * 1000.0).round() as i64)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S%.3f").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+/// Parses a single csv field, the error names the column and the offending value
+pub fn parse_field<T: FromStr>(value: &str, name: &str) -> Result<T> {
+    value
+        .trim()
+        .parse::<T>()
+        .ok()
+        .with_context(|| format!("cannot parse {} '{}'", name, value))
+}
+
+/// Record closest in time, the records are sorted by time
+pub fn nearest<T>(records: &[T], timestamp: f64, time: fn(&T) -> f64) -> Option<&T> {
+    let i = records.partition_point(|r| time(r) < timestamp);
+    let next = records.get(i);
+    let prev = i.checked_sub(1).and_then(|i| records.get(i));
+    match (prev, next) {
+        (Some(p), Some(n)) => Some(if timestamp - time(p) < time(n) - timestamp {
+            p
+        } else {
+            n
+        }),
+        (p, n) => p.or(n),
     }
-    println!("{}", s);
 }
 
-// pub trait LevelFromU32 {
-//     fn from_u32(value: u32) -> log::Level;
-// }
-
-// impl LevelFromU32 for log::Level {
-//     fn from_u32(value: u32) -> log::Level {
-//         match value {
-//             0 => log::Level::Error,
-//             1 => log::Level::Warn,
-//             2 => log::Level::Info,
-//             3 => log::Level::Debug,
-//             4 => log::Level::Trace,
-//             _ => log::Level::Info,
-//         }
-//     }
-// }
+/// Mixes the bits of the value (splitmix64 finalizer)
+pub fn mix64(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+    x ^ (x >> 31)
+}
+
+/// Bytes as space separated hex pairs
+pub fn format_buff_hex(buff: &[u8]) -> String {
+    buff.iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
 #[allow(dead_code)]
 pub fn nearly_equal(a: f64, b: f64) -> bool {
@@ -53,56 +87,159 @@ pub fn nearly_equal(a: f64, b: f64) -> bool {
         // One of a or b is zero (or both are extremely close to it,) use absolute error.
         let res = diff < (f64::EPSILON * f64::MIN_POSITIVE);
         if !res {
-            println!("a: {}, b: {}, diff: {}", a, b, diff);
+            tracing::debug!("a: {}, b: {}, diff: {}", a, b, diff);
         }
         res
     } else {
         // Use relative error.
         let res = (diff / f64::min(abs_a + abs_b, f64::MAX)) < f64::EPSILON;
         if !res {
-            println!("a: {}, b: {}, diff: {}", a, b, diff);
+            tracing::debug!("a: {}, b: {}, diff: {}", a, b, diff);
         }
         res
     }
 }
 
-// pub fn load_ascii_matrix<T: std::str::FromStr>(file_path: &Path) -> Result<Vec<T>> {
-//     let mut matrix: Vec<T> = Vec::new();
-//     let content = fs::read_to_string(file_path).context(format!(
-//         "Cannot load matrix from file {}",
-//         file_path.to_string_lossy()
-//     ))?;
-
-//     content.lines().for_each(|line| {
-//         line.split_whitespace().for_each(|s| {
-//             if let Ok(value) = s.parse::<T>() {
-//                 matrix.push(value);
-//             }
-//         });
-//     });
-//     Ok(matrix)
-// }
-
-// #[allow(dead_code)]
-// pub fn save_ascii_matrix<T: std::fmt::Display>(
-//     file_path: &Path,
-//     matrix: &Vec<T>,
-//     columns: u32,
-// ) -> Result<()> {
-//     let mut content = String::new();
-//     for (i, value) in matrix.iter().enumerate() {
-//         content.push_str(&format!("{} ", value));
-//         if (i + 1) % columns as usize == 0 {
-//             content.push_str("\n");
-//         } else {
-//             content.push_str(" ");
-//         }
-//     }
-//     fs::write(file_path, &content).context(format!(
-//         "Cannot save matrix to file {}",
-//         &file_path.to_string_lossy()
-//     ))
-// }
+/// Loads a whitespace separated matrix, the values are returned row by row
+pub fn load_ascii_matrix<T: FromStr>(file_path: &Path) -> Result<Vec<T>> {
+    let content = fs::read_to_string(file_path).context(format!(
+        "Cannot load matrix from file {}",
+        file_path.to_string_lossy()
+    ))?;
+    let mut matrix: Vec<T> = Vec::new();
+    for (line_no, line) in content.lines().enumerate() {
+        for s in line.split_whitespace() {
+            let value = s.parse::<T>().ok().with_context(|| {
+                format!(
+                    "{}:{}: invalid matrix value '{}'",
+                    file_path.to_string_lossy(),
+                    line_no + 1,
+                    s
+                )
+            })?;
+            matrix.push(value);
+        }
+    }
+    Ok(matrix)
+}
+
+/// Parses a NumPy .npy array of integers or floats, the values are returned in C order
+pub fn parse_npy(bytes: &[u8]) -> Result<(Vec<usize>, Vec<f64>)> {
+    let Some(rest) = bytes.strip_prefix(b"\x93NUMPY") else {
+        bail!("not a .npy file");
+    };
+    let (header_len, data_start) = match rest.first() {
+        Some(1) if rest.len() >= 4 => (u16::from_le_bytes([rest[2], rest[3]]) as usize, 10),
+        Some(2 | 3) if rest.len() >= 6 => (
+            u32::from_le_bytes([rest[2], rest[3], rest[4], rest[5]]) as usize,
+            12,
+        ),
+        _ => bail!("unsupported .npy version"),
+    };
+    let header = bytes
+        .get(data_start..data_start + header_len)
+        .context("truncated .npy header")?;
+    let header = String::from_utf8_lossy(header);
+    let field = |key: &str| -> Result<String> {
+        let start = header
+            .find(&format!("'{}':", key))
+            .with_context(|| format!("missing '{}' in .npy header", key))?
+            + key.len()
+            + 3;
+        let value = header[start..].trim_start();
+        let end = match value.chars().next() {
+            Some('(') => value.find(')').map(|i| i + 1),
+            Some('\'') => value[1..].find('\'').map(|i| i + 2),
+            _ => value.find([',', '}']),
+        }
+        .with_context(|| format!("invalid '{}' in .npy header", key))?;
+        Ok(value[..end].trim().to_string())
+    };
+
+    let descr = field("descr")?;
+    let descr = descr.trim_matches('\'');
+    if field("fortran_order")? != "False" {
+        bail!("Fortran ordered .npy arrays are not supported");
+    }
+    let shape: Vec<usize> = field("shape")?
+        .trim_matches(['(', ')'])
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<usize>())
+        .collect::<Result<_, _>>()
+        .context("invalid .npy shape")?;
+
+    let (order, kind, size) = match descr.as_bytes() {
+        [order @ (b'<' | b'>' | b'|' | b'='), kind, size @ ..] => {
+            (*order, *kind, std::str::from_utf8(size)?.parse::<usize>()?)
+        }
+        _ => bail!("unsupported .npy dtype '{}'", descr),
+    };
+    if !matches!((kind, size), (b'u' | b'i', 1 | 2 | 4 | 8) | (b'f', 4 | 8)) {
+        bail!("unsupported .npy dtype '{}'", descr);
+    }
+
+    let count: usize = shape.iter().product();
+    let data = &bytes[data_start + header_len..];
+    if data.len() < count * size {
+        bail!(
+            "truncated .npy data, expected {} values of {} bytes",
+            count,
+            size
+        );
+    }
+    let values = data
+        .chunks_exact(size)
+        .take(count)
+        .map(|chunk| {
+            let mut b = [0u8; 8];
+            b[..size].copy_from_slice(chunk);
+            if order == b'>' {
+                b[..size].reverse();
+            }
+            match (kind, size) {
+                (b'f', 4) => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                (b'f', _) => f64::from_le_bytes(b),
+                (b'i', _) => {
+                    // sign extend from the value size
+                    let shift = 64 - 8 * size as u32;
+                    ((i64::from_le_bytes(b) << shift) >> shift) as f64
+                }
+                _ => u64::from_le_bytes(b) as f64,
+            }
+        })
+        .collect();
+    Ok((shape, values))
+}
+
+/// Loads a NumPy .npy matrix, see `parse_npy`
+pub fn load_npy_matrix(file_path: &Path) -> Result<Vec<f64>> {
+    let bytes = fs::read(file_path).context(format!(
+        "Cannot load matrix from file {}",
+        file_path.to_string_lossy()
+    ))?;
+    let (_, values) = parse_npy(&bytes)
+        .with_context(|| format!("invalid matrix file {}", file_path.to_string_lossy()))?;
+    Ok(values)
+}
+
+pub fn save_ascii_matrix<T: std::fmt::Display>(
+    file_path: &Path,
+    matrix: &[T],
+    columns: usize,
+) -> Result<()> {
+    let mut content = String::new();
+    for row in matrix.chunks(columns) {
+        let line: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+        content.push_str(&line.join(" "));
+        content.push('\n');
+    }
+    fs::write(file_path, &content).context(format!(
+        "Cannot save matrix to file {}",
+        &file_path.to_string_lossy()
+    ))
+}
 
 #[cfg(test)]
 mod tests {
@@ -118,4 +255,76 @@ mod tests {
         let result = parse_time(datetime).unwrap();
         assert_eq!(result, 1696163696.789);
     }
+
+    #[test]
+    fn test_parse_datetime_arg() {
+        assert_eq!(
+            parse_datetime_arg("2023-10-01 12:34:56").unwrap(),
+            1696163696.0
+        );
+        assert_eq!(
+            parse_datetime_arg("2023-10-01 12:34:56.789").unwrap(),
+            1696163696.789
+        );
+        assert!(parse_datetime_arg("2023-10-01").is_err());
+        assert_eq!(format_time(1696163696.789), "2023-10-01 12:34:56.789");
+    }
+
+    #[test]
+    fn test_parse_field() {
+        assert_eq!(parse_field::<i32>(" -4", "Temp").unwrap(), -4);
+        let err = parse_field::<i32>("x1", "Temp").unwrap_err();
+        assert_eq!(err.to_string(), "cannot parse Temp 'x1'");
+    }
+
+    #[test]
+    fn test_nearest() {
+        let times = [1.0, 2.0, 4.0];
+        assert_eq!(nearest(&times, 2.9, |t| *t), Some(&2.0));
+        assert_eq!(nearest(&times, 3.1, |t| *t), Some(&4.0));
+        assert_eq!(nearest(&times, 9.0, |t| *t), Some(&4.0));
+        assert_eq!(nearest(&[] as &[f64], 1.0, |t| *t), None);
+    }
+
+    fn npy(descr: &str, shape: &str, data: &[u8]) -> Vec<u8> {
+        let mut header = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+            descr, shape
+        );
+        while (10 + header.len() + 1) % 64 != 0 {
+            header.push(' ');
+        }
+        header.push('\n');
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn test_parse_npy() {
+        let data: Vec<u8> = [1u16, 2, 300, 4]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let (shape, values) = parse_npy(&npy("<u2", "(2, 2)", &data)).unwrap();
+        assert_eq!(shape, vec![2, 2]);
+        assert_eq!(values, vec![1.0, 2.0, 300.0, 4.0]);
+
+        let data: Vec<u8> = [1.5f64, -2.0]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        let (shape, values) = parse_npy(&npy(">f8", "(2,)", &data)).unwrap();
+        assert_eq!(shape, vec![2]);
+        assert_eq!(values, vec![1.5, -2.0]);
+
+        let (_, values) = parse_npy(&npy("<i2", "(1,)", &(-7i16).to_le_bytes())).unwrap();
+        assert_eq!(values, vec![-7.0]);
+
+        assert!(parse_npy(&npy("<u2", "(2, 2)", &[0; 6])).is_err());
+        assert!(parse_npy(&npy("<c16", "(1,)", &[0; 16])).is_err());
+        assert!(parse_npy(b"0 1 2").is_err());
+    }
 }
@@ -0,0 +1,171 @@
+use crate::data_processor::DataProcessor;
+use crate::gps_processor::{self, GpsProcessor};
+use crate::info_processor::{self, MeasInfoProcessor};
+use crate::line_reader::LineReader;
+use anyhow::Result;
+use std::fmt;
+use std::io::Read;
+
+/// Input CSV files of the conversion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    Gps,
+    MeasInfo,
+    Data,
+}
+
+impl InputKind {
+    /// Column names of the header line, without units
+    pub fn header(&self) -> &'static [&'static str] {
+        match self {
+            InputKind::Gps => &gps_processor::COLUMNS,
+            InputKind::MeasInfo => &info_processor::COLUMNS,
+            InputKind::Data => &["TIMESTAMP", "DATA"],
+        }
+    }
+}
+
+impl fmt::Display for InputKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputKind::Gps => write!(f, "GPS"),
+            InputKind::MeasInfo => write!(f, "measurement info"),
+            InputKind::Data => write!(f, "data"),
+        }
+    }
+}
+
+/// Result of checking an input file against its schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationReport {
+    pub kind: InputKind,
+    /// Record lines parsed
+    pub records: usize,
+    /// Time of the first and last record
+    pub span: Option<(f64, f64)>,
+    /// Problems with their file location
+    pub problems: Vec<String>,
+}
+
+/// Column name of a header field without quotes and unit (`"J2000_X (m)"`)
+pub(crate) fn header_name(field: &str) -> &str {
+    let name = field.trim().trim_matches('"');
+    name.split(" (").next().unwrap_or(name)
+}
+
+/// Checks the header, the record format and the time order of an input file; only
+/// reading errors are returned as errors, the schema problems are listed in the report
+pub fn validate<R: Read>(kind: InputKind, reader: &mut LineReader<R>) -> Result<ValidationReport> {
+    let mut report = ValidationReport {
+        kind,
+        records: 0,
+        span: None,
+        problems: Vec::new(),
+    };
+    let mut header = true;
+    while let Some(line) = reader.next_line()? {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if std::mem::take(&mut header) {
+            let names: Vec<&str> = line.split(',').map(header_name).collect();
+            if names == kind.header() {
+                continue;
+            }
+            report.problems.push(format!(
+                "{}: expected the header {}",
+                reader.location(),
+                kind.header().join(",")
+            ));
+            if !line.starts_with("20") {
+                continue;
+            }
+        }
+        let parsed = match kind {
+            InputKind::Gps => {
+                GpsProcessor::parse_line(line).map(|gps| (gps.timestamp, gps.problems))
+            }
+            InputKind::MeasInfo => {
+                MeasInfoProcessor::parse_line(line).map(|info| (info.timestamp, Vec::new()))
+            }
+            InputKind::Data => {
+                DataProcessor::parse_line(line).map(|(timestamp, _)| (timestamp, Vec::new()))
+            }
+        };
+        let (timestamp, problems) = match parsed {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                report.problems.push(format!("{:#}", reader.error_at(e)));
+                continue;
+            }
+        };
+        report.records += 1;
+        for problem in problems {
+            report
+                .problems
+                .push(format!("{}: {}", reader.location(), problem));
+        }
+        report.span = match report.span {
+            Some((first, last)) => {
+                if timestamp < last {
+                    report.problems.push(format!(
+                        "{}: time goes back {:.3} s",
+                        reader.location(),
+                        last - timestamp
+                    ));
+                }
+                Some((first, last.max(timestamp)))
+            }
+            None => Some((timestamp, timestamp)),
+        };
+    }
+    if header {
+        report
+            .problems
+            .push(format!("{}: empty file", reader.source()));
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Cursor};
+
+    fn check(kind: InputKind, content: &str) -> ValidationReport {
+        let mut reader = LineReader::new(BufReader::new(Cursor::new(content.to_string())), "in");
+        validate(kind, &mut reader).unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        let report = check(
+            InputKind::Data,
+            "TIMESTAMP,DATA\n2024-03-01 00:00:01.000,71AF\n2024-03-01 00:00:02.000,00\n",
+        );
+        assert_eq!(report.records, 2);
+        assert!(report.problems.is_empty(), "{:?}", report.problems);
+
+        let report = check(
+            InputKind::Data,
+            "2024-03-01 00:00:02.000,71AF\n2024-03-01 00:00:01.000,0G\n2024-03-01 00:00:01.000,00\n",
+        );
+        assert_eq!(report.records, 2);
+        assert_eq!(report.problems.len(), 3, "{:?}", report.problems);
+        assert!(report.problems[0].starts_with("in:1: expected the header TIMESTAMP,DATA"));
+        assert!(report.problems[1].starts_with("in:2: cannot decode DATA"));
+        assert!(report.problems[2].contains("time goes back 1.000 s"));
+
+        let report = check(
+            InputKind::Gps,
+            "\"TIME\",\"J2000_X (m)\",\"J2000_Y (m)\",\"J2000_Z (m)\",\"iae_qEstProp_BJ.scalar\",\"iae_qEstProp_BJ.vector(1)\",\"iae_qEstProp_BJ.vector(2)\",\"iae_qEstProp_BJ.vector(3)\"\n\
+             2024-03-01 00:00:09.000,2.51279e+6,5.64324e+5,-6.50431e+6,9.64920e-1,5.96500e-3,-1.87169e-1,1.84013e-1\n",
+        );
+        assert_eq!(report.records, 1);
+        assert!(report.problems.is_empty(), "{:?}", report.problems);
+
+        let report = check(InputKind::MeasInfo, "");
+        assert_eq!(report.problems, ["in: empty file"]);
+    }
+}
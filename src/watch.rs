@@ -0,0 +1,167 @@
+use crate::line_reader::{LineReader, Position};
+use crate::source::{InputSource, SourceReader};
+use anyhow::{Result, bail};
+use std::io::{self, Read};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Set when the watching is interrupted
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// Ends the watching of all inputs as if they had ended, returns whether it was already
+/// stopped
+pub fn stop() -> bool {
+    STOP.swap(true, Ordering::SeqCst)
+}
+
+pub fn stopped() -> bool {
+    STOP.load(Ordering::SeqCst)
+}
+
+/// Following of inputs that are still being written: the end of an input is checked for
+/// appended data every interval, until nothing arrived for the idle time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchPolicy {
+    pub interval: Duration,
+    /// Watching forever when None
+    pub idle: Option<Duration>,
+}
+
+impl WatchPolicy {
+    /// Policy of the interval and idle time in s
+    pub fn new(interval: f64, idle: Option<f64>) -> Result<Self> {
+        if !(interval > 0.0 && interval.is_finite()) {
+            bail!("watch interval {} must be a positive time in s", interval);
+        }
+        if let Some(idle) = idle
+            && !(idle >= 0.0 && idle.is_finite())
+        {
+            bail!("watch idle time {} must be a non-negative time in s", idle);
+        }
+        Ok(WatchPolicy {
+            interval: Duration::from_secs_f64(interval),
+            idle: idle.map(Duration::from_secs_f64),
+        })
+    }
+}
+
+/// Reader of a source that, with a watch policy, waits for appended data at the end of the
+/// source instead of ending; seekable sources are opened again at the last read byte, so
+/// appended lines and new chunk files of a directory or glob are seen
+pub struct Follow {
+    source: Arc<dyn InputSource>,
+    reader: SourceReader,
+    offset: u64,
+    policy: Option<WatchPolicy>,
+    last_data: Instant,
+}
+
+impl Follow {
+    pub fn open(
+        source: &Arc<dyn InputSource>,
+        offset: u64,
+        policy: Option<WatchPolicy>,
+    ) -> Result<Self> {
+        Ok(Follow {
+            source: source.clone(),
+            reader: SourceReader::open(source.clone(), offset)?,
+            offset,
+            policy,
+            last_data: Instant::now(),
+        })
+    }
+}
+
+impl Read for Follow {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let Some(policy) = self.policy else {
+                return self.reader.read(buf);
+            };
+            if stopped() {
+                return Ok(0);
+            }
+            let read = self.reader.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                self.offset += read as u64;
+                self.last_data = Instant::now();
+                return Ok(read);
+            }
+            if policy
+                .idle
+                .is_some_and(|idle| self.last_data.elapsed() >= idle)
+            {
+                return Ok(0);
+            }
+            std::thread::sleep(policy.interval);
+            if self.source.seekable() {
+                self.reader = SourceReader::open(self.source.clone(), self.offset)
+                    .map_err(|e| io::Error::other(format!("{:#}", e)))?;
+            }
+        }
+    }
+}
+
+/// Line reader of the source from the position on, following it with the watch policy
+pub fn line_reader(
+    source: &Arc<dyn InputSource>,
+    position: Position,
+    policy: Option<WatchPolicy>,
+) -> Result<LineReader<Follow>> {
+    let reader = Follow::open(source, position.offset, policy)?;
+    Ok(LineReader::new(io::BufReader::new(reader), source.name()).with_position(position))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::LocalFile;
+    use std::fs;
+    use std::io::Write;
+
+    #[test]
+    fn test_follow() {
+        let path = std::env::temp_dir().join(format!("oneweb-watch-{}.csv", std::process::id()));
+        fs::write(&path, "TIMESTAMP,DATA\n2024-03-01 00:00:01.000,71AF").unwrap();
+        let source: Arc<dyn InputSource> = Arc::new(LocalFile::new(path.to_str().unwrap()));
+
+        let mut reader = line_reader(&source, Position::default(), None).unwrap();
+        assert_eq!(reader.next_line().unwrap().unwrap(), "TIMESTAMP,DATA");
+        assert!(reader.next_line().unwrap().is_some());
+        assert!(reader.next_line().unwrap().is_none());
+
+        let policy = WatchPolicy::new(0.01, Some(0.3)).unwrap();
+        let mut reader = line_reader(&source, Position::default(), Some(policy)).unwrap();
+        assert_eq!(reader.next_line().unwrap().unwrap(), "TIMESTAMP,DATA");
+        let writer = std::thread::spawn({
+            let path = path.clone();
+            move || {
+                std::thread::sleep(Duration::from_millis(50));
+                let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+                file.write_all(b"0000\n2024-03-01 00:00:02.000,71A0")
+                    .unwrap();
+                file.flush().unwrap();
+                std::thread::sleep(Duration::from_millis(50));
+                file.write_all(b"0000\n").unwrap();
+            }
+        });
+        // the partial line is completed by the appended data
+        assert_eq!(
+            reader.next_line().unwrap().unwrap(),
+            "2024-03-01 00:00:01.000,71AF0000"
+        );
+        assert_eq!(
+            reader.next_line().unwrap().unwrap(),
+            "2024-03-01 00:00:02.000,71A00000"
+        );
+        let start = Instant::now();
+        assert!(reader.next_line().unwrap().is_none());
+        assert!(start.elapsed() >= Duration::from_millis(250));
+        writer.join().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(WatchPolicy::new(0.0, None).is_err());
+        assert!(WatchPolicy::new(1.0, Some(-1.0)).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::utils::{self, format_time};
+use anyhow::{Result, bail};
+use std::fmt;
+
+/// Data lines this many s outside the window are skipped without decoding; the frame time
+/// can differ from the time of the first line of the frame by the acquisition time and the
+/// distance to the matched measurement info record
+pub const LINE_MARGIN: f64 = 300.0;
+
+/// Time range of the frames decoded and written by `--start` and `--end`, open when a bound
+/// is not given
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TimeWindow {
+    pub start: Option<f64>,
+    /// Exclusive
+    pub end: Option<f64>,
+}
+
+impl TimeWindow {
+    /// Window of the user supplied date times
+    pub fn parse(start: Option<&str>, end: Option<&str>) -> Result<Self> {
+        let window = TimeWindow {
+            start: start.map(utils::parse_datetime_arg).transpose()?,
+            end: end.map(utils::parse_datetime_arg).transpose()?,
+        };
+        if let (Some(start), Some(end)) = (window.start, window.end)
+            && start >= end
+        {
+            bail!(
+                "the window start {} is not before its end {}",
+                format_time(start),
+                format_time(end)
+            );
+        }
+        Ok(window)
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.start.is_none() && self.end.is_none()
+    }
+
+    /// Whether a frame of the time is processed
+    pub fn contains(&self, timestamp: f64) -> bool {
+        self.start.is_none_or(|start| timestamp >= start)
+            && self.end.is_none_or(|end| timestamp < end)
+    }
+
+    /// A data line of the time cannot start a frame of the window
+    pub fn line_before(&self, timestamp: f64) -> bool {
+        self.start
+            .is_some_and(|start| timestamp < start - LINE_MARGIN)
+    }
+
+    /// A data line of the time and the lines after it cannot start a frame of the window
+    pub fn line_after(&self, timestamp: f64) -> bool {
+        self.end.is_some_and(|end| timestamp >= end + LINE_MARGIN)
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_none() {
+            return f.write_str("none");
+        }
+        let bound = |time: Option<f64>| time.map(format_time).unwrap_or_default();
+        write!(f, "{}..{}", bound(self.start), bound(self.end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_window() {
+        assert!(TimeWindow::parse(Some("2024-03-01 10:00:00"), Some("2024-03-01 10:30")).is_err());
+        let window =
+            TimeWindow::parse(Some("2024-03-01 10:00:00"), Some("2024-03-01 10:30:00")).unwrap();
+        assert_eq!(
+            window.to_string(),
+            "2024-03-01 10:00:00.000..2024-03-01 10:30:00.000"
+        );
+        let start = window.start.unwrap();
+        assert!(window.contains(start));
+        assert!(!window.contains(start - 0.1));
+        assert!(!window.contains(start + 1800.0));
+        assert!(!window.line_before(start - LINE_MARGIN));
+        assert!(window.line_before(start - LINE_MARGIN - 1.0));
+        assert!(window.line_after(start + 1800.0 + LINE_MARGIN));
+
+        let open = TimeWindow::parse(None, Some("2024-03-01 10:30:00")).unwrap();
+        assert!(open.contains(0.0) && !open.line_before(0.0));
+        assert_eq!(open.to_string(), "..2024-03-01 10:30:00.000");
+        assert!(TimeWindow::default().is_none());
+        assert!(
+            TimeWindow::parse(Some("2024-03-01 11:00:00"), Some("2024-03-01 10:30:00")).is_err()
+        );
+    }
+}